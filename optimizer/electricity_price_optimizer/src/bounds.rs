@@ -0,0 +1,163 @@
+//! Lower bounds on the total cost, from solving the flow exactly with constraints left out.
+use crate::{
+    optimizer::{FlowSolveError, Relaxation, SmartHomeFlowBuilder},
+    optimizer_context::OptimizerContext,
+};
+
+/// The lowest cost any schedule could reach if the constraints in `relaxation` didn't exist.
+///
+/// Constant actions can't be placed by the flow, so their consumption is spread freely over
+/// their windows instead (see `SmartHomeFlowBuilder::add_relaxed_constant_action`). The result
+/// is therefore a lower bound even without any relaxation; without constant actions and
+/// relaxations it is the exact optimum.
+///
+/// Comparing the bounds of single relaxations shows which constraint costs the most.
+///
+/// # Errors
+/// Fails if the flow can't be solved, see `SmartHomeFlow::get_cost`.
+pub fn theoretical_best(
+    context: &OptimizerContext,
+    relaxation: Relaxation,
+) -> Result<i64, FlowSolveError> {
+    let mut builder = SmartHomeFlowBuilder::from_context_with_relaxation(context, relaxation);
+    for action in context.get_constant_actions() {
+        builder = builder.add_relaxed_constant_action(action);
+    }
+    builder.build().get_cost()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        optimizer_context::{
+            action::{constant::ConstantAction, variable::VariableAction},
+            battery::Battery,
+            prognoses::Prognoses,
+        },
+        time::Time,
+    };
+
+    use super::*;
+
+    /// Cheap nights, midday generation and an evening action, so that every relaxation matters
+    /// as long as `limited` is set.
+    fn example_context(limited: bool) -> OptimizerContext {
+        let hour = |t: Time| t.to_timestep() / 60;
+        let (max_rate, max_export, window) = if limited {
+            (
+                100,
+                Some(50),
+                (
+                    Time::at_default_resolution(16, 0),
+                    Time::at_default_resolution(20, 0),
+                ),
+            )
+        } else {
+            (
+                30_000,
+                None,
+                (Time::at_default_resolution(0, 0), Time::get_day_end()),
+            )
+        };
+        OptimizerContext::new(
+            Prognoses::from_closure(|t| if hour(t) < 4 { 10 } else { 100 }),
+            Prognoses::from_closure(|t| if (10..14).contains(&hour(t)) { 200 } else { 0 }),
+            Prognoses::from_closure(|t| if (18..22).contains(&hour(t)) { 50 } else { 0 }),
+            vec![Rc::new(Battery::new(30_000, 0, max_rate, max_rate, 1.0, 1))],
+            vec![],
+            vec![Rc::new(VariableAction::new(
+                window.0, window.1, 20_000, 200, 2,
+            ))],
+            1.0,
+        )
+        .unwrap()
+        .with_feed_in_tariff(Prognoses::from_closure(|_| 50), max_export)
+    }
+
+    fn single_relaxations() -> [Relaxation; 3] {
+        [
+            Relaxation {
+                battery_power: true,
+                ..Default::default()
+            },
+            Relaxation {
+                grid_limit: true,
+                ..Default::default()
+            },
+            Relaxation {
+                action_windows: true,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_relaxed_costs_are_below_optimum() {
+        let context = example_context(true);
+        let optimum = theoretical_best(&context, Relaxation::default()).unwrap();
+        assert_eq!(
+            optimum,
+            SmartHomeFlowBuilder::from_context(&context)
+                .build()
+                .get_cost()
+                .unwrap()
+        );
+        for relaxation in single_relaxations() {
+            assert!(
+                theoretical_best(&context, relaxation).unwrap() < optimum,
+                "{:?}",
+                relaxation
+            );
+        }
+        let all = Relaxation {
+            battery_power: true,
+            grid_limit: true,
+            action_windows: true,
+        };
+        let best = theoretical_best(&context, all).unwrap();
+        for relaxation in single_relaxations() {
+            assert!(best <= theoretical_best(&context, relaxation).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_relaxing_inactive_constraints_changes_nothing() {
+        let context = example_context(false);
+        let optimum = theoretical_best(&context, Relaxation::default()).unwrap();
+        for relaxation in single_relaxations() {
+            assert_eq!(theoretical_best(&context, relaxation).unwrap(), optimum);
+        }
+    }
+
+    #[test]
+    fn test_constant_actions_are_bounded_from_below() {
+        let action = Rc::new(ConstantAction::new_profiled(
+            Time::at_default_resolution(3, 0),
+            Time::at_default_resolution(6, 0),
+            vec![300; 50].into_iter().chain(vec![20; 40]).collect(),
+            3,
+        ));
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|t| (t.to_timestep() as i64 - 240).abs() + 1),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![action.clone()],
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        let bound = theoretical_best(&context, Relaxation::default()).unwrap();
+
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        let end = action.get_end_before().to_timestep() - action.duration.to_timestep();
+        for start in action.get_start_from().to_timestep()..=end {
+            flow.add_constant_consumption(
+                action.clone().with_start_time(Time::from_timestep(start)),
+            );
+            assert!(bound <= flow.get_cost().unwrap());
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Estimates of how large a solve gets, to check it against a memory or time budget before
+//! running it.
+use crate::{
+    optimizer::{FlowSize, SmartHomeFlowBuilder},
+    optimizer_context::OptimizerContext,
+    simulated_annealing::AnnealingConfig,
+};
+
+/// Edge-iterations an optimized build gets through per second on a desktop CPU, measured
+/// with incremental flow updates. Slower devices take proportionally longer.
+const EDGE_ITERATIONS_PER_SECOND: usize = 10_000_000;
+
+/// How long a solve takes, roughly, see [`Complexity::runtime_class`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RuntimeClass {
+    /// Less than a minute.
+    Seconds,
+    /// Less than an hour.
+    Minutes,
+    Hours,
+}
+
+impl RuntimeClass {
+    /// Name of the class in lower case, e.g. "seconds".
+    pub fn name(self) -> &'static str {
+        match self {
+            RuntimeClass::Seconds => "seconds",
+            RuntimeClass::Minutes => "minutes",
+            RuntimeClass::Hours => "hours",
+        }
+    }
+}
+
+/// The size of a simulated annealing solve, see [`estimate_complexity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Complexity {
+    /// Nodes and edges of the flow network solved in every iteration.
+    pub flow_size: FlowSize,
+    /// Constant actions the annealing moves, i.e. those whose window leaves room to move
+    /// them. Contiguous variable actions are moved as constant actions.
+    pub decision_variables: usize,
+    /// Iterations of the cooling schedule, none without decision variables.
+    pub iterations: usize,
+    /// Memory of the flow network, which the solver keeps once as built and once as solved.
+    /// Everything else the solver holds is small against it.
+    pub peak_memory_bytes: usize,
+}
+
+impl Complexity {
+    /// How long the solve takes, from the edges times the iterations. Every iteration only
+    /// updates a part of the flow, but the edges are what that part grows with.
+    pub fn runtime_class(&self) -> RuntimeClass {
+        let seconds = self.flow_size.edges * (self.iterations + 1) / EDGE_ITERATIONS_PER_SECOND;
+        match seconds {
+            0..60 => RuntimeClass::Seconds,
+            60..3600 => RuntimeClass::Minutes,
+            _ => RuntimeClass::Hours,
+        }
+    }
+}
+
+/// Estimates the size of a simulated annealing solve of `context` with `config`, without
+/// building the flow network.
+pub fn estimate_complexity(context: &OptimizerContext, config: &AnnealingConfig) -> Complexity {
+    // The solver places contiguous variable actions as constant actions, see `State::new`
+    let context = context.with_blocks_as_constant_actions();
+    let flow_size = SmartHomeFlowBuilder::estimate_size(&context);
+    let decision_variables = context
+        .get_constant_actions()
+        .iter()
+        .filter(|action| action.get_end_before() - action.get_start_from() > action.duration)
+        .count();
+    let iterations = if decision_variables > 0 {
+        config.planned_iterations()
+    } else {
+        0
+    };
+    Complexity {
+        flow_size,
+        decision_variables,
+        iterations,
+        peak_memory_bytes: 2 * flow_size.estimated_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        optimizer_context::{
+            action::{constant::ConstantAction, variable::VariableAction},
+            battery::Battery,
+            prognoses::Prognoses,
+        },
+        simulated_annealing::Cooling,
+        time::Time,
+    };
+
+    use super::*;
+
+    fn context(batteries: Vec<Battery>, feed_in: bool, fraction: f32) -> OptimizerContext {
+        let hour = |t: Time| t.to_timestep() / 60;
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|t| hour(t) as i64 + 1),
+            Prognoses::from_closure(|t| if (9..15).contains(&hour(t)) { 40 } else { 0 }),
+            Prognoses::from_closure(|t| if hour(t) >= 18 { 25 } else { 0 }),
+            batteries.into_iter().map(Rc::new).collect(),
+            vec![
+                Rc::new(ConstantAction::new(
+                    Time::at_default_resolution(1, 0),
+                    Time::at_default_resolution(5, 0),
+                    Time::at_default_resolution(1, 0),
+                    30,
+                    10,
+                )),
+                // Fills its window, so it isn't moved
+                Rc::new(ConstantAction::new(
+                    Time::at_default_resolution(2, 0),
+                    Time::at_default_resolution(3, 0),
+                    Time::at_default_resolution(1, 0),
+                    30,
+                    11,
+                )),
+            ],
+            vec![
+                Rc::new(VariableAction::new(
+                    Time::at_default_resolution(6, 0),
+                    Time::at_default_resolution(9, 30),
+                    500,
+                    20,
+                    20,
+                )),
+                Rc::new(
+                    VariableAction::new(
+                        Time::at_default_resolution(20, 0),
+                        Time::at_default_resolution(23, 0),
+                        600,
+                        10,
+                        21,
+                    )
+                    .with_contiguous(true),
+                ),
+            ],
+            fraction,
+        )
+        .unwrap();
+        if feed_in {
+            context.with_feed_in_tariff(Prognoses::from_closure(|_| 3), Some(30))
+        } else {
+            context
+        }
+    }
+
+    #[test]
+    fn test_estimated_flow_size_matches_built_flow() {
+        let green = |id| Battery::new(500, 100, 20, 20, 0.9, id).with_grid_charging_allowed(false);
+        let contexts = [
+            context(vec![], false, 1.0),
+            context(vec![], true, 0.5),
+            context(
+                vec![
+                    Battery::new(1000, 200, 50, 50, 1.0, 1).with_final_charge_target(400),
+                    // Must end full, so nothing is kept beyond the end of the day
+                    green(2).with_final_charge_target(500),
+                ],
+                true,
+                0.25,
+            ),
+            // Only stores generation but can't discharge, without an export
+            context(
+                vec![Battery::new(500, 0, 20, 0, 1.0, 3).with_grid_charging_allowed(false)],
+                false,
+                1.0,
+            ),
+            context(vec![green(4).with_min_charge(50)], true, 1.0),
+        ];
+        for context in contexts {
+            let context = context.with_blocks_as_constant_actions();
+            let built = SmartHomeFlowBuilder::from_context(&context).build();
+            assert_eq!(
+                SmartHomeFlowBuilder::estimate_size(&context),
+                built.get_flow_size()
+            );
+        }
+    }
+
+    #[test]
+    fn test_complexity_counts_moves_and_iterations() {
+        let config = AnnealingConfig {
+            cooling: Cooling::Linear { iterations: 250 },
+            ..Default::default()
+        };
+        let complexity = estimate_complexity(&context(vec![], false, 1.0), &config);
+        // The contiguous action is moved as a constant action, the action filling its window
+        // isn't moved at all
+        assert_eq!(complexity.decision_variables, 2);
+        assert_eq!(complexity.iterations, 250);
+        assert!(complexity.peak_memory_bytes > complexity.flow_size.edges * 32);
+        assert_eq!(complexity.runtime_class(), RuntimeClass::Seconds);
+
+        let geometric = AnnealingConfig::default();
+        let expected = (0.1f64 / 40.0).ln() / 0.999f64.ln();
+        let iterations = estimate_complexity(&context(vec![], false, 1.0), &geometric).iterations;
+        assert!((iterations as f64 - expected).abs() <= 1.0, "{iterations}");
+    }
+}
@@ -1 +1 @@
-pub mod stack_proxy;
\ No newline at end of file
+pub mod stack_proxy;
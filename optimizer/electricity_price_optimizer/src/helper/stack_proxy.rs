@@ -1,26 +1,78 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+/// Number of states that can be pushed on top of the base state by default.
+/// Every pushed state is a full clone, so this only guards against pushes without matching pops.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Errors of the fallible `StackProxy` operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// `push` was called with the maximum depth already reached.
+    MaxDepthReached(usize),
+    /// `pop` was called with only the base state left.
+    PopLast,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackError::MaxDepthReached(max_depth) => write!(
+                f,
+                "Cannot push more than {max_depth} states, is a pop missing?"
+            ),
+            StackError::PopLast => write!(f, "Cannot pop the last state from the stack"),
+        }
+    }
+}
+
+impl std::error::Error for StackError {}
 
 pub struct StackProxy<T: Clone> {
     stack: Vec<T>,
+    max_depth: usize,
 }
 
 impl<T: Clone> StackProxy<T> {
     pub fn new(initial: T) -> Self {
         Self {
             stack: vec![initial],
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
-    pub fn push(&mut self) {
+    /// Sets how many states can be pushed on top of the base state.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+    /// Number of states pushed on top of the base state.
+    pub fn depth(&self) -> usize {
+        self.stack.len() - 1
+    }
+    /// Pushes a copy of the current state. Fails if the maximum depth is reached.
+    pub fn push(&mut self) -> Result<(), StackError> {
+        if self.depth() >= self.max_depth {
+            return Err(StackError::MaxDepthReached(self.max_depth));
+        }
         let top = self.stack.last().unwrap().clone();
         self.stack.push(top);
+        Ok(())
     }
-    pub fn pop(&mut self) {
+    /// Discards the current state. Fails if only the base state is left.
+    pub fn pop(&mut self) -> Result<(), StackError> {
         if self.stack.len() > 1 {
             self.stack.pop();
-        }else {
-            panic!("Cannot pop the last state from the stack");
+            Ok(())
+        } else {
+            Err(StackError::PopLast)
         }
     }
+    /// Discards all states above the given depth. Does nothing if the stack isn't that deep.
+    pub fn shrink_to(&mut self, depth: usize) {
+        self.stack.truncate(depth + 1);
+    }
 }
 
 impl<T: Clone> Deref for StackProxy<T> {
@@ -44,4 +96,47 @@ where
     fn default() -> Self {
         Self::new(T::default())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_fails_at_max_depth() {
+        let mut stack = StackProxy::new(0).with_max_depth(2);
+        assert_eq!(stack.push(), Ok(()));
+        assert_eq!(stack.push(), Ok(()));
+        assert_eq!(stack.push(), Err(StackError::MaxDepthReached(2)));
+        assert_eq!(stack.depth(), 2);
+    }
+
+    #[test]
+    fn test_shrink_to_restores_earlier_state() {
+        let mut stack = StackProxy::new(0);
+        for value in 1..=3 {
+            stack.push().unwrap();
+            *stack = value;
+        }
+        stack.shrink_to(1);
+        assert_eq!(stack.depth(), 1);
+        assert_eq!(*stack, 1);
+
+        // Shrinking to a larger depth keeps everything.
+        stack.shrink_to(5);
+        assert_eq!(stack.depth(), 1);
+        assert_eq!(*stack, 1);
+
+        stack.shrink_to(0);
+        assert_eq!(*stack, 0);
+    }
+
+    #[test]
+    fn test_pop_last_state_fails() {
+        let mut stack = StackProxy::new(7);
+        stack.push().unwrap();
+        assert_eq!(stack.pop(), Ok(()));
+        assert_eq!(stack.pop(), Err(StackError::PopLast));
+        assert_eq!(*stack, 7);
+    }
+}
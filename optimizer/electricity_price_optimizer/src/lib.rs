@@ -1,17 +1,22 @@
 use crate::{optimizer_context::OptimizerContext, schedule::Schedule};
 
-mod helper;
+pub mod bounds;
+pub mod complexity;
+pub(crate) mod helper;
 pub mod optimizer;
 pub mod optimizer_context;
 pub mod schedule;
+pub mod sensitivity;
 pub mod simulated_annealing;
 pub mod time;
 
+pub use helper::stack_proxy::{StackError, StackProxy};
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
-pub fn optimize(data: OptimizerContext) -> Schedule {
+pub fn optimize(_data: OptimizerContext) -> Schedule {
     todo!()
 }
 
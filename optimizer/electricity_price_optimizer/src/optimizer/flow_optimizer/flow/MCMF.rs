@@ -1,11 +1,69 @@
 use std::{
     cmp::Reverse,
     collections::{BinaryHeap, VecDeque},
-    io::{self, Read, Write},
+    fmt,
 };
 
 const INF: i64 = 1_i64 << 60;
 
+/// Default of `MinCostFlow::set_max_iterations`. Solving the flows of the optimizer takes a
+/// tiny fraction of it, so it only stops solves that don't terminate, e.g. because the network
+/// has a cycle of negative cost.
+pub const DEFAULT_MAX_ITERATIONS: u64 = 1 << 30;
+
+/// Shortest path search a solve was in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolvePhase {
+    /// Bellman-Ford search of `mincostflow`.
+    Spfa,
+    /// Dijkstra search of `set_sink_capacities`, including finding its potentials.
+    Dijkstra,
+}
+
+impl fmt::Display for SolvePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolvePhase::Spfa => write!(f, "SPFA"),
+            SolvePhase::Dijkstra => write!(f, "Dijkstra"),
+        }
+    }
+}
+
+/// Work done by the last `mincostflow` or `set_sink_capacities`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolveCounters {
+    /// Nodes taken from the queue of a shortest path search, see `set_max_iterations`.
+    pub iterations: u64,
+    /// Paths flow was sent along.
+    pub augmentations: u64,
+    /// Negative cycles flow was sent around.
+    pub cycle_cancellations: u64,
+}
+
+/// A solve that reached the maximum number of iterations, with the flow it had found so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolverStalled {
+    pub phase: SolvePhase,
+    /// Flow sent from the source to the sink so far.
+    pub flow: i64,
+    /// Cost of that flow.
+    pub cost: i64,
+    pub counters: SolveCounters,
+}
+
+impl fmt::Display for SolverStalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Min cost flow stalled in the {} phase after {} iterations and {} augmentations, \
+             with flow {} at cost {}",
+            self.phase, self.counters.iterations, self.counters.augmentations, self.flow, self.cost
+        )
+    }
+}
+
+impl std::error::Error for SolverStalled {}
+
 #[derive(Clone)]
 struct Edge {
     to: usize,
@@ -25,6 +83,11 @@ pub struct MinCostFlow {
     t: usize,
     maxflow: i64,
     mincost: i64,
+    /// Whether `pi` keeps every reduced cost of the residual graph non-negative, except
+    /// those of edges into the sink.
+    potentials_valid: bool,
+    max_iterations: u64,
+    counters: SolveCounters,
 }
 
 impl MinCostFlow {
@@ -41,7 +104,35 @@ impl MinCostFlow {
             t: 1,
             maxflow: 0,
             mincost: 0,
+            potentials_valid: false,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            counters: SolveCounters::default(),
+        }
+    }
+
+    /// Sets how many nodes the shortest path searches of a single `mincostflow` or
+    /// `set_sink_capacities` may take from their queues before giving up with `SolverStalled`.
+    pub fn set_max_iterations(&mut self, max_iterations: u64) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Work done by the last `mincostflow` or `set_sink_capacities`.
+    pub fn get_counters(&self) -> SolveCounters {
+        self.counters
+    }
+
+    /// Counts an iteration of a shortest path search, failing once there were too many.
+    fn tick(&mut self, phase: SolvePhase) -> Result<(), SolverStalled> {
+        if self.counters.iterations >= self.max_iterations {
+            return Err(SolverStalled {
+                phase,
+                flow: self.maxflow,
+                cost: self.mincost,
+                counters: self.counters,
+            });
         }
+        self.counters.iterations += 1;
+        Ok(())
     }
 
     pub fn get_source(&self) -> usize {
@@ -59,6 +150,7 @@ impl MinCostFlow {
     pub fn new_node(&mut self) -> usize {
         self.adj.push(vec![]);
         self.n += 1;
+        self.potentials_valid = false;
         self.n - 1
     }
 
@@ -75,8 +167,192 @@ impl MinCostFlow {
             f: 0,
             cost: -cost,
         });
-        return self.edges.len() - 2;
+        self.potentials_valid = false;
+        self.edges.len() - 2
+    }
+
+    /// Changes the capacity of an edge. Only valid before any flow was sent over it.
+    pub fn set_capacity(&mut self, edge_id: usize, cap: i64) {
+        self.edges[edge_id].f = cap;
+        self.potentials_valid = false;
+    }
+
+    /// Changes the cost per unit of an edge. Only valid before any flow was sent.
+    pub fn set_cost(&mut self, edge_id: usize, cost: i64) {
+        self.edges[edge_id].cost = cost;
+        self.edges[edge_id ^ 1].cost = -cost;
+        self.potentials_valid = false;
+    }
+
+    /// Changes the cost per unit of an edge that may already carry flow, and the cost of the
+    /// flow with it. The flow may not be a minimum cost flow afterwards, so it mustn't be
+    /// updated with `set_sink_capacities` anymore.
+    pub fn reprice(&mut self, edge_id: usize, cost: i64) {
+        self.mincost += (cost - self.edges[edge_id].cost) * self.get_flow(edge_id);
+        self.set_cost(edge_id, cost);
+    }
+
+    /// Lowers the capacity of an edge to `cap`, which mustn't be below its flow. Only residual
+    /// capacity is taken away, so a minimum cost flow stays one and can still be updated with
+    /// `set_sink_capacities`.
+    pub fn lower_capacity(&mut self, edge_id: usize, cap: i64) {
+        let flow = self.get_flow(edge_id);
+        debug_assert!(flow <= cap, "Capacity {cap} below the flow {flow}");
+        self.edges[edge_id].f = self.edges[edge_id].f.min(cap - flow);
+    }
+
+    /// Removes all flow and keeps the capacities, so that they can be changed with
+    /// `set_capacity` and the network solved again with `mincostflow`.
+    pub fn clear_flow(&mut self) {
+        for edge_id in (0..self.edges.len()).step_by(2) {
+            self.edges[edge_id].f += std::mem::take(&mut self.edges[edge_id ^ 1].f);
+        }
+        self.maxflow = 0;
+        self.mincost = 0;
+        self.potentials_valid = false;
     }
+
+    /// Capacity of an edge, including the part already used by the flow.
+    pub fn get_capacity(&self, edge_id: usize) -> i64 {
+        self.edges[edge_id].f + self.edges[edge_id ^ 1].f
+    }
+
+    /// Cost per unit of flow over an edge.
+    pub fn get_edge_cost(&self, edge_id: usize) -> i64 {
+        self.edges[edge_id].cost
+    }
+
+    /// Nodes an edge leads from and to.
+    pub fn get_edge_nodes(&self, edge_id: usize) -> (usize, usize) {
+        (self.edges[edge_id ^ 1].to, self.edges[edge_id].to)
+    }
+
+    /// Number of nodes, including the source and the sink.
+    pub fn node_count(&self) -> usize {
+        self.n
+    }
+
+    /// Number of edges added with `add_edge`, without their reverse edges.
+    pub fn edge_count(&self) -> usize {
+        self.edges.len() / 2
+    }
+
+    /// Memory a solved network with this many nodes and edges uses, in bytes: every edge is
+    /// stored with its reverse edge and listed in the adjacency of both its nodes, every node
+    /// has its adjacency list, potential, distance and predecessor.
+    pub fn estimated_bytes(nodes: usize, edges: usize) -> usize {
+        let per_edge = 2 * (size_of::<Edge>() + size_of::<usize>());
+        let per_node = size_of::<Vec<usize>>() + 2 * size_of::<i64>() + 2 * size_of::<usize>();
+        edges * per_edge + nodes * per_node
+    }
+
+    /// IDs of all edges added with `add_edge`, without their reverse edges.
+    pub fn edge_ids(&self) -> impl Iterator<Item = usize> + use<> {
+        (0..self.edges.len()).step_by(2)
+    }
+
+    /// Cost of the current flow.
+    pub fn get_cost(&self) -> i64 {
+        self.mincost
+    }
+
+    /// Whether every edge into the sink is used to its full capacity, i.e. the flow can't be
+    /// larger. Only then the flow can be updated with `set_sink_capacities`.
+    pub fn is_sink_saturated(&self) -> bool {
+        // Edges into the sink show up as their reverse edges in the adjacency of the sink
+        self.adj[self.t]
+            .iter()
+            .all(|&id| id % 2 == 0 || self.edges[id ^ 1].f == 0)
+    }
+
+    /// Changes the capacities of edges into the sink of a minimum cost flow that saturates
+    /// all of them, and keeps it one without solving it again. Returns false if the new
+    /// capacities can't all be saturated. The flow has to be solved again with `mincostflow`
+    /// then, as well as after an error.
+    ///
+    /// Flow above a lowered capacity is left where it arrived, and raised capacities need
+    /// flow at the start of their edges. These imbalances are evened out along shortest paths
+    /// of the residual graph, with the source covering the difference. Like augmenting along
+    /// shortest paths, this never creates a negative cycle, so the flow stays optimal. No
+    /// path can lead through the saturated sink.
+    pub fn set_sink_capacities(
+        &mut self,
+        capacities: &[(usize, i64)],
+    ) -> Result<bool, SolverStalled> {
+        debug_assert!(self.is_sink_saturated(), "Flow doesn't saturate the sink");
+        self.counters = SolveCounters::default();
+        // Flow a node has left over (positive) or still needs (negative)
+        let mut imbalance = vec![0; self.adj.len()];
+        let mut raised = Vec::new();
+        for &(edge_id, cap) in capacities {
+            debug_assert_eq!(
+                self.edges[edge_id].to, self.t,
+                "Edge doesn't end in the sink"
+            );
+            let flow = self.get_flow(edge_id);
+            if cap < flow {
+                self.edges[edge_id ^ 1].f = cap;
+                self.maxflow -= flow - cap;
+                self.mincost -= self.edges[edge_id].cost * (flow - cap);
+                imbalance[self.edges[edge_id ^ 1].to] += flow - cap;
+            } else if cap > flow {
+                raised.push((edge_id, cap));
+            }
+        }
+        // Left over flow serves the raised edges of its own node first
+        for &(edge_id, cap) in &raised {
+            let from = self.edges[edge_id ^ 1].to;
+            let sent = (cap - self.get_flow(edge_id)).min(imbalance[from]);
+            if sent > 0 {
+                self.send_to_sink(edge_id, sent);
+                imbalance[from] -= sent;
+            }
+        }
+        for &(edge_id, cap) in &raised {
+            imbalance[self.edges[edge_id ^ 1].to] -= cap - self.get_flow(edge_id);
+        }
+        // The source takes back what isn't needed anymore and covers the rest
+        let total: i64 = imbalance.iter().sum();
+        imbalance[self.s] -= total;
+
+        let starts: Vec<usize> = (0..imbalance.len()).filter(|&v| imbalance[v] > 0).collect();
+        let mut left_over: i64 = starts.iter().map(|&v| imbalance[v]).sum();
+        while left_over > 0 {
+            let Some((target, path_cost)) = self.dijkstra_imbalanced(&starts, &imbalance)? else {
+                return Ok(false);
+            };
+            let source = self.path_start(target);
+            let limit = imbalance[source].min(-imbalance[target]);
+            let sent = self.augment(target, limit);
+            self.counters.augmentations += 1;
+            self.mincost += path_cost * sent;
+            imbalance[source] -= sent;
+            imbalance[target] += sent;
+            left_over -= sent;
+            if target == self.s {
+                continue;
+            }
+            let mut arrived = sent;
+            for &(edge_id, cap) in &raised {
+                if self.edges[edge_id ^ 1].to == target {
+                    let used = (cap - self.get_flow(edge_id)).min(arrived);
+                    self.send_to_sink(edge_id, used);
+                    arrived -= used;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Sends flow that arrived at the start of an edge into the sink over it, without
+    /// touching its remaining capacity.
+    fn send_to_sink(&mut self, edge_id: usize, amount: i64) {
+        self.edges[edge_id ^ 1].f += amount;
+        self.maxflow += amount;
+        self.mincost += self.edges[edge_id].cost * amount;
+    }
+
+    #[allow(dead_code)]
     fn spfa_with_cycle_cancel(&mut self) -> bool {
         let n = self.adj.len();
         self.pref = vec![usize::MAX; n];
@@ -117,10 +393,10 @@ impl MinCostFlow {
         self.pref[self.t] != usize::MAX
     }
 
+    #[allow(dead_code)]
     fn cancel_negative_cycle(&mut self, start: usize) {
         // Find the cycle by walking back through predecessors
         let n = self.adj.len();
-        let mut visited = vec![false; n];
         let mut u = start;
 
         // Get to a node definitely in the cycle
@@ -153,8 +429,16 @@ impl MinCostFlow {
 
         // Update mincost (cycle_cost is negative, so this reduces total cost)
         self.mincost += cycle_cost * min_cap;
+        self.counters.cycle_cancellations += 1;
     }
 
+    /// Shortest augmenting path on the reduced costs `cost + pi[u] - pi[v]`.
+    ///
+    /// The potentials left by `extend` keep every reduced cost of a residual edge non-negative,
+    /// so this finds the same paths as `spfa` even if edge costs are negative. It is slower
+    /// than `spfa` on the layered graphs of the optimizer though. Afterwards `dist` holds the
+    /// real distances, INF for nodes that can't be reached.
+    #[allow(dead_code)]
     fn dijkstra(&mut self) -> bool {
         let n = self.adj.len();
         // reset predecessor, distance
@@ -178,8 +462,11 @@ impl MinCostFlow {
             for &id in &self.adj[u] {
                 let e = &self.edges[id];
                 let v = e.to;
-                if e.f > 0 && self.pi[u] != INF && self.pi[v] != INF {
-                    let nd = d + (e.cost - self.pi[v] + self.pi[u]);
+                // Nodes without a potential were unreachable from the start and stay so
+                if e.f > 0 && self.pi[v] != INF {
+                    let reduced_cost = e.cost + self.pi[u] - self.pi[v];
+                    debug_assert!(reduced_cost >= 0, "Negative reduced cost {reduced_cost}");
+                    let nd = d + reduced_cost;
                     if nd < self.dist[v] {
                         self.dist[v] = nd;
                         self.pref[v] = u;
@@ -194,14 +481,115 @@ impl MinCostFlow {
             return false;
         }
 
-        for i in 0..self.dist.len() {
-            self.dist[i] -= self.pi[self.s] - self.pi[i];
+        // Back from reduced to real distances, pi[s] is always 0
+        for i in 0..n {
+            if self.dist[i] < INF {
+                self.dist[i] += self.pi[i] - self.pi[self.s];
+            }
         }
 
         true
     }
 
-    fn spfa(&mut self) -> bool {
+    /// Potentials for `dijkstra_imbalanced`: the shortest distance to every node from any other
+    /// node, found with Bellman-Ford. As long as the flow is optimal, the residual graph has
+    /// no negative cycle and these distances exist.
+    fn reset_potentials(&mut self) -> Result<(), SolverStalled> {
+        let n = self.adj.len();
+        self.pi = vec![0; n];
+        let mut inq = vec![true; n];
+        let mut q: VecDeque<usize> = (0..n).collect();
+        while let Some(u) = q.pop_front() {
+            self.tick(SolvePhase::Dijkstra)?;
+            inq[u] = false;
+            for &id in &self.adj[u] {
+                let e = &self.edges[id];
+                if e.f > 0 && self.pi[e.to] > self.pi[u] + e.cost {
+                    self.pi[e.to] = self.pi[u] + e.cost;
+                    if !inq[e.to] {
+                        inq[e.to] = true;
+                        q.push_back(e.to);
+                    }
+                }
+            }
+        }
+        self.potentials_valid = true;
+        Ok(())
+    }
+
+    /// Shortest path in the residual graph from one of the `starts` that still has left over
+    /// flow according to `imbalance` to any node that needs flow, never leading through the
+    /// sink. Returns the node it ends at and its cost, or None if there is no such path.
+    ///
+    /// Runs Dijkstra on the reduced costs `cost + pi[u] - pi[v]` and stops as soon as a node
+    /// that needs flow is reached, which is much faster than `spfa` for the short paths of
+    /// `set_sink_capacities`. Afterwards the potentials are moved by the distances, capped at
+    /// the one of the path, so that they stay valid after augmenting along it.
+    fn dijkstra_imbalanced(
+        &mut self,
+        starts: &[usize],
+        imbalance: &[i64],
+    ) -> Result<Option<(usize, i64)>, SolverStalled> {
+        if !self.potentials_valid {
+            self.reset_potentials()?;
+        }
+        let n = self.adj.len();
+        self.pref = vec![usize::MAX; n];
+        self.dist = vec![INF; n];
+        self.con.resize(n, 0);
+        let mut done = vec![false; n];
+        let mut heap = BinaryHeap::new();
+
+        // All starts are connected to a virtual node with a potential above all of theirs
+        let starts = starts.iter().copied().filter(|&v| imbalance[v] > 0);
+        let Some(top) = starts.clone().map(|v| self.pi[v]).max() else {
+            return Ok(None);
+        };
+        for v in starts {
+            self.dist[v] = top - self.pi[v];
+            self.pref[v] = v;
+            heap.push(Reverse((self.dist[v], v)));
+        }
+        let mut target = None;
+        while let Some(Reverse((d, u))) = heap.pop() {
+            if done[u] {
+                continue;
+            }
+            self.tick(SolvePhase::Dijkstra)?;
+            done[u] = true;
+            if imbalance[u] < 0 {
+                target = Some(u);
+                break;
+            }
+            for &id in &self.adj[u] {
+                let e = &self.edges[id];
+                let v = e.to;
+                if e.f > 0 && v != self.t && !done[v] {
+                    let reduced_cost = e.cost + self.pi[u] - self.pi[v];
+                    debug_assert!(reduced_cost >= 0, "Negative reduced cost {reduced_cost}");
+                    let nd = d + reduced_cost;
+                    if nd < self.dist[v] {
+                        self.dist[v] = nd;
+                        self.pref[v] = u;
+                        self.con[v] = id;
+                        heap.push(Reverse((nd, v)));
+                    }
+                }
+            }
+        }
+
+        let Some(target) = target else {
+            return Ok(None);
+        };
+        let cap = self.dist[target];
+        let path_cost = cap - top + self.pi[target];
+        for ((pi, &done), &dist) in self.pi.iter_mut().zip(&done).zip(&self.dist) {
+            *pi += if done { dist } else { cap };
+        }
+        Ok(Some((target, path_cost)))
+    }
+
+    fn spfa(&mut self) -> Result<bool, SolverStalled> {
         let n = self.adj.len();
         self.pref = vec![usize::MAX; n];
         self.dist = vec![INF; n];
@@ -214,6 +602,7 @@ impl MinCostFlow {
         inq[self.s] = true;
 
         while let Some(u) = q.pop_front() {
+            self.tick(SolvePhase::Spfa)?;
             inq[u] = false;
             for &id in &self.adj[u] {
                 let e = &self.edges[id];
@@ -228,62 +617,75 @@ impl MinCostFlow {
                 }
             }
         }
-        self.pref[self.t] != usize::MAX
+        Ok(self.pref[self.t] != usize::MAX)
     }
 
-    fn extend(&mut self) {
-        let mut w = INF;
-        let mut u = self.t;
+    /// First node of the path to `target` found by the last shortest path search.
+    fn path_start(&self, target: usize) -> usize {
+        let mut u = target;
+        while self.pref[u] != u {
+            u = self.pref[u];
+        }
+        u
+    }
+
+    /// Sends as much as possible, but at most `limit`, along the path to `target` found by the
+    /// last shortest path search. Returns the amount sent.
+    fn augment(&mut self, target: usize, limit: i64) -> i64 {
+        let mut w = limit;
+        let mut u = target;
         while self.pref[u] != u {
             let id = self.con[u];
             w = w.min(self.edges[id].f);
             u = self.pref[u];
         }
 
-        self.maxflow += w;
-        self.mincost += self.dist[self.t] * w;
-
-        let mut u = self.t;
+        let mut u = target;
         while self.pref[u] != u {
             let id = self.con[u];
             self.edges[id].f -= w;
             self.edges[id ^ 1].f += w;
             u = self.pref[u];
         }
+        w
+    }
+
+    fn extend(&mut self) {
+        let w = self.augment(self.t, INF);
+        self.counters.augmentations += 1;
+        self.maxflow += w;
+        self.mincost += self.dist[self.t] * w;
 
+        // The new distances keep the reduced costs of the residual graph non-negative,
+        // including those of the reverse edges along the path, which have reduced cost 0.
         for i in 0..self.pi.len() {
             if self.dist[i] < INF {
-                self.pi[i] += self.dist[i];
+                self.pi[i] = self.dist[i];
             }
         }
     }
 
-    pub fn mincostflow(&mut self) -> (i64, i64) {
+    /// Sends the maximum flow from source to sink at the lowest cost and returns
+    /// `(cost, flow)`, starting over from an empty flow.
+    ///
+    /// Edge costs may be negative as long as the graph has no cycle of negative cost.
+    /// Augmenting paths are found with Bellman-Ford, which doesn't need non-negative costs,
+    /// and augmenting along shortest paths never creates a negative cycle.
+    ///
+    /// # Errors
+    /// Fails with the flow found so far if the searches take more than the maximum number of
+    /// iterations, see `set_max_iterations`. A negative cycle makes Bellman-Ford loop forever.
+    pub fn mincostflow(&mut self) -> Result<(i64, i64), SolverStalled> {
         let n = self.adj.len();
         self.con = vec![0; n];
-        self.pi = vec![0; n];
+        self.pi = vec![INF; n];
         self.maxflow = 0;
         self.mincost = 0;
-        return self.update_flow();
-    }
-
-    pub fn update_flow(&mut self) -> (i64, i64) {
-        println!("Updating flow...");
-        let n = self.adj.len();
-        if self.con.len() < n {
-            self.con.resize(n, 0);
-        }
-        if self.pi.len() < n {
-            self.pi.resize(n, 0);
-        }
-
-        while self.spfa() {
+        self.potentials_valid = false;
+        self.counters = SolveCounters::default();
+        while self.spfa()? {
             self.extend();
         }
-        println!(
-            "Flow updated: cost = {}, flow = {}",
-            self.mincost, self.maxflow
-        );
-        return (self.mincost, self.maxflow);
+        Ok((self.mincost, self.maxflow))
     }
 }
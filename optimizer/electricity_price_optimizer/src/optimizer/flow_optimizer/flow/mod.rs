@@ -1,4 +1,5 @@
-pub mod wrapper;
+#[allow(non_snake_case)]
 pub mod MCMF;
-pub use wrapper::FlowWrapper;
-pub use MCMF::MinCostFlow;
\ No newline at end of file
+pub mod wrapper;
+pub use MCMF::{MinCostFlow, SolveCounters, SolvePhase, SolverStalled};
+pub use wrapper::{FlowError, FlowWrapper};
@@ -1,14 +1,45 @@
 use std::{
     collections::HashMap,
+    fmt,
     ops::{Deref, DerefMut},
 };
 
-use crate::{optimizer::flow_optimizer::flow::MinCostFlow, time::Time};
+use crate::{
+    optimizer::flow_optimizer::flow::{MinCostFlow, SolverStalled},
+    schedule::FlowEdge,
+    time::Time,
+};
+
+/// Errors of reading the solution of a `FlowWrapper`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowError {
+    /// The edge id wasn't returned by `add_edge` of this flow, e.g. because the edge was added
+    /// to a state of a `StackProxy` that has been popped since.
+    UnknownEdge { edge_id: usize, edge_count: usize },
+}
+
+impl fmt::Display for FlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowError::UnknownEdge {
+                edge_id,
+                edge_count,
+            } => write!(
+                f,
+                "Edge {edge_id} doesn't belong to the flow, it has {edge_count} edges"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FlowError {}
 
 #[derive(Clone)]
 pub struct FlowWrapper {
     pub inner: MinCostFlow,
     node_map: HashMap<FlowNode, usize>,
+    /// Category of every edge added with a cost, see `add_priced_edge`.
+    categories: Vec<(usize, EdgeCategory)>,
 }
 
 impl FlowWrapper {
@@ -18,7 +49,11 @@ impl FlowWrapper {
             (FlowNode::Source, inner.get_source()),
             (FlowNode::Sink, inner.get_sink()),
         ]);
-        Self { inner, node_map }
+        Self {
+            inner,
+            node_map,
+            categories: Vec::new(),
+        }
     }
 
     fn node(&mut self, key: FlowNode) -> usize {
@@ -37,11 +72,94 @@ impl FlowWrapper {
         self.inner.add_edge(u_id, v_id, cap, cost)
     }
 
+    /// Adds an edge with a cost and tags it with what the cost is for, so that
+    /// `cost_by_category` can attribute the cost of the flow. Edges added with `add_edge`
+    /// should be free.
+    pub fn add_priced_edge(
+        &mut self,
+        u: FlowNode,
+        v: FlowNode,
+        cap: i64,
+        cost: i64,
+        category: EdgeCategory,
+    ) -> usize {
+        let edge_id = self.add_edge(u, v, cap, cost);
+        self.categories.push((edge_id, category));
+        edge_id
+    }
+
+    /// Flow over an edge returned by `add_edge`: its capacity minus the residual capacity of
+    /// the edge. Fails instead of panicking if the edge doesn't belong to this flow.
+    pub fn get_flow(&self, edge_id: usize) -> Result<i64, FlowError> {
+        let edge_count = self.inner.edge_count();
+        // Reverse edges have odd ids and are never handed out
+        if !edge_id.is_multiple_of(2) || edge_id / 2 >= edge_count {
+            return Err(FlowError::UnknownEdge {
+                edge_id,
+                edge_count,
+            });
+        }
+        Ok(self.inner.get_flow(edge_id))
+    }
+
+    /// Flow times cost of the tagged edges, summed per category. Since only the tagged edges
+    /// have a cost, the sum over all categories is the cost of the flow.
+    pub fn cost_by_category(&self) -> HashMap<EdgeCategory, i64> {
+        let mut costs = HashMap::new();
+        for &(edge_id, category) in &self.categories {
+            *costs.entry(category).or_insert(0) +=
+                self.inner.get_flow(edge_id) * self.inner.get_edge_cost(edge_id);
+        }
+        costs
+    }
+
+    /// Every edge with its flow in domain terms, in the order the edges were added.
+    pub fn edge_table(&self) -> Vec<FlowEdge> {
+        let nodes: HashMap<usize, &FlowNode> =
+            self.node_map.iter().map(|(node, &id)| (id, node)).collect();
+        let categories: HashMap<usize, EdgeCategory> = self.categories.iter().copied().collect();
+        let label = |id: usize| match nodes.get(&id) {
+            Some(node) => node.label(),
+            None => format!("Node {}", id),
+        };
+        self.inner
+            .edge_ids()
+            .map(|edge_id| {
+                let (from, to) = self.inner.get_edge_nodes(edge_id);
+                let time = |id: usize| nodes.get(&id).and_then(|node| node.time());
+                FlowEdge {
+                    from: label(from),
+                    to: label(to),
+                    time: time(from).or_else(|| time(to)),
+                    capacity: self.inner.get_capacity(edge_id),
+                    flow: self.inner.get_flow(edge_id),
+                    unit_cost: self.inner.get_edge_cost(edge_id),
+                    category: categories.get(&edge_id).map(|category| category.name()),
+                }
+            })
+            .collect()
+    }
+
+    /// Memory a network with this many nodes and edges uses, in bytes, see
+    /// `MinCostFlow::estimated_bytes`. Every node is also looked up by its `FlowNode`, and
+    /// every edge may carry a category.
+    pub fn estimated_bytes(nodes: usize, edges: usize) -> usize {
+        let node_map = nodes * (size_of::<FlowNode>() + size_of::<usize>());
+        let categories = edges * size_of::<(usize, EdgeCategory)>();
+        MinCostFlow::estimated_bytes(nodes, edges) + node_map + categories
+    }
+
     pub fn new_node(&mut self) -> usize {
         self.inner.new_node()
     }
 
-    pub fn mincostflow(&mut self) -> (i64, i64) {
+    /// Sets how long a solve may take before it gives up, see `MinCostFlow::set_max_iterations`.
+    pub fn with_max_iterations(mut self, max_iterations: u64) -> Self {
+        self.inner.set_max_iterations(max_iterations);
+        self
+    }
+
+    pub fn mincostflow(&mut self) -> Result<(i64, i64), SolverStalled> {
         self.inner.mincostflow()
     }
 }
@@ -66,13 +184,260 @@ impl DerefMut for FlowWrapper {
     }
 }
 
+/// What the cost of a priced edge is for, see `FlowWrapper::add_priced_edge`.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum EdgeCategory {
+    /// Network -> Wire, electricity bought at the grid price.
+    GridImport,
+    /// Charging edges into batteries, the cycle cost of the stored energy. While the flow is
+    /// solved, they also carry a price for the energy lost while charging.
+    ChargingLoss,
+    /// Battery persistence edges, the price of the energy lost to self-discharge.
+    SelfDischarge,
+    /// Generation and green battery output into the export, paid with the feed-in tariff.
+    GridExport,
+    /// Source -> Battery, the bonus keeping the initial charge in the battery. It isn't part
+    /// of the cost of a schedule.
+    InitialCharge,
+    /// Wire -> Action, the preference of a variable action for some timesteps. It isn't money.
+    Preference,
+}
+
+impl EdgeCategory {
+    /// Name of the category in snake case, e.g. "grid_import".
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeCategory::GridImport => "grid_import",
+            EdgeCategory::ChargingLoss => "charging_loss",
+            EdgeCategory::SelfDischarge => "self_discharge",
+            EdgeCategory::GridExport => "grid_export",
+            EdgeCategory::InitialCharge => "initial_charge",
+            EdgeCategory::Preference => "preference",
+        }
+    }
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub enum FlowNode {
     Wire(Time),           // timestep
     Action(usize),        // action id
+    RelaxedAction(usize), // constant action id, consumption spread over its window
     Battery(usize, Time), // battery id, timestep
+    BatteryEnd(usize),    // battery id, charge kept beyond the end of the day
+    Export(Time),         // timestep
+    Green(Time),          // timestep, locally generated electricity only
+    GreenOutput(Time),    // timestep, output of batteries that only store generated electricity
+    GridOutput(Time), // timestep, output of batteries that may charge from the grid, if they export
     Source,
     Sink,
     Network,
     Generator,
 }
+
+impl FlowNode {
+    /// What the node stands for without its timestep, e.g. "Wire" or "Battery 3".
+    pub fn label(&self) -> String {
+        match self {
+            FlowNode::Wire(_) => "Wire".to_string(),
+            FlowNode::Action(id) => format!("Action {}", id),
+            FlowNode::RelaxedAction(id) => format!("Relaxed action {}", id),
+            FlowNode::Battery(id, _) => format!("Battery {}", id),
+            FlowNode::BatteryEnd(id) => format!("Battery {} end", id),
+            FlowNode::Export(_) => "Export".to_string(),
+            FlowNode::Green(_) => "Green".to_string(),
+            FlowNode::GreenOutput(_) => "Green output".to_string(),
+            FlowNode::GridOutput(_) => "Grid output".to_string(),
+            FlowNode::Source => "Source".to_string(),
+            FlowNode::Sink => "Sink".to_string(),
+            FlowNode::Network => "Network".to_string(),
+            FlowNode::Generator => "Generator".to_string(),
+        }
+    }
+
+    /// Timestep of the node, None for nodes shared by all timesteps.
+    pub fn time(&self) -> Option<Time> {
+        match self {
+            FlowNode::Wire(time)
+            | FlowNode::Battery(_, time)
+            | FlowNode::Export(time)
+            | FlowNode::Green(time)
+            | FlowNode::GreenOutput(time)
+            | FlowNode::GridOutput(time) => Some(*time),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        helper::stack_proxy::StackProxy,
+        optimizer::flow_optimizer::flow::{SolveCounters, SolvePhase},
+    };
+
+    /// Source to one wire that serves the sink directly and through an export node, plus a
+    /// dead end that can't carry anything. Not solved yet.
+    fn example_flow() -> (FlowWrapper, [usize; 5]) {
+        let wire = || FlowNode::Wire(Time::from_timestep(0));
+        let export = || FlowNode::Export(Time::from_timestep(0));
+        let mut flow = FlowWrapper::new();
+        let supply = flow.add_edge(FlowNode::Source, wire(), 10, 0);
+        let direct = flow.add_edge(wire(), FlowNode::Sink, 4, 1);
+        let to_export = flow.add_edge(wire(), export(), 5, 2);
+        let export_sink = flow.add_edge(export(), FlowNode::Sink, 3, 0);
+        let dead_end = flow.add_edge(wire(), FlowNode::Network, 5, 0);
+        (flow, [supply, direct, to_export, export_sink, dead_end])
+    }
+
+    fn solved_flow() -> (FlowWrapper, [usize; 5]) {
+        let (mut flow, edges) = example_flow();
+        flow.mincostflow().unwrap();
+        (flow, edges)
+    }
+
+    #[test]
+    fn test_get_flow_of_saturated_edges() {
+        let (flow, [_, direct, _, export_sink, _]) = solved_flow();
+        assert_eq!(flow.get_flow(direct), Ok(4));
+        assert_eq!(flow.get_flow(export_sink), Ok(3));
+    }
+
+    #[test]
+    fn test_get_flow_of_partially_used_edges() {
+        let (flow, [supply, _, to_export, _, _]) = solved_flow();
+        assert_eq!(flow.get_flow(supply), Ok(7));
+        assert_eq!(flow.get_flow(to_export), Ok(3));
+    }
+
+    #[test]
+    fn test_get_flow_of_unused_edge() {
+        let (flow, [.., dead_end]) = solved_flow();
+        assert_eq!(flow.get_flow(dead_end), Ok(0));
+    }
+
+    #[test]
+    fn test_get_flow_rejects_unknown_edges() {
+        let (flow, [supply, ..]) = solved_flow();
+        // The reverse edge of an added edge
+        assert_eq!(
+            flow.get_flow(supply + 1),
+            Err(FlowError::UnknownEdge {
+                edge_id: supply + 1,
+                edge_count: 5,
+            })
+        );
+        assert!(flow.get_flow(10).is_err());
+    }
+
+    #[test]
+    fn test_get_flow_of_stale_edge_is_an_error() {
+        let (flow, _) = example_flow();
+        let mut stack = StackProxy::new(flow);
+        stack.push().unwrap();
+        let stale = stack.add_edge(FlowNode::Source, FlowNode::Sink, 2, 0);
+        stack.mincostflow().unwrap();
+        assert_eq!(stack.get_flow(stale), Ok(2));
+        stack.pop().unwrap();
+        assert_eq!(
+            stack.get_flow(stale),
+            Err(FlowError::UnknownEdge {
+                edge_id: stale,
+                edge_count: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_solve_counts_augmentations() {
+        let (flow, _) = solved_flow();
+        let counters = flow.get_counters();
+        // One path over the direct edge, one through the export node
+        assert_eq!(counters.augmentations, 2);
+        assert_eq!(counters.cycle_cancellations, 0);
+        assert!(counters.iterations > 0);
+    }
+
+    #[test]
+    fn test_lowered_capacity_limits_updates() {
+        let (mut flow, [_, _, to_export, export_sink, _]) = solved_flow();
+        flow.lower_capacity(to_export, 4);
+        assert_eq!(flow.set_sink_capacities(&[(export_sink, 4)]), Ok(true));
+        assert_eq!(flow.get_cost(), 12);
+        assert_eq!(flow.set_sink_capacities(&[(export_sink, 5)]), Ok(false));
+    }
+
+    #[test]
+    fn test_cleared_flow_is_solved_with_new_capacities() {
+        let (mut flow, [supply, direct, ..]) = solved_flow();
+        flow.clear_flow();
+        assert_eq!(flow.get_flow(supply), Ok(0));
+        flow.set_capacity(direct, 2);
+        assert_eq!(flow.mincostflow(), Ok((8, 5)));
+        assert_eq!(flow.get_flow(direct), Ok(2));
+    }
+
+    #[test]
+    fn test_reprice_changes_the_cost_of_the_flow() {
+        let (mut flow, [_, direct, ..]) = solved_flow();
+        assert_eq!(flow.get_cost(), 10);
+        flow.reprice(direct, 3);
+        assert_eq!(flow.get_cost(), 18);
+        assert_eq!(flow.get_edge_cost(direct), 3);
+    }
+
+    #[test]
+    fn test_zero_cost_cycle_terminates() {
+        let wire = || FlowNode::Wire(Time::from_timestep(0));
+        let battery = || FlowNode::Battery(0, Time::from_timestep(0));
+        let mut flow = FlowWrapper::new().with_max_iterations(1000);
+        let served = flow.add_edge(FlowNode::Source, wire(), 3, 1);
+        flow.add_edge(wire(), battery(), i64::MAX / 4, 0);
+        flow.add_edge(battery(), wire(), i64::MAX / 4, 0);
+        flow.add_edge(wire(), FlowNode::Sink, 3, 0);
+        assert_eq!(flow.mincostflow(), Ok((3, 3)));
+        assert_eq!(flow.get_flow(served), Ok(3));
+    }
+
+    #[test]
+    fn test_negative_cycle_stalls_instead_of_hanging() {
+        let wire = || FlowNode::Wire(Time::from_timestep(0));
+        let battery = || FlowNode::Battery(0, Time::from_timestep(0));
+        let mut flow = FlowWrapper::new().with_max_iterations(1000);
+        flow.add_edge(FlowNode::Source, wire(), 3, 0);
+        flow.add_edge(wire(), battery(), i64::MAX / 4, -1);
+        flow.add_edge(battery(), wire(), i64::MAX / 4, 0);
+        flow.add_edge(wire(), FlowNode::Sink, 3, 0);
+        assert_eq!(
+            flow.mincostflow(),
+            Err(SolverStalled {
+                phase: SolvePhase::Spfa,
+                flow: 0,
+                cost: 0,
+                counters: SolveCounters {
+                    iterations: 1000,
+                    augmentations: 0,
+                    cycle_cancellations: 0,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_negative_cycle_stalls_update_of_solved_flow() {
+        let (flow, [_, direct, ..]) = example_flow();
+        let mut flow = flow.with_max_iterations(1000);
+        flow.mincostflow().unwrap();
+        // Unreachable from the source, but finding the potentials looks at every node
+        let battery = |t| FlowNode::Battery(0, Time::from_timestep(t));
+        flow.add_edge(battery(0), battery(1), 5, -1);
+        flow.add_edge(battery(1), battery(0), 5, 0);
+        let Err(stalled) = flow.set_sink_capacities(&[(direct, 2)]) else {
+            panic!("Update didn't stall");
+        };
+        assert_eq!(stalled.phase, SolvePhase::Dijkstra);
+        assert_eq!(stalled.counters.iterations, 1000);
+        // Lowering the capacity already took back the flow above it
+        assert_eq!(stalled.flow, 5);
+    }
+}
@@ -8,20 +8,9 @@ use crate::optimizer::variable_maker::VariableMaker;
 use crate::optimizer_context::OptimizerContext;
 use crate::time::STEPS_PER_DAY;
 
+/// Kept for the old mcmf construction, see `crate::optimizer::required_consumption`.
 pub fn calculate_total_flow(context: &OptimizerContext) -> i64 {
-    let mut total = 0;
-    for action in context.get_variable_actions() {
-        total += action.get_total_consumption() as i64;
-    }
-
-    total += context
-        .get_beyond_control_consumption()
-        .get_data()
-        .iter()
-        .to_owned()
-        .sum::<i64>() as i64;
-    println!("total should be: {}", total);
-    return total;
+    crate::optimizer::required_consumption(context, [])
 }
 
 pub fn add_variable_capacity(
@@ -1,3 +1 @@
 pub mod flow;
-
-use crate::optimizer::flow_optimizer::flow::FlowWrapper;
\ No newline at end of file
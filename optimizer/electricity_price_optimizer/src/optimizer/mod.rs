@@ -1,56 +1,410 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::rc::Rc;
-use std::{collections::HashMap, hash::Hash};
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use crate::helper::stack_proxy::StackProxy;
-use crate::optimizer::flow_optimizer::flow::FlowWrapper;
-use crate::optimizer::flow_optimizer::flow::wrapper::FlowNode;
-use crate::optimizer_context::action::constant::{self, AssignedConstantAction, ConstantAction};
+use crate::helper::stack_proxy::{StackError, StackProxy};
+use crate::optimizer::flow_optimizer::flow::wrapper::{EdgeCategory, FlowNode};
+use crate::optimizer::flow_optimizer::flow::{FlowError, FlowWrapper};
+use crate::optimizer_context::OptimizerContext;
+use crate::optimizer_context::action::constant::{AssignedConstantAction, ConstantAction};
 use crate::optimizer_context::action::variable::{AssignedVariableAction, VariableAction};
 use crate::optimizer_context::battery::{AssignedBattery, Battery};
 use crate::optimizer_context::prognoses::Prognoses;
-use crate::schedule::Schedule;
-use crate::time::{STEPS_PER_DAY, Time, TimeIterator};
+use crate::schedule::{CostBreakdown, FlowEdge, Schedule, UnservedConstantAction};
+use crate::time::{SettlementPeriods, Time, TimeIterator, TimestepConfig};
 
 mod flow_optimizer;
 
+pub use flow_optimizer::flow::{
+    MCMF::DEFAULT_MAX_ITERATIONS, SolveCounters, SolvePhase, SolverStalled,
+};
+
+/// Reasons the flow of a `SmartHomeFlow` can't be solved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowSolveError {
+    /// The flow stack can't hold the state to solve, see `SmartHomeFlow::get_cost`.
+    Stack(StackError),
+    /// The solve didn't finish, see `MinCostFlow::mincostflow`.
+    Stalled(SolverStalled),
+}
+
+impl fmt::Display for FlowSolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowSolveError::Stack(e) => write!(f, "{}", e),
+            FlowSolveError::Stalled(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FlowSolveError {}
+
+impl From<StackError> for FlowSolveError {
+    fn from(e: StackError) -> Self {
+        FlowSolveError::Stack(e)
+    }
+}
+
+impl From<SolverStalled> for FlowSolveError {
+    fn from(e: SolverStalled) -> Self {
+        FlowSolveError::Stalled(e)
+    }
+}
+
+/// A limit that kept the flow from serving more consumption, see [`Shortfall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BindingLimit {
+    /// The variable action `id` consumes its maximum consumption in every timestep of its
+    /// window.
+    ActionMaxConsumption { id: u32 },
+    /// The grid import reaches the grid limit at `time`.
+    GridLimit { time: Time },
+    /// The battery `id` is at its minimum charge at the start of `time`.
+    BatteryEmpty { id: u32, time: Time },
+    /// The battery `id` discharges at its maximum output during `time`.
+    BatteryMaxOutput { id: u32, time: Time },
+}
+
+impl fmt::Display for BindingLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingLimit::ActionMaxConsumption { id } => write!(
+                f,
+                "Variable action {} runs at its max_consumption in its whole window",
+                id
+            ),
+            BindingLimit::GridLimit { time } => write!(f, "Grid limit reached at {:?}", time),
+            BindingLimit::BatteryEmpty { id, time } => {
+                write!(f, "Battery {} is empty at {:?}", id, time)
+            }
+            BindingLimit::BatteryMaxOutput { id, time } => {
+                write!(
+                    f,
+                    "Battery {} discharges at its max_output at {:?}",
+                    id, time
+                )
+            }
+        }
+    }
+}
+
+/// Beyond control and variable action consumption a solved flow doesn't serve and final
+/// charge targets it doesn't reach, see `SmartHomeFlow::get_shortfall`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortfall {
+    /// Unserved beyond control consumption per timestep, only timesteps with some.
+    pub by_time: Vec<(Time, i64)>,
+    /// Unserved total consumption per variable action, only actions with some.
+    pub by_action: Vec<(u32, i64)>,
+    /// Charge missing to the final charge target per battery, only batteries with some.
+    pub by_battery: Vec<(u32, i64)>,
+    /// Limits at the timesteps where more could have been served.
+    pub binding: Vec<BindingLimit>,
+}
+
+impl fmt::Display for Shortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Not all consumption and charge targets can be met:")?;
+        for (time, unserved) in &self.by_time {
+            write!(
+                f,
+                "\n- {} of beyond control consumption at {:?}",
+                unserved, time
+            )?;
+        }
+        for (id, unserved) in &self.by_action {
+            write!(f, "\n- {} of variable action {}", unserved, id)?;
+        }
+        for (id, missing) in &self.by_battery {
+            write!(
+                f,
+                "\n- {} missing to the final charge target of battery {}",
+                missing, id
+            )?;
+        }
+        for limit in &self.binding {
+            write!(f, "\n- {}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scales a per-timestep amount to the part of the first timestep that is left.
+pub(crate) fn scale_first_timestep(amount: i64, time: Time, first_timestep_fraction: f32) -> i64 {
+    if time.to_timestep() == 0 {
+        (amount as f32 * first_timestep_fraction).round() as i64
+    } else {
+        amount
+    }
+}
+
+/// Consumption of constant actions at their assigned start times, scaled in the first timestep.
+fn constant_consumption<'a>(
+    assignments: impl IntoIterator<Item = &'a AssignedConstantAction>,
+    first_timestep_fraction: f32,
+) -> i64 {
+    assignments
+        .into_iter()
+        .flat_map(|action| action.iter_consumption())
+        .map(|(time, consumption)| scale_first_timestep(consumption, time, first_timestep_fraction))
+        .sum()
+}
+
+/// Total energy the consumers of a context need over the day, with the constant actions placed
+/// as in `assignments`.
+///
+/// This is the total of all variable actions, the constant actions at their assigned start
+/// times (scaled in the first timestep, like in `SmartHomeFlow`) and the beyond control
+/// consumption. A schedule serves everything if its flow delivers exactly this amount.
+pub fn required_consumption<'a>(
+    context: &OptimizerContext,
+    assignments: impl IntoIterator<Item = &'a AssignedConstantAction>,
+) -> i64 {
+    let variable: i64 = context
+        .get_variable_actions()
+        .iter()
+        .map(|action| action.get_total_consumption())
+        .sum();
+    // Like in the flow, negative consumption doesn't offset anything.
+    let beyond_control: i64 = context
+        .get_beyond_control_consumption()
+        .get_data()
+        .iter()
+        .map(|consumption| (*consumption).max(0))
+        .sum();
+    let constant = constant_consumption(assignments, context.get_first_timestep_fraction());
+    variable + beyond_control + constant
+}
+
+/// Timesteps at which the beyond control consumption alone is more than the grid limit and
+/// the generation can deliver, so that no schedule can serve it. Empty without a grid limit.
+///
+/// Batteries aren't taken into account, since their charge depends on the schedule.
+pub fn grid_overloads(context: &OptimizerContext) -> Vec<Time> {
+    let Some(grid_limit) = context.get_grid_limit() else {
+        return Vec::new();
+    };
+    (0..context.get_steps())
+        .map(Time::from_timestep)
+        .filter(|&time| {
+            let limit =
+                scale_first_timestep(grid_limit, time, context.get_first_timestep_fraction());
+            let generated = (*context.get_generated_electricity().get(time).unwrap_or(&0)).max(0);
+            let consumption = *context
+                .get_beyond_control_consumption()
+                .get(time)
+                .unwrap_or(&0);
+            consumption > limit + generated
+        })
+        .collect()
+}
+
+/// Reasons `solve_exact` can fail.
+#[derive(Debug)]
+pub enum ExactSolveError {
+    /// The context has constant actions or contiguous variable actions with these ids. The
+    /// flow can't place them, use simulated annealing instead.
+    ConstantActions(Vec<u32>),
+    /// The beyond control consumption alone exceeds the grid limit at these timesteps,
+    /// see `grid_overloads`.
+    GridLimitExceeded(Vec<Time>),
+    /// The flow couldn't be solved, see `SmartHomeFlow::get_cost`.
+    Flow(FlowSolveError),
+    /// The flow can't serve all consumption or reach all final charge targets, see
+    /// `SmartHomeFlow::get_shortfall`.
+    Infeasible(Shortfall),
+}
+
+impl fmt::Display for ExactSolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExactSolveError::ConstantActions(ids) => write!(
+                f,
+                "The exact solver can't place constant actions, found ids {:?}",
+                ids
+            ),
+            ExactSolveError::GridLimitExceeded(times) => write!(
+                f,
+                "Beyond control consumption exceeds the grid limit at {:?}",
+                times
+            ),
+            ExactSolveError::Flow(e) => write!(f, "Flow couldn't be solved: {}", e),
+            ExactSolveError::Infeasible(shortfall) => write!(f, "{}", shortfall),
+        }
+    }
+}
+
+/// Solves a context without constant actions exactly.
+///
+/// Batteries and variable actions are fully handled by the min-cost flow, so its solution is
+/// the optimal schedule. Returns its cost and the schedule, which always carries the breakdown
+/// of the cost, see `Schedule::get_objective_breakdown`. A peak price isn't taken into account,
+/// since the flow can't express it. Neither can it express minimum consumptions of variable
+/// actions, so the schedule is only optimal without them.
+///
+/// # Errors
+/// Fails if the context has constant actions or contiguous variable actions, if the grid limit
+/// can't be kept, if the flow can't be solved or if it can't serve all consumption.
+pub fn solve_exact(context: &OptimizerContext) -> Result<(i64, Schedule), ExactSolveError> {
+    let constant_actions: Vec<u32> = context
+        .get_constant_actions()
+        .iter()
+        .map(|action| action.get_id())
+        .chain(
+            context
+                .get_variable_actions()
+                .iter()
+                .filter(|action| action.is_contiguous())
+                .map(|action| action.get_id()),
+        )
+        .collect();
+    if !constant_actions.is_empty() {
+        return Err(ExactSolveError::ConstantActions(constant_actions));
+    }
+    let overloads = grid_overloads(context);
+    if !overloads.is_empty() {
+        return Err(ExactSolveError::GridLimitExceeded(overloads));
+    }
+    let mut flow = SmartHomeFlowBuilder::from_context(context).build();
+    if let Some(shortfall) = flow.get_shortfall().map_err(ExactSolveError::Flow)? {
+        return Err(ExactSolveError::Infeasible(shortfall));
+    }
+    let rounding = flow
+        .get_min_consumption_rounding()
+        .map_err(ExactSolveError::Flow)?;
+    let cost = flow.get_cost().map_err(ExactSolveError::Flow)? + rounding.cost;
+    let mut schedule = flow.get_schedule().map_err(ExactSolveError::Flow)?;
+    schedule.set_objective_breakdown(flow.get_cost_breakdown().map_err(ExactSolveError::Flow)?);
+    Ok((cost, schedule))
+}
+
 pub struct BatteryBlueprint {
     battery: Rc<Battery>,
+    timestep: TimestepConfig,
     relevant_edges: HashMap<Time, usize>,
+    /// Charging edges from the green wire, only used for batteries that can't charge from the grid.
+    green_charge_edges: HashMap<Time, usize>,
+    /// All charging edges, each stored unit of their flow carries the cycle cost.
+    charge_edges: Vec<usize>,
+    /// Discharging edge of every timestep.
+    output_edges: HashMap<Time, usize>,
+    /// Edge from the end of the day to the sink that holds the final charge target, if the
+    /// battery has one above its minimum charge.
+    target_edge: Option<usize>,
 }
 
 impl BatteryBlueprint {
-    pub fn new(battery: Rc<Battery>) -> Self {
+    pub fn new(battery: Rc<Battery>, timestep: TimestepConfig) -> Self {
         Self {
             battery,
+            timestep,
             relevant_edges: HashMap::new(),
+            green_charge_edges: HashMap::new(),
+            charge_edges: Vec::new(),
+            output_edges: HashMap::new(),
+            target_edge: None,
         }
     }
 
+    /// Registers the edge whose flow is the usable charge at the start of `time`.
+    /// That is the persistence edge from `time - 1` to `time`.
     pub fn set_relevant_edge(&mut self, time: Time, edge_id: usize) {
         self.relevant_edges.insert(time, edge_id);
     }
+
+    pub fn set_green_charge_edge(&mut self, time: Time, edge_id: usize) {
+        self.green_charge_edges.insert(time, edge_id);
+    }
+
+    pub fn add_charge_edge(&mut self, edge_id: usize) {
+        self.charge_edges.push(edge_id);
+    }
+
+    pub fn set_output_edge(&mut self, time: Time, edge_id: usize) {
+        self.output_edges.insert(time, edge_id);
+    }
+
+    pub fn set_target_edge(&mut self, edge_id: usize) {
+        self.target_edge = Some(edge_id);
+    }
+
+    /// Charge missing to the final charge target at the end of the day.
+    fn unmet_target(&self, from: &FlowWrapper) -> Result<i64, FlowError> {
+        self.target_edge.map_or(Ok(0), |edge_id| {
+            Ok(from.get_capacity(edge_id) - from.get_flow(edge_id)?)
+        })
+    }
+
+    /// Limits of the battery that keep it from discharging more during `time`.
+    fn binding_at(&self, from: &FlowWrapper, time: Time) -> Result<Vec<BindingLimit>, FlowError> {
+        let id = self.battery.get_id();
+        let mut binding = Vec::new();
+        let usable_charge = match self.relevant_edges.get(&time) {
+            Some(&edge_id) => from.get_flow(edge_id)?,
+            None => self.battery.get_initial_level() - self.battery.get_min_charge(),
+        };
+        if usable_charge <= 0 {
+            binding.push(BindingLimit::BatteryEmpty { id, time });
+        }
+        if let Some(&edge_id) = self.output_edges.get(&time)
+            && from.get_flow(edge_id)? >= from.get_capacity(edge_id)
+        {
+            binding.push(BindingLimit::BatteryMaxOutput { id, time });
+        }
+        Ok(binding)
+    }
+
+    /// Cycle cost of everything stored in the battery.
+    fn wear_cost(&self, from: &FlowWrapper) -> Result<i64, FlowError> {
+        let stored = self
+            .charge_edges
+            .iter()
+            .map(|&id| from.get_flow(id))
+            .sum::<Result<i64, _>>()?;
+        Ok(stored * self.battery.get_cycle_cost())
+    }
 }
 
 impl Blueprint<FlowWrapper, AssignedBattery> for BatteryBlueprint {
-    fn construct(&self, from: &FlowWrapper) -> AssignedBattery {
+    fn construct(&self, from: &FlowWrapper) -> Result<AssignedBattery, FlowError> {
         let mut edge_flows: HashMap<Time, i64> = HashMap::new();
         for (time, edge_id) in &self.relevant_edges {
-            let flow = from.get_flow(*edge_id);
+            let flow = from.get_flow(*edge_id)?;
             edge_flows.insert(*time, flow);
         }
-        edge_flows.insert(Time::from_timestep(0), self.battery.get_initial_level());
-        let charge_level =
-            Prognoses::from_closure(|t| edge_flows.get(&t).expect("Missing edge flow").clone());
-        AssignedBattery::new(self.battery.clone(), charge_level)
+        // The flow only carries the usable charge above the reserve.
+        edge_flows.insert(
+            Time::from_timestep(0),
+            self.battery.get_initial_level() - self.battery.get_min_charge(),
+        );
+        let level = |t: Time| {
+            *edge_flows.get(&t).expect("Missing edge flow") + self.battery.get_min_charge()
+        };
+        let assigned = AssignedBattery::new(
+            self.battery.clone(),
+            Prognoses::from_closure_for(self.timestep, level),
+        )
+        .with_final_charge_level(level(self.timestep.get_day_end()));
+        if self.battery.is_grid_charging_allowed() {
+            Ok(assigned)
+        } else {
+            let green_charge = Prognoses::from_closure_result_for(self.timestep, |t| {
+                self.green_charge_edges
+                    .get(&t)
+                    .map_or(Ok(0), |edge_id| from.get_flow(*edge_id))
+            })?;
+            Ok(assigned.with_green_charge(green_charge))
+        }
     }
 }
 
 pub struct VariableActionBlueprint {
     variable_action: Rc<VariableAction>,
     relevant_edges: HashMap<Time, usize>,
+    /// Action -> Sink edge, whose capacity is the total consumption.
+    demand_edge: Option<usize>,
 }
 
 impl VariableActionBlueprint {
@@ -58,38 +412,66 @@ impl VariableActionBlueprint {
         Self {
             variable_action,
             relevant_edges: HashMap::new(),
+            demand_edge: None,
         }
     }
 
     pub fn set_relevant_edge(&mut self, time: Time, edge_id: usize) {
         self.relevant_edges.insert(time, edge_id);
     }
+
+    pub fn set_demand_edge(&mut self, edge_id: usize) {
+        self.demand_edge = Some(edge_id);
+    }
+
+    /// Total consumption that isn't served.
+    fn unserved(&self, from: &FlowWrapper) -> Result<i64, FlowError> {
+        self.demand_edge.map_or(Ok(0), |edge_id| {
+            Ok(from.get_capacity(edge_id) - from.get_flow(edge_id)?)
+        })
+    }
+
+    /// Timesteps of the window in which the action could consume more than it does.
+    fn times_with_room(&self, from: &FlowWrapper) -> Result<Vec<Time>, FlowError> {
+        let mut times = Vec::new();
+        for (&time, &edge_id) in &self.relevant_edges {
+            if from.get_flow(edge_id)? < from.get_capacity(edge_id) {
+                times.push(time);
+            }
+        }
+        Ok(times)
+    }
 }
 
 impl Blueprint<FlowWrapper, AssignedVariableAction> for VariableActionBlueprint {
-    fn construct(&self, from: &FlowWrapper) -> AssignedVariableAction {
+    fn construct(&self, from: &FlowWrapper) -> Result<AssignedVariableAction, FlowError> {
         let mut edge_flows: HashMap<Time, i64> = HashMap::new();
         for (time, edge_id) in &self.relevant_edges {
-            let flow = from.get_flow(*edge_id);
+            let flow = from.get_flow(*edge_id)?;
             edge_flows.insert(*time, flow);
         }
         let start_time = self.variable_action.get_start();
         let end_time = self.variable_action.get_end();
         let consumption = (start_time..end_time)
             .iter_steps()
-            .map(|t| edge_flows.get(&t).expect("Missing edge flow").clone())
+            .map(|t| *edge_flows.get(&t).expect("Missing edge flow"))
             .collect();
-        AssignedVariableAction::new(self.variable_action.clone(), consumption)
+        Ok(AssignedVariableAction::new(
+            self.variable_action.clone(),
+            consumption,
+        ))
     }
 }
 
 pub struct NetworkConsumptionBlueprint {
+    timestep: TimestepConfig,
     relevant_edges: HashMap<Time, usize>,
 }
 
 impl NetworkConsumptionBlueprint {
-    pub fn new() -> Self {
+    pub fn new(timestep: TimestepConfig) -> Self {
         Self {
+            timestep,
             relevant_edges: HashMap::new(),
         }
     }
@@ -97,38 +479,134 @@ impl NetworkConsumptionBlueprint {
     pub fn set_relevant_edge(&mut self, time: Time, edge_id: usize) {
         self.relevant_edges.insert(time, edge_id);
     }
+
+    /// How much more could be drawn from the grid in every timestep before the grid limit.
+    fn room(&self, from: &FlowWrapper) -> Result<Vec<i64>, FlowError> {
+        (0..self.timestep.get_steps_per_day())
+            .map(|t| {
+                let edge_id = self.relevant_edges[&Time::from_timestep(t)];
+                Ok(from.get_capacity(edge_id) - from.get_flow(edge_id)?)
+            })
+            .collect()
+    }
 }
 
 impl Blueprint<FlowWrapper, Prognoses<i64>> for NetworkConsumptionBlueprint {
-    fn construct(&self, from: &FlowWrapper) -> Prognoses<i64> {
-        Prognoses::from_closure(|t| {
+    fn construct(&self, from: &FlowWrapper) -> Result<Prognoses<i64>, FlowError> {
+        Prognoses::from_closure_result_for(self.timestep, |t| {
             let edge_id = self
                 .relevant_edges
                 .get(&t)
                 .expect("Missing relevant edge for network consumption");
-            let flow = from.get_flow(*edge_id);
-            flow as i64
+            from.get_flow(*edge_id)
+        })
+    }
+}
+
+pub struct GridExportBlueprint {
+    timestep: TimestepConfig,
+    relevant_edges: HashMap<Time, Vec<usize>>,
+}
+
+impl GridExportBlueprint {
+    pub fn new(timestep: TimestepConfig) -> Self {
+        Self {
+            timestep,
+            relevant_edges: HashMap::new(),
+        }
+    }
+
+    /// Adds an edge whose flow is exported at the given time.
+    pub fn add_relevant_edge(&mut self, time: Time, edge_id: usize) {
+        self.relevant_edges.entry(time).or_default().push(edge_id);
+    }
+}
+
+impl Blueprint<FlowWrapper, Prognoses<i64>> for GridExportBlueprint {
+    fn construct(&self, from: &FlowWrapper) -> Result<Prognoses<i64>, FlowError> {
+        // Timesteps without an export edge can't export anything
+        Prognoses::from_closure_result_for(self.timestep, |t| {
+            self.relevant_edges.get(&t).map_or(Ok(0), |edge_ids| {
+                edge_ids.iter().map(|edge_id| from.get_flow(*edge_id)).sum()
+            })
         })
     }
 }
 
 pub struct SmartHomeBlueprint {
     battery_blueprints: Vec<BatteryBlueprint>,
+    /// Batteries with a fixed dispatch, reported unchanged in every schedule.
+    fixed_batteries: Vec<AssignedBattery>,
     variable_action_blueprints: Vec<VariableActionBlueprint>,
     network_consumption_blueprint: NetworkConsumptionBlueprint,
+    grid_export_blueprint: GridExportBlueprint,
+    /// Price snapshot handed to every constructed schedule.
+    electricity_price: Prognoses<i64>,
+    /// Network fee snapshot handed to every constructed schedule, if imports pay one.
+    network_fee: Option<Box<Prognoses<i64>>>,
+    /// CO2 intensity snapshot handed to every constructed schedule, with the price of the CO2
+    /// emitted by a unit drawn from the grid, if one is set.
+    co2: Option<Box<(Prognoses<i64>, Prognoses<i64>)>>,
+    /// Periods the grid draw is measured on, handed to every constructed schedule.
+    settlement_periods: SettlementPeriods,
+    /// Fraction of the first timestep, which scales the caps of variable actions in it.
+    first_timestep_fraction: f32,
 }
 
 impl SmartHomeBlueprint {
-    pub fn new(network_consumption_blueprint: NetworkConsumptionBlueprint) -> Self {
+    pub fn new(
+        network_consumption_blueprint: NetworkConsumptionBlueprint,
+        electricity_price: Prognoses<i64>,
+        first_timestep_fraction: f32,
+    ) -> Self {
         Self {
             battery_blueprints: Vec::new(),
+            fixed_batteries: Vec::new(),
             variable_action_blueprints: Vec::new(),
             network_consumption_blueprint,
+            grid_export_blueprint: GridExportBlueprint::new(
+                electricity_price.get_timestep_config(),
+            ),
+            electricity_price,
+            network_fee: None,
+            co2: None,
+            settlement_periods: SettlementPeriods::default(),
+            first_timestep_fraction,
         }
     }
+    pub fn set_network_fee(&mut self, network_fee: Prognoses<i64>) {
+        self.network_fee = Some(Box::new(network_fee));
+    }
+    pub fn set_co2(&mut self, co2_intensity: Prognoses<i64>, co2_cost: Prognoses<i64>) {
+        self.co2 = Some(Box::new((co2_intensity, co2_cost)));
+    }
+    pub fn set_settlement_periods(&mut self, settlement_periods: SettlementPeriods) {
+        self.settlement_periods = settlement_periods;
+    }
+    /// Electricity price plus network fee and CO2 cost of a unit drawn from the grid at the
+    /// given time.
+    fn import_price(&self, time: Time) -> i64 {
+        let fee = self.network_fee.as_ref().and_then(|fee| fee.get(time));
+        let co2_cost = self.co2.as_ref().and_then(|co2| co2.1.get(time));
+        self.electricity_price.get(time).unwrap_or(&0) + fee.unwrap_or(&0) + co2_cost.unwrap_or(&0)
+    }
+    pub fn set_grid_export_blueprint(&mut self, grid_export_blueprint: GridExportBlueprint) {
+        self.grid_export_blueprint = grid_export_blueprint;
+    }
     pub fn add_battery_blueprint(&mut self, battery_blueprint: BatteryBlueprint) {
         self.battery_blueprints.push(battery_blueprint);
     }
+    /// Cycle cost of every battery that has one, by id.
+    fn battery_wear(&self, from: &FlowWrapper) -> Result<BTreeMap<u32, i64>, FlowError> {
+        self.battery_blueprints
+            .iter()
+            .filter(|bp| bp.battery.get_cycle_cost() > 0)
+            .map(|bp| Ok((bp.battery.get_id(), bp.wear_cost(from)?)))
+            .collect()
+    }
+    pub fn add_fixed_battery(&mut self, battery: AssignedBattery) {
+        self.fixed_batteries.push(battery);
+    }
     pub fn add_variable_action_blueprint(
         &mut self,
         variable_action_blueprint: VariableActionBlueprint,
@@ -136,39 +614,218 @@ impl SmartHomeBlueprint {
         self.variable_action_blueprints
             .push(variable_action_blueprint);
     }
+
+    /// Constructs the variable actions, rounded to their minimum consumption within the grid
+    /// limit.
+    fn construct_variable_actions(
+        &self,
+        from: &FlowWrapper,
+    ) -> Result<RoundedVariableActions, FlowError> {
+        let mut delta: Option<Vec<i64>> = None;
+        let mut room: Option<Vec<i64>> = None;
+        let variable_actions = self
+            .variable_action_blueprints
+            .iter()
+            .map(|bp| {
+                let mut assigned = bp.construct(from)?;
+                if assigned.get_min_consumption() > 0 {
+                    let flow_assigned = assigned.clone();
+                    let room = match &mut room {
+                        Some(room) => room,
+                        None => room.insert(self.network_consumption_blueprint.room(from)?),
+                    };
+                    assigned.round_to_min_consumption(self.first_timestep_fraction, |t| {
+                        room[t.to_timestep() as usize]
+                    });
+                    let delta =
+                        delta.get_or_insert_with(|| vec![0; self.electricity_price.len() as usize]);
+                    for ((time, rounded), (_, flow)) in assigned
+                        .iter_consumption()
+                        .zip(flow_assigned.iter_consumption())
+                    {
+                        let t = time.to_timestep() as usize;
+                        delta[t] += rounded - flow;
+                        room[t] -= (rounded - flow).max(0);
+                    }
+                }
+                Ok((assigned.get_id(), assigned))
+            })
+            .collect::<Result<_, FlowError>>()?;
+        Ok(RoundedVariableActions {
+            actions: variable_actions,
+            delta,
+        })
+    }
+
+    /// Network consumption of the flow, changed by rounding variable actions to their minimum
+    /// consumption. The flow can't express the rounding, so the difference is assumed to be
+    /// drawn from or returned to the grid.
+    fn construct_network_consumption(
+        &self,
+        from: &FlowWrapper,
+        delta: Option<&[i64]>,
+    ) -> Result<Prognoses<i64>, FlowError> {
+        let network_consumption = self.network_consumption_blueprint.construct(from)?;
+        Ok(match delta {
+            Some(delta) => {
+                Prognoses::from_closure_for(network_consumption.get_timestep_config(), |t| {
+                    let flow = *network_consumption.get(t).unwrap_or(&0);
+                    (flow + delta[t.to_timestep() as usize]).max(0)
+                })
+            }
+            None => network_consumption,
+        })
+    }
+
+    /// Effect of rounding variable actions to their minimum consumption on the cost and the
+    /// served consumption, nothing if no timestep had to be rounded.
+    pub fn min_consumption_rounding(
+        &self,
+        from: &FlowWrapper,
+    ) -> Result<MinConsumptionRounding, FlowError> {
+        if !self
+            .variable_action_blueprints
+            .iter()
+            .any(|bp| bp.variable_action.get_min_consumption() > 0)
+        {
+            return Ok(MinConsumptionRounding::default());
+        }
+        let Some(delta) = self.construct_variable_actions(from)?.delta else {
+            return Ok(MinConsumptionRounding::default());
+        };
+        let flow = self.network_consumption_blueprint.construct(from)?;
+        let rounded = self.construct_network_consumption(from, Some(&delta))?;
+        let cost = (0..self.electricity_price.len())
+            .map(Time::from_timestep)
+            .map(|t| {
+                (rounded.get(t).unwrap_or(&0) - flow.get(t).unwrap_or(&0)) * self.import_price(t)
+            })
+            .sum();
+        Ok(MinConsumptionRounding {
+            cost,
+            dropped: -delta.iter().sum::<i64>(),
+        })
+    }
+}
+
+/// Variable actions read from a flow, rounded to their minimum consumption, see
+/// `SmartHomeBlueprint::construct_variable_actions`.
+struct RoundedVariableActions {
+    actions: HashMap<u32, AssignedVariableAction>,
+    /// How much the rounding changed the consumption of every timestep, if it changed any.
+    delta: Option<Vec<i64>>,
+}
+
+/// How rounding variable actions to their minimum consumption changes a flow's solution,
+/// see `SmartHomeFlow::get_min_consumption_rounding`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MinConsumptionRounding {
+    /// Cost of the changed network consumption.
+    pub cost: i64,
+    /// Consumption that couldn't be kept, e.g. because the grid limit leaves no room to round
+    /// up.
+    pub dropped: i64,
 }
 
 impl Blueprint<FlowWrapper, Schedule> for SmartHomeBlueprint {
-    fn construct(&self, from: &FlowWrapper) -> Schedule {
+    fn construct(&self, from: &FlowWrapper) -> Result<Schedule, FlowError> {
         let batteries: HashMap<u32, AssignedBattery> = self
             .battery_blueprints
             .iter()
             .map(|bp| bp.construct(from))
-            .map(|ab| (ab.get_battery().get_id(), ab))
-            .collect();
-        let variable_actions: HashMap<u32, AssignedVariableAction> = self
-            .variable_action_blueprints
-            .iter()
-            .map(|bp| bp.construct(from))
-            .map(|ava| (ava.get_id(), ava))
-            .collect();
-        let network_consumption = self.network_consumption_blueprint.construct(from);
-        Schedule::new(
+            .chain(self.fixed_batteries.iter().cloned().map(Ok))
+            .map(|ab| ab.map(|ab| (ab.get_battery().get_id(), ab)))
+            .collect::<Result<_, _>>()?;
+        let RoundedVariableActions {
+            actions: variable_actions,
+            delta,
+        } = self.construct_variable_actions(from)?;
+        let network_consumption = self.construct_network_consumption(from, delta.as_deref())?;
+        let grid_export = self.grid_export_blueprint.construct(from)?;
+        let mut schedule = Schedule::new(
             HashMap::new(),
             variable_actions,
             batteries,
             network_consumption,
-        )
+            grid_export,
+            self.electricity_price.clone(),
+        );
+        if let Some(network_fee) = &self.network_fee {
+            schedule.set_network_fee((**network_fee).clone());
+        }
+        if let Some(co2) = &self.co2 {
+            schedule.set_co2_intensity(co2.0.clone());
+        }
+        schedule.set_settlement_periods(self.settlement_periods);
+        Ok(schedule)
     }
 }
 
+/// Reads a solution of type `T` out of a solved `F`. Fails if the blueprint refers to edges
+/// that aren't part of `from`.
 pub trait Blueprint<F, T> {
-    fn construct(&self, from: &F) -> T;
+    fn construct(&self, from: &F) -> Result<T, FlowError>;
+}
+
+/// Constraints a `SmartHomeFlowBuilder` leaves out, e.g. to see how much each of them costs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Relaxation {
+    /// Batteries charge and discharge at any rate, only their capacity limits them.
+    pub battery_power: bool,
+    /// Neither the grid import is limited by the grid limit nor the export by the maximum export.
+    pub grid_limit: bool,
+    /// Actions can consume at any time of the day instead of only within their windows.
+    pub action_windows: bool,
+}
+
+/// Number of nodes and edges of a flow network, see [`SmartHomeFlowBuilder::estimate_size`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlowSize {
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+impl FlowSize {
+    /// Memory a solved network of this size uses, in bytes.
+    pub fn estimated_bytes(&self) -> usize {
+        FlowWrapper::estimated_bytes(self.nodes, self.edges)
+    }
 }
+
 pub struct SmartHomeFlowBuilder {
     flow: FlowWrapper,
+    /// Length of the timesteps, set by the prognoses.
+    timestep: TimestepConfig,
     blueprint: SmartHomeBlueprint,
     first_timestep_fraction: f32,
+    /// Electricity price per timestep, kept to price battery charging losses.
+    electricity_price: Prognoses<i64>,
+    /// Generated electricity per timestep, kept to cap the grid export.
+    generated_electricity: Prognoses<i64>,
+    /// Sum of the maximum discharge of all added batteries that only store generated
+    /// electricity, see `add_grid_export`.
+    green_battery_output: i64,
+    /// Sum of the maximum discharge of all added batteries that may charge from the grid, if
+    /// they were added after the feed-in tariff and may export, see `add_grid_export`.
+    grid_battery_output: i64,
+    /// Feed-in tariff and maximum export per timestep, if exporting is possible.
+    feed_in: Option<(Prognoses<i64>, Option<i64>)>,
+    /// Maximum grid import per timestep, applied in `build`.
+    grid_limit: Option<i64>,
+    /// Network -> Wire edge of every timestep.
+    network_edges: Vec<usize>,
+    /// Constraints that are left out.
+    relaxation: Relaxation,
+    /// Edges into the sink that serve consumption, i.e. beyond control consumption and actions.
+    demand_edges: Vec<usize>,
+    /// Wire -> Sink edges of the beyond control consumption, by timestep.
+    consumption_edges: Vec<(Time, usize)>,
+    /// Sum of the capacities of `demand_edges`.
+    required_consumption: i64,
+    /// Source -> Battery edge of every initial charge and the bonus per unit on it.
+    initial_charge_edges: Vec<(usize, i64)>,
+    /// Charging edges of batteries that lose energy while charging, with their loss edges.
+    lossy_charges: Vec<LossyCharge>,
 }
 impl SmartHomeFlowBuilder {
     pub fn new(
@@ -177,18 +834,30 @@ impl SmartHomeFlowBuilder {
         consume_prog: &Prognoses<i64>,
         first_timestep_fraction: f32,
     ) -> Self {
+        let timestep = price_prog.get_timestep_config();
         let mut flow = FlowWrapper::new();
-        let mut consumption_blueprint = NetworkConsumptionBlueprint::new();
+        let mut consumption_blueprint = NetworkConsumptionBlueprint::new(timestep);
+        let mut demand_edges = Vec::new();
+        let mut consumption_edges = Vec::new();
+        let mut required_consumption = 0;
+        let mut network_edges = Vec::new();
 
         flow.add_edge(FlowNode::Source, FlowNode::Generator, i64::MAX, 0);
         flow.add_edge(FlowNode::Source, FlowNode::Network, i64::MAX, 0);
 
-        for i in 0..STEPS_PER_DAY {
-            // Edge from GENERATOR to wire for generation
-            let gen_amount = *generate_prog.get(Time::from_timestep(i)).unwrap_or(&0) as i64;
+        for i in 0..timestep.get_steps_per_day() {
+            // Edges from GENERATOR over the green wire to the wire for generation.
+            // Batteries that may only store generated electricity charge from the green wire.
+            let gen_amount = *generate_prog.get(Time::from_timestep(i)).unwrap_or(&0);
             if gen_amount > 0 {
                 flow.add_edge(
                     FlowNode::Generator,
+                    FlowNode::Green(Time::from_timestep(i)),
+                    gen_amount,
+                    0,
+                );
+                flow.add_edge(
+                    FlowNode::Green(Time::from_timestep(i)),
                     FlowNode::Wire(Time::from_timestep(i)),
                     gen_amount,
                     0,
@@ -196,100 +865,490 @@ impl SmartHomeFlowBuilder {
             }
 
             // Edge from NETWORK to wire with cost based on price
-            let price = *price_prog.get(Time::from_timestep(i)).unwrap_or(&0) as i64;
-            // flow.add_edge(
-            //     FlowNode::Network,
-            //     FlowNode::Wire(Time::from_timestep(i)),
-            //     i64::MAX,
-            //     price,
-            // );
-            let edge_id = flow.add_edge(
+            let price = *price_prog.get(Time::from_timestep(i)).unwrap_or(&0);
+            let edge_id = flow.add_priced_edge(
                 FlowNode::Network,
                 FlowNode::Wire(Time::from_timestep(i)),
                 i64::MAX,
                 price,
+                EdgeCategory::GridImport,
             );
             consumption_blueprint.set_relevant_edge(Time::from_timestep(i), edge_id);
+            network_edges.push(edge_id);
 
             // Edge from wire to SINK for consumption
-            let cons_amount = *consume_prog.get(Time::from_timestep(i)).unwrap_or(&0) as i64;
+            let cons_amount = *consume_prog.get(Time::from_timestep(i)).unwrap_or(&0);
             if cons_amount > 0 {
-                flow.add_edge(
+                let edge_id = flow.add_edge(
                     FlowNode::Wire(Time::from_timestep(i)),
                     FlowNode::Sink,
                     cons_amount,
                     0,
                 );
+                demand_edges.push(edge_id);
+                consumption_edges.push((Time::from_timestep(i), edge_id));
+                required_consumption += cons_amount;
             }
         }
 
-        let blueprint = SmartHomeBlueprint::new(consumption_blueprint);
+        let blueprint = SmartHomeBlueprint::new(
+            consumption_blueprint,
+            price_prog.clone(),
+            first_timestep_fraction,
+        );
 
         Self {
             flow,
+            timestep,
             blueprint,
             first_timestep_fraction,
+            electricity_price: price_prog.clone(),
+            generated_electricity: generate_prog.clone(),
+            green_battery_output: 0,
+            grid_battery_output: 0,
+            feed_in: None,
+            grid_limit: None,
+            network_edges,
+            relaxation: Relaxation::default(),
+            demand_edges,
+            consumption_edges,
+            required_consumption,
+            initial_charge_edges: Vec::new(),
+            lossy_charges: Vec::new(),
+        }
+    }
+
+    /// Creates a builder with all prognoses, batteries, variable actions and the feed-in
+    /// tariff of a context. Constant actions are not added, since they need a start time.
+    pub fn from_context(context: &OptimizerContext) -> Self {
+        Self::from_context_with_relaxation(context, Relaxation::default())
+    }
+
+    /// Sets how many iterations every solve of the built flow may take before it gives up with
+    /// [`SolverStalled`], by default [`DEFAULT_MAX_ITERATIONS`].
+    pub fn with_max_iterations(mut self, max_iterations: u64) -> Self {
+        self.flow = self.flow.with_max_iterations(max_iterations);
+        self
+    }
+
+    /// Size of the flow `from_context(context).build()` creates, counted from the prognoses
+    /// and assets without building it. Like `build`, this follows the edges added by `new`,
+    /// `add_battery`, `add_action` and `add_grid_export`, so changes to them have to be made
+    /// here as well.
+    pub fn estimate_size(context: &OptimizerContext) -> FlowSize {
+        let steps = context.get_steps() as usize;
+        let generating = |t: u32| {
+            *context
+                .get_generated_electricity()
+                .get(Time::from_timestep(t))
+                .unwrap_or(&0)
+                > 0
+        };
+        let generating_steps = (0..context.get_steps()).filter(|&t| generating(t)).count();
+        let consuming_steps = (0..context.get_steps())
+            .filter(|&t| {
+                *context
+                    .get_beyond_control_consumption()
+                    .get(Time::from_timestep(t))
+                    .unwrap_or(&0)
+                    > 0
+            })
+            .count();
+        // Source, sink, generator, network and a wire per timestep, a green wire per timestep
+        // with generation. Edges to the generator and the network, generation over the green
+        // wire, the import, the beyond control consumption and the constant actions.
+        let mut nodes = 4 + steps + generating_steps;
+        let mut edges = 2 + 2 * generating_steps + steps + consuming_steps + steps;
+
+        let batteries = context.get_batteries();
+        for battery in batteries {
+            // Charge level at the start of every timestep and after the last one
+            nodes += steps + 1;
+            let charging_steps = if battery.is_grid_charging_allowed() {
+                steps
+            } else {
+                generating_steps
+            };
+            // Initial charge, charging, discharging and persistence, and the charging losses
+            edges += 1 + charging_steps + steps + steps;
+            if battery.get_efficiency() < 1.0 {
+                edges += charging_steps;
+            }
+            let min_charge = battery.get_min_charge();
+            let target = battery
+                .get_final_charge_target()
+                .map_or(0, |target| (target - min_charge).max(0));
+            if target > 0 {
+                edges += 1;
+            }
+            if battery.get_capacity() - min_charge - target > 0 {
+                nodes += 1;
+                edges += 3;
+            }
+        }
+        let green_battery_output: i64 = batteries
+            .iter()
+            .filter(|battery| !battery.is_grid_charging_allowed())
+            .map(|battery| battery.get_max_output())
+            .sum();
+        if batteries
+            .iter()
+            .any(|battery| !battery.is_grid_charging_allowed())
+        {
+            nodes += steps;
+        }
+        if green_battery_output > 0 {
+            edges += steps;
+        }
+        // Batteries that may charge from the grid only discharge over the grid output if
+        // they may export
+        let exporting = context.get_feed_in_tariff().is_some();
+        let grid_battery_output: i64 = batteries
+            .iter()
+            .filter(|battery| exporting && battery.is_grid_charging_allowed())
+            .map(|battery| battery.get_max_output())
+            .sum();
+        if exporting
+            && batteries
+                .iter()
+                .any(|battery| battery.is_grid_charging_allowed())
+        {
+            nodes += steps;
+        }
+        if grid_battery_output > 0 {
+            edges += steps;
+        }
+
+        for action in context.get_variable_actions() {
+            nodes += 1;
+            edges += (action.get_start()..action.get_end()).iter_steps().count() + 1;
+        }
+
+        if exporting {
+            let max_export = context.get_max_export().unwrap_or(i64::MAX);
+            let fraction = context.get_first_timestep_fraction();
+            for t in 0..context.get_steps() {
+                let time = Time::from_timestep(t);
+                let generated =
+                    (*context.get_generated_electricity().get(time).unwrap_or(&0)).max(0);
+                let battery_output = scale_first_timestep(green_battery_output, time, fraction);
+                let grid_battery_output = scale_first_timestep(grid_battery_output, time, fraction);
+                if (generated + battery_output + grid_battery_output).min(max_export) <= 0 {
+                    continue;
+                }
+                // Export node with its bypass and the edge to the sink
+                nodes += 1;
+                edges += 2
+                    + usize::from(generated > 0)
+                    + usize::from(battery_output > 0)
+                    + usize::from(grid_battery_output > 0);
+            }
+        }
+        FlowSize { nodes, edges }
+    }
+
+    /// Like `from_context`, but leaves out the constraints selected in `relaxation`.
+    pub fn from_context_with_relaxation(
+        context: &OptimizerContext,
+        relaxation: Relaxation,
+    ) -> Self {
+        let mut builder = Self::new(
+            context.get_generated_electricity(),
+            context.get_electricity_price(),
+            context.get_beyond_control_consumption(),
+            context.get_first_timestep_fraction(),
+        )
+        .with_relaxation(relaxation);
+        if let Some(network_fee) = context.get_network_fee() {
+            builder = builder.with_network_fee(network_fee);
+        }
+        if let (Some(co2_intensity), Some(co2_cost)) =
+            (context.get_co2_intensity(), context.get_co2_cost())
+        {
+            builder = builder.with_co2(co2_intensity, &co2_cost);
+        }
+        if let Some(feed_in_tariff) = context.get_feed_in_tariff() {
+            builder = builder.with_feed_in_tariff(feed_in_tariff, context.get_max_export());
+        }
+        builder = builder
+            .add_batteries(context.get_batteries())
+            .add_fixed_batteries(context.get_fixed_batteries())
+            .add_actions(context.get_variable_actions());
+        if let Some(grid_limit) = context.get_grid_limit() {
+            builder = builder.with_grid_limit(grid_limit);
+        }
+        builder.with_settlement_periods(context.get_settlement_periods())
+    }
+
+    /// Measures the peak grid draw as the average over the given settlement periods.
+    pub fn with_settlement_periods(mut self, settlement_periods: SettlementPeriods) -> Self {
+        self.blueprint.set_settlement_periods(settlement_periods);
+        self
+    }
+
+    /// Charges `network_fee` on top of the electricity price for everything drawn from the
+    /// grid. Only applies to the charging losses of batteries added afterwards.
+    pub fn with_network_fee(mut self, network_fee: &Prognoses<i64>) -> Self {
+        self.blueprint.set_network_fee(network_fee.clone());
+        self.update_import_costs();
+        self
+    }
+
+    /// Charges the CO2 emitted by everything drawn from the grid on top of the electricity
+    /// price, `co2_cost` being its price per unit and `co2_intensity` the emissions per unit
+    /// reported by the schedules. Only applies to the charging losses of batteries added
+    /// afterwards.
+    pub fn with_co2(mut self, co2_intensity: &Prognoses<i64>, co2_cost: &Prognoses<i64>) -> Self {
+        self.blueprint
+            .set_co2(co2_intensity.clone(), co2_cost.clone());
+        self.update_import_costs();
+        self
+    }
+
+    /// Sets the cost of the network edges to the import price of the blueprint.
+    fn update_import_costs(&mut self) {
+        for (t, edge_id) in self.network_edges.iter().enumerate() {
+            let price = self.blueprint.import_price(Time::from_timestep(t as u32));
+            self.flow.set_cost(*edge_id, price);
         }
     }
 
+    /// Leaves out the constraints selected in `relaxation`.
+    /// Only applies to batteries and actions added afterwards.
+    pub fn with_relaxation(mut self, relaxation: Relaxation) -> Self {
+        self.relaxation = relaxation;
+        self
+    }
+
     pub fn add_battery(mut self, battery: &Rc<Battery>) -> Self {
         let id = battery.get_id();
-        let mut battery_blueprint = BatteryBlueprint::new(battery.clone());
+        let mut battery_blueprint = BatteryBlueprint::new(battery.clone(), self.timestep);
+        let steps = self.timestep.get_steps_per_day();
 
-        // Initialize battery
-        let initial_level = battery.get_initial_level() as i64;
-        self.flow.add_edge(
+        // Initialize battery. The reserve below min_charge is never available, so only the
+        // usable charge above it flows through the battery nodes.
+        let min_charge = battery.get_min_charge();
+        let initial_level = battery.get_initial_level() - min_charge;
+        // Charging or discharging more than the usable capacity in one timestep is impossible,
+        // so it is as good as an unlimited rate.
+        let unlimited_rate = battery.get_capacity() - min_charge;
+        // The initial charge is in the battery, the flow mustn't drop it to make room for
+        // electricity bought at a negative price. The bonus outweighs what a unit bought at
+        // the lowest price earns and is removed from the cost again in `calc_flow`.
+        let bonus = self.initial_charge_bonus();
+        let edge_id = self.flow.add_priced_edge(
             FlowNode::Source,
             FlowNode::Battery(id as usize, Time::from_timestep(0)),
             initial_level,
-            0,
+            -bonus,
+            EdgeCategory::InitialCharge,
         );
+        self.initial_charge_edges.push((edge_id, bonus));
 
         // Wire to Batteries
-        for t in 0..STEPS_PER_DAY {
-            let max_charge = if t == 0 {
+        for t in 0..steps {
+            let max_charge = if self.relaxation.battery_power {
+                unlimited_rate
+            } else if t == 0 {
                 (battery.get_max_charge() as f32 * self.first_timestep_fraction).round() as i64
             } else {
                 battery.get_max_charge()
-            } as i64;
+            };
 
-            // Wire to battery
-            self.flow.add_edge(
-                FlowNode::Wire(Time::from_timestep(t)),
-                FlowNode::Battery(id as usize, Time::from_timestep(t)),
-                max_charge,
-                0,
-            );
+            // Wire to battery, or green wire to battery if only generated electricity may be stored.
+            // Every stored unit also pays for the wear of the battery, which is split off the
+            // charging loss again in the cost breakdown.
+            let loss_price = self.charging_loss_cost(battery, Time::from_timestep(t));
+            let charge_cost = loss_price + battery.get_cycle_cost();
+            let charge_from = if battery.is_grid_charging_allowed() {
+                Some(FlowNode::Wire(Time::from_timestep(t)))
+            } else if self.has_generation(Time::from_timestep(t)) {
+                Some(FlowNode::Green(Time::from_timestep(t)))
+            } else {
+                None
+            };
+            if let Some(charge_from) = charge_from {
+                let edge_id = self.flow.add_priced_edge(
+                    charge_from.clone(),
+                    FlowNode::Battery(id as usize, Time::from_timestep(t)),
+                    max_charge,
+                    charge_cost,
+                    EdgeCategory::ChargingLoss,
+                );
+                if !battery.is_grid_charging_allowed() {
+                    battery_blueprint.set_green_charge_edge(Time::from_timestep(t), edge_id);
+                }
+                battery_blueprint.add_charge_edge(edge_id);
+                // The loss is drawn from where the battery charges from, once the flow knows
+                // how much it charges, see `SmartHomeFlow::draw_charging_losses`
+                if battery.get_efficiency() < 1.0 {
+                    let loss_edge = self.flow.add_edge(charge_from, FlowNode::Sink, 0, 0);
+                    self.lossy_charges.push(LossyCharge {
+                        time: Time::from_timestep(t),
+                        charge_edge: edge_id,
+                        loss_edge,
+                        loss_factor: 1.0 / battery.get_efficiency() as f64 - 1.0,
+                        loss_price,
+                        from_grid: battery.is_grid_charging_allowed(),
+                    });
+                }
+            }
 
-            let max_output = if t == 0 {
+            let max_output = if self.relaxation.battery_power {
+                unlimited_rate
+            } else if t == 0 {
                 (battery.get_max_output() as f32 * self.first_timestep_fraction).round() as i64
             } else {
                 battery.get_max_output()
-            } as i64;
+            };
 
-            // Battery to wire
-            self.flow.add_edge(
+            // Battery to wire. Batteries with only generated electricity discharge over the
+            // green output, the others over the grid output if they may export. Both also
+            // feed the export.
+            let output = if !battery.is_grid_charging_allowed() {
+                FlowNode::GreenOutput(Time::from_timestep(t))
+            } else if self.feed_in.is_some() {
+                FlowNode::GridOutput(Time::from_timestep(t))
+            } else {
+                FlowNode::Wire(Time::from_timestep(t))
+            };
+            let edge_id = self.flow.add_edge(
                 FlowNode::Battery(id as usize, Time::from_timestep(t)),
-                FlowNode::Wire(Time::from_timestep(t)),
+                output,
                 max_output,
                 0,
             );
+            battery_blueprint.set_output_edge(Time::from_timestep(t), edge_id);
         }
 
-        // Battery persistence
-        for t in 0..STEPS_PER_DAY {
-            let edge_id = self.flow.add_edge(
+        let output_rate = if self.relaxation.battery_power {
+            unlimited_rate
+        } else {
+            battery.get_max_output()
+        };
+        if !battery.is_grid_charging_allowed() {
+            self.green_battery_output += output_rate;
+        } else if self.feed_in.is_some() {
+            self.grid_battery_output += output_rate;
+        }
+
+        // Battery persistence. The edge into t + 1 carries the level at the start of t + 1,
+        // after charging and discharging during t.
+        let self_discharge_costs = self.self_discharge_costs(battery);
+        for t in 0..steps {
+            let edge_id = self.flow.add_priced_edge(
                 FlowNode::Battery(id as usize, Time::from_timestep(t)),
                 FlowNode::Battery(id as usize, Time::from_timestep(t + 1)),
-                battery.get_capacity() as i64,
-                0,
+                battery.get_capacity() - min_charge,
+                self_discharge_costs[t as usize],
+                EdgeCategory::SelfDischarge,
             );
             battery_blueprint.set_relevant_edge(Time::from_timestep(t + 1), edge_id);
         }
+
+        // The flow is maximized, so the charge target at the end of the day is always filled
+        // if it can be reached at all.
+        let target = battery
+            .get_final_charge_target()
+            .map_or(0, |target| (target - min_charge).max(0));
+        if target > 0 {
+            let edge_id = self.flow.add_edge(
+                FlowNode::Battery(id as usize, Time::from_timestep(steps)),
+                FlowNode::Sink,
+                target,
+                0,
+            );
+            battery_blueprint.set_target_edge(edge_id);
+        }
+
+        // The rest of the capacity may stay charged beyond the end of the day, which pays off
+        // when electricity is bought at a negative price. Like the export, a zero cost bypass
+        // Source -> BatteryEnd fills this capacity whenever keeping the charge isn't worth it.
+        let keep = battery.get_capacity() - min_charge - target;
+        if keep > 0 {
+            let end = FlowNode::BatteryEnd(id as usize);
+            self.flow.add_edge(
+                FlowNode::Battery(id as usize, Time::from_timestep(steps)),
+                end.clone(),
+                keep,
+                0,
+            );
+            self.flow.add_edge(FlowNode::Source, end.clone(), keep, 0);
+            self.flow.add_edge(end, FlowNode::Sink, keep, 0);
+        }
         self.blueprint.add_battery_blueprint(battery_blueprint);
         self
     }
 
+    /// Bonus per unit of initial charge kept in a battery, higher than any negative import price.
+    fn initial_charge_bonus(&self) -> i64 {
+        let lowest_price = (0..self.timestep.get_steps_per_day())
+            .map(|t| self.blueprint.import_price(Time::from_timestep(t)))
+            .min()
+            .unwrap_or(0);
+        (-lowest_price).max(0) + 1
+    }
+
+    /// Whether any electricity is generated at the given time, i.e. whether the green wire exists.
+    fn has_generation(&self, time: Time) -> bool {
+        *self.generated_electricity.get(time).unwrap_or(&0) > 0
+    }
+
+    /// Cost per stored unit of energy caused by charging losses at the given time.
+    ///
+    /// A battery with efficiency `e` has to draw `1 / e` units to store one unit. The flow
+    /// stays in stored (net) energy, which keeps the reported charge levels exact, and only
+    /// draws the additional `1 / e - 1` units once it knows how much it charges, see
+    /// `SmartHomeFlow::draw_charging_losses`. Until then this price on the charging edges
+    /// stands in for them, so that the flow charges as if it paid for the loss: the grid price
+    /// of that timestep including the network fee, or the feed-in tariff the lost generation
+    /// could have earned for batteries that only store generated electricity. It is removed
+    /// from the cost again. Negative prices never make charging losses profitable.
+    fn charging_loss_cost(&self, battery: &Battery, time: Time) -> i64 {
+        let price = if battery.is_grid_charging_allowed() {
+            self.blueprint.import_price(time)
+        } else {
+            self.feed_in
+                .as_ref()
+                .map_or(0, |(tariff, _)| *tariff.get(time).unwrap_or(&0))
+        };
+        let loss_factor = 1.0 / battery.get_efficiency() as f64 - 1.0;
+        (price as f64 * loss_factor).round().max(0.0) as i64
+    }
+
+    /// Cost per unit kept in the battery over each timestep, for the energy lost to
+    /// self-discharge during it.
+    ///
+    /// Like charging losses, the flow can't lose part of what it carries, so the loss is only
+    /// priced: a unit kept over timestep `t` loses the self-discharge per step of it, priced
+    /// at the grid price of `t` as if it were bought again. The charge levels stay the ones
+    /// of the flow. The loss is a fraction of a cost unit per timestep, so the costs of the
+    /// steps are rounded from their running sum, which keeps the cost of keeping a unit over
+    /// any span within one unit of the exact value.
+    fn self_discharge_costs(&self, battery: &Battery) -> Vec<i64> {
+        let loss = battery.get_self_discharge_per_step(self.timestep);
+        let mut total = 0.0;
+        let mut charged = 0;
+        (0..self.timestep.get_steps_per_day())
+            .map(|t| {
+                let price = self.blueprint.import_price(Time::from_timestep(t)).max(0);
+                total += price as f64 * loss;
+                let cost = total.round() as i64 - charged;
+                charged += cost;
+                cost
+            })
+            .collect()
+    }
+
+    /// Reports batteries with a fixed dispatch in the schedule. They don't add any edges, their
+    /// net power is already part of the prognoses, see `OptimizerContext::with_fixed_battery_plan`.
+    pub fn add_fixed_batteries(mut self, batteries: &[AssignedBattery]) -> Self {
+        for battery in batteries {
+            self.blueprint.add_fixed_battery(battery.clone());
+        }
+        self
+    }
+
     pub fn add_batteries(mut self, batteries: &Vec<Rc<Battery>>) -> Self {
         for battery in batteries {
             self = self.add_battery(battery);
@@ -297,66 +1356,361 @@ impl SmartHomeFlowBuilder {
         self
     }
     pub fn add_action(mut self, action: &Rc<VariableAction>) -> Self {
+        let action = &if self.relaxation.action_windows {
+            Rc::new(VariableAction::new(
+                Time::from_timestep(0),
+                self.timestep.get_day_end(),
+                action.get_total_consumption(),
+                action.get_max_consumption(),
+                action.get_id(),
+            ))
+        } else {
+            action.clone()
+        };
         let mut variable_action_blueprint = VariableActionBlueprint::new(action.clone());
+        let typical_price = self.typical_price();
         for t in (action.get_start()..action.get_end()).iter_steps() {
-            let max_consumption = if t.to_timestep() == 0 {
-                (action.get_max_consumption() as f32 * self.first_timestep_fraction).round() as i64
-            } else {
-                action.get_max_consumption()
-            } as i64;
-            // Wire to action
-            let edge_id = self.flow.add_edge(
+            let max_consumption = scale_first_timestep(
+                action.get_max_consumption_at(t),
+                t,
+                self.first_timestep_fraction,
+            );
+            // Wire to action, with the preference of the action as cost
+            let edge_id = self.flow.add_priced_edge(
                 FlowNode::Wire(t),
                 FlowNode::Action(action.get_id() as usize),
                 max_consumption,
-                0,
+                action.get_preference_at(t, typical_price),
+                EdgeCategory::Preference,
             );
             variable_action_blueprint.set_relevant_edge(t, edge_id);
         }
 
         // Action to Sink
-        self.flow.add_edge(
+        let edge_id = self.flow.add_edge(
             FlowNode::Action(action.get_id() as usize),
             FlowNode::Sink,
-            action.get_total_consumption() as i64,
+            action.get_total_consumption(),
             0,
         );
+        variable_action_blueprint.set_demand_edge(edge_id);
+        self.demand_edges.push(edge_id);
+        self.required_consumption += action.get_total_consumption();
 
         self.blueprint
             .add_variable_action_blueprint(variable_action_blueprint);
         self
     }
+    /// Average absolute electricity price, the preferences of variable actions are bounded
+    /// relative to it.
+    fn typical_price(&self) -> i64 {
+        let prices = self.electricity_price.get_data();
+        prices.iter().map(|price| price.abs()).sum::<i64>() / prices.len().max(1) as i64
+    }
     pub fn add_actions(mut self, variable_actions: &Vec<Rc<VariableAction>>) -> Self {
         for action in variable_actions {
             self = self.add_action(action);
         }
         self
     }
-    pub fn build(mut self) -> SmartHomeFlow {
-        // self.flow.mincostflow();
-        SmartHomeFlow::new(self.flow, self.blueprint)
+    /// Adds a constant action as if its consumption could be spread freely over its window,
+    /// with at most its highest profile value per timestep.
+    ///
+    /// Every placement of the action fits into this, so the resulting cost is a lower bound
+    /// for all placements. The consumption is priced like constant consumption in
+    /// `SmartHomeFlow`. The action doesn't show up in the schedule.
+    pub fn add_relaxed_constant_action(mut self, action: &Rc<ConstantAction>) -> Self {
+        let (start, end) = if self.relaxation.action_windows {
+            (Time::from_timestep(0), self.timestep.get_day_end())
+        } else {
+            (action.get_start_from(), action.get_end_before())
+        };
+        let profile = action.get_profile();
+        let max_consumption = profile.iter().copied().max().unwrap_or(0);
+        let node = FlowNode::RelaxedAction(action.get_id() as usize);
+        for t in (start..end).iter_steps() {
+            self.flow
+                .add_edge(FlowNode::Wire(t), node.clone(), max_consumption, 0);
+        }
+        let total = profile.iter().sum();
+        let edge_id = self.flow.add_edge(node, FlowNode::Sink, total, 0);
+        self.demand_edges.push(edge_id);
+        self.required_consumption += total;
+        self
     }
-}
+    /// Allows exporting electricity to the grid, paid with `feed_in_tariff`.
+    /// The export edges are added in `build`, once all batteries are known. Batteries that may
+    /// charge from the grid only export if they are added afterwards.
+    pub fn with_feed_in_tariff(
+        mut self,
+        feed_in_tariff: &Prognoses<i64>,
+        max_export: Option<i64>,
+    ) -> Self {
+        self.feed_in = Some((feed_in_tariff.clone(), max_export));
+        self
+    }
+
+    /// Limits the electricity drawn from the grid to `grid_limit` per timestep.
+    /// Consumption that can't be served within the limit is left unserved by the flow,
+    /// see `SmartHomeFlow::get_unserved_consumption`.
+    pub fn with_grid_limit(mut self, grid_limit: i64) -> Self {
+        self.grid_limit = Some(grid_limit);
+        self
+    }
+
+    /// Adds the edges for exporting electricity to the grid.
+    ///
+    /// Generated electricity and battery discharge are exported: they flow Green(t) ->
+    /// Export(t) -> Sink, GreenOutput(t) -> Export(t) -> Sink from batteries that can't charge
+    /// from the grid or GridOutput(t) -> Export(t) -> Sink from the others, with the negative
+    /// tariff as cost. Electricity bought in a timestep can't be sold in it without passing
+    /// through a battery, so the export is capped by the generation and the discharge rates
+    /// and a tariff above the purchase price can't make buying and selling unbounded.
+    /// Since the flow is always maximized, a zero cost bypass Source -> Export(t) fills the
+    /// export capacity whenever exporting isn't worth it.
+    fn add_grid_export(&mut self, feed_in_tariff: &Prognoses<i64>, max_export: Option<i64>) {
+        let mut grid_export_blueprint = GridExportBlueprint::new(self.timestep);
+        let max_export = max_export.filter(|_| !self.relaxation.grid_limit);
+        for t in 0..self.timestep.get_steps_per_day() {
+            let time = Time::from_timestep(t);
+            let generated = (*self.generated_electricity.get(time).unwrap_or(&0)).max(0);
+            let battery_output = scale_first_timestep(
+                self.green_battery_output,
+                time,
+                self.first_timestep_fraction,
+            );
+            let grid_battery_output =
+                scale_first_timestep(self.grid_battery_output, time, self.first_timestep_fraction);
+            let cap = (generated + battery_output + grid_battery_output)
+                .min(max_export.unwrap_or(i64::MAX));
+            if cap <= 0 {
+                continue;
+            }
+            let tariff = *feed_in_tariff.get(time).unwrap_or(&0);
+
+            // Generation and green battery output to export
+            if generated > 0 {
+                let edge_id = self.flow.add_priced_edge(
+                    FlowNode::Green(time),
+                    FlowNode::Export(time),
+                    generated.min(cap),
+                    -tariff,
+                    EdgeCategory::GridExport,
+                );
+                grid_export_blueprint.add_relevant_edge(time, edge_id);
+            }
+            if battery_output > 0 {
+                let edge_id = self.flow.add_priced_edge(
+                    FlowNode::GreenOutput(time),
+                    FlowNode::Export(time),
+                    battery_output.min(cap),
+                    -tariff,
+                    EdgeCategory::GridExport,
+                );
+                grid_export_blueprint.add_relevant_edge(time, edge_id);
+            }
+            if grid_battery_output > 0 {
+                let edge_id = self.flow.add_priced_edge(
+                    FlowNode::GridOutput(time),
+                    FlowNode::Export(time),
+                    grid_battery_output.min(cap),
+                    -tariff,
+                    EdgeCategory::GridExport,
+                );
+                grid_export_blueprint.add_relevant_edge(time, edge_id);
+            }
+
+            // Bypass for not exporting
+            self.flow
+                .add_edge(FlowNode::Source, FlowNode::Export(time), cap, 0);
+
+            // Export to sink
+            self.flow
+                .add_edge(FlowNode::Export(time), FlowNode::Sink, cap, 0);
+        }
+        self.blueprint
+            .set_grid_export_blueprint(grid_export_blueprint);
+    }
+
+    pub fn build(mut self) -> SmartHomeFlow {
+        // self.flow.mincostflow();
+        if let Some((feed_in_tariff, max_export)) = self.feed_in.take() {
+            self.add_grid_export(&feed_in_tariff, max_export);
+        }
+        if let Some(grid_limit) = self.grid_limit.filter(|_| !self.relaxation.grid_limit) {
+            for (t, edge_id) in self.network_edges.iter().enumerate() {
+                let cap = scale_first_timestep(
+                    grid_limit,
+                    Time::from_timestep(t as u32),
+                    self.first_timestep_fraction,
+                );
+                self.flow.set_capacity(*edge_id, cap);
+            }
+        }
+        // Battery output that isn't exported is used at home
+        if self.green_battery_output > 0 {
+            for t in 0..self.timestep.get_steps_per_day() {
+                self.flow.add_edge(
+                    FlowNode::GreenOutput(Time::from_timestep(t)),
+                    FlowNode::Wire(Time::from_timestep(t)),
+                    i64::MAX,
+                    0,
+                );
+            }
+        }
+        if self.grid_battery_output > 0 {
+            for t in 0..self.timestep.get_steps_per_day() {
+                self.flow.add_edge(
+                    FlowNode::GridOutput(Time::from_timestep(t)),
+                    FlowNode::Wire(Time::from_timestep(t)),
+                    i64::MAX,
+                    0,
+                );
+            }
+        }
+        let mut flow = SmartHomeFlow::new(self.flow, self.blueprint);
+        flow.demand_edges = self.demand_edges;
+        flow.consumption_edges = self.consumption_edges;
+        flow.base_consumption = self.required_consumption;
+        flow.first_timestep_fraction = self.first_timestep_fraction;
+        flow.network_edges = self.network_edges;
+        flow.initial_charge_edges = self.initial_charge_edges;
+        flow.lossy_charges = self.lossy_charges;
+        flow
+    }
+}
+
+/// Charging edge of a battery that loses energy while charging, with the edge into the sink
+/// its loss is drawn over, see `SmartHomeFlow::draw_charging_losses`.
+struct LossyCharge {
+    time: Time,
+    charge_edge: usize,
+    /// Wire -> Sink for batteries that may charge from the grid, Green -> Sink for the others.
+    loss_edge: usize,
+    /// Energy lost per unit stored, `1 / e - 1` for efficiency `e`.
+    loss_factor: f64,
+    /// Price per stored unit on the charging edge until the loss is drawn, see
+    /// `SmartHomeFlowBuilder::charging_loss_cost`.
+    loss_price: i64,
+    /// Whether the battery may charge from the grid.
+    from_grid: bool,
+}
+
+impl LossyCharge {
+    /// Energy lost by storing `stored`.
+    fn loss(&self, stored: i64) -> i64 {
+        (stored as f64 * self.loss_factor).round() as i64
+    }
+
+    /// Most that can be stored without losing more than `loss`.
+    fn max_stored(&self, loss: i64) -> i64 {
+        // Rounding keeps the loss at most `loss` below `(loss + 0.5) / loss_factor`
+        let mut stored = ((loss as f64 + 0.5) / self.loss_factor).ceil() as i64 - 1;
+        while self.loss(stored + 1) <= loss {
+            stored += 1;
+        }
+        while self.loss(stored) > loss {
+            stored -= 1;
+        }
+        stored
+    }
+}
+
+/// The builder hands the blueprint and the `SmartHomeFlow` only edges of the flow it builds, and
+/// popping the flow stack never goes below the state that holds them.
+const BLUEPRINT_EDGES: &str = "Edges of the blueprint belong to the flow";
+
 pub struct SmartHomeFlow {
     flow: StackProxy<FlowWrapper>,
 
-    constant_actions: HashMap<u32, AssignedConstantAction>,
+    // Ordered so that the constant consumption is always summed up in the same order.
+    constant_actions: BTreeMap<u32, AssignedConstantAction>,
 
     calc_result: Option<i64>,
 
     blueprint: SmartHomeBlueprint,
+
+    flow_stats: FlowStats,
+
+    /// Edges into the sink that serve the consumption added by the builder.
+    demand_edges: Vec<usize>,
+    /// Wire -> Sink edges of the beyond control consumption, by timestep.
+    consumption_edges: Vec<(Time, usize)>,
+    /// Consumption added by the builder, without the constant actions.
+    base_consumption: i64,
+    first_timestep_fraction: f32,
+    /// Consumption the last solve couldn't serve.
+    unserved_consumption: i64,
+    /// Edges from the network to the wire, one per timestep.
+    network_edges: Vec<usize>,
+    /// Highest average network consumption per timestep of any settlement period in the last
+    /// solve.
+    peak_network_consumption: i64,
+    /// Source -> Battery edges of the initial charges and the bonus per unit on them, which
+    /// isn't part of the cost.
+    initial_charge_edges: Vec<(usize, i64)>,
+    /// Charging edges of batteries that lose energy while charging, with their loss edges.
+    lossy_charges: Vec<LossyCharge>,
+    /// Wire -> Sink edge of every timestep, serving all constant actions placed there. Their
+    /// consumption adds up and must be served in full, see `get_unserved_constant_actions`.
+    constant_edges: Vec<usize>,
+    /// Whether the state above the base state of the flow stack holds a solved flow that
+    /// `update_constant_demand` can adapt to other constant actions.
+    solved: bool,
+}
+
+/// Counts how often a `SmartHomeFlow` had to be solved and how often a cached result was reused.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlowStats {
+    pub solves: usize,
+    pub reuses: usize,
+    pub solve_time: Duration,
+    /// Paths the solves sent flow along, including those of updating a solved flow.
+    pub augmentations: u64,
+    /// Negative cycles the solves sent flow around.
+    pub cycle_cancellations: u64,
+}
+
+impl FlowStats {
+    fn add_counters(&mut self, counters: SolveCounters) {
+        self.augmentations += counters.augmentations;
+        self.cycle_cancellations += counters.cycle_cancellations;
+    }
+    /// Average time of a single flow solve, zero if nothing was solved yet.
+    pub fn average_solve_time(&self) -> Duration {
+        if self.solves == 0 {
+            Duration::ZERO
+        } else {
+            self.solve_time / self.solves as u32
+        }
+    }
 }
 
-// WARNING: wire has ID = 0, make sure no node uses this ID!
 impl SmartHomeFlow {
-    pub fn new(flow: FlowWrapper, blueprint: SmartHomeBlueprint) -> Self {
-        let mut flow: StackProxy<FlowWrapper> = StackProxy::new(flow);
-        flow.push();
+    pub fn new(mut flow: FlowWrapper, blueprint: SmartHomeBlueprint) -> Self {
+        // Constant actions only change the capacities of these edges. Like every edge into the
+        // sink they are free, the consumption is paid for where it is drawn from.
+        let constant_edges = (0..blueprint.electricity_price.len())
+            .map(|t| flow.add_edge(FlowNode::Wire(Time::from_timestep(t)), FlowNode::Sink, 0, 0))
+            .collect();
+        // The base state is never solved, `calc_flow` pushes a copy of it first.
         SmartHomeFlow {
-            flow,
-            constant_actions: HashMap::new(),
+            flow: StackProxy::new(flow),
+            constant_actions: BTreeMap::new(),
             calc_result: None,
             blueprint,
+            flow_stats: FlowStats::default(),
+            demand_edges: Vec::new(),
+            consumption_edges: Vec::new(),
+            base_consumption: 0,
+            first_timestep_fraction: 1.0,
+            unserved_consumption: 0,
+            network_edges: Vec::new(),
+            peak_network_consumption: 0,
+            initial_charge_edges: Vec::new(),
+            lossy_charges: Vec::new(),
+            constant_edges,
+            solved: false,
         }
     }
 
@@ -372,45 +1726,406 @@ impl SmartHomeFlow {
         self.constant_actions.remove(&id)
     }
 
-    fn calc_flow(&mut self) {
+    /// Adapts the solved flow to a new constant consumption per timestep, without solving it
+    /// again. Returns false if that isn't possible because some consumption can't be served,
+    /// the flow has to be solved from scratch then.
+    fn update_constant_demand(&mut self, constant_demand: &[i64]) -> Result<bool, SolverStalled> {
+        let changed: Vec<_> = self
+            .constant_edges
+            .iter()
+            .zip(constant_demand)
+            .filter(|&(&edge_id, &demand)| self.flow.get_capacity(edge_id) != demand)
+            .map(|(&edge_id, &demand)| (edge_id, demand))
+            .collect();
+        let updated = self.flow.set_sink_capacities(&changed)?;
+        self.flow_stats.add_counters(self.flow.get_counters());
+        Ok(updated)
+    }
+
+    fn calc_flow(&mut self) -> Result<(), FlowSolveError> {
         let start = Instant::now();
-        self.flow.pop();
-        self.flow.push();
-
-        let inner_start = Instant::now();
-        for (_, constant_action) in &self.constant_actions {
-            let start = constant_action.get_start_time().to_timestep() as usize;
-            let end = constant_action.get_end_time().to_timestep() as usize;
-            for t in start..end {
-                // Wire to sink
-                self.flow.add_edge(
-                    FlowNode::Wire(Time::from_timestep(t as u32)),
-                    FlowNode::Sink,
-                    constant_action.get_consumption() as i64,
-                    1,
-                );
+        // Wire to sink, following the profiles from the assigned start times
+        let mut constant_demand = vec![0; self.constant_edges.len()];
+        for constant_action in self.constant_actions.values() {
+            for (time, consumption) in constant_action.iter_consumption() {
+                constant_demand[time.to_timestep() as usize] +=
+                    scale_first_timestep(consumption, time, self.first_timestep_fraction);
+            }
+        }
+        // Changing a few constant actions only changes a small part of the flow. It is updated
+        // in place as long as the last solve served everything, solved from scratch otherwise.
+        // A solve that fails leaves the flow half done, so the next one starts over.
+        let solved = std::mem::take(&mut self.solved);
+        // Drops the copy the charging losses were drawn on
+        self.flow.shrink_to(1);
+        if !(solved && self.update_constant_demand(&constant_demand)?) {
+            // Start over from the state without constant actions.
+            self.flow.shrink_to(0);
+            self.flow.push()?;
+            for (&edge_id, &demand) in self.constant_edges.iter().zip(&constant_demand) {
+                self.flow.set_capacity(edge_id, demand);
             }
+            self.flow.mincostflow()?;
+            self.flow_stats.add_counters(self.flow.get_counters());
         }
-        println!("start flow");
-        let (flow_cost, flow_value) = self.flow.mincostflow();
-        self.calc_result = Some(flow_cost);
-        println!("Total flow: {}, Total cost: {}", flow_value, flow_cost);
-        let inner_duration = inner_start.elapsed();
-        println!("Flow setup took: {:?}", inner_duration);
+        self.solved = self.flow.is_sink_saturated();
+        self.draw_charging_losses()?;
+
+        let flow_cost = self.flow.get_cost();
+        let initial_charge_bonus: i64 = self
+            .initial_charge_edges
+            .iter()
+            .map(|&(edge_id, bonus)| self.flow_of(edge_id) * bonus)
+            .sum();
+        self.calc_result = Some(flow_cost + initial_charge_bonus);
+
+        let required = self.base_consumption + constant_demand.iter().sum::<i64>();
+        let served: i64 = self
+            .demand_edges
+            .iter()
+            .chain(&self.constant_edges)
+            .map(|&edge_id| self.flow_of(edge_id))
+            .sum();
+        debug_assert!(served <= required, "Served more than was required");
+        self.unserved_consumption = required - served;
+        let network_consumption: Vec<i64> = self
+            .network_edges
+            .iter()
+            .map(|&edge_id| self.flow_of(edge_id))
+            .collect();
+        self.peak_network_consumption = self
+            .blueprint
+            .settlement_periods
+            .averages(&network_consumption)
+            .into_iter()
+            .map(|(_, average)| average)
+            .max()
+            .unwrap_or(0);
         let duration = start.elapsed();
-        println!("Flow calculation took: {:?}", duration);
+        self.flow_stats.solves += 1;
+        self.flow_stats.solve_time += duration;
+        Ok(())
     }
-    pub fn get_cost(&mut self) -> i64 {
-        if self.calc_result.is_none() {
-            self.calc_flow();
+    /// Draws the energy the batteries lose while charging over their loss edges, on a copy of
+    /// the solved flow that `update_constant_demand` can't adapt anymore. The drawn loss is
+    /// paid for where it comes from, so the loss price is taken off the charging edges
+    /// afterwards.
+    ///
+    /// The flow can't lose part of what it carries, so the charging edges are capped at what
+    /// the flow charges and the loss of that is demanded from the loss edges. Drawing the
+    /// loss may push charging out, e.g. at the grid limit or when the generation can't cover
+    /// both. The charging edges are then capped halfway between the charging that is left
+    /// and their last cap, with the loss of that demanded, until charging and loss match.
+    /// Loss that can't be drawn at all caps the charging to what it allows. The caps only
+    /// ever go down, so this ends.
+    fn draw_charging_losses(&mut self) -> Result<(), FlowSolveError> {
+        if self
+            .lossy_charges
+            .iter()
+            .all(|charge| self.flow_of(charge.charge_edge) == 0)
+        {
+            return Ok(());
         }
-        self.calc_result.unwrap()
+        self.flow.push()?;
+        // Until the first loss is drawn the flow charges freely, cap it at what it charges
+        let mut first = true;
+        loop {
+            let mut capacities = Vec::with_capacity(self.lossy_charges.len());
+            let mut changed = false;
+            for charge in &self.lossy_charges {
+                let cap = self.flow.get_capacity(charge.charge_edge);
+                let stored = self.flow_of(charge.charge_edge);
+                let demanded = self.flow.get_capacity(charge.loss_edge);
+                let drawn = self.flow_of(charge.loss_edge);
+                let mut new_cap = if first {
+                    stored
+                } else {
+                    stored + (cap - stored) / 2
+                };
+                if drawn < demanded {
+                    new_cap = new_cap.min(charge.max_stored(drawn));
+                }
+                let loss = charge.loss(new_cap);
+                changed |= new_cap != cap || loss != demanded;
+                capacities.push((new_cap, loss));
+            }
+            if !changed {
+                break;
+            }
+            first = false;
+            // Loss that wasn't drawn leaves the sink unsaturated. Only then a cap can drop
+            // below the charging and the flow has to be solved again.
+            let updated = self.flow.is_sink_saturated() && {
+                let mut losses = Vec::new();
+                for (charge, &(cap, loss)) in self.lossy_charges.iter().zip(&capacities) {
+                    self.flow.lower_capacity(charge.charge_edge, cap);
+                    losses.push((charge.loss_edge, loss));
+                }
+                self.flow.set_sink_capacities(&losses)?
+            };
+            if !updated {
+                self.flow.clear_flow();
+                for (charge, &(cap, loss)) in self.lossy_charges.iter().zip(&capacities) {
+                    self.flow.set_capacity(charge.charge_edge, cap);
+                    self.flow.set_capacity(charge.loss_edge, loss);
+                }
+                self.flow.mincostflow()?;
+            }
+            self.flow_stats.add_counters(self.flow.get_counters());
+        }
+        for charge in &self.lossy_charges {
+            let cost = self.flow.get_edge_cost(charge.charge_edge) - charge.loss_price;
+            self.flow.reprice(charge.charge_edge, cost);
+        }
+        Ok(())
+    }
+    /// Flow over an edge the builder added.
+    fn flow_of(&self, edge_id: usize) -> i64 {
+        self.flow.get_flow(edge_id).expect(BLUEPRINT_EDGES)
     }
-    pub fn get_schedule(&mut self) -> Schedule {
+    /// Solves the flow unless the current result is still valid.
+    fn ensure_calculated(&mut self) -> Result<(), FlowSolveError> {
         if self.calc_result.is_none() {
-            self.calc_flow();
+            self.calc_flow()?;
+        } else {
+            self.flow_stats.reuses += 1;
+        }
+        Ok(())
+    }
+    /// Total cost of the current solution, solving the flow first if needed.
+    ///
+    /// Solving from scratch pushes a copy of the base state onto the flow stack, which fails
+    /// with a `StackError` if the stack can't hold it. The solve itself fails with
+    /// `SolverStalled` if it doesn't finish. The other getters fail the same way.
+    pub fn get_cost(&mut self) -> Result<i64, FlowSolveError> {
+        self.ensure_calculated()?;
+        Ok(self.calc_result.unwrap())
+    }
+    /// Every edge of the solved flow network, see [`Schedule::get_flow_table`].
+    pub fn get_flow_table(&mut self) -> Result<Vec<FlowEdge>, FlowSolveError> {
+        self.ensure_calculated()?;
+        Ok(self.flow.edge_table())
+    }
+    pub fn get_schedule(&mut self) -> Result<Schedule, FlowSolveError> {
+        self.ensure_calculated()?;
+        let mut schedule = self.blueprint.construct(&self.flow).expect(BLUEPRINT_EDGES);
+        schedule.unserved_constant_actions = self.get_unserved_constant_actions()?;
+        Ok(schedule)
+    }
+    /// Consumption of the constant actions the flow couldn't serve, ordered by time. The
+    /// constant actions of a timestep share one edge, so the flow can't tell which of them was
+    /// short. The actions are served in the order they start, then by id, and the shortfall
+    /// of a timestep is blamed on the actions last in that order.
+    pub fn get_unserved_constant_actions(
+        &mut self,
+    ) -> Result<Vec<UnservedConstantAction>, FlowSolveError> {
+        self.ensure_calculated()?;
+        let mut serve_order: Vec<_> = self.constant_actions.values().collect();
+        serve_order.sort_by_key(|action| (action.get_start_time(), action.get_id()));
+        let mut unserved = Vec::new();
+        for (t, &edge_id) in self.constant_edges.iter().enumerate() {
+            let mut shortfall = self.flow.get_capacity(edge_id) - self.flow_of(edge_id);
+            let time = Time::from_timestep(t as u32);
+            for action in serve_order.iter().rev() {
+                if shortfall <= 0 {
+                    break;
+                }
+                let consumption = scale_first_timestep(
+                    action.get_consumption(time),
+                    time,
+                    self.first_timestep_fraction,
+                );
+                let short = shortfall.min(consumption);
+                if short > 0 {
+                    unserved.push(UnservedConstantAction {
+                        id: action.get_id(),
+                        time,
+                        unserved: short,
+                    });
+                    shortfall -= short;
+                }
+            }
+        }
+        Ok(unserved)
+    }
+    pub fn get_flow_stats(&self) -> FlowStats {
+        self.flow_stats
+    }
+    /// Number of nodes and edges of the flow network.
+    pub fn get_flow_size(&self) -> FlowSize {
+        FlowSize {
+            nodes: self.flow.node_count(),
+            edges: self.flow.edge_count(),
+        }
+    }
+    /// Charge missing to the final charge target of every battery that doesn't reach it, by
+    /// battery id. The flow is maximized, so a target is only missed if it can't be reached,
+    /// e.g. because the battery can't charge enough before the end of the day.
+    pub fn get_unmet_charge_targets(&mut self) -> Result<Vec<(u32, i64)>, FlowSolveError> {
+        self.ensure_calculated()?;
+        let flow: &FlowWrapper = &self.flow;
+        Ok(self
+            .blueprint
+            .battery_blueprints
+            .iter()
+            .map(|blueprint| {
+                let missing = blueprint.unmet_target(flow).expect(BLUEPRINT_EDGES);
+                (blueprint.battery.get_id(), missing)
+            })
+            .filter(|&(_, missing)| missing > 0)
+            .collect())
+    }
+    /// Beyond control and variable action consumption that can't be served, final charge
+    /// targets that can't be reached and the limits in the way, or None if everything is met.
+    /// The flow serves as much as it can, so any shortfall is a problem of the context.
+    /// Constant actions that aren't served in full are reported by
+    /// `get_unserved_constant_actions` instead.
+    pub fn get_shortfall(&mut self) -> Result<Option<Shortfall>, FlowSolveError> {
+        let by_battery = self.get_unmet_charge_targets()?;
+        let flow: &FlowWrapper = &self.flow;
+        let by_time: Vec<(Time, i64)> = self
+            .consumption_edges
+            .iter()
+            .map(|&(time, edge_id)| (time, flow.get_capacity(edge_id) - self.flow_of(edge_id)))
+            .filter(|&(_, unserved)| unserved > 0)
+            .collect();
+        let mut by_action = Vec::new();
+        let mut binding = BTreeSet::new();
+        let mut times: BTreeSet<Time> = by_time.iter().map(|&(time, _)| time).collect();
+        for blueprint in &self.blueprint.variable_action_blueprints {
+            let unserved = blueprint.unserved(flow).expect(BLUEPRINT_EDGES);
+            if unserved == 0 {
+                continue;
+            }
+            let id = blueprint.variable_action.get_id();
+            by_action.push((id, unserved));
+            let room = blueprint.times_with_room(flow).expect(BLUEPRINT_EDGES);
+            if room.is_empty() {
+                binding.insert(BindingLimit::ActionMaxConsumption { id });
+            }
+            times.extend(room);
+        }
+        if by_time.is_empty() && by_action.is_empty() && by_battery.is_empty() {
+            return Ok(None);
+        }
+        for &time in &times {
+            let edge_id = self.network_edges[time.to_timestep() as usize];
+            if flow.get_capacity(edge_id) != i64::MAX
+                && self.flow_of(edge_id) >= flow.get_capacity(edge_id)
+            {
+                binding.insert(BindingLimit::GridLimit { time });
+            }
+            for blueprint in &self.blueprint.battery_blueprints {
+                binding.extend(blueprint.binding_at(flow, time).expect(BLUEPRINT_EDGES));
+            }
         }
-        self.blueprint.construct(&self.flow)
+        Ok(Some(Shortfall {
+            by_time,
+            by_action,
+            by_battery,
+            binding: binding.into_iter().collect(),
+        }))
+    }
+    /// How rounding variable actions to their minimum consumption in the schedule changes the
+    /// cost and the served consumption. Neither `get_cost` nor `get_unserved_consumption`
+    /// include it, since the flow can't express it.
+    pub fn get_min_consumption_rounding(
+        &mut self,
+    ) -> Result<MinConsumptionRounding, FlowSolveError> {
+        self.ensure_calculated()?;
+        Ok(self
+            .blueprint
+            .min_consumption_rounding(&self.flow)
+            .expect(BLUEPRINT_EDGES))
+    }
+    /// Consumption that can't be served, e.g. because an action window is too short for its
+    /// total consumption. Zero whenever the schedule serves all consumers.
+    pub fn get_unserved_consumption(&mut self) -> Result<i64, FlowSolveError> {
+        self.ensure_calculated()?;
+        Ok(self.unserved_consumption)
+    }
+    /// Parts of `get_cost` plus the cost of rounding variable actions to their minimum
+    /// consumption, see `get_min_consumption_rounding`. The penalty for unserved consumption
+    /// and the peak charge are left at zero, the flow doesn't know them.
+    pub fn get_cost_breakdown(&mut self) -> Result<CostBreakdown, FlowSolveError> {
+        self.ensure_calculated()?;
+        let costs = self.flow.cost_by_category();
+        let cost_of = |category| costs.get(&category).copied().unwrap_or(0);
+        debug_assert_eq!(
+            costs.values().sum::<i64>(),
+            self.flow.get_cost(),
+            "Flow cost not fully attributed to edge categories"
+        );
+        // Network edges cost the price plus the fee and the CO2 cost, split them up again
+        let import_cost_of = |cost: Option<&Prognoses<i64>>| -> i64 {
+            let Some(cost) = cost else {
+                return 0;
+            };
+            self.network_edges
+                .iter()
+                .enumerate()
+                .map(|(t, &edge_id)| {
+                    self.flow_of(edge_id) * cost.get(Time::from_timestep(t as u32)).unwrap_or(&0)
+                })
+                .sum()
+        };
+        let network_fee = import_cost_of(self.blueprint.network_fee.as_deref());
+        let co2 = import_cost_of(self.blueprint.co2.as_ref().map(|co2| &co2.1));
+        // Charging edges only cost the cycle cost once the charging losses are drawn. The
+        // losses are paid for where they are drawn from: split those drawn from the grid off
+        // the import. Losses drawn from generation only cost the export they replace.
+        let battery_wear = self
+            .blueprint
+            .battery_wear(&self.flow)
+            .expect(BLUEPRINT_EDGES);
+        let mut grid_losses = vec![0; self.network_edges.len()];
+        for charge in self.lossy_charges.iter().filter(|charge| charge.from_grid) {
+            grid_losses[charge.time.to_timestep() as usize] += self.flow_of(charge.loss_edge);
+        }
+        let charging_loss = self
+            .network_edges
+            .iter()
+            .zip(grid_losses)
+            .enumerate()
+            .map(|(t, (&edge_id, loss))| {
+                let price = self
+                    .blueprint
+                    .electricity_price
+                    .get(Time::from_timestep(t as u32));
+                loss.min(self.flow_of(edge_id)) * price.unwrap_or(&0)
+            })
+            .sum();
+        let breakdown = CostBreakdown {
+            grid_import: cost_of(EdgeCategory::GridImport) - network_fee - co2 - charging_loss,
+            network_fee,
+            co2,
+            charging_loss,
+            battery_wear,
+            self_discharge: cost_of(EdgeCategory::SelfDischarge),
+            grid_export: cost_of(EdgeCategory::GridExport),
+            preference: cost_of(EdgeCategory::Preference),
+            min_consumption_rounding: self
+                .blueprint
+                .min_consumption_rounding(&self.flow)
+                .expect(BLUEPRINT_EDGES)
+                .cost,
+            ..CostBreakdown::default()
+        };
+        // The initial charge bonus is removed from the cost again in `calc_flow`
+        debug_assert_eq!(
+            breakdown.total() - breakdown.min_consumption_rounding,
+            self.calc_result.unwrap()
+        );
+        Ok(breakdown)
+    }
+    /// Highest electricity drawn from the grid per timestep, on average over a settlement
+    /// period. Without settlement periods, this is the draw of a single timestep.
+    ///
+    /// The flow only minimizes the energy cost, so this is the peak of the cheapest solution
+    /// and not necessarily the lowest peak possible.
+    pub fn get_peak_network_consumption(&mut self) -> Result<i64, FlowSolveError> {
+        self.ensure_calculated()?;
+        Ok(self.peak_network_consumption)
     }
 }
 
@@ -425,3 +2140,1564 @@ let builder = SmartHomeFlowBuilder::new(
 .add_variable_action(variable_action1);
 let smart_home_flow = builder.build();
 */
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{optimizer_context::battery::FixedBatteryPlanError, time::STEPS_PER_DAY};
+
+    /// Cheap prices during the day, expensive ones in the evening when all consumption happens.
+    fn evening_peak_flow(battery: Battery) -> SmartHomeFlow {
+        let evening = Time::at_default_resolution(18, 0);
+        let electricity_price = Prognoses::from_closure(|t| if t < evening { 10 } else { 12 });
+        let generated_electricity = Prognoses::from_closure(|_| 0);
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if t < evening { 0 } else { 10 });
+        SmartHomeFlowBuilder::new(
+            &generated_electricity,
+            &electricity_price,
+            &beyond_control_consumption,
+            1.0,
+        )
+        .add_battery(&Rc::new(battery))
+        .build()
+    }
+
+    /// Charging is cheap in exactly one timestep and the charge is used in exactly one other.
+    /// The battery starts and has to end with 20.
+    fn single_charge_flow() -> SmartHomeFlow {
+        let charge_step = Time::from_timestep(100);
+        let use_step = Time::from_timestep(200);
+        let electricity_price =
+            Prognoses::from_closure(|t| if t == charge_step { 1 } else { 1000 });
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if t == use_step { 50 } else { 0 });
+        let battery = Battery::new(1000, 20, 50, 50, 1.0, 1).with_final_charge_target(20);
+        SmartHomeFlowBuilder::new(
+            &Prognoses::from_closure(|_| 0),
+            &electricity_price,
+            &beyond_control_consumption,
+            1.0,
+        )
+        .add_battery(&Rc::new(battery))
+        .build()
+    }
+
+    #[test]
+    fn test_charge_level_is_level_at_start_of_timestep() {
+        let schedule = single_charge_flow().get_schedule().unwrap();
+        let battery = schedule.get_battery(1).unwrap();
+        let level = |t: u32| *battery.get_charge_level(Time::from_timestep(t)).unwrap();
+        // Charged during timestep 100, so the level rises at the start of 101.
+        assert_eq!((level(99), level(100), level(101)), (20, 20, 70));
+        // Used during timestep 200.
+        assert_eq!((level(200), level(201)), (70, 20));
+        assert_eq!(battery.get_charge_level(Time::get_day_end()), Some(&20));
+        assert_eq!(
+            battery.get_charge_level(Time::from_timestep(STEPS_PER_DAY + 1)),
+            None
+        );
+        assert_eq!(
+            *schedule
+                .network_consumption
+                .get(Time::from_timestep(100))
+                .unwrap(),
+            50
+        );
+        assert_eq!(
+            *schedule
+                .network_consumption
+                .get(Time::from_timestep(200))
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_lossy_battery_is_charged_less() {
+        let evening = Time::at_default_resolution(18, 0);
+
+        let mut perfect = evening_peak_flow(Battery::new(1000, 0, 50, 50, 1.0, 1));
+        let perfect_schedule = perfect.get_schedule().unwrap();
+        let perfect_level = *perfect_schedule
+            .get_battery(1)
+            .unwrap()
+            .get_charge_level(evening)
+            .unwrap();
+
+        // Storing one unit costs 10 / 0.8 = 12.5 > 12, so arbitrage is no longer worth it.
+        let mut lossy = evening_peak_flow(Battery::new(1000, 0, 50, 50, 0.8, 1));
+        let lossy_schedule = lossy.get_schedule().unwrap();
+        let lossy_level = *lossy_schedule
+            .get_battery(1)
+            .unwrap()
+            .get_charge_level(evening)
+            .unwrap();
+
+        assert_eq!(perfect_level, 1000);
+        assert_eq!(lossy_level, 0);
+        assert!(perfect.get_cost().unwrap() < lossy.get_cost().unwrap());
+    }
+
+    #[test]
+    fn test_network_consumption_includes_charging_losses() {
+        let charge_step = Time::from_timestep(100);
+        let use_step = Time::from_timestep(200);
+        let electricity_price =
+            Prognoses::from_closure(|t| if t == charge_step { 1 } else { 1000 });
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if t == use_step { 60 } else { 0 });
+        let solve = |generated: i64, battery: Battery| {
+            let generated_electricity =
+                Prognoses::from_closure(|t| if t == charge_step { generated } else { 0 });
+            let mut flow = SmartHomeFlowBuilder::new(
+                &generated_electricity,
+                &electricity_price,
+                &beyond_control_consumption,
+                1.0,
+            )
+            .add_battery(&Rc::new(battery))
+            .build();
+            let schedule = flow.get_schedule().unwrap();
+            // The losses are paid for like any other consumption
+            assert_eq!(flow.get_cost().unwrap(), schedule.get_energy_cost());
+            let network = |t: Time| *schedule.network_consumption.get(t).unwrap();
+            (
+                network(charge_step),
+                network(use_step),
+                flow.get_cost_breakdown().unwrap().charging_loss,
+            )
+        };
+
+        // Storing 60 at half efficiency draws 120 from the grid
+        assert_eq!(
+            solve(0, Battery::new(1000, 0, 60, 60, 0.5, 1)),
+            (120, 0, 60)
+        );
+        // A battery that only stores generated electricity loses generated electricity too.
+        // 100 generated store 50, the grid serves the rest of the consumption.
+        let green = Battery::new(1000, 0, 60, 60, 0.5, 1).with_grid_charging_allowed(false);
+        assert_eq!(solve(100, green), (0, 10, 0));
+    }
+
+    #[test]
+    fn test_charging_losses_respect_the_grid_limit() {
+        let charge_step = Time::from_timestep(100);
+        let use_step = Time::from_timestep(200);
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|t| if t == charge_step { 1 } else { 1000 }),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|t| if t == use_step { 100 } else { 0 }),
+            vec![Rc::new(Battery::new(1000, 0, 100, 100, 0.5, 1))],
+            vec![],
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(100);
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        let schedule = flow.get_schedule().unwrap();
+        assert_eq!(schedule.verify(&context), vec![]);
+        // Half of the grid limit is stored, the other half lost
+        let network = |t: Time| *schedule.network_consumption.get(t).unwrap();
+        assert_eq!((network(charge_step), network(use_step)), (100, 50));
+        assert_eq!(flow.get_cost().unwrap(), schedule.get_energy_cost());
+    }
+
+    #[test]
+    fn test_self_discharging_battery_is_charged_just_in_time() {
+        let evening = Time::at_default_resolution(18, 0);
+        // Cheapest right after midnight, slightly more expensive until the evening peak
+        let electricity_price = Prognoses::from_closure(|t| {
+            if t < Time::at_default_resolution(1, 0) {
+                10
+            } else if t < evening {
+                11
+            } else {
+                40
+            }
+        });
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if t < evening { 0 } else { 10 });
+        let solve = |battery: Battery| {
+            let mut flow = SmartHomeFlowBuilder::new(
+                &Prognoses::from_closure(|_| 0),
+                &electricity_price,
+                &beyond_control_consumption,
+                1.0,
+            )
+            .add_battery(&Rc::new(battery))
+            .build();
+            let schedule = flow.get_schedule().unwrap();
+            let battery = schedule.get_battery(1).unwrap().clone();
+            let level = move |time: Time| *battery.get_charge_level(time).unwrap();
+            (level, flow.get_cost_breakdown().unwrap())
+        };
+
+        let (level, breakdown) = solve(Battery::new(1000, 0, 50, 50, 1.0, 1));
+        assert_eq!(level(Time::at_default_resolution(1, 0)), 1000);
+        assert_eq!(breakdown.self_discharge, 0);
+
+        // Keeping the charge from midnight to the evening loses far more than the cheaper
+        // price saves
+        let battery = Battery::new(1000, 0, 50, 50, 1.0, 1).with_self_discharge_per_hour(0.5);
+        let (level, breakdown) = solve(battery);
+        assert_eq!(level(Time::at_default_resolution(17, 0)), 0);
+        // Less is stored, the charge for the end of the evening would be lost on the way
+        assert!((1..1000).contains(&level(evening)));
+        assert!(breakdown.self_discharge > 0);
+    }
+
+    #[test]
+    fn test_cycle_cost_keeps_battery_idle_unless_arbitrage_pays() {
+        let evening = Time::at_default_resolution(18, 0);
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if t < evening { 0 } else { 10 });
+        let solve = |evening_price: i64| {
+            let electricity_price =
+                Prognoses::from_closure(|t| if t < evening { 10 } else { evening_price });
+            let battery = Battery::new(1000, 0, 50, 50, 1.0, 1).with_cycle_cost(5);
+            let mut flow = SmartHomeFlowBuilder::new(
+                &Prognoses::from_closure(|_| 0),
+                &electricity_price,
+                &beyond_control_consumption,
+                1.0,
+            )
+            .add_battery(&Rc::new(battery))
+            .build();
+            let schedule = flow.get_schedule().unwrap();
+            let level = *schedule
+                .get_battery(1)
+                .unwrap()
+                .get_charge_level(evening)
+                .unwrap();
+            let breakdown = flow.get_cost_breakdown().unwrap();
+            assert_eq!(breakdown.total(), flow.get_cost().unwrap());
+            (level, breakdown)
+        };
+
+        // Flat prices and a spike below the wear leave nothing to gain from cycling
+        for evening_price in [10, 14] {
+            let (level, breakdown) = solve(evening_price);
+            assert_eq!(level, 0);
+            assert_eq!(breakdown.battery_wear[&1], 0);
+        }
+
+        let (level, breakdown) = solve(40);
+        assert_eq!(level, 1000);
+        assert_eq!(breakdown.battery_wear[&1], 1000 * 5);
+        assert_eq!(breakdown.charging_loss, 0);
+    }
+
+    #[test]
+    fn test_battery_keeps_reserve_when_prices_spike() {
+        let battery = Battery::new(1000, 500, 50, 50, 1.0, 1).with_min_charge(200);
+        let mut flow = evening_peak_flow(battery);
+        let schedule = flow.get_schedule().unwrap();
+        let battery = schedule.get_battery(1).unwrap();
+        let levels: Vec<i64> = (0..STEPS_PER_DAY)
+            .map(|t| *battery.get_charge_level(Time::from_timestep(t)).unwrap())
+            .collect();
+        assert!(levels.iter().all(|level| *level >= 200));
+        // Everything above the reserve is used during the evening peak.
+        assert_eq!(*levels.last().unwrap(), 200);
+    }
+
+    #[test]
+    fn test_battery_reaches_final_charge_target() {
+        let mut without_target = evening_peak_flow(Battery::new(1000, 0, 50, 50, 1.0, 1));
+        let battery = Battery::new(1000, 0, 50, 50, 1.0, 1).with_final_charge_target(600);
+        let mut with_target = evening_peak_flow(battery);
+        let schedule = with_target.get_schedule().unwrap();
+        let final_level = *schedule
+            .get_battery(1)
+            .unwrap()
+            .get_charge_level(Time::get_day_end())
+            .unwrap();
+        assert!(final_level >= 600);
+        // The stored energy can't be used during the evening peak anymore.
+        assert_eq!(
+            with_target.get_cost().unwrap() - without_target.get_cost().unwrap(),
+            600 * 12
+        );
+    }
+
+    #[test]
+    fn test_pv_only_battery_is_not_charged_from_grid() {
+        let battery = Battery::new(1000, 0, 50, 50, 1.0, 1).with_grid_charging_allowed(false);
+        let mut flow = evening_peak_flow(battery);
+        let schedule = flow.get_schedule().unwrap();
+        let battery = schedule.get_battery(1).unwrap();
+        for t in 0..STEPS_PER_DAY {
+            let time = Time::from_timestep(t);
+            assert_eq!(*battery.get_charge_level(time).unwrap(), 0);
+            assert_eq!(battery.get_green_charge(time), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_pv_only_battery_is_charged_from_generation() {
+        let evening = Time::at_default_resolution(18, 0);
+        let midday = |t: Time| {
+            Time::at_default_resolution(10, 0) <= t && t < Time::at_default_resolution(14, 0)
+        };
+        let electricity_price = Prognoses::from_closure(|t| if t < evening { 10 } else { 12 });
+        let generated_electricity = Prognoses::from_closure(|t| if midday(t) { 30 } else { 0 });
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if t < evening { 0 } else { 10 });
+        let battery = Battery::new(10000, 0, 50, 50, 1.0, 1).with_grid_charging_allowed(false);
+        let mut flow = SmartHomeFlowBuilder::new(
+            &generated_electricity,
+            &electricity_price,
+            &beyond_control_consumption,
+            1.0,
+        )
+        .add_battery(&Rc::new(battery))
+        .build();
+
+        let schedule = flow.get_schedule().unwrap();
+        let battery = schedule.get_battery(1).unwrap();
+        for t in 0..STEPS_PER_DAY - 1 {
+            let time = Time::from_timestep(t);
+            let delta = battery.get_charge_level(time.get_next_timestep()).unwrap()
+                - battery.get_charge_level(time).unwrap();
+            let green_charge = battery.get_green_charge(time).unwrap();
+            assert!(delta <= *generated_electricity.get(time).unwrap());
+            if delta > 0 {
+                assert_eq!(green_charge, delta);
+            }
+        }
+        // Only the evening consumption is worth storing.
+        assert_eq!(
+            *battery
+                .get_charge_level(Time::at_default_resolution(14, 0))
+                .unwrap(),
+            10 * 6 * 60
+        );
+    }
+
+    #[test]
+    fn test_surplus_generation_is_exported() {
+        let midday = |t: Time| {
+            Time::at_default_resolution(10, 0) <= t && t < Time::at_default_resolution(14, 0)
+        };
+        let generated_electricity = Prognoses::from_closure(|t| if midday(t) { 100 } else { 0 });
+        let beyond_control_consumption =
+            Prognoses::from_closure(|t| if midday(t) { 30 } else { 0 });
+        let electricity_price = Prognoses::from_closure(|_| 20);
+        let feed_in_tariff = Prognoses::from_closure(|_| 8);
+        let mut flow = SmartHomeFlowBuilder::new(
+            &generated_electricity,
+            &electricity_price,
+            &beyond_control_consumption,
+            1.0,
+        )
+        .with_feed_in_tariff(&feed_in_tariff, Some(50))
+        .build();
+
+        let schedule = flow.get_schedule().unwrap();
+        assert_eq!(
+            schedule.get_grid_export(Time::at_default_resolution(12, 0)),
+            50
+        );
+        assert_eq!(
+            schedule.get_grid_export(Time::at_default_resolution(9, 0)),
+            0
+        );
+        assert_eq!(flow.get_cost().unwrap(), -8 * 50 * 4 * 60);
+    }
+
+    #[test]
+    fn test_export_above_purchase_price_is_bounded() {
+        // Selling is more profitable than buying, but only generated electricity and what the
+        // battery can discharge are sold.
+        let electricity_price = Prognoses::from_closure(|_| 5);
+        let feed_in_tariff = Prognoses::from_closure(|_| 10);
+        let generated_electricity = Prognoses::from_closure(|t| {
+            if t < Time::at_default_resolution(1, 0) {
+                7
+            } else {
+                0
+            }
+        });
+        let mut flow = SmartHomeFlowBuilder::new(
+            &generated_electricity,
+            &electricity_price,
+            &Prognoses::from_closure(|_| 0),
+            1.0,
+        )
+        .with_feed_in_tariff(&feed_in_tariff, None)
+        .add_battery(&Rc::new(Battery::new(1000, 0, 50, 20, 1.0, 1)))
+        .build();
+
+        let schedule = flow.get_schedule().unwrap();
+        for t in 0..STEPS_PER_DAY {
+            let time = Time::from_timestep(t);
+            assert_eq!(*schedule.network_consumption.get(time).unwrap(), 20);
+            assert_eq!(
+                schedule.get_grid_export(time),
+                *generated_electricity.get(time).unwrap() + 20
+            );
+        }
+        let bought = 5 * 20 * STEPS_PER_DAY as i64;
+        let sold = 10 * (7 * 60 + 20 * STEPS_PER_DAY as i64);
+        assert_eq!(flow.get_cost().unwrap(), bought - sold);
+    }
+
+    #[test]
+    fn test_green_battery_exports_stored_generation() {
+        let evening = |t: Time| t >= Time::at_default_resolution(18, 0);
+        let generated_electricity = Prognoses::from_closure(|t| {
+            if t < Time::at_default_resolution(1, 0) {
+                30
+            } else {
+                0
+            }
+        });
+        let feed_in_tariff = Prognoses::from_closure(|t| if evening(t) { 10 } else { 1 });
+        let battery = Battery::new(2000, 0, 50, 20, 1.0, 1).with_grid_charging_allowed(false);
+        let mut flow = SmartHomeFlowBuilder::new(
+            &generated_electricity,
+            &Prognoses::from_closure(|_| 5),
+            &Prognoses::from_closure(|_| 0),
+            1.0,
+        )
+        .add_battery(&Rc::new(battery))
+        .with_feed_in_tariff(&feed_in_tariff, None)
+        .build();
+
+        // Everything generated in the morning is sold in the evening.
+        let schedule = flow.get_schedule().unwrap();
+        assert_eq!(
+            schedule.get_grid_export(Time::at_default_resolution(0, 30)),
+            0
+        );
+        assert_eq!(
+            schedule.get_grid_export(Time::at_default_resolution(18, 30)),
+            20
+        );
+        let exported: i64 = (0..STEPS_PER_DAY)
+            .map(|t| schedule.get_grid_export(Time::from_timestep(t)))
+            .sum();
+        assert_eq!(exported, 30 * 60);
+        assert_eq!(flow.get_cost().unwrap(), -10 * 30 * 60);
+    }
+
+    /// A context with one component of every kind of consumption, starting with a half timestep.
+    fn consumption_context() -> (OptimizerContext, Rc<ConstantAction>) {
+        let constant_action = Rc::new(ConstantAction::new_profiled(
+            Time::from_timestep(0),
+            Time::at_default_resolution(2, 0),
+            vec![40, 30, 20],
+            3,
+        ));
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|_| 10),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|t| {
+                if t < Time::at_default_resolution(1, 0) {
+                    5
+                } else {
+                    0
+                }
+            }),
+            vec![],
+            vec![constant_action.clone()],
+            vec![Rc::new(VariableAction::new(
+                Time::at_default_resolution(4, 0),
+                Time::at_default_resolution(6, 0),
+                700,
+                20,
+                2,
+            ))],
+            0.5,
+        )
+        .unwrap();
+        (context, constant_action)
+    }
+
+    #[test]
+    fn test_required_consumption_components() {
+        let (context, constant_action) = consumption_context();
+        let beyond_control = 5 * 60;
+        let variable = 700;
+        assert_eq!(
+            required_consumption(&context, []),
+            beyond_control + variable
+        );
+        let later = constant_action
+            .clone()
+            .with_start_time(Time::from_timestep(10));
+        assert_eq!(
+            required_consumption(&context, [&later]),
+            beyond_control + variable + 90
+        );
+    }
+
+    #[test]
+    fn test_required_consumption_scales_first_timestep() {
+        let (context, constant_action) = consumption_context();
+        let at_start = constant_action.with_start_time(Time::from_timestep(0));
+        // Only half of the first timestep is left, so only 20 of the first 40 are needed.
+        assert_eq!(
+            required_consumption(&context, [&at_start]) - required_consumption(&context, []),
+            20 + 30 + 20
+        );
+    }
+
+    #[test]
+    fn test_flow_serves_required_consumption() {
+        let (context, constant_action) = consumption_context();
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        assert_eq!(flow.base_consumption, required_consumption(&context, []));
+        flow.add_constant_consumption(constant_action.with_start_time(Time::from_timestep(0)));
+        assert_eq!(flow.get_unserved_consumption().unwrap(), 0);
+        let network: i64 = flow
+            .get_schedule()
+            .unwrap()
+            .network_consumption
+            .get_data()
+            .iter()
+            .sum();
+        assert_eq!(
+            network,
+            required_consumption(&context, flow.constant_actions.values())
+        );
+
+        // 20 per timestep over two hours can't reach 3000.
+        let too_short = Rc::new(VariableAction::new(
+            Time::at_default_resolution(4, 0),
+            Time::at_default_resolution(6, 0),
+            3000,
+            20,
+            4,
+        ));
+        let mut flow = SmartHomeFlowBuilder::from_context(&context)
+            .add_action(&too_short)
+            .build();
+        assert_eq!(flow.get_unserved_consumption().unwrap(), 3000 - 20 * 120);
+    }
+
+    #[test]
+    fn test_shortfall_names_the_binding_limits() {
+        let (context, _) = consumption_context();
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        assert_eq!(flow.get_shortfall().unwrap(), None);
+
+        let too_short = Rc::new(VariableAction::new(
+            Time::at_default_resolution(4, 0),
+            Time::at_default_resolution(6, 0),
+            3000,
+            20,
+            4,
+        ));
+        let mut flow = SmartHomeFlowBuilder::from_context(&context)
+            .add_action(&too_short)
+            .build();
+        assert_eq!(
+            flow.get_shortfall().unwrap(),
+            Some(Shortfall {
+                by_time: vec![],
+                by_action: vec![(4, 3000 - 20 * 120)],
+                by_battery: vec![],
+                binding: vec![BindingLimit::ActionMaxConsumption { id: 4 }],
+            })
+        );
+
+        // 200 are needed for ten minutes, the grid gives 150 and a battery 30
+        let peak = |t: Time| {
+            Time::at_default_resolution(12, 0) <= t && t < Time::at_default_resolution(12, 10)
+        };
+        let mut flow = SmartHomeFlowBuilder::new(
+            &Prognoses::from_closure(|_| 0),
+            &Prognoses::from_closure(|_| 10),
+            &Prognoses::from_closure(|t| if peak(t) { 200 } else { 0 }),
+            1.0,
+        )
+        .add_battery(&Rc::new(Battery::new(1000, 1000, 0, 30, 1.0, 1)))
+        .add_battery(&Rc::new(Battery::new(1000, 0, 0, 30, 1.0, 2)))
+        .with_grid_limit(150)
+        .build();
+        let shortfall = flow.get_shortfall().unwrap().unwrap();
+        let times: Vec<Time> = (720..730).map(Time::from_timestep).collect();
+        assert_eq!(
+            shortfall.by_time,
+            times.iter().map(|&time| (time, 20)).collect::<Vec<_>>()
+        );
+        assert!(shortfall.by_action.is_empty());
+        let expected: Vec<BindingLimit> = times
+            .iter()
+            .map(|&time| BindingLimit::GridLimit { time })
+            .chain(
+                times
+                    .iter()
+                    .map(|&time| BindingLimit::BatteryEmpty { id: 2, time }),
+            )
+            .chain(
+                times
+                    .iter()
+                    .map(|&time| BindingLimit::BatteryMaxOutput { id: 1, time }),
+            )
+            .collect();
+        assert_eq!(shortfall.binding, expected);
+    }
+
+    #[test]
+    fn test_unreachable_charge_target_is_a_shortfall() {
+        // Charging 10 per step for a whole day, 5 in the first half step, gives 14395 of the
+        // 20000 targeted
+        let battery =
+            Rc::new(Battery::new(20000, 0, 10, 10, 1.0, 1).with_final_charge_target(20000));
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|_| 10),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![battery],
+            vec![],
+            vec![],
+            0.5,
+        )
+        .unwrap();
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        assert_eq!(flow.get_unmet_charge_targets().unwrap(), vec![(1, 5605)]);
+        let shortfall = flow.get_shortfall().unwrap().unwrap();
+        assert_eq!(shortfall.by_battery, vec![(1, 5605)]);
+        assert!(shortfall.by_time.is_empty() && shortfall.by_action.is_empty());
+        assert!(
+            shortfall
+                .to_string()
+                .contains("final charge target of battery 1")
+        );
+        assert!(matches!(
+            solve_exact(&context),
+            Err(ExactSolveError::Infeasible(shortfall)) if shortfall.by_battery == vec![(1, 5605)]
+        ));
+    }
+
+    #[test]
+    fn test_solve_exact_rejects_constant_actions() {
+        let (context, _) = consumption_context();
+        assert!(matches!(
+            solve_exact(&context),
+            Err(ExactSolveError::ConstantActions(ids)) if ids == vec![3]
+        ));
+
+        let context = OptimizerContext::new(
+            (**context.get_electricity_price()).clone(),
+            (**context.get_generated_electricity()).clone(),
+            context.get_beyond_control_consumption().clone(),
+            vec![],
+            vec![],
+            context.get_variable_actions().clone(),
+            0.5,
+        )
+        .unwrap();
+        let (cost, schedule) = solve_exact(&context).unwrap();
+        let network: i64 = schedule.network_consumption.get_data().iter().sum();
+        assert_eq!(network, required_consumption(&context, []));
+        assert_eq!(
+            cost,
+            SmartHomeFlowBuilder::from_context(&context)
+                .build()
+                .get_cost()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_flow_nodes_of_all_ids_are_independent() {
+        // Ids 0 to 2 and a battery 5 below an action id once shared keys with other nodes
+        let cheap_end = Time::at_default_resolution(1, 0);
+        let electricity_price = Prognoses::from_closure(|t| if t < cheap_end { 1 } else { 10 });
+        let windows = [(0, 0, 600), (1, 2, 1_200), (2, 4, 1_800), (8, 6, 2_400)];
+        let actions = windows
+            .iter()
+            .map(|&(id, hour, total)| {
+                Rc::new(VariableAction::new(
+                    Time::at_default_resolution(hour, 0),
+                    Time::at_default_resolution(hour + 1, 0),
+                    total,
+                    100,
+                    id,
+                ))
+            })
+            .collect();
+        let battery = Rc::new(Battery::new(10_000, 0, 100, 100, 1.0, 3));
+        let context = OptimizerContext::new(
+            electricity_price,
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![battery],
+            vec![],
+            actions,
+            1.0,
+        )
+        .unwrap();
+        let (_, schedule) = solve_exact(&context).unwrap();
+
+        for (id, hour, total) in windows {
+            let action = &schedule.variable_actions[&id];
+            let window =
+                Time::at_default_resolution(hour, 0)..Time::at_default_resolution(hour + 1, 0);
+            let consumed: i64 = action.iter_consumption().map(|(_, c)| c).sum();
+            assert_eq!(consumed, total, "action {id}");
+            for (time, consumption) in action.iter_consumption() {
+                assert!(
+                    consumption == 0 || window.contains(&time),
+                    "action {id} consumes at {time:?}"
+                );
+            }
+        }
+        // The battery stores cheap electricity for the later actions and nothing else
+        let battery = &schedule.batteries[&3];
+        let stored = 1_200 + 1_800 + 2_400;
+        assert_eq!(battery.get_charge_level(cheap_end), Some(&stored));
+        assert_eq!(battery.get_charge_level(Time::get_day_end()), Some(&0));
+        let network: i64 = schedule.network_consumption.get_data().iter().sum();
+        assert_eq!(network, 600 + stored);
+    }
+
+    #[test]
+    fn test_fixed_battery_plan_is_planned_around() {
+        let cheap_end = Time::at_default_resolution(1, 0);
+        let electricity_price = Prognoses::from_closure(|t| if t < cheap_end { 1 } else { 10 });
+        let battery = Rc::new(Battery::new(10_000, 2_000, 100, 100, 1.0, 1).with_min_charge(500));
+        let action = Rc::new(VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(2, 0),
+            6000,
+            100,
+            2,
+        ));
+        let context = |charge: Prognoses<i64>| {
+            OptimizerContext::new(
+                electricity_price.clone(),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 0),
+                vec![battery.clone()],
+                vec![],
+                vec![action.clone()],
+                1.0,
+            )
+            .unwrap()
+            .with_grid_limit(150)
+            .with_fixed_battery_plan(1, charge)
+        };
+        // Charge while it's cheap, discharge in the evening
+        let plan = Prognoses::from_closure(|t| match t.to_timestep() {
+            0..60 => 100,
+            1200..1260 => -100,
+            _ => 0,
+        });
+        let fixed_context = context(plan).unwrap();
+        assert!(fixed_context.get_batteries().is_empty());
+        let generated = fixed_context.get_generated_electricity();
+        assert_eq!(
+            *generated.get(Time::at_default_resolution(20, 0)).unwrap(),
+            100
+        );
+
+        let (_, schedule) = solve_exact(&fixed_context).unwrap();
+        let assigned = schedule.get_variable_action(2).unwrap();
+        // Only what the battery leaves of the grid limit is available while it's cheap
+        for (time, consumption) in assigned.iter_consumption() {
+            if time < cheap_end {
+                assert_eq!(consumption, 50);
+            }
+        }
+        let total: i64 = assigned.iter_consumption().map(|(_, c)| c).sum();
+        assert_eq!(total, 6000);
+        let fixed = schedule.get_battery(1).unwrap();
+        assert_eq!(fixed.get_charge_level(cheap_end), Some(&8_000));
+        assert_eq!(fixed.get_charge_level(Time::get_day_end()), Some(&2_000));
+
+        let error = |plan: fn(u32) -> i64| {
+            context(Prognoses::from_closure(|t| plan(t.to_timestep()))).err()
+        };
+        assert_eq!(
+            error(|t| if t == 5 { 101 } else { 0 }),
+            Some(FixedBatteryPlanError::RateExceeded {
+                time: Time::from_timestep(5),
+                charge: 101
+            })
+        );
+        assert_eq!(
+            error(|t| if t < 20 { -100 } else { 0 }),
+            Some(FixedBatteryPlanError::LevelOutOfRange {
+                time: Time::from_timestep(16),
+                level: 400
+            })
+        );
+        assert!(matches!(
+            context(Prognoses::from_closure(|_| 0))
+                .unwrap()
+                .with_fixed_battery_plan(1, Prognoses::from_closure(|_| 0)),
+            Err(FixedBatteryPlanError::UnknownBattery(1))
+        ));
+    }
+
+    #[test]
+    fn test_min_consumption_rounds_dribbles() {
+        // Free generation makes the flow spread the action thinly over its window
+        let action = |min_consumption| {
+            Rc::new(
+                VariableAction::new(
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(2, 0),
+                    610,
+                    100,
+                    1,
+                )
+                .with_min_consumption(min_consumption),
+            )
+        };
+        let context = |action| {
+            OptimizerContext::new(
+                Prognoses::from_closure(|_| 10),
+                Prognoses::from_closure(|t| {
+                    if t < Time::at_default_resolution(1, 0) {
+                        20
+                    } else {
+                        0
+                    }
+                }),
+                Prognoses::from_closure(|_| 0),
+                vec![],
+                vec![],
+                vec![action],
+                1.0,
+            )
+            .unwrap()
+        };
+        let (cost, _) = solve_exact(&context(action(0))).unwrap();
+        assert_eq!(cost, 0);
+
+        let context = context(action(60));
+        let (rounded_cost, schedule) = solve_exact(&context).unwrap();
+        let assigned = schedule.get_variable_action(1).unwrap();
+        assert!(
+            assigned
+                .iter_consumption()
+                .all(|(_, consumption)| consumption == 0 || consumption >= 60)
+        );
+        assert_eq!(assigned.get_total_assigned(), 610);
+        assert!(schedule.verify(&context).is_empty());
+        // What the generation doesn't cover any more is bought
+        let network: i64 = schedule.network_consumption.get_data().iter().sum();
+        assert!(network > 0);
+        assert_eq!(rounded_cost, network * 10);
+
+        assert!(action(60).can_reach_total_with_min_consumption());
+        // Two timesteps hold at most 140 and three at least 180, so 150 can't be split
+        let gap = VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(2, 0),
+            150,
+            70,
+            2,
+        )
+        .with_min_consumption(60);
+        assert!(!gap.can_reach_total_with_min_consumption());
+        let below_min = VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(2, 0),
+            150,
+            50,
+            3,
+        )
+        .with_min_consumption(60);
+        assert!(!below_min.can_reach_total_with_min_consumption());
+    }
+
+    #[test]
+    fn test_variable_action_respects_max_consumption_profile() {
+        let morning_end = Time::at_default_resolution(1, 0);
+        let profile = (0..120).map(|t| if t < 60 { 10 } else { 50 }).collect();
+        let action = Rc::new(
+            VariableAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(2, 0),
+                3000,
+                50,
+                1,
+            )
+            .with_max_consumption_profile(profile),
+        );
+        assert_eq!(action.get_max_total_consumption(), 10 * 60 + 50 * 60);
+        // Cheapest while the cap is low
+        let electricity_price = Prognoses::from_closure(|t| if t < morning_end { 1 } else { 10 });
+        let mut flow = SmartHomeFlowBuilder::new(
+            &Prognoses::from_closure(|_| 0),
+            &electricity_price,
+            &Prognoses::from_closure(|_| 0),
+            1.0,
+        )
+        .add_action(&action)
+        .build();
+        assert_eq!(flow.get_unserved_consumption().unwrap(), 0);
+        let schedule = flow.get_schedule().unwrap();
+        let assigned = schedule.get_variable_action(1).unwrap();
+        for (time, consumption) in assigned.iter_consumption() {
+            assert!(consumption <= action.get_max_consumption_at(time));
+        }
+        let morning: i64 = assigned
+            .iter_consumption()
+            .filter(|(time, _)| *time < morning_end)
+            .map(|(_, consumption)| consumption)
+            .sum();
+        let total: i64 = assigned.iter_consumption().map(|(_, c)| c).sum();
+        assert_eq!((morning, total), (10 * 60, 3000));
+    }
+
+    #[test]
+    fn test_battery_keeps_electricity_bought_at_negative_price() {
+        let negative_step = Time::from_timestep(300);
+        let electricity_price =
+            Prognoses::from_closure(|t| if t == negative_step { -10 } else { 20 });
+        let mut flow = SmartHomeFlowBuilder::new(
+            &Prognoses::from_closure(|_| 0),
+            &electricity_price,
+            &Prognoses::from_closure(|_| 0),
+            1.0,
+        )
+        .add_battery(&Rc::new(Battery::new(100, 30, 50, 50, 1.0, 1)))
+        .build();
+        // Paid for charging as much as possible, nothing is bought at a positive price
+        assert_eq!(flow.get_cost().unwrap(), -10 * 50);
+        let schedule = flow.get_schedule().unwrap();
+        let battery = schedule.get_battery(1).unwrap();
+        assert_eq!(
+            battery.get_charge_level(negative_step.get_next_timestep()),
+            Some(&80)
+        );
+        assert_eq!(battery.get_charge_level(Time::get_day_end()), Some(&80));
+    }
+
+    /// Timesteps at the start of the day in which everything happens in a `SmallInstance`.
+    const ACTIVE_STEPS: usize = 4;
+    /// Price in all other timesteps, high enough that nothing is bought there unless required.
+    const INERT_PRICE: i64 = 50;
+
+    /// An instance small enough to find its optimal cost by enumerating all schedules.
+    #[derive(Debug)]
+    struct SmallInstance {
+        price: [i64; ACTIVE_STEPS],
+        consumption: [i64; ACTIVE_STEPS],
+        generation: [i64; ACTIVE_STEPS],
+        /// Capacity, initial level, maximum charge, maximum output and final charge target.
+        battery: Option<(i64, i64, i64, i64, i64)>,
+        /// Start and end timestep, total and maximum consumption.
+        action: Option<(usize, usize, i64, i64)>,
+    }
+
+    impl SmallInstance {
+        fn random(rng: &mut StdRng) -> Self {
+            let mut steps = |range: std::ops::RangeInclusive<i64>| {
+                std::array::from_fn(|_| rng.random_range(range.clone()))
+            };
+            let price = steps(-5..=5);
+            let consumption = steps(0..=3);
+            let generation = steps(0..=2);
+            let battery = rng.random_bool(0.7).then(|| {
+                let capacity = rng.random_range(1..=4);
+                (
+                    capacity,
+                    rng.random_range(0..=capacity),
+                    // Charging keeps the final charge target reachable
+                    rng.random_range(1..=2),
+                    rng.random_range(0..=2),
+                    rng.random_range(0..=capacity),
+                )
+            });
+            let action = rng.random_bool(0.5).then(|| {
+                let start = rng.random_range(0..ACTIVE_STEPS);
+                let end = rng.random_range(start + 1..=ACTIVE_STEPS);
+                let max = rng.random_range(1..=2);
+                let total = rng.random_range(0..=max * (end - start) as i64);
+                (start, end, total, max)
+            });
+            SmallInstance {
+                price,
+                consumption,
+                generation,
+                battery,
+                action,
+            }
+        }
+
+        fn context(&self) -> OptimizerContext {
+            let active = |values: [i64; ACTIVE_STEPS], otherwise: i64| {
+                Prognoses::from_closure(move |t: Time| {
+                    values
+                        .get(t.to_timestep() as usize)
+                        .copied()
+                        .unwrap_or(otherwise)
+                })
+            };
+            let batteries = self
+                .battery
+                .iter()
+                .map(|&(capacity, initial, charge, output, target)| {
+                    Rc::new(
+                        Battery::new(capacity, initial, charge, output, 1.0, 1)
+                            .with_final_charge_target(target),
+                    )
+                })
+                .collect();
+            let variable_actions = self
+                .action
+                .iter()
+                .map(|&(start, end, total, max)| {
+                    Rc::new(VariableAction::new(
+                        Time::from_timestep(start as u32),
+                        Time::from_timestep(end as u32),
+                        total,
+                        max,
+                        2,
+                    ))
+                })
+                .collect();
+            OptimizerContext::new(
+                active(self.price, INERT_PRICE),
+                active(self.generation, 0),
+                active(self.consumption, 0),
+                batteries,
+                vec![],
+                variable_actions,
+                1.0,
+            )
+            .unwrap()
+        }
+
+        /// Lowest cost of all schedules, found by trying every battery charge and action
+        /// consumption in every active timestep.
+        fn brute_force_cost(&self) -> i64 {
+            let (level, action_left) = (
+                self.battery.map_or(0, |battery| battery.1),
+                self.action.map_or(0, |action| action.2),
+            );
+            self.search(0, level, action_left)
+                .expect("Small instances are always feasible")
+        }
+
+        fn search(&self, step: usize, level: i64, action_left: i64) -> Option<i64> {
+            if step == ACTIVE_STEPS {
+                if action_left > 0 {
+                    return None;
+                }
+                // A missing final charge is bought later in the day
+                let target = self.battery.map_or(0, |battery| battery.4);
+                return Some(INERT_PRICE * (target - level).max(0));
+            }
+            let (capacity, charge, output) = self
+                .battery
+                .map_or((0, 0, 0), |(capacity, _, charge, output, _)| {
+                    (capacity, charge, output)
+                });
+            let action_max = match self.action {
+                Some((start, end, _, max)) if (start..end).contains(&step) => max.min(action_left),
+                _ => 0,
+            };
+            let mut best = None;
+            for delta in -output..=charge {
+                if !(0..=capacity).contains(&(level + delta)) {
+                    continue;
+                }
+                for action in 0..=action_max {
+                    // Discharged electricity has to be consumed, it can't be exported
+                    let demand = self.consumption[step] + action + delta;
+                    if demand < 0 {
+                        continue;
+                    }
+                    // Generation is curtailed whenever buying is paid
+                    let import = if self.price[step] < 0 {
+                        demand
+                    } else {
+                        (demand - self.generation[step]).max(0)
+                    };
+                    let rest = self.search(step + 1, level + delta, action_left - action);
+                    if let Some(rest) = rest {
+                        let cost = import * self.price[step] + rest;
+                        best = Some(best.map_or(cost, |best: i64| best.min(cost)));
+                    }
+                }
+            }
+            best
+        }
+    }
+
+    #[test]
+    fn test_flow_matches_brute_force_with_negative_prices() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..150 {
+            let instance = SmallInstance::random(&mut rng);
+            let mut flow = SmartHomeFlowBuilder::from_context(&instance.context()).build();
+            assert_eq!(
+                flow.get_cost().unwrap(),
+                instance.brute_force_cost(),
+                "{:?}",
+                instance
+            );
+            assert_eq!(flow.get_unserved_consumption().unwrap(), 0);
+        }
+    }
+
+    /// A full day of quarter hours with hourly prices, the given number of batteries and five
+    /// constant actions that can move over most of it. The amounts per timestep are those of
+    /// a quarter hour.
+    ///
+    /// Tests that compare updating the flow in place with solving it from scratch use lossless
+    /// batteries: the charging losses are drawn starting from whichever of the equally cheap
+    /// flows a solve found, so the costs of lossy batteries may end up slightly apart.
+    fn full_day_context(
+        batteries: u32,
+        efficiency: f32,
+    ) -> (OptimizerContext, Vec<Rc<ConstantAction>>) {
+        let quarter_hour = TimestepConfig::new(15).unwrap();
+        let at = |hours: u32, minutes: u32| Time::from_clock(hours, minutes, quarter_hour);
+        let hour = |t: Time| t.to_timestep() as i64 / 4;
+        let constant_actions: Vec<_> = (0..5)
+            .map(|i| {
+                Rc::new(ConstantAction::new(
+                    at(i, 0),
+                    at(20 + i, 0),
+                    at(1, 30),
+                    1500 + 300 * i as i64,
+                    i,
+                ))
+            })
+            .collect();
+        let batteries = (0..batteries)
+            .map(|i| Rc::new(Battery::new(20_000, 5_000, 3000, 2250, efficiency, 10 + i)))
+            .collect();
+        let context = OptimizerContext::new(
+            Prognoses::from_closure_for(quarter_hour, |t| 20 + (hour(t) * 7) % 24),
+            Prognoses::from_closure_for(quarter_hour, |t| {
+                let step = t.to_timestep() as i64;
+                if (28..72).contains(&step) {
+                    2250 - 75 * (step - 50).abs()
+                } else {
+                    0
+                }
+            }),
+            Prognoses::from_closure_for(quarter_hour, |t| 450 + (hour(t) * 195) % 600),
+            batteries,
+            constant_actions.clone(),
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        (context, constant_actions)
+    }
+
+    /// Moves a random constant action to a random start time in its window.
+    fn move_random_action(
+        flow: &mut SmartHomeFlow,
+        constant_actions: &[Rc<ConstantAction>],
+        rng: &mut StdRng,
+    ) -> AssignedConstantAction {
+        let action = &constant_actions[rng.random_range(0..constant_actions.len())];
+        let latest = action.end_before.to_timestep() - action.duration.to_timestep();
+        let start = rng.random_range(action.start_from.to_timestep()..=latest);
+        let assigned = action.clone().with_start_time(Time::from_timestep(start));
+        flow.remove_constant_consumption(action.get_id());
+        flow.add_constant_consumption(assigned.clone());
+        assigned
+    }
+
+    #[test]
+    fn test_incremental_cost_matches_recalculation() {
+        let (context, constant_actions) = full_day_context(1, 1.0);
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..8 {
+            let assigned = move_random_action(&mut flow, &constant_actions, &mut rng);
+            let cost = flow.get_cost().unwrap();
+            assert!(flow.solved);
+
+            let mut fresh = SmartHomeFlowBuilder::from_context(&context).build();
+            for action in flow.constant_actions.values() {
+                fresh.add_constant_consumption(action.clone());
+            }
+            assert_eq!(cost, fresh.get_cost().unwrap(), "after moving {assigned:?}");
+            assert_eq!(flow.get_unserved_consumption().unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_unserved_consumption_is_solved_from_scratch() {
+        let (context, constant_actions) = full_day_context(1, 1.0);
+        // Too little for all actions running at the same time
+        let context = context.with_grid_limit(1000);
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut outcomes = Vec::new();
+        for _ in 0..4 {
+            move_random_action(&mut flow, &constant_actions, &mut rng);
+            let cost = flow.get_cost().unwrap();
+            let unserved = flow.get_unserved_consumption().unwrap();
+            assert_eq!(flow.solved, unserved == 0);
+            outcomes.push(unserved == 0);
+
+            let mut fresh = SmartHomeFlowBuilder::from_context(&context).build();
+            for action in flow.constant_actions.values() {
+                fresh.add_constant_consumption(action.clone());
+            }
+            assert_eq!(cost, fresh.get_cost().unwrap());
+            assert_eq!(unserved, fresh.get_unserved_consumption().unwrap());
+        }
+        // The moves cover both a served and an unserved layout
+        assert!(outcomes.contains(&true) && outcomes.contains(&false));
+    }
+
+    #[test]
+    fn test_cost_breakdown_attributes_whole_cost() {
+        let (context, constant_actions) = full_day_context(2, 0.9);
+        let feed_in_tariff = Prognoses::from_closure_for(context.get_timestep_config(), |_| 30);
+        let context = context.with_feed_in_tariff(feed_in_tariff, Some(900));
+        let mut flow = SmartHomeFlowBuilder::from_context(&context).build();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..4 {
+            move_random_action(&mut flow, &constant_actions, &mut rng);
+            let breakdown = flow.get_cost_breakdown().unwrap();
+            // The tagged edges account for the raw cost of the flow, including the bonus
+            let raw: i64 = flow.flow.cost_by_category().values().sum();
+            assert_eq!(raw, flow.flow.get_cost());
+
+            let rounding = flow.get_min_consumption_rounding().unwrap();
+            assert_eq!(breakdown.total(), flow.get_cost().unwrap() + rounding.cost);
+            assert!(breakdown.grid_import > 0);
+            assert!(breakdown.charging_loss > 0);
+            assert!(breakdown.grid_export < 0);
+            assert_eq!(breakdown.unserved_penalty, 0);
+            assert_eq!(breakdown.peak_charge, 0);
+        }
+    }
+
+    #[test]
+    fn test_network_fee_shifts_consumption_to_low_fee_band() {
+        let night = |t: Time| {
+            t < Time::at_default_resolution(6, 0) || t >= Time::at_default_resolution(22, 0)
+        };
+        let network_fee = Prognoses::from_closure(|t| if night(t) { 80 } else { 30 });
+        let action = VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::get_day_end(),
+            6000,
+            100,
+            1,
+        );
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![],
+            vec![Rc::new(action)],
+            1.0,
+        )
+        .unwrap()
+        .with_network_fee(network_fee);
+        let (cost, schedule) = solve_exact(&context).unwrap();
+
+        let action = schedule.get_variable_action(1).unwrap();
+        for (time, consumption) in action.iter_consumption() {
+            if night(time) {
+                assert_eq!(consumption, 0, "consumes at {time:?}");
+            }
+        }
+        assert_eq!(schedule.get_energy_cost(), 0);
+        assert_eq!(schedule.get_network_fee_cost(), 30 * 6000);
+        let breakdown = schedule.get_objective_breakdown().unwrap();
+        assert_eq!(breakdown.network_fee, 30 * 6000);
+        assert_eq!(breakdown.grid_import, 0);
+        assert_eq!(cost, 30 * 6000);
+    }
+
+    #[test]
+    fn test_network_fee_is_not_charged_on_generation() {
+        // Generation in the evening could be stored, but it doesn't pay the fee anyway
+        let evening = |t: Time| t >= Time::at_default_resolution(18, 0);
+        let mut flow = SmartHomeFlowBuilder::new(
+            &Prognoses::from_closure(|t| if evening(t) { 0 } else { 10 }),
+            &Prognoses::from_closure(|_| 10),
+            &Prognoses::from_closure(|t| if evening(t) { 10 } else { 0 }),
+            1.0,
+        )
+        .with_network_fee(&Prognoses::from_closure(|_| 50))
+        .add_battery(&Rc::new(Battery::new(100_000, 0, 50, 50, 1.0, 1)))
+        .build();
+        assert_eq!(flow.get_cost().unwrap(), 0);
+        let schedule = flow.get_schedule().unwrap();
+        assert_eq!(schedule.get_network_fee_cost(), 0);
+    }
+
+    #[test]
+    fn test_co2_cost_shifts_consumption_to_low_carbon_hours() {
+        let sunny = |t: Time| {
+            t >= Time::at_default_resolution(10, 0) && t < Time::at_default_resolution(16, 0)
+        };
+        let co2_intensity = Prognoses::from_closure(|t| if sunny(t) { 100 } else { 400 });
+        let action = VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::get_day_end(),
+            6000,
+            100,
+            1,
+        );
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|_| 50),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![],
+            vec![Rc::new(action)],
+            1.0,
+        )
+        .unwrap()
+        .with_co2_intensity(co2_intensity, 0.1);
+        let (cost, schedule) = solve_exact(&context).unwrap();
+
+        let action = schedule.get_variable_action(1).unwrap();
+        for (time, consumption) in action.iter_consumption() {
+            if !sunny(time) {
+                assert_eq!(consumption, 0, "consumes at {time:?}");
+            }
+        }
+        assert_eq!(schedule.get_energy_cost(), 50 * 6000);
+        assert_eq!(schedule.get_co2_emissions(), Some(100 * 6000));
+        let breakdown = schedule.get_objective_breakdown().unwrap();
+        assert_eq!(breakdown.co2, 10 * 6000);
+        assert_eq!(breakdown.grid_import, 50 * 6000);
+        assert_eq!(breakdown.energy_cost(), 50 * 6000);
+        assert_eq!(cost, 60 * 6000);
+    }
+
+    /// Two 3 kW constant actions for an hour each, overlapping from 1:30 to 2:00. The one
+    /// starting later has the lower id.
+    fn overlapping_actions_context(grid_limit_watt: i64) -> OptimizerContext {
+        let three_kw = 50_000;
+        let action = |start: Time, id| {
+            Rc::new(ConstantAction::new(
+                start,
+                start + Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(1, 0),
+                three_kw,
+                id,
+            ))
+        };
+        OptimizerContext::new(
+            Prognoses::from_closure(|_| 10),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![
+                action(Time::at_default_resolution(1, 0), 2),
+                action(Time::at_default_resolution(1, 30), 1),
+            ],
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(grid_limit_watt * 1000 / 60)
+    }
+
+    fn solve_at_earliest_start(context: &OptimizerContext) -> SmartHomeFlow {
+        let mut flow = SmartHomeFlowBuilder::from_context(context).build();
+        for action in context.get_constant_actions() {
+            flow.add_constant_consumption(action.clone().with_start_time(action.get_start_from()));
+        }
+        flow
+    }
+
+    #[test]
+    fn test_overlapping_constant_actions_add_up() {
+        let context = overlapping_actions_context(11_000);
+        let mut flow = solve_at_earliest_start(&context);
+        assert_eq!(flow.get_unserved_consumption().unwrap(), 0);
+        assert!(flow.get_unserved_constant_actions().unwrap().is_empty());
+        let schedule = flow.get_schedule().unwrap();
+        assert!(schedule.get_unserved_constant_actions().is_empty());
+        let network = schedule.network_consumption.get_data();
+        for (timestep, expected) in [(59, 0), (60, 50_000), (90, 100_000), (119, 100_000)] {
+            assert_eq!(network[timestep], expected, "at timestep {timestep}");
+        }
+
+        // Adding them to the beyond control consumption sums them the same way
+        let mut fixed = context.clone();
+        for action in context.get_constant_actions() {
+            fixed.add_constant_action_to_consumption(
+                &action.clone().with_start_time(action.get_start_from()),
+            );
+        }
+        assert_eq!(fixed.get_beyond_control_consumption().get_data(), network);
+        assert!(grid_overloads(&fixed).is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_constant_actions_blame_the_later_one() {
+        let context = overlapping_actions_context(5_000);
+        let mut flow = solve_at_earliest_start(&context);
+        let limit = 5_000 * 1000 / 60;
+        let shortfall = 100_000 - limit;
+        assert_eq!(flow.get_unserved_consumption().unwrap(), 30 * shortfall);
+
+        let unserved = flow.get_unserved_constant_actions().unwrap();
+        let expected: Vec<_> = (Time::at_default_resolution(1, 30)
+            ..Time::at_default_resolution(2, 0))
+            .iter_steps()
+            .map(|time| UnservedConstantAction {
+                id: 1,
+                time,
+                unserved: shortfall,
+            })
+            .collect();
+        assert_eq!(unserved, expected);
+        assert_eq!(
+            flow.get_schedule().unwrap().get_unserved_constant_actions(),
+            expected
+        );
+
+        let mut fixed = context.clone();
+        for action in context.get_constant_actions() {
+            fixed.add_constant_action_to_consumption(
+                &action.clone().with_start_time(action.get_start_from()),
+            );
+        }
+        let overloads: Vec<_> = (Time::at_default_resolution(1, 30)
+            ..Time::at_default_resolution(2, 0))
+            .iter_steps()
+            .collect();
+        assert_eq!(grid_overloads(&fixed), overloads);
+    }
+
+    #[test]
+    fn test_preference_front_loads_consumption_at_flat_prices() {
+        let solve = |action: VariableAction| {
+            let context = OptimizerContext::new(
+                Prognoses::from_closure(|_| 100),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 0),
+                vec![],
+                vec![],
+                vec![Rc::new(action)],
+                1.0,
+            )
+            .unwrap();
+            solve_exact(&context).unwrap().1
+        };
+        let action = VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::get_day_end(),
+            6000,
+            100,
+            1,
+        );
+        let plain = solve(action.clone());
+        // Later is worse, bounded far above the largest preference
+        let preference = (0..STEPS_PER_DAY as i64).collect();
+        let preferring = solve(action.with_preference(preference, 20.0));
+
+        let consumption = preferring.get_variable_action(1).unwrap();
+        for (time, consumed) in consumption.iter_consumption() {
+            let expected = if time < Time::at_default_resolution(1, 0) {
+                100
+            } else {
+                0
+            };
+            assert_eq!(consumed, expected, "consumes {consumed} at {time:?}");
+        }
+        assert_eq!(preferring.get_energy_cost(), plain.get_energy_cost());
+        let breakdown = preferring.get_objective_breakdown().unwrap();
+        assert_eq!(breakdown.preference, 100 * (0..60).sum::<i64>());
+        assert_eq!(
+            breakdown.monetary(),
+            plain.get_objective_breakdown().unwrap().monetary()
+        );
+        assert_eq!(plain.get_objective_breakdown().unwrap().preference, 0);
+    }
+
+    #[test]
+    fn test_preference_is_bounded_by_typical_price() {
+        let action = VariableAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(0, 4),
+            100,
+            100,
+            1,
+        )
+        .with_preference(vec![-500, -10, 10, 500], 0.5);
+        assert_eq!(
+            action.get_preference_at(Time::at_default_resolution(0, 0), 100),
+            -50
+        );
+        assert_eq!(
+            action.get_preference_at(Time::at_default_resolution(0, 1), 100),
+            -10
+        );
+        assert_eq!(
+            action.get_preference_at(Time::at_default_resolution(0, 3), -100),
+            50
+        );
+        assert_eq!(
+            action.get_preference_at(Time::at_default_resolution(0, 4), 100),
+            0
+        );
+    }
+
+    /// Costs after shifting random actions by up to 45 minutes like the annealing does, with
+    /// the flow updated in place or solved from scratch, and how long that took.
+    fn timed_moves(
+        context: &OptimizerContext,
+        constant_actions: &[Rc<ConstantAction>],
+        runs: usize,
+        incremental: bool,
+    ) -> (Duration, Vec<i64>) {
+        let mut flow = SmartHomeFlowBuilder::from_context(context).build();
+        for action in constant_actions {
+            flow.add_constant_consumption(action.clone().with_start_time(action.start_from));
+        }
+        flow.get_cost().unwrap();
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut costs = Vec::new();
+        let started = Instant::now();
+        for _ in 0..runs {
+            let action = &constant_actions[rng.random_range(0..constant_actions.len())];
+            let current = flow.constant_actions[&action.get_id()].get_start_time();
+            let latest = action.end_before.to_timestep() - action.duration.to_timestep();
+            let start = (current.to_timestep() as i64 + rng.random_range(-3..=3))
+                .clamp(action.start_from.to_timestep() as i64, latest as i64);
+            flow.add_constant_consumption(
+                action
+                    .clone()
+                    .with_start_time(Time::from_timestep(start as u32)),
+            );
+            flow.solved &= incremental;
+            costs.push(flow.get_cost().unwrap());
+        }
+        (started.elapsed(), costs)
+    }
+
+    #[test]
+    fn test_incremental_update_matches_recalculation() {
+        let (context, constant_actions) = full_day_context(3, 1.0);
+        let (_, expected) = timed_moves(&context, &constant_actions, 4, false);
+        let (_, costs) = timed_moves(&context, &constant_actions, 4, true);
+        assert_eq!(costs, expected);
+    }
+
+    #[test]
+    #[ignore = "compares wall-clock times, run it on an idle machine"]
+    fn test_incremental_update_is_faster_than_recalculation() {
+        let (context, constant_actions) = full_day_context(3, 1.0);
+        let (recalculated, expected) = timed_moves(&context, &constant_actions, 20, false);
+        let (incremental, costs) = timed_moves(&context, &constant_actions, 20, true);
+        assert_eq!(costs, expected);
+        assert!(
+            incremental * 5 < recalculated,
+            "incremental: {incremental:?}, recalculated: {recalculated:?}"
+        );
+    }
+}
@@ -1,9 +1,11 @@
-use std::{hash::Hash, ops::Deref, rc::Rc};
+use std::{fmt, hash::Hash, ops::Deref, rc::Rc};
 
-use crate::time::Time;
+use super::{ActionError, check_consumption, check_horizon};
+use crate::time::{Time, TimestepConfig};
 
-/// A constant action that consumes a fixed amount of energy over a specified duration within given time bounds.
+/// A constant action that consumes a fixed profile of energy over a specified duration within given time bounds.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConstantAction {
     /// The earliest time the action can start.
     pub start_from: Time,
@@ -11,8 +13,12 @@ pub struct ConstantAction {
     pub end_before: Time,
     /// The duration of the action.
     pub duration: Time,
-    /// The fixed consumption amount of the action for every timestep.
-    pub consumption: i64,
+    /// The consumption of the action for every timestep of its duration, relative to its start.
+    profile: Vec<i64>,
+    /// The consumption for every timestep of the horizon if the action isn't scheduled, see
+    /// [`ConstantAction::with_default_profile`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    default_profile: Option<Vec<i64>>,
     id: u32,
 }
 impl ConstantAction {
@@ -24,7 +30,7 @@ impl ConstantAction {
     /// * `consumption` - The fixed consumption amount of the action for every timestep.
     /// * `id` - The unique identifier for the action.
     /// # Panics
-    /// * Panics if the time bounds are invalid (i.e., if start_from + duration > end_before).
+    /// * Panics if [`ConstantAction::try_new`] would return an error.
     /// # Returns
     /// * A new ConstantAction instance.
     pub fn new(
@@ -34,18 +40,109 @@ impl ConstantAction {
         consumption: i64,
         id: u32,
     ) -> Self {
-        assert!(
-            start_from + duration <= end_before,
-            "Invalid time bounds for ConstantAction: start_from + duration must be <= end_before. Got start_from: {start_from:?}, duration: {duration:?}, end_before: {end_before:?}, current calculated end time: {:?}",
-            start_from + duration
-        );
-        Self {
+        Self::try_new(start_from, end_before, duration, consumption, id)
+            .unwrap_or_else(|e| panic!("Invalid ConstantAction: {e}"))
+    }
+    /// Creates a new ConstantAction like [`ConstantAction::new`].
+    /// # Errors
+    /// * If the window ends after the end of the day, if the duration doesn't fit into the
+    ///   window (i.e., if start_from + duration > end_before) or if the consumption is negative.
+    pub fn try_new(
+        start_from: Time,
+        end_before: Time,
+        duration: Time,
+        consumption: i64,
+        id: u32,
+    ) -> Result<Self, ActionError> {
+        check_consumption(id, "consumption", consumption)?;
+        let profile = vec![consumption; duration.to_timestep() as usize];
+        Self::try_new_profiled(start_from, end_before, profile, id)
+    }
+    /// Creates a new ConstantAction whose consumption changes over its duration.
+    /// # Arguments
+    /// * `start_from` - The earliest time the action can start.
+    /// * `end_before` - The latest time the action must end before.
+    /// * `profile` - The consumption for every timestep of the action. Its length is the duration.
+    /// * `id` - The unique identifier for the action.
+    /// # Panics
+    /// * Panics if [`ConstantAction::try_new_profiled`] would return an error.
+    /// # Returns
+    /// * A new ConstantAction instance.
+    pub fn new_profiled(start_from: Time, end_before: Time, profile: Vec<i64>, id: u32) -> Self {
+        Self::try_new_profiled(start_from, end_before, profile, id)
+            .unwrap_or_else(|e| panic!("Invalid ConstantAction: {e}"))
+    }
+    /// Creates a new ConstantAction like [`ConstantAction::new_profiled`].
+    /// # Errors
+    /// * If the window ends after the end of the day, if the profile doesn't fit into the
+    ///   window or if a value of the profile is negative.
+    pub fn try_new_profiled(
+        start_from: Time,
+        end_before: Time,
+        profile: Vec<i64>,
+        id: u32,
+    ) -> Result<Self, ActionError> {
+        let action = Self {
             start_from,
             end_before,
-            duration,
-            consumption,
+            duration: Time::from_timestep(profile.len() as u32),
+            profile,
+            default_profile: None,
             id,
+        };
+        action.validate()?;
+        Ok(action)
+    }
+    /// Returns the action with a default profile: the consumption for every timestep of the
+    /// horizon that happens anyway if the action isn't scheduled, e.g. a water heater left to
+    /// its thermostat. It is part of the beyond control consumption and is removed from it
+    /// when the action is scheduled, see
+    /// [`OptimizerContext::new`](crate::optimizer_context::OptimizerContext::new).
+    /// # Errors
+    /// * If a value of the default profile is negative.
+    pub fn with_default_profile(mut self, default_profile: Vec<i64>) -> Result<Self, ActionError> {
+        self.default_profile = Some(default_profile);
+        self.validate()?;
+        Ok(self)
+    }
+    /// Checks the action like [`ConstantAction::try_new_profiled`], e.g. after its public
+    /// fields were changed.
+    /// # Errors
+    /// * If the window ends after the end of the day, if the duration doesn't fit into the
+    ///   window or if a value of the profile or the default profile is negative.
+    pub fn validate(&self) -> Result<(), ActionError> {
+        self.validate_for(TimestepConfig::default())
+    }
+    /// Checks the action like [`ConstantAction::validate`], against the end of the day of
+    /// `timestep`. The constructors only know the finest timesteps, so an action for coarser
+    /// ones has to be checked again, see
+    /// [`OptimizerContext::new`](crate::optimizer_context::OptimizerContext::new).
+    /// # Errors
+    /// * Like [`ConstantAction::validate`]. Since the action has to end before `end_before`,
+    ///   it then never runs past the end of the day.
+    pub fn validate_for(&self, timestep: TimestepConfig) -> Result<(), ActionError> {
+        check_horizon(self.id, "start_from", self.start_from, timestep)?;
+        check_horizon(self.id, "end_before", self.end_before, timestep)?;
+        if self.start_from + self.duration > self.end_before {
+            return Err(ActionError::DoesNotFit {
+                id: self.id,
+                start_from: self.start_from,
+                duration: self.duration,
+                end_before: self.end_before,
+            });
+        }
+        if let Some(value) = self.profile.iter().find(|value| **value < 0) {
+            check_consumption(self.id, "profile", *value)?;
         }
+        if let Some(value) = self
+            .default_profile
+            .iter()
+            .flatten()
+            .find(|value| **value < 0)
+        {
+            check_consumption(self.id, "default_profile", *value)?;
+        }
+        Ok(())
     }
     /// Returns the start_from time of the action.
     pub fn get_start_from(&self) -> Time {
@@ -56,13 +153,43 @@ impl ConstantAction {
         self.end_before
     }
     /// Returns the duration of the action.
+    pub fn get_duration(&self) -> Time {
+        self.duration
+    }
+    /// Returns the unique identifier of the action.
     pub fn get_id(&self) -> u32 {
         self.id
     }
 
-    /// Returns the consumption of the action.
+    /// Returns the consumption for every timestep of the action, relative to its start.
+    pub fn get_profile(&self) -> &[i64] {
+        &self.profile
+    }
+
+    /// Returns the consumption for every timestep of the horizon if the action isn't scheduled,
+    /// see [`ConstantAction::with_default_profile`].
+    pub fn get_default_profile(&self) -> Option<&[i64]> {
+        self.default_profile.as_deref()
+    }
+
+    /// Returns the consumption of the action if it is the same for every timestep.
+    pub fn get_flat_consumption(&self) -> Option<i64> {
+        match self.profile.split_first() {
+            Some((first, rest)) if rest.iter().all(|c| c == first) => Some(*first),
+            Some(_) => None,
+            None => Some(0),
+        }
+    }
+
+    /// Returns the consumption of the action for every timestep, like before actions could
+    /// have a profile.
+    /// # Panics
+    /// * Panics if the consumption changes over the profile, see
+    ///   [`ConstantAction::get_flat_consumption`].
+    #[deprecated(note = "use get_flat_consumption, or get_profile for profiled actions")]
     pub fn get_consumption(&self) -> i64 {
-        self.consumption
+        self.get_flat_consumption()
+            .unwrap_or_else(|| panic!("Constant action {} has a changing profile", self.id))
     }
 
     pub fn with_start_time(self: Rc<Self>, start_time: Time) -> AssignedConstantAction {
@@ -72,6 +199,7 @@ impl ConstantAction {
 
 /// A constant action where the start time has been fixed / assigned.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssignedConstantAction {
     /// The constant action being assigned.
     action: Rc<ConstantAction>,
@@ -115,6 +243,38 @@ impl AssignedConstantAction {
     pub fn get_end_time(&self) -> Time {
         self.start_time + self.action.duration
     }
+
+    /// Returns the consumption at the given time, 0 outside of the assigned interval.
+    pub fn get_consumption(&self, time: Time) -> i64 {
+        if time < self.start_time {
+            return 0;
+        }
+        let offset = (time.to_timestep() - self.start_time.to_timestep()) as usize;
+        self.action.profile.get(offset).copied().unwrap_or(0)
+    }
+
+    /// Returns the consumption at `time` that could be avoided by starting one timestep later:
+    /// the first value of the profile if the action starts at `time` and its window leaves
+    /// room to start later, 0 otherwise.
+    pub fn get_deferrable_consumption(&self, time: Time) -> i64 {
+        let delayed_end = self.get_end_time().get_next_timestep();
+        if self.start_time != time || delayed_end > self.action.end_before {
+            return 0;
+        }
+        self.get_consumption(time)
+    }
+
+    /// Returns the consumption of every assigned timestep as `(time, consumption)`.
+    pub fn iter_consumption(&self) -> impl Iterator<Item = (Time, i64)> + '_ {
+        let start = self.start_time.to_timestep();
+        self.action
+            .profile
+            .iter()
+            .enumerate()
+            .map(move |(offset, consumption)| {
+                (Time::from_timestep(start + offset as u32), *consumption)
+            })
+    }
 }
 
 impl Deref for AssignedConstantAction {
@@ -140,3 +300,54 @@ impl PartialEq for AssignedConstantAction {
 }
 
 impl Eq for AssignedConstantAction {}
+
+/// Reasons a precedence between constant actions can't be kept, see
+/// [`OptimizerContext::with_precedence`](crate::optimizer_context::OptimizerContext::with_precedence).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrecedenceError {
+    /// The context has no constant action with this id.
+    UnknownAction(u32),
+    /// The precedences require an action to end before it starts, e.g. A before B before A.
+    Cycle,
+    /// The window of this action leaves no room to start after its predecessors and end
+    /// before its successors.
+    Infeasible(u32),
+}
+
+/// Reasons an exclusion group of constant actions can't be kept, see
+/// [`OptimizerContext::with_exclusion_group`](crate::optimizer_context::OptimizerContext::with_exclusion_group).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExclusionError {
+    /// The context has no constant action with this id.
+    UnknownAction(u32),
+    /// No start time in the window of this action keeps it apart from the actions of its
+    /// groups that are placed before it.
+    Infeasible(u32),
+}
+
+impl fmt::Display for ExclusionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExclusionError::UnknownAction(id) => write!(f, "No constant action with id {}", id),
+            ExclusionError::Infeasible(id) => write!(
+                f,
+                "The window of constant action {} leaves no room to run apart from its exclusion groups",
+                id
+            ),
+        }
+    }
+}
+
+impl fmt::Display for PrecedenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrecedenceError::UnknownAction(id) => write!(f, "No constant action with id {}", id),
+            PrecedenceError::Cycle => write!(f, "The precedences form a cycle"),
+            PrecedenceError::Infeasible(id) => write!(
+                f,
+                "The window of constant action {} leaves no room for the precedences",
+                id
+            ),
+        }
+    }
+}
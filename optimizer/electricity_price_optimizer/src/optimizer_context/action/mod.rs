@@ -1,2 +1,278 @@
+use std::fmt;
+
+use crate::time::{Time, TimestepConfig};
+
 pub mod constant;
 pub mod variable;
+
+/// Reasons an action can't be created, see
+/// [`ConstantAction::try_new`](constant::ConstantAction::try_new) and
+/// [`VariableAction::try_new`](variable::VariableAction::try_new).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionError {
+    /// A bound of the window, named by `field`, is after `day_end`, the end of the horizon.
+    BeyondHorizon {
+        id: u32,
+        field: &'static str,
+        time: Time,
+        day_end: Time,
+    },
+    /// The duration of a constant action doesn't fit between `start_from` and `end_before`.
+    DoesNotFit {
+        id: u32,
+        start_from: Time,
+        duration: Time,
+        end_before: Time,
+    },
+    /// The window of a variable action doesn't end after it starts.
+    EmptyWindow { id: u32, start: Time, end: Time },
+    /// A contiguous variable action has no block of consecutive timesteps that holds its total
+    /// within its window, caps and minimum consumption.
+    NoContiguousBlock { id: u32 },
+    /// A consumption, named by `field`, is negative.
+    NegativeConsumption {
+        id: u32,
+        field: &'static str,
+        value: i64,
+    },
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::BeyondHorizon {
+                id,
+                field,
+                time,
+                day_end,
+            } => write!(
+                f,
+                "Action {}: {} {:?} is after the end of the day {:?}",
+                id, field, time, day_end
+            ),
+            ActionError::DoesNotFit {
+                id,
+                start_from,
+                duration,
+                end_before,
+            } => write!(
+                f,
+                "Action {}: duration {:?} doesn't fit between start_from {:?} and end_before {:?}",
+                id, duration, start_from, end_before
+            ),
+            ActionError::EmptyWindow { id, start, end } => write!(
+                f,
+                "Action {}: start {:?} must be before end {:?}",
+                id, start, end
+            ),
+            ActionError::NoContiguousBlock { id } => write!(
+                f,
+                "Action {}: total_consumption doesn't fit into one block of consecutive timesteps within the window, max_consumption and min_consumption",
+                id
+            ),
+            ActionError::NegativeConsumption { id, field, value } => write!(
+                f,
+                "Action {}: {} must not be negative, got {}",
+                id, field, value
+            ),
+        }
+    }
+}
+
+/// Check that `time`, the bound of action `id` named `field`, is within the day of `timestep`.
+fn check_horizon(
+    id: u32,
+    field: &'static str,
+    time: Time,
+    timestep: TimestepConfig,
+) -> Result<(), ActionError> {
+    let day_end = timestep.get_day_end();
+    if time > day_end {
+        return Err(ActionError::BeyondHorizon {
+            id,
+            field,
+            time,
+            day_end,
+        });
+    }
+    Ok(())
+}
+
+/// Check that `value`, the consumption of action `id` named `field`, isn't negative.
+fn check_consumption(id: u32, field: &'static str, value: i64) -> Result<(), ActionError> {
+    if value < 0 {
+        return Err(ActionError::NegativeConsumption { id, field, value });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActionError, constant::ConstantAction, variable::VariableAction};
+    use crate::time::{Time, TimestepConfig};
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_flat_consumption_keeps_its_old_accessor() {
+        let flat = ConstantAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(3, 0),
+            Time::at_default_resolution(1, 0),
+            10,
+            1,
+        );
+        assert_eq!(flat.get_consumption(), 10);
+        assert_eq!(flat.get_flat_consumption(), Some(10));
+        let profiled = ConstantAction::new_profiled(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(3, 0),
+            vec![10, 20],
+            2,
+        );
+        assert_eq!(profiled.get_flat_consumption(), None);
+        assert!(std::panic::catch_unwind(|| profiled.get_consumption()).is_err());
+    }
+
+    #[test]
+    fn test_constructors_reject_invalid_actions() {
+        let day_end = Time::get_day_end();
+        let hour = Time::at_default_resolution(1, 0);
+        assert!(
+            ConstantAction::try_new(Time::at_default_resolution(23, 0), day_end, hour, 10, 1)
+                .is_ok()
+        );
+        assert_eq!(
+            ConstantAction::try_new(
+                Time::at_default_resolution(23, 0),
+                Time::at_default_resolution(24, 1),
+                hour,
+                10,
+                1
+            )
+            .unwrap_err(),
+            ActionError::BeyondHorizon {
+                id: 1,
+                field: "end_before",
+                time: Time::at_default_resolution(24, 1),
+                day_end,
+            }
+        );
+        // The constructors only know the finest timesteps, hourly ones end at step 24
+        let hourly = TimestepConfig::new(60).unwrap();
+        let straddling = ConstantAction::try_new(
+            Time::from_timestep(23),
+            Time::from_timestep(25),
+            Time::from_timestep(2),
+            10,
+            1,
+        );
+        assert_eq!(
+            straddling.unwrap().validate_for(hourly),
+            Err(ActionError::BeyondHorizon {
+                id: 1,
+                field: "end_before",
+                time: Time::from_timestep(25),
+                day_end: Time::from_timestep(24),
+            })
+        );
+        let last_hour = ConstantAction::try_new(
+            Time::from_timestep(23),
+            Time::from_timestep(24),
+            Time::from_timestep(1),
+            10,
+            1,
+        );
+        assert!(last_hour.unwrap().validate_for(hourly).is_ok());
+        assert!(matches!(
+            ConstantAction::try_new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(1, 30),
+                hour,
+                10,
+                2
+            ),
+            Err(ActionError::DoesNotFit { id: 2, .. })
+        ));
+        assert!(matches!(
+            ConstantAction::try_new_profiled(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(2, 0),
+                vec![5, -5],
+                3
+            ),
+            Err(ActionError::NegativeConsumption {
+                id: 3,
+                field: "profile",
+                value: -5
+            })
+        ));
+
+        assert!(
+            VariableAction::try_new(Time::at_default_resolution(23, 0), day_end, 100, 10, 4)
+                .is_ok()
+        );
+        assert!(matches!(
+            VariableAction::try_new(
+                Time::at_default_resolution(23, 0),
+                Time::at_default_resolution(30, 0),
+                100,
+                10,
+                4
+            ),
+            Err(ActionError::BeyondHorizon { field: "end", .. })
+        ));
+        assert!(matches!(
+            VariableAction::try_new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(2, 0),
+                100,
+                10,
+                5
+            ),
+            Err(ActionError::EmptyWindow { id: 5, .. })
+        ));
+        assert!(matches!(
+            VariableAction::try_new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(2, 0),
+                -100,
+                10,
+                6
+            ),
+            Err(ActionError::NegativeConsumption {
+                field: "total_consumption",
+                ..
+            })
+        ));
+
+        // 1000 at most 30 per timestep takes 34 timesteps, 14 of them at 30 and 20 at 29
+        let contiguous = VariableAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(2, 0),
+            1_000,
+            30,
+            7,
+        )
+        .with_contiguous(true);
+        let block = contiguous.get_block().unwrap();
+        assert_eq!(block.get_id(), 7);
+        assert_eq!(block.get_duration(), Time::at_default_resolution(0, 34));
+        assert_eq!(block.get_profile()[..15], [vec![30; 14], vec![29]].concat());
+        assert_eq!(block.get_profile().iter().sum::<i64>(), 1_000);
+        assert!(matches!(
+            VariableAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(1, 30),
+                1_000,
+                30,
+                8
+            )
+            .get_block(),
+            Err(ActionError::NoContiguousBlock { id: 8 })
+        ));
+        assert!(matches!(
+            contiguous.with_min_consumption(30).get_block(),
+            Err(ActionError::NoContiguousBlock { id: 7 })
+        ));
+    }
+}
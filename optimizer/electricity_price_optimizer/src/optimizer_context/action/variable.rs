@@ -1,13 +1,17 @@
-use std::{
-    ops::{Deref, DerefMut},
-    panic,
-    rc::Rc,
-};
+use std::{cmp::Reverse, ops::Deref, panic, rc::Rc};
 
-use crate::time::Time;
+use super::{
+    ActionError, check_consumption, check_horizon,
+    constant::{AssignedConstantAction, ConstantAction},
+};
+use crate::{
+    optimizer::scale_first_timestep,
+    time::{Time, TimeIterator, TimestepConfig},
+};
 
 /// A variable action that consumes a total amount of energy within specified time bounds, with a maximum consumption limit per timestep.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VariableAction {
     /// The earliest time the action can start.
     pub start: Time,
@@ -16,7 +20,27 @@ pub struct VariableAction {
     /// The total consumption amount of the action.
     pub total_consumption: i64,
     /// The maximum consumption amount of the action for every timestep.
+    /// With a `max_consumption_profile` this is the largest of its caps.
     pub max_consumption: i64,
+    /// Maximum consumption of every timestep from `start` to `end`, if it varies over time.
+    #[cfg_attr(feature = "serde", serde(default))]
+    max_consumption_profile: Option<Vec<i64>>,
+    /// The least consumption of a timestep in which the action consumes anything, e.g. for
+    /// chargers that can't run below a minimum power.
+    #[cfg_attr(feature = "serde", serde(default))]
+    min_consumption: i64,
+    /// Cost added per unit consumed in every timestep from `start` to `end`, in the unit of the
+    /// electricity price, to prefer some timesteps over others. It breaks ties and isn't money.
+    #[cfg_attr(feature = "serde", serde(default))]
+    preference: Option<Vec<i64>>,
+    /// Largest preference as a share of the average absolute electricity price, see
+    /// [`VariableAction::with_preference`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    preference_limit: f32,
+    /// Whether the action consumes in one block of consecutive timesteps, see
+    /// [`VariableAction::with_contiguous`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    contiguous: bool,
     /// The unique identifier for the action.
     id: u32,
 }
@@ -31,7 +55,7 @@ impl VariableAction {
     /// * `max_consumption` - The maximum consumption amount of the action for every timestep.
     /// * `id` - The unique identifier for the action.
     /// # Panics
-    /// * Panics if the time bounds are invalid (i.e., if start >= end).
+    /// * Panics if [`VariableAction::try_new`] would return an error.
     /// # Returns
     /// * A new VariableAction instance.
     pub fn new(
@@ -41,17 +65,188 @@ impl VariableAction {
         max_consumption: i64,
         id: u32,
     ) -> Self {
-        assert!(
-            start < end,
-            "Invalid variable action time bounds: start must be less than end"
-        );
-        Self {
+        Self::try_new(start, end, total_consumption, max_consumption, id)
+            .unwrap_or_else(|e| panic!("Invalid VariableAction: {e}"))
+    }
+
+    /// Creates a new VariableAction like [`VariableAction::new`].
+    ///
+    /// # Errors
+    /// * If the time bounds are invalid (i.e., if start >= end), if the window ends after the
+    ///   end of the day or if a consumption is negative.
+    pub fn try_new(
+        start: Time,
+        end: Time,
+        total_consumption: i64,
+        max_consumption: i64,
+        id: u32,
+    ) -> Result<Self, ActionError> {
+        let action = Self {
             start,
             end,
             total_consumption,
             max_consumption,
+            max_consumption_profile: None,
+            min_consumption: 0,
+            preference: None,
+            preference_limit: 0.0,
+            contiguous: false,
             id,
+        };
+        action.validate()?;
+        Ok(action)
+    }
+
+    /// Checks the action like [`VariableAction::try_new`], e.g. after its public fields were
+    /// changed. A contiguous action also needs a block, see [`VariableAction::get_block`].
+    ///
+    /// # Errors
+    /// * If the time bounds are invalid (i.e., if start >= end), if the window ends after the
+    ///   end of the day, if a consumption is negative or if a contiguous action has no block.
+    pub fn validate(&self) -> Result<(), ActionError> {
+        self.validate_for(TimestepConfig::default())
+    }
+
+    /// Checks the action like [`VariableAction::validate`], against the end of the day of
+    /// `timestep`, see [`ConstantAction::validate_for`].
+    ///
+    /// # Errors
+    /// * Like [`VariableAction::validate`].
+    pub fn validate_for(&self, timestep: TimestepConfig) -> Result<(), ActionError> {
+        let (id, start, end) = (self.id, self.start, self.end);
+        check_horizon(id, "start", start, timestep)?;
+        check_horizon(id, "end", end, timestep)?;
+        if start >= end {
+            return Err(ActionError::EmptyWindow { id, start, end });
+        }
+        check_consumption(id, "total_consumption", self.total_consumption)?;
+        check_consumption(id, "max_consumption", self.max_consumption)?;
+        if self.contiguous {
+            self.get_block()?;
+        }
+        Ok(())
+    }
+
+    /// Sets a maximum consumption for every timestep of the action, replacing the constant one.
+    ///
+    /// # Panics
+    /// * Panics if the profile doesn't have exactly one value per timestep from start to end.
+    pub fn with_max_consumption_profile(mut self, profile: Vec<i64>) -> Self {
+        assert_eq!(
+            profile.len() as u32,
+            self.end.to_timestep() - self.start.to_timestep(),
+            "Max consumption profile length does not match action duration"
+        );
+        self.max_consumption = profile.iter().copied().max().unwrap_or(0);
+        self.max_consumption_profile = Some(profile);
+        self
+    }
+    /// Sets the least consumption of every timestep in which the action consumes anything.
+    /// The flow can't express "off or at least the minimum", so its result is rounded
+    /// afterwards, see `AssignedVariableAction::round_to_min_consumption`.
+    ///
+    /// # Panics
+    /// * Panics if the minimum is negative.
+    pub fn with_min_consumption(mut self, min_consumption: i64) -> Self {
+        assert!(
+            min_consumption >= 0,
+            "Minimum consumption must not be negative"
+        );
+        self.min_consumption = min_consumption;
+        self
+    }
+    /// Sets a cost per unit consumed for every timestep of the action, e.g. negative for the
+    /// early timesteps to consume as early as the prices allow. The flow adds it to the cost of
+    /// consuming, bounded to `limit` times the average absolute electricity price so that it
+    /// can't outweigh the prices.
+    ///
+    /// # Panics
+    /// * Panics if the profile doesn't have exactly one value per timestep from start to end or
+    ///   if the limit is negative or not finite.
+    pub fn with_preference(mut self, profile: Vec<i64>, limit: f32) -> Self {
+        assert_eq!(
+            profile.len() as u32,
+            self.end.to_timestep() - self.start.to_timestep(),
+            "Preference length does not match action duration"
+        );
+        assert!(
+            limit.is_finite() && limit >= 0.0,
+            "Preference limit must not be negative"
+        );
+        self.preference = Some(profile);
+        self.preference_limit = limit;
+        self
+    }
+    /// Makes the action consume its total in one block of consecutive timesteps at a fixed
+    /// rate, e.g. for a heat pump boost that mustn't be interrupted. The flow can't express
+    /// this, so the simulated annealing places the block like a constant action, see
+    /// [`VariableAction::get_block`]. A preference isn't taken into account for the block.
+    pub fn with_contiguous(mut self, contiguous: bool) -> Self {
+        self.contiguous = contiguous;
+        self
+    }
+    /// Returns whether the action consumes in one block, see `with_contiguous`.
+    pub fn is_contiguous(&self) -> bool {
+        self.contiguous
+    }
+    /// Returns the block of a contiguous action as a constant action with the same id and
+    /// window. The block is the shortest that holds the total at the smallest cap of the
+    /// window, its rate is the total divided by its duration, with the remainder spread over
+    /// its first timesteps.
+    ///
+    /// # Errors
+    /// * If the block doesn't fit into the window or its rate is below the minimum consumption.
+    pub fn get_block(&self) -> Result<ConstantAction, ActionError> {
+        let no_block = ActionError::NoContiguousBlock { id: self.id };
+        if self.total_consumption == 0 {
+            return Ok(ConstantAction::new_profiled(
+                self.start,
+                self.end,
+                vec![],
+                self.id,
+            ));
+        }
+        let cap = (self.start..self.end)
+            .iter_steps()
+            .map(|time| self.get_max_consumption_at(time))
+            .min()
+            .unwrap_or(0);
+        if cap <= 0 {
+            return Err(no_block);
         }
+        let duration = (self.total_consumption + cap - 1) / cap;
+        let (rate, remainder) = (
+            self.total_consumption / duration,
+            self.total_consumption % duration,
+        );
+        if rate < self.min_consumption {
+            return Err(no_block);
+        }
+        let profile = (0..duration)
+            .map(|i| if i < remainder { rate + 1 } else { rate })
+            .collect();
+        ConstantAction::try_new_profiled(self.start, self.end, profile, self.id)
+            .map_err(|_| no_block)
+    }
+    /// Returns the preference set by `with_preference`, unbounded.
+    pub fn get_preference(&self) -> Option<&[i64]> {
+        self.preference.as_deref()
+    }
+    /// Returns the limit of the preference set by `with_preference`.
+    pub fn get_preference_limit(&self) -> f32 {
+        self.preference_limit
+    }
+    /// Returns the preference in the timestep at `time`, bounded to the limit times
+    /// `typical_price`. 0 without a preference or outside of the action window.
+    pub fn get_preference_at(&self, time: Time, typical_price: i64) -> i64 {
+        let Some(preference) = &self.preference else {
+            return 0;
+        };
+        if time < self.start || time >= self.end {
+            return 0;
+        }
+        let bound = (typical_price.abs() as f64 * self.preference_limit as f64).round() as i64;
+        preference[(time.to_timestep() - self.start.to_timestep()) as usize].clamp(-bound, bound)
     }
     /// Returns the start time of the action.
     pub fn get_start(&self) -> Time {
@@ -70,13 +265,49 @@ impl VariableAction {
         self.total_consumption
     }
     /// Returns the maximum consumption per timestep of the action.
+    /// With a max consumption profile this is the largest cap.
     pub fn get_max_consumption(&self) -> i64 {
         self.max_consumption
     }
+    /// Returns the per-timestep caps set by `with_max_consumption_profile`.
+    pub fn get_max_consumption_profile(&self) -> Option<&[i64]> {
+        self.max_consumption_profile.as_deref()
+    }
+    /// Returns the maximum consumption in the timestep at `time`, 0 outside of the action window.
+    pub fn get_max_consumption_at(&self, time: Time) -> i64 {
+        if time < self.start || time >= self.end {
+            return 0;
+        }
+        match &self.max_consumption_profile {
+            Some(profile) => profile[(time.to_timestep() - self.start.to_timestep()) as usize],
+            None => self.max_consumption,
+        }
+    }
+    /// Returns the least consumption of a timestep in which the action consumes anything.
+    pub fn get_min_consumption(&self) -> i64 {
+        self.min_consumption
+    }
+    /// Returns whether the total consumption can be split into timesteps that either consume
+    /// nothing or at least the minimum consumption, without exceeding any cap.
+    pub fn can_reach_total_with_min_consumption(&self) -> bool {
+        let caps: Vec<i64> = (self.start..self.end)
+            .iter_steps()
+            .map(|time| self.get_max_consumption_at(time))
+            .collect();
+        total_reachable_with_min(&caps, self.min_consumption, self.total_consumption)
+    }
+    /// Returns the most the action can consume over its window if it runs at its cap throughout.
+    pub fn get_max_total_consumption(&self) -> i64 {
+        (self.start..self.end)
+            .iter_steps()
+            .map(|time| self.get_max_consumption_at(time))
+            .sum()
+    }
 }
 
 /// A variable action where the consumption per timestep has been fixed to specific values.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssignedVariableAction {
     /// The variable action being assigned.
     action: Rc<VariableAction>,
@@ -104,6 +335,10 @@ impl AssignedVariableAction {
         }
     }
 
+    /// Returns the consumption assigned to timestep `time`.
+    ///
+    /// # Panics
+    /// * Panics if `time` is outside of the window of the action.
     pub fn get_consumption(&self, time: Time) -> i64 {
         if time < self.action.start || time >= self.action.end {
             panic!(
@@ -114,6 +349,112 @@ impl AssignedVariableAction {
         let index = (time.to_timestep() - self.action.start.to_timestep()) as usize;
         self.consumption[index]
     }
+
+    /// Returns an iterator over the assigned consumption of every timestep from start to end.
+    pub fn iter_consumption(&self) -> impl Iterator<Item = (Time, i64)> + '_ {
+        let start = self.action.start.to_timestep();
+        self.consumption
+            .iter()
+            .enumerate()
+            .map(move |(i, consumption)| (Time::from_timestep(start + i as u32), *consumption))
+    }
+
+    /// Returns how much of the consumption assigned to timestep `time` could be moved to the
+    /// later timesteps of the window, as far as their caps leave room. 0 outside the window.
+    pub fn get_deferrable_consumption(&self, time: Time) -> i64 {
+        if time < self.action.start || time >= self.action.end {
+            return 0;
+        }
+        let room: i64 = (time.get_next_timestep()..self.action.end)
+            .iter_steps()
+            .map(|later| self.action.get_max_consumption_at(later) - self.get_consumption(later))
+            .sum();
+        self.get_consumption(time).min(room.max(0))
+    }
+
+    /// Rounds timesteps consuming less than the minimum consumption up to it or down to 0,
+    /// keeping the total.
+    ///
+    /// No timestep grows by more than `room` allows, e.g. to keep the grid limit. The timesteps
+    /// the flow assigned the most to stay on, as few more as needed are added. They get the
+    /// minimum, then what the flow assigned and then up to their cap, in that order. If the
+    /// total can't be kept, e.g. because it is below the minimum, as much as possible is kept.
+    pub fn round_to_min_consumption(
+        &mut self,
+        first_timestep_fraction: f32,
+        room: impl Fn(Time) -> i64,
+    ) {
+        let min = self.action.min_consumption;
+        if min == 0 || self.consumption.iter().all(|&c| c == 0 || c >= min) {
+            return;
+        }
+        let caps: Vec<i64> = self
+            .iter_consumption()
+            .map(|(time, consumption)| {
+                let cap = self.action.get_max_consumption_at(time);
+                let cap = scale_first_timestep(cap, time, first_timestep_fraction);
+                cap.min(consumption.saturating_add(room(time).max(0)))
+            })
+            .collect();
+        let total = self.get_total_assigned();
+        let mut order: Vec<usize> = (0..caps.len()).filter(|&i| caps[i] >= min).collect();
+        order.sort_by_key(|&i| (Reverse(self.consumption[i]), Reverse(caps[i]), i));
+        let most = ((total / min) as usize).min(order.len());
+        let used = order.iter().filter(|&&i| self.consumption[i] > 0).count();
+        // The fewest of the preferred timesteps that can hold the total, or the largest caps
+        let mut reach = 0;
+        let active_count = (0..most).find(|&k| {
+            reach += caps[order[k]];
+            k + 1 >= used && reach >= total
+        });
+        let active = match active_count {
+            Some(k) => order[..=k].to_vec(),
+            None => {
+                order.sort_by_key(|&i| (Reverse(caps[i]), i));
+                order[..most].to_vec()
+            }
+        };
+
+        let mut rounded = vec![0; caps.len()];
+        let mut left = total;
+        for &i in &active {
+            rounded[i] = min;
+            left -= min;
+        }
+        for limit in [&self.consumption, &caps] {
+            for &i in &active {
+                let extra = (limit[i] - rounded[i]).clamp(0, left);
+                rounded[i] += extra;
+                left -= extra;
+            }
+        }
+        self.consumption = rounded;
+    }
+
+    /// Assigns a contiguous action the consumption of its block, placed as in `block`. The
+    /// first timestep of the day is scaled by `first_timestep_fraction`, like the flow scales
+    /// constant consumption.
+    ///
+    /// # Panics
+    /// * Panics if the block doesn't lie within the window of the action.
+    pub fn from_block(
+        action: Rc<VariableAction>,
+        block: &AssignedConstantAction,
+        first_timestep_fraction: f32,
+    ) -> Self {
+        let mut consumption =
+            vec![0; (action.end.to_timestep() - action.start.to_timestep()) as usize];
+        for (time, value) in block.iter_consumption() {
+            let index = (time.to_timestep() - action.start.to_timestep()) as usize;
+            consumption[index] = scale_first_timestep(value, time, first_timestep_fraction);
+        }
+        Self::new(action, consumption)
+    }
+
+    /// Returns the sum of the assigned consumption over all timesteps.
+    pub fn get_total_assigned(&self) -> i64 {
+        self.consumption.iter().sum()
+    }
 }
 
 impl Deref for AssignedVariableAction {
@@ -123,3 +464,24 @@ impl Deref for AssignedVariableAction {
         &self.action
     }
 }
+
+/// Returns whether `total` can be split into parts that are each at least `min` and at most
+/// the cap of their timestep.
+fn total_reachable_with_min(caps: &[i64], min: i64, total: i64) -> bool {
+    if total == 0 {
+        return true;
+    }
+    let mut caps: Vec<i64> = caps
+        .iter()
+        .copied()
+        .filter(|&cap| cap >= min.max(1))
+        .collect();
+    caps.sort_unstable_by_key(|&cap| Reverse(cap));
+    // k timesteps reach everything from k * min to the sum of the k largest caps, and the
+    // largest k with k * min <= total reaches the furthest.
+    let most = match min {
+        0 => caps.len(),
+        _ => ((total / min) as usize).min(caps.len()),
+    };
+    caps[..most].iter().sum::<i64>() >= total
+}
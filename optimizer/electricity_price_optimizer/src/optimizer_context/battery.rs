@@ -1,9 +1,14 @@
-use std::rc::Rc;
+use std::{fmt, rc::Rc};
 
-use crate::{optimizer_context::prognoses::Prognoses, time::Time};
+use crate::{
+    optimizer::scale_first_timestep,
+    optimizer_context::prognoses::Prognoses,
+    time::{Time, TimestepConfig},
+};
 
 /// A struct representing a battery with various attributes.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Battery {
     /// The maximum capacity of the battery.
     capacity: i64,
@@ -13,9 +18,23 @@ pub struct Battery {
     maximum_charge_rate: i64,
     /// The maximum rate at which the battery can output energy.
     maximum_output_rate: i64,
+    /// Fraction of the energy drawn while charging that ends up stored, in (0, 1].
     efficiency: f32,
     /// Unique identifier for the battery. Used to distinguish between multiple batteries.
     id: u32,
+    /// Whether the battery may be charged with electricity from the grid.
+    /// If false, it can only be charged with locally generated electricity.
+    grid_charging_allowed: bool,
+    /// Reserve the charge level never drops below.
+    min_charge: i64,
+    /// Charge level the battery should have at least at the end of the horizon.
+    final_charge_target: Option<i64>,
+    /// Fraction of the stored energy lost per hour, in [0, 1).
+    #[cfg_attr(feature = "serde", serde(default))]
+    self_discharge_per_hour: f32,
+    /// Wear cost per unit of energy stored, in the unit of the electricity price.
+    #[cfg_attr(feature = "serde", serde(default))]
+    cycle_cost: i64,
 }
 
 impl Battery {
@@ -26,10 +45,11 @@ impl Battery {
     /// * `initial_level` - The initial charge level of the battery.
     /// * `maximum_charge_rate` - The maximum rate at which the battery can be charged.
     /// * `maximum_output_rate` - The maximum rate at which the battery can output energy.
-    /// * `efficiency` - The efficiency of the battery.
+    /// * `efficiency` - The charging efficiency of the battery, in (0, 1].
     /// * `id` - Unique identifier for the battery.
     /// # Panics
     /// * Panics if the initial_level exceeds the capacity.
+    /// * Panics if the efficiency is not in (0, 1].
     /// # Returns
     ///
     /// A new Battery instance.
@@ -45,6 +65,10 @@ impl Battery {
             initial_level <= capacity,
             "Initial battery level cannot exceed capacity"
         );
+        assert!(
+            efficiency > 0.0 && efficiency <= 1.0,
+            "Battery efficiency must be in (0, 1], got {efficiency}"
+        );
         Self {
             capacity,
             initial_level,
@@ -52,34 +76,130 @@ impl Battery {
             maximum_output_rate,
             efficiency,
             id,
+            grid_charging_allowed: true,
+            min_charge: 0,
+            final_charge_target: None,
+            self_discharge_per_hour: 0.0,
+            cycle_cost: 0,
         }
     }
+    /// Sets whether the battery may be charged from the grid. Allowed by default.
+    pub fn with_grid_charging_allowed(mut self, allowed: bool) -> Self {
+        self.grid_charging_allowed = allowed;
+        self
+    }
+    /// Sets the reserve the charge level never drops below. No reserve by default.
+    /// # Panics
+    /// * Panics if the reserve exceeds the capacity or the initial level.
+    pub fn with_min_charge(mut self, min_charge: i64) -> Self {
+        assert!(
+            (0..=self.capacity).contains(&min_charge),
+            "Minimum battery charge must be between 0 and the capacity"
+        );
+        assert!(
+            min_charge <= self.initial_level,
+            "Initial battery level cannot be below the minimum charge"
+        );
+        self.min_charge = min_charge;
+        self
+    }
+    /// Sets the charge level the battery should reach by the end of the horizon.
+    /// # Panics
+    /// * Panics if the target exceeds the capacity.
+    pub fn with_final_charge_target(mut self, target: i64) -> Self {
+        assert!(
+            target <= self.capacity,
+            "Final battery charge target cannot exceed capacity"
+        );
+        self.final_charge_target = Some(target);
+        self
+    }
+    /// Sets the fraction of the stored energy lost per hour. No self-discharge by default.
+    /// # Panics
+    /// * Panics if the fraction is not in [0, 1).
+    pub fn with_self_discharge_per_hour(mut self, self_discharge_per_hour: f32) -> Self {
+        assert!(
+            (0.0..1.0).contains(&self_discharge_per_hour),
+            "Battery self-discharge must be in [0, 1), got {self_discharge_per_hour}"
+        );
+        self.self_discharge_per_hour = self_discharge_per_hour;
+        self
+    }
+    /// Sets the wear cost of every unit of energy stored, which keeps the battery from cycling
+    /// when it doesn't save more than that. No wear cost by default.
+    /// # Panics
+    /// * Panics if the cost is negative.
+    pub fn with_cycle_cost(mut self, cycle_cost: i64) -> Self {
+        assert!(
+            cycle_cost >= 0,
+            "Battery cycle cost can't be negative, got {cycle_cost}"
+        );
+        self.cycle_cost = cycle_cost;
+        self
+    }
     /// Returns the unique identifier of the battery.
     pub fn get_id(&self) -> u32 {
         self.id
     }
     /// Returns the maximum charge rate of the battery.
     pub fn get_max_charge(&self) -> i64 {
-        return self.maximum_charge_rate;
+        self.maximum_charge_rate
     }
     /// Returns the maximum output rate of the battery.
     pub fn get_max_output(&self) -> i64 {
-        return self.maximum_output_rate;
+        self.maximum_output_rate
     }
     /// Returns the capacity of the battery.
     pub fn get_capacity(&self) -> i64 {
-        return self.capacity;
+        self.capacity
     }
     /// Returns the initial charge level of the battery.
     pub fn get_initial_level(&self) -> i64 {
-        return self.initial_level;
+        self.initial_level
+    }
+    /// Returns the charging efficiency of the battery.
+    pub fn get_efficiency(&self) -> f32 {
+        self.efficiency
+    }
+    /// Returns whether the battery may be charged from the grid.
+    pub fn is_grid_charging_allowed(&self) -> bool {
+        self.grid_charging_allowed
+    }
+    /// Returns the reserve the charge level never drops below.
+    pub fn get_min_charge(&self) -> i64 {
+        self.min_charge
+    }
+    /// Returns the charge level the battery should have at the end of the horizon, if any.
+    pub fn get_final_charge_target(&self) -> Option<i64> {
+        self.final_charge_target
+    }
+    /// Returns the fraction of the stored energy lost per hour.
+    pub fn get_self_discharge_per_hour(&self) -> f32 {
+        self.self_discharge_per_hour
+    }
+    /// Returns the wear cost per unit of energy stored.
+    pub fn get_cycle_cost(&self) -> i64 {
+        self.cycle_cost
+    }
+    /// Returns the fraction of the stored energy lost per timestep of `timestep`, compounding
+    /// to the self-discharge per hour.
+    pub fn get_self_discharge_per_step(&self, timestep: TimestepConfig) -> f64 {
+        let steps_per_hour = 60.0 / timestep.get_minutes_per_timestep() as f64;
+        1.0 - (1.0 - self.self_discharge_per_hour as f64).powf(1.0 / steps_per_hour)
     }
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssignedBattery {
     battery: Rc<Battery>,
+    /// Charge level at the start of every timestep.
     charge_level: Prognoses<i64>,
+    /// Charge level at the end of the last timestep, if known.
+    final_charge_level: Option<i64>,
+    /// Locally generated electricity stored per timestep.
+    /// Only known for batteries that can't be charged from the grid.
+    green_charge: Option<Prognoses<i64>>,
 }
 
 impl AssignedBattery {
@@ -87,14 +207,136 @@ impl AssignedBattery {
         Self {
             battery,
             charge_level,
+            final_charge_level: None,
+            green_charge: None,
         }
     }
 
+    pub fn with_final_charge_level(mut self, final_charge_level: i64) -> Self {
+        self.final_charge_level = Some(final_charge_level);
+        self
+    }
+
+    pub fn with_green_charge(mut self, green_charge: Prognoses<i64>) -> Self {
+        self.green_charge = Some(green_charge);
+        self
+    }
+
+    /// Returns how much locally generated electricity was stored at the given time.
+    /// `None` if the battery can be charged from the grid, since the source of its charge isn't tracked then.
+    pub fn get_green_charge(&self, time: Time) -> Option<i64> {
+        self.green_charge
+            .as_ref()
+            .map(|green_charge| *green_charge.get(time).unwrap_or(&0))
+    }
+
     pub fn get_battery(&self) -> &Rc<Battery> {
         &self.battery
     }
 
+    /// Returns the charge level at the start of timestep `time`, before charging or
+    /// discharging during it. The charge of timestep `t` is therefore the difference between
+    /// the levels at `t + 1` and `t`. At the end of the day the level at the end of the
+    /// horizon is returned, if known.
     pub fn get_charge_level(&self, time: Time) -> Option<&i64> {
-        self.charge_level.get(time)
+        if time.to_timestep() == self.charge_level.len() {
+            self.final_charge_level.as_ref()
+        } else {
+            self.charge_level.get(time)
+        }
+    }
+
+    /// Returns the charge level at the start of every timestep.
+    pub fn get_charge_levels(&self) -> &Prognoses<i64> {
+        &self.charge_level
+    }
+
+    /// Returns the locally generated electricity stored in every timestep, if it is known.
+    pub fn get_green_charges(&self) -> Option<&Prognoses<i64>> {
+        self.green_charge.as_ref()
+    }
+
+    /// Returns the charge level at the start of every timestep, like `get_charge_level`,
+    /// followed by the level at the end of the horizon if it is known.
+    pub fn iter_charge_levels(&self) -> impl Iterator<Item = (Time, i64)> + '_ {
+        self.charge_level
+            .get_data()
+            .iter()
+            .copied()
+            .chain(self.final_charge_level)
+            .enumerate()
+            .map(|(timestep, level)| (Time::from_timestep(timestep as u32), level))
+    }
+
+    /// Returns how much more the battery could discharge during timestep `time` than planned:
+    /// its output rate minus the planned discharge, at most the charge left above the reserve
+    /// at the end of the timestep. The rate of the first timestep is scaled by
+    /// `first_timestep_fraction` like in the optimizer. `None` if the levels around `time`
+    /// aren't known.
+    pub fn get_discharge_headroom(&self, time: Time, first_timestep_fraction: f32) -> Option<i64> {
+        let (level, next_level) = self.levels_around(time)?;
+        let rate =
+            scale_first_timestep(self.battery.get_max_output(), time, first_timestep_fraction);
+        let discharge = (level - next_level).max(0);
+        let available = next_level - self.battery.get_min_charge();
+        Some((rate - discharge).min(available).max(0))
+    }
+
+    /// Returns how much more the battery could charge during timestep `time` than planned:
+    /// its charge rate minus the planned charge, at most the room left below the capacity at
+    /// the end of the timestep. See [`AssignedBattery::get_discharge_headroom`].
+    pub fn get_charge_headroom(&self, time: Time, first_timestep_fraction: f32) -> Option<i64> {
+        let (level, next_level) = self.levels_around(time)?;
+        let rate =
+            scale_first_timestep(self.battery.get_max_charge(), time, first_timestep_fraction);
+        let charge = (next_level - level).max(0);
+        let room = self.battery.get_capacity() - next_level;
+        Some((rate - charge).min(room).max(0))
+    }
+
+    /// Levels at the start and the end of timestep `time`.
+    fn levels_around(&self, time: Time) -> Option<(i64, i64)> {
+        let level = *self.get_charge_level(time)?;
+        let next_level = *self.get_charge_level(time.get_next_timestep())?;
+        Some((level, next_level))
+    }
+}
+
+/// Reasons a fixed battery plan can't be followed, see
+/// [`OptimizerContext::with_fixed_battery_plan`](crate::optimizer_context::OptimizerContext::with_fixed_battery_plan).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixedBatteryPlanError {
+    /// The context has no battery with this id.
+    UnknownBattery(u32),
+    /// The plan charges (positive) or discharges (negative) faster than the battery allows.
+    RateExceeded { time: Time, charge: i64 },
+    /// The plan takes the charge level at the start of `time` below the reserve or above the
+    /// capacity.
+    LevelOutOfRange { time: Time, level: i64 },
+    /// The plan charges a battery that may only store generated electricity beyond what is
+    /// generated.
+    ChargedFromGrid { time: Time, charge: i64 },
+}
+
+impl fmt::Display for FixedBatteryPlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixedBatteryPlanError::UnknownBattery(id) => write!(f, "No battery with id {}", id),
+            FixedBatteryPlanError::RateExceeded { time, charge } => write!(
+                f,
+                "Charge of {} at {:?} exceeds the battery's charge or discharge rate",
+                charge, time
+            ),
+            FixedBatteryPlanError::LevelOutOfRange { time, level } => write!(
+                f,
+                "Charge level {} at {:?} is outside of the battery's minimum charge and capacity",
+                level, time
+            ),
+            FixedBatteryPlanError::ChargedFromGrid { time, charge } => write!(
+                f,
+                "Charge of {} at {:?} exceeds the generated electricity, but the battery can't charge from the grid",
+                charge, time
+            ),
+        }
     }
 }
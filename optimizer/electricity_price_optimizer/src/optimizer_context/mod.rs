@@ -8,16 +8,41 @@ pub mod action;
 pub mod battery;
 pub mod prognoses;
 
-use std::rc::Rc;
+use std::{collections::HashMap, fmt, rc::Rc};
 
 use crate::optimizer_context::{
     action::{
-        constant::{AssignedConstantAction, ConstantAction},
+        constant::{AssignedConstantAction, ConstantAction, ExclusionError, PrecedenceError},
         variable::VariableAction,
     },
-    battery::Battery,
+    battery::{AssignedBattery, Battery, FixedBatteryPlanError},
     prognoses::Prognoses,
 };
+use crate::time::{SettlementPeriods, Time, TimestepConfig};
+
+/// Two assets of a context share an id, see [`OptimizerContext::new`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateIdError {
+    pub id: u32,
+    /// Kind of the asset that was added first, e.g. "battery".
+    pub first: &'static str,
+    /// Kind of the asset that was added second.
+    pub second: &'static str,
+}
+
+impl fmt::Display for DuplicateIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first == self.second {
+            write!(f, "Two {}s have the id {}", self.first, self.id)
+        } else {
+            write!(
+                f,
+                "The id {} is used by a {} and a {}",
+                self.id, self.first, self.second
+            )
+        }
+    }
+}
 
 /// Holds all data needed for optimization
 /// Holds all data needed for optimization.
@@ -47,6 +72,36 @@ pub struct OptimizerContext {
     /// The first timestep might not be a full timestep
     /// This parameter dictates what fraction of a full timestep the first timestep is
     first_timestep_fraction: f32,
+
+    /// Price paid for electricity exported to the grid at each timestep, if exporting is possible
+    feed_in_tariff: Option<Rc<Prognoses<i64>>>,
+    /// Maximum amount of electricity that can be exported per timestep, unlimited if `None`
+    max_export: Option<i64>,
+    /// Network fee per unit of electricity drawn from the grid at each timestep, on top of
+    /// the electricity price
+    network_fee: Option<Rc<Prognoses<i64>>>,
+    /// CO2 intensity of electricity drawn from the grid at each timestep, in mg per Wh, with
+    /// the price of a mg in µ€ it is weighed with
+    co2_intensity: Option<(Rc<Prognoses<i64>>, f64)>,
+    /// Maximum amount of electricity that can be drawn from the grid per timestep, unlimited if `None`
+    grid_limit: Option<i64>,
+    /// Price per unit of the highest average grid draw of any settlement period, e.g. a
+    /// capacity fee
+    peak_price: Option<i64>,
+    /// Periods over which the grid draw is averaged for the peak charge
+    settlement_periods: SettlementPeriods,
+    /// Batteries whose dispatch is fixed, with their trajectory. Their net power is part of
+    /// the generated electricity and beyond control consumption.
+    fixed_batteries: Vec<AssignedBattery>,
+    /// Pairs of constant action ids `(before, after)`, where `before` must end before `after`
+    /// starts.
+    precedences: Vec<(u32, u32)>,
+    /// Timesteps that must pass between the end of `before` and the start of `after` of a
+    /// precedence, none for those not listed.
+    min_gaps: HashMap<(u32, u32), u32>,
+    /// Groups of constant action ids of which no two may run at the same time, e.g. because
+    /// they share a circuit.
+    exclusion_groups: Vec<Vec<u32>>,
 }
 impl OptimizerContext {
     ///
@@ -56,12 +111,24 @@ impl OptimizerContext {
     /// * `generated_electricity` - Prognosis of generated electricity (e.g., solar).
     /// * `beyond_control_consumption` - Prognosis of uncontrollable energy consumption.
     /// * `batteries` - List of batteries in the system.
-    /// * `constant_actions` - Actions with fixed load and duration.
+    /// * `constant_actions` - Actions with fixed load and duration. Their default profiles, see
+    ///   [`ConstantAction::with_default_profile`], are removed from `beyond_control_consumption`
+    ///   (at most down to zero), since the actions run instead.
     /// * `variable_actions` - Actions with adjustable load or timing.
     ///
     /// # Returns
     ///
     /// A fully constructed [`OptimizerContext`] ready for use in optimization.
+    ///
+    /// # Errors
+    ///
+    /// Fails if two assets share an id. Schedules key all assets by id, so ids must be unique
+    /// across batteries, constant actions and variable actions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prognoses and default profiles don't have the same number of timesteps,
+    /// or if an action doesn't fit into them, see [`ConstantAction::validate_for`].
     pub fn new(
         electricity_price: Prognoses<i64>,
         generated_electricity: Prognoses<i64>,
@@ -70,18 +137,523 @@ impl OptimizerContext {
         constant_actions: Vec<Rc<ConstantAction>>,
         variable_actions: Vec<Rc<VariableAction>>,
         first_timestep_fraction: f32,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, DuplicateIdError> {
+        let steps = electricity_price.len();
+        assert!(
+            generated_electricity.len() == steps && beyond_control_consumption.len() == steps,
+            "Prognoses must have the same number of timesteps"
+        );
+        let timestep = electricity_price.get_timestep_config();
+        for action in &constant_actions {
+            if let Err(e) = action.validate_for(timestep) {
+                panic!("Invalid ConstantAction: {e}");
+            }
+        }
+        for action in &variable_actions {
+            if let Err(e) = action.validate_for(timestep) {
+                panic!("Invalid VariableAction: {e}");
+            }
+        }
+        let mut beyond_control_consumption = beyond_control_consumption;
+        for default_profile in constant_actions
+            .iter()
+            .filter_map(|a| a.get_default_profile())
+        {
+            assert!(
+                default_profile.len() == steps as usize,
+                "Default profiles must have one value per timestep"
+            );
+            for (t, default) in default_profile.iter().enumerate() {
+                let time = Time::from_timestep(t as u32);
+                let consumption = *beyond_control_consumption.get(time).unwrap();
+                beyond_control_consumption.set(time, (consumption - default).max(0));
+            }
+        }
+        let ids = (batteries.iter().map(|b| (b.get_id(), "battery")))
+            .chain(
+                constant_actions
+                    .iter()
+                    .map(|a| (a.get_id(), "constant action")),
+            )
+            .chain(
+                variable_actions
+                    .iter()
+                    .map(|a| (a.get_id(), "variable action")),
+            );
+        let mut kinds = HashMap::new();
+        for (id, kind) in ids {
+            if let Some(first) = kinds.insert(id, kind) {
+                return Err(DuplicateIdError {
+                    id,
+                    first,
+                    second: kind,
+                });
+            }
+        }
+        Ok(Self {
             electricity_price: Rc::new(electricity_price),
             generated_electricity: Rc::new(generated_electricity),
             beyond_control_consumption,
-            batteries: batteries,
+            batteries,
             constant_actions,
             variable_actions,
             first_timestep_fraction,
+            feed_in_tariff: None,
+            max_export: None,
+            network_fee: None,
+            co2_intensity: None,
+            grid_limit: None,
+            peak_price: None,
+            settlement_periods: SettlementPeriods::default(),
+            fixed_batteries: Vec::new(),
+            precedences: Vec::new(),
+            min_gaps: HashMap::new(),
+            exclusion_groups: Vec::new(),
+        })
+    }
+
+    /// Allows exporting electricity to the grid, paid with the given feed-in tariff.
+    ///
+    /// # Arguments
+    ///
+    /// * `feed_in_tariff` - Prognosis of the price paid for exported electricity.
+    /// * `max_export` - Maximum export per timestep. Generated electricity and battery
+    ///   discharge are exported, so the export never exceeds their sum.
+    pub fn with_feed_in_tariff(
+        mut self,
+        feed_in_tariff: Prognoses<i64>,
+        max_export: Option<i64>,
+    ) -> Self {
+        assert_eq!(
+            feed_in_tariff.len(),
+            self.electricity_price.len(),
+            "Feed-in tariff must have one value per timestep"
+        );
+        self.feed_in_tariff = Some(Rc::new(feed_in_tariff));
+        self.max_export = max_export;
+        self
+    }
+
+    /// Charges `network_fee` for every unit of electricity drawn from the grid, on top of the
+    /// electricity price. Generated electricity, also when stored in a battery, and the export
+    /// don't pay it.
+    pub fn with_network_fee(mut self, network_fee: Prognoses<i64>) -> Self {
+        assert_eq!(
+            network_fee.len(),
+            self.electricity_price.len(),
+            "Network fee must have one value per timestep"
+        );
+        self.network_fee = Some(Rc::new(network_fee));
+        self
+    }
+
+    /// Weighs the CO2 emitted by electricity drawn from the grid into the cost, so every unit
+    /// drawn costs `co2_intensity` (mg per Wh) times `co2_price` (µ€ per mg) on top of the
+    /// electricity price. With a `co2_price` of zero the intensity only serves to report the
+    /// emissions of the schedule.
+    pub fn with_co2_intensity(mut self, co2_intensity: Prognoses<i64>, co2_price: f64) -> Self {
+        assert_eq!(
+            co2_intensity.len(),
+            self.electricity_price.len(),
+            "CO2 intensity must have one value per timestep"
+        );
+        assert!(
+            co2_price.is_finite() && co2_price >= 0.0,
+            "CO2 price must be a finite number that isn't negative"
+        );
+        self.co2_intensity = Some((Rc::new(co2_intensity), co2_price));
+        self
+    }
+
+    /// Limits the electricity drawn from the grid per timestep, e.g. to respect the fuses of
+    /// the grid connection.
+    pub fn with_grid_limit(mut self, grid_limit: i64) -> Self {
+        self.grid_limit = Some(grid_limit);
+        self
+    }
+
+    /// Charges `peak_price` for every unit of electricity drawn from the grid per timestep,
+    /// on average over the settlement period with the highest draw, on top of the energy cost.
+    pub fn with_peak_price(mut self, peak_price: i64) -> Self {
+        self.peak_price = Some(peak_price);
+        self
+    }
+
+    /// Measures the grid draw on the given settlement periods instead of single timesteps,
+    /// see [`OptimizerContext::with_peak_price`].
+    pub fn with_settlement_periods(mut self, settlement_periods: SettlementPeriods) -> Self {
+        self.settlement_periods = settlement_periods;
+        self
+    }
+
+    /// Fixes the dispatch of the battery with the given id instead of optimizing it, e.g. when
+    /// its own controller decides when to charge.
+    ///
+    /// `charge` is the energy stored (positive) or discharged (negative) per timestep.
+    /// The battery is removed from the optimized batteries: its discharge is added to the
+    /// generated electricity and its charge, including the charging losses, to the beyond
+    /// control consumption. Its final charge target isn't enforced.
+    ///
+    /// # Errors
+    /// Fails if there is no such battery, or if the plan exceeds its charge or discharge rate,
+    /// takes it below its reserve or above its capacity, or charges a battery that can't
+    /// charge from the grid with more than is generated.
+    pub fn with_fixed_battery_plan(
+        mut self,
+        id: u32,
+        charge: Prognoses<i64>,
+    ) -> Result<Self, FixedBatteryPlanError> {
+        let index = self
+            .batteries
+            .iter()
+            .position(|battery| battery.get_id() == id)
+            .ok_or(FixedBatteryPlanError::UnknownBattery(id))?;
+        let battery = self.batteries[index].clone();
+        let timestep = self.get_timestep_config();
+
+        let mut levels = Vec::with_capacity(timestep.get_steps_per_day() as usize + 1);
+        let mut level = battery.get_initial_level();
+        levels.push(level);
+        for t in 0..timestep.get_steps_per_day() {
+            let time = Time::from_timestep(t);
+            let charge = *charge.get(time).unwrap_or(&0);
+            // The first timestep might not be a full timestep, so only a fraction fits in.
+            let fraction = if t == 0 {
+                self.first_timestep_fraction
+            } else {
+                1.0
+            };
+            let max_rate = if charge > 0 {
+                battery.get_max_charge()
+            } else {
+                battery.get_max_output()
+            };
+            if charge.abs() > (max_rate as f32 * fraction).round() as i64 {
+                return Err(FixedBatteryPlanError::RateExceeded { time, charge });
+            }
+            if !battery.is_grid_charging_allowed()
+                && charge > *self.generated_electricity.get(time).unwrap_or(&0)
+            {
+                return Err(FixedBatteryPlanError::ChargedFromGrid { time, charge });
+            }
+            level += charge;
+            if !(battery.get_min_charge()..=battery.get_capacity()).contains(&level) {
+                return Err(FixedBatteryPlanError::LevelOutOfRange {
+                    time: time.get_next_timestep(),
+                    level,
+                });
+            }
+            levels.push(level);
+        }
+
+        let efficiency = battery.get_efficiency() as f64;
+        *Rc::make_mut(&mut self.generated_electricity) +=
+            Prognoses::from_closure_for(timestep, |t| (-*charge.get(t).unwrap_or(&0)).max(0));
+        self.beyond_control_consumption += Prognoses::from_closure_for(timestep, |t| {
+            let charge = (*charge.get(t).unwrap_or(&0)).max(0);
+            (charge as f64 / efficiency).round() as i64
+        });
+        let assigned = AssignedBattery::new(
+            battery.clone(),
+            Prognoses::from_closure_for(timestep, |t| levels[t.to_timestep() as usize]),
+        )
+        .with_final_charge_level(levels[timestep.get_steps_per_day() as usize]);
+        let assigned = if battery.is_grid_charging_allowed() {
+            assigned
+        } else {
+            assigned.with_green_charge(Prognoses::from_closure_for(timestep, |t| {
+                (*charge.get(t).unwrap_or(&0)).max(0)
+            }))
+        };
+        self.batteries.remove(index);
+        self.fixed_batteries.push(assigned);
+        Ok(self)
+    }
+
+    /// Requires constant action `before` to end before constant action `after` starts.
+    ///
+    /// # Errors
+    /// Fails if either action doesn't exist, if the precedences form a cycle, or if the windows
+    /// of the actions leave no room to keep all precedences.
+    pub fn with_precedence(mut self, before: u32, after: u32) -> Result<Self, PrecedenceError> {
+        for id in [before, after] {
+            if self.find_constant_action(id).is_none() {
+                return Err(PrecedenceError::UnknownAction(id));
+            }
+        }
+        self.precedences.push((before, after));
+        self.start_ranges()?;
+        self.staggered_starts()
+            .map_err(PrecedenceError::Infeasible)?;
+        Ok(self)
+    }
+
+    /// Requires constant action `before` to end at least `gap` before constant action `after`
+    /// starts, e.g. to let a pump rest between two runs.
+    ///
+    /// # Errors
+    /// Fails like [`OptimizerContext::with_precedence`].
+    pub fn with_min_gap(
+        mut self,
+        before: u32,
+        after: u32,
+        gap: Time,
+    ) -> Result<Self, PrecedenceError> {
+        self.min_gaps.insert((before, after), gap.to_timestep());
+        self.with_precedence(before, after)
+    }
+
+    /// Returns how long after the end of `before` the action `after` can start at the
+    /// earliest, zero for precedences without a gap.
+    pub fn get_min_gap(&self, before: u32, after: u32) -> Time {
+        Time::from_timestep(self.min_gaps.get(&(before, after)).copied().unwrap_or(0))
+    }
+
+    /// Forbids any two of the constant actions `ids` to run at the same time.
+    ///
+    /// # Errors
+    /// Fails if an action doesn't exist, or if the windows of the actions leave no room to
+    /// run them one after another while keeping the precedences.
+    pub fn with_exclusion_group(mut self, ids: Vec<u32>) -> Result<Self, ExclusionError> {
+        if let Some(&id) = ids
+            .iter()
+            .find(|&&id| self.find_constant_action(id).is_none())
+        {
+            return Err(ExclusionError::UnknownAction(id));
+        }
+        self.exclusion_groups.push(ids);
+        self.staggered_starts()
+            .map_err(ExclusionError::Infeasible)?;
+        Ok(self)
+    }
+
+    /// Returns the groups of constant action ids of which no two may run at the same time.
+    pub fn get_exclusion_groups(&self) -> &Vec<Vec<u32>> {
+        &self.exclusion_groups
+    }
+
+    /// Whether `action` starting at `start` would run at the same time as an action that
+    /// shares an exclusion group with it. `start_of` gives the start of the other actions,
+    /// `None` for those that aren't placed yet.
+    pub fn overlaps_excluded(
+        &self,
+        action: &ConstantAction,
+        start: Time,
+        start_of: impl Fn(u32) -> Option<Time>,
+    ) -> bool {
+        let id = action.get_id();
+        let end = start + action.duration;
+        self.exclusion_groups
+            .iter()
+            .filter(|group| group.contains(&id))
+            .flatten()
+            .filter(|&&other| other != id)
+            .any(|&other| {
+                start_of(other).is_some_and(|other_start| {
+                    let other_end = Time::from_timestep(
+                        other_start.to_timestep() + self.duration_of(other) as u32,
+                    );
+                    other_start < end && start < other_end
+                })
+            })
+    }
+
+    /// Returns a start of every constant action that keeps the windows, the precedences and
+    /// the exclusion groups, keyed by action id. The actions start as early as they can.
+    pub fn get_staggered_starts(&self) -> HashMap<u32, Time> {
+        self.staggered_starts()
+            .expect("Exclusion groups are checked when added")
+    }
+
+    /// Returns the precedences as pairs `(before, after)` of constant action ids.
+    pub fn get_precedences(&self) -> &Vec<(u32, u32)> {
+        &self.precedences
+    }
+
+    /// Returns the constant actions so that every action comes after those that must end
+    /// before it starts. Without precedences, this is the order they were added in.
+    pub fn get_constant_actions_in_precedence_order(&self) -> Vec<Rc<ConstantAction>> {
+        self.precedence_order()
+            .expect("Precedences are checked for cycles when added")
+    }
+
+    /// Returns the latest start of every constant action that leaves room for the actions
+    /// that must start after it, keyed by action id.
+    pub fn get_latest_starts(&self) -> HashMap<u32, Time> {
+        self.start_ranges()
+            .expect("Precedences are checked when added")
+            .into_iter()
+            .map(|(id, (_, latest))| (id, latest))
+            .collect()
+    }
+
+    /// Returns the earliest and latest start of `action` that keep its window and the
+    /// precedences, with the other constant actions starting at `start_of`.
+    /// `None` if no start time is left.
+    pub fn get_start_bounds(
+        &self,
+        action: &ConstantAction,
+        start_of: impl Fn(u32) -> Time,
+    ) -> Option<(Time, Time)> {
+        let id = action.get_id();
+        let duration = action.duration.to_timestep() as i64;
+        let mut earliest = action.get_start_from().to_timestep() as i64;
+        let mut latest = action.get_end_before().to_timestep() as i64 - duration;
+        for &(before, after) in &self.precedences {
+            let gap = self.get_min_gap(before, after).to_timestep() as i64;
+            if after == id {
+                let before_duration = self.duration_of(before);
+                earliest =
+                    earliest.max(start_of(before).to_timestep() as i64 + before_duration + gap);
+            } else if before == id {
+                latest = latest.min(start_of(after).to_timestep() as i64 - duration - gap);
+            }
+        }
+        (earliest <= latest).then(|| {
+            (
+                Time::from_timestep(earliest as u32),
+                Time::from_timestep(latest as u32),
+            )
+        })
+    }
+
+    /// Places the constant actions one after another, each as early as its window, its
+    /// predecessors and the actions of its exclusion groups placed before it allow. Of the
+    /// actions whose predecessors are placed, the one that has to start first goes next.
+    /// Returns the id of an action that doesn't fit if the placement fails.
+    fn staggered_starts(&self) -> Result<HashMap<u32, Time>, u32> {
+        let latest_starts = self.get_latest_starts();
+        let mut remaining = self.get_constant_actions_in_precedence_order();
+        let mut starts: HashMap<u32, Time> = HashMap::new();
+        while !remaining.is_empty() {
+            let next = remaining
+                .iter()
+                .enumerate()
+                .filter(|(_, action)| {
+                    self.precedences.iter().all(|&(before, after)| {
+                        after != action.get_id() || starts.contains_key(&before)
+                    })
+                })
+                .min_by_key(|(_, action)| latest_starts[&action.get_id()])
+                .map(|(index, _)| index)
+                .expect("Precedences are checked for cycles when added");
+            let action = remaining.remove(next);
+            let id = action.get_id();
+            let mut start = self
+                .precedences
+                .iter()
+                .filter(|&&(_, after)| after == id)
+                .map(|&(before, _)| {
+                    starts[&before].to_timestep()
+                        + self.duration_of(before) as u32
+                        + self.get_min_gap(before, id).to_timestep()
+                })
+                .fold(action.get_start_from().to_timestep(), u32::max);
+            // Move it on until it runs apart from the placed actions of its groups
+            let latest = latest_starts[&id].to_timestep();
+            while start <= latest
+                && self.overlaps_excluded(&action, Time::from_timestep(start), |other| {
+                    starts.get(&other).copied()
+                })
+            {
+                start += 1;
+            }
+            if start > latest {
+                return Err(id);
+            }
+            starts.insert(id, Time::from_timestep(start));
+        }
+        Ok(starts)
+    }
+
+    fn find_constant_action(&self, id: u32) -> Option<&Rc<ConstantAction>> {
+        self.constant_actions
+            .iter()
+            .find(|action| action.get_id() == id)
+    }
+
+    fn duration_of(&self, id: u32) -> i64 {
+        self.find_constant_action(id)
+            .map_or(0, |action| action.duration.to_timestep() as i64)
+    }
+
+    /// Orders the constant actions topologically. Among the actions that are ready, the one
+    /// added first is taken, so the order of the context is kept where possible.
+    fn precedence_order(&self) -> Result<Vec<Rc<ConstantAction>>, PrecedenceError> {
+        let mut remaining = self.constant_actions.clone();
+        let mut order = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let ready = remaining
+                .iter()
+                .position(|action| {
+                    self.precedences.iter().all(|&(before, after)| {
+                        after != action.get_id()
+                            || remaining.iter().all(|other| other.get_id() != before)
+                    })
+                })
+                .ok_or(PrecedenceError::Cycle)?;
+            order.push(remaining.remove(ready));
+        }
+        Ok(order)
+    }
+
+    /// Returns the earliest and latest start of every constant action that keep all
+    /// precedences, keyed by action id.
+    fn start_ranges(&self) -> Result<HashMap<u32, (Time, Time)>, PrecedenceError> {
+        let order = self.precedence_order()?;
+        let mut earliest: HashMap<u32, i64> = HashMap::new();
+        for action in &order {
+            let start = self
+                .precedences
+                .iter()
+                .filter(|&&(_, after)| after == action.get_id())
+                .map(|&(before, after)| {
+                    earliest[&before]
+                        + self.duration_of(before)
+                        + self.get_min_gap(before, after).to_timestep() as i64
+                })
+                .fold(action.get_start_from().to_timestep() as i64, i64::max);
+            earliest.insert(action.get_id(), start);
+        }
+        let mut latest: HashMap<u32, i64> = HashMap::new();
+        for action in order.iter().rev() {
+            let duration = action.duration.to_timestep() as i64;
+            let start = self
+                .precedences
+                .iter()
+                .filter(|&&(before, _)| before == action.get_id())
+                .map(|&(before, after)| {
+                    latest[&after] - duration - self.get_min_gap(before, after).to_timestep() as i64
+                })
+                .fold(
+                    action.get_end_before().to_timestep() as i64 - duration,
+                    i64::min,
+                );
+            latest.insert(action.get_id(), start);
         }
+        order
+            .iter()
+            .map(|action| {
+                let id = action.get_id();
+                let (earliest, latest) = (earliest[&id], latest[&id]);
+                if earliest > latest {
+                    return Err(PrecedenceError::Infeasible(id));
+                }
+                Ok((
+                    id,
+                    (
+                        Time::from_timestep(earliest as u32),
+                        Time::from_timestep(latest as u32),
+                    ),
+                ))
+            })
+            .collect()
     }
 
+    /// Returns the batteries with a fixed dispatch and their trajectories.
+    pub fn get_fixed_batteries(&self) -> &Vec<AssignedBattery> {
+        &self.fixed_batteries
+    }
     /// Returns a reference to the list of constant actions.
     pub fn get_constant_actions(&self) -> &Vec<Rc<ConstantAction>> {
         &self.constant_actions
@@ -95,12 +667,44 @@ impl OptimizerContext {
         &self.batteries
     }
 
+    /// Returns this context with every contiguous variable action replaced by its block, a
+    /// constant action with the same id, see [`VariableAction::get_block`]. The simulated
+    /// annealing searches the start of the blocks like that of the other constant actions.
+    ///
+    /// # Panics
+    /// Panics if a contiguous action has no block.
+    pub fn with_blocks_as_constant_actions(&self) -> OptimizerContext {
+        let (contiguous, variable_actions): (Vec<_>, Vec<_>) = self
+            .variable_actions
+            .iter()
+            .cloned()
+            .partition(|action| action.is_contiguous());
+        let blocks = contiguous.iter().map(|action| {
+            let block = action
+                .get_block()
+                .unwrap_or_else(|e| panic!("Invalid contiguous action: {e}"));
+            Rc::new(block)
+        });
+        OptimizerContext {
+            constant_actions: self
+                .constant_actions
+                .iter()
+                .cloned()
+                .chain(blocks)
+                .collect(),
+            variable_actions,
+            ..self.clone()
+        }
+    }
+
     /// Adds the effect of a constant action to the uncontrollable consumption profile.
     ///
     /// This function updates [`beyond_control_consumption`] to reflect additional
     /// loads from scheduled constant actions.
     pub fn add_constant_action_to_consumption(&mut self, action: &AssignedConstantAction) {
-        self.beyond_control_consumption.add_constant_action(action);
+        let fraction = self.first_timestep_fraction;
+        self.beyond_control_consumption
+            .add_constant_action(action, fraction);
     }
 
     /// Returns a reference to the electricity price prognoses.
@@ -118,8 +722,186 @@ impl OptimizerContext {
         &self.beyond_control_consumption
     }
 
+    /// Returns the length of the timesteps, set by the prognoses.
+    pub fn get_timestep_config(&self) -> TimestepConfig {
+        self.electricity_price.get_timestep_config()
+    }
+
+    /// Returns the number of timesteps in the day.
+    pub fn get_steps(&self) -> u32 {
+        self.electricity_price.len()
+    }
+
+    /// Returns the end of the last timestep of the day.
+    pub fn get_day_end(&self) -> Time {
+        Time::from_timestep(self.get_steps())
+    }
+
     /// Returns the fraction of the first timestep.
     pub fn get_first_timestep_fraction(&self) -> f32 {
         self.first_timestep_fraction
     }
+
+    /// Returns the feed-in tariff prognoses, if exporting is possible.
+    pub fn get_feed_in_tariff(&self) -> Option<&Rc<Prognoses<i64>>> {
+        self.feed_in_tariff.as_ref()
+    }
+
+    /// Returns the maximum export per timestep, if limited.
+    pub fn get_max_export(&self) -> Option<i64> {
+        self.max_export
+    }
+
+    /// Returns the network fee prognoses, if grid imports pay one.
+    pub fn get_network_fee(&self) -> Option<&Rc<Prognoses<i64>>> {
+        self.network_fee.as_ref()
+    }
+
+    /// Returns the CO2 intensity prognoses in mg per Wh, if set.
+    pub fn get_co2_intensity(&self) -> Option<&Rc<Prognoses<i64>>> {
+        self.co2_intensity.as_ref().map(|(intensity, _)| intensity)
+    }
+
+    /// Returns the price of the CO2 emitted by a unit drawn from the grid at each timestep, in
+    /// µ€/Wh, if a CO2 intensity is set.
+    pub fn get_co2_cost(&self) -> Option<Prognoses<i64>> {
+        self.co2_intensity.as_ref().map(|(intensity, co2_price)| {
+            Prognoses::from_closure_for(intensity.get_timestep_config(), |time| {
+                (*intensity.get(time).unwrap_or(&0) as f64 * co2_price).round() as i64
+            })
+        })
+    }
+
+    /// Returns the maximum grid import per timestep, if limited.
+    pub fn get_grid_limit(&self) -> Option<i64> {
+        self.grid_limit
+    }
+
+    /// Returns the price per unit of the peak grid draw, if there is a peak charge.
+    pub fn get_peak_price(&self) -> Option<i64> {
+        self.peak_price
+    }
+
+    /// Returns the periods the grid draw is measured on, every timestep by default.
+    pub fn get_settlement_periods(&self) -> SettlementPeriods {
+        self.settlement_periods
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(
+        batteries: &[u32],
+        constant_actions: &[u32],
+        variable_actions: &[u32],
+    ) -> Result<OptimizerContext, DuplicateIdError> {
+        OptimizerContext::new(
+            Prognoses::from_closure(|_| 10),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            batteries
+                .iter()
+                .map(|id| Rc::new(Battery::new(1000, 0, 100, 100, 1.0, *id)))
+                .collect(),
+            constant_actions
+                .iter()
+                .map(|id| {
+                    Rc::new(ConstantAction::new(
+                        Time::at_default_resolution(0, 0),
+                        Time::at_default_resolution(2, 0),
+                        Time::at_default_resolution(1, 0),
+                        50,
+                        *id,
+                    ))
+                })
+                .collect(),
+            variable_actions
+                .iter()
+                .map(|id| {
+                    Rc::new(VariableAction::new(
+                        Time::at_default_resolution(0, 0),
+                        Time::at_default_resolution(2, 0),
+                        100,
+                        50,
+                        *id,
+                    ))
+                })
+                .collect(),
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_new_rejects_duplicate_ids() {
+        assert!(context(&[1], &[2, 3], &[4]).is_ok());
+        let duplicate = |id, first, second| DuplicateIdError { id, first, second };
+        assert_eq!(
+            context(&[1, 1], &[], &[]).err(),
+            Some(duplicate(1, "battery", "battery"))
+        );
+        assert_eq!(
+            context(&[], &[2, 2], &[]).err(),
+            Some(duplicate(2, "constant action", "constant action"))
+        );
+        assert_eq!(
+            context(&[], &[3], &[3]).err(),
+            Some(duplicate(3, "constant action", "variable action"))
+        );
+        let error = context(&[4], &[], &[4]).err().unwrap();
+        assert_eq!(error, duplicate(4, "battery", "variable action"));
+        assert_eq!(
+            error.to_string(),
+            "The id 4 is used by a battery and a variable action"
+        );
+        assert_eq!(
+            context(&[], &[], &[5, 5]).err().unwrap().to_string(),
+            "Two variable actions have the id 5"
+        );
+    }
+
+    #[test]
+    fn test_new_removes_default_profiles_from_consumption() {
+        use crate::optimizer_context::action::ActionError;
+
+        let steps = TimestepConfig::default().get_steps_per_day() as usize;
+        let action = ConstantAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(2, 0),
+            Time::at_default_resolution(1, 0),
+            50,
+            1,
+        )
+        .with_default_profile((0..steps).map(|t| if t < 2 { 30 } else { 0 }).collect())
+        .unwrap();
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|_| 10),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|t| if t.to_timestep() == 0 { 20 } else { 40 }),
+            vec![],
+            vec![Rc::new(action)],
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        let consumption = context.get_beyond_control_consumption().get_data();
+        assert_eq!(&consumption[..3], &[0, 10, 40]);
+
+        let negative = ConstantAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(2, 0),
+            Time::at_default_resolution(1, 0),
+            50,
+            1,
+        )
+        .with_default_profile(vec![-1; steps]);
+        assert!(matches!(
+            negative,
+            Err(ActionError::NegativeConsumption {
+                field: "default_profile",
+                ..
+            })
+        ));
+    }
 }
@@ -4,23 +4,50 @@ use std::{
 };
 
 use crate::{
+    optimizer::scale_first_timestep,
     optimizer_context::action::constant::AssignedConstantAction,
-    time::{STEPS_PER_DAY, Time},
+    time::{STEPS_PER_DAY, Time, TimestepConfig},
 };
 
 // const MINUTES_PER_DAY: usize = 24 * 60;
 
 /// Holds prognoses data for each timestep in a day.
 /// For example, electricity prices, generated electricity, or beyond control consumption.
-#[derive(Clone, Debug)]
+/// The number of values sets the length of the timesteps, see [`TimestepConfig`].
+/// Serialized as a plain list with one value per timestep.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "Vec<T>")
+)]
 pub struct Prognoses<T: Clone> {
     /// Data for each timestep in a day.
-    data: [T; STEPS_PER_DAY as usize],
+    data: Vec<T>,
 }
 
 impl<T: Clone> Prognoses<T> {
+    /// Creates prognoses at the default resolution.
     pub fn new(data: [T; STEPS_PER_DAY as usize]) -> Self {
-        Self { data }
+        Self {
+            data: data.to_vec(),
+        }
+    }
+
+    /// Returns the number of timesteps in the day.
+    pub fn len(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    /// Always false, as a day has at least one timestep.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the length of the timesteps of these prognoses.
+    pub fn get_timestep_config(&self) -> TimestepConfig {
+        TimestepConfig::from_steps_per_day(self.len())
+            .expect("Prognoses always divide the day into whole minutes")
     }
 
     pub fn get(&self, time: Time) -> Option<&T> {
@@ -35,13 +62,13 @@ impl<T: Clone> Prognoses<T> {
     /// # Notes
     /// If the time is out of bounds, the function does nothing.
     pub fn set(&mut self, time: Time, value: T) {
-        if time.to_timestep() < STEPS_PER_DAY {
-            self.data[time.to_timestep() as usize] = value;
+        if let Some(slot) = self.data.get_mut(time.to_timestep() as usize) {
+            *slot = value;
         }
     }
 
-    /// Returns a reference to the internal data array.
-    pub fn get_data(&self) -> &[T; STEPS_PER_DAY as usize] {
+    /// Returns the values of all timesteps.
+    pub fn get_data(&self) -> &[T] {
         &self.data
     }
 }
@@ -52,52 +79,104 @@ impl<T: Debug + Clone> Prognoses<T> {
     /// # Arguments
     /// * `f` - A closure that takes a timestep index and returns a value of type T.
     /// # Returns
-    /// * A Prognoses instance with data generated by the closure.
+    /// * A Prognoses instance with data generated by the closure, at the default resolution.
     pub fn from_closure<F: Fn(Time) -> T>(f: F) -> Self {
-        let data = std::array::from_fn(|t| f(Time::from_timestep(t as u32)));
+        Self::from_closure_for(TimestepConfig::default(), f)
+    }
+
+    /// Same as [`Prognoses::from_closure`], with one value per timestep of `timestep`.
+    pub fn from_closure_for<F: Fn(Time) -> T>(timestep: TimestepConfig, f: F) -> Self {
+        let data = (0..timestep.get_steps_per_day())
+            .map(|t| f(Time::from_timestep(t)))
+            .collect();
         Self { data }
     }
 }
 impl<T: Debug + Clone + Default> Prognoses<T> {
     // same but can return Result<T, E>
     pub fn from_closure_result<F: Fn(Time) -> Result<T, E>, E>(f: F) -> Result<Self, E> {
-        let mut data: [T; STEPS_PER_DAY as usize] = std::array::from_fn(|_| T::default());
-        for t in 0..STEPS_PER_DAY as usize {
-            data[t] = f(Time::from_timestep(t as u32))?;
-        }
+        Self::from_closure_result_for(TimestepConfig::default(), f)
+    }
+
+    /// Same as [`Prognoses::from_closure_result`], with one value per timestep of `timestep`.
+    pub fn from_closure_result_for<F: Fn(Time) -> Result<T, E>, E>(
+        timestep: TimestepConfig,
+        f: F,
+    ) -> Result<Self, E> {
+        let data = (0..timestep.get_steps_per_day())
+            .map(|t| f(Time::from_timestep(t)))
+            .collect::<Result<_, E>>()?;
         Ok(Self { data })
     }
 }
 
 impl<T: From<i64> + Add<T, Output = T> + Clone> Prognoses<T> {
     /// Adds the consumption of a constant action to the prognoses data.
-    /// Used to update consumption prognoses when scheduling constant actions.
+    /// Used to update consumption prognoses when scheduling constant actions. Overlapping
+    /// actions add up, like in the flow, and the first timestep is scaled the same way.
     ///
     /// # Arguments
     /// * `action` - The assigned constant action to add.
-    pub fn add_constant_action(&mut self, action: &AssignedConstantAction) {
-        let start = action.get_start_time().to_timestep() as usize;
-        let end = action.get_end_time().to_timestep() as usize;
-        let consumption = action.get_action().get_consumption();
-
-        for t in start..end {
+    /// * `first_timestep_fraction` - Part of the first timestep that is left.
+    pub fn add_constant_action(
+        &mut self,
+        action: &AssignedConstantAction,
+        first_timestep_fraction: f32,
+    ) {
+        for (time, consumption) in action.iter_consumption() {
+            let t = time.to_timestep() as usize;
+            let consumption = scale_first_timestep(consumption, time, first_timestep_fraction);
             self.data[t] = self.data[t].clone() + T::from(consumption);
         }
     }
 }
 
+impl<T: Clone> From<Prognoses<T>> for Vec<T> {
+    fn from(prognoses: Prognoses<T>) -> Self {
+        prognoses.data
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Clone + serde::Serialize> serde::Serialize for Prognoses<T> {
+    /// Serializes the values in place, without copying them into a Vec first.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.data)
+    }
+}
+
+impl<T: Clone> TryFrom<Vec<T>> for Prognoses<T> {
+    type Error = String;
+
+    /// Fails unless the data divides the day into timesteps of whole minutes.
+    fn try_from(data: Vec<T>) -> Result<Self, Self::Error> {
+        let len = data.len();
+        if TimestepConfig::from_steps_per_day(len as u32).is_none() {
+            return Err(format!(
+                "Prognoses need one value per timestep, dividing the day into whole minutes, \
+                 e.g. {STEPS_PER_DAY} values, got {len}"
+            ));
+        }
+        Ok(Self { data })
+    }
+}
+
 impl<T> Add for Prognoses<T>
 where
     T: Add<T, Output = T> + Clone,
 {
     type Output = Prognoses<T>;
 
+    /// # Panics
+    /// Panics if the prognoses have different lengths.
     fn add(self, other: Prognoses<T>) -> Prognoses<T> {
-        let mut result_data: [T; STEPS_PER_DAY as usize] =
-            std::array::from_fn(|_| self.data[0].clone());
-        for t in 0..STEPS_PER_DAY as usize {
-            result_data[t] = self.data[t].clone() + other.data[t].clone();
-        }
+        assert_eq!(self.len(), other.len(), "Prognoses have different lengths");
+        let result_data = self
+            .data
+            .into_iter()
+            .zip(other.data)
+            .map(|(a, b)| a + b)
+            .collect();
         Prognoses { data: result_data }
     }
 }
@@ -107,8 +186,9 @@ where
     T: Add<T, Output = T> + Clone,
 {
     fn add_assign(&mut self, other: Prognoses<T>) {
-        for t in 0..STEPS_PER_DAY as usize {
-            self.data[t] = self.data[t].clone() + other.data[t].clone();
+        assert_eq!(self.len(), other.len(), "Prognoses have different lengths");
+        for (value, other) in self.data.iter_mut().zip(other.data) {
+            *value = value.clone() + other;
         }
     }
 }
@@ -1,17 +1,525 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    rc::Rc,
+};
 
-use crate::optimizer_context::{
-    action::{constant::AssignedConstantAction, variable::AssignedVariableAction},
-    battery::AssignedBattery,
-    prognoses::Prognoses,
+use crate::{
+    optimizer::scale_first_timestep,
+    optimizer_context::{
+        OptimizerContext,
+        action::{
+            ActionError,
+            constant::{AssignedConstantAction, ConstantAction},
+            variable::{AssignedVariableAction, VariableAction},
+        },
+        battery::AssignedBattery,
+        prognoses::Prognoses,
+    },
+    time::{SettlementPeriods, Time, TimeIterator, TimestepConfig},
 };
 
+/// A way a schedule breaks the constraints of its context, found by [`Schedule::verify`].
+///
+/// The optimizers only produce schedules without violations, so any violation is a bug.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// A constant action of the context isn't in the schedule.
+    MissingConstantAction { id: u32 },
+    /// A constant action starts before its window or ends after it.
+    ConstantActionOutsideWindow { id: u32, start: Time, end: Time },
+    /// Constant action `before` ends after constant action `after` starts, or less than their
+    /// minimum gap before, although it must end before.
+    PrecedenceViolated { before: u32, after: u32 },
+    /// Two constant actions of an exclusion group run at the same time.
+    ExclusionViolated { first: u32, second: u32 },
+    /// A variable action of the context isn't in the schedule.
+    MissingVariableAction { id: u32 },
+    /// A variable action is assigned a different window than declared.
+    VariableActionOutsideWindow { id: u32, start: Time, end: Time },
+    /// A variable action consumes less than zero or more than its maximum in a timestep.
+    VariableActionOverMax {
+        id: u32,
+        time: Time,
+        consumption: i64,
+        max: i64,
+    },
+    /// A variable action consumes something, but less than its minimum, in a timestep.
+    VariableActionBelowMin {
+        id: u32,
+        time: Time,
+        consumption: i64,
+        min: i64,
+    },
+    /// A battery of the context isn't in the schedule.
+    MissingBattery { id: u32 },
+    /// A battery charge level is below the minimum charge or above the capacity. The level at
+    /// the end of the horizon is reported at the end of the day.
+    BatteryLevelOutOfRange { id: u32, time: Time, level: i64 },
+    /// More electricity is drawn from the grid than the grid limit allows.
+    GridLimitExceeded {
+        time: Time,
+        consumption: i64,
+        limit: i64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingConstantAction { id } => {
+                write!(f, "Constant action {} is missing", id)
+            }
+            Violation::ConstantActionOutsideWindow { id, start, end } => write!(
+                f,
+                "Constant action {} runs from {:?} to {:?}, outside of its window",
+                id, start, end
+            ),
+            Violation::PrecedenceViolated { before, after } => write!(
+                f,
+                "Constant action {} doesn't end early enough before constant action {} starts",
+                before, after
+            ),
+            Violation::ExclusionViolated { first, second } => write!(
+                f,
+                "Constant actions {} and {} run at the same time, but share an exclusion group",
+                first, second
+            ),
+            Violation::MissingVariableAction { id } => {
+                write!(f, "Variable action {} is missing", id)
+            }
+            Violation::VariableActionOutsideWindow { id, start, end } => write!(
+                f,
+                "Variable action {} is assigned the window {:?} to {:?}, not its own",
+                id, start, end
+            ),
+            Violation::VariableActionOverMax {
+                id,
+                time,
+                consumption,
+                max,
+            } => write!(
+                f,
+                "Variable action {} consumes {} at {:?}, allowed are 0 to {}",
+                id, consumption, time, max
+            ),
+            Violation::VariableActionBelowMin {
+                id,
+                time,
+                consumption,
+                min,
+            } => write!(
+                f,
+                "Variable action {} consumes {} at {:?}, less than its minimum {}",
+                id, consumption, time, min
+            ),
+            Violation::MissingBattery { id } => write!(f, "Battery {} is missing", id),
+            Violation::BatteryLevelOutOfRange { id, time, level } => write!(
+                f,
+                "Battery {} has charge level {} at {:?}, outside of its minimum charge and capacity",
+                id, level, time
+            ),
+            Violation::GridLimitExceeded {
+                time,
+                consumption,
+                limit,
+            } => write!(
+                f,
+                "Grid import {} at {:?} exceeds the grid limit {}",
+                consumption, time, limit
+            ),
+        }
+    }
+}
+
+/// Something [`Schedule::concat`] changed about an asset to merge two schedules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MergeNote {
+    /// The constant action is in both schedules, the assignment of the next one is kept.
+    ConstantActionReplaced { id: u32 },
+    /// The constant action is only in the first schedule and starts at or after the seam, so
+    /// it is left out.
+    ConstantActionDropped { id: u32 },
+    /// The constant action of the next schedule ends after the merged horizon, so it is left
+    /// out.
+    ConstantActionBeyondHorizon { id: u32 },
+    /// The variable action is in both schedules, the assignment of the next one is used from
+    /// the seam on.
+    VariableActionReplaced { id: u32 },
+    /// The variable action is only in the first schedule, its assignment ends at the seam.
+    VariableActionCutAtSeam { id: u32 },
+    /// The variable action of the next schedule runs past the merged horizon, its assignment
+    /// ends there.
+    VariableActionCutAtEnd { id: u32 },
+    /// The battery is only in the first schedule, its level is held from the seam on.
+    BatteryHeldFromSeam { id: u32 },
+    /// The battery is only in the next schedule, its initial level is held before the seam.
+    BatteryHeldBeforeSeam { id: u32 },
+}
+
+impl fmt::Display for MergeNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeNote::ConstantActionReplaced { id } => write!(
+                f,
+                "Constant action {} is in both schedules, the next schedule's assignment is kept",
+                id
+            ),
+            MergeNote::ConstantActionDropped { id } => write!(
+                f,
+                "Constant action {} is only planned after the seam by the first schedule and is left out",
+                id
+            ),
+            MergeNote::ConstantActionBeyondHorizon { id } => write!(
+                f,
+                "Constant action {} ends after the merged horizon and is left out",
+                id
+            ),
+            MergeNote::VariableActionReplaced { id } => write!(
+                f,
+                "Variable action {} is in both schedules, the next schedule's assignment is used from the seam on",
+                id
+            ),
+            MergeNote::VariableActionCutAtSeam { id } => write!(
+                f,
+                "Variable action {} is only in the first schedule and ends at the seam",
+                id
+            ),
+            MergeNote::VariableActionCutAtEnd { id } => write!(
+                f,
+                "Variable action {} runs past the merged horizon and ends there",
+                id
+            ),
+            MergeNote::BatteryHeldFromSeam { id } => write!(
+                f,
+                "Battery {} is only in the first schedule, its level is held from the seam on",
+                id
+            ),
+            MergeNote::BatteryHeldBeforeSeam { id } => write!(
+                f,
+                "Battery {} is only in the next schedule, its initial level is held before the seam",
+                id
+            ),
+        }
+    }
+}
+
+/// Reasons two schedules can't be merged, see [`Schedule::concat`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConcatError {
+    /// The charge levels of a battery in both schedules differ at the seam by more than the
+    /// tolerance.
+    BatteryLevelMismatch {
+        id: u32,
+        level: i64,
+        next_level: i64,
+    },
+}
+
+impl fmt::Display for ConcatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConcatError::BatteryLevelMismatch {
+                id,
+                level,
+                next_level,
+            } => write!(
+                f,
+                "Battery {} has charge level {} at the seam, but the next schedule starts it at {}",
+                id, level, next_level
+            ),
+        }
+    }
+}
+
+/// Ways a schedule doesn't fit together, found by [`Schedule::check_shape`].
+///
+/// The optimizers never produce them, but a schedule read from elsewhere, e.g. deserialized,
+/// can have them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShapeError {
+    /// A list, named by `field`, doesn't have the `expected` number of values.
+    LengthMismatch {
+        field: String,
+        len: usize,
+        expected: usize,
+    },
+    /// An action of the schedule isn't valid for the timesteps of the schedule.
+    InvalidAction(ActionError),
+    /// A constant action starts before its window or ends after it.
+    ConstantActionOutsideWindow { id: u32, start: Time },
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShapeError::LengthMismatch {
+                field,
+                len,
+                expected,
+            } => write!(f, "{} has {} values, expected {}", field, len, expected),
+            ShapeError::InvalidAction(error) => error.fmt(f),
+            ShapeError::ConstantActionOutsideWindow { id, start } => write!(
+                f,
+                "Constant action {} starts at {:?}, it doesn't run within its window",
+                id, start
+            ),
+        }
+    }
+}
+
+/// An asset planned by a schedule, see [`ScheduleDiff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScheduledAsset {
+    ConstantAction { id: u32 },
+    VariableAction { id: u32 },
+    Battery { id: u32 },
+}
+
+/// How much two schedules over the same horizon differ, see [`Schedule::diff`]. Every list
+/// is ordered by ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleDiff {
+    /// Start of every constant action in both schedules, in this and in the other one.
+    pub constant_actions: Vec<(u32, Time, Time)>,
+    /// Sum of the absolute differences of the consumption per timestep in mWh, for every
+    /// variable action in both schedules.
+    pub variable_actions: Vec<(u32, i64)>,
+    /// Largest difference of the charge levels in mWh, for every battery in both schedules.
+    pub batteries: Vec<(u32, i64)>,
+    /// Assets only the schedule `diff` was called on plans.
+    pub only_in_this: Vec<ScheduledAsset>,
+    /// Assets only the other schedule plans.
+    pub only_in_other: Vec<ScheduledAsset>,
+    /// 1 for identical schedules, down to 0 if every asset changed completely, see
+    /// [`Schedule::diff`].
+    pub stability: f64,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Schedule {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     pub constant_actions: HashMap<u32, AssignedConstantAction>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     pub variable_actions: HashMap<u32, AssignedVariableAction>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     pub batteries: HashMap<u32, AssignedBattery>,
     pub network_consumption: Prognoses<i64>,
+    pub grid_export: Prognoses<i64>,
+    /// Electricity price the schedule was optimized for.
+    pub electricity_price: Prognoses<i64>,
+    /// Network fee on grid imports the schedule was optimized for, if there was one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub network_fee: Option<Box<Prognoses<i64>>>,
+    /// CO2 intensity of grid imports in mg per Wh the schedule was optimized for, if there was
+    /// one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub co2_intensity: Option<Box<Prognoses<i64>>>,
+    /// Periods the grid draw was measured on for the peak charge.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub settlement_periods: SettlementPeriods,
+    /// What the cost of the schedule is made of, if the optimizer was asked for it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub objective_breakdown: Option<CostBreakdown>,
+    /// What was changed about the assets to merge schedules, see [`Schedule::concat`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub merge_notes: Vec<MergeNote>,
+    /// Consumption of constant actions the optimizer couldn't serve, ordered by time.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unserved_constant_actions: Vec<UnservedConstantAction>,
+    /// Edges of the solved flow network, if the optimizer was asked to capture them. It is
+    /// only meant for inspecting a solve and never serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub flow_table: Option<Vec<FlowEdge>>,
+}
+
+/// An edge of the flow network a schedule was read from, with its solution, see
+/// [`Schedule::get_flow_table`]. Capacity and flow are in mWh per timestep, the unit cost in
+/// µ€/Wh, so flow times unit cost is in n€.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowEdge {
+    /// Node the edge leads from, e.g. "Wire", "Network" or "Battery 3".
+    pub from: String,
+    /// Node the edge leads to.
+    pub to: String,
+    /// Timestep of the node the edge leads from, or of the one it leads to if the first is
+    /// shared by all timesteps. None if both are.
+    pub time: Option<Time>,
+    pub capacity: i64,
+    pub flow: i64,
+    pub unit_cost: i64,
+    /// What the cost of the edge is for, e.g. "grid_import", None for free edges.
+    pub category: Option<&'static str>,
+}
+
+/// One timestep of a schedule, see [`Schedule::to_rows`]. Energies are in mWh per timestep,
+/// battery levels in mWh and the price in µ€/Wh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRow {
+    pub time: Time,
+    pub electricity_price: i64,
+    /// Electricity drawn from the grid.
+    pub network_consumption: i64,
+    pub grid_export: i64,
+    /// Generated electricity, None if no context was given.
+    pub generation: Option<i64>,
+    /// `(id, level, charge)` of every battery by id, the level at the start of the timestep
+    /// and the charge during it, negative when discharging.
+    pub batteries: Vec<(u32, i64, i64)>,
+    /// `(id, consumption)` of every constant and variable action by id, 0 while it doesn't run.
+    pub actions: Vec<(u32, i64)>,
+}
+
+/// Consumption of a constant action that wasn't served in a timestep, e.g. because the grid
+/// limit is too low.
+///
+/// Constant actions overlapping in a timestep add up and must all be served in full. If their
+/// sum can't be served, the actions are served in the order they start, then by id, so the
+/// shortfall is blamed on the action that started last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnservedConstantAction {
+    pub id: u32,
+    pub time: Time,
+    /// Consumption that wasn't served, at most the consumption of the action in the timestep.
+    pub unserved: i64,
+}
+
+/// The cost of a schedule split up by what it is paid for, in n€. The parts sum up to the cost
+/// the optimizer returned, see [`CostBreakdown::total`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CostBreakdown {
+    /// Electricity bought from the grid, at the electricity price.
+    pub grid_import: i64,
+    /// Network fee of the electricity bought from the grid.
+    pub network_fee: i64,
+    /// CO2 emitted by the electricity bought from the grid, at the CO2 price. It steers the
+    /// optimizer and isn't money, see [`CostBreakdown::penalties`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub co2: i64,
+    /// Electricity drawn from the grid and lost while charging batteries, priced at the grid
+    /// price. Losses covered by generation only cost the export they replace.
+    pub charging_loss: i64,
+    /// Electricity lost to the self-discharge of batteries, priced at the grid price.
+    pub self_discharge: i64,
+    /// Cycle cost of the energy stored in every battery that has one, by id, see
+    /// `Battery::with_cycle_cost`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub battery_wear: BTreeMap<u32, i64>,
+    /// Electricity exported to the grid, negative since it is paid for.
+    pub grid_export: i64,
+    /// Preferences of variable actions for some timesteps, see
+    /// `VariableAction::with_preference`. It breaks ties and isn't money, see
+    /// [`CostBreakdown::monetary`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preference: i64,
+    /// Change of the grid import caused by rounding variable actions to their minimum
+    /// consumption.
+    pub min_consumption_rounding: i64,
+    /// Penalty for consumption that couldn't be served and charge missing to final charge
+    /// targets.
+    pub unserved_penalty: i64,
+    /// Peak price for the highest grid import.
+    pub peak_charge: i64,
+}
+
+impl CostBreakdown {
+    /// Sum of all parts but the preference, i.e. the cost without what only breaks ties.
+    pub fn monetary(&self) -> i64 {
+        self.total() - self.preference
+    }
+
+    /// The parts that aren't paid for but only steer the optimizer, by name, leaving out those
+    /// that are zero: the preference, the CO2 and the penalty for unserved consumption.
+    pub fn penalties(&self) -> Vec<(&'static str, i64)> {
+        [
+            ("preference", self.preference),
+            ("co2", self.co2),
+            ("unserved_penalty", self.unserved_penalty),
+        ]
+        .into_iter()
+        .filter(|&(_, cost)| cost != 0)
+        .collect()
+    }
+
+    /// Sum of all parts but the [`CostBreakdown::penalties`], i.e. what the schedule really costs.
+    pub fn energy_cost(&self) -> i64 {
+        self.total() - self.penalties().iter().map(|(_, cost)| cost).sum::<i64>()
+    }
+
+    /// Sum of all parts.
+    pub fn total(&self) -> i64 {
+        self.grid_import
+            + self.network_fee
+            + self.co2
+            + self.charging_loss
+            + self.self_discharge
+            + self.battery_wear.values().sum::<i64>()
+            + self.grid_export
+            + self.preference
+            + self.min_consumption_rounding
+            + self.unserved_penalty
+            + self.peak_charge
+    }
+}
+
+/// The grid cost of a schedule attributed to the consumers, in n€, see
+/// [`Schedule::get_cost_breakdown`].
+///
+/// Every consumer is charged its consumption at the electricity price, as if it were drawn from
+/// the grid when it is used. What generation and batteries save against that is listed
+/// separately, so that the consumers minus the savings sum up to the energy cost, see
+/// [`AssetCostBreakdown::total`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssetCostBreakdown {
+    /// Cost of the grid import of every timestep.
+    pub per_timestep: Vec<i64>,
+    /// Cost of every constant action by id.
+    pub constant_actions: BTreeMap<u32, i64>,
+    /// Cost of every variable action by id.
+    pub variable_actions: BTreeMap<u32, i64>,
+    /// Cost of the beyond control consumption.
+    pub beyond_control: i64,
+    /// Cost saved by using generated electricity when it is generated.
+    pub generation_savings: i64,
+    /// Cost saved by everything else that lowers the grid import, mainly batteries shifting
+    /// the consumption to cheaper timesteps. Negative if they make the import more expensive.
+    pub battery_savings: i64,
+}
+
+impl AssetCostBreakdown {
+    /// Cost of all consumers minus the savings, equal to the sum of `per_timestep`.
+    pub fn total(&self) -> i64 {
+        self.constant_actions.values().sum::<i64>()
+            + self.variable_actions.values().sum::<i64>()
+            + self.beyond_control
+            - self.generation_savings
+            - self.battery_savings
+    }
+}
+
+/// Serializes a map ordered by ID, so equal schedules serialize to equal bytes.
+/// Check that `field` has `len` values, `expected` are needed.
+fn check_length(field: String, len: usize, expected: usize) -> Result<(), ShapeError> {
+    if len != expected {
+        return Err(ShapeError::LengthMismatch {
+            field,
+            len,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn serialize_sorted<V: serde::Serialize, S: serde::Serializer>(
+    map: &HashMap<u32, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().collect::<std::collections::BTreeMap<_, _>>())
 }
 
 impl Schedule {
@@ -20,19 +528,146 @@ impl Schedule {
         variable_actions: HashMap<u32, AssignedVariableAction>,
         batteries: HashMap<u32, AssignedBattery>,
         network_consumption: Prognoses<i64>,
+        grid_export: Prognoses<i64>,
+        electricity_price: Prognoses<i64>,
     ) -> Self {
         Self {
             constant_actions,
             variable_actions,
             batteries,
             network_consumption,
+            grid_export,
+            electricity_price,
+            network_fee: None,
+            co2_intensity: None,
+            settlement_periods: SettlementPeriods::default(),
+            objective_breakdown: None,
+            merge_notes: Vec::new(),
+            unserved_constant_actions: Vec::new(),
+            flow_table: None,
         }
     }
 
+    /// Returns the length of the timesteps, set by the prognoses of the context.
+    pub fn get_timestep_config(&self) -> TimestepConfig {
+        self.electricity_price.get_timestep_config()
+    }
+
     pub fn set_constant_actions(&mut self, actions: HashMap<u32, AssignedConstantAction>) {
         self.constant_actions = actions;
     }
 
+    pub fn set_network_fee(&mut self, network_fee: Prognoses<i64>) {
+        self.network_fee = Some(Box::new(network_fee));
+    }
+
+    pub fn set_co2_intensity(&mut self, co2_intensity: Prognoses<i64>) {
+        self.co2_intensity = Some(Box::new(co2_intensity));
+    }
+
+    pub fn set_settlement_periods(&mut self, settlement_periods: SettlementPeriods) {
+        self.settlement_periods = settlement_periods;
+    }
+
+    /// Average electricity drawn from the grid per timestep over every settlement period, as
+    /// the start of the period and the average.
+    pub fn get_settlement_profile(&self) -> Vec<(Time, i64)> {
+        self.settlement_periods
+            .averages(self.network_consumption.get_data())
+    }
+
+    /// Cost of the electricity drawn from the grid at the electricity price, without the
+    /// network fee.
+    pub fn get_energy_cost(&self) -> i64 {
+        self.network_consumption
+            .get_data()
+            .iter()
+            .zip(self.electricity_price.get_data())
+            .map(|(consumption, price)| consumption * price)
+            .sum()
+    }
+
+    /// Network fee paid for the electricity drawn from the grid, zero without a network fee.
+    pub fn get_network_fee_cost(&self) -> i64 {
+        let Some(network_fee) = &self.network_fee else {
+            return 0;
+        };
+        self.network_consumption
+            .get_data()
+            .iter()
+            .zip(network_fee.get_data())
+            .map(|(consumption, fee)| consumption * fee)
+            .sum()
+    }
+
+    /// CO2 emitted by the electricity drawn from the grid, in µg, None if the schedule wasn't
+    /// optimized with a CO2 intensity. Generated electricity, also when stored in a battery,
+    /// emits none.
+    pub fn get_co2_emissions(&self) -> Option<i64> {
+        let co2_intensity = self.co2_intensity.as_ref()?;
+        Some(
+            self.network_consumption
+                .get_data()
+                .iter()
+                .zip(co2_intensity.get_data())
+                .map(|(consumption, intensity)| consumption * intensity)
+                .sum(),
+        )
+    }
+
+    /// Price paid per Wh drawn from the grid in the timestep at `time`, in µ€/Wh: the cost of
+    /// the import at the electricity price plus the network fee the schedule was optimized for,
+    /// divided by the import. None if nothing is drawn from the grid in the timestep. The peak
+    /// charge isn't part of it, since it isn't paid for a single timestep.
+    pub fn get_effective_import_price(&self, time: Time) -> Option<f64> {
+        let import = *self.network_consumption.get(time)?;
+        if import <= 0 {
+            return None;
+        }
+        let fee = self
+            .network_fee
+            .as_ref()
+            .map_or(0, |fee| *fee.get(time).unwrap_or(&0));
+        let cost = import * (self.electricity_price.get(time).unwrap_or(&0) + fee);
+        Some(cost as f64 / import as f64)
+    }
+
+    /// Bill under net metering: the energy drawn from the grid minus the energy exported, priced
+    /// at the flat rate (micro-euro per Wh), plus the network fee on the import, which isn't
+    /// netted. Negative if more was exported than drawn. The peak charge isn't part of it.
+    pub fn get_net_metering_bill(&self, rate: i64) -> i64 {
+        let import: i64 = self.network_consumption.get_data().iter().sum();
+        let export: i64 = self.grid_export.get_data().iter().sum();
+        (import - export) * rate + self.get_network_fee_cost()
+    }
+
+    pub fn set_objective_breakdown(&mut self, breakdown: CostBreakdown) {
+        self.objective_breakdown = Some(breakdown);
+    }
+
+    /// What the cost of the schedule is made of. Only set if the optimizer was asked to
+    /// explain the cost, see `AnnealingConfig::explain_cost`, and always by `solve_exact`.
+    pub fn get_objective_breakdown(&self) -> Option<&CostBreakdown> {
+        self.objective_breakdown.as_ref()
+    }
+
+    /// Every edge of the solved flow network the schedule was read from. Only set if the
+    /// optimizer was asked to capture it, see `AnnealingConfig::capture_flow`.
+    ///
+    /// The flow is the one before variable actions are rounded to their minimum consumption.
+    /// Its cost, the sum of flow times unit cost, contains the bonus for keeping the initial
+    /// charge of batteries, which isn't part of the cost of a schedule.
+    pub fn get_flow_table(&self) -> Option<&[FlowEdge]> {
+        self.flow_table.as_deref()
+    }
+
+    /// Consumption of constant actions that wasn't served, empty if every constant action
+    /// is served in full. See [`UnservedConstantAction`] for which action is blamed when
+    /// overlapping actions can't all be served.
+    pub fn get_unserved_constant_actions(&self) -> &[UnservedConstantAction] {
+        &self.unserved_constant_actions
+    }
+
     pub fn get_variable_action(&self, id: u32) -> Option<&AssignedVariableAction> {
         self.variable_actions.get(&id)
     }
@@ -44,4 +679,1476 @@ impl Schedule {
     pub fn get_battery(&self, id: u32) -> Option<&AssignedBattery> {
         self.batteries.get(&id)
     }
+
+    /// Attributes the energy cost of the schedule to its consumers, see [`AssetCostBreakdown`].
+    ///
+    /// `context` provides the beyond control consumption and the generation, which the
+    /// schedule doesn't keep. Prices are the ones the schedule was optimized for, without the
+    /// network fee. Like in the optimizer, constant actions are scaled in the first timestep
+    /// and negative beyond control consumption and generation count as 0.
+    pub fn get_cost_breakdown(&self, context: &OptimizerContext) -> AssetCostBreakdown {
+        let fraction = context.get_first_timestep_fraction();
+        let price = |time: Time| *self.electricity_price.get(time).unwrap_or(&0);
+        let mut breakdown = AssetCostBreakdown::default();
+        let mut load = vec![0; self.electricity_price.len() as usize];
+
+        for (&id, action) in &self.constant_actions {
+            let mut cost = 0;
+            for (time, consumption) in action.iter_consumption() {
+                let consumption = scale_first_timestep(consumption, time, fraction);
+                load[time.to_timestep() as usize] += consumption;
+                cost += consumption * price(time);
+            }
+            breakdown.constant_actions.insert(id, cost);
+        }
+        for (&id, action) in &self.variable_actions {
+            let mut cost = 0;
+            for (time, consumption) in action.iter_consumption() {
+                load[time.to_timestep() as usize] += consumption;
+                cost += consumption * price(time);
+            }
+            breakdown.variable_actions.insert(id, cost);
+        }
+        for (t, load) in load.iter_mut().enumerate() {
+            let time = Time::from_timestep(t as u32);
+            let price = price(time);
+            let beyond_control = (*context
+                .get_beyond_control_consumption()
+                .get(time)
+                .unwrap_or(&0))
+            .max(0);
+            let generated = (*context.get_generated_electricity().get(time).unwrap_or(&0)).max(0);
+            let import = *self.network_consumption.get(time).unwrap_or(&0);
+            *load += beyond_control;
+
+            breakdown.beyond_control += beyond_control * price;
+            breakdown.generation_savings += (*load).min(generated) * price;
+            breakdown.battery_savings += ((*load - generated).max(0) - import) * price;
+            breakdown.per_timestep.push(import * price);
+        }
+        breakdown
+    }
+
+    /// Returns one row per timestep of the horizon with the price, the grid import and export,
+    /// the level and charge of every battery and the consumption of every action. `context`
+    /// provides the generation, which the schedule doesn't keep.
+    pub fn to_rows(&self, context: Option<&OptimizerContext>) -> Vec<ScheduleRow> {
+        (0..self.electricity_price.len())
+            .map(|t| {
+                let time = Time::from_timestep(t);
+                let mut batteries: Vec<_> = self
+                    .batteries
+                    .iter()
+                    .map(|(&id, battery)| {
+                        let level = *battery.get_charge_level(time).unwrap_or(&0);
+                        let next_level = battery
+                            .get_charge_level(time.get_next_timestep())
+                            .map_or(level, |&next| next);
+                        (id, level, next_level - level)
+                    })
+                    .collect();
+                batteries.sort_unstable();
+                let mut actions: Vec<_> = self
+                    .constant_actions
+                    .iter()
+                    .map(|(&id, action)| (id, action.get_consumption(time)))
+                    .collect();
+                for (&id, action) in &self.variable_actions {
+                    let running = action.get_start() <= time && time < action.get_end();
+                    let consumption = if running {
+                        action.get_consumption(time)
+                    } else {
+                        0
+                    };
+                    actions.push((id, consumption));
+                }
+                actions.sort_unstable();
+                ScheduleRow {
+                    time,
+                    electricity_price: *self.electricity_price.get(time).unwrap_or(&0),
+                    network_consumption: *self.network_consumption.get(time).unwrap_or(&0),
+                    grid_export: self.get_grid_export(time),
+                    generation: context.map(|context| {
+                        *context.get_generated_electricity().get(time).unwrap_or(&0)
+                    }),
+                    batteries,
+                    actions,
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the schedule as a text Gantt chart of `columns` columns, for debugging in a
+    /// terminal. Each column covers the timesteps from `c * steps / columns` up to the
+    /// next column's start. The first line is the price of each column, from `_` for the
+    /// lowest mean price of the day to `@` for the highest. Then every battery by id shows
+    /// whether it charges (`+`), discharges (`-`) or idles (`.`) and every action by id
+    /// whether it consumes (`#`) or not (`.`). A column shows the state of most of its
+    /// timesteps, ties go to the state listed first. Each line starts with the label of its
+    /// row padded to the longest label and a space, so no line is longer than that gutter
+    /// plus `columns`.
+    ///
+    /// # Panics
+    /// If `columns` is 0 or greater than the number of timesteps.
+    pub fn gantt(&self, columns: usize) -> String {
+        const PRICE_LEVELS: &[u8] = b"_.:-=+*#%@";
+        let steps = self.electricity_price.len();
+        assert!(
+            (1..=steps as usize).contains(&columns),
+            "columns must be between 1 and {steps}"
+        );
+        let rows = self.to_rows(None);
+        let buckets: Vec<_> = (0..columns)
+            .map(|c| &rows[c * rows.len() / columns..(c + 1) * rows.len() / columns])
+            .collect();
+        // The state of most timesteps of each bucket, from `states` ordered by precedence
+        let majority = |states: &[u8], state_of: &dyn Fn(&ScheduleRow) -> u8| -> String {
+            buckets
+                .iter()
+                .map(|bucket| {
+                    let count = |state| bucket.iter().filter(|row| state_of(row) == state).count();
+                    // max_by_key returns the last maximum, so search from the back
+                    *states
+                        .iter()
+                        .rev()
+                        .max_by_key(|&&state| count(state))
+                        .unwrap() as char
+                })
+                .collect()
+        };
+
+        let means: Vec<i64> = buckets
+            .iter()
+            .map(|bucket| {
+                bucket.iter().map(|row| row.electricity_price).sum::<i64>() / bucket.len() as i64
+            })
+            .collect();
+        let (min, max) = (means.iter().min().unwrap(), means.iter().max().unwrap());
+        let prices: String = means
+            .iter()
+            .map(|mean| {
+                let top = PRICE_LEVELS.len() as i64 - 1;
+                let level = (mean - min) * top / (max - min).max(1);
+                PRICE_LEVELS[level as usize] as char
+            })
+            .collect();
+
+        let mut lines = vec![("Price".to_string(), prices)];
+        let first_row = &rows[0];
+        for (i, &(id, _, _)) in first_row.batteries.iter().enumerate() {
+            let chart = majority(b"+-.", &|row| match row.batteries[i].2 {
+                charge if charge > 0 => b'+',
+                charge if charge < 0 => b'-',
+                _ => b'.',
+            });
+            lines.push((format!("Battery {id}"), chart));
+        }
+        for (i, &(id, _)) in first_row.actions.iter().enumerate() {
+            let chart = majority(b"#.", &|row| if row.actions[i].1 > 0 { b'#' } else { b'.' });
+            lines.push((format!("Action {id}"), chart));
+        }
+
+        let width = lines.iter().map(|(label, _)| label.len()).max().unwrap();
+        lines
+            .iter()
+            .map(|(label, chart)| format!("{label:<width$} {chart}\n"))
+            .collect()
+    }
+
+    /// Returns how much the grid import during timestep `time` could be lowered against the
+    /// plan, e.g. for demand response: the discharge headroom of all batteries, the consumption
+    /// of variable actions that could be moved to later in their window and of constant actions
+    /// that start at `time` and could start later. Precedences between constant actions aren't
+    /// considered. The consumption of the first timestep is scaled by `first_timestep_fraction`
+    /// like in the optimizer.
+    pub fn get_total_flex(&self, time: Time, first_timestep_fraction: f32) -> i64 {
+        let batteries: i64 = self
+            .batteries
+            .values()
+            .filter_map(|battery| battery.get_discharge_headroom(time, first_timestep_fraction))
+            .sum();
+        let variable: i64 = self
+            .variable_actions
+            .values()
+            .map(|action| action.get_deferrable_consumption(time))
+            .sum();
+        let constant: i64 = self
+            .constant_actions
+            .values()
+            .map(|action| {
+                let consumption = action.get_deferrable_consumption(time);
+                scale_first_timestep(consumption, time, first_timestep_fraction)
+            })
+            .sum();
+        batteries + variable + constant
+    }
+
+    /// Returns the electricity exported to the grid at the given time, 0 outside the horizon.
+    pub fn get_grid_export(&self, time: Time) -> i64 {
+        *self.grid_export.get(time).unwrap_or(&0)
+    }
+
+    /// Returns the first timestep with the highest grid import and that import.
+    pub fn get_peak_consumption(&self) -> (Time, i64) {
+        let (timestep, consumption) = self
+            .network_consumption
+            .get_data()
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, consumption)| **consumption)
+            .expect("Schedules always cover the whole day");
+        (Time::from_timestep(timestep as u32), *consumption)
+    }
+
+    /// Returns all timesteps where electricity is drawn from the grid while the price is above
+    /// the given percentile of the day's prices, as `(time, grid import, price)`.
+    ///
+    /// The percentile uses the nearest-rank method, so the result never contains the timesteps
+    /// with the lowest prices and is empty for a percentile of 1.
+    /// # Panics
+    /// * Panics if the percentile is not in [0, 1].
+    pub fn get_expensive_consumption(&self, percentile: f64) -> Vec<(Time, i64, i64)> {
+        assert!(
+            (0.0..=1.0).contains(&percentile),
+            "Percentile must be in [0, 1], got {percentile}"
+        );
+        let mut prices = self.electricity_price.get_data().to_vec();
+        prices.sort_unstable();
+        let rank = (percentile * prices.len() as f64).ceil() as usize;
+        let threshold = prices[rank.saturating_sub(1)];
+
+        (0..self.electricity_price.len())
+            .map(Time::from_timestep)
+            .filter_map(|time| {
+                let price = *self.electricity_price.get(time)?;
+                let import = *self.network_consumption.get(time).unwrap_or(&0);
+                (price > threshold && import > 0).then_some((time, import, price))
+            })
+            .collect()
+    }
+
+    /// Checks that the parts of the schedule fit together: every series has one value per
+    /// timestep, the actions are valid for these timesteps and every assignment lies within
+    /// the window of its action. Unlike [`Schedule::verify`] it needs no context, e.g. for
+    /// deserialized schedules. Returns the first problem found.
+    pub fn check_shape(&self) -> Result<(), ShapeError> {
+        let timestep = self.get_timestep_config();
+        let steps = self.electricity_price.len() as usize;
+        let series = [
+            ("network_consumption", Some(&self.network_consumption)),
+            ("grid_export", Some(&self.grid_export)),
+            ("network_fee", self.network_fee.as_deref()),
+            ("co2_intensity", self.co2_intensity.as_deref()),
+        ];
+        for (field, prognoses) in series {
+            if let Some(prognoses) = prognoses {
+                check_length(field.to_string(), prognoses.len() as usize, steps)?;
+            }
+        }
+
+        let constant_actions: BTreeMap<_, _> = self.constant_actions.iter().collect();
+        for (id, assigned) in constant_actions {
+            let action = assigned.get_action();
+            action
+                .validate_for(timestep)
+                .map_err(ShapeError::InvalidAction)?;
+            check_length(
+                format!("profile of constant action {}", id),
+                action.get_profile().len(),
+                action.get_duration().to_timestep() as usize,
+            )?;
+            if let Some(default_profile) = action.get_default_profile() {
+                check_length(
+                    format!("default_profile of constant action {}", id),
+                    default_profile.len(),
+                    steps,
+                )?;
+            }
+            let start = assigned.get_start_time();
+            if start < action.get_start_from() || assigned.get_end_time() > action.get_end_before()
+            {
+                return Err(ShapeError::ConstantActionOutsideWindow { id: *id, start });
+            }
+        }
+
+        let variable_actions: BTreeMap<_, _> = self.variable_actions.iter().collect();
+        for (id, action) in variable_actions {
+            action
+                .validate_for(timestep)
+                .map_err(ShapeError::InvalidAction)?;
+            let window = (action.end.to_timestep() - action.start.to_timestep()) as usize;
+            check_length(
+                format!("consumption of variable action {}", id),
+                action.iter_consumption().count(),
+                window,
+            )?;
+            if let Some(profile) = action.get_max_consumption_profile() {
+                check_length(
+                    format!("max_consumption_profile of variable action {}", id),
+                    profile.len(),
+                    window,
+                )?;
+            }
+            if let Some(preference) = action.get_preference() {
+                check_length(
+                    format!("preference of variable action {}", id),
+                    preference.len(),
+                    window,
+                )?;
+            }
+        }
+
+        let batteries: BTreeMap<_, _> = self.batteries.iter().collect();
+        for (id, assigned) in batteries {
+            check_length(
+                format!("charge_level of battery {}", id),
+                assigned.get_charge_levels().len() as usize,
+                steps,
+            )?;
+            if let Some(green_charge) = assigned.get_green_charges() {
+                check_length(
+                    format!("green_charge of battery {}", id),
+                    green_charge.len() as usize,
+                    steps,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the schedule against the constraints of `context`: constant actions inside
+    /// their windows, variable actions inside their windows, under their maximum consumption
+    /// and either off or at least at their minimum, battery levels between minimum charge and
+    /// capacity, the grid import under the grid limit and the actions of an exclusion group
+    /// apart. Returns every violation found, empty for a valid schedule.
+    pub fn verify(&self, context: &OptimizerContext) -> Vec<Violation> {
+        let fraction = context.get_first_timestep_fraction();
+        let mut violations = Vec::new();
+        for action in context.get_constant_actions() {
+            let id = action.get_id();
+            let Some(assigned) = self.get_constant_action(id) else {
+                violations.push(Violation::MissingConstantAction { id });
+                continue;
+            };
+            let (start, end) = (assigned.get_start_time(), assigned.get_end_time());
+            if start < action.get_start_from()
+                || end > action.get_end_before()
+                || end - start != action.duration
+            {
+                violations.push(Violation::ConstantActionOutsideWindow { id, start, end });
+            }
+        }
+        for &(before, after) in context.get_precedences() {
+            let (Some(first), Some(second)) = (
+                self.get_constant_action(before),
+                self.get_constant_action(after),
+            ) else {
+                continue;
+            };
+            if first.get_end_time() + context.get_min_gap(before, after) > second.get_start_time() {
+                violations.push(Violation::PrecedenceViolated { before, after });
+            }
+        }
+        for group in context.get_exclusion_groups() {
+            for (index, &first) in group.iter().enumerate() {
+                for &second in &group[index + 1..] {
+                    let (Some(a), Some(b)) = (
+                        self.get_constant_action(first),
+                        self.get_constant_action(second),
+                    ) else {
+                        continue;
+                    };
+                    if a.get_start_time() < b.get_end_time()
+                        && b.get_start_time() < a.get_end_time()
+                    {
+                        violations.push(Violation::ExclusionViolated { first, second });
+                    }
+                }
+            }
+        }
+        for action in context.get_variable_actions() {
+            let id = action.get_id();
+            let Some(assigned) = self.get_variable_action(id) else {
+                violations.push(Violation::MissingVariableAction { id });
+                continue;
+            };
+            if assigned.get_start() != action.get_start() || assigned.get_end() != action.get_end()
+            {
+                violations.push(Violation::VariableActionOutsideWindow {
+                    id,
+                    start: assigned.get_start(),
+                    end: assigned.get_end(),
+                });
+                continue;
+            }
+            for (time, consumption) in assigned.iter_consumption() {
+                let max = scale_first_timestep(action.get_max_consumption_at(time), time, fraction);
+                if !(0..=max).contains(&consumption) {
+                    violations.push(Violation::VariableActionOverMax {
+                        id,
+                        time,
+                        consumption,
+                        max,
+                    });
+                }
+                let min = action.get_min_consumption();
+                if 0 < consumption && consumption < min {
+                    violations.push(Violation::VariableActionBelowMin {
+                        id,
+                        time,
+                        consumption,
+                        min,
+                    });
+                }
+            }
+        }
+        for battery in context.get_batteries() {
+            let id = battery.get_id();
+            let Some(assigned) = self.get_battery(id) else {
+                violations.push(Violation::MissingBattery { id });
+                continue;
+            };
+            let allowed = battery.get_min_charge()..=battery.get_capacity();
+            for time in (0..=self.electricity_price.len()).map(Time::from_timestep) {
+                if let Some(&level) = assigned.get_charge_level(time)
+                    && !allowed.contains(&level)
+                {
+                    violations.push(Violation::BatteryLevelOutOfRange { id, time, level });
+                }
+            }
+        }
+        if let Some(grid_limit) = context.get_grid_limit() {
+            for (timestep, &consumption) in self.network_consumption.get_data().iter().enumerate() {
+                let time = Time::from_timestep(timestep as u32);
+                let limit = scale_first_timestep(grid_limit, time, fraction);
+                if consumption > limit {
+                    violations.push(Violation::GridLimitExceeded {
+                        time,
+                        consumption,
+                        limit,
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// Compares this schedule with `other` over the same horizon, e.g. the plans of two runs
+    /// on nearly the same inputs: how far constant actions moved, how differently variable
+    /// actions consume and how far battery levels deviate. Assets planned by only one of the
+    /// schedules are listed as such.
+    ///
+    /// The stability is 1 minus the mean change of all assets, each between 0 and 1: the
+    /// shift of a constant action relative to the slack of its window in this schedule, the
+    /// difference of a variable action relative to what both schedules assign to it, the
+    /// deviation of a battery relative to its capacity, and 1 for an asset in only one of the
+    /// schedules. It is 1 if there are no assets.
+    ///
+    /// # Panics
+    /// * Panics if the schedules have different timesteps.
+    pub fn diff(&self, other: &Schedule) -> ScheduleDiff {
+        assert_eq!(
+            self.get_timestep_config(),
+            other.get_timestep_config(),
+            "Only schedules with the same timesteps can be compared"
+        );
+        let mut changes = Vec::new();
+        let mut only_in_this = Vec::new();
+        let mut only_in_other = Vec::new();
+        let mut missing = |in_this: bool, asset: ScheduledAsset, changes: &mut Vec<f64>| {
+            changes.push(1.0);
+            if in_this {
+                only_in_this.push(asset);
+            } else {
+                only_in_other.push(asset);
+            }
+        };
+
+        let mut constant_actions = Vec::new();
+        for id in union_of_keys(&self.constant_actions, &other.constant_actions) {
+            let asset = ScheduledAsset::ConstantAction { id };
+            let (first, second) = match (
+                self.constant_actions.get(&id),
+                other.constant_actions.get(&id),
+            ) {
+                (Some(first), Some(second)) => (first, second),
+                (first, _) => {
+                    missing(first.is_some(), asset, &mut changes);
+                    continue;
+                }
+            };
+            let (start, other_start) = (first.get_start_time(), second.get_start_time());
+            let shift = (other_start.to_timestep() as i64 - start.to_timestep() as i64).abs();
+            let action = first.get_action();
+            let slack = action.end_before.to_timestep() as i64
+                - action.start_from.to_timestep() as i64
+                - action.duration.to_timestep() as i64;
+            changes.push(relative(shift, slack));
+            constant_actions.push((id, start, other_start));
+        }
+
+        let mut variable_actions = Vec::new();
+        for id in union_of_keys(&self.variable_actions, &other.variable_actions) {
+            let asset = ScheduledAsset::VariableAction { id };
+            let (first, second) = match (
+                self.variable_actions.get(&id),
+                other.variable_actions.get(&id),
+            ) {
+                (Some(first), Some(second)) => (first, second),
+                (first, _) => {
+                    missing(first.is_some(), asset, &mut changes);
+                    continue;
+                }
+            };
+            // The windows may differ if the schedules were made for different contexts
+            let mut difference: BTreeMap<Time, i64> = first.iter_consumption().collect();
+            for (time, consumption) in second.iter_consumption() {
+                *difference.entry(time).or_default() -= consumption;
+            }
+            let difference: i64 = difference.values().map(|d| d.abs()).sum();
+            let assigned = first.get_total_assigned() + second.get_total_assigned();
+            changes.push(relative(difference, assigned));
+            variable_actions.push((id, difference));
+        }
+
+        let mut batteries = Vec::new();
+        for id in union_of_keys(&self.batteries, &other.batteries) {
+            let asset = ScheduledAsset::Battery { id };
+            let (first, second) = match (self.batteries.get(&id), other.batteries.get(&id)) {
+                (Some(first), Some(second)) => (first, second),
+                (first, _) => {
+                    missing(first.is_some(), asset, &mut changes);
+                    continue;
+                }
+            };
+            let deviation = first
+                .iter_charge_levels()
+                .zip(second.iter_charge_levels())
+                .map(|((_, level), (_, other_level))| (level - other_level).abs())
+                .max()
+                .unwrap_or(0);
+            changes.push(relative(deviation, first.get_battery().get_capacity()));
+            batteries.push((id, deviation));
+        }
+
+        let stability = if changes.is_empty() {
+            1.0
+        } else {
+            1.0 - changes.iter().sum::<f64>() / changes.len() as f64
+        };
+        ScheduleDiff {
+            constant_actions,
+            variable_actions,
+            batteries,
+            only_in_this,
+            only_in_other,
+            stability,
+        }
+    }
+
+    /// What was changed about the assets to merge schedules, empty unless the schedule was
+    /// merged by [`Schedule::concat`].
+    pub fn get_merge_notes(&self) -> &[MergeNote] {
+        &self.merge_notes
+    }
+
+    /// Merges this schedule with `next`, a schedule starting `seam` timesteps later, into
+    /// one over this schedule's horizon: this schedule up to the seam, then `next`. What
+    /// `next` plans after the end of this horizon is cut off.
+    ///
+    /// Per-timestep series are spliced at the seam. Assets in both schedules take the
+    /// assignment of `next` from the seam on, batteries in both must have charge levels
+    /// within `tolerance` of each other at the seam. Assets in only one schedule are cut at
+    /// the seam or at the end of the horizon. Every such change is recorded as a
+    /// [`MergeNote`]. Merged variable actions consume in total what is assigned to them.
+    /// The merged schedule keeps this schedule's settlement periods and has no objective
+    /// breakdown.
+    ///
+    /// # Panics
+    /// * Panics if the seam is not inside the horizon or the schedules have different
+    ///   timesteps.
+    pub fn concat(
+        &self,
+        next: &Schedule,
+        seam: Time,
+        tolerance: i64,
+    ) -> Result<Schedule, ConcatError> {
+        let timestep = self.get_timestep_config();
+        let day_end = timestep.get_day_end();
+        assert!(
+            seam < day_end,
+            "The seam must be inside the horizon, got {seam:?}"
+        );
+        assert_eq!(
+            timestep,
+            next.get_timestep_config(),
+            "Only schedules with the same timesteps can be merged"
+        );
+        let mut notes = self.merge_notes.clone();
+        notes.extend_from_slice(&next.merge_notes);
+
+        let mut constant_actions = HashMap::new();
+        for id in union_of_keys(&self.constant_actions, &next.constant_actions) {
+            let (first, second) = (
+                self.constant_actions.get(&id),
+                next.constant_actions.get(&id),
+            );
+            match (first, second) {
+                (_, Some(action)) => {
+                    let start = action.get_start_time() + seam;
+                    if start + action.duration > day_end {
+                        notes.push(MergeNote::ConstantActionBeyondHorizon { id });
+                        continue;
+                    }
+                    if first.is_some() {
+                        notes.push(MergeNote::ConstantActionReplaced { id });
+                    }
+                    let shifted = Rc::new(ConstantAction::new_profiled(
+                        action.start_from + seam,
+                        (action.end_before + seam).min(day_end),
+                        action.get_profile().to_vec(),
+                        id,
+                    ));
+                    constant_actions.insert(id, AssignedConstantAction::new(shifted, start));
+                }
+                (Some(action), None) if action.get_start_time() < seam => {
+                    constant_actions.insert(id, action.clone());
+                }
+                (Some(_), None) => notes.push(MergeNote::ConstantActionDropped { id }),
+                (None, None) => unreachable!("The ID is a key of one of the maps"),
+            }
+        }
+
+        let mut variable_actions = HashMap::new();
+        for id in union_of_keys(&self.variable_actions, &next.variable_actions) {
+            let (first, second) = (
+                self.variable_actions.get(&id),
+                next.variable_actions.get(&id),
+            );
+            match (first, second) {
+                (Some(_), Some(_)) => notes.push(MergeNote::VariableActionReplaced { id }),
+                (Some(action), None) if action.end > seam => {
+                    notes.push(MergeNote::VariableActionCutAtSeam { id })
+                }
+                _ => {}
+            }
+            if second.is_some_and(|action| action.end + seam > day_end) {
+                notes.push(MergeNote::VariableActionCutAtEnd { id });
+            }
+            if let Some(merged) = merge_variable_actions(id, first, second, seam, day_end) {
+                variable_actions.insert(id, merged);
+            }
+        }
+
+        let mut batteries = HashMap::new();
+        for id in union_of_keys(&self.batteries, &next.batteries) {
+            let (first, second) = (self.batteries.get(&id), next.batteries.get(&id));
+            match (first, second) {
+                (Some(first), Some(second)) => {
+                    let level = *first
+                        .get_charge_level(seam)
+                        .expect("The seam is a timestep");
+                    let next_level = *second
+                        .get_charge_level(Time::from_timestep(0))
+                        .expect("Schedules start with a timestep");
+                    if (level - next_level).abs() > tolerance {
+                        return Err(ConcatError::BatteryLevelMismatch {
+                            id,
+                            level,
+                            next_level,
+                        });
+                    }
+                }
+                (Some(_), None) => notes.push(MergeNote::BatteryHeldFromSeam { id }),
+                (None, Some(_)) => notes.push(MergeNote::BatteryHeldBeforeSeam { id }),
+                (None, None) => unreachable!("The ID is a key of one of the maps"),
+            }
+            batteries.insert(id, merge_batteries(first, second, seam, timestep));
+        }
+
+        let splice_optional = |first: &Option<Box<Prognoses<i64>>>,
+                               second: &Option<Box<Prognoses<i64>>>| {
+            match (first, second) {
+                (None, None) => None,
+                (first, second) => {
+                    let none = Prognoses::from_closure_for(timestep, |_| 0);
+                    Some(Box::new(splice(
+                        first.as_deref().unwrap_or(&none),
+                        second.as_deref().unwrap_or(&none),
+                        seam,
+                    )))
+                }
+            }
+        };
+        let network_fee = splice_optional(&self.network_fee, &next.network_fee);
+        let co2_intensity = splice_optional(&self.co2_intensity, &next.co2_intensity);
+        let mut merged = Schedule::new(
+            constant_actions,
+            variable_actions,
+            batteries,
+            splice(&self.network_consumption, &next.network_consumption, seam),
+            splice(&self.grid_export, &next.grid_export, seam),
+            splice(&self.electricity_price, &next.electricity_price, seam),
+        );
+        merged.network_fee = network_fee;
+        merged.co2_intensity = co2_intensity;
+        merged.settlement_periods = self.settlement_periods;
+        merged.merge_notes = notes;
+        merged.unserved_constant_actions = self
+            .unserved_constant_actions
+            .iter()
+            .filter(|unserved| unserved.time < seam)
+            .copied()
+            .chain(
+                next.unserved_constant_actions
+                    .iter()
+                    .filter_map(|unserved| {
+                        let time = unserved.time + seam;
+                        (time < day_end).then_some(UnservedConstantAction { time, ..*unserved })
+                    }),
+            )
+            .collect();
+        Ok(merged)
+    }
+}
+
+/// IDs that are keys of either map, in ascending order.
+fn union_of_keys<V>(first: &HashMap<u32, V>, second: &HashMap<u32, V>) -> BTreeSet<u32> {
+    first.keys().chain(second.keys()).copied().collect()
+}
+
+/// `change` relative to `scale`, at most 1. Any change of something without scale is complete.
+fn relative(change: i64, scale: i64) -> f64 {
+    if change == 0 {
+        0.0
+    } else if scale <= 0 {
+        1.0
+    } else {
+        (change as f64 / scale as f64).min(1.0)
+    }
+}
+
+/// `first` before the seam, then `second` shifted to start at it.
+fn splice(first: &Prognoses<i64>, second: &Prognoses<i64>, seam: Time) -> Prognoses<i64> {
+    Prognoses::from_closure_for(first.get_timestep_config(), |time| {
+        if time < seam {
+            first.get_data()[time.to_timestep() as usize]
+        } else {
+            second.get_data()[(time - seam).to_timestep() as usize]
+        }
+    })
+}
+
+/// The variable action `first` before the seam and `second`, shifted to start at the seam,
+/// after it, or `None` if neither assigns anything to the merged horizon ending at `day_end`.
+fn merge_variable_actions(
+    id: u32,
+    first: Option<&AssignedVariableAction>,
+    second: Option<&AssignedVariableAction>,
+    seam: Time,
+    day_end: Time,
+) -> Option<AssignedVariableAction> {
+    let first = first.filter(|action| action.start < seam);
+    let second = second.filter(|action| action.start + seam < day_end);
+    let start = match (first, second) {
+        (Some(action), _) => action.start,
+        (None, Some(action)) => action.start + seam,
+        (None, None) => return None,
+    };
+    let end = match (first, second) {
+        (_, Some(action)) => (action.end + seam).min(day_end),
+        (Some(action), None) => action.end.min(seam),
+        (None, None) => unreachable!("One of the actions is kept"),
+    };
+    // Outside of an action's window both its consumption and its cap are 0
+    let at = |time: Time, f: &dyn Fn(&AssignedVariableAction, Time) -> i64| {
+        let (action, time) = if time < seam {
+            (first, time)
+        } else {
+            (second, time - seam)
+        };
+        action
+            .filter(|action| (action.start..action.end).contains(&time))
+            .map_or(0, |action| f(action, time))
+    };
+    let (consumption, caps): (Vec<i64>, Vec<i64>) = (start..end)
+        .iter_steps()
+        .map(|time| {
+            (
+                at(time, &|action, time| action.get_consumption(time)),
+                at(time, &|action, time| action.get_max_consumption_at(time)),
+            )
+        })
+        .unzip();
+    let max_consumption = caps.iter().copied().max().unwrap_or(0);
+    let min_consumption = second
+        .or(first)
+        .map_or(0, |action| action.get_min_consumption());
+    let mut action = VariableAction::new(start, end, consumption.iter().sum(), max_consumption, id)
+        .with_min_consumption(min_consumption);
+    if caps.iter().any(|&cap| cap != max_consumption) {
+        action = action.with_max_consumption_profile(caps);
+    }
+    Some(AssignedVariableAction::new(Rc::new(action), consumption))
+}
+
+/// The battery levels of `first` before the seam and of `second`, shifted to start at the
+/// seam, after it. A battery missing on one side holds the level it has at the seam.
+fn merge_batteries(
+    first: Option<&AssignedBattery>,
+    second: Option<&AssignedBattery>,
+    seam: Time,
+    timestep: TimestepConfig,
+) -> AssignedBattery {
+    let level_at_seam = |battery: &AssignedBattery, time: Time| {
+        *battery
+            .get_charge_level(time)
+            .expect("Schedules have a level at every timestep")
+    };
+    let level = |time: Time| match (first, second) {
+        (Some(battery), _) if time < seam => Some(level_at_seam(battery, time)),
+        (None, Some(battery)) if time < seam => {
+            Some(level_at_seam(battery, Time::from_timestep(0)))
+        }
+        (_, Some(battery)) => battery.get_charge_level(time - seam).copied(),
+        (Some(battery), None) => Some(level_at_seam(battery, seam)),
+        (None, None) => unreachable!("One of the batteries is present"),
+    };
+    let battery = second.or(first).expect("One of the batteries is present");
+    let levels = Prognoses::from_closure_for(timestep, |time| {
+        level(time).expect("Schedules have a level at every timestep")
+    });
+    let mut merged = AssignedBattery::new(battery.get_battery().clone(), levels);
+    if let Some(final_level) = level(timestep.get_day_end()) {
+        merged = merged.with_final_charge_level(final_level);
+    }
+    // Green charge is only known if it is known on both sides
+    let green = |time: Time| {
+        let (battery, time) = if time < seam {
+            (first, time)
+        } else {
+            (second, time - seam)
+        };
+        battery.map_or(Some(0), |battery| battery.get_green_charge(time))
+    };
+    let tracked = [first, second]
+        .into_iter()
+        .flatten()
+        .all(|battery| battery.get_green_charge(Time::from_timestep(0)).is_some());
+    if tracked {
+        merged = merged.with_green_charge(Prognoses::from_closure_for(timestep, |time| {
+            green(time).expect("Green charge is tracked on both sides")
+        }));
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        optimizer::solve_exact,
+        optimizer_context::{
+            action::{constant::ConstantAction, variable::VariableAction},
+            battery::Battery,
+        },
+        time::STEPS_PER_DAY,
+    };
+
+    /// A context with one asset of each kind and a valid schedule for it. The flow only plans
+    /// the battery and the variable action, the constant action is placed by hand.
+    fn solved() -> (OptimizerContext, Schedule) {
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|t| (t.to_timestep() % 90) as i64 + 1),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 50),
+            vec![Rc::new(
+                Battery::new(2_000, 1_000, 100, 100, 0.9, 1).with_min_charge(200),
+            )],
+            vec![],
+            vec![Rc::new(VariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(8, 0),
+                5_000,
+                100,
+                3,
+            ))],
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(1_000);
+        let (_, mut schedule) = solve_exact(&context).unwrap();
+        let action = Rc::new(ConstantAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(4, 0),
+            Time::at_default_resolution(0, 30),
+            300,
+            2,
+        ));
+        schedule.constant_actions.insert(
+            2,
+            AssignedConstantAction::new(action.clone(), Time::at_default_resolution(1, 0)),
+        );
+        let context = OptimizerContext::new(
+            context.get_electricity_price().as_ref().clone(),
+            context.get_generated_electricity().as_ref().clone(),
+            context.get_beyond_control_consumption().clone(),
+            context.get_batteries().clone(),
+            vec![action],
+            context.get_variable_actions().clone(),
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(1_000);
+        (context, schedule)
+    }
+
+    #[test]
+    fn test_cost_breakdown_of_four_timesteps_sums_to_energy_cost() {
+        // Nothing happens after the first 4 timesteps
+        let first_four = |values: [i64; 4]| {
+            Prognoses::from_closure(move |t| *values.get(t.to_timestep() as usize).unwrap_or(&0))
+        };
+        let end = Time::from_timestep(4);
+        let constant = Rc::new(ConstantAction::new(
+            Time::from_timestep(0),
+            end,
+            Time::from_timestep(2),
+            100,
+            2,
+        ));
+        let variable = Rc::new(VariableAction::new(Time::from_timestep(0), end, 60, 30, 3));
+        let battery = Rc::new(Battery::new(2_000, 1_000, 100, 100, 1.0, 1));
+        let context = OptimizerContext::new(
+            first_four([10, 20, 30, 40]),
+            first_four([80, 0, 0, 0]),
+            first_four([50, 50, 50, 50]),
+            vec![battery.clone()],
+            vec![constant.clone()],
+            vec![variable.clone()],
+            1.0,
+        )
+        .unwrap();
+        // The battery charges 50 from the grid at timestep 1 and discharges 80 at timestep 3
+        let levels = Prognoses::from_closure(|t| match t.to_timestep() {
+            0 | 1 => 1_000,
+            2 | 3 => 1_050,
+            _ => 970,
+        });
+        let schedule = Schedule::new(
+            HashMap::from([(
+                2,
+                AssignedConstantAction::new(constant, Time::from_timestep(0)),
+            )]),
+            HashMap::from([(3, AssignedVariableAction::new(variable, vec![0, 0, 30, 30]))]),
+            HashMap::from([(
+                1,
+                AssignedBattery::new(battery, levels).with_final_charge_level(970),
+            )]),
+            first_four([70, 200, 80, 0]),
+            Prognoses::from_closure(|_| 0),
+            context.get_electricity_price().as_ref().clone(),
+        );
+
+        let breakdown = schedule.get_cost_breakdown(&context);
+        assert_eq!(&breakdown.per_timestep[..5], &[700, 4_000, 2_400, 0, 0]);
+        assert_eq!(
+            breakdown.constant_actions,
+            BTreeMap::from([(2, 100 * (10 + 20))])
+        );
+        assert_eq!(
+            breakdown.variable_actions,
+            BTreeMap::from([(3, 30 * (30 + 40))])
+        );
+        assert_eq!(breakdown.beyond_control, 50 * (10 + 20 + 30 + 40));
+        // The 80 generated at timestep 0 are used right away
+        assert_eq!(breakdown.generation_savings, 80 * 10);
+        // Charging costs 50 at 20, discharging saves 80 at 40
+        assert_eq!(breakdown.battery_savings, -50 * 20 + 80 * 40);
+        assert_eq!(breakdown.total(), 7_100);
+        assert_eq!(breakdown.per_timestep.iter().sum::<i64>(), 7_100);
+        assert_eq!(schedule.get_energy_cost(), 7_100);
+    }
+
+    #[test]
+    fn test_headroom_and_flex_of_hand_made_plan() {
+        let (_, mut schedule) = solved();
+        // Discharges 50 per timestep for the first 10 timesteps, then holds 500
+        let battery = Rc::new(Battery::new(1_000, 1_000, 100, 150, 1.0, 1).with_min_charge(450));
+        let levels = Prognoses::from_closure(|t| 1_000 - 50 * t.to_timestep().min(10) as i64);
+        let battery = AssignedBattery::new(battery, levels).with_final_charge_level(500);
+        let at = |minutes| Time::at_default_resolution(0, minutes);
+
+        // The rate of the first timestep is halved to 75, of which 50 are planned
+        assert_eq!(battery.get_discharge_headroom(at(0), 0.5), Some(25));
+        assert_eq!(battery.get_discharge_headroom(at(5), 0.5), Some(100));
+        // Only 50 are left above the reserve of 450
+        assert_eq!(battery.get_discharge_headroom(at(9), 1.0), Some(50));
+        assert_eq!(
+            battery.get_discharge_headroom(Time::at_default_resolution(23, 59), 1.0),
+            Some(50)
+        );
+        assert_eq!(
+            battery.get_discharge_headroom(Time::get_day_end(), 1.0),
+            None
+        );
+        // Full up to 50 after the first timestep
+        assert_eq!(battery.get_charge_headroom(at(0), 1.0), Some(50));
+        assert_eq!(battery.get_charge_headroom(at(0), 0.2), Some(20));
+        assert_eq!(battery.get_charge_headroom(at(20), 1.0), Some(100));
+
+        let action = Rc::new(VariableAction::new(
+            Time::at_default_resolution(2, 0),
+            Time::at_default_resolution(2, 5),
+            250,
+            60,
+            3,
+        ));
+        let action = AssignedVariableAction::new(action, vec![60, 60, 60, 40, 30]);
+        // Room under the caps of the later timesteps: 0 + 0 + 20 + 30
+        assert_eq!(
+            action.get_deferrable_consumption(Time::at_default_resolution(2, 0)),
+            50
+        );
+        assert_eq!(
+            action.get_deferrable_consumption(Time::at_default_resolution(2, 3)),
+            30
+        );
+        assert_eq!(
+            action.get_deferrable_consumption(Time::at_default_resolution(2, 4)),
+            0
+        );
+        assert_eq!(
+            action.get_deferrable_consumption(Time::at_default_resolution(1, 59)),
+            0
+        );
+
+        // The constant action starts at 1:00 and could start up to 3:30
+        let constant = &schedule.constant_actions[&2];
+        assert_eq!(
+            constant.get_deferrable_consumption(Time::at_default_resolution(1, 0)),
+            300
+        );
+        assert_eq!(
+            constant.get_deferrable_consumption(Time::at_default_resolution(1, 1)),
+            0
+        );
+        let latest = constant
+            .get_action()
+            .clone()
+            .with_start_time(Time::at_default_resolution(3, 30));
+        assert_eq!(
+            latest.get_deferrable_consumption(Time::at_default_resolution(3, 30)),
+            0
+        );
+
+        schedule.batteries = HashMap::from([(1, battery)]);
+        schedule.variable_actions = HashMap::from([(3, action)]);
+        assert_eq!(
+            schedule.get_total_flex(Time::at_default_resolution(2, 0), 1.0),
+            50 + 50
+        );
+        assert_eq!(
+            schedule.get_total_flex(Time::at_default_resolution(1, 0), 1.0),
+            50 + 300
+        );
+        // Only the first timestep is scaled
+        assert_eq!(schedule.get_total_flex(at(0), 0.5), 25);
+    }
+
+    #[test]
+    fn test_solved_schedule_has_no_violations() {
+        let (context, schedule) = solved();
+        assert_eq!(schedule.verify(&context), vec![]);
+    }
+
+    #[test]
+    fn test_verify_detects_constant_action_outside_window() {
+        let (context, mut schedule) = solved();
+        let action = schedule.constant_actions.get_mut(&2).unwrap();
+        *action.get_start_time_mut() = Time::at_default_resolution(5, 0);
+        assert_eq!(
+            schedule.verify(&context),
+            vec![Violation::ConstantActionOutsideWindow {
+                id: 2,
+                start: Time::at_default_resolution(5, 0),
+                end: Time::at_default_resolution(5, 30),
+            }]
+        );
+
+        schedule.constant_actions.clear();
+        assert_eq!(
+            schedule.verify(&context),
+            vec![Violation::MissingConstantAction { id: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_check_shape_detects_mismatched_lengths_and_windows() {
+        let (_, schedule) = solved();
+        assert_eq!(schedule.check_shape(), Ok(()));
+
+        let mut moved = schedule.clone();
+        let action = moved.constant_actions.get_mut(&2).unwrap();
+        *action.get_start_time_mut() = Time::at_default_resolution(5, 0);
+        assert_eq!(
+            moved.check_shape(),
+            Err(ShapeError::ConstantActionOutsideWindow {
+                id: 2,
+                start: Time::at_default_resolution(5, 0),
+            })
+        );
+
+        let hourly = Prognoses::try_from(vec![0; 24]).unwrap();
+        let mut short = schedule.clone();
+        short.grid_export = hourly.clone();
+        assert_eq!(
+            short.check_shape(),
+            Err(ShapeError::LengthMismatch {
+                field: "grid_export".to_string(),
+                len: 24,
+                expected: STEPS_PER_DAY as usize,
+            })
+        );
+
+        let mut short = schedule;
+        let battery = short.batteries[&1].get_battery().clone();
+        short
+            .batteries
+            .insert(1, AssignedBattery::new(battery, hourly));
+        assert_eq!(
+            short.check_shape(),
+            Err(ShapeError::LengthMismatch {
+                field: "charge_level of battery 1".to_string(),
+                len: 24,
+                expected: STEPS_PER_DAY as usize,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_variable_action_over_max() {
+        let (context, mut schedule) = solved();
+        let action = context.get_variable_actions()[0].clone();
+        let mut consumption = vec![0; 6 * 60];
+        consumption[10] = 101;
+        consumption[11] = -1;
+        schedule
+            .variable_actions
+            .insert(3, AssignedVariableAction::new(action, consumption));
+        assert_eq!(
+            schedule.verify(&context),
+            vec![
+                Violation::VariableActionOverMax {
+                    id: 3,
+                    time: Time::at_default_resolution(2, 10),
+                    consumption: 101,
+                    max: 100,
+                },
+                Violation::VariableActionOverMax {
+                    id: 3,
+                    time: Time::at_default_resolution(2, 11),
+                    consumption: -1,
+                    max: 100,
+                },
+            ]
+        );
+
+        let shifted = Rc::new(VariableAction::new(
+            Time::at_default_resolution(3, 0),
+            Time::at_default_resolution(9, 0),
+            5_000,
+            100,
+            3,
+        ));
+        schedule
+            .variable_actions
+            .insert(3, AssignedVariableAction::new(shifted, vec![0; 6 * 60]));
+        assert_eq!(
+            schedule.verify(&context),
+            vec![Violation::VariableActionOutsideWindow {
+                id: 3,
+                start: Time::at_default_resolution(3, 0),
+                end: Time::at_default_resolution(9, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_battery_level_out_of_range() {
+        let (context, mut schedule) = solved();
+        let battery = context.get_batteries()[0].clone();
+        let levels = Prognoses::from_closure(|t| match t.to_timestep() {
+            5 => 199,
+            6 => 2_001,
+            _ => 1_000,
+        });
+        schedule.batteries.insert(
+            1,
+            AssignedBattery::new(battery, levels).with_final_charge_level(-5),
+        );
+        assert_eq!(
+            schedule.verify(&context),
+            vec![
+                Violation::BatteryLevelOutOfRange {
+                    id: 1,
+                    time: Time::from_timestep(5),
+                    level: 199,
+                },
+                Violation::BatteryLevelOutOfRange {
+                    id: 1,
+                    time: Time::from_timestep(6),
+                    level: 2_001,
+                },
+                Violation::BatteryLevelOutOfRange {
+                    id: 1,
+                    time: Time::get_day_end(),
+                    level: -5,
+                },
+            ]
+        );
+
+        schedule.batteries.clear();
+        assert_eq!(
+            schedule.verify(&context),
+            vec![Violation::MissingBattery { id: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_diff_of_shifted_copy() {
+        let (context, schedule) = solved();
+        let same = schedule.diff(&schedule.clone());
+        assert_eq!(
+            same.constant_actions,
+            vec![(
+                2,
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(1, 0)
+            )]
+        );
+        assert_eq!(same.variable_actions, vec![(3, 0)]);
+        assert_eq!(same.batteries, vec![(1, 0)]);
+        assert!(same.only_in_this.is_empty() && same.only_in_other.is_empty());
+        assert_eq!(same.stability, 1.0);
+
+        // The constant action starts half an hour later, the variable action consumes a
+        // timestep later and the battery is left out
+        let mut shifted = schedule.clone();
+        let constant = shifted.constant_actions.get_mut(&2).unwrap();
+        *constant.get_start_time_mut() = Time::at_default_resolution(1, 30);
+        let mut consumption: Vec<i64> = schedule.variable_actions[&3]
+            .iter_consumption()
+            .map(|(_, consumption)| consumption)
+            .collect();
+        consumption.rotate_right(1);
+        let l1: i64 = schedule.variable_actions[&3]
+            .iter_consumption()
+            .zip(&consumption)
+            .map(|((_, before), after)| (before - after).abs())
+            .sum();
+        assert!(l1 > 0);
+        shifted.variable_actions.insert(
+            3,
+            AssignedVariableAction::new(context.get_variable_actions()[0].clone(), consumption),
+        );
+        shifted.batteries.clear();
+
+        let diff = schedule.diff(&shifted);
+        assert_eq!(
+            diff.constant_actions,
+            vec![(
+                2,
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(1, 30)
+            )]
+        );
+        assert_eq!(diff.variable_actions, vec![(3, l1)]);
+        assert!(diff.batteries.is_empty());
+        assert_eq!(diff.only_in_this, vec![ScheduledAsset::Battery { id: 1 }]);
+        assert!(diff.only_in_other.is_empty());
+        // The slack of the constant action is 2:30, the action is assigned 5 Wh in both
+        let mean_change = (30.0 / 150.0 + l1 as f64 / 10_000.0 + 1.0) / 3.0;
+        assert!((diff.stability - (1.0 - mean_change)).abs() < 1e-12);
+
+        let reverse = shifted.diff(&schedule);
+        assert_eq!(
+            reverse.only_in_other,
+            vec![ScheduledAsset::Battery { id: 1 }]
+        );
+        assert!(reverse.only_in_this.is_empty());
+    }
+
+    #[test]
+    fn test_concat_splices_at_seam() {
+        let (context, first) = solved();
+        let seam = Time::at_default_resolution(12, 0);
+        let level = *first
+            .get_battery(1)
+            .unwrap()
+            .get_charge_level(seam)
+            .unwrap();
+        let next_with_level = |start_level: i64| {
+            let battery = AssignedBattery::new(
+                context.get_batteries()[0].clone(),
+                Prognoses::from_closure(|_| start_level),
+            )
+            .with_final_charge_level(start_level);
+            let action = |start: Time, id: u32| {
+                Rc::new(ConstantAction::new(
+                    start,
+                    Time::get_day_end(),
+                    Time::at_default_resolution(0, 30),
+                    300,
+                    id,
+                ))
+                .with_start_time(start)
+            };
+            let variable = Rc::new(VariableAction::new(
+                Time::at_default_resolution(11, 0),
+                Time::at_default_resolution(13, 0),
+                1_200,
+                10,
+                6,
+            ));
+            Schedule::new(
+                HashMap::from([
+                    (2, action(Time::at_default_resolution(1, 0), 2)),
+                    (4, action(Time::at_default_resolution(23, 0), 4)),
+                ]),
+                HashMap::from([(6, AssignedVariableAction::new(variable, vec![10; 120]))]),
+                HashMap::from([(1, battery)]),
+                Prognoses::from_closure(|_| 7),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 3),
+            )
+        };
+
+        let merged = first.concat(&next_with_level(level + 1), seam, 1).unwrap();
+        let before = Time::at_default_resolution(11, 59);
+        for (series, original, spliced) in [
+            (&merged.network_consumption, &first.network_consumption, 7),
+            (&merged.electricity_price, &first.electricity_price, 3),
+        ] {
+            assert_eq!(series.get(before), original.get(before));
+            assert_eq!(series.get(seam), Some(&spliced));
+            assert_eq!(
+                series.get(Time::at_default_resolution(23, 59)),
+                Some(&spliced)
+            );
+        }
+        let battery = merged.get_battery(1).unwrap();
+        assert_eq!(
+            battery.get_charge_level(before),
+            first.get_battery(1).unwrap().get_charge_level(before)
+        );
+        assert_eq!(battery.get_charge_level(seam), Some(&(level + 1)));
+        assert_eq!(
+            battery.get_charge_level(Time::get_day_end()),
+            Some(&(level + 1))
+        );
+        assert_eq!(
+            merged.get_constant_action(2).unwrap().get_start_time(),
+            Time::at_default_resolution(13, 0)
+        );
+        assert!(merged.get_constant_action(4).is_none());
+        // Only the first schedule plans it and it ends before the seam
+        assert_eq!(
+            merged.get_variable_action(3).unwrap().get_total_assigned(),
+            first.get_variable_action(3).unwrap().get_total_assigned()
+        );
+        let cut = merged.get_variable_action(6).unwrap();
+        assert_eq!(
+            (cut.get_start(), cut.get_end()),
+            (Time::at_default_resolution(23, 0), Time::get_day_end())
+        );
+        assert_eq!(cut.get_total_consumption(), 600);
+        assert_eq!(
+            merged.get_merge_notes(),
+            [
+                MergeNote::ConstantActionReplaced { id: 2 },
+                MergeNote::ConstantActionBeyondHorizon { id: 4 },
+                MergeNote::VariableActionCutAtEnd { id: 6 },
+            ]
+        );
+        assert!(merged.get_objective_breakdown().is_none());
+
+        assert_eq!(
+            first
+                .concat(&next_with_level(level + 2), seam, 1)
+                .unwrap_err(),
+            ConcatError::BatteryLevelMismatch {
+                id: 1,
+                level,
+                next_level: level + 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_grid_limit_exceeded() {
+        let (context, mut schedule) = solved();
+        schedule
+            .network_consumption
+            .set(Time::at_default_resolution(12, 0), 1_001);
+        assert_eq!(
+            schedule.verify(&context),
+            vec![Violation::GridLimitExceeded {
+                time: Time::at_default_resolution(12, 0),
+                consumption: 1_001,
+                limit: 1_000,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_gantt_of_hand_made_plan() {
+        // Charges until 6:00 and discharges from 18:00
+        let battery = Rc::new(Battery::new(10_000, 100, 100, 0, 1.0, 1));
+        let levels = Prognoses::from_closure(|t| {
+            let t = t.to_timestep() as i64;
+            10 * t.min(360) - 10 * (t - 1080).max(0)
+        });
+        let battery = AssignedBattery::new(battery, levels).with_final_charge_level(0);
+        let at = |hour, minutes| Time::at_default_resolution(hour, minutes);
+        let constant = |start, id| {
+            let action = ConstantAction::new(at(0, 0), at(23, 0), at(1, 0), 300, id);
+            AssignedConstantAction::new(Rc::new(action), start)
+        };
+        // Consumes during the first 40 minutes of its window
+        let variable = Rc::new(VariableAction::new(at(12, 0), at(14, 0), 400, 10, 3));
+        let consumption = (0..120).map(|t| if t < 40 { 10 } else { 0 }).collect();
+        let schedule = Schedule::new(
+            // The second action runs half of 10:00 and half of 11:00, so the ties go to '#'
+            HashMap::from([(2, constant(at(2, 0), 2)), (4, constant(at(10, 30), 4))]),
+            HashMap::from([(3, AssignedVariableAction::new(variable, consumption))]),
+            HashMap::from([(1, battery)]),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|t| (t.to_timestep() / 60) as i64),
+        );
+
+        assert_eq!(
+            schedule.gantt(24),
+            "\
+Price     ___...::---==+++**###%%@
+Battery 1 ++++++............------
+Action 2  ..#.....................
+Action 3  ............#...........
+Action 4  ..........##............
+"
+        );
+        for columns in [1, 7, 48, 1000, STEPS_PER_DAY as usize] {
+            let gantt = schedule.gantt(columns);
+            assert_eq!(gantt.lines().count(), 5);
+            assert!(
+                gantt
+                    .lines()
+                    .all(|line| line.len() == "Battery 1 ".len() + columns)
+            );
+        }
+    }
 }
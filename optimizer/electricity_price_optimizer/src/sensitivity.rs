@@ -0,0 +1,284 @@
+//! Sensitivity of the total cost to the placement of constant actions.
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    optimizer::{FlowSolveError, SmartHomeFlowBuilder},
+    optimizer_context::{OptimizerContext, action::constant::ConstantAction},
+    schedule::Schedule,
+    time::Time,
+};
+
+/// Evaluates the total cost with each constant action moved to other feasible start times.
+///
+/// For every constant action in `schedule`, the action is moved to every `stride`-th
+/// feasible start time while all other constant actions stay where they are. Start times
+/// that break a precedence with those actions or overlap an action of one of its exclusion
+/// groups aren't feasible. The currently assigned start
+/// time is always part of the curve.
+///
+/// # Returns
+/// The cost curve of every constant action as `(start time, total cost)` pairs, ordered by
+/// start time.
+///
+/// # Errors
+/// Fails if the flow can't be solved, see `SmartHomeFlow::get_cost`.
+///
+/// # Panics
+/// Panics if `stride` is 0.
+pub fn placement_sensitivity(
+    context: &OptimizerContext,
+    schedule: &Schedule,
+    stride: u32,
+) -> Result<HashMap<u32, Vec<(Time, i64)>>, FlowSolveError> {
+    assert!(stride > 0, "Stride must be at least 1");
+    let mut flow = SmartHomeFlowBuilder::from_context(context).build();
+    for action in schedule.constant_actions.values() {
+        flow.add_constant_consumption(action.clone());
+    }
+
+    let mut curves = HashMap::new();
+    for (id, assigned) in &schedule.constant_actions {
+        let action = assigned.get_action();
+        let chosen = assigned.get_start_time().to_timestep();
+        let start_of = |id: u32| schedule.constant_actions[&id].get_start_time();
+        let mut candidates: Vec<u32> = match context.get_start_bounds(action, start_of) {
+            Some((start_bound, end_bound)) => (start_bound.to_timestep()..=end_bound.to_timestep())
+                .step_by(stride as usize)
+                .filter(|&start| {
+                    !context.overlaps_excluded(action, Time::from_timestep(start), |id| {
+                        Some(start_of(id))
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if let Err(index) = candidates.binary_search(&chosen) {
+            candidates.insert(index, chosen);
+        }
+
+        let curve = candidates
+            .into_iter()
+            .map(|start| {
+                let start = Time::from_timestep(start);
+                flow.add_constant_consumption(action.clone().with_start_time(start));
+                Ok((start, flow.get_cost()?))
+            })
+            .collect::<Result<_, FlowSolveError>>()?;
+        // Put the action back so the next one is evaluated against the original schedule
+        flow.add_constant_consumption(assigned.clone());
+        curves.insert(*id, curve);
+    }
+    Ok(curves)
+}
+
+/// Finds the cheapest start time for an additional constant action on top of `schedule`.
+///
+/// The constant actions of `schedule` stay where they are while `action` is placed at every
+/// feasible start time of its window. Start times at which more consumption can't be served
+/// than without `action`, e.g. because of the grid limit, are skipped.
+///
+/// # Returns
+/// The cheapest start time and the marginal cost of `action` there, i.e. how much the total
+/// cost rises over that of `schedule`. Ties go to the earliest start. `None` if no start time
+/// can serve `action`.
+///
+/// # Errors
+/// Fails if the flow can't be solved, see `SmartHomeFlow::get_cost`.
+///
+/// # Panics
+/// Panics if `schedule` already has a constant action with the ID of `action`.
+pub fn cheapest_additional_slot(
+    context: &OptimizerContext,
+    schedule: &Schedule,
+    action: Rc<ConstantAction>,
+) -> Result<Option<(Time, i64)>, FlowSolveError> {
+    assert!(
+        !schedule.constant_actions.contains_key(&action.get_id()),
+        "Constant action {} is already part of the schedule",
+        action.get_id()
+    );
+    let mut flow = SmartHomeFlowBuilder::from_context(context).build();
+    for assigned in schedule.constant_actions.values() {
+        flow.add_constant_consumption(assigned.clone());
+    }
+    let base_cost = flow.get_cost()?;
+    let base_unserved = flow.get_unserved_consumption()?;
+
+    let start_bound = action.get_start_from().to_timestep();
+    let end_bound = action.get_end_before().to_timestep();
+    let duration = action.duration.to_timestep();
+    let mut best: Option<(Time, i64)> = None;
+    for start in (start_bound..end_bound.saturating_sub(duration) + 1).map(Time::from_timestep) {
+        flow.add_constant_consumption(action.clone().with_start_time(start));
+        if flow.get_unserved_consumption()? > base_unserved {
+            continue;
+        }
+        let marginal_cost = flow.get_cost()? - base_cost;
+        if best.is_none_or(|(_, best_cost)| marginal_cost < best_cost) {
+            best = Some((start, marginal_cost));
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        optimizer_context::{action::constant::ConstantAction, prognoses::Prognoses},
+        simulated_annealing::{AnnealingConfig, Cooling, run_simulated_annealing_with_config},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_curve_minimum_is_chosen_start() {
+        // Two price valleys at 0:30 and 2:00, each action fits into one of them.
+        let electricity_price = Prognoses::from_closure(|t| {
+            let minutes = t.to_timestep() as i64;
+            match minutes {
+                0..60 => (minutes - 30).abs() + 5,
+                60..180 => (minutes - 120).abs() + 5,
+                _ => 100,
+            }
+        });
+        let constant_actions = vec![
+            Rc::new(ConstantAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(0, 21),
+                100,
+                1,
+            )),
+            Rc::new(ConstantAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(3, 0),
+                Time::at_default_resolution(0, 31),
+                100,
+                2,
+            )),
+        ];
+        let context = OptimizerContext::new(
+            electricity_price,
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            constant_actions,
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.99 },
+            ..Default::default()
+        };
+        let (cost, schedule, _) =
+            run_simulated_annealing_with_config(context.clone(), &config, Some(5), None);
+
+        let curves = placement_sensitivity(&context, &schedule, 1).unwrap();
+        assert_eq!(curves[&1].len(), 40);
+        for (id, curve) in &curves {
+            let chosen = schedule.get_constant_action(*id).unwrap().get_start_time();
+            let (best_start, best_cost) = curve.iter().min_by_key(|(_, cost)| *cost).unwrap();
+            assert_eq!(*best_start, chosen);
+            assert_eq!(*best_cost, cost);
+        }
+        assert_eq!(
+            schedule.get_constant_action(1).unwrap().get_start_time(),
+            Time::at_default_resolution(0, 20)
+        );
+        assert_eq!(
+            schedule.get_constant_action(2).unwrap().get_start_time(),
+            Time::at_default_resolution(1, 45)
+        );
+
+        let coarse = placement_sensitivity(&context, &schedule, 15).unwrap();
+        let starts: Vec<Time> = coarse[&1].iter().map(|(start, _)| *start).collect();
+        assert_eq!(
+            starts,
+            vec![
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(0, 15),
+                Time::at_default_resolution(0, 20),
+                Time::at_default_resolution(0, 30)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_additional_slot_avoids_existing_load() {
+        // Cheapest from 1:00 to 2:00, where a fixed action already runs into the grid limit
+        let electricity_price = Prognoses::from_closure(|t| {
+            if (Time::at_default_resolution(1, 0)..Time::at_default_resolution(2, 0)).contains(&t) {
+                10
+            } else {
+                20 + t.to_timestep() as i64 % 7
+            }
+        });
+        let fixed = Rc::new(ConstantAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(2, 0),
+            Time::at_default_resolution(1, 0),
+            300,
+            1,
+        ));
+        let context = OptimizerContext::new(
+            electricity_price,
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![fixed.clone()],
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(400);
+        // Only the constant actions of the schedule are used
+        let schedule = Schedule::new(
+            HashMap::from([(1, fixed.with_start_time(Time::at_default_resolution(1, 0)))]),
+            HashMap::new(),
+            HashMap::new(),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+        );
+
+        // 200 per timestep doesn't fit next to the fixed action
+        let extra = Rc::new(ConstantAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(3, 0),
+            Time::at_default_resolution(0, 10),
+            200,
+            2,
+        ));
+        let (start, cost) = cheapest_additional_slot(&context, &schedule, extra)
+            .unwrap()
+            .unwrap();
+        // Cheapest ten minutes that don't overlap the fixed action
+        let (expected_start, expected_cost) = (0..=170)
+            .filter(|minute| !(51..120).contains(minute))
+            .map(|minute| {
+                let cost: i64 = (minute..minute + 10)
+                    .map(|m| 200 * (20 + m as i64 % 7))
+                    .sum();
+                (Time::at_default_resolution(0, minute), cost)
+            })
+            .min_by_key(|(_, cost)| *cost)
+            .unwrap();
+        assert_eq!((start, cost), (expected_start, expected_cost));
+
+        // Without room anywhere, there is no slot
+        let too_large = Rc::new(ConstantAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(2, 0),
+            Time::at_default_resolution(0, 10),
+            200,
+            2,
+        ));
+        assert_eq!(
+            cheapest_additional_slot(&context, &schedule, too_large).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+/// Settings for adapting the number of moves combined into one annealing iteration.
+///
+/// Every `window` iterations the acceptance rate of that window is compared against
+/// the target band: if too many changes are accepted the moves are too timid and the
+/// size grows, if too few are accepted the size shrinks. When a single flow solve is
+/// more expensive than `expensive_flow_solve`, the size is never shrunk below
+/// `expensive_min_moves`, since each evaluation should then cover more ground.
+#[derive(Debug, Clone)]
+pub struct AdaptiveMoveConfig {
+    /// Smallest number of moves per iteration.
+    pub min_moves: usize,
+    /// Largest number of moves per iteration.
+    pub max_moves: usize,
+    /// Acceptance rate below which the move size is decreased.
+    pub min_acceptance: f64,
+    /// Acceptance rate above which the move size is increased.
+    pub max_acceptance: f64,
+    /// Number of iterations between adjustments.
+    pub window: usize,
+    /// Average flow solve time from which on solves count as expensive.
+    pub expensive_flow_solve: Duration,
+    /// Lower bound of the move size while flow solves are expensive.
+    pub expensive_min_moves: usize,
+}
+
+impl Default for AdaptiveMoveConfig {
+    fn default() -> Self {
+        Self {
+            min_moves: 1,
+            max_moves: 8,
+            min_acceptance: 0.2,
+            max_acceptance: 0.5,
+            window: 50,
+            expensive_flow_solve: Duration::from_millis(50),
+            expensive_min_moves: 2,
+        }
+    }
+}
+
+/// Tracks acceptance during a window of iterations and picks the next move size.
+pub(crate) struct MoveSizeController {
+    config: AdaptiveMoveConfig,
+    size: usize,
+    iterations: usize,
+    accepted: usize,
+}
+
+impl MoveSizeController {
+    pub(crate) fn new(config: AdaptiveMoveConfig, initial_size: usize) -> Self {
+        assert!(
+            1 <= config.min_moves && config.min_moves <= config.max_moves,
+            "Invalid adaptive move bounds: {}..={}",
+            config.min_moves,
+            config.max_moves
+        );
+        let size = initial_size.clamp(config.min_moves, config.max_moves);
+        Self {
+            config,
+            size,
+            iterations: 0,
+            accepted: 0,
+        }
+    }
+
+    /// Number of moves to combine in the next iteration.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Records the outcome of an iteration.
+    /// Returns the new size if the window is complete and the size changed.
+    pub(crate) fn record(&mut self, accepted: bool, average_flow_solve: Duration) -> Option<usize> {
+        self.iterations += 1;
+        if accepted {
+            self.accepted += 1;
+        }
+        if self.iterations < self.config.window {
+            return None;
+        }
+        let acceptance = self.accepted as f64 / self.iterations as f64;
+        self.iterations = 0;
+        self.accepted = 0;
+
+        let mut min_moves = self.config.min_moves;
+        if average_flow_solve >= self.config.expensive_flow_solve {
+            min_moves = self.config.expensive_min_moves.max(min_moves);
+        }
+        let new_size = if acceptance > self.config.max_acceptance {
+            self.size + 1
+        } else if acceptance < self.config.min_acceptance {
+            self.size.saturating_sub(1)
+        } else {
+            self.size
+        }
+        .clamp(min_moves, self.config.max_moves.max(min_moves));
+
+        if new_size != self.size {
+            self.size = new_size;
+            Some(new_size)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(initial_size: usize) -> MoveSizeController {
+        MoveSizeController::new(
+            AdaptiveMoveConfig {
+                window: 10,
+                ..Default::default()
+            },
+            initial_size,
+        )
+    }
+
+    #[test]
+    fn test_move_size_follows_acceptance_band() {
+        let mut controller = controller(2);
+        let cheap = Duration::from_millis(1);
+        for _ in 0..9 {
+            assert_eq!(controller.record(true, cheap), None);
+        }
+        assert_eq!(controller.record(true, cheap), Some(3));
+
+        for _ in 0..10 {
+            controller.record(false, cheap);
+        }
+        assert_eq!(controller.size(), 2);
+        for _ in 0..10 {
+            controller.record(false, cheap);
+        }
+        assert_eq!(controller.size(), 1);
+        for _ in 0..10 {
+            controller.record(false, cheap);
+        }
+        assert_eq!(controller.size(), 1);
+    }
+
+    #[test]
+    fn test_expensive_solves_keep_composite_moves() {
+        let mut controller = controller(2);
+        for _ in 0..10 {
+            controller.record(false, Duration::from_secs(1));
+        }
+        assert_eq!(controller.size(), 2);
+    }
+
+    #[test]
+    fn test_move_size_is_bounded() {
+        let mut controller = controller(20);
+        assert_eq!(controller.size(), 8);
+        for _ in 0..10 {
+            controller.record(true, Duration::ZERO);
+        }
+        assert_eq!(controller.size(), 8);
+    }
+}
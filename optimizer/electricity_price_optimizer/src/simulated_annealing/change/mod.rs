@@ -1,9 +1,67 @@
 pub mod multi_change;
 mod random_helpers;
 mod random_move;
+use std::ops::{Deref, DerefMut};
+
 use crate::simulated_annealing::state::State;
 
 pub trait Change {
     fn apply(&self, state: &mut State);
     fn undo(&self, state: &mut State);
 }
+
+/// Applies a change to a state and undoes it again when dropped, unless it was committed.
+///
+/// This keeps apply/undo balanced on every exit path. If the guard is dropped while
+/// unwinding from a panic, undoing isn't attempted; the state is marked poisoned instead.
+/// The same happens if applying or undoing the change panics halfway through.
+pub struct ChangeGuard<'a, C: Change> {
+    state: &'a mut State,
+    change: C,
+    committed: bool,
+}
+
+impl<'a, C: Change> ChangeGuard<'a, C> {
+    pub fn apply(state: &'a mut State, change: C) -> Self {
+        state.poison();
+        change.apply(state);
+        state.unpoison();
+        Self {
+            state,
+            change,
+            committed: false,
+        }
+    }
+
+    /// Keeps the change applied.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl<C: Change> Deref for ChangeGuard<'_, C> {
+    type Target = State;
+
+    fn deref(&self) -> &Self::Target {
+        self.state
+    }
+}
+
+impl<C: Change> DerefMut for ChangeGuard<'_, C> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.state
+    }
+}
+
+impl<C: Change> Drop for ChangeGuard<'_, C> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        self.state.poison();
+        if !std::thread::panicking() {
+            self.change.undo(self.state);
+            self.state.unpoison();
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::simulated_annealing::{
     change::{Change, random_move::RandomMoveChange},
     state::State,
@@ -29,8 +31,12 @@ impl MultiChange {
         num_changes: usize,
     ) -> Self {
         let mut changes: Vec<Box<dyn Change>> = Vec::new();
+        // Later moves have to keep the precedences with the actions moved before them.
+        let mut pending = HashMap::new();
         for _ in 0..num_changes {
-            let change = RandomMoveChange::new_random(rng, state, random_move_sigma);
+            let change = RandomMoveChange::new_random(rng, state, random_move_sigma, &pending);
+            let (action_id, new_time) = change.get_move();
+            pending.insert(action_id, new_time);
             changes.push(Box::new(change));
         }
         Self { changes }
@@ -6,6 +6,11 @@ use crate::{
     time::Time,
 };
 use rand::{Rng, seq::IndexedRandom};
+use std::collections::HashMap;
+
+/// How often a move is resampled because it overlaps an action of an exclusion group before
+/// the action is left where it is.
+const MAX_RESAMPLES: usize = 64;
 
 pub struct RandomMoveChange {
     action_id: u32,
@@ -20,11 +25,6 @@ impl Change for RandomMoveChange {
             .clone()
             .with_start_time(self.new_time);
         state.add_constant_action(new_action);
-
-        println!(
-            "Moved action {} from {:?} to {:?}",
-            self.action_id, self.old_time, self.new_time
-        );
     }
     fn undo(&self, state: &mut State) {
         let new_action = state.remove_constant_action(self.action_id).unwrap();
@@ -33,53 +33,88 @@ impl Change for RandomMoveChange {
             .clone()
             .with_start_time(self.old_time);
         state.add_constant_action(old_action);
-
-        println!(
-            "Reverted action {} from {:?} to {:?}",
-            self.action_id, self.new_time, self.old_time
-        );
     }
 }
 
 impl RandomMoveChange {
-    pub fn new_random<R: Rng>(rng: &mut R, state: &State, sigma: f64) -> Self {
+    /// Moves a random constant action, keeping the precedences with the other actions and
+    /// apart from the actions of its exclusion groups. Start times that overlap one of those
+    /// are resampled. `pending` holds the start times of actions moved by changes that are
+    /// applied before this one. If the precedences leave the action no room, or no free start
+    /// time is found, the change doesn't move it.
+    pub fn new_random<R: Rng>(
+        rng: &mut R,
+        state: &State,
+        sigma: f64,
+        pending: &HashMap<u32, Time>,
+    ) -> Self {
         // let constant_actions = state.get_constant_actions();
         // let action_index = rng.random_range(0..constant_actions.len());
         // let action = &constant_actions[action_index];
         // let action_ref = action.get_action();
-        // let old_time = action.get_start_time().get_minutes() as u32;
-        // let start_bound = action_ref.get_start_from().get_minutes() as u32;
+        // let old_time = action.get_start_time().to_timestep() as u32;
+        // let start_bound = action_ref.get_start_from().to_timestep() as u32;
         // let end_bound =
-        //     (action_ref.get_end_before().get_minutes() - action_ref.duration.get_minutes()) as u32;
+        //     (action_ref.get_end_before().to_timestep() - action_ref.duration.to_timestep()) as u32;
         // let mut new_time = old_time;
         // while new_time == old_time {
         //     new_time = sample_centered_int(start_bound, end_bound, old_time, sigma, rng);
         // }
         // Self {
         //     action_index,
-        //     old_time: Time::new(0, old_time),
-        //     new_time: Time::new(0, new_time),
+        //     old_time: Time::from_timestep(old_time),
+        //     new_time: Time::from_timestep(new_time),
         // }
         // new version
         let constant_action_ids = state.get_constant_action_ids();
-        let action_id = constant_action_ids
+        let action_id = *constant_action_ids
             .choose(rng)
-            .expect("No constant actions available")
-            .clone();
-        let action = state.get_constant_action(action_id);
-        let old_time = action.get_start_time().get_minutes() as u32;
-        let start_bound = action.get_start_from().get_minutes() as u32;
-        let end_bound = (action.get_end_before().get_minutes()
-            - action.get_action().duration.get_minutes()) as u32;
+            .expect("No constant actions available");
+        let start_of = |id: u32| {
+            pending
+                .get(&id)
+                .copied()
+                .unwrap_or_else(|| state.get_constant_action(id).get_start_time())
+        };
+        let old_time = start_of(action_id).to_timestep();
+        let Some((start_bound, end_bound)) = state.get_start_bounds(action_id, start_of) else {
+            return Self::unmoved(action_id, old_time);
+        };
+        let (start_bound, end_bound) = (start_bound.to_timestep(), end_bound.to_timestep());
+        if start_bound == end_bound {
+            return Self::unmoved(action_id, old_time);
+        }
 
+        let excluded =
+            |time: u32| state.overlaps_excluded(action_id, Time::from_timestep(time), start_of);
         let mut new_time = old_time;
-        while new_time == old_time {
+        let mut resamples = 0;
+        while new_time == old_time || excluded(new_time) {
+            if new_time != old_time {
+                resamples += 1;
+                if resamples > MAX_RESAMPLES {
+                    return Self::unmoved(action_id, old_time);
+                }
+            }
             new_time = sample_centered_int(start_bound, end_bound, old_time, sigma, rng);
         }
         Self {
             action_id,
-            old_time: Time::new(0, old_time),
-            new_time: Time::new(0, new_time),
+            old_time: Time::from_timestep(old_time),
+            new_time: Time::from_timestep(new_time),
         }
     }
+
+    fn unmoved(action_id: u32, time: u32) -> Self {
+        Self {
+            action_id,
+            old_time: Time::from_timestep(time),
+            new_time: Time::from_timestep(time),
+        }
+    }
+
+    /// Returns the id of the moved action and its new start time.
+    pub fn get_move(&self) -> (u32, Time) {
+        (self.action_id, self.new_time)
+    }
 }
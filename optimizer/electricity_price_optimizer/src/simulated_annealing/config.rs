@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
+use crate::{
+    optimizer::DEFAULT_MAX_ITERATIONS,
+    simulated_annealing::{adaptive::AdaptiveMoveConfig, cooling::Cooling},
+};
+
+/// Settings of the simulated annealing loop.
+#[derive(Debug, Clone)]
+pub struct AnnealingConfig {
+    /// Temperature at the start of the run.
+    pub initial_temperature: f64,
+    /// Lowest temperature of the run. Geometric cooling stops below it,
+    /// linear cooling ends at it.
+    pub min_temperature: f64,
+    /// How the temperature develops and when the run ends.
+    pub cooling: Cooling,
+    /// Number of constant action moves combined into one iteration.
+    /// Used as is when `adaptive_moves` is `None`, otherwise as the starting size.
+    pub moves_per_iteration: usize,
+    /// Adapts the number of moves per iteration to the acceptance rate when set.
+    pub adaptive_moves: Option<AdaptiveMoveConfig>,
+    /// Optional wall-time budget. The run stops early once it is exceeded.
+    pub time_limit: Option<Duration>,
+    /// Optional cancellation flag, e.g. set from another thread. The run stops early once it
+    /// is set and returns the best schedule seen, see [`SolveStats::cancelled`].
+    ///
+    /// [`SolveStats::cancelled`]: crate::simulated_annealing::SolveStats::cancelled
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Break the cost of the returned schedule down into its parts, see
+    /// [`Schedule::get_objective_breakdown`](crate::schedule::Schedule::get_objective_breakdown).
+    pub explain_cost: bool,
+    /// Keep the solved flow network of the returned schedule, see
+    /// [`Schedule::get_flow_table`](crate::schedule::Schedule::get_flow_table).
+    pub capture_flow: bool,
+    /// Start timesteps of constant actions to begin with instead of random ones, e.g. those of
+    /// the schedule of a previous run shifted to the new context. Starts outside the window of
+    /// their action are clamped into it, ids the context doesn't know are ignored. Only used
+    /// when a [`Solver`](crate::simulated_annealing::Solver) is created.
+    pub initial_starts: HashMap<u32, i64>,
+    /// Iterations every solve of the flow may take, see
+    /// [`SmartHomeFlowBuilder::with_max_iterations`](crate::optimizer::SmartHomeFlowBuilder::with_max_iterations).
+    pub flow_max_iterations: u64,
+}
+
+impl AnnealingConfig {
+    /// Iterations the cooling schedule runs for. A run stops earlier when it is cancelled or
+    /// reaches its time limit.
+    pub fn planned_iterations(&self) -> usize {
+        let mut cooling = self
+            .cooling
+            .start(self.initial_temperature, self.min_temperature);
+        let mut iterations = 0;
+        while !cooling.is_finished() {
+            cooling.advance(false);
+            iterations += 1;
+        }
+        iterations
+    }
+}
+
+impl Default for AnnealingConfig {
+    fn default() -> Self {
+        Self {
+            initial_temperature: 40.0,
+            min_temperature: 0.1,
+            cooling: Cooling::default(),
+            moves_per_iteration: 2,
+            adaptive_moves: None,
+            time_limit: None,
+            cancel: None,
+            explain_cost: false,
+            capture_flow: false,
+            initial_starts: HashMap::new(),
+            flow_max_iterations: DEFAULT_MAX_ITERATIONS,
+        }
+    }
+}
+
+/// The settings of a run that can be stored, e.g. to repeat it later. Unlike
+/// [`AnnealingConfig`], it holds no state of a particular run like a cancellation flag or
+/// initial starts.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolveOptions {
+    /// Seed of the random number generator, see [`Solver::new`](crate::simulated_annealing::Solver::new).
+    pub seed: Option<u64>,
+    /// See [`AnnealingConfig::initial_temperature`].
+    pub initial_temperature: f64,
+    /// See [`AnnealingConfig::min_temperature`].
+    pub min_temperature: f64,
+    /// See [`AnnealingConfig::cooling`].
+    pub cooling: Cooling,
+    /// See [`AnnealingConfig::moves_per_iteration`].
+    pub moves_per_iteration: usize,
+    /// See [`AnnealingConfig::explain_cost`].
+    pub explain_cost: bool,
+    /// See [`AnnealingConfig::capture_flow`].
+    pub capture_flow: bool,
+    /// See [`AnnealingConfig::flow_max_iterations`].
+    pub flow_max_iterations: u64,
+}
+
+impl SolveOptions {
+    /// The config of a run with these options. Everything else is left at its default.
+    pub fn annealing_config(&self) -> AnnealingConfig {
+        AnnealingConfig {
+            initial_temperature: self.initial_temperature,
+            min_temperature: self.min_temperature,
+            cooling: self.cooling.clone(),
+            moves_per_iteration: self.moves_per_iteration,
+            explain_cost: self.explain_cost,
+            capture_flow: self.capture_flow,
+            flow_max_iterations: self.flow_max_iterations,
+            ..AnnealingConfig::default()
+        }
+    }
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        let config = AnnealingConfig::default();
+        Self {
+            seed: None,
+            initial_temperature: config.initial_temperature,
+            min_temperature: config.min_temperature,
+            cooling: config.cooling,
+            moves_per_iteration: config.moves_per_iteration,
+            explain_cost: config.explain_cost,
+            capture_flow: config.capture_flow,
+            flow_max_iterations: config.flow_max_iterations,
+        }
+    }
+}
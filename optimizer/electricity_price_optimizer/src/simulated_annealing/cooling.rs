@@ -0,0 +1,260 @@
+/// Decides the temperature of every annealing iteration and when the run ends.
+pub trait CoolingSchedule {
+    /// Temperature of the current iteration.
+    fn temperature(&self) -> f64;
+    /// Moves on to the next iteration. `accepted` tells whether the change of the
+    /// iteration that just ended was accepted.
+    fn advance(&mut self, accepted: bool);
+    /// True once the run should stop.
+    fn is_finished(&self) -> bool;
+}
+
+/// Selects a [`CoolingSchedule`] and its parameters.
+///
+/// The schedules start at [`AnnealingConfig::initial_temperature`](super::AnnealingConfig).
+/// `Geometric` stops below the minimum temperature, all others after a fixed number of
+/// iterations.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Cooling {
+    /// Multiplies the temperature by `factor` after every iteration.
+    Geometric { factor: f64 },
+    /// Lowers the temperature in equal steps, reaching the minimum temperature after
+    /// `iterations` iterations.
+    Linear { iterations: usize },
+    /// Lowers the temperature proportional to `1 / ln(k + 2)` in iteration `k`.
+    Logarithmic { iterations: usize },
+    /// Adapts the temperature so the acceptance rate follows the target curve of Lam and
+    /// Delosme: high at the start, 44% for most of the run and falling towards the end.
+    Lam { iterations: usize },
+}
+
+impl Default for Cooling {
+    fn default() -> Self {
+        Cooling::Geometric { factor: 0.999 }
+    }
+}
+
+impl Cooling {
+    /// Creates the schedule for a run from `initial_temperature` down to `min_temperature`.
+    ///
+    /// # Panics
+    /// If the geometric factor is not in `(0, 1)` or an iteration count is zero.
+    pub fn start(
+        &self,
+        initial_temperature: f64,
+        min_temperature: f64,
+    ) -> Box<dyn CoolingSchedule> {
+        match *self {
+            Cooling::Geometric { factor } => {
+                assert!(
+                    0.0 < factor && factor < 1.0,
+                    "Geometric cooling factor must be in (0, 1), got {factor}"
+                );
+                Box::new(GeometricCooling {
+                    temperature: initial_temperature,
+                    min_temperature,
+                    factor,
+                })
+            }
+            Cooling::Linear { iterations } => Box::new(LinearCooling {
+                initial_temperature,
+                min_temperature,
+                iterations: check_iterations(iterations),
+                iteration: 0,
+            }),
+            Cooling::Logarithmic { iterations } => Box::new(LogarithmicCooling {
+                initial_temperature,
+                iterations: check_iterations(iterations),
+                iteration: 0,
+            }),
+            Cooling::Lam { iterations } => Box::new(LamCooling {
+                temperature: initial_temperature,
+                acceptance: LamCooling::target_acceptance(0.0),
+                iterations: check_iterations(iterations),
+                iteration: 0,
+            }),
+        }
+    }
+}
+
+fn check_iterations(iterations: usize) -> usize {
+    assert!(
+        iterations > 0,
+        "Cooling schedule needs at least one iteration"
+    );
+    iterations
+}
+
+struct GeometricCooling {
+    temperature: f64,
+    min_temperature: f64,
+    factor: f64,
+}
+
+impl CoolingSchedule for GeometricCooling {
+    fn temperature(&self) -> f64 {
+        self.temperature
+    }
+    fn advance(&mut self, _accepted: bool) {
+        self.temperature *= self.factor;
+    }
+    fn is_finished(&self) -> bool {
+        self.temperature <= self.min_temperature
+    }
+}
+
+struct LinearCooling {
+    initial_temperature: f64,
+    min_temperature: f64,
+    iterations: usize,
+    iteration: usize,
+}
+
+impl CoolingSchedule for LinearCooling {
+    fn temperature(&self) -> f64 {
+        let progress = self.iteration as f64 / self.iterations as f64;
+        self.initial_temperature + (self.min_temperature - self.initial_temperature) * progress
+    }
+    fn advance(&mut self, _accepted: bool) {
+        self.iteration += 1;
+    }
+    fn is_finished(&self) -> bool {
+        self.iteration >= self.iterations
+    }
+}
+
+struct LogarithmicCooling {
+    initial_temperature: f64,
+    iterations: usize,
+    iteration: usize,
+}
+
+impl CoolingSchedule for LogarithmicCooling {
+    fn temperature(&self) -> f64 {
+        // Scaled so iteration 0 starts at the initial temperature
+        self.initial_temperature * 2f64.ln() / (self.iteration as f64 + 2.0).ln()
+    }
+    fn advance(&mut self, _accepted: bool) {
+        self.iteration += 1;
+    }
+    fn is_finished(&self) -> bool {
+        self.iteration >= self.iterations
+    }
+}
+
+/// Weight of the latest iteration in the running acceptance rate.
+const LAM_SMOOTHING: f64 = 0.01;
+/// Factor the temperature is changed by per iteration to steer the acceptance rate.
+const LAM_STEP: f64 = 0.995;
+
+struct LamCooling {
+    temperature: f64,
+    /// Exponentially smoothed acceptance rate.
+    acceptance: f64,
+    iterations: usize,
+    iteration: usize,
+}
+
+impl LamCooling {
+    /// Acceptance rate the schedule aims for at `progress` (0 at the start, 1 at the end).
+    fn target_acceptance(progress: f64) -> f64 {
+        if progress < 0.15 {
+            0.44 + 0.56 * 560f64.powf(-progress / 0.15)
+        } else if progress < 0.65 {
+            0.44
+        } else {
+            0.44 * 440f64.powf(-(progress - 0.65) / 0.35)
+        }
+    }
+}
+
+impl CoolingSchedule for LamCooling {
+    fn temperature(&self) -> f64 {
+        self.temperature
+    }
+    fn advance(&mut self, accepted: bool) {
+        let accepted = if accepted { 1.0 } else { 0.0 };
+        self.acceptance += LAM_SMOOTHING * (accepted - self.acceptance);
+        self.iteration += 1;
+        let target = Self::target_acceptance(self.iteration as f64 / self.iterations as f64);
+        if self.acceptance > target {
+            self.temperature *= LAM_STEP;
+        } else {
+            self.temperature /= LAM_STEP;
+        }
+    }
+    fn is_finished(&self) -> bool {
+        self.iteration >= self.iterations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trajectory(schedule: &mut dyn CoolingSchedule, accepted: bool) -> Vec<f64> {
+        let mut temperatures = Vec::new();
+        while !schedule.is_finished() {
+            temperatures.push(schedule.temperature());
+            schedule.advance(accepted);
+        }
+        temperatures
+    }
+
+    #[test]
+    fn test_geometric_trajectory() {
+        let mut schedule = Cooling::Geometric { factor: 0.5 }.start(8.0, 0.9);
+        assert_eq!(trajectory(&mut *schedule, true), vec![8.0, 4.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn test_linear_trajectory() {
+        let mut schedule = Cooling::Linear { iterations: 4 }.start(9.0, 1.0);
+        assert_eq!(trajectory(&mut *schedule, true), vec![9.0, 7.0, 5.0, 3.0]);
+        assert_eq!(schedule.temperature(), 1.0);
+    }
+
+    #[test]
+    fn test_logarithmic_trajectory() {
+        let mut schedule = Cooling::Logarithmic { iterations: 100 }.start(10.0, 0.1);
+        let temperatures = trajectory(&mut *schedule, true);
+        assert_eq!(temperatures.len(), 100);
+        assert_eq!(temperatures[0], 10.0);
+        assert!((temperatures[2] - 5.0).abs() < 1e-9);
+        assert!(temperatures.windows(2).all(|pair| pair[1] < pair[0]));
+        // Cools much slower than geometrically
+        assert!(temperatures[99] > 1.0);
+    }
+
+    #[test]
+    fn test_lam_steers_towards_target_acceptance() {
+        // Once the running acceptance rate has fallen below the 44% plateau, the schedule heats up
+        let mut rejecting = Cooling::Lam { iterations: 1000 }.start(10.0, 0.1);
+        let temperatures = trajectory(&mut *rejecting, false);
+        assert_eq!(temperatures.len(), 1000);
+        assert!(
+            temperatures[150..650]
+                .windows(2)
+                .all(|pair| pair[1] > pair[0])
+        );
+
+        let mut accepting = Cooling::Lam { iterations: 1000 }.start(10.0, 0.1);
+        let temperatures = trajectory(&mut *accepting, true);
+        assert!(temperatures.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn test_lam_target_curve() {
+        assert!((LamCooling::target_acceptance(0.0) - 1.0).abs() < 1e-9);
+        assert!((LamCooling::target_acceptance(0.15) - 0.44).abs() < 1e-9);
+        assert_eq!(LamCooling::target_acceptance(0.4), 0.44);
+        assert!((LamCooling::target_acceptance(1.0) - 0.001).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Geometric cooling factor")]
+    fn test_geometric_factor_must_cool() {
+        Cooling::Geometric { factor: 1.0 }.start(10.0, 0.1);
+    }
+}
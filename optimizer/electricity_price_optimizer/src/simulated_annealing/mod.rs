@@ -1,16 +1,20 @@
-use rand::Rng;
+use std::convert::Infallible;
 
-use crate::{
-    optimizer_context::OptimizerContext,
-    schedule::{self, Schedule},
-    simulated_annealing::{
-        change::{Change, multi_change::MultiChange},
-        state::State,
-    },
-};
+use crate::{optimizer_context::OptimizerContext, schedule::Schedule};
 
+pub use adaptive::AdaptiveMoveConfig;
+pub use config::{AnnealingConfig, SolveOptions};
+pub use cooling::{Cooling, CoolingSchedule};
+pub use solver::{Progress, SolveError, Solver};
+pub use stats::SolveStats;
+
+mod adaptive;
 mod change;
+mod config;
+mod cooling;
+mod solver;
 pub mod state;
+mod stats;
 /// Runs the simulated annealing algorithm to optimize electricity usage and costs.
 ///
 /// This function takes an `OptimizerContext` containing the necessary data such as
@@ -21,27 +25,40 @@ pub mod state;
 ///
 /// # Parameters
 /// - `context`: An `OptimizerContext` instance containing all the required data for optimization.
+/// - `seed`: Seed for the random number generator. Runs with the same seed and context produce
+///   identical costs and schedules. With `None` a fresh random generator is used.
+/// - `progress`: Called once per iteration with the current [`Progress`]. Nothing is printed,
+///   so this is the only way to follow a running optimization.
 ///
 /// # Returns
-/// The result of the simulated annealing process, which could be a schedule or a cost value,
-/// depending on the implementation.
+/// The lowest cost seen during the run and its schedule. This is not necessarily the state
+/// the annealing ended in, as uphill moves are accepted until the end.
 ///
 /// # Example
-/// ```
+/// ```no_run
+/// # use std::rc::Rc;
+/// # use electricity_price_optimizer::optimizer_context::{
+/// #     OptimizerContext,
+/// #     action::{constant::ConstantAction, variable::VariableAction},
+/// #     battery::Battery,
+/// #     prognoses::Prognoses,
+/// # };
+/// # use electricity_price_optimizer::simulated_annealing::run_simulated_annealing;
+/// # use electricity_price_optimizer::time::{STEPS_PER_DAY, Time};
 /// let electricity_price_data = [10; STEPS_PER_DAY as usize];
 /// let generated_electricity_data = [100; STEPS_PER_DAY as usize];
 /// let beyond_control_consumption_data = [20; STEPS_PER_DAY as usize];
-/// let batteries = vec![Battery::new(1000, 10, 10, 7, 1.0, 1)];
+/// let batteries = vec![Rc::new(Battery::new(1000, 10, 10, 7, 1.0, 1))];
 /// let constant_actions = vec![Rc::new(ConstantAction::new(
-///     Time::new(0, 0),
-///     Time::new(2, 0),
-///     Time::new(1, 0),
+///     Time::at_default_resolution(0, 0),
+///     Time::at_default_resolution(2, 0),
+///     Time::at_default_resolution(1, 0),
 ///     300,
 ///     2,
 /// ))];
 /// let variable_actions = vec![Rc::new(VariableAction::new(
-///     Time::new(1, 15),
-///     Time::new(10, 0),
+///     Time::at_default_resolution(1, 15),
+///     Time::at_default_resolution(10, 0),
 ///     300,
 ///     100,
 ///     3,
@@ -54,9 +71,10 @@ pub mod state;
 ///     batteries,
 ///     constant_actions,
 ///     variable_actions,
-/// );
-/// let result = run_simulated_annealing(context);
-/// println!("Optimization result: {result}");
+///     1.0,
+/// ).unwrap();
+/// let (cost, schedule) = run_simulated_annealing(context, Some(42), None);
+/// println!("Optimization result: {cost}");
 /// ```
 ///
 /// # Notes
@@ -65,70 +83,55 @@ pub mod state;
 ///   a good approximation within a reasonable time frame.
 ///
 /// # Panics
-/// This function may panic if the `OptimizerContext` contains invalid or inconsistent data.
-pub fn run_simulated_annealing(context: OptimizerContext) -> (i64, Schedule) {
-    let mut rng = rand::rng();
-
-    let mut state = State::new_random(context, &mut rng);
-    let mut temperature: f64 = 40.0;
-
-    let mut old_cost = state.get_cost();
-    let mut n_iterations = 0;
-    let mut min_cost = old_cost;
-    while temperature > 0.1 {
-        n_iterations += 1;
-        // Determine random_move_sigma based on temperature
-        let random_move_sigma = 30.0 * temperature.sqrt();
-        let change = MultiChange::new_random(&mut rng, &state, random_move_sigma, 2);
-        change.apply(&mut state);
-        // Evaluate the new state and decide whether to accept or reject the change
-        let new_cost = state.get_cost();
-        let cost_diff = new_cost - old_cost;
-        if cost_diff < 0 {
-            // Accept the change
-            old_cost = new_cost;
-        } else {
-            let acceptance_probability = (-cost_diff as f64 / temperature).exp();
-            if rng.random_range(0.0..1.0) < acceptance_probability {
-                // Accept the change
-                old_cost = new_cost;
-            } else {
-                // Reject the change
-                change.undo(&mut state);
-            }
-        }
-        if old_cost < min_cost {
-            min_cost = old_cost;
-        }
-        temperature *= 0.999; // Cool down
-        println!("temperature: {temperature}, cost: {old_cost}");
-    }
-
-    println!("Total iterations: {n_iterations}, min cost: {min_cost}");
-    let schedule = state.get_schedule();
-    (old_cost, schedule)
+/// This function may panic if the `OptimizerContext` contains invalid or inconsistent data,
+/// or if the final schedule fails [`Schedule::verify`].
+pub fn run_simulated_annealing(
+    context: OptimizerContext,
+    seed: Option<u64>,
+    progress: Option<&mut dyn FnMut(&Progress)>,
+) -> (i64, Schedule) {
+    let (cost, schedule, _stats) =
+        run_simulated_annealing_with_config(context, &AnnealingConfig::default(), seed, progress);
+    (cost, schedule)
+}
 
-    // somehow also get the final schedule out of the state
+/// Runs simulated annealing with explicit settings and returns statistics about the run.
+///
+/// See [`run_simulated_annealing`] for details and the meaning of `seed` and `progress`. With adaptive
+/// moves enabled in `config`, the number of moves per iteration is adjusted during the run;
+/// the chosen sizes are recorded in [`SolveStats::move_sizes`].
+pub fn run_simulated_annealing_with_config(
+    context: OptimizerContext,
+    config: &AnnealingConfig,
+    seed: Option<u64>,
+    mut progress: Option<&mut dyn FnMut(&Progress)>,
+) -> (i64, Schedule, SolveStats) {
+    Solver::new(context, config.clone(), seed)
+        .solve(|p| {
+            if let Some(progress) = progress.as_mut() {
+                progress(p);
+            }
+            Ok::<(), Infallible>(())
+        })
+        .unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[cfg(test)]
-
 mod tests {
-    use std::{rc::Rc, time::Instant};
-
-    use rand::rand_core::le;
-    use statrs::generate;
+    use std::{
+        rc::Rc,
+        time::{Duration, Instant},
+    };
 
     use crate::{
+        optimizer::SmartHomeFlowBuilder,
         optimizer_context::{
-            action::{
-                constant::{self, ConstantAction},
-                variable::{self, VariableAction},
-            },
+            action::{constant::ConstantAction, variable::VariableAction},
             battery::Battery,
             prognoses::Prognoses,
         },
-        time::{STEPS_PER_DAY, Time},
+        schedule::FlowEdge,
+        time::{STEPS_PER_DAY, SettlementPeriods, Time},
     };
 
     use super::*;
@@ -140,15 +143,15 @@ mod tests {
         let beyond_control_consumption_data = [20; STEPS_PER_DAY as usize];
         let batteries = vec![Rc::new(Battery::new(1000, 10, 10, 7, 1.0, 1))];
         let constant_actions = vec![Rc::new(ConstantAction::new(
-            Time::new(0, 0),
-            Time::new(2, 0),
-            Time::new(1, 0),
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(2, 0),
+            Time::at_default_resolution(1, 0),
             300,
             2,
         ))];
         let variable_actions = vec![Rc::new(VariableAction::new(
-            Time::new(1, 15),
-            Time::new(10, 0),
+            Time::at_default_resolution(1, 15),
+            Time::at_default_resolution(10, 0),
             300,
             100,
             3,
@@ -162,8 +165,9 @@ mod tests {
             constant_actions,
             variable_actions,
             1.0,
-        ); // Assuming a constructor exists
-        let (result, schedule) = run_simulated_annealing(context);
+        )
+        .unwrap(); // Assuming a constructor exists
+        let (result, _schedule) = run_simulated_annealing(context, None, None);
         println!("result: {result}");
         // Add assertions to verify the results
     }
@@ -172,7 +176,7 @@ mod tests {
     fn test_simulated_annealing2() {
         let start = Instant::now();
         let electricity_price: Prognoses<i64> = Prognoses::from_closure(|t| {
-            (t.to_timestep() as i64 - (STEPS_PER_DAY as i64 / 2)).abs() as i64 + 5
+            (t.to_timestep() as i64 - (STEPS_PER_DAY as i64 / 2)).abs() + 5
         });
         // downward parabola with maximum in the middle of the day. Low prices at the edges of the day.
         // let generated_electricity: Prognoses<i64> = Prognoses::from_closure(|t| {
@@ -189,16 +193,16 @@ mod tests {
 
         let constant_actions: Vec<Rc<ConstantAction>> = vec![
             Rc::new(ConstantAction::new(
-                Time::new(0, 0),
-                Time::new(23, 55),
-                Time::new(1, 0),
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(23, 55),
+                Time::at_default_resolution(1, 0),
                 300,
                 2,
             )),
             Rc::new(ConstantAction::new(
-                Time::new(12, 0),
-                Time::new(23, 55),
-                Time::new(2, 0),
+                Time::at_default_resolution(12, 0),
+                Time::at_default_resolution(23, 55),
+                Time::at_default_resolution(2, 0),
                 150,
                 3,
             )),
@@ -206,18 +210,18 @@ mod tests {
 
         let variable_actions = vec![
             Rc::new(VariableAction::new(
-                Time::new(6, 0),
-                Time::new(18, 0),
+                Time::at_default_resolution(6, 0),
+                Time::at_default_resolution(18, 0),
                 500,
                 100,
-                3,
+                4,
             )),
             Rc::new(VariableAction::new(
-                Time::new(0, 0),
-                Time::new(23, 55),
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(23, 55),
                 2000,
                 50,
-                4,
+                5,
             )),
         ];
 
@@ -229,12 +233,506 @@ mod tests {
             constant_actions,
             variable_actions,
             1.0,
-        );
+        )
+        .unwrap();
 
-        let (result, schedule) = run_simulated_annealing(context);
+        let (result, _schedule) = run_simulated_annealing(context, None, None);
         // println!("schedule: {schedule:#?}");
         println!("result: {result}");
         let duration = start.elapsed();
         println!("Time elapsed in test() is: {:?}", duration);
     }
+
+    /// Cheap instance: no baseline consumption, so every flow solve only routes the actions.
+    fn two_window_context(seed: u64) -> OptimizerContext {
+        let cheap_start = Time::at_default_resolution(2 + (seed % 3) as u32, 0);
+        let cheap_end = Time::at_default_resolution(5 + (seed % 3) as u32, 0);
+        let electricity_price = Prognoses::from_closure(|t| {
+            if cheap_start <= t && t < cheap_end {
+                5
+            } else {
+                20 + (t.to_timestep() % 7) as i64
+            }
+        });
+        let constant_actions = (0..4)
+            .map(|i| {
+                Rc::new(ConstantAction::new(
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(23, 0),
+                    Time::at_default_resolution(0, 20 + 10 * i),
+                    100 + 50 * i as i64,
+                    i + 1,
+                ))
+            })
+            .collect();
+        OptimizerContext::new(
+            electricity_price,
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            constant_actions,
+            vec![],
+            1.0,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_adaptive_moves_not_worse_than_fixed() {
+        let fixed = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.99 },
+            time_limit: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let adaptive = AnnealingConfig {
+            adaptive_moves: Some(AdaptiveMoveConfig {
+                window: 20,
+                ..Default::default()
+            }),
+            ..fixed.clone()
+        };
+        for seed in [1, 2] {
+            let (fixed_cost, _, _) = run_simulated_annealing_with_config(
+                two_window_context(seed),
+                &fixed,
+                Some(seed),
+                None,
+            );
+            let (adaptive_cost, _, stats) = run_simulated_annealing_with_config(
+                two_window_context(seed),
+                &adaptive,
+                Some(seed),
+                None,
+            );
+            assert!(
+                adaptive_cost as f64 <= fixed_cost as f64 * 1.05,
+                "seed {seed}: adaptive cost {adaptive_cost} much worse than fixed cost \
+                 {fixed_cost}, {stats:?}"
+            );
+            assert_eq!(stats.move_sizes[0], (0, 2));
+            assert!(stats.flow_solves > 0);
+        }
+    }
+
+    #[test]
+    fn test_progress_callback_is_called_every_iteration() {
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.9 },
+            ..Default::default()
+        };
+        let mut temperatures = Vec::new();
+        let mut record = |progress: &Progress| temperatures.push(progress.temperature);
+        let (_, _, stats) = run_simulated_annealing_with_config(
+            two_window_context(1),
+            &config,
+            Some(1),
+            Some(&mut record),
+        );
+        assert_eq!(temperatures.len(), stats.iterations);
+        assert!(temperatures.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn test_best_schedule_is_returned() {
+        // Hot enough to keep accepting uphill moves until the end
+        let config = AnnealingConfig {
+            cooling: Cooling::Linear { iterations: 200 },
+            initial_temperature: 10_000_000.0,
+            min_temperature: 1_000_000.0,
+            ..Default::default()
+        };
+        let mut logged = Vec::new();
+        let mut record = |progress: &Progress| logged.push(*progress);
+        let (cost, schedule, _) = run_simulated_annealing_with_config(
+            two_window_context(3),
+            &config,
+            Some(3),
+            Some(&mut record),
+        );
+        let best = logged.iter().map(|p| p.best_cost).min().unwrap();
+        assert_eq!(cost, best);
+        assert!(logged.iter().all(|p| p.candidate_cost >= cost));
+        assert!(
+            cost < logged.last().unwrap().cost,
+            "the run should end uphill"
+        );
+
+        // The schedule is the one the cost belongs to
+        let mut flow = SmartHomeFlowBuilder::from_context(&two_window_context(3)).build();
+        for action in schedule.constant_actions.values() {
+            flow.add_constant_consumption(action.clone());
+        }
+        assert_eq!(flow.get_cost().unwrap(), cost);
+    }
+
+    #[test]
+    fn test_cost_breakdown_sums_to_cost() {
+        let context = two_window_context(2).with_peak_price(50);
+        let config = AnnealingConfig {
+            cooling: Cooling::Linear { iterations: 50 },
+            explain_cost: true,
+            ..Default::default()
+        };
+        let (cost, schedule, stats) =
+            run_simulated_annealing_with_config(context.clone(), &config, Some(4), None);
+        let breakdown = schedule.get_objective_breakdown().unwrap().clone();
+        assert_eq!(breakdown.total(), cost);
+        assert!(breakdown.grid_import > 0);
+        assert!(breakdown.peak_charge > 0);
+        assert_eq!(stats.objective_breakdown, Some(breakdown.clone()));
+
+        assert_eq!(breakdown.penalties(), Vec::new());
+        assert_eq!(breakdown.energy_cost(), cost);
+
+        // Only attached to the schedule when asked for
+        let config = AnnealingConfig {
+            explain_cost: false,
+            ..config
+        };
+        let (_, schedule, stats) =
+            run_simulated_annealing_with_config(context, &config, Some(4), None);
+        assert!(schedule.get_objective_breakdown().is_none());
+        assert_eq!(stats.objective_breakdown, Some(breakdown));
+    }
+
+    #[test]
+    fn test_flow_table_is_conserved_and_sums_to_cost() {
+        let base = two_window_context(2);
+        let context = OptimizerContext::new(
+            base.get_electricity_price().as_ref().clone(),
+            Prognoses::from_closure(|t| {
+                if t < Time::at_default_resolution(1, 0) {
+                    40
+                } else {
+                    0
+                }
+            }),
+            Prognoses::from_closure(|_| 10),
+            vec![Rc::new(Battery::new(5_000, 1_000, 200, 200, 0.9, 10))],
+            base.get_constant_actions().clone(),
+            vec![Rc::new(VariableAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(8, 0),
+                3_000,
+                50,
+                11,
+            ))],
+            1.0,
+        )
+        .unwrap();
+        let config = AnnealingConfig {
+            cooling: Cooling::Linear { iterations: 50 },
+            explain_cost: true,
+            capture_flow: true,
+            ..Default::default()
+        };
+        let (_, schedule, _) =
+            run_simulated_annealing_with_config(context.clone(), &config, Some(4), None);
+        let table = schedule.get_flow_table().unwrap();
+        assert!(
+            table
+                .iter()
+                .all(|edge| 0 <= edge.flow && edge.flow <= edge.capacity)
+        );
+        assert!(
+            table
+                .iter()
+                .any(|edge| edge.from == "Battery 10" && edge.flow > 0)
+        );
+        assert!(
+            table
+                .iter()
+                .any(|edge| edge.to == "Action 11" && edge.flow > 0)
+        );
+
+        // Everything flowing into the wire of a timestep flows out again
+        for t in 0..STEPS_PER_DAY {
+            let time = Some(Time::from_timestep(t));
+            let at_wire = |end: fn(&FlowEdge) -> &str| -> i64 {
+                table
+                    .iter()
+                    .filter(|edge| end(edge) == "Wire" && edge.time == time)
+                    .map(|edge| edge.flow)
+                    .sum()
+            };
+            assert_eq!(at_wire(|edge| &edge.to), at_wire(|edge| &edge.from), "{t}");
+        }
+
+        // The flow costs what the schedule costs, apart from the bonus on the initial charge
+        let breakdown = schedule.get_objective_breakdown().unwrap();
+        let flow_cost: i64 = table
+            .iter()
+            .filter(|edge| edge.category != Some("initial_charge"))
+            .map(|edge| edge.flow * edge.unit_cost)
+            .sum();
+        assert_eq!(
+            flow_cost,
+            breakdown.total() - breakdown.min_consumption_rounding - breakdown.unserved_penalty
+        );
+
+        // Only captured when asked for
+        let config = AnnealingConfig {
+            capture_flow: false,
+            ..config
+        };
+        let (_, schedule, _) = run_simulated_annealing_with_config(context, &config, Some(4), None);
+        assert!(schedule.get_flow_table().is_none());
+    }
+
+    #[test]
+    fn test_all_cooling_schedules_find_feasible_schedules() {
+        let schedules = [
+            Cooling::Geometric { factor: 0.98 },
+            Cooling::Linear { iterations: 300 },
+            Cooling::Logarithmic { iterations: 300 },
+            Cooling::Lam { iterations: 300 },
+        ];
+        for cooling in schedules {
+            let config = AnnealingConfig {
+                cooling: cooling.clone(),
+                ..Default::default()
+            };
+            let mut best_costs = Vec::new();
+            let mut record = |progress: &Progress| best_costs.push(progress.best_cost);
+            let (_, schedule, _) = run_simulated_annealing_with_config(
+                two_window_context(1),
+                &config,
+                Some(1),
+                Some(&mut record),
+            );
+            assert!(
+                best_costs.windows(2).all(|pair| pair[1] <= pair[0]),
+                "best cost increased with {cooling:?}"
+            );
+            assert_eq!(schedule.constant_actions.len(), 4);
+            for t in 0..STEPS_PER_DAY {
+                let t = Time::from_timestep(t);
+                let demand: i64 = schedule
+                    .constant_actions
+                    .values()
+                    .map(|action| action.get_consumption(t))
+                    .sum();
+                assert!(
+                    *schedule.network_consumption.get(t).unwrap() >= demand,
+                    "{cooling:?} leaves demand at {t:?} unserved"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_peak_price_runs_identical_actions_sequentially() {
+        let run = |peak_price: Option<i64>| {
+            // The first 20 minutes are cheapest, so without a peak charge both run then
+            let electricity_price = Prognoses::from_closure(|t| {
+                if t < Time::at_default_resolution(0, 20) {
+                    10
+                } else {
+                    20
+                }
+            });
+            let actions = (1..=2)
+                .map(|id| {
+                    Rc::new(ConstantAction::new(
+                        Time::at_default_resolution(0, 0),
+                        Time::at_default_resolution(1, 0),
+                        Time::at_default_resolution(0, 20),
+                        100,
+                        id,
+                    ))
+                })
+                .collect();
+            let context = OptimizerContext::new(
+                electricity_price,
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 0),
+                vec![],
+                actions,
+                vec![],
+                1.0,
+            )
+            .unwrap();
+            let context = match peak_price {
+                Some(peak_price) => context.with_peak_price(peak_price),
+                None => context,
+            };
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.99 },
+                ..Default::default()
+            };
+            let (_, schedule, _) =
+                run_simulated_annealing_with_config(context, &config, Some(4), None);
+            schedule
+        };
+
+        let schedule = run(None);
+        assert_eq!(
+            schedule.get_peak_consumption(),
+            (Time::at_default_resolution(0, 0), 200)
+        );
+
+        let schedule = run(Some(1000));
+        let first = schedule.get_constant_action(1).unwrap();
+        let second = schedule.get_constant_action(2).unwrap();
+        assert!(
+            first.get_end_time() <= second.get_start_time()
+                || second.get_end_time() <= first.get_start_time(),
+            "actions overlap: {:?} and {:?}",
+            first.get_start_time(),
+            second.get_start_time()
+        );
+        assert_eq!(schedule.get_peak_consumption().1, 100);
+    }
+
+    #[test]
+    fn test_peak_price_measures_settlement_period_averages() {
+        // A 5 minute spike of 300 per timestep, fixed at the start of the day
+        let peak_charge = |settlement_periods: SettlementPeriods| {
+            let context = OptimizerContext::new(
+                Prognoses::from_closure(|_| 10),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 0),
+                vec![],
+                vec![Rc::new(ConstantAction::new(
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(0, 5),
+                    Time::at_default_resolution(0, 5),
+                    300,
+                    1,
+                ))],
+                vec![],
+                1.0,
+            )
+            .unwrap()
+            .with_peak_price(7)
+            .with_settlement_periods(settlement_periods);
+            let config = AnnealingConfig {
+                explain_cost: true,
+                ..Default::default()
+            };
+            let (_, schedule, _) =
+                run_simulated_annealing_with_config(context, &config, Some(1), None);
+            (
+                schedule.get_objective_breakdown().unwrap().peak_charge,
+                schedule.get_settlement_profile(),
+            )
+        };
+
+        let (charge, profile) = peak_charge(SettlementPeriods::default());
+        assert_eq!(charge, 300 * 7);
+        assert_eq!(profile.len(), STEPS_PER_DAY as usize);
+
+        let (charge, profile) = peak_charge(SettlementPeriods::new(15, 0));
+        assert_eq!(charge, 100 * 7);
+        assert_eq!(profile[0], (Time::at_default_resolution(0, 0), 100));
+        assert_eq!(profile[1], (Time::at_default_resolution(0, 15), 0));
+
+        // The period before the first full one is shorter, 1500 over 10 timesteps
+        let (charge, profile) = peak_charge(SettlementPeriods::new(15, 10));
+        assert_eq!(charge, 150 * 7);
+        assert_eq!(profile[1], (Time::at_default_resolution(0, 10), 0));
+    }
+
+    #[test]
+    fn test_profile_spike_is_placed_at_cheapest_price() {
+        let cheapest = Time::at_default_resolution(0, 20);
+        // Prices fall towards the cheapest minute, so the heating spike is pulled towards it.
+        let electricity_price = Prognoses::from_closure(|t: Time| {
+            (t.to_timestep() as i64 - cheapest.to_timestep() as i64).abs() + 1
+        });
+        let dishwasher = ConstantAction::new_profiled(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(1, 0),
+            vec![10, 10, 1000, 1000, 10, 10, 10],
+            1,
+        );
+        let context = OptimizerContext::new(
+            electricity_price,
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![Rc::new(dishwasher)],
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.99 },
+            ..Default::default()
+        };
+        let (_, schedule, _) = run_simulated_annealing_with_config(context, &config, Some(3), None);
+
+        let action = schedule.get_constant_action(1).unwrap();
+        let spike = action
+            .iter_consumption()
+            .filter(|(_, consumption)| *consumption == 1000)
+            .map(|(time, _)| time)
+            .collect::<Vec<_>>();
+        assert!(spike.contains(&cheapest), "spike at {spike:?}");
+        assert_eq!(action.get_consumption(cheapest), 1000);
+    }
+
+    fn assert_same_schedule(a: &Schedule, b: &Schedule) {
+        assert_eq!(
+            a.network_consumption.get_data(),
+            b.network_consumption.get_data()
+        );
+        assert_eq!(a.constant_actions.len(), b.constant_actions.len());
+        for (id, action) in &a.constant_actions {
+            assert_eq!(
+                Some(action.get_start_time()),
+                b.get_constant_action(*id)
+                    .map(|other| other.get_start_time())
+            );
+        }
+        assert_eq!(a.batteries.len(), b.batteries.len());
+        for (id, battery) in &a.batteries {
+            let other = b.get_battery(*id).unwrap();
+            for t in 0..STEPS_PER_DAY {
+                let t = Time::from_timestep(t);
+                assert_eq!(battery.get_charge_level(t), other.get_charge_level(t));
+            }
+        }
+        assert_eq!(a.variable_actions.len(), b.variable_actions.len());
+        for (id, action) in &a.variable_actions {
+            let other = b.get_variable_action(*id).unwrap();
+            for t in action.get_start().to_timestep()..action.get_end().to_timestep() {
+                let t = Time::from_timestep(t);
+                assert_eq!(action.get_consumption(t), other.get_consumption(t));
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_seed_gives_identical_result() {
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.95 },
+            ..Default::default()
+        };
+        let (cost_a, schedule_a, _) =
+            run_simulated_annealing_with_config(two_window_context(1), &config, Some(7), None);
+        let (cost_b, schedule_b, _) =
+            run_simulated_annealing_with_config(two_window_context(1), &config, Some(7), None);
+        assert_eq!(cost_a, cost_b);
+        assert_same_schedule(&schedule_a, &schedule_b);
+
+        // Different seeds should lead to different start times for at least one seed pair.
+        let start_times = |seed| {
+            let (_, schedule, _) = run_simulated_annealing_with_config(
+                two_window_context(1),
+                &config,
+                Some(seed),
+                None,
+            );
+            let mut start_times: Vec<_> = schedule
+                .constant_actions
+                .values()
+                .map(|action| (action.get_id(), action.get_start_time()))
+                .collect();
+            start_times.sort();
+            start_times
+        };
+        let reference = start_times(7);
+        assert!((8..12).any(|seed| start_times(seed) != reference));
+    }
 }
@@ -0,0 +1,808 @@
+use std::{fmt, sync::atomic::Ordering, time::Instant};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    optimizer::{FlowSolveError, Shortfall, grid_overloads},
+    optimizer_context::OptimizerContext,
+    schedule::{Schedule, Violation},
+    simulated_annealing::{
+        adaptive::MoveSizeController,
+        change::{ChangeGuard, multi_change::MultiChange},
+        config::AnnealingConfig,
+        state::State,
+        stats::SolveStats,
+    },
+    time::Time,
+};
+
+/// Snapshot of a running solve, passed to the progress callback of [`Solver::solve`].
+///
+/// The callback is invoked after a candidate change has been evaluated and before it is
+/// accepted or rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub iteration: usize,
+    pub temperature: f64,
+    /// Cost of the current (last accepted) state.
+    pub cost: i64,
+    /// Cost of the candidate that is about to be accepted or rejected.
+    pub candidate_cost: i64,
+    /// Lowest cost seen so far in this solve.
+    pub best_cost: i64,
+}
+
+/// Reasons a call to [`Solver::solve`] can fail.
+#[derive(Debug)]
+pub enum SolveError<E> {
+    /// The progress callback returned an error. The solver stays usable.
+    Aborted(E),
+    /// A previous solve was interrupted while the state was being modified, so its
+    /// consistency can't be guaranteed anymore. Create a new solver.
+    Poisoned,
+    /// The flow couldn't be solved (see [`crate::optimizer::SmartHomeFlow::get_cost`]). The pending change is
+    /// undone, so the solver stays usable.
+    Flow(FlowSolveError),
+    /// The beyond control consumption alone exceeds the grid limit at these timesteps, so no
+    /// schedule can serve it (see [`crate::optimizer::grid_overloads`]).
+    GridLimitExceeded(Vec<Time>),
+    /// Even the best schedule found leaves beyond control or variable action consumption
+    /// unserved or a final charge target unreached (see
+    /// [`crate::optimizer::SmartHomeFlow::get_shortfall`]). The state is left at that schedule.
+    Infeasible(Shortfall),
+    /// The final schedule breaks constraints of the context (see [`Schedule::verify`]). This
+    /// is a bug in the solver, not in the context.
+    Inconsistent(Vec<Violation>),
+}
+
+impl<E: fmt::Display> fmt::Display for SolveError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::Aborted(e) => write!(f, "Solve aborted: {}", e),
+            SolveError::Poisoned => write!(
+                f,
+                "Solver is poisoned because a previous solve was interrupted mid-change"
+            ),
+            SolveError::Flow(e) => write!(f, "Flow couldn't be solved: {}", e),
+            SolveError::GridLimitExceeded(times) => write!(
+                f,
+                "Beyond control consumption exceeds the grid limit at {:?}",
+                times
+            ),
+            SolveError::Infeasible(shortfall) => write!(f, "{}", shortfall),
+            SolveError::Inconsistent(violations) => {
+                write!(f, "Solver produced an inconsistent schedule:")?;
+                for violation in violations {
+                    write!(f, "\n- {}", violation)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A reusable simulated annealing solver.
+///
+/// Every call to [`Solver::solve`] runs a full annealing schedule, starting from the
+/// state the previous call ended in. Changes are applied through [`ChangeGuard`]s, so a
+/// solve that exits early (callback error or panic) never leaves a half-applied change
+/// behind: either the change is undone, or the solver is marked poisoned.
+pub struct Solver {
+    state: State,
+    config: AnnealingConfig,
+    rng: StdRng,
+    /// Timesteps at which the context can't be served, reported by every solve.
+    grid_overloads: Vec<Time>,
+    /// The context being solved, every final schedule is verified against it.
+    context: OptimizerContext,
+}
+
+impl Solver {
+    /// Creates a solver with a random initial state, or one seeded with
+    /// [`AnnealingConfig::initial_starts`]. With a `seed`, the solver produces the same results
+    /// every time.
+    pub fn new(context: OptimizerContext, config: AnnealingConfig, seed: Option<u64>) -> Self {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        let grid_overloads = grid_overloads(&context);
+        let state = State::new(context.clone(), &mut rng, &config);
+        Self {
+            state,
+            config,
+            rng,
+            grid_overloads,
+            context,
+        }
+    }
+
+    /// Returns true if the solver can't be used anymore.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.is_poisoned()
+    }
+
+    /// Runs the annealing schedule and returns the best cost seen, its schedule and statistics.
+    /// The state is left at that schedule, so the next solve continues from it.
+    ///
+    /// `progress` is called once per iteration. Returning an error stops the solve; the
+    /// pending change is undone and the error is returned as [`SolveError::Aborted`].
+    pub fn solve<E, F>(
+        &mut self,
+        mut progress: F,
+    ) -> Result<(i64, Schedule, SolveStats), SolveError<E>>
+    where
+        F: FnMut(&Progress) -> Result<(), E>,
+    {
+        if self.state.is_poisoned() {
+            return Err(SolveError::Poisoned);
+        }
+        if !self.grid_overloads.is_empty() {
+            return Err(SolveError::GridLimitExceeded(self.grid_overloads.clone()));
+        }
+        let config = &self.config;
+        let rng = &mut self.rng;
+        let state = &mut self.state;
+
+        let start = Instant::now();
+        let flow_stats_before = state.get_flow_stats();
+        let mut cooling = config
+            .cooling
+            .start(config.initial_temperature, config.min_temperature);
+
+        let mut move_size_controller = config
+            .adaptive_moves
+            .clone()
+            .map(|adaptive| MoveSizeController::new(adaptive, config.moves_per_iteration));
+        let mut num_moves = move_size_controller
+            .as_ref()
+            .map_or(config.moves_per_iteration, MoveSizeController::size);
+        let mut stats = SolveStats {
+            move_sizes: vec![(0, num_moves)],
+            ..Default::default()
+        };
+
+        let mut old_cost = state.get_cost().map_err(SolveError::Flow)?;
+        let mut min_cost = old_cost;
+        // Uphill moves are accepted until the end, so the last state isn't necessarily the best
+        let mut best = state.snapshot();
+        // Without movable constant actions there is nothing to move, the flow alone is optimal
+        let has_moves = !state.get_constant_action_ids().is_empty();
+        while has_moves && !cooling.is_finished() {
+            if config
+                .time_limit
+                .is_some_and(|limit| start.elapsed() >= limit)
+            {
+                break;
+            }
+            if config
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            {
+                stats.cancelled = true;
+                break;
+            }
+            stats.iterations += 1;
+            let temperature = cooling.temperature();
+            // Determine random_move_sigma based on temperature
+            let random_move_sigma = 30.0 * temperature.sqrt();
+            let change = MultiChange::new_random(rng, state, random_move_sigma, num_moves);
+            // Undoes the change when dropped, unless it is committed
+            let mut guard = ChangeGuard::apply(state, change);
+            // Evaluate the new state and decide whether to accept or reject the change
+            let new_cost = guard.get_cost().map_err(SolveError::Flow)?;
+            // Rejected candidates count as well, so the reported best cost never goes up
+            if new_cost < min_cost {
+                min_cost = new_cost;
+                best = guard.snapshot();
+            }
+            progress(&Progress {
+                iteration: stats.iterations,
+                temperature,
+                cost: old_cost,
+                candidate_cost: new_cost,
+                best_cost: min_cost,
+            })
+            .map_err(SolveError::Aborted)?;
+            let cost_diff = new_cost - old_cost;
+            let accepted = cost_diff < 0
+                || rng.random_range(0.0..1.0) < (-cost_diff as f64 / temperature).exp();
+            if accepted {
+                old_cost = new_cost;
+                stats.accepted += 1;
+                guard.commit();
+            } else {
+                drop(guard);
+            }
+            if let Some(controller) = move_size_controller.as_mut() {
+                let average_flow_solve = state.get_flow_stats().average_solve_time();
+                if let Some(new_size) = controller.record(accepted, average_flow_solve) {
+                    num_moves = new_size;
+                    stats.move_sizes.push((stats.iterations, num_moves));
+                }
+            }
+            cooling.advance(accepted);
+        }
+
+        if min_cost < old_cost {
+            state.restore(&best);
+        }
+        if let Some(shortfall) = state.get_shortfall().map_err(SolveError::Flow)? {
+            return Err(SolveError::Infeasible(shortfall));
+        }
+        let mut schedule = state.get_schedule().map_err(SolveError::Flow)?;
+        let breakdown = state.get_cost_breakdown().map_err(SolveError::Flow)?;
+        if self.config.explain_cost {
+            schedule.set_objective_breakdown(breakdown.clone());
+        }
+        stats.objective_breakdown = Some(breakdown);
+        if self.config.capture_flow {
+            schedule.flow_table = Some(state.get_flow_table().map_err(SolveError::Flow)?);
+        }
+        let violations = schedule.verify(&self.context);
+        if !violations.is_empty() {
+            return Err(SolveError::Inconsistent(violations));
+        }
+        let flow_stats = state.get_flow_stats();
+        stats.flow_solves = flow_stats.solves - flow_stats_before.solves;
+        stats.flow_reuses = flow_stats.reuses - flow_stats_before.reuses;
+        stats.flow_solve_time = flow_stats.solve_time - flow_stats_before.solve_time;
+        stats.flow_augmentations = flow_stats.augmentations - flow_stats_before.augmentations;
+        stats.flow_cycle_cancellations =
+            flow_stats.cycle_cancellations - flow_stats_before.cycle_cancellations;
+        stats.elapsed = start.elapsed();
+        Ok((min_cost, schedule, stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        panic::{AssertUnwindSafe, catch_unwind},
+        rc::Rc,
+        sync::{Arc, atomic::AtomicBool},
+    };
+
+    use crate::{
+        optimizer::{ExactSolveError, SmartHomeFlowBuilder, solve_exact},
+        optimizer_context::{
+            action::{
+                constant::{ConstantAction, ExclusionError, PrecedenceError},
+                variable::VariableAction,
+            },
+            prognoses::Prognoses,
+        },
+        simulated_annealing::Cooling,
+        time::Time,
+    };
+
+    use super::*;
+
+    fn context() -> OptimizerContext {
+        let constant_actions = (1..=3)
+            .map(|id| {
+                Rc::new(ConstantAction::new(
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(12, 0),
+                    Time::at_default_resolution(0, 30),
+                    100 * id as i64,
+                    id,
+                ))
+            })
+            .collect();
+        OptimizerContext::new(
+            Prognoses::from_closure(|t| (t.to_timestep() % 120) as i64 + 1),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            constant_actions,
+            vec![],
+            1.0,
+        )
+        .unwrap()
+    }
+
+    fn config() -> AnnealingConfig {
+        AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.9 },
+            ..Default::default()
+        }
+    }
+
+    /// Cost of the schedule's constant action placement, computed from scratch.
+    fn exact_cost(context: &OptimizerContext, schedule: &Schedule) -> i64 {
+        let mut flow = SmartHomeFlowBuilder::new(
+            context.get_generated_electricity(),
+            context.get_electricity_price(),
+            context.get_beyond_control_consumption(),
+            context.get_first_timestep_fraction(),
+        )
+        .build();
+        for action in schedule.constant_actions.values() {
+            flow.add_constant_consumption(action.clone());
+        }
+        flow.get_cost().unwrap()
+    }
+
+    #[test]
+    fn test_solver_is_reusable_after_aborted_solve() {
+        let mut solver = Solver::new(context(), config(), Some(3));
+        let result = solver.solve(|progress| {
+            if progress.iteration == 10 {
+                Err("stop")
+            } else {
+                Ok(())
+            }
+        });
+        assert!(matches!(result, Err(SolveError::Aborted("stop"))));
+        assert!(!solver.is_poisoned());
+
+        let (cost, schedule, _) = solver.solve(|_| Ok::<(), ()>(())).unwrap();
+        assert_eq!(cost, exact_cost(&context(), &schedule));
+    }
+
+    #[test]
+    fn test_cancelled_solve_returns_best_schedule() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let config = AnnealingConfig {
+            cancel: Some(cancel.clone()),
+            ..config()
+        };
+        let mut solver = Solver::new(context(), config, Some(3));
+        let (cost, schedule, stats) = solver
+            .solve(|progress| {
+                if progress.iteration == 10 {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+        assert!(stats.cancelled);
+        assert_eq!(stats.iterations, 10);
+        assert_eq!(cost, exact_cost(&context(), &schedule));
+    }
+
+    #[test]
+    fn test_solver_is_poisoned_after_panic() {
+        let mut solver = Solver::new(context(), config(), Some(3));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            solver.solve(|progress| -> Result<(), ()> {
+                if progress.iteration == 10 {
+                    panic!("callback panicked");
+                }
+                Ok(())
+            })
+        }));
+        assert!(result.is_err());
+        assert!(solver.is_poisoned());
+        assert!(matches!(
+            solver.solve(|_| Ok::<(), ()>(())),
+            Err(SolveError::Poisoned)
+        ));
+    }
+
+    #[test]
+    fn test_warm_start_begins_at_the_initial_starts() {
+        let (cost, schedule, _) = Solver::new(context(), config(), Some(3))
+            .solve(|_| Ok::<(), ()>(()))
+            .unwrap();
+        let mut initial_starts: HashMap<u32, i64> = (1..=3)
+            .map(|id| {
+                let start = schedule.get_constant_action(id).unwrap().get_start_time();
+                (id, start.to_timestep() as i64)
+            })
+            .collect();
+        initial_starts.insert(99, 0);
+        let config = AnnealingConfig {
+            initial_starts: initial_starts.clone(),
+            ..config()
+        };
+        let mut first_best = None;
+        let (warm_cost, _, _) = Solver::new(context(), config, Some(4))
+            .solve(|progress| {
+                first_best.get_or_insert(progress.best_cost);
+                Ok::<(), ()>(())
+            })
+            .unwrap();
+        assert_eq!(first_best, Some(cost));
+        assert!(warm_cost <= cost);
+
+        // Starts outside the window are clamped into it
+        initial_starts.insert(1, -30);
+        initial_starts.insert(2, 5_000);
+        let clamped = AnnealingConfig {
+            initial_starts,
+            ..AnnealingConfig::default()
+        };
+        let state = State::new(context(), &mut StdRng::seed_from_u64(1), &clamped);
+        assert_eq!(
+            state.get_constant_action(1).get_start_time(),
+            Time::at_default_resolution(0, 0)
+        );
+        assert_eq!(
+            state.get_constant_action(2).get_start_time(),
+            Time::at_default_resolution(11, 30)
+        );
+    }
+
+    /// Two actions that can't run at the same time within the grid limit and only just fit
+    /// into their window one after the other. Both prefer the cheap first half hour.
+    fn grid_limited_context(grid_limit: i64) -> OptimizerContext {
+        let constant_actions = (1..=2)
+            .map(|id| {
+                Rc::new(ConstantAction::new(
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(1, 10),
+                    Time::at_default_resolution(0, 30),
+                    1_000,
+                    id,
+                ))
+            })
+            .collect();
+        OptimizerContext::new(
+            Prognoses::from_closure(|t| {
+                if t < Time::at_default_resolution(0, 30) {
+                    1
+                } else {
+                    5
+                }
+            }),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            constant_actions,
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(grid_limit)
+    }
+
+    #[test]
+    fn test_grid_limit_forces_actions_apart() {
+        let overlap = |schedule: &Schedule| {
+            let (a, b) = (
+                &schedule.constant_actions[&1],
+                &schedule.constant_actions[&2],
+            );
+            a.get_start_time() < b.get_end_time() && b.get_start_time() < a.get_end_time()
+        };
+        let context = grid_limited_context(1_500);
+        let mut solver = Solver::new(context.clone(), config(), Some(3));
+        let (cost, schedule, _) = solver.solve(|_| Ok::<(), ()>(())).unwrap();
+        assert!(!overlap(&schedule));
+        // Everything is served, so no penalty is part of the cost.
+        assert_eq!(cost, exact_cost(&context, &schedule));
+        assert!(
+            schedule
+                .network_consumption
+                .get_data()
+                .iter()
+                .all(|import| *import <= 1_500)
+        );
+    }
+
+    #[test]
+    fn test_grid_overloads_are_reported() {
+        let evening = |t: Time| {
+            Time::at_default_resolution(18, 0) <= t && t < Time::at_default_resolution(18, 3)
+        };
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|_| 1),
+            Prognoses::from_closure(|t| {
+                if t == Time::at_default_resolution(18, 1) {
+                    600
+                } else {
+                    0
+                }
+            }),
+            Prognoses::from_closure(|t| if evening(t) { 2_000 } else { 0 }),
+            vec![],
+            vec![],
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_grid_limit(1_500);
+        let mut solver = Solver::new(context, config(), Some(3));
+        let Err(SolveError::GridLimitExceeded(times)) = solver.solve(|_| Ok::<(), ()>(())) else {
+            panic!("Solve should have failed");
+        };
+        // Generation covers the missing power at 18:01.
+        assert_eq!(
+            times,
+            vec![
+                Time::at_default_resolution(18, 0),
+                Time::at_default_resolution(18, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_precedence_keeps_actions_ordered() {
+        // Both actions prefer the cheap hour in the middle of their overlapping windows.
+        let action = |id: u32, start_from: Time, end_before: Time| {
+            Rc::new(ConstantAction::new(
+                start_from,
+                end_before,
+                Time::at_default_resolution(0, 40),
+                1_000,
+                id,
+            ))
+        };
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|t| {
+                if Time::at_default_resolution(1, 0) <= t && t < Time::at_default_resolution(2, 0) {
+                    1
+                } else {
+                    5
+                }
+            }),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![
+                action(
+                    1,
+                    Time::at_default_resolution(0, 30),
+                    Time::at_default_resolution(3, 0),
+                ),
+                action(
+                    2,
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(2, 30),
+                ),
+            ],
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_precedence(1, 2)
+        .unwrap();
+        for seed in 0..5 {
+            let mut solver = Solver::new(context.clone(), config(), Some(seed));
+            let (_, schedule, _) = solver.solve(|_| Ok::<(), ()>(())).unwrap();
+            let (first, second) = (
+                &schedule.constant_actions[&1],
+                &schedule.constant_actions[&2],
+            );
+            assert!(first.get_end_time() <= second.get_start_time());
+            assert!(schedule.verify(&context).is_empty());
+        }
+
+        assert_eq!(
+            context.clone().with_precedence(1, 3).err(),
+            Some(PrecedenceError::UnknownAction(3))
+        );
+        assert_eq!(
+            context.clone().with_precedence(2, 1).err(),
+            Some(PrecedenceError::Cycle)
+        );
+        // Action 1 starts at 2:00 at the earliest, too late for action 2 to end by 2:30.
+        let tight = OptimizerContext::new(
+            Prognoses::from_closure(|_| 1),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![
+                action(
+                    1,
+                    Time::at_default_resolution(2, 0),
+                    Time::at_default_resolution(3, 0),
+                ),
+                action(
+                    2,
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(2, 30),
+                ),
+            ],
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        assert_eq!(
+            tight.with_precedence(1, 2).err(),
+            Some(PrecedenceError::Infeasible(1))
+        );
+    }
+
+    #[test]
+    fn test_min_gap_keeps_actions_apart() {
+        // Both actions prefer the cheap hour, but must run at least an hour apart.
+        let action = |id: u32| {
+            Rc::new(ConstantAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(4, 0),
+                Time::at_default_resolution(0, 30),
+                1_000,
+                id,
+            ))
+        };
+        let context = OptimizerContext::new(
+            Prognoses::from_closure(|t| {
+                if Time::at_default_resolution(1, 0) <= t && t < Time::at_default_resolution(2, 0) {
+                    1
+                } else {
+                    5
+                }
+            }),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![action(1), action(2)],
+            vec![],
+            1.0,
+        )
+        .unwrap()
+        .with_min_gap(1, 2, Time::at_default_resolution(1, 0))
+        .unwrap();
+        assert_eq!(context.get_min_gap(1, 2), Time::at_default_resolution(1, 0));
+        assert_eq!(context.get_min_gap(2, 1), Time::at_default_resolution(0, 0));
+        for seed in 0..5 {
+            let mut solver = Solver::new(context.clone(), config(), Some(seed));
+            let (_, schedule, _) = solver.solve(|_| Ok::<(), ()>(())).unwrap();
+            let (first, second) = (
+                &schedule.constant_actions[&1],
+                &schedule.constant_actions[&2],
+            );
+            assert!(
+                first.get_end_time() + Time::at_default_resolution(1, 0) <= second.get_start_time()
+            );
+            assert!(schedule.verify(&context).is_empty());
+        }
+
+        // Two half-hour runs fill the four hour window with three hours of rest between them.
+        let tight = OptimizerContext::new(
+            Prognoses::from_closure(|_| 1),
+            Prognoses::from_closure(|_| 0),
+            Prognoses::from_closure(|_| 0),
+            vec![],
+            vec![action(1), action(2)],
+            vec![],
+            1.0,
+        )
+        .unwrap();
+        assert!(
+            tight
+                .clone()
+                .with_min_gap(1, 2, Time::at_default_resolution(3, 0))
+                .is_ok()
+        );
+        assert_eq!(
+            tight
+                .with_min_gap(1, 2, Time::at_default_resolution(3, 1))
+                .err(),
+            Some(PrecedenceError::Infeasible(1))
+        );
+    }
+
+    #[test]
+    fn test_exclusion_group_keeps_actions_apart() {
+        // All three actions prefer the cheap hour, their window barely fits them in a row.
+        let context = |end_before: Time| {
+            OptimizerContext::new(
+                Prognoses::from_closure(|t| {
+                    if Time::at_default_resolution(1, 0) <= t
+                        && t < Time::at_default_resolution(2, 0)
+                    {
+                        1
+                    } else {
+                        5
+                    }
+                }),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 0),
+                vec![],
+                (1..=3)
+                    .map(|id| {
+                        Rc::new(ConstantAction::new(
+                            Time::at_default_resolution(0, 0),
+                            end_before,
+                            Time::at_default_resolution(0, 40),
+                            1_000,
+                            id,
+                        ))
+                    })
+                    .collect(),
+                vec![],
+                1.0,
+            )
+            .unwrap()
+        };
+        let grouped = context(Time::at_default_resolution(2, 10))
+            .with_exclusion_group(vec![1, 2, 3])
+            .unwrap();
+        for seed in 0..5 {
+            let mut solver = Solver::new(grouped.clone(), config(), Some(seed));
+            let (_, schedule, _) = solver.solve(|_| Ok::<(), ()>(())).unwrap();
+            let mut runs: Vec<_> = schedule
+                .constant_actions
+                .values()
+                .map(|action| (action.get_start_time(), action.get_end_time()))
+                .collect();
+            runs.sort();
+            assert!(
+                runs.windows(2).all(|pair| pair[0].1 <= pair[1].0),
+                "{runs:?}"
+            );
+            assert!(schedule.verify(&grouped).is_empty());
+        }
+
+        assert_eq!(
+            context(Time::at_default_resolution(2, 10))
+                .with_exclusion_group(vec![1, 4])
+                .err(),
+            Some(ExclusionError::UnknownAction(4))
+        );
+        // 119 minutes are one short of fitting three actions of 40 minutes
+        assert_eq!(
+            context(Time::at_default_resolution(1, 59))
+                .with_exclusion_group(vec![1, 2, 3])
+                .err(),
+            Some(ExclusionError::Infeasible(3))
+        );
+    }
+
+    #[test]
+    fn test_contiguous_action_consumes_in_one_block() {
+        // Cheap every other 10 minutes, so a free action would only consume in those
+        let action = VariableAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(5, 0),
+            1_000,
+            30,
+            1,
+        );
+        let context = |action: VariableAction| {
+            OptimizerContext::new(
+                Prognoses::from_closure(|t| if t.to_timestep() / 10 % 2 == 0 { 1 } else { 9 }),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|_| 0),
+                vec![],
+                vec![],
+                vec![Rc::new(action)],
+                1.0,
+            )
+            .unwrap()
+        };
+        let gaps = |schedule: &Schedule| {
+            let consumption: Vec<i64> = schedule.variable_actions[&1]
+                .iter_consumption()
+                .map(|(_, consumption)| consumption)
+                .collect();
+            let first = consumption.iter().position(|&c| c > 0).unwrap();
+            let last = consumption.iter().rposition(|&c| c > 0).unwrap();
+            consumption[first..=last]
+                .iter()
+                .filter(|&&c| c == 0)
+                .count()
+        };
+
+        let (_, free) = solve_exact(&context(action.clone())).unwrap();
+        assert!(gaps(&free) > 0);
+
+        let contiguous = context(action.with_contiguous(true));
+        assert!(matches!(
+            solve_exact(&contiguous),
+            Err(ExactSolveError::ConstantActions(ids)) if ids == vec![1]
+        ));
+        for seed in 0..3 {
+            let mut solver = Solver::new(contiguous.clone(), config(), Some(seed));
+            let (_, schedule, _) = solver.solve(|_| Ok::<(), ()>(())).unwrap();
+            assert_eq!(gaps(&schedule), 0);
+            assert!(schedule.constant_actions.is_empty());
+            let assigned = &schedule.variable_actions[&1];
+            assert_eq!(assigned.get_total_assigned(), 1_000);
+            // 34 timesteps, the first 14 of them consume one more
+            let mut rates: Vec<i64> = assigned
+                .iter_consumption()
+                .map(|(_, consumption)| consumption)
+                .filter(|&consumption| consumption > 0)
+                .collect();
+            rates.dedup();
+            assert_eq!(rates, vec![30, 29]);
+        }
+    }
+}
@@ -1,73 +1,152 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, rc::Rc};
+
+use rand::seq::IndexedRandom;
 
 use crate::{
-    optimizer::{SmartHomeFlow, SmartHomeFlowBuilder},
+    optimizer::{FlowSolveError, FlowStats, Shortfall, SmartHomeFlow, SmartHomeFlowBuilder},
     optimizer_context::{
         OptimizerContext,
         action::{
-            constant::{self, AssignedConstantAction},
-            variable::VariableAction,
+            constant::AssignedConstantAction,
+            variable::{AssignedVariableAction, VariableAction},
         },
+        prognoses::Prognoses,
     },
-    schedule::Schedule,
+    schedule::{CostBreakdown, FlowEdge, Schedule},
+    simulated_annealing::AnnealingConfig,
     time::Time,
 };
 
+/// Start times of the constant actions of a [`State`], see [`State::snapshot`].
+#[derive(Clone, Debug)]
+pub struct StateSnapshot {
+    starts: HashMap<u32, Time>,
+}
+
 pub struct State {
     constant_actions: HashMap<u32, AssignedConstantAction>,
+    /// Constant actions whose window leaves room to move them.
     constant_action_ids: Vec<u32>,
+    /// Contiguous variable actions, placed as constant actions with the same id, see
+    /// [`OptimizerContext::with_blocks_as_constant_actions`].
+    contiguous_actions: HashMap<u32, Rc<VariableAction>>,
 
     smart_home_flow: SmartHomeFlow,
+    /// The context the state was created from, used to keep moves within the precedences.
+    context: OptimizerContext,
+    /// Cost added per unit of consumption the flow can't serve, see `unserved_penalty`.
+    unserved_penalty: i64,
+    /// Cost per unit of the peak network consumption, zero without a peak charge.
+    peak_price: i64,
+
+    /// Set while a change is being applied or undone, and left set if that got interrupted.
+    poisoned: bool,
 }
 
 impl State {
-    pub fn new_random<R: rand::Rng>(context: OptimizerContext, rng: &mut R) -> Self {
-        let constant_actions: HashMap<u32, AssignedConstantAction> = context
-            .get_constant_actions()
+    /// Creates a state with the constant actions at the `initial_starts` of `config`, clamped
+    /// into their windows, and the other constant actions at random starts.
+    pub fn new<R: rand::Rng>(
+        context: OptimizerContext,
+        rng: &mut R,
+        config: &AnnealingConfig,
+    ) -> Self {
+        let initial_starts = &config.initial_starts;
+        let contiguous_actions = context
+            .get_variable_actions()
             .iter()
-            .map(|action| {
-                // let start_minutes = action.get_start_from().get_minutes();
-                // let end_minutes =
-                //     action.get_end_before().get_minutes() - action.duration.get_minutes();
-                // let middle_minutes = (start_minutes + end_minutes) / 2;
-                // AssignedConstantAction::new(action.clone(), Time::new(0, middle_minutes))
-                // (
-                //     action.get_id(),
-                //     AssignedConstantAction::new(action.clone(), action.get_start_from()),
-                // )
-                let start_bound = action.get_start_from().to_timestep();
-                let end_bound =
-                    action.get_end_before().to_timestep() - action.duration.to_timestep();
-                let random_start_step = rng.random_range(start_bound..=end_bound);
-                (
-                    action.get_id(),
-                    AssignedConstantAction::new(
-                        action.clone(),
-                        Time::from_timestep(random_start_step),
-                    ),
-                )
-            })
+            .filter(|action| action.is_contiguous())
+            .map(|action| (action.get_id(), action.clone()))
             .collect();
-        let mut smart_home_flow = SmartHomeFlowBuilder::new(
-            context.get_generated_electricity(),
-            context.get_electricity_price(),
-            context.get_beyond_control_consumption(),
-            context.get_first_timestep_fraction(),
-        )
-        .add_batteries(context.get_batteries())
-        .add_actions(context.get_variable_actions())
-        .build();
+        let context = context.with_blocks_as_constant_actions();
+        // Place the actions in precedence order, each after its predecessors and early enough
+        // to leave room for its successors. Without precedences, this is the context order.
+        // Actions of exclusion groups start apart from those placed before them; if that
+        // leaves one no room, all actions are staggered as early as they can run instead.
+        // Initial starts are kept where they fit, only those overlapping an excluded action are
+        // drawn at random.
+        let latest_starts = context.get_latest_starts();
+        let mut constant_actions: HashMap<u32, AssignedConstantAction> = HashMap::new();
+        for action in context.get_constant_actions_in_precedence_order() {
+            let start_bound = context
+                .get_precedences()
+                .iter()
+                .filter(|&&(_, after)| after == action.get_id())
+                .map(|&(before, after)| {
+                    (constant_actions[&before].get_end_time() + context.get_min_gap(before, after))
+                        .to_timestep()
+                })
+                .fold(action.get_start_from().to_timestep(), u32::max);
+            let end_bound = latest_starts[&action.get_id()].to_timestep();
+            let grouped = context
+                .get_exclusion_groups()
+                .iter()
+                .any(|group| group.contains(&action.get_id()));
+            let placed = |id: u32| constant_actions.get(&id).map(|a| a.get_start_time());
+            let initial_start = initial_starts
+                .get(&action.get_id())
+                .map(|&start| start.clamp(start_bound as i64, end_bound as i64) as u32)
+                .filter(|&start| {
+                    !grouped
+                        || !context.overlaps_excluded(&action, Time::from_timestep(start), placed)
+                });
+            let random_start_step = if initial_start.is_some() {
+                initial_start
+            } else if grouped {
+                let starts: Vec<u32> = (start_bound..=end_bound)
+                    .filter(|&start| {
+                        !context.overlaps_excluded(&action, Time::from_timestep(start), placed)
+                    })
+                    .collect();
+                starts.choose(rng).copied()
+            } else {
+                Some(rng.random_range(start_bound..=end_bound))
+            };
+            let Some(random_start_step) = random_start_step else {
+                let starts = context.get_staggered_starts();
+                constant_actions = context
+                    .get_constant_actions()
+                    .iter()
+                    .map(|action| {
+                        let start = starts[&action.get_id()];
+                        let assigned = AssignedConstantAction::new(action.clone(), start);
+                        (action.get_id(), assigned)
+                    })
+                    .collect();
+                break;
+            };
+            constant_actions.insert(
+                action.get_id(),
+                AssignedConstantAction::new(action.clone(), Time::from_timestep(random_start_step)),
+            );
+        }
+        let mut smart_home_flow = SmartHomeFlowBuilder::from_context(&context)
+            .with_max_iterations(config.flow_max_iterations)
+            .build();
+        let unserved_penalty = unserved_penalty(&context);
 
         for (_, action) in constant_actions.iter() {
             smart_home_flow.add_constant_consumption(action.clone());
         }
 
-        let constant_action_ids = constant_actions.keys().cloned().collect();
+        // Keep the context order so that a seeded run picks the same actions every time.
+        // Actions that exactly fill their window have only one start and are never moved.
+        let constant_action_ids = context
+            .get_constant_actions()
+            .iter()
+            .filter(|action| action.get_end_before() - action.get_start_from() > action.duration)
+            .map(|action| action.get_id())
+            .collect();
 
         Self {
             constant_actions,
             constant_action_ids,
+            contiguous_actions,
             smart_home_flow,
+            unserved_penalty,
+            peak_price: context.get_peak_price().unwrap_or(0),
+            context,
+            poisoned: false,
         }
     }
     pub fn add_constant_action(&mut self, action: AssignedConstantAction) {
@@ -88,14 +167,151 @@ impl State {
         &self.constant_action_ids
     }
 
-    pub fn get_cost(&mut self) -> i64 {
-        self.smart_home_flow.get_cost()
+    /// Returns the earliest and latest start of a constant action that keep its window and the
+    /// precedences, with the other actions at the start times given by `start_of`.
+    /// `None` if there is no room to move it.
+    pub fn get_start_bounds(
+        &self,
+        action_id: u32,
+        start_of: impl Fn(u32) -> Time,
+    ) -> Option<(Time, Time)> {
+        self.context
+            .get_start_bounds(self.get_constant_action(action_id), start_of)
     }
 
-    pub fn get_schedule(&mut self) -> Schedule {
-        let mut schedule = self.smart_home_flow.get_schedule();
-        schedule.set_constant_actions(self.constant_actions.clone());
-        schedule
+    /// Whether a constant action starting at `start` would run at the same time as an action
+    /// that shares an exclusion group with it, with the other actions at the start times given
+    /// by `start_of`.
+    pub fn overlaps_excluded(
+        &self,
+        action_id: u32,
+        start: Time,
+        start_of: impl Fn(u32) -> Time,
+    ) -> bool {
+        self.context.overlaps_excluded(
+            self.get_constant_action(action_id).get_action(),
+            start,
+            |id| Some(start_of(id)),
+        )
+    }
+
+    /// Cost of the flow plus a penalty for consumption it can't serve, e.g. because constant
+    /// actions overlap while the grid limit is reached, and for charge missing to final charge
+    /// targets. Without the penalty, not serving an action would be cheaper than serving it.
+    ///
+    /// The peak charge is added on top. The flow can't express a maximum, so it is evaluated
+    /// on the cheapest flow for the current placement of the constant actions: batteries and
+    /// variable actions aren't moved to lower the peak, only the constant actions are.
+    /// The same goes for rounding variable actions to their minimum consumption, whose cost is
+    /// re-evaluated on the rounded schedule.
+    pub fn get_cost(&mut self) -> Result<i64, FlowSolveError> {
+        let rounding = self.smart_home_flow.get_min_consumption_rounding()?;
+        let cost = self.smart_home_flow.get_cost()? + rounding.cost;
+        let unserved = self.unserved(rounding.dropped)?;
+        let peak_cost = if self.peak_price == 0 {
+            0
+        } else {
+            self.smart_home_flow.get_peak_network_consumption()? * self.peak_price
+        };
+        Ok(cost + unserved * self.unserved_penalty + peak_cost)
+    }
+
+    /// The parts of `get_cost`, see [`CostBreakdown`]. They always sum up to `get_cost`.
+    pub fn get_cost_breakdown(&mut self) -> Result<CostBreakdown, FlowSolveError> {
+        let breakdown = self.smart_home_flow.get_cost_breakdown()?;
+        let rounding = self.smart_home_flow.get_min_consumption_rounding()?;
+        let unserved = self.unserved(rounding.dropped)?;
+        let peak_charge = if self.peak_price == 0 {
+            0
+        } else {
+            self.smart_home_flow.get_peak_network_consumption()? * self.peak_price
+        };
+        Ok(CostBreakdown {
+            unserved_penalty: unserved * self.unserved_penalty,
+            peak_charge,
+            ..breakdown
+        })
+    }
+
+    /// Consumption the flow can't serve, with `dropped` by rounding to minimum consumptions,
+    /// plus the charge missing to final charge targets. Each unit of it is penalized.
+    fn unserved(&mut self, dropped: i64) -> Result<i64, FlowSolveError> {
+        let missing: i64 = self
+            .smart_home_flow
+            .get_unmet_charge_targets()?
+            .iter()
+            .map(|&(_, missing)| missing)
+            .sum();
+        Ok(self.smart_home_flow.get_unserved_consumption()? + dropped + missing)
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    pub(crate) fn poison(&mut self) {
+        self.poisoned = true;
+    }
+
+    pub(crate) fn unpoison(&mut self) {
+        self.poisoned = false;
+    }
+
+    /// Consumption the flow of the current state can't serve, see
+    /// [`SmartHomeFlow::get_shortfall`].
+    pub fn get_shortfall(&mut self) -> Result<Option<Shortfall>, FlowSolveError> {
+        self.smart_home_flow.get_shortfall()
+    }
+
+    pub fn get_flow_stats(&self) -> FlowStats {
+        self.smart_home_flow.get_flow_stats()
+    }
+
+    /// Captures where the constant actions start. Everything else follows from the flow, so
+    /// this is enough to get back to the current state with [`State::restore`].
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            starts: self
+                .constant_actions
+                .iter()
+                .map(|(id, action)| (*id, action.get_start_time()))
+                .collect(),
+        }
+    }
+
+    /// Moves the constant actions back to the start times of a snapshot of this state. Only
+    /// the actions that moved since are updated in the flow.
+    pub fn restore(&mut self, snapshot: &StateSnapshot) {
+        self.poison();
+        for (&id, &start) in &snapshot.starts {
+            if self.get_constant_action(id).get_start_time() == start {
+                continue;
+            }
+            let action = self.remove_constant_action(id).unwrap();
+            self.add_constant_action(action.get_action().clone().with_start_time(start));
+        }
+        self.unpoison();
+    }
+
+    /// Every edge of the solved flow network, see [`Schedule::get_flow_table`].
+    pub fn get_flow_table(&mut self) -> Result<Vec<FlowEdge>, FlowSolveError> {
+        self.smart_home_flow.get_flow_table()
+    }
+
+    pub fn get_schedule(&mut self) -> Result<Schedule, FlowSolveError> {
+        let mut schedule = self.smart_home_flow.get_schedule()?;
+        let mut constant_actions = self.constant_actions.clone();
+        for (id, action) in &self.contiguous_actions {
+            let block = constant_actions.remove(id).unwrap();
+            let assigned = AssignedVariableAction::from_block(
+                action.clone(),
+                &block,
+                self.context.get_first_timestep_fraction(),
+            );
+            schedule.variable_actions.insert(*id, assigned);
+        }
+        schedule.set_constant_actions(constant_actions);
+        Ok(schedule)
     }
     // pub fn to_fixed_context(&self) -> OptimizerContext {
     //     let mut new_context = self.context.clone();
@@ -105,3 +321,22 @@ impl State {
     //     new_context
     // }
 }
+
+/// Penalty per unit of unserved consumption or charge missing to a final charge target.
+///
+/// Serving a unit costs at most the highest price plus the highest network fee and CO2 cost,
+/// twice that with charging losses of a battery down to 50% efficiency. Not serving it frees at
+/// most the highest feed-in tariff. Anything above the sum makes serving everything the cheapest option.
+fn unserved_penalty(context: &OptimizerContext) -> i64 {
+    let max = |prognoses: Option<&Prognoses<i64>>| {
+        prognoses
+            .and_then(|prognoses| prognoses.get_data().iter().copied().max())
+            .unwrap_or(0)
+            .max(0)
+    };
+    2 * (max(Some(context.get_electricity_price()))
+        + max(context.get_network_fee().map(|fee| &**fee))
+        + max(context.get_co2_cost().as_ref()))
+        + max(context.get_feed_in_tariff().map(|tariff| &**tariff))
+        + 1
+}
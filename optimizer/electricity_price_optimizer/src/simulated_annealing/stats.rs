@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use crate::schedule::CostBreakdown;
+
+/// Statistics collected during a simulated annealing run.
+#[derive(Debug, Clone, Default)]
+pub struct SolveStats {
+    /// Number of annealing iterations.
+    pub iterations: usize,
+    /// Number of accepted changes.
+    pub accepted: usize,
+    /// Number of times the flow had to be solved.
+    pub flow_solves: usize,
+    /// Number of cost queries answered from the last flow solution without solving again.
+    pub flow_reuses: usize,
+    /// Total time spent solving flows.
+    pub flow_solve_time: Duration,
+    /// Number of paths the flow solves sent flow along.
+    pub flow_augmentations: u64,
+    /// Number of negative cycles the flow solves sent flow around.
+    pub flow_cycle_cancellations: u64,
+    /// Number of moves per iteration over time as `(iteration, size)` pairs.
+    /// Contains the initial size at iteration 0 and an entry for every change.
+    pub move_sizes: Vec<(usize, usize)>,
+    /// Wall time of the whole run.
+    pub elapsed: Duration,
+    /// True if the run was stopped early through [`AnnealingConfig::cancel`].
+    ///
+    /// [`AnnealingConfig::cancel`]: crate::simulated_annealing::AnnealingConfig::cancel
+    pub cancelled: bool,
+    /// Parts of the returned cost. Always set by a finished run, while the schedule only
+    /// carries them with [`AnnealingConfig::explain_cost`].
+    ///
+    /// [`AnnealingConfig::explain_cost`]: crate::simulated_annealing::AnnealingConfig::explain_cost
+    pub objective_breakdown: Option<CostBreakdown>,
+}
+
+impl SolveStats {
+    /// Fraction of iterations whose change was accepted.
+    pub fn acceptance_rate(&self) -> f64 {
+        if self.iterations == 0 {
+            0.0
+        } else {
+            self.accepted as f64 / self.iterations as f64
+        }
+    }
+
+    /// Average time of a single flow solve.
+    pub fn average_flow_solve_time(&self) -> Duration {
+        if self.flow_solves == 0 {
+            Duration::ZERO
+        } else {
+            self.flow_solve_time / self.flow_solves as u32
+        }
+    }
+}
@@ -3,60 +3,111 @@ use std::{
     ops::{Add, Range, Sub},
 };
 
+/// Length of a timestep at the default resolution.
 pub const MINUTES_PER_TIMESTEP: u32 = 1;
 
 const MINUTES_PER_DAY: u32 = 60 * 24;
+/// Timesteps per day at the default resolution, also the most a day can be divided into.
 pub const STEPS_PER_DAY: u32 = MINUTES_PER_DAY / MINUTES_PER_TIMESTEP;
 
-/// Represents a specific time of day in minutes.
+/// Length of the timesteps the day is divided into, e.g. 15 minutes for quarter-hour prices.
+/// The prognoses of a context have one value per timestep, so their length sets it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimestepConfig {
+    minutes_per_timestep: u32,
+}
+
+impl TimestepConfig {
+    /// Timesteps of `minutes_per_timestep` minutes, None unless they evenly divide the day.
+    pub fn new(minutes_per_timestep: u32) -> Option<Self> {
+        (minutes_per_timestep > 0 && MINUTES_PER_DAY.is_multiple_of(minutes_per_timestep))
+            .then_some(Self {
+                minutes_per_timestep,
+            })
+    }
+
+    /// Timesteps dividing the day into `steps_per_day`, None unless they are whole minutes.
+    pub fn from_steps_per_day(steps_per_day: u32) -> Option<Self> {
+        if steps_per_day == 0 || !MINUTES_PER_DAY.is_multiple_of(steps_per_day) {
+            return None;
+        }
+        Self::new(MINUTES_PER_DAY / steps_per_day)
+    }
+
+    pub fn get_minutes_per_timestep(&self) -> u32 {
+        self.minutes_per_timestep
+    }
+
+    pub fn get_steps_per_day(&self) -> u32 {
+        MINUTES_PER_DAY / self.minutes_per_timestep
+    }
+
+    /// Returns the end of the last timestep of the day.
+    pub fn get_day_end(&self) -> Time {
+        Time::from_timestep(self.get_steps_per_day())
+    }
+}
+
+impl Default for TimestepConfig {
+    fn default() -> Self {
+        Self {
+            minutes_per_timestep: MINUTES_PER_TIMESTEP,
+        }
+    }
+}
+
+/// Represents a specific time of day in timesteps since the start. How long a timestep is
+/// depends on the [`TimestepConfig`] of the context.
 /// Provides methods for conversion between time and timesteps.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Time {
-    /// Total minutes since the current time.
-    pub(crate) minutes: u32,
+    /// Total timesteps since the current time.
+    #[cfg_attr(feature = "serde", serde(alias = "minutes"))]
+    pub(crate) timestep: u32,
 }
 
 impl Time {
-    /// Creates a new Time instance from hours and minutes.
+    /// Creates the Time of the timestep that contains the time of day `hours:minutes`.
     ///
     /// # Arguments
     /// * `hours` - The hour component of the time.
     /// * `minutes` - The minute component of the time.
+    /// * `timestep` - Length of the timesteps.
     /// # Returns
     /// * A new Time instance.
-    pub fn new(hours: u32, minutes: u32) -> Self {
+    pub fn from_clock(hours: u32, minutes: u32, timestep: TimestepConfig) -> Self {
         Self {
-            minutes: hours * 60 + minutes,
+            timestep: (hours * 60 + minutes) / timestep.get_minutes_per_timestep(),
         }
     }
 
+    /// Creates the Time of `hours:minutes` at the default resolution, see
+    /// [`Time::from_clock`] for others.
+    pub fn at_default_resolution(hours: u32, minutes: u32) -> Self {
+        Self::from_clock(hours, minutes, TimestepConfig::default())
+    }
+
     /// Converts the Time instance to a timestep.
     pub fn to_timestep(&self) -> u32 {
-        self.minutes / MINUTES_PER_TIMESTEP
+        self.timestep
     }
 
     /// Creates a Time instance from a given timestep.
     pub fn from_timestep(timestep: u32) -> Self {
-        Self {
-            minutes: timestep * MINUTES_PER_TIMESTEP,
-        }
-    }
-
-    /// Returns the total minutes since the current time.
-    pub fn get_minutes(&self) -> u32 {
-        self.minutes
+        Self { timestep }
     }
 
     pub fn get_next_timestep(&self) -> Time {
         Time {
-            minutes: self.minutes + MINUTES_PER_TIMESTEP,
+            timestep: self.timestep + 1,
         }
     }
 
+    /// Returns the end of the day at the default resolution, see
+    /// [`TimestepConfig::get_day_end`] for others.
     pub fn get_day_end() -> Time {
-        Time {
-            minutes: MINUTES_PER_DAY,
-        }
+        TimestepConfig::default().get_day_end()
     }
 }
 
@@ -65,7 +116,7 @@ impl Add<Time> for Time {
 
     fn add(self, other: Time) -> Time {
         Time {
-            minutes: self.minutes + other.minutes,
+            timestep: self.timestep + other.timestep,
         }
     }
 }
@@ -75,7 +126,7 @@ impl Sub<Time> for Time {
 
     fn sub(self, other: Time) -> Time {
         Time {
-            minutes: self.minutes - other.minutes,
+            timestep: self.timestep - other.timestep,
         }
     }
 }
@@ -93,10 +144,149 @@ impl TimeIterator for Range<Time> {
     }
 }
 
+/// Shows the timestep, the time of day it stands for depends on the [`TimestepConfig`].
 impl Debug for Time {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let hours = self.minutes / 60;
-        let minutes = self.minutes % 60;
-        write!(f, "{:02}:{:02}", hours, minutes)
+        write!(f, "timestep {}", self.timestep)
+    }
+}
+
+/// Settlement periods of equal length covering the day, e.g. the 15-minute periods in which
+/// grid imports are billed. The first full period starts `offset` timesteps into the day, the
+/// timesteps before it and those left at the end of the day form shorter periods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettlementPeriods {
+    /// Timesteps per period.
+    length: u32,
+    /// Timestep the first full period starts at, less than `length`.
+    offset: u32,
+}
+
+impl SettlementPeriods {
+    /// Creates periods of `length` timesteps, aligned such that one starts at timestep `offset`.
+    ///
+    /// # Panics
+    /// Panics if `length` is zero.
+    pub fn new(length: u32, offset: u32) -> Self {
+        assert!(
+            length > 0,
+            "Settlement periods must be at least one timestep long"
+        );
+        Self {
+            length,
+            offset: offset % length,
+        }
+    }
+
+    pub fn get_length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn get_offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Timesteps of every period of a day of `steps` timesteps in order, the first and last
+    /// may be shorter.
+    pub fn ranges(&self, steps: u32) -> impl Iterator<Item = Range<u32>> + use<> {
+        let length = self.length;
+        let first = (self.offset > 0).then_some(0..self.offset.min(steps));
+        let full = (self.offset..steps)
+            .step_by(length as usize)
+            .map(move |start| start..(start + length).min(steps));
+        first.into_iter().chain(full)
+    }
+
+    /// Average of `values` per timestep over every period, as the start of the period and the
+    /// average. The values of a period are summed up exactly and divided once, rounding down.
+    /// The day has one timestep per value.
+    pub fn averages(&self, values: &[i64]) -> Vec<(Time, i64)> {
+        self.ranges(values.len() as u32)
+            .map(|range| range.start as usize..range.end as usize)
+            .filter(|range| !range.is_empty())
+            .map(|range| {
+                let sum: i64 = values[range.clone()].iter().sum();
+                (
+                    Time::from_timestep(range.start as u32),
+                    sum.div_euclid(range.len() as i64),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Every timestep is a period of its own.
+impl Default for SettlementPeriods {
+    fn default() -> Self {
+        Self::new(1, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestep_configs_divide_the_day() {
+        let quarter_hour = TimestepConfig::new(15).unwrap();
+        assert_eq!(quarter_hour.get_steps_per_day(), 96);
+        assert_eq!(quarter_hour.get_day_end(), Time::from_timestep(96));
+        assert_eq!(
+            TimestepConfig::from_steps_per_day(24),
+            TimestepConfig::new(60)
+        );
+        assert_eq!(TimestepConfig::default().get_steps_per_day(), STEPS_PER_DAY);
+        for minutes in [0, 7, 25 * 60] {
+            assert_eq!(TimestepConfig::new(minutes), None);
+        }
+        // A day can't be divided into fractions of a minute
+        assert_eq!(TimestepConfig::from_steps_per_day(2 * STEPS_PER_DAY), None);
+    }
+
+    #[test]
+    fn test_clock_times_depend_on_the_resolution() {
+        let quarter_hour = TimestepConfig::new(15).unwrap();
+        assert_eq!(
+            Time::from_clock(6, 30, quarter_hour),
+            Time::from_timestep(26)
+        );
+        // Within a timestep, the time of day belongs to the timestep it starts in
+        assert_eq!(
+            Time::from_clock(6, 44, quarter_hour),
+            Time::from_timestep(26)
+        );
+        assert_eq!(Time::at_default_resolution(6, 30), Time::from_timestep(390));
+        assert_eq!(format!("{:?}", Time::from_timestep(26)), "timestep 26");
+    }
+
+    #[test]
+    fn test_settlement_periods_cover_the_day() {
+        let periods = SettlementPeriods::new(15, 20);
+        let ranges: Vec<_> = periods.ranges(STEPS_PER_DAY).collect();
+        assert_eq!(ranges[0], 0..5);
+        assert_eq!(ranges[1], 5..20);
+        assert_eq!(ranges.last(), Some(&(1430..STEPS_PER_DAY)));
+        let mut covered = 0;
+        for range in &ranges {
+            assert_eq!(range.start, covered);
+            covered = range.end;
+        }
+        assert_eq!(covered, STEPS_PER_DAY);
+    }
+
+    #[test]
+    fn test_settlement_averages_are_exact() {
+        let periods = SettlementPeriods::new(3, 1);
+        let values = [7, 1, 1, 2, 5, 5, 6];
+        assert_eq!(
+            periods.averages(&values),
+            vec![
+                (Time::from_timestep(0), 7),
+                // 4 / 3 rounds down
+                (Time::from_timestep(1), 1),
+                (Time::from_timestep(4), 5),
+            ]
+        );
     }
 }
@@ -4,8 +4,21 @@ from .electricity_price_optimizer_py import *
 from . import units
 __all__ = [
     "units",
+    "OptimizerError",
+    "TimestepAlignmentError",
+    "InfeasibleError",
+    "PrognosesError",
+    "UnitError",
+    "OutOfRange",
+    "SolverStalled",
+    "InternalConsistencyError",
+    "OptimizerWarning",
+    "PlausibilityLimits",
     "PrognosesProvider",
+    "Prognoses",
     "ConstantAction",
+    "ProfiledConstantAction",
+    "RecurringConstantAction",
     "AssignedConstantAction",
     "VariableAction",
     "AssignedVariableAction",
@@ -13,5 +26,17 @@ __all__ = [
     "AssignedBattery",
     "OptimizerContext",
     "Schedule",
+    "ScheduleRecordIterator",
+    "ScheduleDiff",
+    "Solver",
+    "CancellationToken",
+    "OptimizerOptions",
+    "OptimizeResult",
     "run_simulated_annealing",
+    "run_exact",
+    "load_problem",
+    "create_audit_bundle",
+    "verify_audit_bundle",
+    "theoretical_best",
+    "set_instrumentation",
 ]
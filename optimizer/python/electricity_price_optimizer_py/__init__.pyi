@@ -1,13 +1,151 @@
-from datetime import datetime, timedelta
-from typing import Callable, Optional, Tuple
+from datetime import datetime, time, timedelta
+from typing import Any, Callable, Dict, Iterator, List, Optional, Sequence, Tuple, Union
 from typing import Generic, TypeVar
 from . import units as units
 
 T = TypeVar('T')
 
 
+class OptimizerError(ValueError):
+    """
+    Base class of the errors raised for invalid inputs and problems that can't be solved.
+
+    It is a ValueError, so code catching ValueError keeps working. The subclasses below tell
+    the failures apart, other invalid inputs raise OptimizerError itself. Failures to read or
+    write files raise OSError and bugs of the library RuntimeError instead.
+    """
+
+
+class TimestepAlignmentError(OptimizerError):
+    """
+    Raised when a time or duration isn't on the timesteps of the optimizer, e.g. a datetime
+    between two timestep boundaries or a duration that isn't a multiple of the timestep.
+    """
+
+
+class InfeasibleError(OptimizerError):
+    """
+    Raised when the constraints can't all be met, e.g. an action that doesn't fit into its
+    window, a fixed battery plan that breaks the battery's limits or beyond control
+    consumption above the grid limit.
+
+    When an optimization can't serve all consumption or reach every final charge target, the
+    message lists what is missing and which limits are in the way. The same is attached as attributes:
+
+    Attributes:
+        unserved: (datetime, Watt) of the beyond control consumption that can't be served,
+            per timestep.
+        unserved_actions: The energy missing from each variable action, by ID.
+        unmet_targets: The energy missing to each battery's final charge target, by ID.
+        binding: (kind, id, datetime) of every limit in the way. kind is "max_consumption"
+            of a variable action (no datetime), "grid_limit" (no id), "battery_empty" or
+            "battery_max_output".
+    """
+
+
+class PrognosesError(OptimizerError):
+    """
+    Raised when prognoses can't be read, e.g. because their callable raised or returned an
+    invalid value, or because they don't cover the day. An error of the callable is the
+    `__cause__`.
+    """
+
+
+class UnitError(OptimizerError):
+    """
+    Raised when a value can't be represented in the units of the optimizer, e.g. because it
+    is too large. Also raised by prognoses returning such a value.
+    """
+
+
+class OutOfRange(OptimizerError):
+    """Raised when a time is outside of the range an accessor covers."""
+    time: datetime
+    """The queried time."""
+    start: datetime
+    """The first valid time."""
+    end: datetime
+    """The last valid time."""
+    asset_id: Optional[int]
+    """The ID of the queried battery or variable action, None for queries of the whole schedule."""
+
+
+class SolverStalled(OptimizerError):
+    """
+    Raised when the min cost flow doesn't finish within its iteration cap, e.g. because the flow
+    network has a cycle of negative cost.
+    """
+    phase: str
+    """Shortest path search it stopped in, 'spfa' or 'dijkstra'."""
+    iterations: int
+    """Nodes the searches took from their queues."""
+    augmentations: int
+    """Paths flow was sent along."""
+    flow: units.WattHour
+    """Flow sent from the source to the sink so far."""
+    cost: units.Euro
+    """Cost of that flow."""
+
+
+class InternalConsistencyError(RuntimeError):
+    """
+    Raised when the optimizer produced a schedule that violates its context.
+
+    This is a bug in the library, not in the context. The message lists every violation.
+    """
+
+
+class OptimizerWarning(UserWarning):
+    """
+    Warns about an input that is valid but most likely a mistake, e.g. prognoses in the wrong
+    unit. See `PlausibilityLimits`.
+    """
+
+
+class PlausibilityLimits:
+    """
+    Limits of plausible prognoses, checked when prognoses are added to an `OptimizerContext`.
+
+    Values outside of them are most likely given in the wrong unit, e.g. 1000 times too large.
+    They warn with an `OptimizerWarning` quoting the first such value and its interval, or
+    raise ValueError in strict mode.
+    """
+
+    max_generation: units.Watt
+    """Highest plausible generated electricity, as average power per timestep."""
+    max_base_load: units.Watt
+    """Highest plausible beyond control consumption, as average power per timestep."""
+    max_price: units.EuroPerWh
+    """Highest plausible electricity price, feed-in tariff and network fee."""
+    min_price: units.EuroPerWh
+    """Lowest plausible electricity price, feed-in tariff and network fee."""
+    strict: bool
+    """Raise ValueError instead of warning."""
+
+    def __init__(
+        self,
+        max_generation: Optional[units.Watt] = None,
+        max_base_load: Optional[units.Watt] = None,
+        max_price: Optional[units.EuroPerWh] = None,
+        min_price: Optional[units.EuroPerWh] = None,
+        strict: bool = False,
+    ) -> None:
+        """
+        Limits that aren't given fit a household: 100 kW of generation, 50 kW of base load
+        and prices between -5 and 10 €/kWh.
+        """
+        ...
+
+
 class PrognosesProvider(Generic[T]):
-    """Provides prognosis data via a callback function."""
+    """
+    Provides prognosis data via a callback function or a fixed sequence of values.
+
+    A provider is immutable. One provider can be shared by any number of threads, which may
+    materialize it at the same time, e.g. workers of a batch runner sharing the price
+    forecast. Each materialization holds the GIL while it calls get_data, so get_data must be
+    safe to call from these threads. Copies share get_data and the values.
+    """
 
     def __init__(self, get_data: Callable[[datetime, datetime], T]) -> None:
         """
@@ -16,12 +154,129 @@ class PrognosesProvider(Generic[T]):
         Args:
             get_data: A function that takes a (start_time, end_time) and returns 
                       the predicted value for that interval.
+
+        If get_data raises or returns a value of the wrong type for an interval, the method
+        consuming the provider raises a PrognosesError naming that interval, with the original
+        exception as its __cause__.
+        """
+        ...
+
+    @staticmethod
+    def from_values(
+        values: Sequence[T],
+        start_time: datetime,
+        resolution: Optional[timedelta] = None,
+    ) -> "PrognosesProvider[T]":
+        """
+        Create a provider serving fixed values instead of calling back into Python.
+
+        Args:
+            values: One value (EuroPerWh, WattHour, Watt, Euro or float) per resolution,
+                    e.g. a list or numpy array.
+            start_time: The time the first value starts at.
+            resolution: The duration each value covers, defaults to one minute.
+
+        Raises PrognosesError if the values don't cover exactly one day, naming the index of
+        any value that is not a unit or float.
+        """
+        ...
+
+    @staticmethod
+    def two_register(
+        high: units.EuroPerWh,
+        low: units.EuroPerWh,
+        low_start: time,
+        low_end: time,
+        tz: str,
+    ) -> "PrognosesProvider[units.EuroPerWh]":
+        """
+        Create a provider for a two-register (high/low) tariff instead of calling back into
+        Python.
+
+        Args:
+            high: The price outside of the low window.
+            low: The price from `low_start` to `low_end`.
+            low_start: The local time the low window starts at, e.g. 22:00.
+            low_end: The local time the low window ends at, e.g. 06:00. The window may cross
+                     midnight.
+            tz: The IANA time zone of the window, e.g. "Europe/Berlin". The window follows
+                daylight saving time, so it is an hour shorter or longer on change nights.
+
+        Raises OptimizerError if the time zone is unknown or the window is empty.
+        """
+        ...
+
+    def __copy__(self) -> "PrognosesProvider[T]": ...
+
+    def __deepcopy__(self, memo: Any) -> "PrognosesProvider[T]": ...
+
+
+class Prognoses:
+    """
+    Prognoses for one day, materialized into one float per timestep.
+
+    Unit values are stored by their value, so a price prognosis holds Euro per Wh. Unlike
+    contexts and schedules, prognoses can be passed between threads.
+    """
+    start_time: datetime
+
+    def __init__(self, start_time: datetime, values: List[float]) -> None:
+        """
+        Args:
+            start_time: Start of the first timestep.
+            values: One value per timestep of the day.
+
+        Raises:
+            ValueError: If there isn't exactly one value per timestep.
+        """
+        ...
+
+    @staticmethod
+    def from_provider(
+        provider: PrognosesProvider[Any],
+        start_time: datetime,
+        resolution: Optional[timedelta] = None,
+    ) -> Prognoses:
+        """
+        Calls the provider for every timestep of the day starting at start_time, one per
+        resolution (default: one minute).
+        """
+        ...
+
+    def values(self) -> List[float]:
+        """The value of every timestep."""
+        ...
+
+    def __len__(self) -> int: ...
+
+    def __eq__(self, other: object) -> bool: ...
+
+    def to_json(self) -> str:
+        """Serializes the prognoses to JSON."""
+        ...
+
+    @staticmethod
+    def from_json(s: str) -> Prognoses:
+        """Rebuilds prognoses created by `to_json`."""
+        ...
+
+    def to_bytes(self) -> bytes:
+        """Serializes the prognoses to a compact binary form, see `Schedule.to_bytes`."""
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes) -> Prognoses:
+        """
+        Rebuilds prognoses created by `to_bytes`.
+
+        Raises:
+            ValueError: If the data is not a prognoses blob of this format version.
         """
         ...
 
 
 class ConstantAction:
-    """An action with a fixed duration and constant consumption rate."""
+    """An action with a fixed duration and constant consumption rate. Can be pickled."""
     start_from: datetime
     end_before: datetime
     duration: timedelta
@@ -34,6 +289,8 @@ class ConstantAction:
         duration: timedelta,
         consumption: units.Watt,
         id: int,
+        default_profile: Optional[PrognosesProvider[units.WattHour]] = None,
+        absolute: bool = False,
     ) -> None:
         """
         Args:
@@ -42,6 +299,99 @@ class ConstantAction:
             duration: The duration of the action (must be < 1 day and a multiple of the timestep).
             consumption: The fixed consumption amount for every timestep.
             id: Unique identifier for the action.
+            default_profile: The consumption per interval if the optimizer doesn't schedule the
+                action, e.g. a water heater heating whenever its thermostat asks. It must be
+                part of the beyond control consumption of the context. While the action is
+                scheduled it is removed from it (at most down to zero), while the action is
+                disabled with `OptimizerContext.set_enabled` it stays. A provider can't be
+                pickled.
+            absolute: Keep the window at its datetimes when the context is rebased, see
+                `OptimizerContext.rebase`. Otherwise it moves along with the context start.
+        """
+        ...
+
+    @staticmethod
+    def with_deadline(
+        deadline: datetime,
+        duration: timedelta,
+        consumption: units.Watt,
+        id: int,
+        default_profile: Optional[PrognosesProvider[units.WattHour]] = None,
+        absolute: bool = False,
+    ) -> ConstantAction:
+        """
+        Creates an action that can start any time after the context start and must be
+        finished by the deadline. The window is resolved when the action is added to a context,
+        which raises ValueError if the action doesn't fit before the deadline.
+        """
+        ...
+
+
+class ProfiledConstantAction:
+    """An action with a fixed duration whose consumption follows a given profile."""
+    start_from: datetime
+    end_before: datetime
+    profile: List[units.Watt]
+
+    def __init__(
+        self,
+        start_from: datetime,
+        end_before: datetime,
+        profile: List[units.Watt],
+        id: int,
+        absolute: bool = False,
+    ) -> None:
+        """
+        Args:
+            start_from: The earliest time the action can start.
+            end_before: The latest time the action must end before.
+            profile: The consumption for every timestep of the action, starting at its
+                start time. The duration is one timestep per value.
+            id: Unique identifier for the action.
+            absolute: Keep the window at its datetimes when the context is rebased, see
+                `OptimizerContext.rebase`.
+        """
+        ...
+
+
+class RecurringConstantAction:
+    """A constant action that runs `count` times in separate blocks, e.g. a pool pump running
+    three one hour blocks a day. Block k is a constant action with the id `id * 1000 + k`.
+    """
+    start_from: datetime
+    end_before: datetime
+    count: int
+    duration: timedelta
+    min_gap: timedelta
+    consumption: units.Watt
+
+    def __init__(
+        self,
+        start_from: datetime,
+        end_before: datetime,
+        count: int,
+        duration: timedelta,
+        min_gap: timedelta,
+        consumption: units.Watt,
+        id: int,
+    ) -> None:
+        """
+        Args:
+            start_from: The earliest time the first block can start.
+            end_before: The latest time the last block must end before.
+            count: Number of blocks, between 1 and 999.
+            duration: Duration of every block.
+            min_gap: Time between the end of a block and the start of the next at the least.
+            consumption: Constant power usage of every block.
+            id: Unique identifier, the blocks use the ids from `id * 1000` on.
+        """
+        ...
+
+    def block_ids(self) -> List[int]:
+        """Returns the ids of the blocks in the order they run.
+
+        Raises:
+            OptimizerError: If count is out of range or the ids don't fit into 32 bits.
         """
         ...
 
@@ -57,33 +407,110 @@ class AssignedConstantAction:
         """Returns the scheduled end time."""
         ...
 
+    def get_consumption_profile(self) -> List[Tuple[datetime, units.Watt]]:
+        """Returns the consumption of every timestep from start to end."""
+        ...
+
+    def get_consumption(self) -> units.Watt:
+        """Returns the power the action runs at.
+
+        Raises:
+            ValueError: If the consumption varies over the duration (ProfiledConstantAction),
+                use get_consumption_profile instead.
+        """
+        ...
+
+    def get_duration(self) -> timedelta:
+        """Returns how long the action runs, as given when creating it."""
+        ...
+
+    def get_window(self) -> Tuple[datetime, datetime]:
+        """Returns the window the action had to run in as (start_from, end_before).
+        Actions created with a deadline start their window at the context start."""
+        ...
+
     def get_id(self) -> int:
         """Returns the action's unique identifier."""
         ...
 
+    def __repr__(self) -> str:
+        """Shows the ID, the start time and the power, "varying" for profiled actions."""
+        ...
+
 
 class VariableAction:
-    """An action where consumption can be spread flexibly over a time window."""
+    """
+    An action where consumption can be spread flexibly over a time window.
+    Can be pickled, unless max_consumption or preference is a PrognosesProvider.
+    """
     start: datetime
     end: datetime
     total_consumption: units.WattHour
-    max_consumption: units.Watt
+    max_consumption: Union[units.Watt, PrognosesProvider, List[units.Watt]]
+    min_consumption: units.Watt
+    contiguous: bool
 
     def __init__(
         self,
         start: datetime,
         end: datetime,
         total_consumption: units.WattHour,
-        max_consumption: units.Watt,
+        max_consumption: Union[units.Watt, PrognosesProvider, List[units.Watt]],
         id: int,
+        min_consumption: Optional[units.Watt] = None,
+        preference: Optional[PrognosesProvider] = None,
+        preference_limit: float = 0.1,
+        contiguous: bool = False,
+        absolute: bool = False,
     ) -> None:
         """
         Args:
             start: The earliest time the action can start.
             end: The latest time the action must end.
             total_consumption: The total energy consumption required.
-            max_consumption: The maximum consumption allowed in a single timestep.
+            max_consumption: The maximum consumption allowed in a single timestep. Either
+                constant, a PrognosesProvider returning Watt per interval, or a list with one
+                Watt per timestep of the window. If it varies, adding the action to a context
+                raises ValueError if total_consumption can't be reached, naming the shortfall.
             id: Unique identifier for the action.
+            min_consumption: The least power of every timestep the action consumes anything
+                in, e.g. for chargers that can't modulate below it. Defaults to 0. The
+                optimizer rounds its result to it, keeping the total. Adding the action to a
+                context raises ValueError if total_consumption can't be split into timesteps
+                of at least min_consumption.
+            preference: A PrognosesProvider returning EuroPerWh per interval, added to the
+                price the action sees to prefer some intervals over others, e.g. increasing
+                over the window to consume as early as the prices allow. It isn't paid and is
+                reported as "preference" in the objective breakdown.
+            preference_limit: Bound of the preference as a share of the average absolute
+                electricity price, so that it only decides between similar prices.
+            contiguous: Consume the total in one uninterrupted block of consecutive
+                timesteps, e.g. for a hot water boost. The block runs at a fixed rate as high
+                as the smallest max_consumption of the window allows and is placed by
+                `run_simulated_annealing` like a constant action, ignoring the preference.
+                Adding the action to a context raises InfeasibleError if the block doesn't fit
+                into the window or runs below min_consumption.
+            absolute: Keep the window at its datetimes when the context is rebased, see
+                `OptimizerContext.rebase`.
+        """
+        ...
+
+    @staticmethod
+    def with_deadline(
+        deadline: datetime,
+        total_consumption: units.WattHour,
+        max_consumption: Union[units.Watt, PrognosesProvider, List[units.Watt]],
+        id: int,
+        min_consumption: Optional[units.Watt] = None,
+        preference: Optional[PrognosesProvider] = None,
+        preference_limit: float = 0.1,
+        contiguous: bool = False,
+        absolute: bool = False,
+    ) -> VariableAction:
+        """
+        Creates an action that can consume any time after the context start and must be
+        finished by the deadline. The window is resolved when the action is added to a context,
+        which raises ValueError if total_consumption can't be consumed before the deadline.
         """
         ...
 
@@ -91,8 +518,23 @@ class VariableAction:
 class AssignedVariableAction:
     """An instance of a VariableAction with consumption allocated across the schedule."""
 
-    def get_consumption(self, time: datetime) -> units.Watt:
-        """Returns the allocated consumption for the specific timestep."""
+    def get_consumption(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns the allocated consumption for the specific timestep within the action's window.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_consumption_profile(self) -> List[Tuple[datetime, units.Watt]]:
+        """Returns the allocated consumption of every timestep from start to end."""
+        ...
+
+    def get_total_assigned(self) -> units.WattHour:
+        """Returns the total energy allocated over the whole profile."""
         ...
 
     def get_id(self) -> int:
@@ -101,11 +543,17 @@ class AssignedVariableAction:
 
 
 class Battery:
-    """Represents a physical battery for energy storage."""
+    """Represents a physical battery for energy storage. Can be pickled."""
     capacity: units.WattHour
     max_charge_rate: units.Watt
     max_discharge_rate: units.Watt
     initial_charge: units.WattHour
+    efficiency: float
+    grid_charging_allowed: bool
+    min_charge: units.WattHour
+    final_charge_target: Optional[units.WattHour]
+    self_discharge_per_hour: float
+    cycle_cost: units.EuroPerWh
 
     def __init__(
         self,
@@ -114,6 +562,12 @@ class Battery:
         max_discharge_rate: units.Watt,
         initial_charge: units.WattHour,
         id: int,
+        efficiency: float = 1.0,
+        grid_charging_allowed: bool = True,
+        min_charge: units.WattHour = units.WattHour(0.0),
+        final_charge_target: Optional[units.WattHour] = None,
+        self_discharge_per_hour: float = 0.0,
+        cycle_cost: units.EuroPerWh = units.EuroPerWh(0.0),
     ) -> None:
         """
         Args:
@@ -122,6 +576,24 @@ class Battery:
             max_discharge_rate: Maximum units of energy removed per timestep.
             initial_charge: Starting energy level.
             id: Unique identifier for the battery.
+            efficiency: Charging efficiency in (0, 1]. Storing one unit draws 1 / efficiency
+                units; charge levels always report the stored (net) energy, the network
+                consumption includes the loss.
+            grid_charging_allowed: If False, the battery is only charged from locally
+                generated electricity (e.g. PV), never from the grid.
+            min_charge: Reserve the charge level never drops below.
+            final_charge_target: Charge level the battery should have at least at the end
+                of the horizon.
+            self_discharge_per_hour: Fraction of the stored charge lost per hour, in [0, 1).
+                The loss is priced at the import price rather than taken off the charge
+                levels, so holding charge costs what refilling it would.
+            cycle_cost: Wear cost of every Wh stored. The battery is only cycled when that
+                saves more than the wear, e.g. not at flat prices.
+
+        Raises:
+            ValueError: If efficiency is not in (0, 1], if self_discharge_per_hour is not
+                in [0, 1), if cycle_cost is negative, if min_charge is above the capacity
+                or the initial charge, or if final_charge_target is above the capacity.
         """
         ...
 
@@ -129,12 +601,86 @@ class Battery:
 class AssignedBattery:
     """A battery's state over the course of a schedule."""
 
-    def get_charge_level(self, time: datetime) -> units.WattHour:
-        """Returns the battery charge level at the given time."""
+    def get_charge_level(self, time: datetime, clamp: bool = False) -> units.WattHour:
+        """
+        Returns the charge level at the start of the timestep beginning at `time`, before any
+        charging or discharging during it. The end of the horizon gives the final level.
+
+        With clamp, times before the start give the initial and times after the end of the
+        horizon the final level instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the schedule and clamp is False.
+        """
+        ...
+
+    def get_charge_speed(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns the charge (positive) or discharge (negative) speed during the timestep
+        beginning at `time`, i.e. the difference between the levels at its start and its end.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_discharge_headroom(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns how much faster than planned the battery could discharge during the timestep
+        beginning at `time`, limited by its maximum output and the charge left above
+        min_charge at the end of the timestep.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_charge_headroom(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns how much faster than planned the battery could charge during the timestep
+        beginning at `time`, limited by its maximum charge rate and the room left below its
+        capacity at the end of the timestep.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_green_charge(self, time: datetime, clamp: bool = False) -> Optional[units.Watt]:
+        """Returns the charge speed drawn from generated electricity at the given time.
+
+        Only available for batteries with grid charging disabled, otherwise None.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_charge_curve(self) -> List[Tuple[datetime, units.WattHour]]:
+        """
+        Returns the charge level at the start of every timestep of the horizon, followed by
+        the final level at its end, in one call.
+
+        Each entry equals `get_charge_level` at its time, so the list has one more entry than
+        the horizon has timesteps.
+        """
         ...
 
-    def get_charge_speed(self, time: datetime) -> units.Watt:
-        """Returns the battery charge/discharge speed at the given time."""
+    def get_speed_curve(self) -> List[Tuple[datetime, units.Watt]]:
+        """
+        Returns the charge speed during every timestep of the horizon in one call.
+
+        Each entry equals `get_charge_speed` at its time. The last entry is the timestep ending
+        at the end of the horizon; like `get_charge_speed`, there is no entry for the end itself.
+        """
         ...
 
     def get_id(self) -> int:
@@ -149,61 +695,1312 @@ class OptimizerContext:
         self,
         time: datetime,
         electricity_price: PrognosesProvider[units.EuroPerWh],
+        grid_limit: Optional[units.Watt] = None,
+        peak_price: Optional[float] = None,
+        settlement_period: Optional[timedelta] = None,
+        plausibility_limits: Optional[PlausibilityLimits] = None,
+        resolution: Optional[timedelta] = None,
     ) -> None:
         """
         Initialize the context.
 
         Args:
             time: The start time for the optimization period.
-            electricity_price: Provider for the electricity price forecast.
+            electricity_price: Provider for the electricity price forecast. Prices may be
+                negative, batteries then charge and keep the paid-for electricity.
+            grid_limit: Maximum power drawn from the grid, e.g. set by the fuses of the
+                grid connection. Unlimited if None.
+            peak_price: Euro per Watt of the highest power drawn from the grid in any
+                settlement period, e.g. a capacity fee. Added to the cost the annealer
+                minimizes. It is evaluated on the cheapest flow for each placement of the
+                constant actions, so only constant actions are moved to lower the peak, not
+                batteries or variable actions. run_exact ignores it.
+            settlement_period: Length of the periods the power drawn from the grid is
+                averaged over for peak_price, e.g. 15 minutes. Periods are aligned to the
+                clock, so 15 minute periods start at full quarter hours. Every timestep is a
+                period of its own if None.
+            plausibility_limits: Limits every prognoses added is checked against, starting
+                with electricity_price. `PlausibilityLimits()` if None.
+            resolution: Length of a timestep, e.g. `timedelta(minutes=15)` for quarter-hour
+                prices or an hour for a faster, coarser plan. Must be a whole number of minutes
+                that divides the day. Providers are called once per timestep and all times
+                and durations must lie on its boundaries. One minute if None.
+
+        Raises:
+            ValueError: If grid_limit or peak_price is negative, or if settlement_period isn't
+                a positive multiple of the timestep of at most a day. Also for implausible
+                prices if plausibility_limits are strict.
+            TimestepAlignmentError: If resolution doesn't divide the day into whole minutes.
+
+        Warns:
+            OptimizerWarning: If electricity_price is outside of plausibility_limits.
         """
         ...
 
-    def add_constant_action(self, action: ConstantAction) -> None:
-        """Adds a new constant action to be scheduled."""
+    def add_constant_action(
+        self, action: Union[ConstantAction, ProfiledConstantAction]
+    ) -> None:
+        """
+        Adds a new constant action to be scheduled. It always ends before end_before, so it
+        never runs past the optimization horizon, whatever the resolution.
+
+        Raises:
+            TimestepAlignmentError: If the action's window isn't on the timesteps.
+            InfeasibleError: If the action doesn't fit into its window.
+            UnitError: If the consumption is too large for the optimizer.
+            OptimizerError: If the window ends after the optimization horizon, one day after
+                the start time, or if a consumption is negative. The message names the action
+                ID and the field. Also if another battery or action already has the ID.
+        """
         ...
 
     def add_variable_action(self, action: VariableAction) -> None:
-        """Adds a new variable action to be scheduled."""
+        """
+        Adds a new variable action to be scheduled.
+
+        Raises:
+            ValueError: If the action's window isn't on the timesteps, is empty or ends after
+                the optimization horizon, one day after the start time, or if a consumption is
+                negative. The message names the action ID and the field. Also if another
+                battery or action already has the ID.
+        """
         ...
 
     def add_battery(self, battery: Battery) -> None:
-        """Adds a battery to be utilized in the optimization."""
-        ...
+        """
+        Adds a battery to be utilized in the optimization.
 
-    def add_past_constant_action(self, action: AssignedConstantAction) -> None:
-        """Adds an action already in progress to the fixed consumption base."""
+        Raises:
+            ValueError: If another battery or action already has the battery's ID. Schedules
+                key all assets by ID, so IDs must be unique across batteries and actions.
+        """
         ...
 
-    def add_generated_electricity_prognoses(self, provider: PrognosesProvider[units.WattHour]) -> None:
-        """Adds predicted energy generation (e.g., Solar) to the context."""
-        ...
+    def set_fixed_battery_plan(
+        self, battery_id: int, plan: List[Tuple[datetime, units.Watt]]
+    ) -> None:
+        """
+        Fixes the dispatch of an added battery, e.g. to the plan of its own controller, so
+        only the other assets are optimized around it.
 
+        Args:
+            battery_id: The id of the battery.
+            plan: (time, power) pairs in ascending order. Each power holds until the next
+                  pair; positive values charge and negative values discharge. The battery
+                  is idle before the first pair.
 
-class Schedule:
-    """The result of an optimization run containing assigned actions and battery states."""
+        The discharge counts as generated electricity and the charge, including losses, as
+        beyond control consumption. Schedules still report the battery's trajectory.
+        Replaces an earlier plan for the same battery.
 
-    def get_constant_action(self, id: int) -> Optional[AssignedConstantAction]:
-        """Retrieve a specific scheduled constant action by ID."""
+        Raises ValueError if the plan exceeds the battery's rates, leaves its minimum charge
+        and capacity, or charges a battery that can't charge from the grid beyond generation.
+        """
         ...
 
-    def get_variable_action(self, id: int) -> Optional[AssignedVariableAction]:
-        """Retrieve a specific scheduled variable action by ID."""
-        ...
+    def add_recurring_constant_action(self, action: RecurringConstantAction) -> None:
+        """
+        Adds the blocks of a recurring constant action, each starting at least min_gap after
+        the previous one ends. Nothing is added if this raises.
 
-    def get_battery(self, id: int) -> Optional[AssignedBattery]:
-        """Retrieve the state of a specific battery by ID."""
+        Raises:
+            TimestepAlignmentError: If the window, duration or min_gap isn't on the timesteps.
+            InfeasibleError: If the window leaves no room for all blocks and their gaps.
+            OptimizerError: If count is out of range, min_gap is negative, or the id of a
+                block is already used.
+        """
         ...
 
+    def add_precedence(self, before_id: int, after_id: int) -> None:
+        """
+        Requires a constant action to end before another one starts, e.g. the washing
+        machine before the dryer. Both actions must have been added already.
 
-def run_simulated_annealing(context: OptimizerContext) -> Tuple[units.Euro, Schedule]:
-    """
-    Runs the simulated annealing optimization algorithm.
+        Args:
+            before_id: The id of the constant action that runs first.
+            after_id: The id of the constant action that may only start once it ended.
 
-    Args:
-        context: The optimization context containing prices, actions, and batteries.
+        Raises ValueError if an id is unknown, if the precedences form a cycle, or if the
+        windows of the actions leave no room for the order.
+        """
+        ...
 
-    Returns:
-        A tuple of (total_cost, optimized_schedule).
+    def add_exclusion_group(self, ids: List[int]) -> None:
+        """
+        Forbids any two of the constant actions to run at the same time, e.g. a sauna and an
+        EV charger on the same breaker. All actions must have been added already.
+
+        Raises ValueError if an id is unknown, or InfeasibleError if the windows of the
+        actions leave no room to run them one after another.
+        """
+        ...
+
+    def set_enabled(self, asset_id: int, enabled: bool) -> None:
+        """
+        Enables or disables all assets with the id, e.g. for a checkbox per appliance.
+
+        Disabled assets stay in the context but are left out when solving, as if they had
+        never been added: they don't appear in schedules, and neither do their fixed battery
+        plans, precedences or places in exclusion groups. Assets are enabled when added.
+
+        Raises ValueError if no asset has the id.
+        """
+        ...
+
+    def is_enabled(self, asset_id: int) -> bool:
+        """Whether the assets with the id are enabled, see set_enabled."""
+        ...
+
+    def list_disabled(self) -> List[int]:
+        """The ids of all disabled assets in ascending order."""
+        ...
+
+    def rebase(
+        self,
+        new_start: datetime,
+        price_provider: PrognosesProvider[units.EuroPerWh],
+        generation_provider: Optional[PrognosesProvider[units.WattHour]] = None,
+        consumption_provider: Optional[PrognosesProvider[units.WattHour]] = None,
+    ) -> OptimizerContext:
+        """
+        Returns a copy of the context starting at `new_start`, e.g. the next day of a
+        backtest, with the same assets. The windows of actions move along with the start,
+        except those of actions created with `absolute=True`, which keep their datetimes.
+
+        The electricity price, generated electricity and beyond control consumption are taken
+        from the providers, the latter two are 0 without one. Everything else, e.g. the
+        network fee, fixed battery plans and the profiles of actions, keeps its values relative
+        to the start.
+
+        Raises:
+            TimestepAlignmentError: If `new_start` isn't a whole number of timesteps away from
+                the start.
+            ValueError: If an absolute window doesn't fit into the new horizon.
+        """
+        ...
+
+    def add_past_constant_action(self, action: AssignedConstantAction) -> None:
+        """
+        Adds an action already in progress to the fixed consumption base.
+
+        The action may come straight from the schedule of an earlier context: its remaining
+        minutes are taken from its absolute end time, so no timestamps need to be shifted.
+        """
+        ...
+
+    def add_past_variable_action(
+        self, action: AssignedVariableAction, energy_already_delivered: units.WattHour
+    ) -> None:
+        """
+        Continues a variable action already in progress, e.g. a charging session that
+        started under an earlier context.
+
+        What is left of its total after energy_already_delivered is scheduled as a variable
+        action with the same ID from the start of this context until the action's original
+        end, with its max and min consumption. The preference isn't carried over. Nothing is
+        added if nothing is left.
+
+        Raises:
+            ValueError: If energy_already_delivered is negative, or if another battery or
+                action already has the ID.
+            InfeasibleError: If the rest can't be consumed until the end of the action.
+        """
+        ...
+
+    def respect_min_off_times(
+        self, previous: Schedule, min_off_times: Dict[int, timedelta]
+    ) -> None:
+        """
+        Keeps the rest periods of appliances across rolling re-optimizations.
+
+        For every constant action id in min_off_times that ran or is planned in the previous
+        schedule, the constant action with the same id in this context starts no earlier than
+        min_off_time after the end of that run. Call it after adding the actions.
+
+        Raises:
+            ValueError: If an id has no constant action in this context, if an off time is
+                negative, or if the rest period leaves too little of the action's window.
+        """
+        ...
+
+    def add_generated_electricity_prognoses(self, provider: PrognosesProvider[units.WattHour]) -> None:
+        """
+        Adds predicted energy generation (e.g., Solar) to the context.
+
+        The values are summed with the generation added before. The provider is called once
+        per timestep here and never again, so stacking providers doesn't slow down solving.
+
+        Raises:
+            ValueError: If the generation is above the plausibility limits and they are strict.
+
+        Warns:
+            OptimizerWarning: If the generation is above the plausibility limits.
+        """
+        ...
+
+    def add_beyond_control_consumption_prognoses(
+        self, provider: PrognosesProvider[units.WattHour]
+    ) -> None:
+        """
+        Adds predicted consumption outside of the optimizer's control (e.g., household base
+        load) to the context, summed with what was added before.
+
+        Raises:
+            ValueError: If the provider returns a negative energy, naming the interval. Also
+                if the consumption is above the plausibility limits and they are strict.
+                Negative consumption for 15 minutes or more while electricity is generated is
+                reported as generation counted twice, naming the time ranges.
+
+        Warns:
+            OptimizerWarning: If the consumption is above the plausibility limits.
+        """
+        ...
+
+    def set_feed_in_tariff(
+        self,
+        provider: PrognosesProvider[units.EuroPerWh],
+        max_export: Optional[units.Watt] = None,
+    ) -> None:
+        """
+        Allows selling electricity to the grid at the given feed-in tariff.
+
+        Only generated electricity is exported, either directly or after storing it in a
+        battery that can't charge from the grid. Electricity bought from the grid is never
+        sold again. The export per timestep is limited by `max_export` (unlimited if None).
+
+        Raises:
+            ValueError: If `max_export` is negative, or if the tariff is outside of the
+                plausibility limits and they are strict.
+
+        Warns:
+            OptimizerWarning: If the tariff is outside of the plausibility limits.
+        """
+        ...
+
+    def set_network_fee(self, provider: PrognosesProvider[units.EuroPerWh]) -> None:
+        """
+        Adds a time-dependent network fee to every Wh bought from the grid, on top of the
+        electricity price. Generated electricity is never charged the fee.
+
+        Raises:
+            ValueError: If the fee is outside of the plausibility limits and they are strict.
+
+        Warns:
+            OptimizerWarning: If the fee is outside of the plausibility limits.
+        """
+        ...
+
+    def set_co2_intensity(
+        self, provider: PrognosesProvider[float], weight: float = 0.0
+    ) -> None:
+        """
+        Tracks the CO2 emitted by electricity bought from the grid, with the intensity from
+        `provider` in grams per Wh, see `Schedule.get_co2_emissions`. A `weight` in euro per
+        gram makes the optimizer pay for every gram on top of the electricity price, moving
+        loads to hours with less carbon. With the default weight of 0 the emissions are only
+        reported.
+
+        Raises:
+            ValueError: If `weight` or an intensity is negative or not finite.
+        """
+        ...
+
+    def set_net_metering(
+        self, rate: Optional[units.EuroPerWh], price_weight: float = 1.0
+    ) -> None:
+        """
+        Bills the schedule with net metering: the energy exported is subtracted from the energy
+        bought from the grid and the difference is paid at the flat `rate`, see
+        `OptimizeResult.bill`. The optimizer keeps minimizing the cost at the electricity
+        price, weighted by `price_weight`. Without a feed-in tariff, export is credited at the
+        same weighted price. A `rate` of None turns net metering off.
+
+        Raises:
+            ValueError: If `price_weight` is negative or not finite.
+        """
+        ...
+
+    def set_plausibility_limits(self, limits: PlausibilityLimits) -> None:
+        """
+        Sets the limits prognoses added from now on are checked against. Prognoses that are
+        already added aren't checked again.
+        """
+        ...
+
+    @staticmethod
+    def from_spec(
+        spec: Dict[str, Any], providers: Dict[str, PrognosesProvider[Any]]
+    ) -> "OptimizerContext":
+        """
+        Builds a context from a declarative spec, e.g. loaded from a YAML config.
+
+        Quantities are strings with a unit ("2.5 kW", "11 kWh"), durations are ISO 8601
+        durations ("PT2H") and timestamps are RFC 3339 strings. Prognoses are referenced by
+        name and looked up in `providers`:
+
+            {
+                "start_time": "2025-03-01T06:00:00Z",
+                "electricity_price": "price",
+                "generated_electricity": ["pv_east", "pv_west"],  # optional, summed
+                "beyond_control_consumption": "base_load",         # optional, summed
+                "feed_in_tariff": "tariff", "max_export": "5 kW",  # optional
+                "batteries": [{"id": 1, "capacity": "11 kWh", "max_charge_rate": "5 kW",
+                               "max_discharge_rate": "5 kW", "initial_charge": "2 kWh"}],
+                "actions": [
+                    {"type": "constant", "id": 2, "end_before": "2025-03-01T10:00:00Z",
+                     "duration": "PT2H", "consumption": "2.5 kW"},
+                    {"type": "profiled", "id": 3, "end_before": "2025-03-01T10:00:00Z",
+                     "profile": ["2 kW", "0.2 kW"]},
+                    {"type": "variable", "id": 4, "end": "2025-03-01T12:00:00Z",
+                     "total_consumption": "11 kWh", "max_consumption": "11 kW"},
+                ],
+            }
+
+        Batteries also accept `efficiency`, `grid_charging_allowed`, `min_charge`,
+        `final_charge_target`, `self_discharge_per_hour` and `cycle_cost` (Euro per Wh stored). Actions also accept `start_from` (`start` for variable actions)
+        and otherwise can start at `start_time`.
+
+        Raises:
+            ValueError: If the spec is invalid. The message starts with the offending path,
+                e.g. "actions[2].duration: missing".
+
+        Warns:
+            OptimizerWarning: If the prognoses are above the plausibility limits, or if the
+                beyond control consumption is negative for 15 minutes or more while
+                electricity is generated, which most likely counts the generation twice.
+        """
+        ...
+
+    def to_spec(self) -> Dict[str, Any]:
+        """
+        Describes this context as a spec for `from_spec`. Prognoses are referenced by the names
+        "electricity_price", "generated_electricity", "beyond_control_consumption" and
+        "feed_in_tariff"; the last three only appear if set. Quantities use W and Wh.
+        """
+        ...
+
+    def total_required_energy(self) -> WattHour:
+        """
+        Total energy all consumers need over the day: the totals of the variable actions, the
+        full profiles of the constant actions and the beyond control consumption. Useful as a
+        sanity check for dashboards, since an optimized schedule has to deliver exactly this.
+        """
+        ...
+
+    def estimate_complexity(
+        self, cooling: str = "geometric", **cooling_options: float
+    ) -> Dict[str, Any]:
+        """
+        Estimates the size of the problem without solving it, e.g. to reject contexts that
+        would take too long before starting a run.
+
+        Args:
+            cooling: The cooling schedule of the planned run, see `run_simulated_annealing`.
+            **cooling_options: Options of the cooling schedule.
+
+        Returns:
+            A dict with "nodes" and "edges" of the flow graph, "decision_variables" (the
+            constant actions the annealer moves), "iterations" planned by the cooling
+            schedule, "peak_memory_bytes" and "runtime_class", one of "seconds", "minutes"
+            or "hours".
+        """
+        ...
+
+    def dump_problem(self, path: Optional[str] = None) -> str:
+        """
+        Serializes the fully materialized problem (prognoses in raw internal units, assets,
+        start time, timestep configuration and crate version) into a versioned JSON document.
+
+        Args:
+            path: If given, the document is also written to this file.
+
+        Returns:
+            The JSON document. Load it again with `load_problem`.
+        """
+        ...
+
+
+class Schedule:
+    """The result of an optimization run containing assigned actions and battery states."""
+
+    def get_constant_action(self, id: int) -> Optional[AssignedConstantAction]:
+        """Retrieve a specific scheduled constant action by ID."""
+        ...
+
+    def get_recurring_action(self, id: int) -> List[AssignedConstantAction]:
+        """Returns the blocks of the recurring action with the given id, ordered by start."""
+        ...
+
+    def get_variable_action(self, id: int) -> Optional[AssignedVariableAction]:
+        """Retrieve a specific scheduled variable action by ID."""
+        ...
+
+    def get_battery(self, id: int) -> Optional[AssignedBattery]:
+        """Retrieve the state of a specific battery by ID."""
+        ...
+
+    def get_total_flex(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns how much the grid import at the given time could be lowered against the
+        schedule, e.g. for demand response: the discharge headroom of all batteries, the
+        consumption of variable actions that could be moved to later in their window and of
+        constant actions that start at `time` and could start later. Precedences between
+        constant actions aren't considered.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_grid_export(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns the power exported to the grid at the given time.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_network_consumption(self, time: datetime, clamp: bool = False) -> units.Watt:
+        """
+        Returns the power drawn from the grid at the given time.
+
+        With clamp, times outside of the range give its first or last value instead.
+
+        Raises:
+            OutOfRange: If the time is outside of the range and clamp is False.
+        """
+        ...
+
+    def get_network_consumption_profile(self) -> List[Tuple[datetime, units.Watt]]:
+        """Returns the power drawn from the grid for every timestep of the schedule."""
+        ...
+
+    def get_peak_consumption(self) -> Tuple[datetime, units.Watt]:
+        """Returns the first timestep with the highest power drawn from the grid and that power."""
+        ...
+
+    def get_settlement_profile(self) -> List[Tuple[datetime, units.Watt]]:
+        """
+        Returns the average power drawn from the grid over every settlement period of the
+        context as (start of the period, average power). The periods the schedule starts and
+        ends in may be cut short. Without a settlement period, every timestep is one.
+        """
+        ...
+
+    def get_energy_cost(self) -> units.Euro:
+        """Returns what the electricity bought from the grid costs at the electricity price alone."""
+        ...
+
+    def get_network_fee_cost(self) -> units.Euro:
+        """Returns the network fees paid for the electricity bought from the grid, 0 without fees."""
+        ...
+
+    def get_co2_emissions(self) -> Optional[float]:
+        """Returns the grams of CO2 emitted by the electricity bought from the grid, or None
+        without a CO2 intensity, see `OptimizerContext.set_co2_intensity`."""
+        ...
+
+    def get_effective_import_price(
+        self, time: datetime, clamp: bool = False
+    ) -> Optional[units.EuroPerWh]:
+        """
+        Returns the price paid per Wh bought from the grid in the timestep at `time`: the cost
+        of the import, at the electricity price plus the network fee, divided by the import.
+        None if nothing is bought in the timestep, e.g. while the battery covers the load. The
+        peak charge isn't included. With `clamp`, times outside of the schedule give the first
+        or last timestep, otherwise they raise ValueError.
+        """
+        ...
+
+    def get_effective_import_price_profile(
+        self,
+    ) -> List[Tuple[datetime, Optional[units.EuroPerWh]]]:
+        """Returns the effective import price of every timestep, see `get_effective_import_price`."""
+        ...
+
+    def get_net_metering_bill(self, rate: units.EuroPerWh) -> units.Euro:
+        """
+        Returns the bill under net metering: the electricity bought from the grid minus the
+        electricity exported at the flat `rate`, plus the network fees on what was bought.
+        Negative if more was exported than bought.
+        """
+        ...
+
+    def get_objective_breakdown(self) -> Optional[Dict[str, units.Euro]]:
+        """Returns what the total cost is made of, or None if the optimizer wasn't asked to
+        explain it (`explain_cost`). `run_exact` always explains it.
+
+        The parts are "grid_import", "network_fee", "charging_loss", "self_discharge",
+        "battery_wear" (the cycle costs of all batteries, see `get_battery_wear`),
+        "grid_export" (negative when the export is paid for), "min_consumption_rounding",
+        "unserved_penalty", "peak_charge", "co2" (the CO2 emissions at their weight, which
+        aren't paid) and "preference" (the preferences of variable actions, which aren't paid). They sum up to the returned total cost.
+        """
+        ...
+
+    def get_battery_wear(self) -> Optional[Dict[int, units.Euro]]:
+        """The cycle cost of the energy stored in every battery with a `cycle_cost`, by
+        battery id, or None if the optimizer wasn't asked to explain the cost."""
+        ...
+
+    def get_cost_breakdown(self, context: OptimizerContext) -> Dict[str, Any]:
+        """
+        Attributes the energy cost (`get_energy_cost`) to the consumers of the schedule.
+
+        Every consumer is charged its consumption at the electricity price, as if it were drawn
+        from the grid when it is used. What generation and batteries save against that is
+        listed separately, so the consumers minus the savings sum up to the energy cost.
+
+        Args:
+            context: The context the schedule was optimized for. It provides the beyond control
+                consumption and the generation.
+
+        Returns:
+            A dict with
+            - "per_timestep": List[Tuple[datetime, units.Euro]], the cost of the grid import of
+              every timestep
+            - "constant_actions", "variable_actions": Dict[int, units.Euro], the cost of every
+              action by id
+            - "beyond_control": units.Euro
+            - "generation_savings": units.Euro, saved by using generated electricity right away
+            - "battery_savings": units.Euro, saved by everything else that lowers the grid
+              import, mainly batteries. Negative if they make the import more expensive.
+            - "total": units.Euro, the consumers minus the savings
+        """
+        ...
+
+    def get_flow_table(self) -> Optional[List[Dict[str, Any]]]:
+        """
+        Returns the solved flow network as a list of its edges, or None if the optimizer wasn't
+        asked to capture it (`capture_flow`).
+
+        The values are the optimizer's raw integers before the minimum consumption of variable
+        actions is rounded: capacity and flow in mWh per timestep, unit_cost in µ€/Wh, so
+        flow * unit_cost is in n€. Summed over all edges except those of "initial_charge",
+        flow * unit_cost gives the objective without "min_consumption_rounding",
+        "unserved_penalty" and "peak_charge".
+
+        Returns:
+            A list of dicts with
+            - "from_label", "to_label": str, the nodes of the edge, e.g. "Wire", "Battery 3",
+              "Action 7" (variable actions), "Export", "Source" or "Sink"
+            - "timestep": Optional[datetime], the time of the edge, None for edges not bound to a
+              timestep
+            - "capacity", "flow": int
+            - "unit_cost": int
+            - "category": Optional[str], the part of `get_objective_breakdown` the edge costs
+              towards, "initial_charge" for the bonus on keeping the charge of batteries, None
+              for free edges
+            - "units": str, the note on units above
+        """
+        ...
+
+    def get_expensive_consumption(
+        self, percentile: float = 0.9
+    ) -> List[Tuple[datetime, units.Watt, units.EuroPerWh]]:
+        """Returns all timesteps where power is imported from the grid while the price is above
+        the given percentile of the day's prices, as (time, grid import, price).
+
+        Raises:
+            ValueError: If percentile is not in [0, 1].
+        """
+        ...
+
+    def get_placement_sensitivity(
+        self, context: OptimizerContext, stride: int = 15
+    ) -> Dict[int, List[Tuple[datetime, units.Euro]]]:
+        """
+        Shows how much the placement of each constant action matters.
+
+        For every constant action, the total cost is evaluated with that action moved to every
+        `stride`-th feasible start time (in timesteps) while everything else stays fixed.
+        The assigned start time is always included.
+
+        Args:
+            context: The context the schedule was optimized for.
+            stride: Distance between evaluated start times in timesteps.
+
+        Returns:
+            The cost curve of every constant action by ID, ordered by start time.
+        """
+        ...
+
+    def suggest_slot(
+        self,
+        context: OptimizerContext,
+        duration: timedelta,
+        consumption: units.Watt,
+        window: Tuple[datetime, datetime],
+    ) -> Tuple[datetime, units.Euro]:
+        """
+        Finds the cheapest time to run an additional load the optimizer didn't know about.
+
+        The load is placed at every start time within the window on top of this schedule,
+        whose constant actions stay where they are. The schedule itself is not modified.
+
+        Args:
+            context: The context the schedule was optimized for.
+            duration: How long the load runs, a multiple of the timestep.
+            consumption: Constant power of the load.
+            window: Earliest start and latest end of the load, on timestep boundaries.
+
+        Returns:
+            The cheapest start time and the marginal cost of the load there, i.e. how much
+            the total cost rises. Ties go to the earliest start.
+
+        Raises:
+            ValueError: If the load doesn't fit into the window or can't be served at any
+                start time, e.g. because of the grid limit.
+        """
+        ...
+
+    def to_records(
+        self, start: Optional[datetime] = None, end: Optional[datetime] = None
+    ) -> List[ScheduleRecord]:
+        """
+        Returns one record per timestep in [start, end).
+
+        Each record has the keys `time`, `network_consumption`, `grid_export`, `constant_actions`,
+        `variable_actions` (ID -> Watt) and `batteries` (ID -> charge level in WattHour).
+        Bounds default to the schedule horizon.
+
+        Raises:
+            ValueError: If a bound is not on a timestep boundary or outside the horizon.
+        """
+        ...
+
+    def iter_records(
+        self, start: Optional[datetime] = None, end: Optional[datetime] = None
+    ) -> ScheduleRecordIterator:
+        """
+        Like `to_records`, but computes each record only when it is requested.
+        Prefer this for long horizons when only part of the schedule is needed.
+        """
+        ...
+
+    def to_csv(self, context: Optional[OptimizerContext] = None) -> str:
+        """
+        Returns every timestep of the horizon as a CSV table with a header row.
+
+        Columns are `time` (ISO 8601), `electricity_price` (Euro/Wh), `network_consumption`,
+        `grid_export` and, if `context` is given, `generation` (W), followed by
+        `battery_<id>_level` (Wh) and `battery_<id>_speed` (W) of every battery and
+        `action_<id>_consumption` (W) of every action, sorted by ID. An action's column is 0
+        while it doesn't run. `context` should be the one the schedule was optimized for.
+        """
+        ...
+
+    def gantt(self, columns: Optional[int] = None) -> str:
+        """
+        Renders the schedule as a text Gantt chart for debugging in a terminal.
+
+        The first line shows the price from `_` (cheapest) to `@` (most expensive), then one
+        line per battery (`+` charging, `-` discharging, `.` idle) and per action (`#`
+        consuming, `.` not), sorted by ID. Each column covers an equal share of the horizon and
+        shows the state of most of its timesteps; ties go to `+` over `-` over `.` and to `#`
+        over `.`. Every line is its label, padded to the longest label, a space and `columns`
+        characters. `columns` defaults to 48, or one per timestep if there are fewer.
+
+        Raises:
+            OptimizerError: If `columns` is not between 1 and the number of timesteps.
+        """
+        ...
+
+    def to_json(self) -> str:
+        """
+        Serializes the schedule to JSON, e.g. to restore it after a restart.
+
+        The start time is not part of the document and has to be passed to `from_json`.
+        """
+        ...
+
+    @staticmethod
+    def from_json(s: str, start_time: datetime) -> Schedule:
+        """
+        Rebuilds a schedule created by `to_json`.
+
+        Args:
+            s: The JSON document.
+            start_time: Start time of the context the schedule was optimized for.
+
+        Raises:
+            ValueError: If the document is not a valid schedule.
+        """
+        ...
+
+    def verify(self, context: OptimizerContext) -> List[str]:
+        """
+        Checks the schedule against the context it was optimized for.
+
+        Checks that constant and variable actions lie within their windows, variable actions
+        stay under their maximum consumption, battery levels stay between minimum charge and
+        capacity and the grid import stays under grid_limit. Schedules returned by the
+        optimizer are verified automatically.
+
+        Returns:
+            A description of every violation, empty for a valid schedule.
+        """
+        ...
+
+    def concat(
+        self, next: "Schedule", tolerance: units.WattHour = units.WattHour(1.0)
+    ) -> "Schedule":
+        """
+        Merges this schedule with one starting later, e.g. from a rolling re-optimization,
+        into one continuous schedule over this schedule's horizon: this schedule up to the
+        start of `next` (the seam), then `next`. What `next` plans after the end of this
+        horizon is cut off.
+
+        Per-timestep values are taken from this schedule before the seam and from `next`
+        from it on. Actions in both schedules keep the assignment of `next` for the
+        overlap, batteries in both must have charge levels within `tolerance` of each other
+        at the seam. Assets in only one schedule are cut at the seam or at the end of the
+        horizon. Every such change is described by `get_merge_notes`. The merged schedule
+        has no objective breakdown.
+
+        Raises:
+            OutOfRange: If `next` doesn't start within this schedule's horizon.
+            ValueError: If `next` doesn't start on a timestep boundary of this schedule,
+                if a battery's charge levels differ by more than `tolerance` at the seam or
+                if the tolerance is negative.
+        """
+        ...
+
+    def diff(self, other: "Schedule") -> "ScheduleDiff":
+        """
+        Compares this schedule with another one over the same horizon, e.g. the results of
+        two runs on nearly the same inputs, to see how much the plan moved.
+
+        Raises:
+            ValueError: If the schedules start at different times or have timesteps of
+                different lengths.
+        """
+        ...
+
+    def get_merge_notes(self) -> List[str]:
+        """Returns what `concat` changed about the assets to merge the schedules, empty
+        for schedules that weren't merged."""
+        ...
+
+    def get_unserved_constant_actions(self) -> List[Tuple[int, datetime, units.Watt]]:
+        """
+        Returns the consumption of constant actions the optimizer couldn't serve, e.g.
+        because of the grid limit, as (action id, start of the interval, unserved power),
+        ordered by time. Empty if every constant action is served in full.
+
+        Overlapping constant actions add up and must all be served in full. If they can't
+        be, they are served in the order they start, so the action that started last is
+        reported as shorted.
+        """
+        ...
+
+    def to_bytes(self) -> bytes:
+        """
+        Serializes the schedule to a compact binary form for passing it between processes.
+
+        The data starts with a magic and a format version byte. Like with `to_json`, the start
+        time is not included.
+        """
+        ...
+
+    @staticmethod
+    def from_bytes(data: bytes, start_time: datetime) -> Schedule:
+        """
+        Rebuilds a schedule created by `to_bytes`.
+
+        Raises:
+            ValueError: If the data is not a schedule blob of this format version.
+        """
+        ...
+
+    def content_hash(self) -> str:
+        """
+        Returns a stable hash of the plan as 32 hex digits, e.g. to check that two machines
+        computed the same plan without sending the whole schedule.
+
+        Covers all action assignments, battery levels, network consumption, grid export,
+        prices, the resulting grid cost and the start time. It doesn't depend on the platform
+        or the order assets were added to the context. Equal hashes imply identical plans;
+        only compare hashes of schedules with the same `to_bytes` format version.
+        The objective breakdown is not covered.
+        """
+        ...
+
+
+ScheduleRecord = Dict[str, Any]
+
+
+class ScheduleRecordIterator(Iterator[ScheduleRecord]):
+    """Lazy iterator over the records of a Schedule."""
+
+    def __iter__(self) -> ScheduleRecordIterator: ...
+    def __next__(self) -> ScheduleRecord: ...
+    def __len__(self) -> int:
+        """Number of records that have not been yielded yet."""
+        ...
+
+
+class CancellationToken:
+    """
+    Stops a running `run_simulated_annealing` early, e.g. when the inputs changed and a new
+    optimization should start.
+    """
+
+    def __init__(self) -> None: ...
+    def cancel(self) -> None:
+        """
+        Requests the runs using this token to stop. They return the best schedule found so
+        far instead of raising. Can be called from any thread while a run is going on.
+        """
+        ...
+
+    def is_cancelled(self) -> bool:
+        """Returns True once `cancel` was called."""
+        ...
+
+
+class OptimizerOptions:
+    """
+    All settings of `run_simulated_annealing` and `Solver` in one object, e.g. to store them
+    with `to_dict` and use them again with `from_dict`. Validated on creation.
+
+    The solve functions take it as `options`. Keywords given to them besides it override its
+    settings; a different `cooling` drops its cooling options.
+    """
+
+    seed: Optional[int]
+    cooling: str
+    cooling_options: Dict[str, float]
+    """Parameters of the cooling schedule with the defaults filled in."""
+    progress_every: int
+    explain_cost: bool
+    capture_flow: bool
+    isolate_errors: bool
+    moves_per_iteration: int
+    """Number of constant action moves combined into one iteration."""
+    flow_max_iterations: int
+    """Iterations a min cost flow solve may take before it raises `SolverStalled`."""
+
+    def __init__(
+        self,
+        seed: Optional[int] = None,
+        cooling: str = "geometric",
+        progress_every: int = 1,
+        explain_cost: bool = False,
+        capture_flow: bool = False,
+        isolate_errors: bool = False,
+        moves_per_iteration: int = 2,
+        flow_max_iterations: int = 2**30,
+        **cooling_options: float,
+    ) -> None:
+        """
+        See `run_simulated_annealing` for the meaning of the arguments.
+
+        Raises:
+            ValueError: If the cooling schedule or one of its options is unknown or invalid,
+                or if progress_every, moves_per_iteration or flow_max_iterations is 0.
+        """
+        ...
+
+    def to_dict(self) -> Dict[str, Any]:
+        """All settings as keywords of the constructor, the cooling options included."""
+        ...
+
+    @staticmethod
+    def from_dict(options: Dict[str, Any]) -> "OptimizerOptions":
+        """
+        Creates options from a dict like the one `to_dict` returns. Missing keys keep their
+        defaults.
+
+        Raises:
+            ValueError: If a key is unknown or a value invalid, like the constructor.
+        """
+        ...
+
+
+class ScheduleDiff:
+    """
+    How much two schedules differ, see `Schedule.diff`. Assets are keyed by ID. Assets
+    planned by only one of the schedules are listed in `only_in_this` and `only_in_other`.
+    """
+
+    @property
+    def constant_actions(self) -> Dict[int, timedelta]:
+        """How much later every constant action in both schedules starts in the other one."""
+        ...
+
+    @property
+    def variable_actions(self) -> Dict[int, units.WattHour]:
+        """
+        Sum of the absolute differences of the energy per timestep, for every variable action
+        in both schedules.
+        """
+        ...
+
+    @property
+    def batteries(self) -> Dict[int, units.WattHour]:
+        """Largest difference of the charge levels, for every battery in both schedules."""
+        ...
+
+    @property
+    def only_in_this(self) -> List[Tuple[str, int]]:
+        """
+        Assets only planned by the schedule `diff` was called on, as (kind, id) with kind
+        "constant_action", "variable_action" or "battery".
+        """
+        ...
+
+    @property
+    def only_in_other(self) -> List[Tuple[str, int]]:
+        """Assets only planned by the other schedule, like `only_in_this`."""
+        ...
+
+    @property
+    def stability(self) -> float:
+        """
+        1 for identical schedules, down to 0 if every asset changed completely: 1 minus the
+        mean change of all assets, each between 0 and 1. A constant action's shift counts
+        relative to the slack of its window, a variable action's difference relative to the
+        energy both schedules assign to it and a battery's deviation relative to its
+        capacity. An asset in only one schedule counts as changed completely.
+        """
+        ...
+
+
+class OptimizeResult:
+    """
+    Result of `run_simulated_annealing`. Unpacks into (cost, schedule) like a tuple, where
+    cost is the total objective including the penalties.
+    """
+
+    @property
+    def cost(self) -> units.Euro:
+        """Lowest total cost seen during the run, the objective the optimizer minimized."""
+        ...
+
+    @property
+    def total_objective(self) -> units.Euro:
+        """The total objective, equal to `cost`. It is `energy_cost` plus the `penalties`."""
+        ...
+
+    @property
+    def energy_cost(self) -> units.Euro:
+        """What the schedule really costs, the total objective without the penalties."""
+        ...
+
+    @property
+    def penalties(self) -> Dict[str, units.Euro]:
+        """
+        Parts of the objective that aren't paid for but only steer the optimizer, by name:
+        "preference" (the preferences of variable actions) and "unserved_penalty". Parts that
+        are zero are left out, so the dict is empty if there are no penalties.
+        """
+        ...
+
+    @property
+    def bill(self) -> Optional[units.Euro]:
+        """
+        What the schedule is billed under net metering (`Schedule.get_net_metering_bill`), or
+        None if the context doesn't use net metering. Not what the optimizer minimized.
+        """
+        ...
+
+    @property
+    def schedule(self) -> Schedule:
+        """Schedule with that cost."""
+        ...
+
+    @property
+    def cancelled(self) -> bool:
+        """True if the run was stopped early through its `CancellationToken`."""
+        ...
+
+    @property
+    def excluded(self) -> Dict[int, OptimizerError]:
+        """
+        Assets left out of the schedule by `isolate_errors`, as {id: the error they raised}.
+        Empty if all assets were scheduled.
+        """
+        ...
+
+    def __iter__(self) -> Iterator[Any]: ...
+
+
+class Solver:
+    """
+    Reusable optimizer. Each call to `solve` continues from the state the previous call ended in.
+
+    A solve that is aborted by an exception leaves the solver in a consistent state. If
+    consistency can't be guaranteed, the solver is poisoned and `solve` raises RuntimeError.
+    """
+
+    def __init__(
+        self,
+        context: OptimizerContext,
+        seed: Optional[int] = None,
+        cooling: Optional[str] = None,
+        explain_cost: Optional[bool] = None,
+        capture_flow: Optional[bool] = None,
+        options: Optional[OptimizerOptions] = None,
+        **cooling_options: float,
+    ) -> None:
+        """
+        Creates a solver for the given context. Settings that aren't given are taken from
+        options, or left at the defaults of `OptimizerOptions`.
+
+        Args:
+            context: The optimization context.
+            seed: Optional seed for the random number generator.
+            cooling: Cooling schedule, see `run_simulated_annealing`.
+            explain_cost: Break the cost of every returned schedule down into its parts, see
+                `Schedule.get_objective_breakdown`.
+            capture_flow: Keep the flow network of every returned schedule, see
+                `Schedule.get_flow_table`.
+            options: All settings in one object. The keywords above override its settings,
+                see `OptimizerOptions`. Its progress_every is used by `solve`.
+            **cooling_options: Parameters of the cooling schedule.
+
+        Raises:
+            ValueError: If the cooling schedule or one of its options is unknown or invalid,
+                or if isolate_errors is set, which only `run_simulated_annealing` supports.
+        """
+        ...
+
+    def solve(
+        self,
+        progress: Optional[Callable[[int, float, units.Euro, units.Euro], None]] = None,
+        progress_every: Optional[int] = None,
+    ) -> Tuple[units.Euro, Schedule]:
+        """
+        Runs the optimization.
+
+        The GIL is released while optimizing, so other threads keep running.
+
+        Args:
+            progress: Called every progress_every-th iteration with
+                (iteration, temperature, cost, best_cost). An exception raised by it aborts
+                the solve and is re-raised.
+            progress_every: Number of iterations between two progress calls. Defaults to
+                the one of the options of the solver.
+
+        Returns:
+            A tuple of (total_cost, optimized_schedule) of the lowest cost seen. The next
+            solve continues from that schedule.
+
+        Raises:
+            RuntimeError: If the solver is poisoned.
+            ValueError: If progress_every is 0, or if the beyond control consumption alone
+                exceeds the grid limit at some timesteps. The message lists them.
+            InfeasibleError: If not all consumption can be served, see its attributes.
+            InternalConsistencyError: If the final schedule violates the context.
+        """
+        ...
+
+    def is_poisoned(self) -> bool:
+        """True if the solver can't be used anymore."""
+        ...
+
+
+def load_problem(json: str) -> OptimizerContext:
+    """
+    Rebuilds an OptimizerContext from a JSON document created by `OptimizerContext.dump_problem`.
+
+    Raises:
+        ValueError: If the document is malformed or was written by an incompatible version.
+    """
+    ...
+
+
+def create_audit_bundle(context: OptimizerContext, schedule: Schedule, path: str) -> None:
+    """
+    Writes the inputs and the result of a solve into one zip archive for audits.
+
+    The archive contains the problem dump of the context (see `OptimizerContext.dump_problem`),
+    the schedule as written by `Schedule.to_json`, and a manifest with the crate version, the
+    start time, the energy and network fee cost, the total cost if the schedule carries its
+    breakdown, the violations found by `Schedule.verify`, the schedule's content hash and the
+    hashes of the other entries. An existing file at `path` is replaced.
+
+    Raises:
+        OSError: If the file can't be written.
+    """
+    ...
+
+
+def verify_audit_bundle(path: str, revalidate: bool = False) -> bool:
+    """
+    Checks that no entry of a bundle written by `create_audit_bundle` was changed.
+
+    The hashes detect changed or corrupted entries, but they aren't a signature: whoever edits
+    a bundle on purpose can update the manifest as well.
+
+    Args:
+        path: The bundle to check.
+        revalidate: Also rebuild the problem from its dump and check the schedule against it.
+            This must find the same violations as when the bundle was written.
+
+    Returns:
+        True if all hashes match and, with revalidate, the problem is valid.
+
+    Raises:
+        OSError: If the file can't be read.
+        ValueError: If the bundle was written in another format version.
+    """
+    ...
+
+
+def theoretical_best(context: OptimizerContext, relax: List[str] = []) -> units.Euro:
+    """
+    Returns the lowest cost any schedule could reach with the given constraints removed,
+    solved exactly with perfect foresight. Compare the results of single relaxations to see
+    which constraint costs the most.
+
+    Args:
+        context: The optimization context.
+        relax: Constraints to remove:
+            "battery_power": batteries charge and discharge at any rate (capacity still applies).
+            "grid_limit": neither the grid import is limited by grid_limit nor the export by max_export.
+            "action_windows": actions can consume at any time of the horizon.
+
+    Returns:
+        The relaxed optimum. Constant actions are treated as if their consumption could be
+        spread freely over their window, so this is a lower bound on the cost even with
+        nothing relaxed.
+
+    Raises:
+        ValueError: If relax contains an unknown name.
+    """
+    ...
+
+
+def run_exact(context: OptimizerContext) -> Tuple[units.Euro, Schedule]:
+    """
+    Solves a context without constant actions or contiguous variable actions exactly.
+
+    Batteries and variable actions are scheduled by a min-cost flow, so the result is the
+    optimum and repeated calls return the same schedule.
+
+    Args:
+        context: The optimization context. It must not contain constant actions or
+            contiguous variable actions.
+
+    Returns:
+        A tuple of (total_cost, optimal_schedule). The schedule always carries the breakdown
+        of the cost, see `Schedule.get_objective_breakdown`.
+
+    Raises:
+        ValueError: If the context contains constant actions or contiguous variable actions,
+            or if the beyond control consumption alone exceeds the grid limit at some
+            timesteps. The message lists them.
+        InfeasibleError: If not all consumption can be served, see its attributes.
+    """
+    ...
+
+
+def run_simulated_annealing(
+    context: OptimizerContext,
+    seed: Optional[int] = None,
+    progress: Optional[Callable[[int, float, units.Euro, units.Euro], None]] = None,
+    progress_every: Optional[int] = None,
+    cooling: Optional[str] = None,
+    explain_cost: Optional[bool] = None,
+    cancel: Optional[CancellationToken] = None,
+    capture_flow: Optional[bool] = None,
+    isolate_errors: Optional[bool] = None,
+    initial_schedule: Optional[Schedule] = None,
+    options: Optional[OptimizerOptions] = None,
+    **cooling_options: float,
+) -> OptimizeResult:
+    """
+    Runs the simulated annealing optimization algorithm.
+
+    Settings that aren't given are taken from options, or left at the defaults of
+    `OptimizerOptions`.
+
+    The GIL is released while optimizing, so other threads keep running. Disabled assets of
+    the context don't appear in the schedule.
+
+    Args:
+        context: The optimization context containing prices, actions, and batteries.
+        seed: Optional seed for the random number generator. Runs with the same seed
+            and context return identical results.
+        progress: Called every progress_every-th iteration with
+            (iteration, temperature, cost, best_cost). Nothing is printed otherwise.
+            An exception raised by it aborts the run and is re-raised.
+        progress_every: Number of iterations between two progress calls.
+        cooling: How the temperature is lowered:
+            "geometric": multiplied by `factor` (default 0.999) every iteration until it
+                drops below min_temperature.
+            "linear": lowered in equal steps to min_temperature over `iterations` iterations.
+            "logarithmic": proportional to 1 / ln(k + 2) in iteration k, for `iterations`
+                iterations.
+            "lam": adapted so the acceptance rate follows the curve of Lam and Delosme,
+                for `iterations` iterations.
+            The iteration-based schedules default to 6000 iterations.
+        explain_cost: Break the cost of the returned schedule down into its parts, see
+            `Schedule.get_objective_breakdown`.
+        cancel: Stops the run early once cancelled, also from another thread. The result
+            then holds the best schedule found so far and has `cancelled` set.
+        capture_flow: Keep the flow network of the returned schedule, see
+            `Schedule.get_flow_table`.
+        isolate_errors: Leave out assets that fail validation instead of failing the run,
+            together with their precedences, exclusion groups and fixed plans. The other
+            assets are still scheduled; `OptimizeResult.excluded` names the ones left out.
+        initial_schedule: Start from this schedule instead of a random one, e.g. the result
+            of the previous run when re-optimizing with updated forecasts. Its constant
+            actions are clamped into their windows of this context; actions it doesn't know
+            start at random, those the context doesn't know are ignored.
+        options: All settings in one object. The keywords above override its settings.
+        **cooling_options: `factor` or `iterations` as listed above. Every schedule also
+            accepts `initial_temperature` (default 40) and `min_temperature` (default 0.1).
+
+    Returns:
+        An `OptimizeResult` that unpacks into (total_cost, optimized_schedule) of the lowest
+        cost seen during the run, which isn't necessarily the last accepted state. The cost
+        is negative when the consumed electricity earns more than it costs, e.g. at negative
+        prices. The cost is the total objective; `energy_cost` and `penalties` split it
+        into what is paid and what only steers the optimizer.
+
+    Raises:
+        ValueError: If progress_every is 0, if the cooling schedule or one of its options is
+            unknown or invalid, or if the beyond control consumption alone exceeds the grid
+            limit at some timesteps. The message lists them.
+        InfeasibleError: If not all consumption can be served, see its attributes.
+        InternalConsistencyError: If the final schedule violates the context.
+    """
+    ...
+
+
+def set_instrumentation(callback: Optional[Callable[[Dict[str, Any]], None]]) -> None:
+    """
+    Installs a callback reporting every solve of `run_simulated_annealing`, `run_exact` and
+    `Solver.solve` in this process, e.g. to a metrics system. Pass None to remove it.
+
+    The callback is called with an event dict before the solve starts and after it returned
+    or failed, never during the solve. Both events have the keys
+        event: "start" or "end".
+        solver: "simulated_annealing", "exact" or "solver".
+        fingerprint: Hash of `OptimizerContext.dump_problem`, the same as the hash of
+            problem.json in an audit bundle. None for a `Solver` created before the callback
+            was installed.
+        start_time: Start time of the context.
+    The end event adds
+        duration_seconds: Wall time of the whole solve.
+        materialize_seconds: Time to build the optimizer's problem from the context.
+        flow_seconds: Time spent solving min-cost flows.
+        annealing_seconds: Wall time of the simulated annealing, flow solves included.
+        iterations: Number of annealing iterations.
+        cost: The returned total cost.
+        cancelled: True if the run was stopped through its CancellationToken.
+        error: The raised exception as "Type: message", or None.
+    Values the solve didn't reach or that don't apply to its solver are None.
+
+    Exceptions raised by the callback don't abort the solve, they are passed to
+    sys.unraisablehook.
     """
     ...
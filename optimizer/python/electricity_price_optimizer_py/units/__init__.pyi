@@ -1,11 +1,21 @@
 from datetime import timedelta
-from typing import overload
+from typing import Union, overload
 
 """
 This module defines custom units for electricity pricing optimization,
 including Watt, WattHour, Euro, and EuroPerWh.
 Each unit class supports arithmetic operations and comparisons, allowing
-for intuitive calculations in the optimization process.
+for intuitive calculations in the optimization process, and can be pickled.
+
+Values handed to the optimizer are limited to about 4 MWh of energy per timestep or
+in total, and 0.1 €/Wh for prices, so the cost of a whole day fits the optimizer's
+integer arithmetic. Larger values raise UnitError naming the field.
+
++, - and comparisons also take plain numbers, taken to be in the same unit, e.g.
+`Watt(500) + 100` or `watt < 100`. Mixing different units raises TypeError.
+
+Constructors and accessors for kW, kWh, ct and ct/kWh spare converting by hand, e.g.
+`EuroPerWh.from_cent_per_kwh(30)`. Watt and WattHour print in kW and kWh from 1000 on.
 """
 
 
@@ -18,6 +28,13 @@ class Watt:
     """
     value: float
     def __init__(self, value: float) -> None: ...
+    @staticmethod
+    def from_kilowatt(value: float) -> Watt:
+        """Creates a Watt from kW."""
+        ...
+    def to_kilowatt(self) -> float:
+        """Returns the value in kW."""
+        ...
 
     @overload
     def __mul__(self, other: timedelta) -> WattHour: ...
@@ -34,16 +51,21 @@ class Watt:
     @overload
     def __truediv__(self, other: Watt) -> float: ...
 
-    def __add__(self, other: Watt) -> Watt: ...
-    def __sub__(self, other: Watt) -> Watt: ...
+    def __add__(self, other: Union[Watt, float]) -> Watt: ...
+    def __radd__(self, other: float) -> Watt: ...
+    def __sub__(self, other: Union[Watt, float]) -> Watt: ...
+    def __rsub__(self, other: float) -> Watt: ...
+    def __neg__(self) -> Watt: ...
+    def __abs__(self) -> Watt: ...
+    def __hash__(self) -> int: ...
     def __repr__(self) -> str: ...
     def get_value(self) -> float: ...
     def __eq__(self, other: object) -> bool: ...
     def __ne__(self, other: object) -> bool: ...
-    def __lt__(self, other: Watt) -> bool: ...
-    def __le__(self, other: Watt) -> bool: ...
-    def __gt__(self, other: Watt) -> bool: ...
-    def __ge__(self, other: Watt) -> bool: ...
+    def __lt__(self, other: Union[Watt, float]) -> bool: ...
+    def __le__(self, other: Union[Watt, float]) -> bool: ...
+    def __gt__(self, other: Union[Watt, float]) -> bool: ...
+    def __ge__(self, other: Union[Watt, float]) -> bool: ...
 
 
 class WattHour:
@@ -55,6 +77,13 @@ class WattHour:
     """
     value: float
     def __init__(self, value: float) -> None: ...
+    @staticmethod
+    def from_kilowatt_hour(value: float) -> WattHour:
+        """Creates a WattHour from kWh, e.g. a battery capacity."""
+        ...
+    def to_kilowatt_hour(self) -> float:
+        """Returns the value in kWh."""
+        ...
 
     @overload
     def __mul__(self, other: EuroPerWh) -> Euro: ...
@@ -75,16 +104,21 @@ class WattHour:
     @overload
     def __truediv__(self, other: Watt) -> timedelta: ...
 
-    def __add__(self, other: WattHour) -> WattHour: ...
-    def __sub__(self, other: WattHour) -> WattHour: ...
+    def __add__(self, other: Union[WattHour, float]) -> WattHour: ...
+    def __radd__(self, other: float) -> WattHour: ...
+    def __sub__(self, other: Union[WattHour, float]) -> WattHour: ...
+    def __rsub__(self, other: float) -> WattHour: ...
+    def __neg__(self) -> WattHour: ...
+    def __abs__(self) -> WattHour: ...
+    def __hash__(self) -> int: ...
     def __repr__(self) -> str: ...
     def get_value(self) -> float: ...
     def __eq__(self, other: object) -> bool: ...
     def __ne__(self, other: object) -> bool: ...
-    def __lt__(self, other: WattHour) -> bool: ...
-    def __le__(self, other: WattHour) -> bool: ...
-    def __gt__(self, other: WattHour) -> bool: ...
-    def __ge__(self, other: WattHour) -> bool: ...
+    def __lt__(self, other: Union[WattHour, float]) -> bool: ...
+    def __le__(self, other: Union[WattHour, float]) -> bool: ...
+    def __gt__(self, other: Union[WattHour, float]) -> bool: ...
+    def __ge__(self, other: Union[WattHour, float]) -> bool: ...
 
 
 class Euro:
@@ -96,6 +130,13 @@ class Euro:
     """
     value: float
     def __init__(self, value: float) -> None: ...
+    @staticmethod
+    def from_cent(value: float) -> Euro:
+        """Creates a Euro from cents."""
+        ...
+    def to_cent(self) -> float:
+        """Returns the value in cents."""
+        ...
 
     def __mul__(self, other: float) -> Euro: ...
     def __rmul__(self, other: float) -> Euro: ...
@@ -107,16 +148,21 @@ class Euro:
     @overload
     def __truediv__(self, other: Euro) -> float: ...
 
-    def __add__(self, other: Euro) -> Euro: ...
-    def __sub__(self, other: Euro) -> Euro: ...
+    def __add__(self, other: Union[Euro, float]) -> Euro: ...
+    def __radd__(self, other: float) -> Euro: ...
+    def __sub__(self, other: Union[Euro, float]) -> Euro: ...
+    def __rsub__(self, other: float) -> Euro: ...
+    def __neg__(self) -> Euro: ...
+    def __abs__(self) -> Euro: ...
+    def __hash__(self) -> int: ...
     def __repr__(self) -> str: ...
     def get_value(self) -> float: ...
     def __eq__(self, other: object) -> bool: ...
     def __ne__(self, other: object) -> bool: ...
-    def __lt__(self, other: Euro) -> bool: ...
-    def __le__(self, other: Euro) -> bool: ...
-    def __gt__(self, other: Euro) -> bool: ...
-    def __ge__(self, other: Euro) -> bool: ...
+    def __lt__(self, other: Union[Euro, float]) -> bool: ...
+    def __le__(self, other: Union[Euro, float]) -> bool: ...
+    def __gt__(self, other: Union[Euro, float]) -> bool: ...
+    def __ge__(self, other: Union[Euro, float]) -> bool: ...
 
 
 class EuroPerWh:
@@ -128,6 +174,13 @@ class EuroPerWh:
     """
     value: float
     def __init__(self, value: float) -> None: ...
+    @staticmethod
+    def from_cent_per_kwh(value: float) -> EuroPerWh:
+        """Creates a EuroPerWh from ct/kWh, the unit prices are usually quoted in."""
+        ...
+    def to_cent_per_kwh(self) -> float:
+        """Returns the value in ct/kWh."""
+        ...
 
     @overload
     def __mul__(self, other: WattHour) -> Euro: ...
@@ -144,13 +197,18 @@ class EuroPerWh:
     @overload
     def __truediv__(self, other: EuroPerWh) -> float: ...
 
-    def __add__(self, other: EuroPerWh) -> EuroPerWh: ...
-    def __sub__(self, other: EuroPerWh) -> EuroPerWh: ...
+    def __add__(self, other: Union[EuroPerWh, float]) -> EuroPerWh: ...
+    def __radd__(self, other: float) -> EuroPerWh: ...
+    def __sub__(self, other: Union[EuroPerWh, float]) -> EuroPerWh: ...
+    def __rsub__(self, other: float) -> EuroPerWh: ...
+    def __neg__(self) -> EuroPerWh: ...
+    def __abs__(self) -> EuroPerWh: ...
+    def __hash__(self) -> int: ...
     def __repr__(self) -> str: ...
     def get_value(self) -> float: ...
     def __eq__(self, other: object) -> bool: ...
     def __ne__(self, other: object) -> bool: ...
-    def __lt__(self, other: EuroPerWh) -> bool: ...
-    def __le__(self, other: EuroPerWh) -> bool: ...
-    def __gt__(self, other: EuroPerWh) -> bool: ...
-    def __ge__(self, other: EuroPerWh) -> bool: ...
+    def __lt__(self, other: Union[EuroPerWh, float]) -> bool: ...
+    def __le__(self, other: Union[EuroPerWh, float]) -> bool: ...
+    def __gt__(self, other: Union[EuroPerWh, float]) -> bool: ...
+    def __ge__(self, other: Union[EuroPerWh, float]) -> bool: ...
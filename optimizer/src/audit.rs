@@ -0,0 +1,318 @@
+//! Audit bundles: the inputs and the result of a solve in one zip archive.
+//!
+//! A bundle contains three entries:
+//! - `problem.json`: the problem dump of the context, see [`crate::problem`]
+//! - `schedule.json`: the schedule as written by `Schedule.to_json`
+//! - `manifest.json`: a [`Manifest`] with the format and crate version, the start time,
+//!   summary statistics of the schedule and the hashes of the other entries
+//!
+//! The hashes are the platform independent FNV-1a hashes also used by `Schedule.content_hash`.
+//! They detect entries that were changed or corrupted after the bundle was written, but they
+//! aren't a signature: whoever edits a bundle on purpose can update the manifest as well.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Write},
+};
+
+use chrono::{DateTime, Utc};
+use pyo3::{PyResult, exceptions::PyIOError};
+use serde::{Deserialize, Serialize};
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    OptimizerContext, OptimizerError, Schedule,
+    binary::fnv1a_128,
+    problem::{FORMAT_VERSION, ProblemDump},
+};
+
+const MANIFEST: &str = "manifest.json";
+const PROBLEM: &str = "problem.json";
+const SCHEDULE: &str = "schedule.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Summary of the schedule, in internal units (nano-euro).
+pub struct ScheduleStats {
+    pub energy_cost: i64,
+    pub network_fee_cost: i64,
+    /// Total cost the optimizer returned, absent if it wasn't asked to explain the cost.
+    pub total_cost: Option<i64>,
+    /// Violations of the context found by `Schedule.verify` when the bundle was written.
+    pub violations: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// Description of a bundle, stored as its `manifest.json`.
+pub struct Manifest {
+    pub format_version: u32,
+    pub crate_version: String,
+    /// True if the bundle was written by a debug build.
+    pub debug_build: bool,
+    pub created_at: DateTime<Utc>,
+    /// Start time of the context, needed to read the schedule.
+    pub start_time: DateTime<Utc>,
+    pub schedule_content_hash: String,
+    /// Hash of every other entry by name.
+    pub entry_hashes: BTreeMap<String, String>,
+    pub stats: ScheduleStats,
+}
+
+fn hash(bytes: &[u8]) -> String {
+    format!("{:032x}", fnv1a_128(bytes))
+}
+
+/// Write a bundle of `context` and `schedule` to `path`, replacing an existing file.
+pub(crate) fn write_bundle(
+    context: &OptimizerContext,
+    schedule: &Schedule,
+    path: &str,
+) -> PyResult<()> {
+    let problem = serde_json::to_string_pretty(&ProblemDump::from_context(context))
+        .map_err(|e| OptimizerError::new_err(format!("Failed to serialize problem: {}", e)))?;
+    let schedule_json = schedule.to_json()?;
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        debug_build: cfg!(debug_assertions),
+        created_at: Utc::now(),
+        start_time: schedule.start_timestamp,
+        schedule_content_hash: schedule.content_hash()?,
+        entry_hashes: [(PROBLEM, &problem), (SCHEDULE, &schedule_json)]
+            .into_iter()
+            .map(|(name, content)| (name.to_string(), hash(content.as_bytes())))
+            .collect(),
+        stats: ScheduleStats {
+            energy_cost: schedule.inner.get_energy_cost(),
+            network_fee_cost: schedule.inner.get_network_fee_cost(),
+            total_cost: schedule
+                .inner
+                .get_objective_breakdown()
+                .map(|breakdown| breakdown.total()),
+            violations: schedule.verify(context)?,
+        },
+    };
+    let manifest = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| OptimizerError::new_err(format!("Failed to serialize manifest: {}", e)))?;
+
+    let io_error = |e: &dyn std::fmt::Display| {
+        PyIOError::new_err(format!("Failed to write audit bundle to {}: {}", path, e))
+    };
+    let file = File::create(path).map_err(|e| io_error(&e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in [
+        (MANIFEST, &manifest),
+        (PROBLEM, &problem),
+        (SCHEDULE, &schedule_json),
+    ] {
+        zip.start_file(name, options).map_err(|e| io_error(&e))?;
+        zip.write_all(content.as_bytes())
+            .map_err(|e| io_error(&e))?;
+    }
+    zip.finish().map_err(|e| io_error(&e))?;
+    Ok(())
+}
+
+/// Check the bundle at `path`, see `verify_audit_bundle`. Errors if the file can't be opened or
+/// was written in another format version, returns false for any other problem.
+pub(crate) fn verify_bundle(path: &str, revalidate: bool) -> PyResult<bool> {
+    let file = File::open(path)
+        .map_err(|e| PyIOError::new_err(format!("Failed to read audit bundle {}: {}", path, e)))?;
+    let Ok(mut zip) = ZipArchive::new(file) else {
+        return Ok(false);
+    };
+    // Reading an entry also checks its CRC, so corrupted archives end up as None
+    let mut read = |name: &str| -> Option<String> {
+        let mut content = String::new();
+        zip.by_name(name).ok()?.read_to_string(&mut content).ok()?;
+        Some(content)
+    };
+    let Some(manifest) = read(MANIFEST) else {
+        return Ok(false);
+    };
+    let Ok(manifest) = serde_json::from_str::<Manifest>(&manifest) else {
+        return Ok(false);
+    };
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(OptimizerError::new_err(format!(
+            "Unsupported audit bundle format version {}, expected {}",
+            manifest.format_version, FORMAT_VERSION
+        )));
+    }
+
+    let mut entries = BTreeMap::new();
+    for name in [PROBLEM, SCHEDULE] {
+        let Some(content) = read(name) else {
+            return Ok(false);
+        };
+        if manifest.entry_hashes.get(name) != Some(&hash(content.as_bytes())) {
+            return Ok(false);
+        }
+        entries.insert(name, content);
+    }
+    let Ok(schedule) = Schedule::from_json(&entries[SCHEDULE], manifest.start_time) else {
+        return Ok(false);
+    };
+    if schedule.content_hash()? != manifest.schedule_content_hash {
+        return Ok(false);
+    }
+    if !revalidate {
+        return Ok(true);
+    }
+
+    let context = serde_json::from_str::<ProblemDump>(&entries[PROBLEM])
+        .ok()
+        .and_then(|dump| dump.into_context().ok());
+    let Some(context) = context else {
+        return Ok(false);
+    };
+    Ok(context.start_time == manifest.start_time
+        && schedule
+            .verify(&context)
+            .is_ok_and(|violations| violations == manifest.stats.violations))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, rc::Rc};
+
+    use chrono::TimeZone;
+    use electricity_price_optimizer::{
+        optimizer_context::{
+            action::variable::VariableAction, battery::Battery, prognoses::Prognoses,
+        },
+        time::{Time, TimestepConfig},
+    };
+    use pyo3::Python;
+
+    use super::*;
+    use crate::{plausibility::PlausibilityLimits, run_exact};
+
+    fn example_context() -> OptimizerContext {
+        OptimizerContext {
+            electricity_price: Prognoses::from_closure(|t| (t.to_timestep() % 90) as i64 + 5),
+            generated_electricity: Prognoses::from_closure(|t| (t.to_timestep() % 60) as i64),
+            beyond_control_consumption: Prognoses::from_closure(|_| 20),
+            batteries: vec![Rc::new(Battery::new(1000, 100, 50, 50, 0.9, 1))],
+            constant_actions: vec![],
+            variable_actions: vec![Rc::new(VariableAction::new(
+                Time::at_default_resolution(6, 0),
+                Time::at_default_resolution(12, 0),
+                2000,
+                50,
+                3,
+            ))],
+            feed_in_tariff: None,
+            max_export: None,
+            network_fee: None,
+            co2_intensity: None,
+            net_metering: None,
+            grid_limit: None,
+            peak_price: None,
+            settlement_period: None,
+            fixed_battery_plans: vec![],
+            precedences: vec![],
+            min_gaps: vec![],
+            exclusion_groups: vec![],
+            disabled: BTreeSet::new(),
+            absolute_actions: vec![],
+            plausibility_limits: PlausibilityLimits::default(),
+            start_time: Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap(),
+            timestep: TimestepConfig::default(),
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        let file = format!("audit_{}_{}.zip", std::process::id(), name);
+        std::env::temp_dir()
+            .join(file)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Copy the bundle at `from` to `to`, passing every entry through `edit`.
+    fn rewrite(from: &str, to: &str, edit: impl Fn(&str, &mut Vec<u8>)) {
+        let mut archive = ZipArchive::new(File::open(from).unwrap()).unwrap();
+        let mut zip = ZipWriter::new(File::create(to).unwrap());
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).unwrap();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            edit(entry.name(), &mut content);
+            zip.start_file(entry.name(), SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(&content).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_audit_bundle_round_trip() {
+        Python::initialize();
+        let context = example_context();
+        let (_, schedule) = Python::attach(|py| run_exact(py, &context)).unwrap();
+        let path = temp_path("round_trip");
+        write_bundle(&context, &schedule, &path).unwrap();
+        assert!(verify_bundle(&path, false).unwrap());
+        assert!(verify_bundle(&path, true).unwrap());
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut manifest = String::new();
+        archive
+            .by_name(MANIFEST)
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        let manifest: Manifest = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(manifest.start_time, context.start_time);
+        assert_eq!(
+            manifest.schedule_content_hash,
+            schedule.content_hash().unwrap()
+        );
+        assert_eq!(
+            manifest.stats.total_cost,
+            Some(schedule.inner.get_objective_breakdown().unwrap().total())
+        );
+        assert!(manifest.stats.violations.is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_audit_bundle_detects_tampering() {
+        Python::initialize();
+        let context = example_context();
+        let (_, schedule) = Python::attach(|py| run_exact(py, &context)).unwrap();
+        let path = temp_path("original");
+        write_bundle(&context, &schedule, &path).unwrap();
+
+        // Changing a single digit of the schedule
+        let tampered = temp_path("tampered");
+        rewrite(&path, &tampered, |name, content| {
+            if name == SCHEDULE {
+                let digit = content.iter().position(u8::is_ascii_digit).unwrap();
+                content[digit] = if content[digit] == b'9' {
+                    b'8'
+                } else {
+                    content[digit] + 1
+                };
+            }
+        });
+        assert!(!verify_bundle(&tampered, false).unwrap());
+
+        // Rewriting without changes keeps the bundle valid
+        rewrite(&path, &tampered, |_, _| {});
+        assert!(verify_bundle(&tampered, true).unwrap());
+
+        // Flipping a byte of the archive itself
+        let mut bytes = std::fs::read(&path).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 1;
+        std::fs::write(&tampered, &bytes).unwrap();
+        assert!(!verify_bundle(&tampered, false).unwrap());
+
+        assert!(verify_bundle(&temp_path("missing"), false).is_err());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&tampered).unwrap();
+    }
+}
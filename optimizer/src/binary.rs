@@ -0,0 +1,140 @@
+//! Compact binary encoding of schedules and prognoses for passing them between processes.
+//!
+//! A blob starts with a four byte magic naming its content, followed by one format version byte
+//! and the bincode encoding of the value. Integers are varint encoded, most per timestep values
+//! are small and fit in one or two bytes. The magic keeps a prognoses blob from being read as a
+//! schedule, the version byte lets readers reject blobs of a format they don't know.
+use bincode::Options;
+use pyo3::PyResult;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::OptimizerError;
+
+/// Version of the encoding after the header. Bump it whenever the serialized types change.
+pub(crate) const FORMAT_VERSION: u8 = 9;
+
+/// Content of a blob, each with its own magic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Blob {
+    Schedule,
+    Prognoses,
+}
+
+impl Blob {
+    fn magic(self) -> &'static [u8; 4] {
+        match self {
+            Blob::Schedule => b"EPOS",
+            Blob::Prognoses => b"EPOP",
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            Blob::Schedule => "schedule",
+            Blob::Prognoses => "prognoses",
+        }
+    }
+}
+
+const HEADER_LEN: usize = 5;
+
+fn options() -> impl Options {
+    // Varint integers, rejecting trailing bytes
+    bincode::DefaultOptions::new().reject_trailing_bytes()
+}
+
+/// Encode `value` with the header of `blob`.
+pub(crate) fn encode<T: Serialize>(blob: Blob, value: &T) -> PyResult<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + 1024);
+    bytes.extend_from_slice(blob.magic());
+    bytes.push(FORMAT_VERSION);
+    options().serialize_into(&mut bytes, value).map_err(|e| {
+        OptimizerError::new_err(format!("Failed to serialize {}: {}", blob.name(), e))
+    })?;
+    Ok(bytes)
+}
+
+/// Decode a blob created by `encode` for the same `blob`. Errors if the magic or version
+/// doesn't match or the blob has trailing bytes.
+pub(crate) fn decode<T: DeserializeOwned>(blob: Blob, bytes: &[u8]) -> PyResult<T> {
+    let name = blob.name();
+    let Some((header, body)) = bytes.split_first_chunk::<HEADER_LEN>() else {
+        return Err(OptimizerError::new_err(format!(
+            "Invalid {name} bytes: only {} bytes, too short for the header",
+            bytes.len()
+        )));
+    };
+    if &header[..4] != blob.magic() {
+        return Err(OptimizerError::new_err(format!(
+            "Invalid {name} bytes: expected magic {:?}, got {:?}",
+            String::from_utf8_lossy(blob.magic()),
+            String::from_utf8_lossy(&header[..4])
+        )));
+    }
+    if header[4] != FORMAT_VERSION {
+        return Err(OptimizerError::new_err(format!(
+            "Unsupported {name} format version {}, this version reads version {}",
+            header[4], FORMAT_VERSION
+        )));
+    }
+    let value = options()
+        .deserialize(body)
+        .map_err(|e| OptimizerError::new_err(format!("Invalid {name} bytes: {}", e)))?;
+    Ok(value)
+}
+
+/// 128 bit FNV-1a hash of `bytes`. Unlike `std::hash`, it is the same on every platform and in
+/// every Rust version.
+pub(crate) fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u128).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::Python;
+
+    fn message(err: pyo3::PyErr) -> String {
+        Python::initialize();
+        Python::attach(|py| err.value(py).to_string())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = vec![1i64, -2, 3];
+        let bytes = encode(Blob::Prognoses, &value).unwrap();
+        assert_eq!(&bytes[..5], b"EPOP\x09");
+        assert_eq!(decode::<Vec<i64>>(Blob::Prognoses, &bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_rejects_foreign_and_outdated_blobs() {
+        let mut bytes = encode(Blob::Prognoses, &vec![1i64]).unwrap();
+        let err = decode::<Vec<i64>>(Blob::Schedule, &bytes).unwrap_err();
+        assert!(message(err).contains("expected magic \"EPOS\", got \"EPOP\""));
+
+        bytes[4] = FORMAT_VERSION + 1;
+        let err = decode::<Vec<i64>>(Blob::Prognoses, &bytes).unwrap_err();
+        assert_eq!(
+            message(err),
+            "Unsupported prognoses format version 10, this version reads version 9"
+        );
+
+        let err = decode::<Vec<i64>>(Blob::Prognoses, b"EPO").unwrap_err();
+        assert!(message(err).contains("too short for the header"));
+
+        let mut bytes = encode(Blob::Prognoses, &vec![1i64]).unwrap();
+        bytes.push(0);
+        let err = decode::<Vec<i64>>(Blob::Prognoses, &bytes).unwrap_err();
+        assert!(message(err).contains("Invalid prognoses bytes"));
+    }
+
+    #[test]
+    fn test_fnv1a_128_reference_values() {
+        assert_eq!(fnv1a_128(b""), 0x6c62272e07bb014262b821756295c58d);
+        assert_eq!(fnv1a_128(b"a"), 0xd228cb696f1a8caf78912b704e4a8964);
+    }
+}
@@ -0,0 +1,205 @@
+//! Optional hook reporting every solve to the caller's logging or metrics.
+//!
+//! `set_instrumentation` installs one callback for the whole process. Each solve calls it with a
+//! "start" event before it does any work and an "end" event when it returned or failed. The
+//! callback only runs at these two boundaries, never during the solve, so an installed callback
+//! costs two Python calls and one hash of the problem per solve.
+//!
+//! Both events are dicts with the keys
+//! - `event`: "start" or "end"
+//! - `solver`: "simulated_annealing", "exact" or "solver" for `Solver.solve`
+//! - `fingerprint`: hash of the problem dump, equal to the hash of `problem.json` in an audit
+//!   bundle of the same context. None for a `Solver` created before the callback was installed
+//! - `start_time`: start time of the context
+//!
+//! The end event adds
+//! - `duration_seconds`: wall time of the whole solve
+//! - `materialize_seconds`: time to build the optimizer's problem from the context
+//! - `flow_seconds`: time spent solving min-cost flows
+//! - `annealing_seconds`: wall time of the simulated annealing, flow solves included
+//! - `iterations`: number of annealing iterations
+//! - `cost`: the returned cost in Euro
+//! - `cancelled`: True if the run was stopped through its CancellationToken
+//! - `error`: the raised exception as "Type: message", or None
+//!
+//! Values a solve didn't reach, or that don't apply to its solver, are None.
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use electricity_price_optimizer::{
+    optimizer_context::OptimizerContext as RustOptimizerContext, simulated_annealing::SolveStats,
+};
+use pyo3::{
+    Py, PyAny, PyErr, PyResult, Python, pyfunction,
+    types::{PyDict, PyDictMethods},
+};
+
+use crate::{OptimizerContext, binary::fnv1a_128, units::Euro};
+
+/// The callback installed by `set_instrumentation`.
+static CALLBACK: Mutex<Option<Py<PyAny>>> = Mutex::new(None);
+
+#[pyfunction]
+/// Install `callback(event)` to be called with a dict at the start and the end of every solve,
+/// or remove it by passing None. Exceptions raised by the callback don't abort the solve, they
+/// are reported through sys.unraisablehook.
+pub(crate) fn set_instrumentation(callback: Option<Py<PyAny>>) {
+    *CALLBACK.lock().unwrap_or_else(PoisonError::into_inner) = callback;
+}
+
+fn installed(py: Python<'_>) -> Option<Py<PyAny>> {
+    CALLBACK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .map(|callback| callback.clone_ref(py))
+}
+
+/// True if a callback is installed.
+pub(crate) fn is_installed() -> bool {
+    CALLBACK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .is_some()
+}
+
+/// Hash identifying the problem of `context`, see the module documentation.
+pub(crate) fn fingerprint(context: &OptimizerContext) -> PyResult<String> {
+    Ok(format!(
+        "{:032x}",
+        fnv1a_128(context.dump_problem(None)?.as_bytes())
+    ))
+}
+
+/// Measurements of one solve, reported as its end event by [`SolveReport::finish`].
+pub(crate) struct SolveReport {
+    /// None if no callback was installed when the solve started.
+    callback: Option<Py<PyAny>>,
+    solver: &'static str,
+    fingerprint: Option<String>,
+    start_time: DateTime<Utc>,
+    started: Instant,
+    materialize: Option<Duration>,
+    stats: Option<SolveStats>,
+    flow: Option<Duration>,
+    cost: Option<i64>,
+}
+
+impl SolveReport {
+    /// Emit the start event of a solve if a callback is installed. `fingerprint` is only
+    /// computed in that case.
+    pub(crate) fn start(
+        py: Python<'_>,
+        solver: &'static str,
+        start_time: DateTime<Utc>,
+        fingerprint: impl FnOnce() -> PyResult<Option<String>>,
+    ) -> PyResult<Self> {
+        let callback = installed(py);
+        let fingerprint = match callback {
+            Some(_) => fingerprint()?,
+            None => None,
+        };
+        let report = SolveReport {
+            callback,
+            solver,
+            fingerprint,
+            start_time,
+            started: Instant::now(),
+            materialize: None,
+            stats: None,
+            flow: None,
+            cost: None,
+        };
+        report.emit(py, "start", |_| Ok(()));
+        Ok(report)
+    }
+
+    /// Build the optimizer's problem from `context`, recording how long it took.
+    /// The problem is boxed, as it holds its prognoses inline and debug builds would copy them
+    /// through every stack frame on the way to the solver.
+    pub(crate) fn materialize(
+        &mut self,
+        context: &OptimizerContext,
+    ) -> PyResult<Box<RustOptimizerContext>> {
+        let started = Instant::now();
+        let result = context.to_rust().map(Box::new);
+        self.materialize = Some(started.elapsed());
+        result
+    }
+
+    /// Like `materialize`, but leaves out the assets that fail and returns their errors by id,
+    /// see `OptimizerContext::to_rust_isolating_errors`.
+    pub(crate) fn materialize_isolating_errors(
+        &mut self,
+        context: &OptimizerContext,
+    ) -> PyResult<(Box<RustOptimizerContext>, BTreeMap<u32, PyErr>)> {
+        let started = Instant::now();
+        let result = context
+            .to_rust_isolating_errors()
+            .map(|(rust_context, errors)| (Box::new(rust_context), errors));
+        self.materialize = Some(started.elapsed());
+        result
+    }
+
+    /// Record the result of a simulated annealing run.
+    pub(crate) fn annealed(&mut self, cost: i64, stats: &SolveStats) {
+        self.cost = Some(cost);
+        self.flow = Some(stats.flow_solve_time);
+        self.stats = Some(stats.clone());
+    }
+
+    /// Record the result of an exact solve, which is a single flow solve taking `flow`.
+    pub(crate) fn solved_exactly(&mut self, cost: i64, flow: Duration) {
+        self.cost = Some(cost);
+        self.flow = Some(flow);
+    }
+
+    /// Emit the end event for `result` and hand it back.
+    pub(crate) fn finish<T>(self, py: Python<'_>, result: PyResult<T>) -> PyResult<T> {
+        let error = result.as_ref().err().map(|e| e.to_string());
+        let stats = self.stats.as_ref();
+        self.emit(py, "end", |event| {
+            let seconds = |duration: Option<Duration>| duration.map(|d| d.as_secs_f64());
+            event.set_item("duration_seconds", self.started.elapsed().as_secs_f64())?;
+            event.set_item("materialize_seconds", seconds(self.materialize))?;
+            event.set_item("flow_seconds", seconds(self.flow))?;
+            event.set_item("annealing_seconds", seconds(stats.map(|s| s.elapsed)))?;
+            event.set_item("iterations", stats.map(|s| s.iterations))?;
+            event.set_item(
+                "cost",
+                self.cost.map(|cost| Euro::from_nano_euro(cost as f64)),
+            )?;
+            event.set_item("cancelled", stats.is_some_and(|s| s.cancelled))?;
+            event.set_item("error", &error)
+        });
+        result
+    }
+
+    /// Call the callback with an event of `kind`, filled in by `fill`.
+    fn emit(
+        &self,
+        py: Python<'_>,
+        kind: &str,
+        fill: impl FnOnce(&pyo3::Bound<'_, PyDict>) -> PyResult<()>,
+    ) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let event = PyDict::new(py);
+        let result = (|| {
+            event.set_item("event", kind)?;
+            event.set_item("solver", self.solver)?;
+            event.set_item("fingerprint", &self.fingerprint)?;
+            event.set_item("start_time", self.start_time)?;
+            fill(&event)?;
+            callback.call1(py, (&event,))
+        })();
+        if let Err(e) = result {
+            e.write_unraisable(py, Some(callback.bind(py)));
+        }
+    }
+}
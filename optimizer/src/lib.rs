@@ -2,41 +2,78 @@
 //!
 //! This module exposes:
 //! - Units (Euro, EuroPerWh, Watt, WattHour) with conversion helpers
-//! - Time conversions between chrono DateTime<Utc> and optimizer Time
-//! - PrognosesProvider for passing Python closures to Rust
+//! - Time conversions between chrono DateTime<Utc> and optimizer Time, see [`timegrid`]
+//! - PrognosesProvider for passing Python closures to Rust, Prognoses for materialized values
 //! - Actions (constant and variable), batteries, optimizer context, and schedules
+//! - OptimizerOptions holding all settings of a solve
+//! - An instrumentation hook reporting every solve, see [`instrumentation`]
 //!
 //! Conventions:
-//! - Timestep length: MINUTES_PER_TIMESTEP minutes
+//! - Timestep length: the resolution of the context, a whole number of minutes dividing the day
+//!   (default: one minute), see TimestepConfig
 //! - Prices: micro-euro per Wh internally (i64)
 //! - Power/energy: milli-Wh and milli-Wh per timestep (i64) internally
-//! - DateTime values must lie on timestep boundaries (minute % MINUTES_PER_TIMESTEP == 0; seconds/nanoseconds == 0)
+//! - DateTime values must lie on timestep boundaries (minute % minutes per timestep == 0; seconds/nanoseconds == 0)
+//!   or be the start_time of the context, which may lie between two boundaries
+//! - Invalid inputs and unsolvable problems raise OptimizerError, a ValueError, or one of its
+//!   subclasses TimestepAlignmentError, InfeasibleError, PrognosesError, UnitError, OutOfRange
+//!   and SolverStalled
+mod audit;
+mod binary;
+mod instrumentation;
+mod options;
+mod plausibility;
+mod problem;
+mod records;
+mod spec;
+mod timegrid;
 mod units;
-use std::{fmt::Debug, rc::Rc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    ops::Range,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
 
-use chrono::{DateTime, Datelike, TimeDelta, TimeZone, Timelike, Utc};
+use chrono::{DateTime, NaiveTime, TimeDelta, Utc};
+use chrono_tz::Tz;
 use electricity_price_optimizer::{
+    bounds,
+    complexity::estimate_complexity,
+    optimizer::{
+        BindingLimit, ExactSolveError, FlowSolveError, Relaxation, Shortfall, SolvePhase,
+        required_consumption, solve_exact,
+    },
     optimizer_context::{
         OptimizerContext as RustOptimizerContext,
         action::{
-            constant::AssignedConstantAction as RustAssignedConstantAction,
-            constant::ConstantAction as RustConstantAction,
+            ActionError, constant::AssignedConstantAction as RustAssignedConstantAction,
+            constant::ConstantAction as RustConstantAction, constant::ExclusionError,
             variable::AssignedVariableAction as RustAssignedVariableAction,
             variable::VariableAction as RustVariableAction,
         },
         battery::AssignedBattery as RustAssignedBattery,
         battery::Battery as RustBattery,
+        battery::FixedBatteryPlanError,
         prognoses::Prognoses,
     },
-    schedule::Schedule as RustSchedule,
-    time::{MINUTES_PER_TIMESTEP, Time},
+    schedule::{Schedule as RustSchedule, ScheduledAsset, Violation},
+    sensitivity::{cheapest_additional_slot, placement_sensitivity},
+    simulated_annealing::{AnnealingConfig, Cooling, SolveError, SolveStats, Solver as RustSolver},
+    time::{SettlementPeriods, Time, TimeIterator, TimestepConfig},
 };
 use pyo3::{
-    Bound, FromPyObject, Py, PyAny, PyErr, PyResult, Python,
-    exceptions::PyValueError,
+    Bound, FromPyObject, IntoPyObject, IntoPyObjectExt, Py, PyAny, PyErr, PyRef, PyResult, Python,
+    create_exception,
+    exceptions::{PyIOError, PyRuntimeError, PyValueError},
     prelude::FromPyObjectOwned,
     pyclass, pyfunction, pymethods, pymodule,
-    types::{PyModule, PyModuleMethods},
+    types::{PyAnyMethods, PyDict, PyDictMethods, PyIterator, PyModule, PyModuleMethods},
     wrap_pyfunction,
 };
 // gives to optimizer:
@@ -45,14 +82,49 @@ use pyo3::{
 // price in micro Euro per Wh
 // thus return cost is in milli micro Euro = nano Euro
 
-use crate::units::{Euro, EuroPerWh, Watt, WattHour, register_units_submodule};
+use crate::binary::Blob;
+use crate::instrumentation::{SolveReport, set_instrumentation};
+use crate::options::{OptimizerOptions, keyword_overrides, set_given};
+use crate::plausibility::{OptimizerWarning, PlausibilityLimits, double_counting};
+use crate::problem::ProblemDump;
+use crate::records::ScheduleRecordIterator;
+use crate::timegrid::{TimeGrid, timestep_duration};
+use crate::units::{
+    Euro, EuroPerWh, MAX_ENERGY, MAX_PEAK_PRICE, MAX_PRICE, Watt, WattHour, checked_internal,
+    register_units_submodule,
+};
 
-#[pyclass]
-/// Provides prognoses data through a Python callable returning values for a time interval.
+#[pyclass(frozen)]
+/// Provides prognoses data through a Python callable returning values for a time interval,
+/// or through a fixed sequence of values.
 /// The callable signature must be: get_data(curr: DateTime[UTC], next: DateTime[UTC]) -> T.
 /// T must be extractable from Python (e.g., EuroPerWh or i64).
+/// A provider is immutable, so one provider can be shared by and materialized from any number
+/// of threads at once. Each materialization holds the GIL while it calls the callable, which
+/// must itself be safe to call from these threads.
 struct PrognosesProvider {
-    get_data: Py<PyAny>,
+    source: ProviderSource,
+}
+
+/// Where a PrognosesProvider gets its values from.
+enum ProviderSource {
+    /// A Python callable, invoked for every interval.
+    Callable(Py<PyAny>),
+    /// Fixed values, the i-th covering [start_time + i * resolution, start_time + (i + 1) * resolution).
+    Values {
+        values: Vec<Py<PyAny>>,
+        start_time: DateTime<Utc>,
+        resolution: TimeDelta,
+    },
+    /// One of two values by the local time of day: low in [low_start, low_end), which may
+    /// cross midnight, high otherwise.
+    TwoRegister {
+        high: Py<PyAny>,
+        low: Py<PyAny>,
+        low_start: NaiveTime,
+        low_end: NaiveTime,
+        tz: Tz,
+    },
 }
 
 #[pymethods]
@@ -60,284 +132,1185 @@ impl PrognosesProvider {
     #[new]
     /// Create a new provider with a Python callable that returns data for a given interval.
     fn new(get_data: Py<PyAny>) -> Self {
-        PrognosesProvider { get_data }
+        PrognosesProvider {
+            source: ProviderSource::Callable(get_data),
+        }
+    }
+    #[staticmethod]
+    #[pyo3(signature = (values, start_time, resolution=None))]
+    /// Create a provider serving fixed values, one per resolution (default: one minute),
+    /// covering the day from start_time on. Never calls back into Python.
+    /// Errors if the values don't cover exactly one day or a value isn't a unit or float.
+    fn from_values(
+        values: &Bound<'_, PyAny>,
+        start_time: DateTime<Utc>,
+        resolution: Option<TimeDelta>,
+    ) -> PyResult<Self> {
+        let resolution = resolution.unwrap_or_else(|| timestep_duration(Default::default()));
+        let day = TimeDelta::days(1);
+        timestep_config(resolution)?;
+        let values = values
+            .try_iter()?
+            .enumerate()
+            .map(|(index, value)| {
+                let value = value?;
+                value.extract::<PrognosisValue>().map_err(|e| {
+                    PrognosesError::new_err(format!(
+                        "Value at index {} is not a unit or float: {}",
+                        index, e
+                    ))
+                })?;
+                Ok(value.unbind())
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let expected = (day.num_minutes() / resolution.num_minutes()) as usize;
+        if values.len() != expected {
+            return Err(PrognosesError::new_err(format!(
+                "Expected {} values of {} each to cover the day, got {}",
+                expected,
+                resolution,
+                values.len()
+            )));
+        }
+        Ok(PrognosesProvider {
+            source: ProviderSource::Values {
+                values,
+                start_time,
+                resolution,
+            },
+        })
+    }
+    #[staticmethod]
+    /// Create a provider for a two-register tariff: the low price from low_start to low_end
+    /// local time in the IANA time zone tz (e.g. 22:00 to 06:00 in "Europe/Berlin"), the high
+    /// price otherwise. The window may cross midnight and follows daylight saving time.
+    /// Never calls back into Python.
+    fn two_register(
+        py: Python<'_>,
+        high: EuroPerWh,
+        low: EuroPerWh,
+        low_start: NaiveTime,
+        low_end: NaiveTime,
+        tz: &str,
+    ) -> PyResult<Self> {
+        let tz = tz
+            .parse::<Tz>()
+            .map_err(|_| OptimizerError::new_err(format!("Unknown time zone {:?}", tz)))?;
+        if low_start == low_end {
+            return Err(OptimizerError::new_err(format!(
+                "The low tariff window must not be empty, it starts and ends at {}",
+                low_start
+            )));
+        }
+        Ok(PrognosesProvider {
+            source: ProviderSource::TwoRegister {
+                high: Py::new(py, high)?.into_any(),
+                low: Py::new(py, low)?.into_any(),
+                low_start,
+                low_end,
+                tz,
+            },
+        })
+    }
+    /// Python __copy__: a provider sharing the callable or values of this one.
+    fn __copy__(&self, py: Python<'_>) -> Self {
+        self.clone_ref(py)
+    }
+    /// Python __deepcopy__: like __copy__, the callable and values aren't copied.
+    fn __deepcopy__(&self, py: Python<'_>, _memo: &Bound<'_, PyAny>) -> Self {
+        self.clone_ref(py)
     }
 }
 
-/// Convert optimizer Time to a DateTime<Utc>, aligned to the timestep boundary relative to start_time.
-/// Rounds down to the nearest timestep and never before start_time.
-fn time_to_datetime(time: Time, start_time: DateTime<Utc>) -> PyResult<DateTime<Utc>> {
-    // 1. Get starting point in nanoseconds
-    // .expect() is used here because Utc timestamps usually fit in i64 nanos
-    // unless you're dealing with years far in the future/past.
-    let start_ns = start_time
-        .timestamp_nanos_opt()
-        .expect("Timestamp out of range");
+create_exception!(
+    electricity_price_optimizer_py,
+    OptimizerError,
+    PyValueError,
+    "Base class of the errors raised for invalid inputs and problems that can't be solved. It is a ValueError, so code catching ValueError keeps working. Failures to read or write files raise OSError and bugs of the library RuntimeError instead."
+);
 
-    // 2. Define our interval in nanoseconds
-    let ns_per_minute: i64 = 60 * 1_000_000_000;
-    let interval_ns = (MINUTES_PER_TIMESTEP as i64 * ns_per_minute);
+create_exception!(
+    electricity_price_optimizer_py,
+    TimestepAlignmentError,
+    OptimizerError,
+    "Raised when a time or duration isn't on the timesteps of the optimizer, e.g. a DateTime between two timestep boundaries."
+);
 
-    // 3. Calculate target time in nanoseconds
-    let added_ns = time.get_minutes() as i64 * ns_per_minute;
-    let target_ns = start_ns + added_ns;
+create_exception!(
+    electricity_price_optimizer_py,
+    InfeasibleError,
+    OptimizerError,
+    "Raised when the constraints can't all be met, e.g. an action that doesn't fit into its window or beyond control consumption above the grid limit."
+);
 
-    // 4. Round down to the nearest timestep
-    // The modulo operation gives us the "overflow" past the last clean interval
-    let rounded_ns = target_ns - (target_ns % interval_ns);
+create_exception!(
+    electricity_price_optimizer_py,
+    PrognosesError,
+    OptimizerError,
+    "Raised when prognoses can't be read, e.g. because their callable raised or returned an invalid value, or because they don't cover the day. The error of the callable is the cause."
+);
 
-    // 5. Ensure we don't round back to a time before the start_time
-    let res_ns = rounded_ns.max(start_ns);
-    // 6. Convert nanoseconds back into a DateTime object
-    let result = Utc.timestamp_nanos(res_ns);
+create_exception!(
+    electricity_price_optimizer_py,
+    UnitError,
+    OptimizerError,
+    "Raised when a value can't be represented in the units of the optimizer, e.g. because it is too large."
+);
 
-    Ok(result)
-}
+create_exception!(
+    electricity_price_optimizer_py,
+    OutOfRange,
+    OptimizerError,
+    "Raised when a time is outside of the range an accessor covers. The attributes time, start and end hold the queried time and the first and last valid time, asset_id the ID of the queried battery or action, or None for queries of the whole schedule."
+);
 
-/// Validate that a DateTime<Utc> is on a timestep boundary relative to start_time.
-/// Returns error if before start_time or not aligned to the timestep.
-fn check_on_timestep_boundary(dt: DateTime<Utc>, start_time: DateTime<Utc>) -> PyResult<()> {
-    if (dt < start_time) {
-        return Err(PyValueError::new_err(format!(
-            "DateTime {} is before start time {}",
-            dt, start_time
-        )));
-    }
-    if dt == start_time {
-        return Ok(());
+create_exception!(
+    electricity_price_optimizer_py,
+    SolverStalled,
+    OptimizerError,
+    "Raised when the min cost flow doesn't finish within its iteration cap, e.g. because the flow network has a cycle of negative cost. The attributes phase ('spfa' or 'dijkstra'), iterations, augmentations, flow and cost describe where it stopped."
+);
+
+create_exception!(
+    electricity_price_optimizer_py,
+    InternalConsistencyError,
+    PyRuntimeError,
+    "Raised when the optimizer produced a schedule that violates its context. This is a bug in the library, not in the context."
+);
+
+/// Create an OutOfRange error with the queried time, the valid range and the ID of the
+/// queried asset as attributes. `asset` is its kind, e.g. "battery", and ID.
+fn out_of_range(
+    py: Python<'_>,
+    time: DateTime<Utc>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    asset: Option<(&str, u32)>,
+) -> PyErr {
+    let subject = asset
+        .map(|(kind, id)| format!(" for {} {}", kind, id))
+        .unwrap_or_default();
+    let err = OutOfRange::new_err(format!(
+        "Time {} is out of range{}, valid times are {} to {}",
+        time, subject, start, end
+    ));
+    let value = err.value(py);
+    for (name, attribute) in [("time", time), ("start", start), ("end", end)] {
+        if let Err(e) = value.setattr(name, attribute) {
+            return e;
+        }
     }
-    if !dt.minute().is_multiple_of(MINUTES_PER_TIMESTEP)
-        || dt.second() != 0
-        || dt.timestamp_subsec_nanos() != 0
-    {
-        return Err(PyValueError::new_err(format!(
-            "DateTime is not on a timestep boundary: minute={}, second={}, nanos={}",
-            dt.minute(),
-            dt.second(),
-            dt.timestamp_subsec_nanos()
-        )));
+    if let Err(e) = value.setattr("asset_id", asset.map(|(_, id)| id)) {
+        return e;
     }
-    Ok(())
+    err
 }
 
-/// Convert a DateTime<Utc> to optimizer Time, assuming dt is on a timestep boundary.
-/// Errors if dt < start_time or cannot construct the base alignment.
-fn datetime_to_time(dt: DateTime<Utc>, start_time: DateTime<Utc>) -> Result<Time, PyErr> {
-    if dt == start_time {
-        return Ok(Time::from_timestep(0));
-    }
-    if dt < start_time {
-        return Err(PyValueError::new_err(format!(
-            "DateTime {} is before start time {}",
-            dt, start_time
-        )));
-    }
-    // the first datetime before or equal to start_time that is on a timestep boundary
-    let base_dt = {
-        let minute = start_time.minute() - (start_time.minute() % MINUTES_PER_TIMESTEP);
-        Utc.with_ymd_and_hms(
-            start_time.year(),
-            start_time.month(),
-            start_time.day(),
-            start_time.hour(),
-            minute,
-            0,
-        )
-        .single()
+/// Get the timesteps of length `resolution`, which must be a whole number of minutes that
+/// divides the day.
+fn timestep_config(resolution: TimeDelta) -> PyResult<TimestepConfig> {
+    Some(resolution)
+        .filter(|r| r.num_nanoseconds() == Some(r.num_minutes() * 60_000_000_000))
+        .and_then(|r| u32::try_from(r.num_minutes()).ok())
+        .and_then(TimestepConfig::new)
         .ok_or_else(|| {
-            PyValueError::new_err(format!("Failed to create base datetime from {}", dt))
-        })?
-    };
+            TimestepAlignmentError::new_err(format!(
+                "Resolution must be a whole number of minutes that divides the day, got {}",
+                resolution
+            ))
+        })
+}
 
-    let duration = dt.signed_duration_since(base_dt);
-    let total_minutes = duration.num_minutes() as u32;
-    let timesteps = total_minutes / MINUTES_PER_TIMESTEP;
-    let result = Time::from_timestep(timesteps);
-    Ok(result)
+/// All timesteps of a schedule on `grid`.
+fn schedule_range(grid: TimeGrid) -> Range<Time> {
+    Time::from_timestep(0)..grid.day_end()
+}
+
+/// Length of the first timestep of `grid` that remains after its start, as a fraction of a
+/// full one.
+fn first_timestep_fraction(grid: TimeGrid) -> PyResult<f32> {
+    let next_timestep = grid.to_datetime(Time::from_timestep(1))?;
+    let remaining_duration = next_timestep.signed_duration_since(grid.anchor());
+    // calculate as precise as possible
+    Ok((remaining_duration.as_seconds_f64() / grid.step().as_seconds_f64()) as f32)
+}
+
+/// Convert a DateTime<Utc> to the timestep of `valid` on `grid` it falls into.
+/// With `clamp`, times outside of `valid` are moved to its first or last timestep instead of
+/// raising OutOfRange, which names `asset` if the query is about one.
+fn time_in_range(
+    py: Python<'_>,
+    time: DateTime<Utc>,
+    grid: TimeGrid,
+    valid: Range<Time>,
+    clamp: bool,
+    asset: Option<(&str, u32)>,
+) -> PyResult<Time> {
+    let start_time = grid.anchor();
+    let first = valid.start;
+    let last = Time::from_timestep(valid.end.to_timestep() - 1);
+    let converted = if time < start_time {
+        None
+    } else {
+        // The first timestep starts at start_time, even if the boundary before it is earlier
+        Some(grid.to_time(grid.floor(time)?.max(grid.anchor()))?)
+    };
+    match converted {
+        Some(t) if valid.contains(&t) => Ok(t),
+        Some(t) if clamp && t >= valid.end => Ok(last),
+        _ if clamp => Ok(first),
+        _ => Err(out_of_range(
+            py,
+            time,
+            grid.to_datetime(first)?,
+            grid.to_datetime(last)?,
+            asset,
+        )),
+    }
 }
 
 impl PrognosesProvider {
-    /// Create a Prognoses<T> from the Python callable, invoked per timestep interval [t, t+1).
-    /// T must implement FromPyObjectOwned. Errors propagate from Python callable or extraction.
-    fn get_prognoses<'py, T: Clone + Debug + Default + FromPyObjectOwned<'py>>(
+    /// A provider sharing the source of this one.
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        let source = match &self.source {
+            ProviderSource::Callable(get_data) => ProviderSource::Callable(get_data.clone_ref(py)),
+            ProviderSource::Values {
+                values,
+                start_time,
+                resolution,
+            } => ProviderSource::Values {
+                values: values.iter().map(|value| value.clone_ref(py)).collect(),
+                start_time: *start_time,
+                resolution: *resolution,
+            },
+            ProviderSource::TwoRegister {
+                high,
+                low,
+                low_start,
+                low_end,
+                tz,
+            } => ProviderSource::TwoRegister {
+                high: high.clone_ref(py),
+                low: low.clone_ref(py),
+                low_start: *low_start,
+                low_end: *low_end,
+                tz: *tz,
+            },
+        };
+        PrognosesProvider { source }
+    }
+
+    /// Create a Prognoses<U> from the Python callable, invoked per interval [t, t+1) of `grid`.
+    /// Every returned T is converted to the internal representation with `convert`.
+    /// Errors of the callable or the extraction become a ValueError naming the interval,
+    /// with the original error as its cause.
+    fn get_prognoses<'py, T: FromPyObjectOwned<'py>, U: Clone + Debug + Default>(
         &self,
         py: Python<'py>,
-        start_time: DateTime<Utc>,
-    ) -> Result<Prognoses<T>, PyErr> {
-        Prognoses::from_closure_result(|t: Time| {
-            let curr_t = time_to_datetime(t, start_time)?;
-            let next_t = time_to_datetime(t.get_next_timestep(), start_time)?;
-            let result = self.get_data.call1(py, (curr_t, next_t))?;
-            result.extract::<T>(py).map_err(Into::into)
+        grid: TimeGrid,
+        convert: impl Fn(T) -> U,
+    ) -> PyResult<Prognoses<U>> {
+        self.try_get_prognoses(py, grid, |value| Ok(convert(value)))
+    }
+
+    /// Like `get_prognoses`, but `convert` may reject a value. Its error is reported like an
+    /// invalid value.
+    fn try_get_prognoses<'py, T: FromPyObjectOwned<'py>, U: Clone + Debug + Default>(
+        &self,
+        py: Python<'py>,
+        grid: TimeGrid,
+        convert: impl Fn(T) -> PyResult<U>,
+    ) -> PyResult<Prognoses<U>> {
+        Prognoses::from_closure_result_for(grid.timestep(), |t: Time| {
+            let curr_t = grid.to_datetime(t)?;
+            let next_t = grid.to_datetime(t.get_next_timestep())?;
+            let interval_error = |message: &str, cause: PyErr| {
+                let message = format!(
+                    "Prognoses provider {} for interval [{}, {}): {}",
+                    message, curr_t, next_t, cause
+                );
+                // Values too large for the optimizer stay a UnitError
+                let err = if cause.is_instance_of::<UnitError>(py) {
+                    UnitError::new_err(message)
+                } else {
+                    PrognosesError::new_err(message)
+                };
+                err.set_cause(py, Some(cause));
+                err
+            };
+            let result = match &self.source {
+                ProviderSource::Callable(get_data) => get_data
+                    .call1(py, (curr_t, next_t))
+                    .map_err(|e| interval_error("failed", e))?,
+                ProviderSource::Values {
+                    values,
+                    start_time: values_start,
+                    resolution,
+                } => {
+                    let index = (curr_t - *values_start).num_minutes() / resolution.num_minutes();
+                    let value = (curr_t >= *values_start)
+                        .then(|| values.get(index as usize))
+                        .flatten()
+                        .ok_or_else(|| {
+                            PrognosesError::new_err(format!(
+                                "Prognoses provider has no value for interval [{}, {}), its values start at {}",
+                                curr_t, next_t, values_start
+                            ))
+                        })?;
+                    value.clone_ref(py)
+                }
+                ProviderSource::TwoRegister {
+                    high,
+                    low,
+                    low_start,
+                    low_end,
+                    tz,
+                } => {
+                    let local = curr_t.with_timezone(tz).time();
+                    let is_low = if low_start < low_end {
+                        *low_start <= local && local < *low_end
+                    } else {
+                        *low_start <= local || local < *low_end
+                    };
+                    if is_low { low } else { high }.clone_ref(py)
+                }
+            };
+            result
+                .extract::<T>(py)
+                .map_err(Into::into)
+                .and_then(&convert)
+                .map_err(|e| interval_error("returned an invalid value", e))
         })
     }
 }
 
-#[pyclass(unsendable)]
-#[derive(Clone)]
+#[pyclass(unsendable, module = "electricity_price_optimizer_py")]
 /// A fixed-duration action with constant consumption per timestep.
 /// Times must be on timestep boundaries.
 pub struct ConstantAction {
-    /// Earliest action start (inclusive).
-    pub start_from: DateTime<Utc>,
+    /// Earliest action start (inclusive). None for the start of the context it is added to.
+    pub start_from: Option<DateTime<Utc>>,
     /// Latest action end (exclusive).
     pub end_before: DateTime<Utc>,
-    /// Duration of the action. Must be < 1 day and a multiple of MINUTES_PER_TIMESTEP.
+    /// Duration of the action. Must be < 1 day and a multiple of the timestep length.
     pub duration: TimeDelta,
     /// Fixed consumption per timestep.
     pub consumption: Watt,
+    /// Consumption that happens instead if the action isn't scheduled, e.g. a water heater
+    /// left to its thermostat. It is part of the beyond control consumption.
+    pub(crate) default_profile: Option<Py<PrognosesProvider>>,
+    /// Whether the window stays at its datetimes when the context is rebased instead of moving
+    /// with the start of the context, see OptimizerContext.rebase.
+    pub absolute: bool,
     /// Unique identifier.
     id: u32,
 }
 #[pymethods]
 impl ConstantAction {
     #[new]
+    #[pyo3(signature = (start_from, end_before, duration, consumption, id, default_profile=None, absolute=false))]
     /// Create a ConstantAction. All DateTime values must align to timestep boundaries.
+    /// default_profile is a PrognosesProvider returning WattHour per interval: the consumption
+    /// of the appliance if the optimizer doesn't schedule it, e.g. a water heater heating
+    /// whenever its thermostat asks. It must be contained in the beyond control consumption of
+    /// the context; while the action is scheduled it is removed from it (at most down to zero),
+    /// while the action is disabled it stays.
+    /// With absolute, the window keeps its datetimes when the context is rebased, see
+    /// OptimizerContext.rebase.
     fn new(
         start_from: DateTime<Utc>,
         end_before: DateTime<Utc>,
         duration: TimeDelta,
         consumption: Watt,
         id: u32,
+        default_profile: Option<Py<PrognosesProvider>>,
+        absolute: bool,
     ) -> Self {
         ConstantAction {
-            start_from,
+            start_from: Some(start_from),
             end_before,
             duration,
             consumption,
+            default_profile,
+            absolute,
+            id,
+        }
+    }
+    #[staticmethod]
+    #[pyo3(signature = (deadline, duration, consumption, id, default_profile=None, absolute=false))]
+    /// Create a ConstantAction that can start any time after the context start and must be
+    /// finished by the deadline. The window is resolved when the action is added to a context.
+    fn with_deadline(
+        deadline: DateTime<Utc>,
+        duration: TimeDelta,
+        consumption: Watt,
+        id: u32,
+        default_profile: Option<Py<PrognosesProvider>>,
+        absolute: bool,
+    ) -> Self {
+        ConstantAction {
+            start_from: None,
+            end_before: deadline,
+            duration,
+            consumption,
+            default_profile,
+            absolute,
+            id,
+        }
+    }
+    /// Python __reduce__: pickle as the arguments of the constructor. The start is restored
+    /// by __setstate__, so that actions created with a deadline keep starting with the context.
+    /// A PrognosesProvider as default_profile can't be pickled.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let action = slf.borrow();
+        let args = (
+            action.start_from.unwrap_or(action.end_before),
+            action.end_before,
+            action.duration,
+            action.consumption.clone(),
+            action.id,
+            action
+                .default_profile
+                .as_ref()
+                .map(|provider| provider.clone_ref(py)),
+            action.absolute,
+        );
+        (slf.get_type(), args, (action.start_from,)).into_bound_py_any(py)
+    }
+    /// Python __setstate__: restore the start, see __reduce__.
+    fn __setstate__(&mut self, state: (Option<DateTime<Utc>>,)) {
+        self.start_from = state.0;
+    }
+}
+/// Convert the time bounds of a constant action, validating timestep alignment.
+fn constant_action_bounds(
+    start_from: DateTime<Utc>,
+    end_before: DateTime<Utc>,
+    grid: TimeGrid,
+) -> PyResult<(Time, Time)> {
+    Ok((grid.to_time(start_from)?, grid.to_time(end_before)?))
+}
+/// Translate an error of the constructor of a "Constant" or "Variable" action, naming the
+/// action and its offending field in the units of the Python API.
+fn action_error(kind: &str, error: ActionError, grid: TimeGrid) -> PyErr {
+    match error {
+        ActionError::BeyondHorizon {
             id,
+            field,
+            time,
+            day_end,
+        } => {
+            let times = grid
+                .to_datetime(time)
+                .and_then(|time| Ok((time, grid.to_datetime(day_end)?)));
+            match times {
+                Ok((time, horizon_end)) => OptimizerError::new_err(format!(
+                    "{} action {} {} {} is after the end of the optimization horizon at {}",
+                    kind, id, field, time, horizon_end
+                )),
+                Err(e) => e,
+            }
+        }
+        ActionError::NegativeConsumption { id, field, value } => {
+            let power = Watt::from_energy_per_step(value as f64, grid.step());
+            let value = match field {
+                "total_consumption" | "default_profile" => {
+                    format!("{} Wh", WattHour::from_milli_wh(value as f64).value)
+                }
+                _ => format!("{} W", power.value),
+            };
+            OptimizerError::new_err(format!(
+                "{} action {} {} must not be negative, got {}",
+                kind, id, field, value
+            ))
         }
+        ActionError::DoesNotFit { .. }
+        | ActionError::EmptyWindow { .. }
+        | ActionError::NoContiguousBlock { .. } => InfeasibleError::new_err(error.to_string()),
     }
 }
 impl ConstantAction {
+    /// A copy sharing the default profile provider of this one.
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        ConstantAction {
+            start_from: self.start_from,
+            end_before: self.end_before,
+            duration: self.duration,
+            consumption: self.consumption.clone(),
+            default_profile: self
+                .default_profile
+                .as_ref()
+                .map(|provider| provider.clone_ref(py)),
+            absolute: self.absolute,
+            id: self.id,
+        }
+    }
     /// Convert to internal RustConstantAction, validating duration and timestep alignment.
-    fn to_rust<'py>(
-        &self,
-        _py: Python<'py>,
-        start_time: DateTime<Utc>,
-    ) -> PyResult<RustConstantAction> {
+    /// The default profile is evaluated once per timestep of the grid.
+    fn to_rust<'py>(&self, py: Python<'py>, grid: TimeGrid) -> PyResult<RustConstantAction> {
         let duration = self.duration;
         if duration.num_days() != 0 {
-            return Err(PyValueError::new_err("Duration must be less than 1 day"));
+            return Err(OptimizerError::new_err("Duration must be less than 1 day"));
         }
         let duration_minutes = duration.num_minutes() as u32;
-        if !duration_minutes.is_multiple_of(MINUTES_PER_TIMESTEP) {
-            return Err(PyValueError::new_err(format!(
+        let minutes_per_timestep = grid.timestep().get_minutes_per_timestep();
+        if !duration_minutes.is_multiple_of(minutes_per_timestep) {
+            return Err(TimestepAlignmentError::new_err(format!(
                 "Duration must be a multiple of {} minutes",
-                MINUTES_PER_TIMESTEP
+                minutes_per_timestep
             )));
         }
-        let duration = Time::new(0, duration_minutes);
+        let duration = Time::from_timestep(duration_minutes / minutes_per_timestep);
 
-        check_on_timestep_boundary(self.start_from, start_time)?;
-        let start_time_converted = datetime_to_time(self.start_from, start_time)?;
-        check_on_timestep_boundary(self.end_before, start_time)?;
-        let end_time_converted = datetime_to_time(self.end_before, start_time)?;
+        let start_from = self.start_from.unwrap_or(grid.anchor());
+        let (start_time_converted, end_time_converted) =
+            constant_action_bounds(start_from, self.end_before, grid)?;
+        if start_time_converted + duration > end_time_converted {
+            return Err(InfeasibleError::new_err(format!(
+                "Constant action {} does not fit before {}",
+                self.id, self.end_before
+            )));
+        }
 
-        Ok(RustConstantAction::new(
+        let action = RustConstantAction::try_new(
             start_time_converted,
             end_time_converted,
             duration,
-            self.consumption.to_milli_watt_hour_per_timestep() as i64,
+            self.consumption.to_internal_per_step(
+                grid.step(),
+                &format!("Constant action {} consumption", self.id),
+            )?,
             self.id,
-        ))
+        )
+        .map_err(|e| action_error("Constant", e, grid))?;
+        let Some(provider) = &self.default_profile else {
+            return Ok(action);
+        };
+        let field = format!("Constant action {} default_profile", self.id);
+        let default_profile =
+            provider
+                .borrow(py)
+                .try_get_prognoses(py, grid, |energy: WattHour| energy.to_internal(&field))?;
+        action
+            .with_default_profile(default_profile.into())
+            .map_err(|e| action_error("Constant", e, grid))
+    }
+}
+
+#[pyclass(unsendable)]
+#[derive(Clone)]
+/// A constant action whose consumption follows a fixed profile, e.g. a dishwasher that heats
+/// first and then runs at low power. The duration is one timestep per profile value.
+pub struct ProfiledConstantAction {
+    /// Earliest action start (inclusive).
+    pub start_from: DateTime<Utc>,
+    /// Latest action end (exclusive).
+    pub end_before: DateTime<Utc>,
+    /// Consumption for every timestep of the action, starting at its start time.
+    pub profile: Vec<Watt>,
+    /// Whether the window stays at its datetimes when the context is rebased, see
+    /// ConstantAction.absolute.
+    pub absolute: bool,
+    /// Unique identifier.
+    id: u32,
+}
+#[pymethods]
+impl ProfiledConstantAction {
+    #[new]
+    #[pyo3(signature = (start_from, end_before, profile, id, absolute=false))]
+    /// Create a ProfiledConstantAction. All DateTime values must align to timestep boundaries.
+    /// absolute works as for ConstantAction.
+    fn new(
+        start_from: DateTime<Utc>,
+        end_before: DateTime<Utc>,
+        profile: Vec<Watt>,
+        id: u32,
+        absolute: bool,
+    ) -> Self {
+        ProfiledConstantAction {
+            start_from,
+            end_before,
+            profile,
+            absolute,
+            id,
+        }
+    }
+}
+impl ProfiledConstantAction {
+    /// Convert to internal RustConstantAction, validating the profile and timestep alignment.
+    fn to_rust(&self, grid: TimeGrid) -> PyResult<RustConstantAction> {
+        let steps = grid.timestep().get_steps_per_day();
+        if self.profile.is_empty() || self.profile.len() >= steps as usize {
+            return Err(OptimizerError::new_err(
+                "Profile must have at least one value and be shorter than 1 day",
+            ));
+        }
+        let (start_time_converted, end_time_converted) =
+            constant_action_bounds(self.start_from, self.end_before, grid)?;
+        if start_time_converted + Time::from_timestep(self.profile.len() as u32)
+            > end_time_converted
+        {
+            return Err(InfeasibleError::new_err(format!(
+                "Constant action {} does not fit between start_from and end_before",
+                self.id
+            )));
+        }
+        let field = format!("Constant action {} profile value", self.id);
+        let profile = self
+            .profile
+            .iter()
+            .map(|power| power.to_internal_per_step(grid.step(), &field))
+            .collect::<PyResult<_>>()?;
+        RustConstantAction::try_new_profiled(
+            start_time_converted,
+            end_time_converted,
+            profile,
+            self.id,
+        )
+        .map_err(|e| action_error("Constant", e, grid))
+    }
+}
+
+/// Number of ids reserved for the blocks of a recurring action: block k of the action with
+/// id `id` has id `id * RECURRING_BLOCK_IDS + k`.
+const RECURRING_BLOCK_IDS: u32 = 1000;
+
+#[pyclass(unsendable)]
+#[derive(Clone)]
+/// A constant action that runs count times in separate blocks, e.g. a pool pump running three
+/// one hour blocks a day. Every block is a constant action of its own with the id
+/// id * 1000 + k for the k-th block, and starts at least min_gap after the previous one ends.
+pub struct RecurringConstantAction {
+    /// Earliest start of the first block (inclusive).
+    pub start_from: DateTime<Utc>,
+    /// Latest end of the last block (exclusive).
+    pub end_before: DateTime<Utc>,
+    /// Number of blocks.
+    pub count: u32,
+    /// Duration of every block. Must be a multiple of the timestep length.
+    pub duration: TimeDelta,
+    /// Time between the end of a block and the start of the next one at the least. Must be a
+    /// multiple of the timestep length.
+    pub min_gap: TimeDelta,
+    /// Fixed consumption per timestep of every block.
+    pub consumption: Watt,
+    /// Unique identifier, the blocks are numbered from id * 1000.
+    id: u32,
+}
+#[pymethods]
+impl RecurringConstantAction {
+    #[new]
+    #[pyo3(signature = (start_from, end_before, count, duration, min_gap, consumption, id))]
+    /// Create a RecurringConstantAction. All DateTime values must align to timestep boundaries.
+    /// count must be between 1 and 999.
+    fn new(
+        start_from: DateTime<Utc>,
+        end_before: DateTime<Utc>,
+        count: u32,
+        duration: TimeDelta,
+        min_gap: TimeDelta,
+        consumption: Watt,
+        id: u32,
+    ) -> Self {
+        RecurringConstantAction {
+            start_from,
+            end_before,
+            count,
+            duration,
+            min_gap,
+            consumption,
+            id,
+        }
+    }
+    /// IDs of the blocks in the order they run. Errors if count is out of range or the ids
+    /// don't fit into 32 bits.
+    fn block_ids(&self) -> PyResult<Vec<u32>> {
+        if self.count == 0 || self.count >= RECURRING_BLOCK_IDS {
+            return Err(OptimizerError::new_err(format!(
+                "Recurring action {} must have between 1 and {} blocks, got {}",
+                self.id,
+                RECURRING_BLOCK_IDS - 1,
+                self.count
+            )));
+        }
+        let first = self
+            .id
+            .checked_mul(RECURRING_BLOCK_IDS)
+            .filter(|first| first.checked_add(RECURRING_BLOCK_IDS).is_some())
+            .ok_or_else(|| {
+                OptimizerError::new_err(format!(
+                    "Recurring action id {} is too large, the ids of its blocks don't fit",
+                    self.id
+                ))
+            })?;
+        Ok((first..first + self.count).collect())
+    }
+}
+impl RecurringConstantAction {
+    /// The blocks as constant actions, in the order they run.
+    fn blocks(&self) -> PyResult<Vec<ConstantAction>> {
+        Ok(self
+            .block_ids()?
+            .into_iter()
+            .map(|id| ConstantAction {
+                start_from: Some(self.start_from),
+                end_before: self.end_before,
+                duration: self.duration,
+                consumption: self.consumption.clone(),
+                default_profile: None,
+                absolute: false,
+                id,
+            })
+            .collect())
+    }
+    /// Timesteps of min_gap, validating that it is a non-negative multiple of the timestep.
+    fn min_gap_steps(&self, timestep: TimestepConfig) -> PyResult<u32> {
+        let step = timestep_duration(timestep);
+        if self.min_gap < TimeDelta::zero() || self.min_gap >= TimeDelta::days(1) {
+            return Err(OptimizerError::new_err(format!(
+                "min_gap of recurring action {} must not be negative and less than a day, got {}",
+                self.id, self.min_gap
+            )));
+        }
+        if self.min_gap.subsec_nanos() != 0 || self.min_gap.num_seconds() % step.num_seconds() != 0
+        {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "min_gap must be a multiple of {} minutes",
+                timestep.get_minutes_per_timestep()
+            )));
+        }
+        Ok((self.min_gap.num_seconds() / step.num_seconds()) as u32)
     }
 }
 
+#[derive(FromPyObject)]
+/// Any of the constant action classes accepted by OptimizerContext.add_constant_action.
+enum AnyConstantAction<'py> {
+    Flat(PyRef<'py, ConstantAction>),
+    Profiled(PyRef<'py, ProfiledConstantAction>),
+}
+
+/// An action added with absolute, kept to resolve its window again when the context is rebased.
+enum AbsoluteAction {
+    Constant(ConstantAction),
+    Profiled(ProfiledConstantAction),
+    Variable(VariableAction),
+}
+
 #[pyclass(unsendable)]
-/// A constant action assigned by the optimizer, exposing start/end times and ID.
+/// A constant action assigned by the optimizer, exposing its start/end times, its consumption,
+/// the window it had to run in and its ID.
 pub struct AssignedConstantAction {
     inner: RustAssignedConstantAction,
-    start_timestamp: DateTime<Utc>,
+    grid: TimeGrid,
 }
 #[pymethods]
 impl AssignedConstantAction {
     /// Get the assigned start time as DateTime<Utc>.
     fn get_start_time(&self) -> PyResult<DateTime<Utc>> {
-        time_to_datetime(self.inner.get_start_time(), self.start_timestamp)
+        self.grid.to_datetime(self.inner.get_start_time())
     }
     /// Get the assigned end time as DateTime<Utc>.
     fn get_end_time(&self) -> PyResult<DateTime<Utc>> {
-        time_to_datetime(self.inner.get_end_time(), self.start_timestamp)
+        self.grid.to_datetime(self.inner.get_end_time())
+    }
+    /// Get the consumption of every timestep from start to end as (DateTime<Utc>, Watt) tuples.
+    fn get_consumption_profile(&self) -> PyResult<Vec<(DateTime<Utc>, Watt)>> {
+        self.inner
+            .iter_consumption()
+            .map(|(time, consumption)| {
+                Ok((
+                    self.grid.to_datetime(time)?,
+                    Watt::from_energy_per_step(consumption as f64, self.grid.step()),
+                ))
+            })
+            .collect()
+    }
+    /// Get the power the action runs at. Errors for actions whose consumption varies over
+    /// their duration, use get_consumption_profile for those.
+    fn get_consumption(&self) -> PyResult<Watt> {
+        let consumption = self.inner.get_flat_consumption().ok_or_else(|| {
+            OptimizerError::new_err(format!(
+                "Constant action {} has a varying consumption, use get_consumption_profile",
+                self.inner.get_id()
+            ))
+        })?;
+        Ok(Watt::from_energy_per_step(
+            consumption as f64,
+            self.grid.step(),
+        ))
+    }
+    /// Get how long the action runs, as given when creating it.
+    fn get_duration(&self) -> TimeDelta {
+        self.grid.step() * self.inner.get_duration().to_timestep() as i32
+    }
+    /// Get the window the action had to run in as (start_from, end_before).
+    fn get_window(&self) -> PyResult<(DateTime<Utc>, DateTime<Utc>)> {
+        let grid = self.grid;
+        Ok((
+            grid.to_datetime(self.inner.get_start_from())?,
+            grid.to_datetime(self.inner.get_end_before())?,
+        ))
     }
     /// Get the unique action ID.
     fn get_id(&self) -> u32 {
         self.inner.get_id()
     }
+    /// Python __repr__: ID, start time and power, "varying" for profiled actions.
+    fn __repr__(&self) -> PyResult<String> {
+        let power = match self.get_consumption() {
+            Ok(power) => format!("{:.2} W", power.value),
+            Err(_) => "varying".to_string(),
+        };
+        Ok(format!(
+            "AssignedConstantAction(id={}, start={}, power={})",
+            self.inner.get_id(),
+            self.get_start_time()?,
+            power
+        ))
+    }
 }
 
-#[pyclass(unsendable)]
+#[derive(FromPyObject)]
+/// Maximum consumption of a variable action: constant, from a provider or one value per timestep.
+enum MaxConsumption {
+    Constant(Watt),
+    Varying(Py<PrognosesProvider>),
+    PerTimestep(Vec<Watt>),
+}
+
+#[pyclass(unsendable, module = "electricity_price_optimizer_py")]
 /// A variable action with total energy and per-timestep max consumption constraints.
 /// Times must be on timestep boundaries.
 pub struct VariableAction {
-    /// Earliest time the action can start (inclusive).
-    pub start: DateTime<Utc>,
+    /// Earliest time the action can start (inclusive). None for the start of the context it is
+    /// added to.
+    pub start: Option<DateTime<Utc>>,
     /// Latest time the action must end (exclusive).
     pub end: DateTime<Utc>,
     /// Total energy to consume over the window.
     pub total_consumption: WattHour,
-    /// Per-timestep maximum consumption.
-    pub max_consumption: Watt,
+    /// Per-timestep maximum consumption, constant or varying over time.
+    pub(crate) max_consumption: MaxConsumption,
+    /// Least consumption of a timestep the action consumes anything in.
+    pub min_consumption: Watt,
+    /// Bonus (negative) or malus (positive) per Wh consumed in an interval, making the optimizer
+    /// prefer some intervals over others. It isn't paid, only weighs the plan.
+    pub(crate) preference: Option<Py<PrognosesProvider>>,
+    /// Largest preference as a share of the average absolute electricity price.
+    pub preference_limit: f64,
+    /// Whether the total is consumed in one block of consecutive timesteps.
+    pub contiguous: bool,
+    /// Whether the window stays at its datetimes when the context is rebased, see
+    /// ConstantAction.absolute.
+    pub absolute: bool,
     /// Unique identifier.
     id: u32,
 }
 #[pymethods]
 impl VariableAction {
     #[new]
+    #[pyo3(signature = (start, end, total_consumption, max_consumption, id, min_consumption=None, preference=None, preference_limit=0.1, contiguous=false, absolute=false))]
     /// Create a VariableAction. DateTimes must be aligned to timestep boundaries.
+    /// max_consumption is a Watt, a PrognosesProvider returning Watt per interval or a list
+    /// with one Watt per timestep of the window. min_consumption (default 0) is the least
+    /// power of every timestep the action consumes anything in. preference is a
+    /// PrognosesProvider returning EuroPerWh per interval, added to the price the action sees
+    /// but not paid: negative for intervals to prefer, e.g. early ones. It is bounded to
+    /// preference_limit (default 0.1) times the average absolute electricity price.
+    /// With contiguous, the total is consumed in one uninterrupted block at a fixed rate as
+    /// high as the smallest max_consumption of the window allows. The block is placed by
+    /// run_simulated_annealing like a constant action and ignores the preference.
+    /// absolute works as for ConstantAction.
+    #[allow(clippy::too_many_arguments)]
     fn new(
         start: DateTime<Utc>,
         end: DateTime<Utc>,
         total_consumption: WattHour,
-        max_consumption: Watt,
+        max_consumption: MaxConsumption,
         id: u32,
+        min_consumption: Option<Watt>,
+        preference: Option<Py<PrognosesProvider>>,
+        preference_limit: f64,
+        contiguous: bool,
+        absolute: bool,
     ) -> Self {
         VariableAction {
-            start,
+            start: Some(start),
             end,
             total_consumption,
             max_consumption,
+            min_consumption: min_consumption.unwrap_or_default(),
+            preference,
+            preference_limit,
+            contiguous,
+            absolute,
+            id,
+        }
+    }
+    #[staticmethod]
+    #[pyo3(signature = (deadline, total_consumption, max_consumption, id, min_consumption=None, preference=None, preference_limit=0.1, contiguous=false, absolute=false))]
+    #[allow(clippy::too_many_arguments)]
+    /// Create a VariableAction that can consume any time after the context start and must be
+    /// finished by the deadline. The window is resolved when the action is added to a context.
+    fn with_deadline(
+        deadline: DateTime<Utc>,
+        total_consumption: WattHour,
+        max_consumption: MaxConsumption,
+        id: u32,
+        min_consumption: Option<Watt>,
+        preference: Option<Py<PrognosesProvider>>,
+        preference_limit: f64,
+        contiguous: bool,
+        absolute: bool,
+    ) -> Self {
+        VariableAction {
+            start: None,
+            end: deadline,
+            total_consumption,
+            max_consumption,
+            min_consumption: min_consumption.unwrap_or_default(),
+            preference,
+            preference_limit,
+            contiguous,
+            absolute,
             id,
         }
     }
+    /// Python __reduce__: pickle as the arguments of the constructor, like
+    /// ConstantAction.__reduce__. A PrognosesProvider as max_consumption or preference can't
+    /// be pickled.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let action = slf.borrow();
+        let max_consumption = match &action.max_consumption {
+            MaxConsumption::Constant(power) => power.clone().into_bound_py_any(py)?,
+            MaxConsumption::Varying(provider) => provider.clone_ref(py).into_bound_py_any(py)?,
+            MaxConsumption::PerTimestep(caps) => caps.clone().into_bound_py_any(py)?,
+        };
+        let args = (
+            action.start.unwrap_or(action.end),
+            action.end,
+            action.total_consumption.clone(),
+            max_consumption,
+            action.id,
+            action.min_consumption.clone(),
+            action
+                .preference
+                .as_ref()
+                .map(|provider| provider.clone_ref(py)),
+            action.preference_limit,
+            action.contiguous,
+            action.absolute,
+        );
+        (slf.get_type(), args, (action.start,)).into_bound_py_any(py)
+    }
+    /// Python __setstate__: restore the start, see __reduce__.
+    fn __setstate__(&mut self, state: (Option<DateTime<Utc>>,)) {
+        self.start = state.0;
+    }
 }
 impl VariableAction {
+    /// A copy sharing the providers of this one.
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        let max_consumption = match &self.max_consumption {
+            MaxConsumption::Constant(power) => MaxConsumption::Constant(power.clone()),
+            MaxConsumption::Varying(provider) => MaxConsumption::Varying(provider.clone_ref(py)),
+            MaxConsumption::PerTimestep(caps) => MaxConsumption::PerTimestep(caps.clone()),
+        };
+        VariableAction {
+            start: self.start,
+            end: self.end,
+            total_consumption: self.total_consumption.clone(),
+            max_consumption,
+            min_consumption: self.min_consumption.clone(),
+            preference: self
+                .preference
+                .as_ref()
+                .map(|provider| provider.clone_ref(py)),
+            preference_limit: self.preference_limit,
+            contiguous: self.contiguous,
+            absolute: self.absolute,
+            id: self.id,
+        }
+    }
     /// Convert to internal RustVariableAction, validating timestep alignment.
-    fn to_rust(&self, start_time: DateTime<Utc>) -> PyResult<RustVariableAction> {
-        check_on_timestep_boundary(self.start, start_time)?;
-        let start_time_converted = datetime_to_time(self.start, start_time)?;
-        check_on_timestep_boundary(self.end, start_time)?;
-        let end_time_converted = datetime_to_time(self.end, start_time)?;
+    /// Actions created with a deadline or with a time-varying max consumption must also be able
+    /// to consume their total within their window, actions with a min consumption must be able
+    /// to split it into timesteps of at least that. Contiguous actions must fit their total into
+    /// one block.
+    fn to_rust(&self, py: Python<'_>, grid: TimeGrid) -> PyResult<RustVariableAction> {
+        let start = self.start.unwrap_or(grid.anchor());
+        let start_time_converted = grid.to_time(start)?;
+        let end_time_converted = grid.to_time(self.end)?;
+        if start_time_converted >= end_time_converted {
+            return Err(OptimizerError::new_err(format!(
+                "Variable action {} must end after it starts",
+                self.id
+            )));
+        }
 
-        Ok(RustVariableAction::new(
+        let total_consumption = self
+            .total_consumption
+            .to_internal(&format!("Variable action {} total_consumption", self.id))?;
+        let to_energy = |power: &Watt, name: &str| {
+            power.to_internal_per_step(
+                grid.step(),
+                &format!("Variable action {} {}", self.id, name),
+            )
+        };
+        let max_consumption = match &self.max_consumption {
+            MaxConsumption::Constant(power) => to_energy(power, "max_consumption")?,
+            _ => 0,
+        };
+        // Validates the window against the horizon before the profile is cut out of it
+        let mut action = RustVariableAction::try_new(
             start_time_converted,
             end_time_converted,
-            self.total_consumption.to_milli_wh() as i64,
-            self.max_consumption.to_milli_watt_hour_per_timestep() as i64,
+            total_consumption,
+            max_consumption,
             self.id,
-        ))
+        )
+        .and_then(|action| action.validate_for(grid.timestep()).map(|_| action))
+        .map_err(|e| action_error("Variable", e, grid))?;
+
+        let window =
+            start_time_converted.to_timestep() as usize..end_time_converted.to_timestep() as usize;
+        let profile = match &self.max_consumption {
+            MaxConsumption::Constant(_) => None,
+            MaxConsumption::Varying(provider) => {
+                let caps = provider
+                    .borrow(py)
+                    .try_get_prognoses(py, grid, |power: Watt| {
+                        to_energy(&power, "max_consumption")
+                    })?;
+                Some(caps.get_data()[window.clone()].to_vec())
+            }
+            MaxConsumption::PerTimestep(caps) => {
+                if caps.len() != window.len() {
+                    return Err(OptimizerError::new_err(format!(
+                        "Variable action {} needs {} max_consumption values, one per timestep, got {}",
+                        self.id,
+                        window.len(),
+                        caps.len()
+                    )));
+                }
+                Some(
+                    caps.iter()
+                        .map(|cap| to_energy(cap, "max_consumption"))
+                        .collect::<PyResult<_>>()?,
+                )
+            }
+        };
+        if profile
+            .as_ref()
+            .is_some_and(|profile| profile.iter().any(|cap| *cap < 0))
+        {
+            return Err(OptimizerError::new_err(format!(
+                "Max consumption of variable action {} must not be negative",
+                self.id
+            )));
+        }
+
+        let varying = profile.is_some();
+        if let Some(profile) = profile {
+            action = action.with_max_consumption_profile(profile);
+        }
+        let min_consumption = &self.min_consumption;
+        if min_consumption.value.is_nan() || min_consumption.value < 0.0 {
+            return Err(OptimizerError::new_err(format!(
+                "Min consumption of variable action {} must not be negative, got {} W",
+                self.id, min_consumption.value
+            )));
+        }
+        if min_consumption.value > 0.0 {
+            action = action.with_min_consumption(to_energy(min_consumption, "min_consumption")?);
+            if !action.can_reach_total_with_min_consumption() {
+                return Err(InfeasibleError::new_err(format!(
+                    "Variable action {} can't split its total into timesteps of at least {} W within its max_consumption",
+                    self.id, min_consumption.value
+                )));
+            }
+        }
+        if let Some(provider) = &self.preference {
+            if !(self.preference_limit.is_finite() && self.preference_limit >= 0.0) {
+                return Err(OptimizerError::new_err(format!(
+                    "Preference limit of variable action {} must not be negative, got {}",
+                    self.id, self.preference_limit
+                )));
+            }
+            let preference =
+                provider
+                    .borrow(py)
+                    .try_get_prognoses(py, grid, |preference: EuroPerWh| {
+                        preference.to_internal(&format!("Variable action {} preference", self.id))
+                    })?;
+            action = action.with_preference(
+                preference.get_data()[window.clone()].to_vec(),
+                self.preference_limit as f32,
+            );
+        }
+        let achievable = action.get_max_total_consumption();
+        if (self.start.is_none() || varying) && total_consumption > achievable {
+            return Err(InfeasibleError::new_err(format!(
+                "Variable action {} can't consume its total before {}, it is {:.2} Wh short",
+                self.id,
+                self.end,
+                WattHour::from_milli_wh((total_consumption - achievable) as f64).value
+            )));
+        }
+        if self.contiguous {
+            action = action.with_contiguous(true);
+            action
+                .get_block()
+                .map_err(|e| action_error("Variable", e, grid))?;
+        }
+        Ok(action)
     }
 }
 
 #[pyclass(unsendable)]
 pub struct AssignedVariableAction {
     inner: RustAssignedVariableAction,
-    start_timestamp: DateTime<Utc>,
+    grid: TimeGrid,
 }
 #[pymethods]
 impl AssignedVariableAction {
-    fn get_consumption(&self, time: DateTime<Utc>) -> PyResult<Watt> {
-        let time_converted = datetime_to_time(time, self.start_timestamp)?;
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the assigned consumption at a given DateTime<Utc> within the action's window.
+    /// With clamp, times outside of the window give its first or last timestep.
+    fn get_consumption(&self, py: Python<'_>, time: DateTime<Utc>, clamp: bool) -> PyResult<Watt> {
+        let valid = self.inner.get_start()..self.inner.get_end();
+        let asset = Some(("variable action", self.inner.get_id()));
+        let time_converted = time_in_range(py, time, self.grid, valid, clamp, asset)?;
         let consumption_per_timestep = self.inner.get_consumption(time_converted);
-        Ok(Watt::from_milli_watt_hour_per_timestep(
+        Ok(Watt::from_energy_per_step(
             consumption_per_timestep as f64,
+            self.grid.step(),
         ))
     }
+    /// Get the assigned consumption of every timestep from start to end as (DateTime<Utc>, Watt) tuples.
+    fn get_consumption_profile(&self) -> PyResult<Vec<(DateTime<Utc>, Watt)>> {
+        self.inner
+            .iter_consumption()
+            .map(|(time, consumption)| {
+                Ok((
+                    self.grid.to_datetime(time)?,
+                    Watt::from_energy_per_step(consumption as f64, self.grid.step()),
+                ))
+            })
+            .collect()
+    }
+    /// Get the total energy assigned over the whole profile.
+    fn get_total_assigned(&self) -> WattHour {
+        WattHour::from_milli_wh(self.inner.get_total_assigned() as f64)
+    }
     fn get_id(&self) -> u32 {
         self.inner.get_id()
     }
 }
-#[pyclass(unsendable)]
+#[pyclass(unsendable, module = "electricity_price_optimizer_py")]
 pub struct Battery {
     /// Maximum capacity.
     pub capacity: WattHour,
@@ -349,38 +1322,142 @@ pub struct Battery {
     pub initial_charge: WattHour,
     /// Unique identifier.
     pub id: u32,
+    /// Charging efficiency in (0, 1]. Fraction of the drawn energy that ends up stored.
+    pub efficiency: f32,
+    /// Whether the battery may be charged from the grid. If false, only generated electricity is stored.
+    pub grid_charging_allowed: bool,
+    /// Reserve the charge level never drops below.
+    pub min_charge: WattHour,
+    /// Charge level to reach at least by the end of the horizon.
+    pub final_charge_target: Option<WattHour>,
+    /// Fraction of the stored charge lost per hour of holding it, in [0, 1).
+    pub self_discharge_per_hour: f32,
+    /// Wear cost of every Wh stored, keeps the battery from cycling for less than that.
+    pub cycle_cost: EuroPerWh,
 }
 #[pymethods]
 impl Battery {
     #[new]
-    /// Create a Battery definition.
+    #[pyo3(signature = (capacity, max_charge_rate, max_discharge_rate, initial_charge, id, efficiency=1.0, grid_charging_allowed=true, min_charge=WattHour::default(), final_charge_target=None, self_discharge_per_hour=0.0, cycle_cost=EuroPerWh::default()))]
+    /// Create a Battery definition. Errors if efficiency is not in (0, 1], if the self-discharge
+    /// rate is not in [0, 1), if the cycle cost is negative, or if the reserve or target don't
+    /// fit the capacity and initial charge.
+    #[allow(clippy::too_many_arguments)]
     fn new(
         capacity: WattHour,
         max_charge_rate: Watt,
         max_discharge_rate: Watt,
         initial_charge: WattHour,
         id: u32,
-    ) -> Self {
-        Battery {
+        efficiency: f32,
+        grid_charging_allowed: bool,
+        min_charge: WattHour,
+        final_charge_target: Option<WattHour>,
+        self_discharge_per_hour: f32,
+        cycle_cost: EuroPerWh,
+    ) -> PyResult<Self> {
+        if !(efficiency > 0.0 && efficiency <= 1.0) {
+            return Err(OptimizerError::new_err(format!(
+                "Battery efficiency must be in (0, 1], got {}",
+                efficiency
+            )));
+        }
+        if !(0.0..1.0).contains(&self_discharge_per_hour) {
+            return Err(OptimizerError::new_err(format!(
+                "Battery self_discharge_per_hour must be in [0, 1), got {}",
+                self_discharge_per_hour
+            )));
+        }
+        if cycle_cost.value.is_nan() || cycle_cost.value < 0.0 {
+            return Err(OptimizerError::new_err(format!(
+                "Battery cycle_cost can't be negative, got {} €/Wh",
+                cycle_cost.value
+            )));
+        }
+        if !(0.0..=capacity.value).contains(&min_charge.value) {
+            return Err(OptimizerError::new_err(
+                "Battery min_charge must be between 0 and the capacity",
+            ));
+        }
+        if initial_charge.value < min_charge.value {
+            return Err(OptimizerError::new_err(
+                "Battery initial_charge cannot be below min_charge",
+            ));
+        }
+        if final_charge_target
+            .as_ref()
+            .is_some_and(|target| target.value > capacity.value)
+        {
+            return Err(OptimizerError::new_err(
+                "Battery final_charge_target cannot exceed the capacity",
+            ));
+        }
+        Ok(Battery {
             capacity,
             max_charge_rate,
             max_discharge_rate,
             initial_charge,
             id,
-        }
+            efficiency,
+            grid_charging_allowed,
+            min_charge,
+            final_charge_target,
+            self_discharge_per_hour,
+            cycle_cost,
+        })
+    }
+    /// Python __reduce__: pickle as the arguments of the constructor.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let battery = slf.borrow();
+        let args = (
+            battery.capacity.clone(),
+            battery.max_charge_rate.clone(),
+            battery.max_discharge_rate.clone(),
+            battery.initial_charge.clone(),
+            battery.id,
+            battery.efficiency,
+            battery.grid_charging_allowed,
+            battery.min_charge.clone(),
+            battery.final_charge_target.clone(),
+            battery.self_discharge_per_hour,
+            battery.cycle_cost.clone(),
+        );
+        (slf.get_type(), args).into_bound_py_any(slf.py())
     }
 }
 impl Battery {
-    /// Convert to internal RustBattery with losses fixed at 1.0 (no loss).
-    fn to_rust(&self) -> RustBattery {
-        RustBattery::new(
-            self.capacity.to_milli_wh() as i64,
-            self.initial_charge.to_milli_wh() as i64,
-            self.max_charge_rate.to_milli_watt_hour_per_timestep() as i64,
-            self.max_discharge_rate.to_milli_watt_hour_per_timestep() as i64,
-            1.0,
+    /// Convert to internal RustBattery. Errors if a value is too large for the optimizer.
+    fn to_rust(&self, timestep: TimestepConfig) -> PyResult<RustBattery> {
+        let energy = |energy: &WattHour, name: &str| {
+            energy.to_internal(&format!("Battery {} {}", self.id, name))
+        };
+        let power = |power: &Watt, name: &str| {
+            power.to_internal_per_step(
+                timestep_duration(timestep),
+                &format!("Battery {} {}", self.id, name),
+            )
+        };
+        let battery = RustBattery::new(
+            energy(&self.capacity, "capacity")?,
+            energy(&self.initial_charge, "initial_charge")?,
+            power(&self.max_charge_rate, "max_charge_rate")?,
+            power(&self.max_discharge_rate, "max_discharge_rate")?,
+            self.efficiency,
             self.id,
         )
+        .with_grid_charging_allowed(self.grid_charging_allowed)
+        .with_min_charge(energy(&self.min_charge, "min_charge")?)
+        .with_self_discharge_per_hour(self.self_discharge_per_hour)
+        .with_cycle_cost(
+            self.cycle_cost
+                .to_internal(&format!("Battery {} cycle_cost", self.id))?,
+        );
+        Ok(match &self.final_charge_target {
+            Some(target) => {
+                battery.with_final_charge_target(energy(target, "final_charge_target")?)
+            }
+            None => battery,
+        })
     }
 }
 
@@ -388,52 +1465,180 @@ impl Battery {
 /// A battery assignment exposing charge level and instantaneous charge speed at timesteps.
 pub struct AssignedBattery {
     inner: RustAssignedBattery,
-    start_timestamp: DateTime<Utc>,
+    grid: TimeGrid,
 }
 #[pymethods]
 impl AssignedBattery {
-    /// Get charge level at a given DateTime<Utc>. Errors if out of range.
-    fn get_charge_level(&self, time: DateTime<Utc>) -> PyResult<WattHour> {
-        let time_converted = datetime_to_time(time, self.start_timestamp)?;
-        if let Some(result) = self.inner.get_charge_level(time_converted) {
-            Ok(WattHour::from_milli_wh(*result as f64))
-        } else {
-            Err(PyValueError::new_err(
-                "Time out of range for battery charge level FIXME",
-            ))
-        }
-    }
-    /// Get charge speed (delta between timestep and next). Returns 0 at end-of-day.
-    fn get_charge_speed(&self, time: DateTime<Utc>) -> PyResult<Watt> {
-        let time_converted = datetime_to_time(time, self.start_timestamp)?;
-        let next_time = time_converted.get_next_timestep();
-        // get charge levels at time and next_time
-        // next time might be end of day in which case we return 0
-        let curr_level = if let Some(level) = self.inner.get_charge_level(time_converted) {
-            *level
-        } else {
-            return Err(PyValueError::new_err(
-                "Time out of range for battery charge level FIXME",
-            ));
-        };
-        let next_level = if let Some(level) = self.inner.get_charge_level(next_time) {
-            *level
-        } else if next_time == Time::get_day_end() {
-            0
-        } else {
-            return Err(PyValueError::new_err(
-                "Time out of range for battery charge level FIXME",
-            ));
-        };
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the charge level at the start of the timestep beginning at a given DateTime<Utc>,
+    /// i.e. before charging or discharging during it. The end of the horizon gives the final level.
+    /// With clamp, times before the start give the initial and times after the end the final level,
+    /// otherwise they raise OutOfRange.
+    fn get_charge_level(
+        &self,
+        py: Python<'_>,
+        time: DateTime<Utc>,
+        clamp: bool,
+    ) -> PyResult<WattHour> {
+        // The end of the horizon is the only level not at the start of a timestep.
+        let valid = Time::from_timestep(0)
+            ..Time::from_timestep(self.grid.timestep().get_steps_per_day() + 1);
+        let time_converted = time_in_range(py, time, self.grid, valid, clamp, self.asset())?;
+        Ok(WattHour::from_milli_wh(
+            self.charge_level_at(time_converted) as f64,
+        ))
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the charge speed during the timestep beginning at a given DateTime<Utc>, i.e. the
+    /// difference between the levels at its start and its end.
+    /// With clamp, times outside of the schedule give the first or last timestep.
+    fn get_charge_speed(&self, py: Python<'_>, time: DateTime<Utc>, clamp: bool) -> PyResult<Watt> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid,
+            schedule_range(self.grid),
+            clamp,
+            self.asset(),
+        )?;
+        let curr_level = self.charge_level_at(time_converted);
+        let next_level = self.charge_level_at(time_converted.get_next_timestep());
 
         let delta_charge = next_level - curr_level;
-        Ok(Watt::from_milli_watt_hour_per_timestep(delta_charge as f64))
+        Ok(Watt::from_energy_per_step(
+            delta_charge as f64,
+            self.grid.step(),
+        ))
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the part of the charge speed that comes from generated electricity.
+    /// Returns None if the battery may be charged from the grid.
+    /// With clamp, times outside of the schedule give the first or last timestep.
+    fn get_green_charge(
+        &self,
+        py: Python<'_>,
+        time: DateTime<Utc>,
+        clamp: bool,
+    ) -> PyResult<Option<Watt>> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid,
+            schedule_range(self.grid),
+            clamp,
+            self.asset(),
+        )?;
+        Ok(self
+            .inner
+            .get_green_charge(time_converted)
+            .map(|charge| Watt::from_energy_per_step(charge as f64, self.grid.step())))
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get how much faster than planned the battery could discharge during the timestep
+    /// beginning at a given DateTime<Utc>, limited by its maximum output and the charge left
+    /// above min_charge. With clamp, times outside of the schedule give the first or last
+    /// timestep.
+    fn get_discharge_headroom(
+        &self,
+        py: Python<'_>,
+        time: DateTime<Utc>,
+        clamp: bool,
+    ) -> PyResult<Watt> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid,
+            schedule_range(self.grid),
+            clamp,
+            self.asset(),
+        )?;
+        let headroom = self
+            .inner
+            .get_discharge_headroom(time_converted, first_timestep_fraction(self.grid)?)
+            .expect("Charge levels are known from the start to the end of the horizon");
+        Ok(Watt::from_energy_per_step(
+            headroom as f64,
+            self.grid.step(),
+        ))
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get how much faster than planned the battery could charge during the timestep
+    /// beginning at a given DateTime<Utc>, limited by its maximum charge rate and the room
+    /// left below its capacity. With clamp, times outside of the schedule give the first or
+    /// last timestep.
+    fn get_charge_headroom(
+        &self,
+        py: Python<'_>,
+        time: DateTime<Utc>,
+        clamp: bool,
+    ) -> PyResult<Watt> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid,
+            schedule_range(self.grid),
+            clamp,
+            self.asset(),
+        )?;
+        let headroom = self
+            .inner
+            .get_charge_headroom(time_converted, first_timestep_fraction(self.grid)?)
+            .expect("Charge levels are known from the start to the end of the horizon");
+        Ok(Watt::from_energy_per_step(
+            headroom as f64,
+            self.grid.step(),
+        ))
+    }
+    /// Get the charge level at the start of every timestep of the horizon, followed by the
+    /// final level at its end, as (DateTime<Utc>, WattHour) pairs. Each entry equals
+    /// get_charge_level at its time, so there is one more entry than timesteps.
+    fn get_charge_curve(&self) -> PyResult<Vec<(DateTime<Utc>, WattHour)>> {
+        let grid = self.grid;
+        self.inner
+            .iter_charge_levels()
+            .map(|(time, level)| {
+                Ok((
+                    grid.to_datetime(time)?,
+                    WattHour::from_milli_wh(level as f64),
+                ))
+            })
+            .collect()
+    }
+    /// Get the charge speed during every timestep of the horizon as (DateTime<Utc>, Watt)
+    /// pairs, each equal to get_charge_speed at its time. The last entry is the timestep
+    /// ending at the end of the horizon, there is none for the end itself, which
+    /// get_charge_speed rejects as well.
+    fn get_speed_curve(&self) -> PyResult<Vec<(DateTime<Utc>, Watt)>> {
+        let grid = self.grid;
+        let levels: Vec<(Time, i64)> = self.inner.iter_charge_levels().collect();
+        levels
+            .windows(2)
+            .map(|pair| {
+                let ((time, level), (_, next_level)) = (pair[0], pair[1]);
+                Ok((
+                    grid.to_datetime(time)?,
+                    Watt::from_energy_per_step((next_level - level) as f64, self.grid.step()),
+                ))
+            })
+            .collect()
     }
     /// Get battery ID.
     fn get_id(&self) -> u32 {
         self.inner.get_battery().get_id()
     }
 }
+impl AssignedBattery {
+    /// The battery, as named by OutOfRange errors.
+    fn asset(&self) -> Option<(&'static str, u32)> {
+        Some(("battery", self.inner.get_battery().get_id()))
+    }
+    fn charge_level_at(&self, time: Time) -> i64 {
+        *self
+            .inner
+            .get_charge_level(time)
+            .expect("Charge levels are known from the start to the end of the horizon")
+    }
+}
 
 #[pyclass(unsendable)]
 /// Builder holding prognoses and assets before solving.
@@ -451,28 +1656,107 @@ struct OptimizerContext {
     constant_actions: Vec<Rc<RustConstantAction>>,
     /// Variable actions.
     variable_actions: Vec<Rc<RustVariableAction>>,
+    /// Feed-in tariff prognoses: micro-euro per Wh (i64) internally. None disables grid export.
+    feed_in_tariff: Option<Prognoses<i64>>,
+    /// Maximum grid export: milli-Wh per timestep (i64). None means unlimited.
+    max_export: Option<i64>,
+    /// Network fee on grid imports: micro-euro per Wh (i64) internally. None for no fee.
+    network_fee: Option<Prognoses<i64>>,
+    /// CO2 intensity of grid imports: milligram per Wh (i64) internally, with the price of the
+    /// CO2: micro-euro per milligram (f64). None doesn't track emissions.
+    co2_intensity: Option<(Prognoses<i64>, f64)>,
+    /// Net metering as (flat rate: micro-euro per Wh (i64), weight of the electricity price in
+    /// the objective). None bills import and export separately.
+    net_metering: Option<(i64, f64)>,
+    /// Maximum grid import: milli-Wh per timestep (i64). None means unlimited.
+    grid_limit: Option<i64>,
+    /// Peak price: nano-euro per milli-Wh per timestep of the highest grid draw. None for no
+    /// peak charge.
+    peak_price: Option<i64>,
+    /// Settlement period: timesteps (u32) over which the grid draw is averaged for the peak
+    /// charge, aligned to the clock. None measures single timesteps.
+    settlement_period: Option<u32>,
+    /// Fixed dispatch of batteries by id: milli-Wh stored (positive) or discharged (negative)
+    /// per timestep (i64).
+    fixed_battery_plans: Vec<(u32, Prognoses<i64>)>,
+    /// Pairs of constant action ids (before, after): before must end before after starts.
+    precedences: Vec<(u32, u32)>,
+    /// Constant action ids and timesteps (before, after, gap) (u32): after must start at least
+    /// gap timesteps after before ends.
+    min_gaps: Vec<(u32, u32, u32)>,
+    /// Groups of constant action ids of which no two may run at the same time.
+    exclusion_groups: Vec<Vec<u32>>,
+    /// IDs of assets that are kept but left out when solving.
+    disabled: BTreeSet<u32>,
+    /// Actions whose windows keep their datetimes when the context is rebased.
+    absolute_actions: Vec<AbsoluteAction>,
+    /// Limits prognoses are checked against when they are added.
+    plausibility_limits: PlausibilityLimits,
     /// Reference start timestamp for conversions and first timestep fraction.
     start_time: DateTime<Utc>,
+    /// Length of the timesteps the day is divided into.
+    timestep: TimestepConfig,
 }
 
 #[pymethods]
 impl OptimizerContext {
     #[new]
+    #[pyo3(signature = (time, electricity_price, grid_limit=None, peak_price=None, settlement_period=None, plausibility_limits=None, resolution=None))]
     /// Create an OptimizerContext with electricity price prognoses provider.
     /// Time is the reference start DateTime<Utc>. Other prognoses default to 0.
+    /// grid_limit (if given, not negative) caps the power drawn from the grid.
+    /// peak_price (if given, not negative) is charged in Euro per Watt of the highest grid draw.
+    /// settlement_period (if given, a multiple of the timestep of at most a day) is the length
+    /// of the periods the grid draw is averaged over for the peak charge, e.g. 15 minutes.
+    /// Periods are aligned to the clock, so 15 minute periods start at full quarter hours.
+    /// plausibility_limits (default: PlausibilityLimits()) are checked for every prognoses
+    /// added, starting with the electricity price.
+    /// resolution (default: one minute) is the length of a timestep, a whole number of minutes
+    /// that divides the day, e.g. 15 minutes. Providers are called once per timestep.
+    #[allow(clippy::too_many_arguments)]
     fn new(
         py: Python<'_>,
         time: DateTime<Utc>,
         electricity_price: &PrognosesProvider,
+        grid_limit: Option<Watt>,
+        peak_price: Option<f64>,
+        settlement_period: Option<TimeDelta>,
+        plausibility_limits: Option<PlausibilityLimits>,
+        resolution: Option<TimeDelta>,
     ) -> Result<Self, PyErr> {
-        let electricity_price = electricity_price.get_prognoses::<EuroPerWh>(py, time)?;
-        let electricity_price = Prognoses::from_closure(|t: Time| {
-            let price = electricity_price.get(t).expect("Electricity price missing");
-            // convert to i64 in micro Euro per Wh
-            price.to_micro_euro_per_wh() as i64
-        });
-        let generated_electricity = Prognoses::from_closure(|_| 0);
-        let beyond_control_consumption = Prognoses::from_closure(|_| 0);
+        let timestep = resolution
+            .map(timestep_config)
+            .transpose()?
+            .unwrap_or_default();
+        let grid = TimeGrid::new(time, timestep);
+        let settlement_period = settlement_period
+            .map(|period| settlement_period_to_steps(period, timestep))
+            .transpose()?;
+        if let Some(peak_price) = peak_price
+            && (peak_price.is_nan() || peak_price < 0.0)
+        {
+            return Err(OptimizerError::new_err(format!(
+                "peak_price must not be negative, got {} Euro/W",
+                peak_price
+            )));
+        }
+        if let Some(grid_limit) = &grid_limit
+            && (grid_limit.value.is_nan() || grid_limit.value < 0.0)
+        {
+            return Err(OptimizerError::new_err(format!(
+                "grid_limit must not be negative, got {} W",
+                grid_limit.value
+            )));
+        }
+        // convert to i64 in micro Euro per Wh
+        let electricity_price =
+            electricity_price.try_get_prognoses(py, grid, |price: EuroPerWh| {
+                price.to_internal("electricity price")
+            })?;
+        let plausibility_limits = plausibility_limits.unwrap_or_default();
+        plausibility_limits.check_price(py, &electricity_price, grid, "Electricity price")?;
+        let generated_electricity = Prognoses::from_closure_for(timestep, |_| 0);
+        let beyond_control_consumption = Prognoses::from_closure_for(timestep, |_| 0);
         let batteries = vec![];
         let constant_actions = vec![];
         let variable_actions = vec![];
@@ -485,168 +1769,8580 @@ impl OptimizerContext {
             batteries,
             constant_actions,
             variable_actions,
+            feed_in_tariff: None,
+            max_export: None,
+            network_fee: None,
+            co2_intensity: None,
+            net_metering: None,
+            grid_limit: grid_limit
+                .map(|w| w.to_internal_per_step(grid.step(), "grid_limit"))
+                .transpose()?,
+            peak_price: peak_price
+                .map(|price| peak_price_to_internal(price, timestep))
+                .transpose()?,
+            settlement_period,
+            fixed_battery_plans: vec![],
+            precedences: vec![],
+            min_gaps: vec![],
+            exclusion_groups: vec![],
+            disabled: BTreeSet::new(),
+            absolute_actions: vec![],
+            plausibility_limits,
             start_time,
+            timestep,
         })
     }
 
-    /// Add a constant action. Validates duration and timestep alignment.
+    /// Add a constant or profiled constant action. Validates duration and timestep alignment.
     fn add_constant_action<'py>(
         &mut self,
         py: Python<'py>,
-        action: &ConstantAction,
+        action: AnyConstantAction<'py>,
     ) -> PyResult<()> {
-        self.constant_actions
-            .push(Rc::new(action.to_rust(py, self.start_time)?));
+        let (rust_action, absolute) = match &action {
+            AnyConstantAction::Flat(action) => (
+                action.to_rust(py, self.grid())?,
+                action
+                    .absolute
+                    .then(|| AbsoluteAction::Constant(action.clone_ref(py))),
+            ),
+            AnyConstantAction::Profiled(action) => (
+                action.to_rust(self.grid())?,
+                action
+                    .absolute
+                    .then(|| AbsoluteAction::Profiled((**action).clone())),
+            ),
+        };
+        self.push_constant_action(rust_action)?;
+        self.absolute_actions.extend(absolute);
         Ok(())
     }
 
+    /// Add the blocks of a recurring constant action, see RecurringConstantAction. Errors if
+    /// the id of a block is already used or if the window leaves no room for all blocks with
+    /// the gaps between them.
+    fn add_recurring_constant_action<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: &RecurringConstantAction,
+    ) -> PyResult<()> {
+        let gap = action.min_gap_steps(self.timestep)?;
+        let blocks = action
+            .blocks()?
+            .iter()
+            .map(|block| block.to_rust(py, self.grid()))
+            .collect::<PyResult<Vec<_>>>()?;
+        let (added_actions, added_gaps) = (self.constant_actions.len(), self.min_gaps.len());
+        let result = blocks
+            .into_iter()
+            .try_for_each(|block| self.push_constant_action(block))
+            .and_then(|()| {
+                let ids = action.block_ids()?;
+                self.min_gaps
+                    .extend(ids.windows(2).map(|pair| (pair[0], pair[1], gap)));
+                // Validate right away instead of when solving
+                self.to_rust().map(|_| ())
+            });
+        if result.is_err() {
+            self.constant_actions.truncate(added_actions);
+            self.min_gaps.truncate(added_gaps);
+        }
+        result
+    }
+
     /// Add a variable action. Validates timestep alignment.
     fn add_variable_action<'py>(
         &mut self,
-        _py: Python<'py>,
+        py: Python<'py>,
         action: &VariableAction,
     ) -> PyResult<()> {
-        self.variable_actions
-            .push(Rc::new(action.to_rust(self.start_time)?));
+        let rust_action = action.to_rust(py, self.grid())?;
+        self.check_id_unused(rust_action.get_id(), "variable action")?;
+        self.variable_actions.push(Rc::new(rust_action));
+        if action.absolute {
+            self.absolute_actions
+                .push(AbsoluteAction::Variable(action.clone_ref(py)));
+        }
         Ok(())
     }
 
     /// Add a battery.
     fn add_battery(&mut self, battery: &Battery) -> PyResult<()> {
-        self.batteries.push(Rc::new(battery.to_rust()));
+        let battery = battery.to_rust(self.timestep)?;
+        self.check_id_unused(battery.get_id(), "battery")?;
+        self.batteries.push(Rc::new(battery));
+        Ok(())
+    }
+
+    /// Fix the dispatch of an added battery to a plan, e.g. the one published by its own
+    /// controller, and only optimize the other assets around it.
+    /// plan lists (time, power) pairs in ascending order. Each power holds from its time until
+    /// the next pair, positive values charge and negative values discharge; before the first
+    /// pair the battery is idle. The discharge counts as generated electricity and the charge,
+    /// including losses, as beyond control consumption. Schedules still report the battery.
+    /// Errors if the plan is physically impossible for the battery. Replaces an earlier plan.
+    fn set_fixed_battery_plan(
+        &mut self,
+        battery_id: u32,
+        plan: Vec<(DateTime<Utc>, Watt)>,
+    ) -> PyResult<()> {
+        if let Some(pair) = plan.windows(2).find(|pair| pair[1].0 < pair[0].0) {
+            return Err(OptimizerError::new_err(format!(
+                "Battery plan must be in ascending order, but {} comes after {}",
+                pair[1].0, pair[0].0
+            )));
+        }
+        let grid = self.grid();
+        let charge =
+            Prognoses::from_closure_result_for(self.timestep, |t: Time| -> PyResult<i64> {
+                let step_start = grid.to_datetime(t)?;
+                let step_end = grid.to_datetime(t.get_next_timestep())?;
+                let Some((_, power)) = plan.iter().rev().find(|(time, _)| *time <= step_start)
+                else {
+                    return Ok(0);
+                };
+                if !power.value.is_finite() {
+                    return Err(OptimizerError::new_err(format!(
+                        "Battery plan power must be finite, got {} W",
+                        power.value
+                    )));
+                }
+                let step = step_end - step_start;
+                checked_internal(
+                    power.to_energy_per_step(step).round(),
+                    MAX_ENERGY,
+                    &format!("Battery {} plan power", battery_id),
+                    |milli_wh| format!("{} W", Watt::from_energy_per_step(milli_wh, step).value),
+                )
+            })?;
+        let previous = self
+            .fixed_battery_plans
+            .iter()
+            .position(|(id, _)| *id == battery_id)
+            .map(|index| self.fixed_battery_plans.remove(index));
+        self.fixed_battery_plans.push((battery_id, charge));
+        // Validate right away instead of when solving
+        if let Err(err) = self.to_rust() {
+            self.fixed_battery_plans.pop();
+            self.fixed_battery_plans.extend(previous);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Require the constant action before_id to end before the constant action after_id
+    /// starts, e.g. the washing machine before the dryer. Both actions must have been added.
+    /// Errors if an id is unknown, if the precedences form a cycle or if the windows of the
+    /// actions leave no room for the order.
+    fn add_precedence(&mut self, before_id: u32, after_id: u32) -> PyResult<()> {
+        self.precedences.push((before_id, after_id));
+        // Validate right away instead of when solving
+        if let Err(err) = self.to_rust() {
+            self.precedences.pop();
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Forbid any two of the constant actions with the given ids to run at the same time, e.g.
+    /// a sauna and an EV charger on the same breaker. All actions must have been added.
+    /// Errors if an id is unknown or if the windows of the actions leave no room to run them
+    /// one after another.
+    fn add_exclusion_group(&mut self, ids: Vec<u32>) -> PyResult<()> {
+        self.exclusion_groups.push(ids);
+        // Validate right away instead of when solving
+        if let Err(err) = self.to_rust() {
+            self.exclusion_groups.pop();
+            return Err(err);
+        }
         Ok(())
     }
 
-    /// Add a constant action that already started before the context start_time.
-    /// Its remaining consumption is added to beyond_control_consumption until its end.
+    /// Enable or disable all assets with asset_id, e.g. for a checkbox per appliance.
+    /// Disabled assets stay in the context but are left out when solving, as if they had never
+    /// been added: they don't appear in schedules and neither do their fixed battery plans,
+    /// precedences or places in exclusion groups. Assets are enabled when added. Errors if no asset has this id.
+    fn set_enabled(&mut self, asset_id: u32, enabled: bool) -> PyResult<()> {
+        if self.asset_kind(asset_id).is_none() {
+            return Err(OptimizerError::new_err(format!(
+                "No asset with id {}",
+                asset_id
+            )));
+        }
+        let changed = if enabled {
+            self.disabled.remove(&asset_id)
+        } else {
+            self.disabled.insert(asset_id)
+        };
+        // Re-enabling brings back precedences and exclusions, which have to fit the other
+        // actions
+        if let Err(err) = self.to_rust() {
+            if changed && enabled {
+                self.disabled.insert(asset_id);
+            } else if changed {
+                self.disabled.remove(&asset_id);
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Whether the assets with asset_id are enabled, see set_enabled.
+    fn is_enabled(&self, asset_id: u32) -> bool {
+        !self.disabled.contains(&asset_id)
+    }
+
+    /// IDs of all disabled assets in ascending order.
+    fn list_disabled(&self) -> Vec<u32> {
+        self.disabled.iter().copied().collect()
+    }
+
+    #[pyo3(signature = (new_start, price_provider, generation_provider=None, consumption_provider=None))]
+    /// Copy the context to another start, e.g. the next day of a backtest, keeping the assets
+    /// and taking the prognoses that change from day to day from the providers.
+    /// new_start must be a whole number of timesteps away from the start. The windows of
+    /// actions move along with the start, except those of actions created with absolute, which
+    /// are resolved again from their datetimes and must fit into the new horizon. The
+    /// electricity price comes from price_provider, the generated electricity and the beyond
+    /// control consumption from the other providers, 0 without them. Everything else, e.g. the
+    /// network fee, fixed battery plans and the profiles of actions, keeps its values relative
+    /// to the start.
+    fn rebase<'py>(
+        &self,
+        py: Python<'py>,
+        new_start: DateTime<Utc>,
+        price_provider: &PrognosesProvider,
+        generation_provider: Option<&PrognosesProvider>,
+        consumption_provider: Option<&PrognosesProvider>,
+    ) -> PyResult<OptimizerContext> {
+        let grid = self.grid();
+        let offset = |start: DateTime<Utc>| grid.floor(start).map(|floor| start - floor);
+        if offset(new_start)? != offset(self.start_time)? {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "Can't rebase the context starting at {} to {}, the starts must be a whole number of {} minute timesteps apart",
+                self.start_time,
+                new_start,
+                self.timestep.get_minutes_per_timestep()
+            )));
+        }
+        let new_grid = TimeGrid::new(new_start, self.timestep);
+        let electricity_price =
+            price_provider.try_get_prognoses(py, new_grid, |price: EuroPerWh| {
+                price.to_internal("electricity price")
+            })?;
+        self.plausibility_limits.check_price(
+            py,
+            &electricity_price,
+            new_grid,
+            "Electricity price",
+        )?;
+        let mut context = OptimizerContext {
+            electricity_price,
+            generated_electricity: Prognoses::from_closure_for(self.timestep, |_| 0),
+            beyond_control_consumption: Prognoses::from_closure_for(self.timestep, |_| 0),
+            batteries: self.batteries.clone(),
+            constant_actions: self.constant_actions.clone(),
+            variable_actions: self.variable_actions.clone(),
+            feed_in_tariff: self.feed_in_tariff.clone(),
+            max_export: self.max_export,
+            network_fee: self.network_fee.clone(),
+            co2_intensity: self.co2_intensity.clone(),
+            net_metering: self.net_metering,
+            grid_limit: self.grid_limit,
+            peak_price: self.peak_price,
+            settlement_period: self.settlement_period,
+            fixed_battery_plans: self.fixed_battery_plans.clone(),
+            precedences: self.precedences.clone(),
+            min_gaps: self.min_gaps.clone(),
+            exclusion_groups: self.exclusion_groups.clone(),
+            disabled: self.disabled.clone(),
+            absolute_actions: Vec::new(),
+            plausibility_limits: self.plausibility_limits.clone(),
+            start_time: new_start,
+            timestep: self.timestep,
+        };
+        if let Some(provider) = generation_provider {
+            context.add_generated_electricity_prognoses(py, provider)?;
+        }
+        if let Some(provider) = consumption_provider {
+            context.add_beyond_control_consumption_prognoses(py, provider)?;
+        }
+        for action in &self.absolute_actions {
+            let action = match action {
+                AbsoluteAction::Constant(action) => {
+                    context.replace_constant_action(action.to_rust(py, new_grid)?)?;
+                    AbsoluteAction::Constant(action.clone_ref(py))
+                }
+                AbsoluteAction::Profiled(action) => {
+                    context.replace_constant_action(action.to_rust(new_grid)?)?;
+                    AbsoluteAction::Profiled(action.clone())
+                }
+                AbsoluteAction::Variable(action) => {
+                    let rust_action = action.to_rust(py, new_grid)?;
+                    let index = context
+                        .variable_actions
+                        .iter()
+                        .position(|added| added.get_id() == rust_action.get_id())
+                        .expect("Absolute actions are added to the context");
+                    context.variable_actions[index] = Rc::new(rust_action);
+                    AbsoluteAction::Variable(action.clone_ref(py))
+                }
+            };
+            context.absolute_actions.push(action);
+        }
+        // Validate right away instead of when solving
+        context.to_rust()?;
+        Ok(context)
+    }
+
+    /// Add a constant action that already started before the context start_time, e.g. one
+    /// assigned by the schedule of an earlier context. Its remaining consumption is added to
+    /// beyond_control_consumption until its end, which is taken as a datetime from the schedule
+    /// it was assigned in. Errors if the end isn't on a timestep boundary of this context.
     fn add_past_constant_action<'py>(
         &mut self,
         _py: Python<'py>,
         action: &AssignedConstantAction,
     ) -> PyResult<()> {
-        // find out how much time has passed since action start
-        let end_time = action.get_end_time()?;
-        let end_time = datetime_to_time(end_time, self.start_time)?;
-        self.beyond_control_consumption += Prognoses::from_closure(|t: Time| {
-            if t >= end_time {
-                0
-            } else {
-                action.inner.get_action().get_consumption()
-            }
+        let action_end = action.get_end_time()?;
+        if action_end <= self.start_time {
+            return Ok(());
+        }
+        let grid = self.grid();
+        if !grid.is_aligned(action_end) {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "Constant action {} ends at {}, between two timesteps of the context starting at {}",
+                action.inner.get_id(),
+                action_end,
+                self.start_time
+            )));
+        }
+        // The last timesteps of the profile fall into this context, up to its end
+        let remaining = grid.to_time(action_end)?.to_timestep() as i64;
+        let profile = action.inner.get_profile();
+        let skipped = profile.len() as i64 - remaining;
+        self.beyond_control_consumption += Prognoses::from_closure_for(self.timestep, |t: Time| {
+            usize::try_from(skipped + t.to_timestep() as i64)
+                .ok()
+                .and_then(|offset| profile.get(offset))
+                .copied()
+                .unwrap_or(0)
         });
         Ok(())
     }
 
+    /// Add a variable action that already started before the context start_time, e.g. one
+    /// assigned by the schedule of an earlier context, of which energy_already_delivered was
+    /// consumed so far. The rest is scheduled as a variable action with the same ID from the
+    /// context start until the original end, keeping its max and min consumption; the
+    /// preference isn't carried over. Nothing is added if nothing is left. Errors if the
+    /// delivered energy is negative, and with InfeasibleError if the rest can't be consumed
+    /// until the end.
+    fn add_past_variable_action<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: &AssignedVariableAction,
+        energy_already_delivered: WattHour,
+    ) -> PyResult<()> {
+        let id = action.inner.get_id();
+        let delivered = &energy_already_delivered;
+        if delivered.value.is_nan() || delivered.value < 0.0 {
+            return Err(OptimizerError::new_err(format!(
+                "Delivered energy of variable action {} must not be negative, got {} Wh",
+                id, delivered.value
+            )));
+        }
+        let past_grid = action.grid;
+        let total = WattHour::from_milli_wh(action.inner.get_total_consumption() as f64);
+        let remaining = WattHour {
+            value: total.value - delivered.value,
+        };
+        if remaining.value <= 0.0 {
+            return Ok(());
+        }
+        let end = past_grid.to_datetime(action.inner.get_end())?;
+        if end <= self.start_time {
+            return Err(InfeasibleError::new_err(format!(
+                "Variable action {} ended at {}, before the context starts at {}, with {:.2} Wh left",
+                id, end, self.start_time, remaining.value
+            )));
+        }
+        let power = |energy: i64| Watt::from_energy_per_step(energy as f64, past_grid.step());
+        let max_consumption = if action.inner.get_max_consumption_profile().is_some() {
+            // The caps of the timesteps of this context, read at their start in the old one
+            let grid = self.grid();
+            let caps = (grid.to_time(self.start_time)?..grid.to_time(end)?)
+                .iter_steps()
+                .map(|time| {
+                    let past = past_grid.to_time(past_grid.floor(grid.to_datetime(time)?)?)?;
+                    Ok(power(action.inner.get_max_consumption_at(past)))
+                })
+                .collect::<PyResult<_>>()?;
+            MaxConsumption::PerTimestep(caps)
+        } else {
+            MaxConsumption::Constant(power(action.inner.get_max_consumption()))
+        };
+        let rest = VariableAction {
+            start: None,
+            end,
+            total_consumption: remaining,
+            max_consumption,
+            min_consumption: power(action.inner.get_min_consumption()),
+            preference: None,
+            preference_limit: 0.1,
+            contiguous: action.inner.is_contiguous(),
+            absolute: false,
+            id,
+        };
+        self.add_variable_action(py, &rest)
+    }
+
+    /// Keep the rest periods of constant actions across rolling re-optimizations.
+    /// min_off_times maps constant action IDs to the time the appliance has to rest after a run.
+    /// If `previous` ran or planned the action with that ID, the action of this context with the
+    /// same ID starts no earlier than that long after the end of the run. Call it after adding
+    /// the actions. Raises ValueError if an ID has no constant action in this context, for
+    /// negative off times, and if the rest period leaves too little of a window for the action.
+    fn respect_min_off_times(
+        &mut self,
+        previous: &Schedule,
+        min_off_times: BTreeMap<u32, TimeDelta>,
+    ) -> PyResult<()> {
+        let grid = self.grid();
+        for (id, min_off_time) in min_off_times {
+            if min_off_time < TimeDelta::zero() {
+                return Err(OptimizerError::new_err(format!(
+                    "min_off_time of constant action {} must not be negative, got {}",
+                    id, min_off_time
+                )));
+            }
+            let Some(index) = self
+                .constant_actions
+                .iter()
+                .position(|action| action.get_id() == id)
+            else {
+                return Err(OptimizerError::new_err(format!(
+                    "No constant action with id {} to keep the min_off_time of",
+                    id
+                )));
+            };
+            let Some(run) = previous.get_constant_action(id) else {
+                continue;
+            };
+            let run_end = run.get_end_time()?;
+            let rest_end = run_end + min_off_time;
+            if rest_end <= self.start_time {
+                continue;
+            }
+            let action = &self.constant_actions[index];
+            let earliest = grid.ceil(rest_end)?;
+            let end_before = grid.to_datetime(action.get_end_before())?;
+            let duration = grid.step() * action.get_duration().to_timestep() as i32;
+            if earliest + duration > end_before {
+                return Err(InfeasibleError::new_err(format!(
+                    "Constant action {} has to rest until {} after its previous run ended at {}, \
+                     its duration of {} minutes no longer fits before end_before {}",
+                    id,
+                    rest_end,
+                    run_end,
+                    duration.num_minutes(),
+                    end_before
+                )));
+            }
+            let start_from = grid.to_time(earliest)?;
+            if start_from > action.get_start_from() {
+                let mut rested = RustConstantAction::clone(action);
+                rested.start_from = start_from;
+                rested
+                    .validate_for(self.timestep)
+                    .map_err(|e| action_error("Constant", e, grid))?;
+                self.constant_actions[index] = Rc::new(rested);
+            }
+        }
+        Ok(())
+    }
+
     /// Add generated electricity prognoses via a provider. Values are summed with existing prognoses.
+    /// The provider is evaluated once per timestep here and never again, so any number of
+    /// providers cost the optimizer no more than one.
     fn add_generated_electricity_prognoses<'py>(
         &mut self,
         py: Python<'py>,
         provider: &PrognosesProvider,
     ) -> PyResult<()> {
-        let prognoses = provider.get_prognoses::<WattHour>(py, self.start_time)?;
-        self.generated_electricity += Prognoses::from_closure(|t| -> i64 {
-            prognoses.get(t).expect("internal error").to_milli_wh() as i64
-        });
+        let generated = provider.try_get_prognoses(py, self.grid(), |energy: WattHour| {
+            energy.to_internal("generated electricity")
+        })?;
+        self.plausibility_limits
+            .check_generation(py, &generated, self.grid())?;
+        self.generated_electricity += generated;
+        Ok(())
+    }
+
+    /// Add beyond control consumption prognoses via a provider, e.g. the household base load.
+    /// Values are summed with existing prognoses and must not be negative. Sustained negative
+    /// stretches while electricity is generated are reported as generation counted twice.
+    fn add_beyond_control_consumption_prognoses<'py>(
+        &mut self,
+        py: Python<'py>,
+        provider: &PrognosesProvider,
+    ) -> PyResult<()> {
+        let grid = self.grid();
+        // Negative values during generation are rejected below, naming the double counting
+        let has_generation = self.generated_electricity.get_data().iter().any(|&g| g > 0);
+        let consumption = provider.try_get_prognoses(py, grid, |energy: WattHour| {
+            if energy.value.is_nan() || (energy.value < 0.0 && !has_generation) {
+                return Err(OptimizerError::new_err(format!(
+                    "consumption must not be negative, got {} Wh",
+                    energy.value
+                )));
+            }
+            energy.to_internal("consumption")
+        })?;
+        if let Some(message) = double_counting(
+            &consumption,
+            &self.generated_electricity,
+            grid,
+            (
+                "add_generated_electricity_prognoses",
+                "add_beyond_control_consumption_prognoses",
+            ),
+        )? {
+            return Err(PrognosesError::new_err(message));
+        }
+        if let Some(t) = consumption.get_data().iter().position(|&energy| energy < 0) {
+            let time = Time::from_timestep(t as u32);
+            return Err(PrognosesError::new_err(format!(
+                "Beyond control consumption must not be negative, got {} Wh for interval [{}, {})",
+                WattHour::from_milli_wh(consumption.get_data()[t] as f64).value,
+                grid.to_datetime(time)?,
+                grid.to_datetime(time.get_next_timestep())?
+            )));
+        }
+        self.plausibility_limits
+            .check_base_load(py, &consumption, grid)?;
+        self.beyond_control_consumption += consumption;
+        Ok(())
+    }
+
+    #[pyo3(signature = (provider, max_export=None))]
+    /// Allow exporting electricity to the grid, paid with the feed-in tariff from the provider.
+    /// Generated electricity and battery discharge are exported, so batteries that may charge
+    /// from the grid can sell what they bought. The export per timestep is limited by max_export
+    /// (if given, not negative).
+    fn set_feed_in_tariff<'py>(
+        &mut self,
+        py: Python<'py>,
+        provider: &PrognosesProvider,
+        max_export: Option<Watt>,
+    ) -> PyResult<()> {
+        if let Some(max_export) = &max_export
+            && (max_export.value.is_nan() || max_export.value < 0.0)
+        {
+            return Err(OptimizerError::new_err(format!(
+                "max_export must not be negative, got {} W",
+                max_export.value
+            )));
+        }
+        let max_export = max_export
+            .map(|w| w.to_internal_per_step(self.grid().step(), "max_export"))
+            .transpose()?;
+        let tariff = provider.try_get_prognoses(py, self.grid(), |tariff: EuroPerWh| {
+            tariff.to_internal("feed-in tariff")
+        })?;
+        self.plausibility_limits
+            .check_price(py, &tariff, self.grid(), "Feed-in tariff")?;
+        self.feed_in_tariff = Some(tariff);
+        self.max_export = max_export;
+        Ok(())
+    }
+
+    /// Charge the network fee from the provider on top of the electricity price for all
+    /// electricity drawn from the grid. Generated electricity, also when stored in a battery,
+    /// doesn't pay it.
+    fn set_network_fee<'py>(
+        &mut self,
+        py: Python<'py>,
+        provider: &PrognosesProvider,
+    ) -> PyResult<()> {
+        let fee = provider.try_get_prognoses(py, self.grid(), |fee: EuroPerWh| {
+            fee.to_internal("network fee")
+        })?;
+        self.plausibility_limits
+            .check_price(py, &fee, self.grid(), "Network fee")?;
+        self.network_fee = Some(fee);
+        Ok(())
+    }
+
+    #[pyo3(signature = (provider, weight=0.0))]
+    /// Track the CO2 emitted by electricity drawn from the grid, with the intensity from the
+    /// provider in grams per Wh, see Schedule.get_co2_emissions. A weight in euro per gram
+    /// (not negative) makes the optimizer pay it for every gram on top of the electricity
+    /// price; with the default weight of zero the emissions are only reported.
+    fn set_co2_intensity<'py>(
+        &mut self,
+        py: Python<'py>,
+        provider: &PrognosesProvider,
+        weight: f64,
+    ) -> PyResult<()> {
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(OptimizerError::new_err(format!(
+                "weight must be a finite number that isn't negative, got {weight}"
+            )));
+        }
+        let intensity = provider.try_get_prognoses(py, self.grid(), |grams: f64| {
+            if grams.is_nan() || grams < 0.0 {
+                return Err(OptimizerError::new_err(format!(
+                    "CO2 intensity must not be negative, got {grams} g/Wh"
+                )));
+            }
+            checked_internal((grams * 1e3).round(), MAX_PRICE, "CO2 intensity", |mg| {
+                format!("{} g/Wh", mg / 1e3)
+            })
+        })?;
+        // Euro per gram is micro-euro per milligram times 1000
+        let co2_price = weight * 1e3;
+        let max_intensity = intensity.get_data().iter().copied().max().unwrap_or(0);
+        checked_internal(
+            (max_intensity as f64 * co2_price).round(),
+            MAX_PRICE,
+            "CO2 cost",
+            |price| format!("{} €/Wh", EuroPerWh::from_micro_euro_per_wh(price).value),
+        )?;
+        self.co2_intensity = Some((intensity, co2_price));
         Ok(())
     }
+
+    #[pyo3(signature = (rate, price_weight=1.0))]
+    /// Bill the schedule with net metering: the energy exported is subtracted from the energy
+    /// drawn from the grid and the difference is paid at the flat rate, see
+    /// OptimizeResult.bill. The optimizer still minimizes the cost at the electricity price,
+    /// weighted by price_weight (not negative); without a feed-in tariff, export is credited at
+    /// the same weighted price. A rate of None turns net metering off.
+    fn set_net_metering(&mut self, rate: Option<EuroPerWh>, price_weight: f64) -> PyResult<()> {
+        if !price_weight.is_finite() || price_weight < 0.0 {
+            return Err(OptimizerError::new_err(format!(
+                "price_weight must be a finite number that isn't negative, got {price_weight}"
+            )));
+        }
+        self.net_metering = rate
+            .map(|rate| rate.to_internal("net metering rate"))
+            .transpose()?
+            .map(|rate| (rate, price_weight));
+        Ok(())
+    }
+
+    /// Set the limits prognoses added from now on are checked against. Prognoses that are
+    /// already added aren't checked again.
+    fn set_plausibility_limits(&mut self, limits: PlausibilityLimits) {
+        self.plausibility_limits = limits;
+    }
+
+    #[staticmethod]
+    /// Build a context from a declarative spec, e.g. loaded from a YAML config. Prognoses are
+    /// referenced by name and looked up in `providers`. Errors name the offending path in the spec.
+    fn from_spec<'py>(
+        py: Python<'py>,
+        spec: &Bound<'py, PyDict>,
+        providers: &Bound<'py, PyDict>,
+    ) -> PyResult<Self> {
+        spec::context_from_spec(py, spec, providers)
+    }
+
+    /// Describe this context as a spec for `from_spec`. Prognoses are referenced by their key
+    /// names ("electricity_price", ...), so the providers have to be passed again when loading.
+    fn to_spec<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        spec::context_to_spec(py, self)
+    }
+
+    /// Total energy all consumers need over the day: variable action totals, constant action
+    /// profiles and beyond control consumption. Constant actions count in full, as they aren't placed yet.
+    fn total_required_energy(&self) -> PyResult<WattHour> {
+        let context = self.to_rust()?;
+        let constant: i64 = context
+            .get_constant_actions()
+            .iter()
+            .map(|action| action.get_profile().iter().sum::<i64>())
+            .sum();
+        let total = required_consumption(&context, []) + constant;
+        Ok(WattHour::from_milli_wh(total as f64))
+    }
+
+    #[pyo3(signature = (cooling="geometric", **cooling_options))]
+    /// Estimate the size of the problem without solving it: the flow graph, the constant
+    /// actions the annealer moves, the planned iterations of the cooling schedule and the peak memory.
+    fn estimate_complexity<'py>(
+        &self,
+        py: Python<'py>,
+        cooling: &str,
+        cooling_options: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let config = annealing_config(cooling, cooling_options)?;
+        let complexity = estimate_complexity(&self.to_rust()?, &config);
+        let dict = PyDict::new(py);
+        dict.set_item("nodes", complexity.flow_size.nodes)?;
+        dict.set_item("edges", complexity.flow_size.edges)?;
+        dict.set_item("decision_variables", complexity.decision_variables)?;
+        dict.set_item("iterations", complexity.iterations)?;
+        dict.set_item("peak_memory_bytes", complexity.peak_memory_bytes)?;
+        dict.set_item("runtime_class", complexity.runtime_class().name())?;
+        Ok(dict)
+    }
+
+    #[pyo3(signature = (path=None))]
+    /// Serialize the fully materialized problem into a JSON document for bug reports.
+    /// Also writes it to `path` if given. Load it again with `load_problem`.
+    fn dump_problem(&self, path: Option<&str>) -> PyResult<String> {
+        let json = serde_json::to_string_pretty(&ProblemDump::from_context(self))
+            .map_err(|e| OptimizerError::new_err(format!("Failed to serialize problem: {}", e)))?;
+        if let Some(path) = path {
+            std::fs::write(path, &json).map_err(|e| {
+                PyIOError::new_err(format!("Failed to write problem to {}: {}", path, e))
+            })?;
+        }
+        Ok(json)
+    }
 }
 impl OptimizerContext {
+    /// The timesteps of the context, starting at its start time.
+    fn grid(&self) -> TimeGrid {
+        TimeGrid::new(self.start_time, self.timestep)
+    }
+
+    /// Kind of the asset with `id`, e.g. "battery", or None if no asset has it.
+    fn asset_kind(&self, id: u32) -> Option<&'static str> {
+        if self.batteries.iter().any(|b| b.get_id() == id) {
+            Some("battery")
+        } else if self.constant_actions.iter().any(|a| a.get_id() == id) {
+            Some("constant action")
+        } else if self.variable_actions.iter().any(|a| a.get_id() == id) {
+            Some("variable action")
+        } else {
+            None
+        }
+    }
+
+    /// Errors if an asset already has the id of the `kind` being added. Schedules key all
+    /// assets by id, so ids are unique across batteries and actions.
+    fn check_id_unused(&self, id: u32, kind: &str) -> PyResult<()> {
+        match self.asset_kind(id) {
+            Some(used_by) => Err(OptimizerError::new_err(format!(
+                "Can't add {} {}, the id {} is already used by a {}",
+                kind, id, id, used_by
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Add a converted constant action, checking that its id is unused and that it ends
+    /// before the end of the horizon.
+    fn push_constant_action(&mut self, action: RustConstantAction) -> PyResult<()> {
+        action
+            .validate_for(self.timestep)
+            .map_err(|e| action_error("Constant", e, self.grid()))?;
+        self.check_id_unused(action.get_id(), "constant action")?;
+        self.constant_actions.push(Rc::new(action));
+        Ok(())
+    }
+
+    /// Replace the constant action with the id of `action` by it, checking that it ends before
+    /// the end of the horizon.
+    fn replace_constant_action(&mut self, action: RustConstantAction) -> PyResult<()> {
+        action
+            .validate_for(self.timestep)
+            .map_err(|e| action_error("Constant", e, self.grid()))?;
+        let index = self
+            .constant_actions
+            .iter()
+            .position(|added| added.get_id() == action.get_id())
+            .expect("Absolute actions are added to the context");
+        self.constant_actions[index] = Rc::new(action);
+        Ok(())
+    }
+
     /// Convert to RustOptimizerContext. Computes first_timestep_fraction from start_time alignment.
     fn to_rust(&self) -> PyResult<RustOptimizerContext> {
-        // first_timestep fraction is the length of the first timestep that is remaining divided by full timestep length
-        let first_timestep_fraction = {
-            let start_time = self.start_time;
-            let next_timestep = time_to_datetime(Time::from_timestep(1), start_time)?;
-            let remaining_duration = next_timestep.signed_duration_since(start_time);
-            // calculate as precise as possible
-            let remaining_nanos = remaining_duration.num_nanoseconds().unwrap() as f64;
-            let full_timestep_nanos = (MINUTES_PER_TIMESTEP as i64 * 60 * 1_000_000_000) as f64;
-            remaining_nanos / full_timestep_nanos
-        };
-        Ok(RustOptimizerContext::new(
-            self.electricity_price.clone(),
+        self.to_rust_without(&BTreeSet::new())
+    }
+
+    /// Convert to RustOptimizerContext like `to_rust`, leaving out the failing assets instead
+    /// of raising. The assets are added one after another in the order of the context; one
+    /// whose addition fails is left out together with its precedences, exclusion groups and
+    /// fixed plan, and its error is returned by id. Errors that no asset causes are raised.
+    fn to_rust_isolating_errors(&self) -> PyResult<(RustOptimizerContext, BTreeMap<u32, PyErr>)> {
+        if let Ok(context) = self.to_rust() {
+            return Ok((context, BTreeMap::new()));
+        }
+        let ids: Vec<u32> = self
+            .batteries
+            .iter()
+            .map(|battery| battery.get_id())
+            .chain(self.constant_actions.iter().map(|action| action.get_id()))
+            .chain(self.variable_actions.iter().map(|action| action.get_id()))
+            .filter(|id| !self.disabled.contains(id))
+            .collect();
+        let mut excluded: BTreeSet<u32> = ids.iter().copied().collect();
+        self.to_rust_without(&excluded)?;
+        let mut errors = BTreeMap::new();
+        for id in ids {
+            excluded.remove(&id);
+            if let Err(err) = self.to_rust_without(&excluded) {
+                excluded.insert(id);
+                errors.insert(id, err);
+            }
+        }
+        Ok((self.to_rust_without(&excluded)?, errors))
+    }
+
+    /// Convert to RustOptimizerContext, leaving out the assets with the `excluded` ids like
+    /// disabled ones.
+    fn to_rust_without(&self, excluded: &BTreeSet<u32>) -> PyResult<RustOptimizerContext> {
+        let enabled = |id: u32| !self.disabled.contains(&id) && !excluded.contains(&id);
+        let grid = self.grid();
+        for action in self.constant_actions.iter().filter(|a| enabled(a.get_id())) {
+            action
+                .validate_for(self.timestep)
+                .map_err(|e| action_error("Constant", e, grid))?;
+        }
+        for action in self.variable_actions.iter().filter(|a| enabled(a.get_id())) {
+            action
+                .validate_for(self.timestep)
+                .map_err(|e| action_error("Variable", e, grid))?;
+        }
+        let electricity_price = match self.net_metering {
+            Some((_, price_weight)) => Prognoses::from_closure_for(self.timestep, |time| {
+                (*self.electricity_price.get(time).unwrap() as f64 * price_weight).round() as i64
+            }),
+            None => self.electricity_price.clone(),
+        };
+        let feed_in_tariff = match (&self.feed_in_tariff, self.net_metering) {
+            (None, Some(_)) => Some(electricity_price.clone()),
+            (tariff, _) => tariff.clone(),
+        };
+        let context = RustOptimizerContext::new(
+            electricity_price,
             self.generated_electricity.clone(),
             self.beyond_control_consumption.clone(),
-            self.batteries.clone(),
-            self.constant_actions.clone(),
-            self.variable_actions.clone(),
-            first_timestep_fraction as f32,
-        ))
+            self.batteries
+                .iter()
+                .filter(|battery| enabled(battery.get_id()))
+                .cloned()
+                .collect(),
+            self.constant_actions
+                .iter()
+                .filter(|action| enabled(action.get_id()))
+                .cloned()
+                .collect(),
+            self.variable_actions
+                .iter()
+                .filter(|action| enabled(action.get_id()))
+                .cloned()
+                .collect(),
+            first_timestep_fraction(grid)?,
+        )
+        .map_err(|e| OptimizerError::new_err(e.to_string()))?;
+        let context = match feed_in_tariff {
+            Some(tariff) => context.with_feed_in_tariff(tariff, self.max_export),
+            None => context,
+        };
+        let context = match &self.network_fee {
+            Some(fee) => context.with_network_fee(fee.clone()),
+            None => context,
+        };
+        let context = match &self.co2_intensity {
+            Some((intensity, co2_price)) => {
+                context.with_co2_intensity(intensity.clone(), *co2_price)
+            }
+            None => context,
+        };
+        let context = match self.grid_limit {
+            Some(grid_limit) => context.with_grid_limit(grid_limit),
+            None => context,
+        };
+        let context = match self.peak_price {
+            Some(peak_price) => context.with_peak_price(peak_price),
+            None => context,
+        };
+        let context = match self.settlement_period {
+            Some(steps) => {
+                let period = grid.step() * steps as i32;
+                // The first boundary of the clock-aligned periods at or after the start
+                let boundary =
+                    TimeGrid::with_step(self.start_time, period).ceil(self.start_time)?;
+                let offset = grid.to_time(boundary)?;
+                context.with_settlement_periods(SettlementPeriods::new(steps, offset.to_timestep()))
+            }
+            None => context,
+        };
+        let context = self
+            .fixed_battery_plans
+            .iter()
+            .filter(|(id, _)| enabled(*id))
+            .try_fold(context, |context, (id, charge)| {
+                context
+                    .with_fixed_battery_plan(*id, charge.clone())
+                    .map_err(|e| fixed_battery_plan_error(*id, e, grid))
+            })?;
+        let context = self
+            .precedences
+            .iter()
+            .filter(|(before, after)| enabled(*before) && enabled(*after))
+            .try_fold(context, |context, &(before, after)| {
+                context.with_precedence(before, after).map_err(|e| {
+                    InfeasibleError::new_err(format!(
+                        "Precedence of constant action {} before {} can't be kept: {}",
+                        before, after, e
+                    ))
+                })
+            })?;
+        let context = self
+            .min_gaps
+            .iter()
+            .filter(|(before, after, _)| enabled(*before) && enabled(*after))
+            .try_fold(context, |context, &(before, after, gap)| {
+                context
+                    .with_min_gap(before, after, Time::from_timestep(gap))
+                    .map_err(|e| {
+                        InfeasibleError::new_err(format!(
+                            "Gap of {} minutes between constant action {} and {} can't be kept: {}",
+                            gap * self.timestep.get_minutes_per_timestep(),
+                            before,
+                            after,
+                            e
+                        ))
+                    })
+            })?;
+        self.exclusion_groups
+            .iter()
+            .try_fold(context, |context, group| {
+                let ids: Vec<u32> = group.iter().copied().filter(|&id| enabled(id)).collect();
+                context.with_exclusion_group(ids).map_err(|e| {
+                    let message = format!("Exclusion group {:?} can't be kept: {}", group, e);
+                    match e {
+                        ExclusionError::UnknownAction(_) => OptimizerError::new_err(message),
+                        ExclusionError::Infeasible(_) => InfeasibleError::new_err(message),
+                    }
+                })
+            })
     }
 }
 
-#[pyclass(unsendable)]
-/// Final schedule returned by the optimizer. Use accessors to retrieve assigned actions and batteries.
-pub struct Schedule {
-    inner: RustSchedule,
-    start_timestamp: DateTime<Utc>,
+/// Describe why a fixed battery plan can't be followed in user units and times.
+fn fixed_battery_plan_error(id: u32, error: FixedBatteryPlanError, grid: TimeGrid) -> PyErr {
+    let at = |time: Time| {
+        grid.to_datetime(time)
+            .map_or_else(|_| format!("{:?}", time), |t| t.to_string())
+    };
+    let wh = |milli_wh: i64| milli_wh as f64 / 1000.0;
+    let reason = match error {
+        FixedBatteryPlanError::UnknownBattery(_) => "no battery with this id was added".to_string(),
+        FixedBatteryPlanError::RateExceeded { time, charge } => format!(
+            "{:.2} Wh during the timestep at {} exceeds its charge or discharge rate",
+            wh(charge),
+            at(time)
+        ),
+        FixedBatteryPlanError::LevelOutOfRange { time, level } => format!(
+            "charge level {:.2} Wh at {} is outside of its minimum charge and capacity",
+            wh(level),
+            at(time)
+        ),
+        FixedBatteryPlanError::ChargedFromGrid { time, charge } => format!(
+            "charging {:.2} Wh at {} exceeds the generated electricity, but it can't charge from the grid",
+            wh(charge),
+            at(time)
+        ),
+    };
+    InfeasibleError::new_err(format!(
+        "Fixed plan for battery {} is impossible: {}",
+        id, reason
+    ))
 }
-#[pymethods]
-impl Schedule {
-    /// Get an assigned constant action by ID, if present.
-    fn get_constant_action(&self, id: u32) -> Option<AssignedConstantAction> {
-        self.inner
-            .get_constant_action(id)
-            .map(|action| AssignedConstantAction {
-                inner: action.clone(),
-                start_timestamp: self.start_timestamp,
-            })
-    }
-    /// Get an assigned variable action by ID, if present.
-    fn get_variable_action(&self, id: u32) -> Option<AssignedVariableAction> {
-        self.inner
-            .get_variable_action(id)
-            .map(|action| AssignedVariableAction {
-                inner: action.clone(),
-                start_timestamp: self.start_timestamp,
-            })
-    }
-    /// Get an assigned battery by ID, if present.
-    fn get_battery(&self, id: u32) -> Option<AssignedBattery> {
-        self.inner.get_battery(id).map(|battery| AssignedBattery {
-            inner: battery.clone(),
-            start_timestamp: self.start_timestamp,
-        })
+
+/// Number of timesteps of `timestep` in a settlement period. It must be a positive multiple of
+/// the timestep and at most a day long.
+fn settlement_period_to_steps(period: TimeDelta, timestep: TimestepConfig) -> PyResult<u32> {
+    let step = timestep_duration(timestep);
+    if period <= TimeDelta::zero()
+        || period > TimeDelta::days(1)
+        || period.subsec_nanos() != 0
+        || period.num_seconds() % step.num_seconds() != 0
+    {
+        return Err(TimestepAlignmentError::new_err(format!(
+            "settlement_period must be a multiple of {} minutes of at most a day, got {}",
+            timestep.get_minutes_per_timestep(),
+            period
+        )));
     }
+    Ok((period.num_seconds() / step.num_seconds()) as u32)
 }
 
-#[pyfunction]
-/// Run simulated annealing with a given OptimizerContext.
-/// Returns total cost in Euro and the resulting Schedule.
-fn run_simulated_annealing(
-    _py: Python<'_>,
+/// Convert a peak price in Euro per Watt to nano-euro per milli-Wh per timestep of `timestep`.
+/// Raises UnitError if it is more than MAX_PEAK_PRICE.
+fn peak_price_to_internal(euro_per_watt: f64, timestep: TimestepConfig) -> PyResult<i64> {
+    let watt_per_energy = Watt::from_energy_per_step(1.0, timestep_duration(timestep)).value;
+    checked_internal(
+        (euro_per_watt * watt_per_energy * 1e9).round(),
+        MAX_PEAK_PRICE,
+        "peak_price",
+        |peak_price| {
+            format!(
+                "{} Euro/W",
+                peak_price_from_internal(peak_price as i64, timestep)
+            )
+        },
+    )
+}
+
+/// Convert a peak price in nano-euro per milli-Wh per timestep of `timestep` to Euro per Watt.
+fn peak_price_from_internal(peak_price: i64, timestep: TimestepConfig) -> f64 {
+    let watt_per_energy = Watt::from_energy_per_step(1.0, timestep_duration(timestep)).value;
+    peak_price as f64 / 1e9 / watt_per_energy
+}
+
+/// A value returned by a provider when materializing it. Units are stored by their value.
+#[derive(FromPyObject)]
+enum PrognosisValue {
+    Watt(Watt),
+    WattHour(WattHour),
+    Euro(Euro),
+    EuroPerWh(EuroPerWh),
+    Plain(f64),
+}
+
+impl PrognosisValue {
+    fn value(&self) -> f64 {
+        match self {
+            PrognosisValue::Watt(watt) => watt.value,
+            PrognosisValue::WattHour(energy) => energy.value,
+            PrognosisValue::Euro(euro) => euro.value,
+            PrognosisValue::EuroPerWh(price) => price.value,
+            PrognosisValue::Plain(value) => *value,
+        }
+    }
+}
+
+#[pyclass(name = "Prognoses")]
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+/// Prognoses for one day, materialized into one float per timestep, so their length gives the
+/// length of the timesteps. Units are stored by their
+/// value, so a price prognosis holds Euro per Wh. Serializes to JSON and a compact binary form.
+/// Unlike the contexts and schedules, prognoses can be passed between threads.
+struct MaterializedPrognoses {
+    start_time: DateTime<Utc>,
+    values: Prognoses<f64>,
+}
+
+#[pymethods]
+impl MaterializedPrognoses {
+    #[new]
+    /// Create prognoses from one value per timestep, starting at start_time.
+    fn new(start_time: DateTime<Utc>, values: Vec<f64>) -> PyResult<Self> {
+        let values = Prognoses::try_from(values).map_err(PrognosesError::new_err)?;
+        Ok(MaterializedPrognoses { start_time, values })
+    }
+    #[staticmethod]
+    #[pyo3(signature = (provider, start_time, resolution=None))]
+    /// Call the provider for every timestep of the day starting at start_time, one per
+    /// resolution (default: one minute).
+    fn from_provider(
+        py: Python<'_>,
+        provider: &PrognosesProvider,
+        start_time: DateTime<Utc>,
+        resolution: Option<TimeDelta>,
+    ) -> PyResult<Self> {
+        let timestep = resolution
+            .map(timestep_config)
+            .transpose()?
+            .unwrap_or_default();
+        let grid = TimeGrid::new(start_time, timestep);
+        let values = provider.get_prognoses(py, grid, |value: PrognosisValue| value.value())?;
+        Ok(MaterializedPrognoses { start_time, values })
+    }
+    #[getter]
+    /// Start of the first timestep.
+    fn start_time(&self) -> DateTime<Utc> {
+        self.start_time
+    }
+    /// The value of every timestep.
+    fn values(&self) -> Vec<f64> {
+        self.values.get_data().to_vec()
+    }
+    fn __len__(&self) -> usize {
+        self.values.len() as usize
+    }
+    fn __eq__(&self, other: &MaterializedPrognoses) -> bool {
+        self == other
+    }
+    /// Serialize the prognoses to JSON.
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(self).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+    #[staticmethod]
+    /// Rebuild prognoses created by to_json.
+    fn from_json(s: &str) -> PyResult<Self> {
+        let value: serde_json::Value = serde_json::from_str(s)
+            .map_err(|e| PrognosesError::new_err(format!("Invalid prognoses JSON: {}", e)))?;
+        serde_json::from_value(value)
+            .map_err(|e| PrognosesError::new_err(format!("Invalid prognoses JSON: {}", e)))
+    }
+    /// Serialize the prognoses to the compact binary form read by from_bytes.
+    fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        binary::encode(Blob::Prognoses, self)
+    }
+    #[staticmethod]
+    /// Rebuild prognoses created by to_bytes. Raises ValueError for blobs of another format version.
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        binary::decode(Blob::Prognoses, data)
+    }
+}
+
+/// Total cost for a sequence of start times of a constant action.
+type CostCurve = Vec<(DateTime<Utc>, Euro)>;
+
+#[pyclass(unsendable)]
+/// Final schedule returned by the optimizer. Use accessors to retrieve assigned actions and batteries.
+pub struct Schedule {
+    inner: RustSchedule,
+    start_timestamp: DateTime<Utc>,
+}
+#[pymethods]
+impl Schedule {
+    /// Get an assigned constant action by ID, if present.
+    fn get_constant_action(&self, id: u32) -> Option<AssignedConstantAction> {
+        self.inner
+            .get_constant_action(id)
+            .map(|action| AssignedConstantAction {
+                inner: action.clone(),
+                grid: self.grid(),
+            })
+    }
+    /// Get the blocks of a recurring constant action by its ID, ordered by start time. Empty if
+    /// the schedule has none of them.
+    fn get_recurring_action(&self, id: u32) -> Vec<AssignedConstantAction> {
+        let Some(first) = id.checked_mul(RECURRING_BLOCK_IDS) else {
+            return Vec::new();
+        };
+        let block_ids = first..first.saturating_add(RECURRING_BLOCK_IDS);
+        let mut blocks: Vec<_> = self
+            .inner
+            .constant_actions
+            .values()
+            .filter(|action| block_ids.contains(&action.get_id()))
+            .collect();
+        blocks.sort_by_key(|action| (action.get_start_time(), action.get_id()));
+        blocks
+            .into_iter()
+            .map(|action| AssignedConstantAction {
+                inner: action.clone(),
+                grid: self.grid(),
+            })
+            .collect()
+    }
+    /// Get an assigned variable action by ID, if present.
+    fn get_variable_action(&self, id: u32) -> Option<AssignedVariableAction> {
+        self.inner
+            .get_variable_action(id)
+            .map(|action| AssignedVariableAction {
+                inner: action.clone(),
+                grid: self.grid(),
+            })
+    }
+    /// Get an assigned battery by ID, if present.
+    fn get_battery(&self, id: u32) -> Option<AssignedBattery> {
+        self.inner.get_battery(id).map(|battery| AssignedBattery {
+            inner: battery.clone(),
+            grid: self.grid(),
+        })
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get how much the grid import at a given DateTime<Utc> could be lowered against the
+    /// schedule: the discharge headroom of all batteries plus the consumption of actions that
+    /// could be moved to later in their window. With clamp, times outside of the schedule give
+    /// the first or last timestep.
+    fn get_total_flex(&self, py: Python<'_>, time: DateTime<Utc>, clamp: bool) -> PyResult<Watt> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid(),
+            schedule_range(self.grid()),
+            clamp,
+            None,
+        )?;
+        let flex = self
+            .inner
+            .get_total_flex(time_converted, first_timestep_fraction(self.grid())?);
+        Ok(Watt::from_energy_per_step(flex as f64, self.grid().step()))
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the electricity exported to the grid at a given DateTime<Utc>.
+    /// With clamp, times outside of the schedule give the first or last timestep.
+    fn get_grid_export(&self, py: Python<'_>, time: DateTime<Utc>, clamp: bool) -> PyResult<Watt> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid(),
+            schedule_range(self.grid()),
+            clamp,
+            None,
+        )?;
+        Ok(Watt::from_energy_per_step(
+            self.inner.get_grid_export(time_converted) as f64,
+            self.grid().step(),
+        ))
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the electricity drawn from the grid at a given DateTime<Utc>.
+    /// With clamp, times outside of the schedule give the first or last timestep.
+    fn get_network_consumption(
+        &self,
+        py: Python<'_>,
+        time: DateTime<Utc>,
+        clamp: bool,
+    ) -> PyResult<Watt> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid(),
+            schedule_range(self.grid()),
+            clamp,
+            None,
+        )?;
+        Ok(Watt::from_energy_per_step(
+            *self
+                .inner
+                .network_consumption
+                .get(time_converted)
+                .unwrap_or(&0) as f64,
+            self.grid().step(),
+        ))
+    }
+    /// Get the electricity drawn from the grid for every timestep of the schedule.
+    fn get_network_consumption_profile(&self) -> PyResult<Vec<(DateTime<Utc>, Watt)>> {
+        let grid = self.grid();
+        schedule_range(grid)
+            .iter_steps()
+            .map(|time| {
+                let consumption = *self.inner.network_consumption.get(time).unwrap_or(&0);
+                Ok((
+                    grid.to_datetime(time)?,
+                    Watt::from_energy_per_step(consumption as f64, grid.step()),
+                ))
+            })
+            .collect()
+    }
+    /// Get the first timestep with the highest grid import and that import.
+    fn get_peak_consumption(&self) -> PyResult<(DateTime<Utc>, Watt)> {
+        let (time, import) = self.inner.get_peak_consumption();
+        Ok((
+            self.grid().to_datetime(time)?,
+            Watt::from_energy_per_step(import as f64, self.grid().step()),
+        ))
+    }
+    /// Get the average power drawn from the grid over every settlement period of the context
+    /// as (start of the period, average import). The period the schedule starts in and the one
+    /// it ends in may be cut short. Without settlement periods, every timestep is one.
+    fn get_settlement_profile(&self) -> PyResult<Vec<(DateTime<Utc>, Watt)>> {
+        self.inner
+            .get_settlement_profile()
+            .into_iter()
+            .map(|(time, import)| {
+                Ok((
+                    self.grid().to_datetime(time)?,
+                    Watt::from_energy_per_step(import as f64, self.grid().step()),
+                ))
+            })
+            .collect()
+    }
+    /// Get the cost of the electricity drawn from the grid at the electricity price, without
+    /// the network fee.
+    fn get_energy_cost(&self) -> Euro {
+        Euro::from_nano_euro(self.inner.get_energy_cost() as f64)
+    }
+    /// Get the network fee paid for the electricity drawn from the grid, 0 without a fee.
+    fn get_network_fee_cost(&self) -> Euro {
+        Euro::from_nano_euro(self.inner.get_network_fee_cost() as f64)
+    }
+    /// Get the grams of CO2 emitted by the electricity drawn from the grid, or None if the
+    /// context has no CO2 intensity, see OptimizerContext.set_co2_intensity.
+    fn get_co2_emissions(&self) -> Option<f64> {
+        // Milli-Wh times milligram per Wh is microgram
+        self.inner
+            .get_co2_emissions()
+            .map(|micrograms| micrograms as f64 / 1e6)
+    }
+    #[pyo3(signature = (time, clamp=false))]
+    /// Get the price paid per Wh drawn from the grid at a given DateTime<Utc>: the cost of the
+    /// import, at the electricity price plus the network fee, divided by the import. None if
+    /// nothing is drawn from the grid in the timestep, e.g. while the battery covers the load.
+    /// With clamp, times outside of the schedule give the first or last timestep.
+    fn get_effective_import_price(
+        &self,
+        py: Python<'_>,
+        time: DateTime<Utc>,
+        clamp: bool,
+    ) -> PyResult<Option<EuroPerWh>> {
+        let time_converted = time_in_range(
+            py,
+            time,
+            self.grid(),
+            schedule_range(self.grid()),
+            clamp,
+            None,
+        )?;
+        Ok(self
+            .inner
+            .get_effective_import_price(time_converted)
+            .map(EuroPerWh::from_micro_euro_per_wh))
+    }
+    /// Get the effective import price, see get_effective_import_price, for every timestep of
+    /// the schedule.
+    fn get_effective_import_price_profile(
+        &self,
+    ) -> PyResult<Vec<(DateTime<Utc>, Option<EuroPerWh>)>> {
+        let grid = self.grid();
+        schedule_range(grid)
+            .iter_steps()
+            .map(|time| {
+                let price = self.inner.get_effective_import_price(time);
+                Ok((
+                    grid.to_datetime(time)?,
+                    price.map(EuroPerWh::from_micro_euro_per_wh),
+                ))
+            })
+            .collect()
+    }
+    /// Get what the schedule is billed under net metering at the flat rate: the electricity
+    /// drawn from the grid minus the electricity exported, plus the network fee on the import.
+    /// Negative if more was exported than drawn.
+    fn get_net_metering_bill(&self, rate: EuroPerWh) -> PyResult<Euro> {
+        let rate = rate.to_internal("net metering rate")?;
+        Ok(Euro::from_nano_euro(
+            self.inner.get_net_metering_bill(rate) as f64
+        ))
+    }
+    /// Get what the cost of the schedule is made of as {part: cost in Euro}, or None if the
+    /// optimizer wasn't asked to explain the cost. The parts are "grid_import", "network_fee",
+    /// "charging_loss", "self_discharge", "battery_wear" (the cycle costs of all batteries, see
+    /// get_battery_wear), "grid_export" (negative when paid for),
+    /// "min_consumption_rounding", "unserved_penalty", "peak_charge", "co2" (the weighted CO2
+    /// emissions, not paid) and "preference" (the preferences of variable actions, not paid). They sum up to the returned total cost.
+    fn get_objective_breakdown(&self) -> Option<BTreeMap<&'static str, Euro>> {
+        let breakdown = self.inner.get_objective_breakdown()?;
+        let parts = [
+            ("grid_import", breakdown.grid_import),
+            ("network_fee", breakdown.network_fee),
+            ("charging_loss", breakdown.charging_loss),
+            ("self_discharge", breakdown.self_discharge),
+            ("battery_wear", breakdown.battery_wear.values().sum()),
+            ("grid_export", breakdown.grid_export),
+            (
+                "min_consumption_rounding",
+                breakdown.min_consumption_rounding,
+            ),
+            ("unserved_penalty", breakdown.unserved_penalty),
+            ("peak_charge", breakdown.peak_charge),
+            ("co2", breakdown.co2),
+            ("preference", breakdown.preference),
+        ];
+        Some(
+            parts
+                .into_iter()
+                .map(|(name, cost)| (name, Euro::from_nano_euro(cost as f64)))
+                .collect(),
+        )
+    }
+    /// Get the cycle cost of the energy stored in every battery with a cycle_cost as
+    /// {id: cost in Euro}, or None if the optimizer wasn't asked to explain the cost.
+    fn get_battery_wear(&self) -> Option<BTreeMap<u32, Euro>> {
+        let breakdown = self.inner.get_objective_breakdown()?;
+        Some(
+            breakdown
+                .battery_wear
+                .iter()
+                .map(|(&id, &cost)| (id, Euro::from_nano_euro(cost as f64)))
+                .collect(),
+        )
+    }
+    /// Attribute the energy cost of the schedule to its consumers. Every consumer is charged
+    /// its consumption at the electricity price, as if it were drawn from the grid when used;
+    /// what generation and batteries save against that is listed separately.
+    /// Returns a dict with "per_timestep" ([(time, cost of the grid import)]),
+    /// "constant_actions" and "variable_actions" ({id: cost}), "beyond_control",
+    /// "generation_savings", "battery_savings" and "total", the cost of all consumers minus
+    /// the savings, which equals get_energy_cost. context provides the beyond control
+    /// consumption and the generation, it should be the one the schedule was optimized for.
+    fn get_cost_breakdown<'py>(
+        &self,
+        py: Python<'py>,
+        context: &OptimizerContext,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let breakdown = self.inner.get_cost_breakdown(&context.to_rust()?);
+        let euro = |cost: i64| Euro::from_nano_euro(cost as f64);
+        let grid = self.grid();
+        let per_timestep = breakdown
+            .per_timestep
+            .iter()
+            .enumerate()
+            .map(|(t, &cost)| Ok((grid.to_datetime(Time::from_timestep(t as u32))?, euro(cost))))
+            .collect::<PyResult<Vec<_>>>()?;
+        let by_id = |costs: &BTreeMap<u32, i64>| {
+            costs
+                .iter()
+                .map(|(&id, &cost)| (id, euro(cost)))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let dict = PyDict::new(py);
+        dict.set_item("per_timestep", per_timestep)?;
+        dict.set_item("constant_actions", by_id(&breakdown.constant_actions))?;
+        dict.set_item("variable_actions", by_id(&breakdown.variable_actions))?;
+        dict.set_item("beyond_control", euro(breakdown.beyond_control))?;
+        dict.set_item("generation_savings", euro(breakdown.generation_savings))?;
+        dict.set_item("battery_savings", euro(breakdown.battery_savings))?;
+        dict.set_item("total", euro(breakdown.total()))?;
+        Ok(dict)
+    }
+    /// Get the solved flow network as a list of its edges, or None unless the solve was run with
+    /// capture_flow. Each edge is a dict with "from_label" and "to_label" (e.g. "Wire",
+    /// "Battery 3", "Action 7"), "timestep" (the time of the edge, None for edges not bound to
+    /// a timestep), "capacity", "flow" and "unit_cost" as the optimizer's raw integers,
+    /// "category" (the cost it counts towards, as in get_objective_breakdown, or
+    /// "initial_charge" for the bonus of keeping charge, None for free edges) and "units".
+    /// The values are taken before rounding the minimum consumption of variable actions.
+    fn get_flow_table<'py>(&self, py: Python<'py>) -> PyResult<Option<Vec<Bound<'py, PyDict>>>> {
+        let Some(table) = self.inner.get_flow_table() else {
+            return Ok(None);
+        };
+        let grid = self.grid();
+        table
+            .iter()
+            .map(|edge| {
+                let dict = PyDict::new(py);
+                dict.set_item("from_label", &edge.from)?;
+                dict.set_item("to_label", &edge.to)?;
+                dict.set_item(
+                    "timestep",
+                    edge.time.map(|time| grid.to_datetime(time)).transpose()?,
+                )?;
+                dict.set_item("capacity", edge.capacity)?;
+                dict.set_item("flow", edge.flow)?;
+                dict.set_item("unit_cost", edge.unit_cost)?;
+                dict.set_item("category", edge.category)?;
+                dict.set_item(
+                    "units",
+                    "capacity and flow in mWh per timestep, unit_cost in µ€/Wh, flow * unit_cost in n€",
+                )?;
+                Ok(dict)
+            })
+            .collect::<PyResult<_>>()
+            .map(Some)
+    }
+    #[pyo3(signature = (percentile=0.9))]
+    /// Get all timesteps where electricity is imported from the grid while the price is above the
+    /// given percentile (in [0, 1]) of the day's prices. Returns [(time, grid import, price)].
+    fn get_expensive_consumption(
+        &self,
+        percentile: f64,
+    ) -> PyResult<Vec<(DateTime<Utc>, Watt, EuroPerWh)>> {
+        if !(0.0..=1.0).contains(&percentile) {
+            return Err(OptimizerError::new_err(format!(
+                "percentile must be in [0, 1], got {}",
+                percentile
+            )));
+        }
+        self.inner
+            .get_expensive_consumption(percentile)
+            .into_iter()
+            .map(|(time, import, price)| {
+                Ok((
+                    self.grid().to_datetime(time)?,
+                    Watt::from_energy_per_step(import as f64, self.grid().step()),
+                    EuroPerWh::from_micro_euro_per_wh(price as f64),
+                ))
+            })
+            .collect()
+    }
+    #[pyo3(signature = (context, stride=15))]
+    /// For each constant action, get the total cost with that action moved to every stride-th
+    /// feasible start time while everything else stays fixed. The assigned start is always included.
+    /// Returns {action id: [(start time, total cost)]}, ordered by start time.
+    fn get_placement_sensitivity(
+        &self,
+        context: &OptimizerContext,
+        stride: u32,
+    ) -> PyResult<HashMap<u32, CostCurve>> {
+        if stride == 0 {
+            return Err(OptimizerError::new_err("stride must be at least 1"));
+        }
+        let curves = placement_sensitivity(&context.to_rust()?, &self.inner, stride)
+            .map_err(flow_solve_error)?;
+        curves
+            .into_iter()
+            .map(|(id, curve)| {
+                let curve = curve
+                    .into_iter()
+                    .map(|(start, cost)| {
+                        Ok((
+                            self.grid().to_datetime(start)?,
+                            Euro::from_nano_euro(cost as f64),
+                        ))
+                    })
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok((id, curve))
+            })
+            .collect()
+    }
+    /// Find the cheapest start for an additional load of consumption for duration within
+    /// window = (start, end), on top of this schedule. Its constant actions stay where they are,
+    /// the schedule itself is not modified. Starts at which the load can't be served, e.g.
+    /// because of the grid limit, are skipped.
+    /// Returns the start time and the marginal cost, i.e. how much the total cost rises.
+    fn suggest_slot(
+        &self,
+        py: Python<'_>,
+        context: &OptimizerContext,
+        duration: TimeDelta,
+        consumption: Watt,
+        window: (DateTime<Utc>, DateTime<Utc>),
+    ) -> PyResult<(DateTime<Utc>, Euro)> {
+        let (start, end) = window;
+        if end - start < duration {
+            return Err(InfeasibleError::new_err(format!(
+                "The window from {} to {} is shorter than the duration {}",
+                start, end, duration
+            )));
+        }
+        let rust_context = context.to_rust()?;
+        // Any ID no constant action of the context or the schedule uses
+        let used: HashSet<u32> = rust_context
+            .get_constant_actions()
+            .iter()
+            .map(|action| action.get_id())
+            .chain(self.inner.constant_actions.keys().copied())
+            .collect();
+        let id = (0..).find(|id| !used.contains(id)).unwrap();
+        let action = ConstantAction::new(start, end, duration, consumption, id, None, false)
+            .to_rust(py, self.grid())?;
+        let slot = cheapest_additional_slot(&rust_context, &self.inner, Rc::new(action))
+            .map_err(flow_solve_error)?;
+        let Some((start, cost)) = slot else {
+            return Err(InfeasibleError::new_err(
+                "The load can't be served at any start time within the window",
+            ));
+        };
+        Ok((
+            self.grid().to_datetime(start)?,
+            Euro::from_nano_euro(cost as f64),
+        ))
+    }
+    #[pyo3(signature = (start=None, end=None))]
+    /// Get one record dict per timestep in [start, end) as a list.
+    /// Bounds default to the schedule horizon and must lie on timestep boundaries.
+    fn to_records<'py>(
+        &self,
+        py: Python<'py>,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let (start_step, end_step) = records::timestep_range(self, start, end)?;
+        records::collect_records(py, self, start_step, end_step)
+    }
+    #[pyo3(signature = (context=None))]
+    /// Get every timestep of the horizon as a CSV table with a header row. Columns are time
+    /// (ISO 8601), electricity_price (Euro/Wh), network_consumption, grid_export and, if
+    /// context is given, generation (W), then battery_<id>_level (Wh) and battery_<id>_speed
+    /// (W) of every battery and action_<id>_consumption (W) of every action by id, which is 0
+    /// while the action doesn't run. context should be the one the schedule was optimized for.
+    fn to_csv(&self, context: Option<&OptimizerContext>) -> PyResult<String> {
+        let context = context.map(OptimizerContext::to_rust).transpose()?;
+        records::to_csv(self, context.as_ref())
+    }
+    #[pyo3(signature = (columns=None))]
+    /// Render the schedule as a text Gantt chart for debugging in a terminal, with a price line
+    /// from _ (cheapest) to @ (most expensive) and a line per battery (+ charging, - discharging,
+    /// . idle) and action (# consuming, . not). Each of the columns covers an equal share of the
+    /// horizon and shows the state of most of its timesteps, ties go to + over - over . and # over
+    /// . columns defaults to 48, or one per timestep if there are fewer.
+    /// Raises OptimizerError if columns isn't between 1 and the number of timesteps.
+    fn gantt(&self, columns: Option<usize>) -> PyResult<String> {
+        let steps = self.grid().timestep().get_steps_per_day() as usize;
+        let columns = columns.unwrap_or(steps.min(48));
+        if !(1..=steps).contains(&columns) {
+            return Err(OptimizerError::new_err(format!(
+                "columns must be between 1 and {steps}, got {columns}"
+            )));
+        }
+        Ok(self.inner.gantt(columns))
+    }
+    #[pyo3(signature = (start=None, end=None))]
+    /// Like to_records, but returns an iterator computing each record on demand.
+    fn iter_records(
+        slf: PyRef<'_, Self>,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> PyResult<ScheduleRecordIterator> {
+        let (start_step, end_step) = records::timestep_range(&slf, start, end)?;
+        Ok(ScheduleRecordIterator::new(slf, start_step, end_step))
+    }
+    /// Serialize the schedule to JSON. The start time is not included, pass it to from_json.
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.inner).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+    #[staticmethod]
+    /// Rebuild a schedule created by to_json. start_time must be the start time of the
+    /// context the schedule was optimized for. Raises ValueError if the JSON is invalid or
+    /// the lists in it don't match the timesteps and windows of the schedule.
+    fn from_json(s: &str, start_time: DateTime<Utc>) -> PyResult<Schedule> {
+        // Going through a Value keeps the stack small, deserializing the day long arrays
+        // straight from the string takes several MB of stack in debug builds.
+        let value: serde_json::Value = serde_json::from_str(s)
+            .map_err(|e| OptimizerError::new_err(format!("Invalid schedule JSON: {}", e)))?;
+        let inner = serde_json::from_value(value)
+            .map_err(|e| OptimizerError::new_err(format!("Invalid schedule JSON: {}", e)))?;
+        Schedule::checked(inner, start_time)
+    }
+    /// Check the schedule against the constraints of the context it was optimized for:
+    /// action windows, maximum consumption, battery limits and the grid limit.
+    /// Returns a description of every violation, empty for a valid schedule.
+    fn verify(&self, context: &OptimizerContext) -> PyResult<Vec<String>> {
+        self.inner
+            .verify(&context.to_rust()?)
+            .iter()
+            .map(|violation| violation_message(violation, self.grid()))
+            .collect()
+    }
+    #[pyo3(signature = (next, tolerance=WattHour { value: 1.0 }))]
+    /// Merge with a schedule starting later into one schedule over this schedule's horizon:
+    /// this schedule up to the start of `next`, then `next`. What `next` plans after the end
+    /// of this horizon is cut off. Assets in both schedules take the assignment of `next` from
+    /// the seam on, batteries in both must have charge levels within `tolerance` at the seam.
+    /// Raises OutOfRange if `next` doesn't start within the horizon and ValueError if it
+    /// doesn't start on a timestep boundary, has timesteps of another length, the battery
+    /// levels differ or the tolerance is negative.
+    fn concat(&self, py: Python<'_>, next: &Schedule, tolerance: WattHour) -> PyResult<Schedule> {
+        if tolerance.value < 0.0 {
+            return Err(OptimizerError::new_err(
+                "The tolerance must not be negative",
+            ));
+        }
+        if next.grid().step() != self.grid().step() {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "The next schedule has timesteps of {}, but this schedule of {}",
+                next.grid().step(),
+                self.grid().step()
+            )));
+        }
+        if !self.grid().is_aligned(next.start_timestamp) {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "The next schedule starts at {}, which is not on the timesteps of this schedule",
+                next.start_timestamp
+            )));
+        }
+        let seam = time_in_range(
+            py,
+            next.start_timestamp,
+            self.grid(),
+            schedule_range(self.grid()),
+            false,
+            None,
+        )?;
+        let merged = self
+            .inner
+            .concat(&next.inner, seam, tolerance.to_internal("tolerance")?)
+            .map_err(|e| OptimizerError::new_err(e.to_string()))?;
+        Ok(Schedule {
+            inner: merged,
+            start_timestamp: self.start_timestamp,
+        })
+    }
+    /// Compare with another schedule over the same horizon, e.g. the result of a second run
+    /// on nearly the same inputs. See ScheduleDiff. Raises ValueError if the schedules start
+    /// at different times or have timesteps of different lengths.
+    fn diff(&self, other: &Schedule) -> PyResult<ScheduleDiff> {
+        if other.grid().step() != self.grid().step() {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "The other schedule has timesteps of {}, but this schedule of {}",
+                other.grid().step(),
+                self.grid().step()
+            )));
+        }
+        if other.start_timestamp != self.start_timestamp {
+            return Err(OptimizerError::new_err(format!(
+                "The other schedule starts at {}, but this schedule at {}",
+                other.start_timestamp, self.start_timestamp
+            )));
+        }
+        let grid = self.grid();
+        let diff = self.inner.diff(&other.inner);
+        let energy = |milli_wh: i64| WattHour::from_milli_wh(milli_wh as f64);
+        let assets = |assets: &[ScheduledAsset]| {
+            assets
+                .iter()
+                .map(|asset| match *asset {
+                    ScheduledAsset::ConstantAction { id } => ("constant_action", id),
+                    ScheduledAsset::VariableAction { id } => ("variable_action", id),
+                    ScheduledAsset::Battery { id } => ("battery", id),
+                })
+                .collect()
+        };
+        Ok(ScheduleDiff {
+            constant_actions: diff
+                .constant_actions
+                .iter()
+                .map(|&(id, start, other_start)| {
+                    Ok((
+                        id,
+                        grid.to_datetime(other_start)? - grid.to_datetime(start)?,
+                    ))
+                })
+                .collect::<PyResult<_>>()?,
+            variable_actions: diff
+                .variable_actions
+                .iter()
+                .map(|&(id, difference)| (id, energy(difference)))
+                .collect(),
+            batteries: diff
+                .batteries
+                .iter()
+                .map(|&(id, deviation)| (id, energy(deviation)))
+                .collect(),
+            only_in_this: assets(&diff.only_in_this),
+            only_in_other: assets(&diff.only_in_other),
+            stability: diff.stability,
+        })
+    }
+    /// Get what was changed about the assets when merging schedules with concat, empty for
+    /// schedules that weren't merged.
+    fn get_merge_notes(&self) -> Vec<String> {
+        self.inner
+            .get_merge_notes()
+            .iter()
+            .map(|note| note.to_string())
+            .collect()
+    }
+    /// Get the consumption of constant actions the optimizer couldn't serve, e.g. because of
+    /// the grid limit, as (action id, start of the interval, unserved power), ordered by
+    /// time. Empty if every constant action is served in full. Overlapping constant actions
+    /// add up and must all be served; if they can't be, the action that started last is
+    /// shorted first.
+    fn get_unserved_constant_actions(&self) -> PyResult<Vec<(u32, DateTime<Utc>, Watt)>> {
+        self.inner
+            .get_unserved_constant_actions()
+            .iter()
+            .map(|unserved| {
+                Ok((
+                    unserved.id,
+                    self.grid().to_datetime(unserved.time)?,
+                    Watt::from_energy_per_step(unserved.unserved as f64, self.grid().step()),
+                ))
+            })
+            .collect()
+    }
+    /// Serialize the schedule to the compact binary form read by from_bytes. Like to_json, the
+    /// start time is not included.
+    fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        binary::encode(Blob::Schedule, &self.inner)
+    }
+    /// Stable hash of the plan: all assignments, battery levels, network consumption and grid
+    /// export, the prices and the resulting grid cost, and the start time. Maps are hashed in
+    /// order of their IDs and all values as their internal integers, so the hash doesn't
+    /// depend on the platform or the order assets were added in. Equal hashes imply identical
+    /// plans; hashes are only comparable between schedules of the same format version.
+    /// The objective breakdown, flow table, merge notes and unserved constant actions aren't
+    /// part of the plan and don't change the hash.
+    fn content_hash(&self) -> PyResult<String> {
+        let plan = RustSchedule {
+            objective_breakdown: None,
+            flow_table: None,
+            merge_notes: Vec::new(),
+            unserved_constant_actions: Vec::new(),
+            ..self.inner.clone()
+        };
+        let mut bytes = binary::encode(Blob::Schedule, &plan)?;
+        let grid_cost: i64 = self
+            .inner
+            .network_consumption
+            .get_data()
+            .iter()
+            .zip(self.inner.electricity_price.get_data())
+            .map(|(consumption, price)| consumption * price)
+            .sum();
+        bytes.extend_from_slice(&grid_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.start_timestamp.timestamp().to_le_bytes());
+        Ok(format!("{:032x}", binary::fnv1a_128(&bytes)))
+    }
+    #[staticmethod]
+    /// Rebuild a schedule created by to_bytes. Raises ValueError for blobs of another format version
+    /// and for schedules whose lists don't match their timesteps and windows.
+    fn from_bytes(data: &[u8], start_time: DateTime<Utc>) -> PyResult<Schedule> {
+        Schedule::checked(binary::decode(Blob::Schedule, data)?, start_time)
+    }
+}
+impl Schedule {
+    /// Wraps a deserialized schedule, raising OptimizerError if its parts don't fit together,
+    /// e.g. a consumption list that doesn't cover the window of its action.
+    fn checked(inner: RustSchedule, start_time: DateTime<Utc>) -> PyResult<Schedule> {
+        inner
+            .check_shape()
+            .map_err(|e| OptimizerError::new_err(format!("Invalid schedule: {}", e)))?;
+        Ok(Schedule {
+            inner,
+            start_timestamp: start_time,
+        })
+    }
+    /// The timesteps of the schedule, starting at its start time.
+    pub(crate) fn grid(&self) -> TimeGrid {
+        TimeGrid::new(self.start_timestamp, self.inner.get_timestep_config())
+    }
+}
+
+#[pyclass(unsendable)]
+/// Reusable optimizer. Each call to solve continues from the state the previous call ended in.
+/// If a solve is interrupted in a way that leaves the state inconsistent, the solver is poisoned
+/// and every further solve raises a RuntimeError.
+struct Solver {
+    inner: RustSolver,
+    grid: TimeGrid,
+    /// Number of iterations between two progress calls when solve isn't given one.
+    progress_every: usize,
+    /// Fingerprint of the context for the instrumentation, if it was installed on creation.
+    fingerprint: Option<String>,
+}
+#[pymethods]
+impl Solver {
+    #[new]
+    #[pyo3(signature = (context, seed=None, cooling=None, explain_cost=None, capture_flow=None, options=None, **cooling_options))]
+    /// Create a solver for the given context. Passing a seed makes the results reproducible.
+    /// cooling names the cooling schedule, cooling_options are its parameters.
+    /// With explain_cost, the returned schedules carry the breakdown of their cost, see
+    /// Schedule.get_objective_breakdown; with capture_flow, their flow network, see
+    /// Schedule.get_flow_table.
+    /// The settings default to those of options, the keywords given override them; see
+    /// OptimizerOptions. isolate_errors isn't supported.
+    #[allow(clippy::too_many_arguments)]
+    fn new<'py>(
+        py: Python<'py>,
+        context: &OptimizerContext,
+        seed: Option<u64>,
+        cooling: Option<&str>,
+        explain_cost: Option<bool>,
+        capture_flow: Option<bool>,
+        options: Option<PyRef<'py, OptimizerOptions>>,
+        cooling_options: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Self> {
+        let overrides = keyword_overrides(py, cooling_options)?;
+        set_given(&overrides, "seed", seed)?;
+        set_given(&overrides, "cooling", cooling)?;
+        set_given(&overrides, "explain_cost", explain_cost)?;
+        set_given(&overrides, "capture_flow", capture_flow)?;
+        let options = OptimizerOptions::overridden(options.as_deref(), &overrides)?;
+        if options.isolate_errors {
+            return Err(OptimizerError::new_err(
+                "isolate_errors is only supported by run_simulated_annealing",
+            ));
+        }
+        Ok(Solver {
+            inner: RustSolver::new(
+                context.to_rust()?,
+                options.inner.annealing_config(),
+                options.inner.seed,
+            ),
+            grid: context.grid(),
+            progress_every: options.progress_every,
+            fingerprint: instrumentation::is_installed()
+                .then(|| instrumentation::fingerprint(context))
+                .transpose()?,
+        })
+    }
+    #[pyo3(signature = (progress=None, progress_every=None))]
+    /// Run the optimization. Returns the lowest total cost seen in Euro and its Schedule; the
+    /// next solve continues from that schedule.
+    /// progress(iteration, temperature, cost, best_cost) is called every progress_every-th
+    /// iteration if given, by default as often as the options of the solver say; an exception
+    /// raised by it aborts the solve and is re-raised, the solver stays usable.
+    fn solve(
+        &mut self,
+        py: Python<'_>,
+        progress: Option<Py<PyAny>>,
+        progress_every: Option<usize>,
+    ) -> PyResult<(Euro, Schedule)> {
+        let mut report = SolveReport::start(py, "solver", self.grid.anchor(), || {
+            Ok(self.fingerprint.clone())
+        })?;
+        let result = solve_with_progress(
+            py,
+            &mut self.inner,
+            self.grid,
+            progress.as_ref(),
+            progress_every.unwrap_or(self.progress_every),
+        );
+        if let Ok((cost, _, stats)) = &result {
+            report.annealed(*cost, stats);
+        }
+        let (cost, rust_schedule, _) = report.finish(py, result)?;
+        Ok((
+            Euro::from_nano_euro(cost as f64),
+            Schedule {
+                inner: rust_schedule,
+                start_timestamp: self.grid.anchor(),
+            },
+        ))
+    }
+    /// True if the solver can't be used anymore.
+    fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+}
+
+/// Iterations of the iteration-based cooling schedules when none are given.
+/// About as many as the default geometric schedule runs.
+const DEFAULT_COOLING_ITERATIONS: usize = 6000;
+
+/// Builds the annealing settings from the name of a cooling schedule and its options.
+///
+/// Every schedule accepts `initial_temperature` and `min_temperature`. "geometric" also takes
+/// `factor`, while "linear", "logarithmic" and "lam" take `iterations`.
+fn annealing_config(
+    cooling: &str,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<AnnealingConfig> {
+    let iteration_based = match cooling {
+        "geometric" => false,
+        "linear" | "logarithmic" | "lam" => true,
+        _ => {
+            return Err(OptimizerError::new_err(format!(
+                "Unknown cooling schedule '{cooling}', expected one of \
+                 'geometric', 'linear', 'logarithmic' or 'lam'"
+            )));
+        }
+    };
+    let mut config = AnnealingConfig::default();
+    let mut factor = 0.999;
+    let mut iterations = DEFAULT_COOLING_ITERATIONS;
+    for (key, value) in options.into_iter().flat_map(|options| options.iter()) {
+        let key: String = key.extract()?;
+        match key.as_str() {
+            "initial_temperature" => config.initial_temperature = value.extract()?,
+            "min_temperature" => config.min_temperature = value.extract()?,
+            "factor" if !iteration_based => factor = value.extract()?,
+            "iterations" if iteration_based => iterations = value.extract()?,
+            _ => {
+                return Err(OptimizerError::new_err(format!(
+                    "Unknown option '{key}' for cooling schedule '{cooling}'"
+                )));
+            }
+        }
+    }
+    if !(0.0 < config.min_temperature && config.min_temperature < config.initial_temperature) {
+        return Err(OptimizerError::new_err(
+            "Temperatures must satisfy 0 < min_temperature < initial_temperature",
+        ));
+    }
+    if !(0.0 < factor && factor < 1.0) {
+        return Err(OptimizerError::new_err("factor must be between 0 and 1"));
+    }
+    if iterations == 0 {
+        return Err(OptimizerError::new_err("iterations must be at least 1"));
+    }
+    config.cooling = match cooling {
+        "geometric" => Cooling::Geometric { factor },
+        "linear" => Cooling::Linear { iterations },
+        "logarithmic" => Cooling::Logarithmic { iterations },
+        _ => Cooling::Lam { iterations },
+    };
+    Ok(config)
+}
+
+/// Moves the solver into `Python::detach` and its result out of it, although they aren't
+/// `Send`. Only these two are `Send` through the wrapper.
+struct AssertSend<T>(T);
+// SAFETY: The solver shares its `Rc`s with `OptimizerContext` and friends. These classes are
+// `unsendable`, so other threads can't reach the `Rc`s while the GIL is released, and `detach`
+// runs the closure on the current thread.
+unsafe impl Send for AssertSend<&mut RustSolver> {}
+// SAFETY: The schedule of the result holds `Rc`s of the solver's context, see above. It is
+// created and consumed on the current thread.
+unsafe impl Send for AssertSend<SolveResult> {}
+/// What `RustSolver::solve` returns when the progress callback can raise.
+type SolveResult = Result<(i64, RustSchedule, SolveStats), SolveError<PyErr>>;
+impl<T> AssertSend<T> {
+    // A method instead of `.0`, so closures capture the wrapper and not the bare field.
+    fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Run a solve, calling the Python progress callback every `every`-th iteration.
+/// The GIL is released during the solve, so other Python threads keep running. It is only
+/// taken back to call the callback; skipped iterations don't touch any Python objects.
+fn solve_with_progress(
+    py: Python<'_>,
+    solver: &mut RustSolver,
+    grid: TimeGrid,
+    progress: Option<&Py<PyAny>>,
+    every: usize,
+) -> PyResult<(i64, RustSchedule, SolveStats)> {
+    if every == 0 {
+        return Err(OptimizerError::new_err("progress_every must be at least 1"));
+    }
+    let solver = AssertSend(solver);
+    let result = py
+        .detach(move || {
+            AssertSend(solver.into_inner().solve(|p| -> PyResult<()> {
+                if let Some(progress) = progress
+                    && p.iteration.is_multiple_of(every)
+                {
+                    Python::attach(|py| {
+                        progress.call1(
+                            py,
+                            (
+                                p.iteration,
+                                p.temperature,
+                                Euro::from_nano_euro(p.cost as f64),
+                                Euro::from_nano_euro(p.best_cost as f64),
+                            ),
+                        )
+                    })?;
+                }
+                Ok(())
+            }))
+        })
+        .into_inner();
+    match result {
+        Ok(result) => Ok(result),
+        Err(SolveError::Aborted(e)) => Err(e),
+        Err(e @ SolveError::Poisoned) => Err(PyRuntimeError::new_err(e.to_string())),
+        Err(SolveError::Flow(e)) => Err(flow_solve_error(e)),
+        Err(SolveError::GridLimitExceeded(times)) => Err(grid_overload_error(times, grid)),
+        Err(SolveError::Infeasible(shortfall)) => Err(shortfall_error(shortfall, grid)),
+        Err(SolveError::Inconsistent(violations)) => {
+            let violations = violations
+                .iter()
+                .map(|violation| Ok(format!("\n- {}", violation_message(violation, grid)?)))
+                .collect::<PyResult<String>>()?;
+            Err(InternalConsistencyError::new_err(format!(
+                "The optimizer produced a schedule that violates its context, please report this bug:{}",
+                violations
+            )))
+        }
+    }
+}
+
+/// Describe a violation found by Schedule::verify in user units.
+fn violation_message(violation: &Violation, grid: TimeGrid) -> PyResult<String> {
+    let at = |time: Time| grid.to_datetime(time);
+    let power = |energy: i64| Watt::from_energy_per_step(energy as f64, grid.step());
+    Ok(match *violation {
+        Violation::MissingConstantAction { id } => format!("Constant action {} is missing", id),
+        Violation::ConstantActionOutsideWindow { id, start, end } => format!(
+            "Constant action {} runs from {} to {}, outside of its window",
+            id,
+            at(start)?,
+            at(end)?
+        ),
+        Violation::PrecedenceViolated { before, after } => format!(
+            "Constant action {} doesn't end early enough before constant action {} starts",
+            before, after
+        ),
+        Violation::ExclusionViolated { first, second } => format!(
+            "Constant actions {} and {} run at the same time, but share an exclusion group",
+            first, second
+        ),
+        Violation::MissingVariableAction { id } => format!("Variable action {} is missing", id),
+        Violation::VariableActionOutsideWindow { id, start, end } => format!(
+            "Variable action {} is assigned the window {} to {}, not its own",
+            id,
+            at(start)?,
+            at(end)?
+        ),
+        Violation::VariableActionOverMax {
+            id,
+            time,
+            consumption,
+            max,
+        } => format!(
+            "Variable action {} consumes {:.2} W at {}, allowed are 0 to {:.2} W",
+            id,
+            power(consumption).value,
+            at(time)?,
+            power(max).value
+        ),
+        Violation::VariableActionBelowMin {
+            id,
+            time,
+            consumption,
+            min,
+        } => format!(
+            "Variable action {} consumes {:.2} W at {}, less than its min_consumption {:.2} W",
+            id,
+            power(consumption).value,
+            at(time)?,
+            power(min).value
+        ),
+        Violation::MissingBattery { id } => format!("Battery {} is missing", id),
+        Violation::BatteryLevelOutOfRange { id, time, level } => format!(
+            "Battery {} has charge level {:.2} Wh at {}, outside of its minimum charge and capacity",
+            id,
+            WattHour::from_milli_wh(level as f64).value,
+            at(time)?
+        ),
+        Violation::GridLimitExceeded {
+            time,
+            consumption,
+            limit,
+        } => format!(
+            "Grid import of {:.2} W at {} exceeds grid_limit {:.2} W",
+            power(consumption).value,
+            at(time)?,
+            power(limit).value
+        ),
+    })
+}
+
+/// ValueError listing the timesteps at which the beyond control consumption exceeds the
+/// grid limit.
+fn grid_overload_error(times: Vec<Time>, grid: TimeGrid) -> PyErr {
+    let times = times
+        .into_iter()
+        .map(|time| Ok(grid.to_datetime(time)?.to_string()))
+        .collect::<PyResult<Vec<_>>>();
+    match times {
+        Ok(times) => InfeasibleError::new_err(format!(
+            "Beyond control consumption exceeds grid_limit at {}",
+            times.join(", ")
+        )),
+        Err(e) => e,
+    }
+}
+
+/// InfeasibleError listing the consumption and charge targets that can't be met and the limits
+/// in the way. They are also attached as the attributes unserved [(datetime, Watt)] of the
+/// beyond control consumption, unserved_actions {id: WattHour} of the variable actions,
+/// unmet_targets {id: WattHour} of the batteries' final charge targets and binding
+/// [(kind, id, datetime)], where id or datetime are None if the kind has none.
+fn shortfall_error(shortfall: Shortfall, grid: TimeGrid) -> PyErr {
+    let power = |energy: i64| Watt::from_energy_per_step(energy as f64, grid.step());
+    let energy = |energy: i64| WattHour::from_milli_wh(energy as f64);
+    Python::attach(|py| {
+        let mut lines = Vec::new();
+        let mut unserved = Vec::new();
+        for &(time, amount) in &shortfall.by_time {
+            let time = grid.to_datetime(time)?;
+            lines.push(format!(
+                "{:.2} W of beyond control consumption at {}",
+                power(amount).value,
+                time
+            ));
+            unserved.push((time, power(amount)));
+        }
+        let unserved_actions = PyDict::new(py);
+        for &(id, amount) in &shortfall.by_action {
+            lines.push(format!(
+                "{:.2} Wh of variable action {}",
+                energy(amount).value,
+                id
+            ));
+            unserved_actions.set_item(id, energy(amount))?;
+        }
+        let unmet_targets = PyDict::new(py);
+        for &(id, amount) in &shortfall.by_battery {
+            lines.push(format!(
+                "{:.2} Wh missing to the final charge target of battery {}",
+                energy(amount).value,
+                id
+            ));
+            unmet_targets.set_item(id, energy(amount))?;
+        }
+        let mut binding = Vec::new();
+        for limit in &shortfall.binding {
+            let (kind, id, time) = match *limit {
+                BindingLimit::ActionMaxConsumption { id } => {
+                    lines.push(format!(
+                        "Variable action {} runs at its max_consumption in its whole window",
+                        id
+                    ));
+                    ("max_consumption", Some(id), None)
+                }
+                BindingLimit::GridLimit { time } => {
+                    let time = grid.to_datetime(time)?;
+                    lines.push(format!("grid_limit is reached at {}", time));
+                    ("grid_limit", None, Some(time))
+                }
+                BindingLimit::BatteryEmpty { id, time } => {
+                    let time = grid.to_datetime(time)?;
+                    lines.push(format!("Battery {} is empty at {}", id, time));
+                    ("battery_empty", Some(id), Some(time))
+                }
+                BindingLimit::BatteryMaxOutput { id, time } => {
+                    let time = grid.to_datetime(time)?;
+                    lines.push(format!(
+                        "Battery {} discharges at its max_output at {}",
+                        id, time
+                    ));
+                    ("battery_max_output", Some(id), Some(time))
+                }
+            };
+            binding.push((kind, id, time));
+        }
+        let err = InfeasibleError::new_err(format!(
+            "Not all consumption and charge targets can be met:\n- {}",
+            lines.join("\n- ")
+        ));
+        let value = err.value(py);
+        value.setattr("unserved", unserved)?;
+        value.setattr("unserved_actions", unserved_actions)?;
+        value.setattr("unmet_targets", unmet_targets)?;
+        value.setattr("binding", binding)?;
+        Ok(err)
+    })
+    .unwrap_or_else(|e| e)
+}
+
+#[pyclass(frozen)]
+#[derive(Default)]
+/// Stops a running run_simulated_annealing early, e.g. when the inputs changed and a new
+/// optimization should start. cancel can be called from any thread while the run is going on.
+struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+#[pymethods]
+impl CancellationToken {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+    /// Request the runs using this token to stop. They return the best schedule found so far.
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    /// True once cancel was called.
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[pyclass(frozen)]
+/// How much two schedules differ, see Schedule.diff. Assets are keyed by ID.
+struct ScheduleDiff {
+    /// How much later every constant action in both schedules starts in the other one.
+    #[pyo3(get)]
+    constant_actions: BTreeMap<u32, TimeDelta>,
+    /// Sum of the absolute differences of the energy per timestep, for every variable action
+    /// in both schedules.
+    #[pyo3(get)]
+    variable_actions: BTreeMap<u32, WattHour>,
+    /// Largest difference of the charge levels, for every battery in both schedules.
+    #[pyo3(get)]
+    batteries: BTreeMap<u32, WattHour>,
+    /// Assets only planned by the schedule diff was called on, as (kind, id) with kind
+    /// "constant_action", "variable_action" or "battery".
+    #[pyo3(get)]
+    only_in_this: Vec<(&'static str, u32)>,
+    /// Assets only planned by the other schedule, like only_in_this.
+    #[pyo3(get)]
+    only_in_other: Vec<(&'static str, u32)>,
+    /// 1 for identical schedules, down to 0 if every asset changed completely: 1 minus the
+    /// mean change of all assets, each between 0 and 1. A constant action's shift counts
+    /// relative to the slack of its window, a variable action's difference relative to the
+    /// energy both schedules assign to it and a battery's deviation relative to its capacity.
+    /// An asset in only one schedule counts as changed completely.
+    #[pyo3(get)]
+    stability: f64,
+}
+#[pymethods]
+impl ScheduleDiff {
+    fn __repr__(&self) -> String {
+        format!(
+            "ScheduleDiff(stability={:.4}, constant_actions={}, variable_actions={}, batteries={}, only_in_this={:?}, only_in_other={:?})",
+            self.stability,
+            self.constant_actions.len(),
+            self.variable_actions.len(),
+            self.batteries.len(),
+            self.only_in_this,
+            self.only_in_other
+        )
+    }
+}
+
+#[pyclass(unsendable)]
+/// Result of run_simulated_annealing. Unpacks into (cost, schedule) like a tuple, where cost is
+/// the total objective including the penalties.
+struct OptimizeResult {
+    /// Lowest total cost seen in Euro, the objective the optimizer minimized.
+    #[pyo3(get)]
+    cost: Euro,
+    /// What the schedule really costs, the total objective without the penalties.
+    #[pyo3(get)]
+    energy_cost: Euro,
+    /// Parts of the objective that aren't paid for, by name. Only those that aren't zero.
+    #[pyo3(get)]
+    penalties: BTreeMap<&'static str, Euro>,
+    /// What is billed under net metering, see Schedule.get_net_metering_bill, or None if the
+    /// context doesn't use net metering. Not what the optimizer minimized.
+    #[pyo3(get)]
+    bill: Option<Euro>,
+    /// Schedule with that cost.
+    #[pyo3(get)]
+    schedule: Py<Schedule>,
+    /// True if the run was stopped early through its CancellationToken.
+    #[pyo3(get)]
+    cancelled: bool,
+    /// Assets left out of the schedule by isolate_errors, as {id: the error they raised}.
+    #[pyo3(get)]
+    excluded: Py<PyDict>,
+}
+#[pymethods]
+impl OptimizeResult {
+    /// The total objective, equal to cost.
+    #[getter]
+    fn total_objective(&self) -> Euro {
+        self.cost.clone()
+    }
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        (self.cost.clone(), self.schedule.clone_ref(py))
+            .into_pyobject(py)?
+            .try_iter()
+    }
+}
+
+/// Start timesteps of the constant actions of a schedule in the grid of a context, negative for
+/// those starting before it.
+fn constant_action_starts(schedule: &Schedule, grid: TimeGrid) -> PyResult<HashMap<u32, i64>> {
+    let first_step = grid.floor(grid.anchor())?;
+    schedule
+        .inner
+        .constant_actions
+        .iter()
+        .map(|(&id, action)| {
+            let start = schedule.grid().to_datetime(action.get_start_time())?;
+            let steps = (grid.floor(start)? - first_step).num_milliseconds()
+                / grid.step().num_milliseconds();
+            Ok((id, steps))
+        })
+        .collect()
+}
+
+#[pyfunction]
+#[pyo3(signature = (context, seed=None, progress=None, progress_every=None, cooling=None, explain_cost=None, cancel=None, capture_flow=None, isolate_errors=None, initial_schedule=None, options=None, **cooling_options))]
+/// Run simulated annealing with a given OptimizerContext.
+/// Returns an OptimizeResult with the lowest total cost seen in Euro and its Schedule, not
+/// necessarily the last accepted one. Passing a seed makes the result reproducible.
+/// progress(iteration, temperature, cost, best_cost) is called every progress_every-th
+/// iteration if given; an exception raised by it aborts the run and is re-raised.
+/// cooling names the cooling schedule, cooling_options are its parameters.
+/// With explain_cost, the schedule carries the breakdown of its cost, see
+/// Schedule.get_objective_breakdown; with capture_flow, its flow network, see
+/// Schedule.get_flow_table.
+/// Cancelling the given CancellationToken stops the run early without raising; the result
+/// then holds the best schedule found so far and has cancelled set.
+/// Disabled assets of the context don't appear in the schedule.
+/// With isolate_errors, assets that fail validation are left out instead of failing the run,
+/// and so are their precedences, exclusion groups and fixed plans; the result names them in
+/// excluded with their errors.
+/// With an initial_schedule, e.g. the one of the previous run, its constant actions start
+/// where it placed them, clamped into their windows of this context; others start at random.
+/// The settings default to those of options, the keywords given override them; see
+/// OptimizerOptions.
+#[allow(clippy::too_many_arguments)]
+fn run_simulated_annealing<'py>(
+    py: Python<'py>,
     context: &OptimizerContext,
-) -> PyResult<(Euro, Schedule)> {
-    let rust_context = context.to_rust();
-    let (cost, rust_schedule) =
-        electricity_price_optimizer::simulated_annealing::run_simulated_annealing(rust_context?);
+    seed: Option<u64>,
+    progress: Option<Py<PyAny>>,
+    progress_every: Option<usize>,
+    cooling: Option<&str>,
+    explain_cost: Option<bool>,
+    cancel: Option<&CancellationToken>,
+    capture_flow: Option<bool>,
+    isolate_errors: Option<bool>,
+    initial_schedule: Option<PyRef<'py, Schedule>>,
+    options: Option<PyRef<'py, OptimizerOptions>>,
+    cooling_options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<OptimizeResult> {
+    let mut report = SolveReport::start(py, "simulated_annealing", context.start_time, || {
+        instrumentation::fingerprint(context).map(Some)
+    })?;
+    let excluded = PyDict::new(py);
+    let result = (|| {
+        let overrides = keyword_overrides(py, cooling_options)?;
+        set_given(&overrides, "seed", seed)?;
+        set_given(&overrides, "progress_every", progress_every)?;
+        set_given(&overrides, "cooling", cooling)?;
+        set_given(&overrides, "explain_cost", explain_cost)?;
+        set_given(&overrides, "capture_flow", capture_flow)?;
+        set_given(&overrides, "isolate_errors", isolate_errors)?;
+        let options = OptimizerOptions::overridden(options.as_deref(), &overrides)?;
+        let mut config = options.inner.annealing_config();
+        config.cancel = cancel.map(|token| token.cancelled.clone());
+        if let Some(schedule) = &initial_schedule {
+            config.initial_starts = constant_action_starts(schedule, context.grid())?;
+        }
+        let rust_context = if options.isolate_errors {
+            let (rust_context, errors) = report.materialize_isolating_errors(context)?;
+            for (id, err) in errors {
+                excluded.set_item(id, err.into_value(py))?;
+            }
+            rust_context
+        } else {
+            report.materialize(context)?
+        };
+        let mut solver = RustSolver::new(*rust_context, config, options.inner.seed);
+        let result = solve_with_progress(
+            py,
+            &mut solver,
+            context.grid(),
+            progress.as_ref(),
+            options.progress_every,
+        )?;
+        report.annealed(result.0, &result.2);
+        Ok(result)
+    })();
+    let (cost, rust_schedule, stats) = report.finish(py, result)?;
+    let penalties = stats
+        .objective_breakdown
+        .map(|breakdown| breakdown.penalties())
+        .unwrap_or_default();
+    let euro = |cost: i64| Euro::from_nano_euro(cost as f64);
+    Ok(OptimizeResult {
+        cost: euro(cost),
+        energy_cost: euro(cost - penalties.iter().map(|(_, cost)| cost).sum::<i64>()),
+        penalties: penalties
+            .into_iter()
+            .map(|(name, cost)| (name, euro(cost)))
+            .collect(),
+        bill: context
+            .net_metering
+            .map(|(rate, _)| euro(rust_schedule.get_net_metering_bill(rate))),
+        schedule: Py::new(
+            py,
+            Schedule {
+                inner: rust_schedule,
+                start_timestamp: context.start_time,
+            },
+        )?,
+        cancelled: stats.cancelled,
+        excluded: excluded.unbind(),
+    })
+}
+
+#[pyfunction]
+/// Solve a context without constant actions or contiguous variable actions exactly with the
+/// min-cost flow.
+/// Returns total cost in Euro and the optimal Schedule, which always carries the breakdown of
+/// its cost.
+fn run_exact(py: Python<'_>, context: &OptimizerContext) -> PyResult<(Euro, Schedule)> {
+    let mut report = SolveReport::start(py, "exact", context.start_time, || {
+        instrumentation::fingerprint(context).map(Some)
+    })?;
+    // The schedule is boxed right away, like the problem, to keep the frames in between small
+    let result = report.materialize(context).and_then(|rust_context| {
+        let started = Instant::now();
+        let (cost, schedule) =
+            solve_exact(&rust_context).map_err(|e| exact_solve_error(e, context))?;
+        report.solved_exactly(cost, started.elapsed());
+        Ok((cost, Box::new(schedule)))
+    });
+    let (cost, rust_schedule) = report.finish(py, result)?;
     Ok((
         Euro::from_nano_euro(cost as f64),
         Schedule {
+            inner: *rust_schedule,
+            start_timestamp: context.start_time,
+        },
+    ))
+}
+
+/// Describe why run_exact failed.
+fn exact_solve_error(e: ExactSolveError, context: &OptimizerContext) -> PyErr {
+    match e {
+        ExactSolveError::ConstantActions(ids) => {
+            let ids = ids.iter().map(u32::to_string).collect::<Vec<_>>();
+            OptimizerError::new_err(format!(
+                "run_exact can't place constant actions or contiguous variable actions (ids {}), use run_simulated_annealing instead",
+                ids.join(", ")
+            ))
+        }
+        ExactSolveError::GridLimitExceeded(times) => grid_overload_error(times, context.grid()),
+        ExactSolveError::Flow(e) => flow_solve_error(e),
+        ExactSolveError::Infeasible(shortfall) => shortfall_error(shortfall, context.grid()),
+    }
+}
+
+/// Describe why the flow couldn't be solved. A full flow stack is a bug of the library, a
+/// stalled solve raises SolverStalled with where it stopped as attributes.
+fn flow_solve_error(e: FlowSolveError) -> PyErr {
+    let FlowSolveError::Stalled(stalled) = e else {
+        return PyRuntimeError::new_err(format!("Flow couldn't be solved: {}", e));
+    };
+    let err = SolverStalled::new_err(format!("Flow couldn't be solved: {}", stalled));
+    Python::attach(|py| {
+        let value = err.value(py);
+        let phase = match stalled.phase {
+            SolvePhase::Spfa => "spfa",
+            SolvePhase::Dijkstra => "dijkstra",
+        };
+        let attributes = [
+            ("phase", phase.into_pyobject(py)?.into_any()),
+            (
+                "iterations",
+                stalled.counters.iterations.into_pyobject(py)?.into_any(),
+            ),
+            (
+                "augmentations",
+                stalled.counters.augmentations.into_pyobject(py)?.into_any(),
+            ),
+            (
+                "flow",
+                Bound::new(py, WattHour::from_milli_wh(stalled.flow as f64))?.into_any(),
+            ),
+            (
+                "cost",
+                Bound::new(py, Euro::from_nano_euro(stalled.cost as f64))?.into_any(),
+            ),
+        ];
+        for (name, attribute) in attributes {
+            value.setattr(name, attribute)?;
+        }
+        Ok(())
+    })
+    .err()
+    .unwrap_or(err)
+}
+
+#[pyfunction]
+#[pyo3(signature = (context, relax=Vec::new()))]
+/// Lowest cost any schedule could reach with the constraints named in relax left out.
+/// relax can contain "battery_power", "grid_limit" (import and export) and "action_windows".
+/// Solved exactly; constant actions are spread over their windows, so the result is a lower bound.
+fn theoretical_best(context: &OptimizerContext, relax: Vec<String>) -> PyResult<Euro> {
+    let mut relaxation = Relaxation::default();
+    for name in &relax {
+        match name.as_str() {
+            "battery_power" => relaxation.battery_power = true,
+            "grid_limit" => relaxation.grid_limit = true,
+            "action_windows" => relaxation.action_windows = true,
+            _ => {
+                return Err(OptimizerError::new_err(format!(
+                    "Unknown relaxation '{}', expected battery_power, grid_limit or action_windows",
+                    name
+                )));
+            }
+        }
+    }
+    let cost =
+        bounds::theoretical_best(&context.to_rust()?, relaxation).map_err(flow_solve_error)?;
+    Ok(Euro::from_nano_euro(cost as f64))
+}
+
+#[pyfunction]
+/// Rebuild an OptimizerContext from a JSON document created by OptimizerContext.dump_problem.
+fn load_problem(json: &str) -> PyResult<OptimizerContext> {
+    let dump: ProblemDump = serde_json::from_str(json)
+        .map_err(|e| OptimizerError::new_err(format!("Invalid problem dump: {}", e)))?;
+    dump.into_context()
+}
+
+#[pyfunction]
+/// Write the problem dump of `context`, `schedule`, summary statistics of the schedule,
+/// content hashes and version information into one zip archive at `path`.
+fn create_audit_bundle(
+    context: &OptimizerContext,
+    schedule: &Schedule,
+    path: &str,
+) -> PyResult<()> {
+    audit::write_bundle(context, schedule, path)
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, revalidate=false))]
+/// Check that no entry of an audit bundle was changed since create_audit_bundle wrote it.
+/// With revalidate, the problem is also rebuilt from its dump and the schedule checked against
+/// it, which must find the same violations as when the bundle was written.
+/// Raises OSError if the file can't be read and ValueError for bundles of another format version.
+fn verify_audit_bundle(path: &str, revalidate: bool) -> PyResult<bool> {
+    audit::verify_bundle(path, revalidate)
+}
+
+#[pymodule]
+/// Python module initializer. Registers units, classes, and functions.
+fn electricity_price_optimizer_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // Register units submodule
+    register_units_submodule(m)?;
+    // Register classes
+    m.add_class::<PrognosesProvider>()?;
+    m.add_class::<MaterializedPrognoses>()?;
+    m.add_class::<ConstantAction>()?;
+    m.add_class::<ProfiledConstantAction>()?;
+    m.add_class::<RecurringConstantAction>()?;
+    m.add_class::<AssignedConstantAction>()?;
+    m.add_class::<VariableAction>()?;
+    m.add_class::<AssignedVariableAction>()?;
+    m.add_class::<Battery>()?;
+    m.add_class::<AssignedBattery>()?;
+    m.add_class::<OptimizerContext>()?;
+    m.add_class::<Schedule>()?;
+    m.add_class::<ScheduleDiff>()?;
+    m.add_class::<ScheduleRecordIterator>()?;
+    m.add_class::<Solver>()?;
+    m.add_class::<CancellationToken>()?;
+    m.add_class::<OptimizerOptions>()?;
+    m.add_class::<OptimizeResult>()?;
+    m.add_class::<PlausibilityLimits>()?;
+    m.add("OptimizerError", m.py().get_type::<OptimizerError>())?;
+    m.add(
+        "TimestepAlignmentError",
+        m.py().get_type::<TimestepAlignmentError>(),
+    )?;
+    m.add("InfeasibleError", m.py().get_type::<InfeasibleError>())?;
+    m.add("PrognosesError", m.py().get_type::<PrognosesError>())?;
+    m.add("UnitError", m.py().get_type::<UnitError>())?;
+    m.add("OutOfRange", m.py().get_type::<OutOfRange>())?;
+    m.add("SolverStalled", m.py().get_type::<SolverStalled>())?;
+    m.add(
+        "InternalConsistencyError",
+        m.py().get_type::<InternalConsistencyError>(),
+    )?;
+    m.add("OptimizerWarning", m.py().get_type::<OptimizerWarning>())?;
+
+    // Register functions
+    m.add_function(wrap_pyfunction!(run_simulated_annealing, m)?)?;
+    m.add_function(wrap_pyfunction!(run_exact, m)?)?;
+    m.add_function(wrap_pyfunction!(load_problem, m)?)?;
+    m.add_function(wrap_pyfunction!(create_audit_bundle, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_audit_bundle, m)?)?;
+    m.add_function(wrap_pyfunction!(theoretical_best, m)?)?;
+    m.add_function(wrap_pyfunction!(set_instrumentation, m)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike};
+    use electricity_price_optimizer::{
+        optimizer::SmartHomeFlowBuilder,
+        schedule::MergeNote,
+        simulated_annealing::SolveOptions,
+        time::{MINUTES_PER_TIMESTEP, STEPS_PER_DAY},
+    };
+    use pyo3::{
+        exceptions::{PyKeyError, PyTypeError},
+        types::{PyAnyMethods, PyDict, PyDictMethods, PyList, PyListMethods},
+    };
+
+    use super::*;
+
+    impl OptimizeResult {
+        /// Cost and schedule, as unpacked in Python.
+        fn unpack(self, py: Python<'_>) -> (Euro, PyRef<'_, Schedule>) {
+            (self.cost, self.schedule.into_bound(py).borrow())
+        }
+    }
+
+    fn example_context() -> OptimizerContext {
+        OptimizerContext {
+            electricity_price: Prognoses::from_closure(|t| (t.to_timestep() % 120) as i64 + 1),
+            generated_electricity: Prognoses::from_closure(|_| 0),
+            beyond_control_consumption: Prognoses::from_closure(|_| 0),
+            batteries: vec![],
+            constant_actions: (1..=2)
+                .map(|id| {
+                    Rc::new(RustConstantAction::new(
+                        Time::at_default_resolution(0, 0),
+                        Time::at_default_resolution(6, 0),
+                        Time::at_default_resolution(0, 30),
+                        200,
+                        id,
+                    ))
+                })
+                .collect(),
+            variable_actions: vec![],
+            feed_in_tariff: None,
+            max_export: None,
+            network_fee: None,
+            co2_intensity: None,
+            net_metering: None,
+            grid_limit: None,
+            peak_price: None,
+            settlement_period: None,
+            fixed_battery_plans: vec![],
+            precedences: vec![],
+            min_gaps: vec![],
+            exclusion_groups: vec![],
+            disabled: BTreeSet::new(),
+            absolute_actions: vec![],
+            plausibility_limits: PlausibilityLimits::default(),
+            start_time: Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap(),
+            timestep: TimestepConfig::default(),
+        }
+    }
+
+    #[test]
+    fn test_negative_max_export_is_rejected() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let get_data = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh(0.0001)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let provider = PrognosesProvider::new(get_data);
+            let mut context = example_context();
+            let mut set = |value| context.set_feed_in_tariff(py, &provider, Some(Watt { value }));
+            assert!(set(0.0).is_ok());
+            assert!(set(-1.0).is_err());
+            assert!(set(f64::NAN).is_err());
+        });
+    }
+
+    #[test]
+    fn test_grid_overload_lists_timesteps() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.grid_limit = Some(100);
+            context.beyond_control_consumption = Prognoses::from_closure(|t| {
+                if t == Time::at_default_resolution(2, 0) || t == Time::at_default_resolution(2, 5)
+                {
+                    150
+                } else {
+                    100
+                }
+            });
+            let Err(err) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            ) else {
+                panic!("Solve should have failed");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert_eq!(
+                err.value(py).to_string(),
+                "Beyond control consumption exceeds grid_limit at 2025-03-01 08:00:00 UTC, 2025-03-01 08:05:00 UTC"
+            );
+        });
+    }
+
+    #[test]
+    fn test_expensive_consumption_above_percentile() {
+        // The last 10% of the day is expensive; the grid is used in the morning and at 23:00.
+        let imports = |t: Time| {
+            t < Time::at_default_resolution(1, 0)
+                || (Time::at_default_resolution(23, 0) <= t
+                    && t < Time::at_default_resolution(23, 30))
+        };
+        let schedule = Schedule {
+            inner: RustSchedule::new(
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+                Prognoses::from_closure(|t| if imports(t) { 60 } else { 0 }),
+                Prognoses::from_closure(|_| 0),
+                Prognoses::from_closure(|t| if t.to_timestep() < 1296 { 10 } else { 500 }),
+            ),
+            start_timestamp: Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap(),
+        };
+
+        let expensive = schedule.get_expensive_consumption(0.9).unwrap();
+        let times: Vec<_> = expensive.iter().map(|(time, _, _)| *time).collect();
+        let expected: Vec<_> = (0..30)
+            .map(|minute| schedule.start_timestamp + TimeDelta::minutes(23 * 60 + minute))
+            .collect();
+        assert_eq!(times, expected);
+        for (_, import, price) in &expensive {
+            assert!((import.value - 3.6).abs() < 1e-9);
+            assert!((price.value - 0.0005).abs() < 1e-12);
+        }
+        // Cheap imports never count, no matter how low the percentile is.
+        assert_eq!(schedule.get_expensive_consumption(0.0).unwrap().len(), 30);
+        assert!(schedule.get_expensive_consumption(0.95).unwrap().is_empty());
+        assert!(schedule.get_expensive_consumption(1.5).is_err());
+        assert!(schedule.get_expensive_consumption(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_solver_reusable_after_callback_raises() {
+        Python::initialize();
+        Python::attach(|py| {
+            let context = example_context();
+            let locals = PyDict::new(py);
+            py.run(
+                c"def progress(iteration, temperature, cost, best_cost):\n    if iteration == 3:\n        raise KeyError('stop')\n",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let progress = locals.get_item("progress").unwrap().unwrap().unbind();
+
+            // Short annealing schedule to keep the test fast
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.7 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let Err(err) = solver.solve(py, Some(progress), Some(1)) else {
+                panic!("Solve should have been aborted");
+            };
+            assert!(err.is_instance_of::<PyKeyError>(py));
+            assert!(!solver.is_poisoned());
+
+            let (cost, schedule) = solver.solve(py, None, Some(1)).unwrap();
+            let rust_context = context.to_rust().unwrap();
+            let mut flow = SmartHomeFlowBuilder::new(
+                rust_context.get_generated_electricity(),
+                rust_context.get_electricity_price(),
+                rust_context.get_beyond_control_consumption(),
+                rust_context.get_first_timestep_fraction(),
+            )
+            .build();
+            for action in schedule.inner.constant_actions.values() {
+                flow.add_constant_consumption(action.clone());
+            }
+            assert_eq!(cost.to_nano_euro().round() as i64, flow.get_cost().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_cancel_from_other_thread_returns_best_schedule() {
+        Python::initialize();
+        Python::attach(|py| {
+            let token = Py::new(py, CancellationToken::new()).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("token", &token).unwrap();
+            py.run(
+                c"import threading\ntimer = threading.Timer(0.05, token.cancel)\ntimer.start()\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let context = example_context();
+            let options = PyDict::new(py);
+            // Far more iterations than finish in the time limit of the assertion below
+            options.set_item("iterations", 10_000_000).unwrap();
+            let start = std::time::Instant::now();
+            let result = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                Some(token.get()),
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap();
+            let elapsed = start.elapsed();
+            py.run(c"timer.join()\n", Some(&globals), None).unwrap();
+
+            assert!(result.cancelled);
+            assert!(elapsed.as_secs_f64() < 5.0, "Took {:?}", elapsed);
+            let (cost, schedule) = result.unpack(py);
+            assert_eq!(schedule.inner.constant_actions.len(), 2);
+            let rust_context = context.to_rust().unwrap();
+            let mut flow = SmartHomeFlowBuilder::new(
+                rust_context.get_generated_electricity(),
+                rust_context.get_electricity_price(),
+                rust_context.get_beyond_control_consumption(),
+                rust_context.get_first_timestep_fraction(),
+            )
+            .build();
+            for action in schedule.inner.constant_actions.values() {
+                flow.add_constant_consumption(action.clone());
+            }
+            assert_eq!(cost.to_nano_euro().round() as i64, flow.get_cost().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_other_threads_run_during_solve() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            py.run(
+                c"import threading, time\nstamps = []\nstop = threading.Event()\ndef work():\n    while not stop.is_set():\n        stamps.append(time.monotonic())\n        time.sleep(0.001)\nworker = threading.Thread(target=work)\nworker.start()\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let stamped = || -> usize {
+                py.eval(c"len(stamps)", Some(&globals), None)
+                    .unwrap()
+                    .extract()
+                    .unwrap()
+            };
+
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.97 },
+                ..Default::default()
+            };
+            let context = example_context();
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            // The GIL is held from counting the stamps until the solve releases it, so the
+            // worker can only add stamps in between if it runs during the solve.
+            let before = stamped();
+            solver.solve(py, None, Some(1)).unwrap();
+            let during = stamped() - before;
+            py.run(c"stop.set()\nworker.join()\n", Some(&globals), None)
+                .unwrap();
+            assert!(during > 0, "The worker made no progress during the solve");
+        });
+    }
+
+    #[test]
+    fn test_progress_called_every_nth_iteration() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            py.run(
+                c"calls = []\ndef progress(iteration, temperature, cost, best_cost):\n    calls.append((iteration, temperature))\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let progress = globals.get_item("progress").unwrap().unwrap().unbind();
+
+            // 0.7^17 * 40 < 0.1, so the run has 17 iterations
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.7 },
+                ..Default::default()
+            };
+            let context = example_context();
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            solver
+                .solve(py, Some(progress.clone_ref(py)), Some(5))
+                .unwrap();
+
+            let calls: Vec<(usize, f64)> = globals
+                .get_item("calls")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            let iterations: Vec<usize> = calls.iter().map(|(iteration, _)| *iteration).collect();
+            assert_eq!(iterations, vec![5, 10, 15]);
+            assert!(calls.windows(2).all(|pair| pair[1].1 < pair[0].1));
+            assert!(solver.solve(py, Some(progress), Some(0)).is_err());
+        });
+    }
+
+    #[test]
+    fn test_cooling_schedule_is_selected_by_name() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            py.run(
+                c"calls = []\ndef progress(iteration, temperature, cost, best_cost):\n    calls.append(temperature)\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let progress = globals.get_item("progress").unwrap().unwrap().unbind();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 4).unwrap();
+            options.set_item("initial_temperature", 9.0).unwrap();
+            options.set_item("min_temperature", 1.0).unwrap();
+            run_simulated_annealing(
+                py,
+                &example_context(),
+                Some(1),
+                Some(progress),
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap();
+            let temperatures: Vec<f64> = globals
+                .get_item("calls")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(temperatures, vec![9.0, 7.0, 5.0, 3.0]);
+
+            let invalid = |cooling: &str, key: &str, value: f64| {
+                let options = PyDict::new(py);
+                options.set_item(key, value).unwrap();
+                let err = annealing_config(cooling, Some(&options)).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                err.value(py).to_string()
+            };
+            assert_eq!(
+                invalid("geometric", "iterations", 10.0),
+                "Unknown option 'iterations' for cooling schedule 'geometric'"
+            );
+            assert_eq!(
+                invalid("geometric", "factor", 1.0),
+                "factor must be between 0 and 1"
+            );
+            assert!(invalid("lam", "min_temperature", 50.0).contains("min_temperature"));
+            assert!(
+                annealing_config("exponential", None)
+                    .unwrap_err()
+                    .value(py)
+                    .to_string()
+                    .starts_with("Unknown cooling schedule 'exponential'")
+            );
+            assert_eq!(
+                annealing_config("lam", None).unwrap().cooling,
+                Cooling::Lam {
+                    iterations: DEFAULT_COOLING_ITERATIONS
+                }
+            );
+        });
+    }
+
+    #[test]
+    fn test_deadline_action_is_finished_before_deadline() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            let deadline = context.start_time + TimeDelta::hours(3);
+            let action = Py::new(
+                py,
+                ConstantAction::with_deadline(
+                    deadline,
+                    TimeDelta::hours(1),
+                    Watt { value: 1000.0 },
+                    7,
+                    None,
+                    false,
+                ),
+            )
+            .unwrap();
+            context
+                .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                .unwrap();
+            let added = context.constant_actions.last().unwrap();
+            assert_eq!(added.get_start_from(), Time::at_default_resolution(0, 0));
+            assert_eq!(added.get_end_before(), Time::at_default_resolution(3, 0));
+
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.9 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(2)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let (_, schedule) = solver.solve(py, None, Some(1)).unwrap();
+            let assigned = schedule.get_constant_action(7).unwrap();
+            assert!(assigned.get_end_time().unwrap() <= deadline);
+
+            // Neither action fits before a deadline 30 minutes after the start.
+            let too_early = context.start_time + TimeDelta::minutes(30);
+            let action = Py::new(
+                py,
+                ConstantAction::with_deadline(
+                    too_early,
+                    TimeDelta::hours(1),
+                    Watt { value: 1000.0 },
+                    8,
+                    None,
+                    false,
+                ),
+            )
+            .unwrap();
+            assert!(
+                context
+                    .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                    .is_err()
+            );
+            let action = VariableAction::with_deadline(
+                too_early,
+                WattHour { value: 1000.0 },
+                MaxConsumption::Constant(Watt { value: 1000.0 }),
+                9,
+                None,
+                None,
+                0.1,
+                false,
+                false,
+            );
+            assert!(context.add_variable_action(py, &action).is_err());
+        });
+    }
+
+    #[test]
+    fn test_action_windows_are_validated_when_added() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            let start = context.start_time;
+            let horizon_end = start + TimeDelta::days(1);
+            let watt = |value| Watt { value };
+            let mut add_constant = |end_before, consumption, id| {
+                let action = ConstantAction::new(
+                    end_before - TimeDelta::hours(1),
+                    end_before,
+                    TimeDelta::hours(1),
+                    watt(consumption),
+                    id,
+                    None,
+                    false,
+                );
+                let action = Py::new(py, action).unwrap();
+                context.add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+            };
+            let rejected = |result: PyResult<()>, parts: &[&str]| {
+                let err = result.unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                let message = err.value(py).to_string();
+                for part in parts {
+                    assert!(message.contains(part), "{message}");
+                }
+            };
+
+            // Ending exactly at the horizon is fine, a timestep later is not
+            add_constant(horizon_end, 1000.0, 7).unwrap();
+            rejected(
+                add_constant(horizon_end + TimeDelta::minutes(1), 1000.0, 8),
+                &[
+                    "Constant action 8 end_before",
+                    "end of the optimization horizon",
+                ],
+            );
+            rejected(
+                add_constant(horizon_end + TimeDelta::hours(6), 1000.0, 8),
+                &["Constant action 8 start_from"],
+            );
+            rejected(
+                add_constant(start + TimeDelta::hours(2), -1.0, 9),
+                &["Constant action 9 consumption must not be negative"],
+            );
+            let profiled = ProfiledConstantAction::new(
+                start,
+                start + TimeDelta::hours(1),
+                vec![watt(1000.0), watt(-500.0)],
+                10,
+                false,
+            );
+            rejected(
+                context.add_constant_action(
+                    py,
+                    AnyConstantAction::Profiled(Py::new(py, profiled).unwrap().borrow(py)),
+                ),
+                &["Constant action 10 profile must not be negative"],
+            );
+            let too_long = ConstantAction::new(
+                start,
+                start + TimeDelta::hours(1),
+                TimeDelta::hours(2),
+                watt(1000.0),
+                11,
+                None,
+                false,
+            );
+            rejected(
+                context.add_constant_action(
+                    py,
+                    AnyConstantAction::Flat(Py::new(py, too_long).unwrap().borrow(py)),
+                ),
+                &["Constant action 11 does not fit"],
+            );
+
+            let variable = |end, total_consumption, max_consumption, id| VariableAction {
+                start: Some(start),
+                end,
+                total_consumption: WattHour {
+                    value: total_consumption,
+                },
+                max_consumption: MaxConsumption::Constant(watt(max_consumption)),
+                min_consumption: Watt::default(),
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: false,
+                absolute: false,
+                id,
+            };
+            context
+                .add_variable_action(py, &variable(horizon_end, 1000.0, 1000.0, 12))
+                .unwrap();
+            let caps = vec![watt(1000.0); 1500];
+            let per_timestep = VariableAction {
+                max_consumption: MaxConsumption::PerTimestep(caps),
+                ..variable(start + TimeDelta::minutes(1500), 1000.0, 0.0, 13)
+            };
+            rejected(
+                context.add_variable_action(py, &per_timestep),
+                &["Variable action 13 end", "end of the optimization horizon"],
+            );
+            rejected(
+                context.add_variable_action(py, &variable(horizon_end, -1.0, 1000.0, 14)),
+                &["Variable action 14 total_consumption must not be negative"],
+            );
+            rejected(
+                context.add_variable_action(py, &variable(horizon_end, 1000.0, -1.0, 15)),
+                &["Variable action 15 max_consumption must not be negative"],
+            );
+            rejected(
+                context.add_variable_action(py, &variable(start, 1000.0, 1000.0, 16)),
+                &["Variable action 16 must end after it starts"],
+            );
+        });
+    }
+
+    #[test]
+    fn test_actions_never_run_past_the_horizon() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+            let horizon_end = start + TimeDelta::days(1);
+            // Cheapest in the last hours, so that actions are pulled towards the end
+            let prices = (0..24).map(|hour| EuroPerWh {
+                value: if hour >= 22 { 0.0001 } else { 0.0004 },
+            });
+            let prices = PyList::new(py, prices).unwrap();
+            let price =
+                PrognosesProvider::from_values(prices.as_any(), start, Some(TimeDelta::hours(1)))
+                    .unwrap();
+            let context = |minutes: i64| {
+                OptimizerContext::new(
+                    py,
+                    start,
+                    &price,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(TimeDelta::minutes(minutes)),
+                )
+                .unwrap()
+            };
+            let add = |context: &mut OptimizerContext, end_before, id| {
+                let action = ConstantAction::new(
+                    start,
+                    end_before,
+                    TimeDelta::hours(1),
+                    Watt { value: 1_000.0 },
+                    id,
+                    None,
+                    false,
+                );
+                let action = Py::new(py, action).unwrap();
+                context.add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+            };
+
+            // The only placements of these would straddle the end of the horizon
+            for (minutes, end_before) in [
+                (15, horizon_end + TimeDelta::minutes(45)),
+                (60, horizon_end + TimeDelta::hours(1)),
+            ] {
+                let err = add(&mut context(minutes), end_before, 1).unwrap_err();
+                let message = err.value(py).to_string();
+                assert!(
+                    message.contains("end of the optimization horizon"),
+                    "{message}"
+                );
+            }
+            let mut hourly = context(60);
+            let globals = PyDict::new(py);
+            globals.set_item("Watt", py.get_type::<Watt>()).unwrap();
+            let caps = py
+                .eval(c"lambda curr, next: Watt(1000.0)", Some(&globals), None)
+                .unwrap()
+                .unbind();
+            let caps = PrognosesProvider::new(caps);
+            let variable = VariableAction {
+                start: Some(start),
+                end: horizon_end + TimeDelta::hours(2),
+                total_consumption: WattHour { value: 1_000.0 },
+                max_consumption: MaxConsumption::Varying(Py::new(py, caps).unwrap()),
+                min_consumption: Watt::default(),
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: false,
+                absolute: false,
+                id: 2,
+            };
+            let err = hourly.add_variable_action(py, &variable).unwrap_err();
+            assert!(
+                err.value(py).to_string().contains("Variable action 2 end"),
+                "{}",
+                err.value(py)
+            );
+
+            // Placed as late as the window allows, but never ending after end_before
+            let mut quarter_hourly = context(15);
+            add(&mut quarter_hourly, horizon_end - TimeDelta::minutes(15), 3).unwrap();
+            add(&mut quarter_hourly, horizon_end, 4).unwrap();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 300).unwrap();
+            let result = run_simulated_annealing(
+                py,
+                &quarter_hourly,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap();
+            let schedule = result.schedule.borrow(py);
+            let end = |id| {
+                schedule
+                    .get_constant_action(id)
+                    .unwrap()
+                    .get_end_time()
+                    .unwrap()
+            };
+            assert!(end(3) <= horizon_end - TimeDelta::minutes(15));
+            assert!(end(4) <= horizon_end);
+            assert!(end(3) >= start + TimeDelta::hours(23), "{}", end(3));
+        });
+    }
+
+    #[test]
+    fn test_duplicate_asset_ids_are_rejected() {
+        Python::initialize();
+        Python::attach(|py| {
+            let kinds = ["battery", "constant action", "variable action"];
+            let add = |context: &mut OptimizerContext, kind: &str, id: u32| {
+                let start = context.start_time;
+                match kind {
+                    "battery" => {
+                        let battery = Battery::new(
+                            WattHour { value: 1000.0 },
+                            Watt { value: 500.0 },
+                            Watt { value: 500.0 },
+                            WattHour::default(),
+                            id,
+                            1.0,
+                            true,
+                            WattHour::default(),
+                            None,
+                            0.0,
+                            EuroPerWh::default(),
+                        )
+                        .unwrap();
+                        context.add_battery(&battery)
+                    }
+                    "constant action" => {
+                        let action = ConstantAction::new(
+                            start,
+                            start + TimeDelta::hours(2),
+                            TimeDelta::hours(1),
+                            Watt { value: 1000.0 },
+                            id,
+                            None,
+                            false,
+                        );
+                        let action = Py::new(py, action).unwrap();
+                        context.add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                    }
+                    _ => {
+                        let action = VariableAction {
+                            start: Some(start),
+                            end: start + TimeDelta::hours(2),
+                            total_consumption: WattHour { value: 100.0 },
+                            max_consumption: MaxConsumption::Constant(Watt { value: 1000.0 }),
+                            min_consumption: Watt::default(),
+                            preference: None,
+                            preference_limit: 0.1,
+                            contiguous: false,
+                            absolute: false,
+                            id,
+                        };
+                        context.add_variable_action(py, &action)
+                    }
+                }
+            };
+
+            for first in kinds {
+                for second in kinds {
+                    let mut context = example_context();
+                    add(&mut context, first, 10).unwrap();
+                    let err = add(&mut context, second, 10).unwrap_err();
+                    assert!(err.is_instance_of::<PyValueError>(py));
+                    assert_eq!(
+                        err.value(py).to_string(),
+                        format!("Can't add {second} 10, the id 10 is already used by a {first}")
+                    );
+                    // The rejected asset wasn't added
+                    let count = context.batteries.len()
+                        + context.constant_actions.len()
+                        + context.variable_actions.len();
+                    assert_eq!(count, 3);
+                }
+            }
+
+            // Distinct ids of all kinds
+            let mut context = example_context();
+            for (id, kind) in (10..).zip(kinds) {
+                add(&mut context, kind, id).unwrap();
+            }
+            context.to_rust().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_assigned_constant_action_returns_its_inputs() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.constant_actions = vec![];
+            let start = context.start_time;
+            let window = (start + TimeDelta::hours(1), start + TimeDelta::hours(4));
+            let action = Py::new(
+                py,
+                ConstantAction::new(
+                    window.0,
+                    window.1,
+                    TimeDelta::minutes(90),
+                    Watt { value: 1500.0 },
+                    7,
+                    None,
+                    false,
+                ),
+            )
+            .unwrap();
+            context
+                .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                .unwrap();
+            let profiled = Py::new(
+                py,
+                ProfiledConstantAction::new(
+                    window.0,
+                    window.1,
+                    vec![Watt { value: 600.0 }, Watt { value: 1200.0 }],
+                    8,
+                    false,
+                ),
+            )
+            .unwrap();
+            context
+                .add_constant_action(py, AnyConstantAction::Profiled(profiled.borrow(py)))
+                .unwrap();
+
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.9 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(3)),
+                grid: TimeGrid::new(start, TimestepConfig::default()),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let (_, schedule) = solver.solve(py, None, Some(1)).unwrap();
+            let assigned = schedule.get_constant_action(7).unwrap();
+            assert_eq!(assigned.get_id(), 7);
+            assert_eq!(assigned.get_consumption().unwrap().value, 1500.0);
+            assert_eq!(assigned.get_duration(), TimeDelta::minutes(90));
+            assert_eq!(assigned.get_window().unwrap(), window);
+            let start_time = assigned.get_start_time().unwrap();
+            assert_eq!(
+                assigned.get_end_time().unwrap() - start_time,
+                TimeDelta::minutes(90)
+            );
+            assert_eq!(
+                assigned.__repr__().unwrap(),
+                format!("AssignedConstantAction(id=7, start={start_time}, power=1500.00 W)")
+            );
+
+            let assigned = schedule.get_constant_action(8).unwrap();
+            assert_eq!(assigned.get_duration(), TimeDelta::minutes(2));
+            assert_eq!(assigned.get_window().unwrap(), window);
+            let err = assigned.get_consumption().unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(assigned.__repr__().unwrap().ends_with("power=varying)"));
+        });
+    }
+
+    #[test]
+    fn test_consumption_profile_sums_to_total() {
+        let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+        let action = VariableAction {
+            start: Some(start_time + TimeDelta::hours(1)),
+            end: start_time + TimeDelta::hours(3),
+            total_consumption: WattHour { value: 123.4567 },
+            max_consumption: MaxConsumption::Constant(Watt { value: 500.0 }),
+            min_consumption: Watt::default(),
+            preference: None,
+            preference_limit: 0.1,
+            contiguous: false,
+            absolute: false,
+            id: 4,
+        };
+        Python::initialize();
+        let action = Python::attach(|py| {
+            Rc::new(
+                action
+                    .to_rust(py, TimeGrid::new(start_time, TimestepConfig::default()))
+                    .unwrap(),
+            )
+        });
+        let context = example_context().to_rust().unwrap();
+        let schedule = SmartHomeFlowBuilder::new(
+            context.get_generated_electricity(),
+            context.get_electricity_price(),
+            context.get_beyond_control_consumption(),
+            context.get_first_timestep_fraction(),
+        )
+        .add_action(&action)
+        .build()
+        .get_schedule()
+        .unwrap();
+        let assigned = AssignedVariableAction {
+            inner: schedule.get_variable_action(4).unwrap().clone(),
+            grid: TimeGrid::new(start_time, TimestepConfig::default()),
+        };
+
+        let profile = assigned.get_consumption_profile().unwrap();
+        assert_eq!(profile.len(), 120);
+        assert_eq!(profile[0].0, start_time + TimeDelta::hours(1));
+        assert_eq!(profile[119].0, start_time + TimeDelta::minutes(179));
+        let profile_total: f64 = profile
+            .iter()
+            .map(|(_, power)| (power * timestep_duration(TimestepConfig::default())).value)
+            .sum();
+        // The total is truncated to whole milli-Wh when converted to internal units.
+        assert!((profile_total - 123.4567).abs() <= 1e-3);
+        assert!((assigned.get_total_assigned().value - profile_total).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unserved_consumption_raises_infeasible_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.constant_actions.clear();
+            // At most 100 mWh per minute for an hour can't serve 10 Wh
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(2, 0),
+                10_000,
+                100,
+                4,
+            ))];
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let Err(annealing_err) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            ) else {
+                panic!("The action can't be served");
+            };
+            let Err(exact_err) = run_exact(py, &context) else {
+                panic!("The action can't be served");
+            };
+            for err in [annealing_err, exact_err] {
+                assert!(err.is_instance_of::<InfeasibleError>(py));
+                let message = err.value(py).to_string();
+                assert!(
+                    message.contains("4.00 Wh of variable action 4"),
+                    "{message}"
+                );
+                assert!(message.contains("max_consumption"), "{message}");
+                let value = err.value(py);
+                let unserved: Vec<(DateTime<Utc>, Watt)> =
+                    value.getattr("unserved").unwrap().extract().unwrap();
+                assert!(unserved.is_empty());
+                let actions: HashMap<u32, WattHour> = value
+                    .getattr("unserved_actions")
+                    .unwrap()
+                    .extract()
+                    .unwrap();
+                assert_eq!(actions.len(), 1);
+                assert!((actions[&4].value - 4.0).abs() < 1e-9);
+                let targets: HashMap<u32, WattHour> =
+                    value.getattr("unmet_targets").unwrap().extract().unwrap();
+                assert!(targets.is_empty());
+                let binding: Vec<(String, Option<u32>, Option<DateTime<Utc>>)> =
+                    value.getattr("binding").unwrap().extract().unwrap();
+                assert_eq!(
+                    binding,
+                    vec![("max_consumption".to_string(), Some(4), None)]
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_isolate_errors_schedules_the_other_assets() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.batteries = vec![Rc::new(RustBattery::new(5_000, 1_000, 1_000, 0, 1.0, 3))];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(4, 0),
+                3_000,
+                100,
+                4,
+            ))];
+            // Action 2 no longer fits its window, which also breaks its precedence
+            let mut broken = (*context.constant_actions[1]).clone();
+            broken.end_before = Time::at_default_resolution(0, 20);
+            context.constant_actions[1] = Rc::new(broken);
+            context.precedences = vec![(2, 1)];
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let solve = |isolate_errors| {
+                run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    Some(isolate_errors),
+                    None,
+                    None,
+                    Some(&options),
+                )
+            };
+
+            let Err(err) = solve(false) else {
+                panic!("The broken action should have failed the run");
+            };
+            assert!(err.is_instance_of::<InfeasibleError>(py));
+
+            let result = solve(true).unwrap();
+            let excluded = result.excluded.bind(py);
+            assert_eq!(excluded.len(), 1);
+            let error = excluded.get_item(2).unwrap().unwrap();
+            assert!(error.is_instance_of::<InfeasibleError>());
+            assert!(error.to_string().contains("Action 2"), "{error}");
+            let schedule = result.schedule.borrow(py);
+            assert!(schedule.inner.get_constant_action(1).is_some());
+            assert!(schedule.inner.get_constant_action(2).is_none());
+            assert!(schedule.inner.get_battery(3).is_some());
+            let assigned = schedule.inner.get_variable_action(4).unwrap();
+            assert_eq!(assigned.get_total_assigned(), 3_000);
+        });
+    }
+
+    #[test]
+    fn test_past_constant_action_carries_over_its_remaining_minutes() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Planned yesterday evening to run from 23:15 until 01:15
+            let yesterday = Utc.with_ymd_and_hms(2025, 3, 1, 20, 0, 0).unwrap();
+            let action = Rc::new(RustConstantAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(12, 0),
+                Time::at_default_resolution(2, 0),
+                16_667,
+                1,
+            ));
+            let assigned = AssignedConstantAction {
+                inner: RustAssignedConstantAction::new(action, Time::at_default_resolution(3, 15)),
+                grid: TimeGrid::new(yesterday, TimestepConfig::default()),
+            };
+
+            for (start, remaining) in [((0, 30, 0), 45), ((0, 30, 30), 45), ((1, 15, 0), 0)] {
+                let mut context = example_context();
+                let (h, m, s) = start;
+                context.start_time = Utc.with_ymd_and_hms(2025, 3, 2, h, m, s).unwrap();
+                context.add_past_constant_action(py, &assigned).unwrap();
+                let carried: Vec<u32> = (0..STEPS_PER_DAY)
+                    .filter(|&t| {
+                        let step = Time::from_timestep(t);
+                        *context.beyond_control_consumption.get(step).unwrap() != 0
+                    })
+                    .collect();
+                assert_eq!(carried, (0..remaining).collect::<Vec<_>>(), "{start:?}");
+                assert!(carried.iter().all(|&t| {
+                    *context
+                        .beyond_control_consumption
+                        .get(Time::from_timestep(t))
+                        .unwrap()
+                        == 16_667
+                }));
+            }
+        });
+    }
+
+    #[test]
+    fn test_past_variable_action_schedules_the_rest_of_a_session() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Charging 20 Wh at up to 6 W from 22:00 until 08:00
+            let yesterday = Utc.with_ymd_and_hms(2025, 3, 1, 20, 0, 0).unwrap();
+            let action = Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(12, 0),
+                20_000,
+                100,
+                5,
+            ));
+            let assigned = AssignedVariableAction {
+                inner: RustAssignedVariableAction::new(action, vec![0; 600]),
+                grid: TimeGrid::new(yesterday, TimestepConfig::default()),
+            };
+            let today = Utc.with_ymd_and_hms(2025, 3, 2, 6, 0, 0).unwrap();
+            let fresh = || {
+                let mut context = example_context();
+                context.start_time = today;
+                context
+            };
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+
+            // Half finished and almost finished sessions
+            for (delivered, remaining) in [(10.0, 10_000), (19.5, 500)] {
+                let mut context = fresh();
+                context
+                    .add_past_variable_action(py, &assigned, WattHour { value: delivered })
+                    .unwrap();
+                let rest = context.variable_actions.last().unwrap();
+                assert_eq!(rest.get_id(), 5);
+                assert_eq!(
+                    (rest.get_start(), rest.get_end()),
+                    (
+                        Time::at_default_resolution(0, 0),
+                        Time::at_default_resolution(2, 0)
+                    )
+                );
+                assert_eq!(rest.get_total_consumption(), remaining);
+                assert_eq!(rest.get_max_consumption(), 100);
+                let (_, schedule) = run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py);
+                let scheduled = schedule.inner.get_variable_action(5).unwrap();
+                assert_eq!(scheduled.get_total_assigned(), remaining);
+            }
+
+            // Finished sessions add nothing
+            let mut context = fresh();
+            context
+                .add_past_variable_action(py, &assigned, WattHour { value: 20.0 })
+                .unwrap();
+            assert!(context.variable_actions.is_empty());
+
+            // 15 Wh are left, but two hours at 6 W only give 12 Wh
+            let mut context = fresh();
+            let err = context
+                .add_past_variable_action(py, &assigned, WattHour { value: 5.0 })
+                .unwrap_err();
+            assert!(err.is_instance_of::<InfeasibleError>(py));
+            assert!(context.variable_actions.is_empty());
+            let err = context
+                .add_past_variable_action(py, &assigned, WattHour { value: -1.0 })
+                .unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_warm_start_resumes_from_the_previous_schedule() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            py.run(
+                c"calls = []\ndef progress(iteration, temperature, cost, best_cost):\n    calls.append((iteration, best_cost.to_cent()))\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let progress = globals.get_item("progress").unwrap().unwrap().unbind();
+            let calls = || {
+                let calls = globals.get_item("calls").unwrap().unwrap();
+                let recorded: Vec<(usize, f64)> = calls.extract().unwrap();
+                calls.call_method0("clear").unwrap();
+                recorded
+            };
+            // Iterations until the best cost of the run was first seen
+            let converged_after = |calls: &[(usize, f64)]| {
+                let best = calls.last().unwrap().1;
+                calls.iter().find(|(_, cost)| *cost == best).unwrap().0
+            };
+            let context = example_context();
+            let solve = |seed, iterations, initial_schedule| {
+                let options = PyDict::new(py);
+                options.set_item("iterations", iterations).unwrap();
+                run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(seed),
+                    Some(progress.clone_ref(py)),
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    initial_schedule,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+            };
+
+            let previous = solve(1, 1_000, None);
+            calls();
+            let cold = solve(2, 200, None);
+            let cold_calls = calls();
+            let warm = solve(2, 200, Some(previous.schedule.bind(py).borrow()));
+            let warm_calls = calls();
+            assert_eq!(warm.cost.value, previous.cost.value);
+            assert!(cold.cost.value >= previous.cost.value);
+            assert_eq!(warm_calls[0].1, previous.cost.value * 100.0);
+            assert_eq!(converged_after(&warm_calls), 1);
+            assert!(converged_after(&cold_calls) > 10);
+        });
+    }
+
+    #[test]
+    fn test_exclusion_group_runs_actions_apart() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            let start = context.start_time;
+            // Three hour-long actions on one circuit, in a window just long enough for all
+            let window_end = start + TimeDelta::minutes(190);
+            let add_action = |context: &mut OptimizerContext, id| {
+                let action = Py::new(
+                    py,
+                    ConstantAction::new(
+                        start,
+                        window_end,
+                        TimeDelta::hours(1),
+                        Watt { value: 3_000.0 },
+                        id,
+                        None,
+                        false,
+                    ),
+                )
+                .unwrap();
+                context
+                    .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                    .unwrap();
+            };
+            context.constant_actions = vec![];
+            for id in 1..=3 {
+                add_action(&mut context, id);
+            }
+            context.add_exclusion_group(vec![1, 2, 3]).unwrap();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            for seed in 0..5 {
+                let (_, schedule) = run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(seed),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py);
+                let mut runs: Vec<_> = (1..=3)
+                    .map(|id| {
+                        let assigned = schedule.inner.get_constant_action(id).unwrap();
+                        (assigned.get_start_time(), assigned.get_end_time())
+                    })
+                    .collect();
+                runs.sort();
+                assert!(
+                    runs.windows(2).all(|pair| pair[0].1 <= pair[1].0),
+                    "{runs:?}"
+                );
+            }
+
+            let err = context.add_exclusion_group(vec![1, 9]).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(
+                err.value(py)
+                    .to_string()
+                    .contains("No constant action with id 9")
+            );
+            add_action(&mut context, 4);
+            let err = context.add_exclusion_group(vec![1, 2, 3, 4]).unwrap_err();
+            assert!(err.is_instance_of::<InfeasibleError>(py));
+            // Rejected groups aren't kept
+            assert_eq!(context.exclusion_groups, vec![vec![1, 2, 3]]);
+        });
+    }
+
+    #[test]
+    fn test_precedence_orders_overlapping_actions() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            // Both actions share their window, action 2 has to run first
+            context.add_precedence(2, 1).unwrap();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            for seed in 0..5 {
+                let (_, schedule) = run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(seed),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py);
+                let first = schedule.inner.get_constant_action(2).unwrap();
+                let second = schedule.inner.get_constant_action(1).unwrap();
+                assert!(first.get_end_time() <= second.get_start_time());
+            }
+
+            let message = |result: PyResult<()>| {
+                let err = result.unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                err.value(py).to_string()
+            };
+            assert!(message(context.add_precedence(2, 9)).contains("No constant action with id 9"));
+            assert!(message(context.add_precedence(1, 2)).contains("cycle"));
+            assert!(message(context.add_precedence(1, 1)).contains("cycle"));
+            // Rejected precedences aren't kept
+            assert_eq!(context.precedences, vec![(2, 1)]);
+        });
+    }
+
+    #[test]
+    fn test_recurring_action_keeps_its_blocks_apart() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            let start = context.start_time;
+            let recurring = |count, id| {
+                RecurringConstantAction::new(
+                    start,
+                    start + TimeDelta::hours(12),
+                    count,
+                    TimeDelta::hours(1),
+                    TimeDelta::hours(2),
+                    Watt { value: 500.0 },
+                    id,
+                )
+            };
+            context
+                .add_recurring_constant_action(py, &recurring(3, 5))
+                .unwrap();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 300).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            let blocks = schedule.get_recurring_action(5);
+            assert_eq!(blocks.len(), 3);
+            for pair in blocks.windows(2) {
+                let end = pair[0].get_end_time().unwrap();
+                let next = pair[1].get_start_time().unwrap();
+                assert!(next - end >= TimeDelta::hours(2), "{end} {next}");
+            }
+            assert!(schedule.verify(&context).unwrap().is_empty());
+            assert!(schedule.get_recurring_action(6).is_empty());
+
+            let rejected = |result: PyResult<()>| {
+                assert!(result.unwrap_err().is_instance_of::<PyValueError>(py));
+            };
+            rejected(context.add_recurring_constant_action(py, &recurring(0, 6)));
+            // The ids of the blocks are taken already
+            rejected(context.add_recurring_constant_action(py, &recurring(2, 5)));
+            // Five blocks and their gaps need 13 hours
+            rejected(context.add_recurring_constant_action(py, &recurring(5, 6)));
+            // Rejected actions leave nothing behind
+            assert_eq!(context.constant_actions.len(), 5);
+            assert_eq!(context.min_gaps, vec![(5000, 5001, 120), (5001, 5002, 120)]);
+        });
+    }
+
+    #[test]
+    fn test_disabled_assets_are_left_out_when_solving() {
+        Python::initialize();
+        Python::attach(|py| {
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let solve = |context: &OptimizerContext| {
+                run_simulated_annealing(
+                    py,
+                    context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py)
+                .1
+            };
+            let hash = |context: &OptimizerContext| solve(context).content_hash().unwrap();
+            let mut context = example_context();
+            context.set_enabled(2, false).unwrap();
+            assert!(!context.is_enabled(2));
+            assert!(context.is_enabled(1));
+            assert_eq!(context.list_disabled(), vec![2]);
+            assert!(solve(&context).inner.get_constant_action(2).is_none());
+
+            let mut without = example_context();
+            without
+                .constant_actions
+                .retain(|action| action.get_id() != 2);
+            assert_eq!(hash(&context), hash(&without));
+
+            context.set_enabled(2, true).unwrap();
+            assert!(context.list_disabled().is_empty());
+            assert_eq!(hash(&context), hash(&example_context()));
+
+            let err = context.set_enabled(9, false).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_min_consumption_is_kept_by_schedule() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            // A little free generation makes the flow dribble into every timestep
+            context.generated_electricity = Prognoses::from_closure(|_| 800);
+            let start = context.start_time;
+            let charger = |min: f64, max: f64| VariableAction {
+                start: Some(start),
+                end: start + TimeDelta::hours(4),
+                total_consumption: WattHour { value: 7_400.0 },
+                max_consumption: MaxConsumption::Constant(Watt { value: max }),
+                min_consumption: Watt { value: min },
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: false,
+                absolute: false,
+                id: 5,
+            };
+            let Err(err) = context.add_variable_action(py, &charger(1_400.0, 1_000.0)) else {
+                panic!("A minimum above the maximum should be rejected");
+            };
+            assert!(
+                err.value(py)
+                    .to_string()
+                    .contains("timesteps of at least 1400 W")
+            );
+            assert!(
+                context
+                    .add_variable_action(py, &charger(-1.0, 11_000.0))
+                    .is_err()
+            );
+            context
+                .add_variable_action(py, &charger(1_400.0, 11_000.0))
+                .unwrap();
+
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            let assigned = schedule.inner.get_variable_action(5).unwrap();
+            let min = Watt { value: 1_400.0 }
+                .to_energy_per_step(timestep_duration(TimestepConfig::default()))
+                as i64;
+            for (time, consumption) in assigned.iter_consumption() {
+                assert!(
+                    consumption == 0 || consumption >= min,
+                    "{} at {:?}",
+                    consumption,
+                    time
+                );
+            }
+            assert_eq!(assigned.get_total_assigned(), 7_400_000);
+            assert!(schedule.verify(&context).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_battery_curves_match_single_queries() {
+        Python::initialize();
+        Python::attach(|py| {
+            import_package(py);
+            let mut context = example_context();
+            context.batteries = vec![Rc::new(RustBattery::new(5000, 1000, 500, 500, 0.9, 4))];
+            let globals = PyDict::new(py);
+            globals
+                .set_item("context", Py::new(py, context).unwrap())
+                .unwrap();
+            py.run(
+                c"
+from datetime import timedelta
+from electricity_price_optimizer_py import run_simulated_annealing
+
+_, schedule = run_simulated_annealing(context, seed=1, cooling='linear', iterations=20)
+battery = schedule.get_battery(4)
+levels = battery.get_charge_curve()
+speeds = battery.get_speed_curve()
+# One call returns plain lists of tuples, nothing is fetched lazily
+assert type(levels) is list and all(type(entry) is tuple for entry in levels)
+assert type(speeds) is list and all(type(entry) is tuple for entry in speeds)
+assert len(levels) == 1441 and len(speeds) == 1440
+assert levels[-1][0] - levels[0][0] == timedelta(days=1)
+assert [time for time, _ in speeds] == [time for time, _ in levels[:-1]]
+for time, level in levels:
+    assert level.get_value() == battery.get_charge_level(time).get_value(), time
+for time, speed in speeds:
+    assert speed.get_value() == battery.get_charge_speed(time).get_value(), time
+assert any(speed.get_value() != 0 for _, speed in speeds)
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_contiguous_action_has_no_gaps() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            // Cheap every other 10 minutes, so a free action would consume in fragments
+            context.electricity_price =
+                Prognoses::from_closure(|t| if t.to_timestep() / 10 % 2 == 0 { 1 } else { 9 });
+            let start = context.start_time;
+            let boost = |max: f64| VariableAction {
+                start: Some(start),
+                end: start + TimeDelta::hours(4),
+                total_consumption: WattHour { value: 7_400.0 },
+                max_consumption: MaxConsumption::Constant(Watt { value: max }),
+                min_consumption: Watt::default(),
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: true,
+                absolute: false,
+                id: 5,
+            };
+            let Err(err) = context.add_variable_action(py, &boost(1_000.0)) else {
+                panic!("A block longer than the window should be rejected");
+            };
+            assert!(err.is_instance_of::<InfeasibleError>(py));
+            context.add_variable_action(py, &boost(11_000.0)).unwrap();
+
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            let consumption: Vec<i64> = schedule
+                .inner
+                .get_variable_action(5)
+                .unwrap()
+                .iter_consumption()
+                .map(|(_, consumption)| consumption)
+                .collect();
+            let first = consumption.iter().position(|&c| c > 0).unwrap();
+            let last = consumption.iter().rposition(|&c| c > 0).unwrap();
+            assert!(consumption[first..=last].iter().all(|&c| c > 0));
+            assert_eq!(consumption.iter().sum::<i64>(), 7_400_000);
+            assert!(schedule.inner.get_constant_action(5).is_none());
+            assert!(schedule.verify(&context).unwrap().is_empty());
+
+            context.constant_actions = vec![];
+            let Err(err) = run_exact(py, &context) else {
+                panic!("Contiguous actions should be rejected");
+            };
+            assert!(err.value(py).to_string().contains("ids 5"));
+        });
+    }
+
+    #[test]
+    fn test_time_varying_max_consumption_is_respected() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("Watt", py.get_type::<Watt>()).unwrap();
+            // Another circuit shares the breaker from 7:00 to 9:00
+            let cap = py
+                .eval(
+                    c"lambda curr, next: Watt(3000.0) if 7 <= curr.hour < 9 else Watt(11000.0)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let mut context = example_context();
+            let morning = Time::at_default_resolution(1, 0)..Time::at_default_resolution(3, 0);
+            context.electricity_price =
+                Prognoses::from_closure(|t| if morning.contains(&t) { 1 } else { 10 });
+            let charger = |total: f64| VariableAction {
+                start: Some(context.start_time),
+                end: context.start_time + TimeDelta::hours(4),
+                total_consumption: WattHour { value: total },
+                max_consumption: MaxConsumption::Varying(
+                    Py::new(py, PrognosesProvider::new(cap.clone_ref(py))).unwrap(),
+                ),
+                min_consumption: Watt::default(),
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: false,
+                absolute: false,
+                id: 5,
+            };
+
+            // 11 kWh + 6 kWh + 11 kWh at most, minus rounding to whole milli-Wh per timestep
+            let Err(err) = charger(30_000.0).to_rust(py, context.grid()) else {
+                panic!("Unreachable total should be rejected");
+            };
+            assert!(
+                err.value(py).to_string().contains("it is 2000.0"),
+                "{}",
+                err.value(py)
+            );
+
+            context.constant_actions = vec![];
+            context.add_variable_action(py, &charger(20_000.0)).unwrap();
+            let (_, schedule) = run_exact(py, &context).unwrap();
+            let assigned = AssignedVariableAction {
+                inner: schedule.inner.get_variable_action(5).unwrap().clone(),
+                grid: TimeGrid::new(context.start_time, TimestepConfig::default()),
+            };
+            for (time, power) in assigned.get_consumption_profile().unwrap() {
+                let limit = if (7..9).contains(&time.hour()) {
+                    3000.0
+                } else {
+                    11_000.0
+                };
+                assert!(power.value <= limit + 1e-6, "{power:?} at {time}");
+                if (7..9).contains(&time.hour()) {
+                    // The morning is cheapest, so the cap is used fully
+                    assert!((power.value - limit).abs() < 1e-6, "{power:?} at {time}");
+                }
+            }
+            assert!((assigned.get_total_assigned().value - 20_000.0).abs() < 1e-6);
+        });
+    }
+
+    #[test]
+    fn test_instrumentation_reports_start_and_end_of_solves() {
+        Python::initialize();
+        Python::attach(|py| {
+            // The callback is global and other tests solve concurrently, so only the events
+            // of this context, with its own start time, are looked at
+            let mut context = example_context();
+            context.start_time = Utc.with_ymd_and_hms(2031, 5, 4, 6, 0, 0).unwrap();
+            let fingerprint = instrumentation::fingerprint(&context).unwrap();
+            let events = PyList::empty(py);
+            let record = events.getattr("append").unwrap();
+            set_instrumentation(Some(record.unbind()));
+            let ours = || -> Vec<Bound<'_, PyDict>> {
+                let ours = events
+                    .iter()
+                    .map(|event| event.cast_into::<PyDict>().unwrap())
+                    .filter(|event| {
+                        event
+                            .get_item("fingerprint")
+                            .unwrap()
+                            .unwrap()
+                            .extract::<Option<String>>()
+                            .unwrap()
+                            == Some(fingerprint.clone())
+                    })
+                    .collect();
+                events.call_method0("clear").unwrap();
+                ours
+            };
+            fn get<'py>(event: &Bound<'py, PyDict>, key: &str) -> Bound<'py, PyAny> {
+                event.get_item(key).unwrap().unwrap()
+            }
+
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (cost, _) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            let run = ours();
+            assert_eq!(run.len(), 2);
+            let (start, end) = (&run[0], &run[1]);
+            for event in [start, end] {
+                assert_eq!(
+                    get(event, "solver").extract::<String>().unwrap(),
+                    "simulated_annealing"
+                );
+                assert_eq!(
+                    get(event, "start_time").extract::<DateTime<Utc>>().unwrap(),
+                    context.start_time
+                );
+            }
+            assert_eq!(get(start, "event").extract::<String>().unwrap(), "start");
+            assert_eq!(get(end, "event").extract::<String>().unwrap(), "end");
+            assert_eq!(
+                get(end, "cost").extract::<Euro>().unwrap().value,
+                cost.value
+            );
+            assert_eq!(get(end, "iterations").extract::<usize>().unwrap(), 20);
+            assert!(!get(end, "cancelled").extract::<bool>().unwrap());
+            assert!(get(end, "error").is_none());
+            let seconds = |key: &str| get(end, key).extract::<f64>().unwrap();
+            let duration = seconds("duration_seconds");
+            assert!(seconds("materialize_seconds") <= duration);
+            assert!(seconds("flow_seconds") <= seconds("annealing_seconds"));
+            assert!(seconds("annealing_seconds") <= duration);
+
+            // run_exact can't place the constant actions of the context
+            let Err(err) = run_exact(py, &context) else {
+                panic!("run_exact placed constant actions");
+            };
+            let run = ours();
+            assert_eq!(run.len(), 2);
+            let (start, end) = (&run[0], &run[1]);
+            assert_eq!(get(start, "event").extract::<String>().unwrap(), "start");
+            assert_eq!(get(end, "solver").extract::<String>().unwrap(), "exact");
+            assert_eq!(
+                get(end, "error").extract::<String>().unwrap(),
+                err.to_string()
+            );
+            assert!(get(end, "materialize_seconds").extract::<f64>().unwrap() >= 0.0);
+            for key in ["flow_seconds", "annealing_seconds", "iterations", "cost"] {
+                assert!(get(end, key).is_none(), "{key} of a failed run");
+            }
+
+            // A failing callback doesn't abort the solve
+            py.run(
+                c"
+import sys
+unraisable = []
+sys.unraisablehook = unraisable.append
+",
+                None,
+                None,
+            )
+            .unwrap();
+            set_instrumentation(Some(
+                py.eval(c"lambda event: 1 / 0", None, None)
+                    .unwrap()
+                    .unbind(),
+            ));
+            let result = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            );
+            set_instrumentation(None);
+            py.run(c"sys.unraisablehook = sys.__unraisablehook__", None, None)
+                .unwrap();
+            assert_eq!(result.unwrap().cost.value, cost.value);
+            let unraisable = py.eval(c"unraisable", None, None).unwrap();
+            assert!(unraisable.len().unwrap() >= 2);
+            let failure = unraisable
+                .get_item(0)
+                .unwrap()
+                .getattr("exc_value")
+                .unwrap();
+            assert!(failure.is_instance_of::<pyo3::exceptions::PyZeroDivisionError>());
+        });
+    }
+
+    /// Make the module importable under its package name, as pickle looks classes up there.
+    fn import_package(py: Python<'_>) {
+        let modules = py.import("sys").unwrap().getattr("modules").unwrap();
+        if modules.contains("electricity_price_optimizer_py").unwrap() {
+            return;
+        }
+        let module = pyo3::wrap_pymodule!(electricity_price_optimizer_py)(py);
+        let units = module.getattr(py, "units").unwrap();
+        modules
+            .set_item("electricity_price_optimizer_py", module)
+            .unwrap();
+        modules
+            .set_item("electricity_price_optimizer_py.units", units)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_definitions_survive_pickling() {
+        Python::initialize();
+        Python::attach(|py| {
+            import_package(py);
+            let globals = PyDict::new(py);
+            py.run(
+                c"
+import pickle
+from datetime import datetime, timedelta, timezone
+from electricity_price_optimizer_py import Battery, ConstantAction, VariableAction
+from electricity_price_optimizer_py.units import Euro, EuroPerWh, Watt, WattHour
+
+start = datetime(2025, 3, 1, 6, tzinfo=timezone.utc)
+originals = [
+    Watt(1500.0),
+    WattHour(7400.0),
+    Euro(-2.5),
+    EuroPerWh(0.0003),
+    ConstantAction(start, start + timedelta(hours=6), timedelta(minutes=30), Watt(2000.0), 3),
+    ConstantAction.with_deadline(start + timedelta(hours=5), timedelta(hours=1), Watt(1000.0), 4),
+    VariableAction(
+        start + timedelta(hours=1),
+        start + timedelta(hours=8),
+        WattHour(5000.0),
+        [Watt(2000.0)] * 420,
+        5,
+        min_consumption=Watt(500.0),
+        preference_limit=0.2,
+    ),
+    VariableAction.with_deadline(start + timedelta(hours=10), WattHour(3000.0), Watt(3000.0), 6),
+    Battery(
+        WattHour(10000.0),
+        Watt(3000.0),
+        Watt(4000.0),
+        WattHour(2000.0),
+        7,
+        efficiency=0.9,
+        grid_charging_allowed=False,
+        min_charge=WattHour(1000.0),
+        final_charge_target=WattHour(1500.0),
+        self_discharge_per_hour=0.01,
+    ),
+]
+copies = [pickle.loads(pickle.dumps(original)) for original in originals]
+assert [type(copy) for copy in copies] == [type(original) for original in originals]
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let originals = globals.get_item("originals").unwrap().unwrap();
+            let copies = globals.get_item("copies").unwrap().unwrap();
+            let both = |index: usize| {
+                (
+                    originals.get_item(index).unwrap(),
+                    copies.get_item(index).unwrap(),
+                )
+            };
+            let (watt, watt_copy) = both(0);
+            assert_eq!(
+                watt_copy.extract::<Watt>().unwrap().value,
+                watt.extract::<Watt>().unwrap().value
+            );
+            let (energy, energy_copy) = both(1);
+            assert_eq!(
+                energy_copy.extract::<WattHour>().unwrap().value,
+                energy.extract::<WattHour>().unwrap().value
+            );
+            let (euro, euro_copy) = both(2);
+            assert_eq!(
+                euro_copy.extract::<Euro>().unwrap().value,
+                euro.extract::<Euro>().unwrap().value
+            );
+            let (price, price_copy) = both(3);
+            assert_eq!(
+                price_copy.extract::<EuroPerWh>().unwrap().value,
+                price.extract::<EuroPerWh>().unwrap().value
+            );
+            for index in [4, 5] {
+                let (action, copy) = both(index);
+                let action = action.extract::<PyRef<ConstantAction>>().unwrap();
+                let copy = copy.extract::<PyRef<ConstantAction>>().unwrap();
+                assert_eq!(copy.start_from, action.start_from);
+                assert_eq!(copy.end_before, action.end_before);
+                assert_eq!(copy.duration, action.duration);
+                assert_eq!(copy.consumption.value, action.consumption.value);
+                assert_eq!(copy.id, action.id);
+            }
+            for index in [6, 7] {
+                let (action, copy) = both(index);
+                let action = action.extract::<PyRef<VariableAction>>().unwrap();
+                let copy = copy.extract::<PyRef<VariableAction>>().unwrap();
+                assert_eq!(copy.start, action.start);
+                assert_eq!(copy.end, action.end);
+                assert_eq!(copy.total_consumption.value, action.total_consumption.value);
+                assert_eq!(copy.min_consumption.value, action.min_consumption.value);
+                assert_eq!(copy.preference_limit, action.preference_limit);
+                assert_eq!(copy.id, action.id);
+                let (copy, action) = (
+                    copy.to_rust(py, example_context().grid()),
+                    action.to_rust(py, example_context().grid()),
+                );
+                assert_eq!(
+                    copy.unwrap().get_max_consumption_profile(),
+                    action.unwrap().get_max_consumption_profile()
+                );
+            }
+            let (battery, copy) = both(8);
+            let battery = battery.extract::<PyRef<Battery>>().unwrap();
+            let copy = copy.extract::<PyRef<Battery>>().unwrap();
+            assert_eq!(
+                format!("{:?}", copy.to_rust(TimestepConfig::default()).unwrap()),
+                format!("{:?}", battery.to_rust(TimestepConfig::default()).unwrap())
+            );
+
+            // Contexts from the copies solve exactly like those from the originals
+            let solve = |objects: &Bound<'_, PyAny>| {
+                let mut context = example_context();
+                for index in [4, 5] {
+                    let action = objects.get_item(index).unwrap();
+                    let action = action.extract::<PyRef<ConstantAction>>().unwrap();
+                    context
+                        .add_constant_action(py, AnyConstantAction::Flat(action))
+                        .unwrap();
+                }
+                for index in [6, 7] {
+                    let action = objects.get_item(index).unwrap();
+                    let action = action.extract::<PyRef<VariableAction>>().unwrap();
+                    context.add_variable_action(py, &action).unwrap();
+                }
+                let battery = objects.get_item(8).unwrap();
+                context
+                    .add_battery(&battery.extract::<PyRef<Battery>>().unwrap())
+                    .unwrap();
+                let options = PyDict::new(py);
+                options.set_item("iterations", 20).unwrap();
+                let (cost, _) = run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py);
+                cost.value
+            };
+            assert_eq!(solve(&copies), solve(&originals));
+        });
+    }
+
+    #[test]
+    fn test_overlapping_constant_actions_are_served_together_or_blamed() {
+        Python::initialize();
+        Python::attach(|py| {
+            let three_kw = Watt { value: 3_000.0 }
+                .to_energy_per_step(timestep_duration(TimestepConfig::default()))
+                as i64;
+            let solve = |grid_limit: f64| {
+                let mut context = example_context();
+                // Fixed windows, overlapping from 7:30 to 8:00. Action 1 starts later.
+                context.constant_actions = [
+                    (Time::at_default_resolution(1, 0), 2),
+                    (Time::at_default_resolution(1, 30), 1),
+                ]
+                .into_iter()
+                .map(|(start, id)| {
+                    Rc::new(RustConstantAction::new(
+                        start,
+                        start + Time::at_default_resolution(1, 0),
+                        Time::at_default_resolution(1, 0),
+                        three_kw,
+                        id,
+                    ))
+                })
+                .collect();
+                context.grid_limit = Some(
+                    Watt { value: grid_limit }
+                        .to_energy_per_step(timestep_duration(TimestepConfig::default()))
+                        as i64,
+                );
+                let options = PyDict::new(py);
+                options.set_item("iterations", 20).unwrap();
+                let (_, schedule) = run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py);
+                schedule
+            };
+            let schedule = solve(11_000.0);
+            assert!(schedule.get_unserved_constant_actions().unwrap().is_empty());
+            let overlap = Utc.with_ymd_and_hms(2025, 3, 1, 7, 30, 0).unwrap();
+            let import = schedule
+                .get_network_consumption(py, overlap, false)
+                .unwrap();
+            assert!((import.value - 6_000.0).abs() < 0.1, "{import:?}");
+
+            let schedule = solve(5_000.0);
+            let unserved = schedule.get_unserved_constant_actions().unwrap();
+            assert_eq!(unserved.len(), 30);
+            for (i, (id, time, power)) in unserved.into_iter().enumerate() {
+                assert_eq!(id, 1);
+                assert_eq!(time, overlap + TimeDelta::minutes(i as i64));
+                assert!((power.value - 1_000.0).abs() < 0.1, "{power:?} at {time}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_preference_front_loads_without_changing_energy_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            // Later hours are worse, within 0.1 of the flat price
+            let later_is_worse = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh(0.000001 * curr.hour)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let solve = |preference: Option<Py<PrognosesProvider>>| {
+                let mut context = example_context();
+                context.constant_actions = vec![];
+                context.electricity_price = Prognoses::from_closure(|_| 100);
+                let charger = VariableAction {
+                    start: Some(context.start_time),
+                    end: context.start_time + TimeDelta::hours(4),
+                    total_consumption: WattHour { value: 6_000.0 },
+                    max_consumption: MaxConsumption::Constant(Watt { value: 6_000.0 }),
+                    min_consumption: Watt::default(),
+                    preference,
+                    preference_limit: 0.1,
+                    contiguous: false,
+                    absolute: false,
+                    id: 5,
+                };
+                context.add_variable_action(py, &charger).unwrap();
+                run_exact(py, &context).unwrap().1
+            };
+            let plain = solve(None);
+            let provider = Py::new(py, PrognosesProvider::new(later_is_worse)).unwrap();
+            let preferring = solve(Some(provider));
+
+            let assigned = AssignedVariableAction {
+                inner: preferring.inner.get_variable_action(5).unwrap().clone(),
+                grid: TimeGrid::new(example_context().start_time, TimestepConfig::default()),
+            };
+            for (time, power) in assigned.get_consumption_profile().unwrap() {
+                let expected = if time.hour() == 6 { 6_000.0 } else { 0.0 };
+                assert!((power.value - expected).abs() < 1e-6, "{power:?} at {time}");
+            }
+            assert_eq!(
+                preferring.inner.get_energy_cost(),
+                plain.inner.get_energy_cost()
+            );
+            let parts = preferring.get_objective_breakdown().unwrap();
+            assert!(parts["preference"].value > 0.0);
+            assert_eq!(
+                plain.get_objective_breakdown().unwrap()["preference"].value,
+                0.0
+            );
+        });
+    }
+
+    #[test]
+    fn test_penalties_are_reported_apart_from_energy_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let solve = |context: &OptimizerContext| {
+                run_simulated_annealing(
+                    py,
+                    context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+            };
+            let nano = |euro: &Euro| euro.to_nano_euro().round() as i64;
+
+            let result = solve(&example_context());
+            assert!(result.penalties.is_empty());
+            assert_eq!(nano(&result.energy_cost), nano(&result.cost));
+
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let later_is_worse = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh(0.000001 * curr.hour)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let mut context = example_context();
+            let charger = VariableAction {
+                start: Some(context.start_time),
+                end: context.start_time + TimeDelta::hours(4),
+                total_consumption: WattHour { value: 6_000.0 },
+                max_consumption: MaxConsumption::Constant(Watt { value: 6_000.0 }),
+                min_consumption: Watt::default(),
+                preference: Some(Py::new(py, PrognosesProvider::new(later_is_worse)).unwrap()),
+                preference_limit: 0.1,
+                contiguous: false,
+                absolute: false,
+                id: 5,
+            };
+            context.add_variable_action(py, &charger).unwrap();
+            let result = solve(&context);
+            assert_eq!(
+                result.penalties.keys().copied().collect::<Vec<_>>(),
+                ["preference"]
+            );
+            assert!(result.penalties["preference"].value > 0.0);
+            let penalties: i64 = result.penalties.values().map(nano).sum();
+            assert_eq!(
+                nano(&result.energy_cost) + penalties,
+                nano(&result.total_objective())
+            );
+        });
+    }
+
+    #[test]
+    fn test_invalid_prognoses_raise_value_error_naming_interval() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            py.run(
+                c"calls = 0\ndef raises_on_fifth(curr, next):\n    global calls\n    calls += 1\n    if calls == 5:\n        raise RuntimeError('no data')\n    return EuroPerWh(0.0001)\ndef returns_string(curr, next):\n    return 'cheap'\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+            let error = |name: &str| {
+                let get_data = globals.get_item(name).unwrap().unwrap().unbind();
+                let Err(err) = OptimizerContext::new(
+                    py,
+                    start_time,
+                    &PrognosesProvider::new(get_data),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ) else {
+                    panic!("{} should have been rejected", name);
+                };
+                assert!(err.is_instance_of::<PyValueError>(py));
+                err
+            };
+
+            let err = error("raises_on_fifth");
+            let message = err.value(py).to_string();
+            assert!(
+                message.starts_with(
+                    "Prognoses provider failed for interval [2025-03-01 06:04:00 UTC, 2025-03-01 06:05:00 UTC)"
+                ),
+                "{}",
+                message
+            );
+            assert!(message.contains("no data"));
+            assert!(err.cause(py).unwrap().is_instance_of::<PyRuntimeError>(py));
+
+            let err = error("returns_string");
+            let message = err.value(py).to_string();
+            assert!(
+                message.starts_with(
+                    "Prognoses provider returned an invalid value for interval [2025-03-01 06:00:00 UTC, 2025-03-01 06:01:00 UTC)"
+                ),
+                "{}",
+                message
+            );
+            assert!(err.cause(py).unwrap().is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn test_implausible_prognoses_warn_or_raise() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("WattHour", py.get_type::<WattHour>())
+                .unwrap();
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let provider = |code: &std::ffi::CStr| {
+                PrognosesProvider::new(py.eval(code, Some(&globals), None).unwrap().unbind())
+            };
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+            let warnings = py.import("warnings").unwrap();
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("record", true).unwrap();
+            let catcher = warnings
+                .call_method("catch_warnings", (), Some(&kwargs))
+                .unwrap();
+            let log = catcher.call_method0("__enter__").unwrap();
+            warnings.call_method1("simplefilter", ("always",)).unwrap();
+            // Returns the messages of the warnings since the last call
+            let take_warnings = || {
+                let messages = log
+                    .try_iter()
+                    .unwrap()
+                    .map(|warning| {
+                        let warning = warning.unwrap();
+                        let category = warning.getattr("category").unwrap();
+                        assert!(category.is(py.get_type::<OptimizerWarning>()));
+                        warning
+                            .getattr("message")
+                            .unwrap()
+                            .str()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>();
+                log.call_method0("clear").unwrap();
+                messages
+            };
+
+            // 0.05 €/kWh given as €/Wh
+            let mut context = OptimizerContext::new(
+                py,
+                start_time,
+                &provider(c"lambda curr, next: EuroPerWh(0.05)"),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            let warned = take_warnings();
+            assert_eq!(warned.len(), 1);
+            assert!(
+                warned[0].starts_with(
+                    "Electricity price of 0.05 €/Wh is above the plausible maximum of 0.01 €/Wh for interval [2025-03-01 06:00:00 UTC, 2025-03-01 06:01:00 UTC) and 1439 more intervals."
+                ),
+                "{}",
+                warned[0]
+            );
+            assert!(warned[0].contains("1000 times too large"));
+
+            // 5 kWh per minute are 300 kW
+            context
+                .add_generated_electricity_prognoses(
+                    py,
+                    &provider(c"lambda curr, next: WattHour(5000.0 if curr.hour == 12 else 1.0)"),
+                )
+                .unwrap();
+            let warned = take_warnings();
+            assert_eq!(warned.len(), 1);
+            assert!(
+                warned[0].starts_with(
+                    "Generated electricity of 300000 W is above the plausible maximum of 100000 W for interval [2025-03-01 12:00:00 UTC, 2025-03-01 12:01:00 UTC) and 59 more intervals."
+                ),
+                "{}",
+                warned[0]
+            );
+            assert!(warned[0].contains("1000 times too large"));
+
+            context
+                .add_beyond_control_consumption_prognoses(
+                    py,
+                    &provider(c"lambda curr, next: WattHour(1000.0)"),
+                )
+                .unwrap();
+            let warned = take_warnings();
+            assert_eq!(warned.len(), 1);
+            assert!(warned[0].starts_with(
+                "Beyond control consumption of 60000 W is above the plausible maximum of 50000 W"
+            ));
+            assert!(warned[0].contains("1000 times too large"));
+
+            context
+                .set_network_fee(py, &provider(c"lambda curr, next: EuroPerWh(-0.03)"))
+                .unwrap();
+            let warned = take_warnings();
+            assert_eq!(warned.len(), 1);
+            assert!(warned[0].starts_with(
+                "Network fee of -0.03 €/Wh is below the plausible minimum of -0.005 €/Wh"
+            ));
+            assert!(warned[0].contains("1000 times too large"));
+
+            // Plausible values don't warn
+            context
+                .set_feed_in_tariff(
+                    py,
+                    &provider(c"lambda curr, next: EuroPerWh(0.00008)"),
+                    None,
+                )
+                .unwrap();
+            context
+                .add_generated_electricity_prognoses(
+                    py,
+                    &provider(c"lambda curr, next: WattHour(100.0)"),
+                )
+                .unwrap();
+            assert!(take_warnings().is_empty());
+
+            // Strict limits raise instead and keep the context unchanged
+            context.set_plausibility_limits(PlausibilityLimits::new(
+                None,
+                Some(Watt { value: 70_000.0 }),
+                None,
+                None,
+                true,
+            ));
+            let generated = context.generated_electricity.clone();
+            let err = context
+                .add_generated_electricity_prognoses(
+                    py,
+                    &provider(c"lambda curr, next: WattHour(5000.0)"),
+                )
+                .unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err.value(py).to_string().contains("1000 times too large"));
+            assert_eq!(context.generated_electricity, generated);
+            context
+                .add_beyond_control_consumption_prognoses(
+                    py,
+                    &provider(c"lambda curr, next: WattHour(1000.0)"),
+                )
+                .unwrap();
+            assert!(take_warnings().is_empty());
+            catcher
+                .call_method1("__exit__", (py.None(), py.None(), py.None()))
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_values_too_large_for_the_optimizer_raise_unit_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let overflow = |result: PyResult<()>, field: &str| {
+                let err = result.unwrap_err();
+                assert!(err.is_instance_of::<UnitError>(py));
+                let message = err.value(py).to_string();
+                assert!(message.contains(field), "{message}");
+                assert!(message.contains("largest supported magnitude"), "{message}");
+            };
+            // Just under and just over the limits
+            let factors = (0.999, 1.001);
+            let energy = |factor: f64| WattHour::from_milli_wh(MAX_ENERGY as f64 * factor);
+            let power = |factor: f64| {
+                Watt::from_energy_per_step(
+                    MAX_ENERGY as f64 * factor,
+                    timestep_duration(TimestepConfig::default()),
+                )
+            };
+
+            let mut context = example_context();
+            let start = context.start_time;
+            let battery = |capacity| {
+                Battery::new(
+                    capacity,
+                    Watt { value: 1000.0 },
+                    Watt { value: 1000.0 },
+                    WattHour::default(),
+                    5,
+                    1.0,
+                    true,
+                    WattHour::default(),
+                    None,
+                    0.0,
+                    EuroPerWh::default(),
+                )
+                .unwrap()
+            };
+            context.add_battery(&battery(energy(factors.0))).unwrap();
+            overflow(
+                context.add_battery(&battery(energy(factors.1))),
+                "Battery 5 capacity",
+            );
+
+            let mut add_constant = |consumption| {
+                let action = ConstantAction::new(
+                    start,
+                    start + TimeDelta::hours(2),
+                    TimeDelta::hours(1),
+                    consumption,
+                    3,
+                    None,
+                    false,
+                );
+                let action = Py::new(py, action).unwrap();
+                context.add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+            };
+            add_constant(power(factors.0)).unwrap();
+            overflow(
+                add_constant(power(factors.1)),
+                "Constant action 3 consumption",
+            );
+
+            let variable = |total_consumption| VariableAction {
+                start: Some(start),
+                end: start + TimeDelta::hours(2),
+                total_consumption,
+                max_consumption: MaxConsumption::Constant(Watt { value: 1000.0 }),
+                min_consumption: Watt::default(),
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: false,
+                absolute: false,
+                id: 4,
+            };
+            context
+                .add_variable_action(py, &variable(energy(factors.0)))
+                .unwrap();
+            overflow(
+                context.add_variable_action(py, &variable(energy(factors.1))),
+                "Variable action 4 total_consumption",
+            );
+
+            let new_context = |factor: f64| {
+                let price = EuroPerWh::from_micro_euro_per_wh(MAX_PRICE as f64 * factor);
+                let prices = PyList::new(py, vec![price; 24]).unwrap();
+                let provider = PrognosesProvider::from_values(
+                    prices.as_any(),
+                    start,
+                    Some(TimeDelta::hours(1)),
+                )
+                .unwrap();
+                OptimizerContext::new(py, start, &provider, None, None, None, None, None)
+                    .map(|_| ())
+            };
+            new_context(-factors.0).unwrap();
+            overflow(new_context(-factors.1), "electricity price");
+        });
+    }
+
+    #[test]
+    fn test_provider_from_values_matches_callable() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+            let quarter_hour = TimeDelta::minutes(15);
+            let prices = |count: usize| {
+                let values = (0..count).map(|i| EuroPerWh {
+                    value: ((i * 7) % 24 + 1) as f64 / 100_000.0,
+                });
+                PyList::new(py, values).unwrap()
+            };
+            let from_values = |values: &Bound<'_, PyList>| {
+                PrognosesProvider::from_values(values.as_any(), start_time, Some(quarter_hour))
+            };
+            let provider = from_values(&prices(96)).unwrap();
+
+            let Err(err) = from_values(&prices(95)) else {
+                panic!("Too few values should have been rejected");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err.value(py).to_string().contains("got 95"));
+            let mixed = prices(96);
+            mixed.set_item(42, "cheap").unwrap();
+            let Err(err) = from_values(&mixed) else {
+                panic!("A string value should have been rejected");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err.value(py).to_string().contains("index 42"));
+            assert!(
+                PrognosesProvider::from_values(
+                    prices(96).as_any(),
+                    start_time,
+                    Some(TimeDelta::minutes(7))
+                )
+                .is_err()
+            );
+
+            let globals = PyDict::new(py);
+            globals.set_item("values", prices(96)).unwrap();
+            globals.set_item("start", start_time).unwrap();
+            let get_data = py
+                .eval(
+                    c"lambda curr, next: values[int((curr - start).total_seconds() // 900)]",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let callable = PrognosesProvider::new(get_data);
+
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let solve = |provider: &PrognosesProvider| {
+                let mut context =
+                    OptimizerContext::new(py, start_time, provider, None, None, None, None, None)
+                        .unwrap();
+                context.constant_actions = example_context().constant_actions;
+                let (cost, schedule) = run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py);
+                (
+                    cost.to_nano_euro(),
+                    schedule.inner.network_consumption.get_data().to_vec(),
+                )
+            };
+            assert_eq!(solve(&provider), solve(&callable));
+        });
+    }
+
+    #[test]
+    fn test_two_register_provider_follows_local_night_window() {
+        Python::initialize();
+        Python::attach(|py| {
+            let night = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+            let tariff = |tz| {
+                PrognosesProvider::two_register(
+                    py,
+                    EuroPerWh { value: 0.00032 },
+                    EuroPerWh { value: 0.00026 },
+                    night(22),
+                    night(6),
+                    tz,
+                )
+            };
+            let low_steps = |provider: &PrognosesProvider, start_time: DateTime<Utc>| {
+                let prices = provider
+                    .try_get_prognoses(
+                        py,
+                        TimeGrid::new(start_time, TimestepConfig::default()),
+                        |price: EuroPerWh| price.to_internal("price"),
+                    )
+                    .unwrap();
+                let grid = TimeGrid::new(start_time, TimestepConfig::default());
+                (0..STEPS_PER_DAY)
+                    .map(Time::from_timestep)
+                    .filter(|&t| {
+                        let price = *prices.get(t).unwrap();
+                        assert!(price == 260 || price == 320, "{price} at {t:?}");
+                        price == 260
+                    })
+                    .map(|t| grid.to_datetime(t).unwrap())
+                    .collect::<Vec<_>>()
+            };
+
+            // Crossing midnight without daylight saving time: 22:00 to 06:00
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+            let low = low_steps(&tariff("UTC").unwrap(), start_time);
+            assert_eq!(low.len(), 8 * 60);
+            assert_eq!(low[0], Utc.with_ymd_and_hms(2025, 3, 1, 22, 0, 0).unwrap());
+            assert_eq!(
+                *low.last().unwrap(),
+                Utc.with_ymd_and_hms(2025, 3, 2, 5, 59, 0).unwrap()
+            );
+
+            // The clocks in Berlin go from 02:00 CET to 03:00 CEST that night, so the low
+            // window is 22:00 CET (21:00 UTC) to 06:00 CEST (04:00 UTC), one hour short
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 29, 12, 0, 0).unwrap();
+            let low = low_steps(&tariff("Europe/Berlin").unwrap(), start_time);
+            assert_eq!(low.len(), 7 * 60);
+            assert_eq!(low[0], Utc.with_ymd_and_hms(2025, 3, 29, 21, 0, 0).unwrap());
+            assert_eq!(
+                *low.last().unwrap(),
+                Utc.with_ymd_and_hms(2025, 3, 30, 3, 59, 0).unwrap()
+            );
+            assert!(low.windows(2).all(|w| w[1] - w[0] == TimeDelta::minutes(1)));
+
+            let Err(err) = tariff("Europe/Atlantis") else {
+                panic!("An unknown time zone should have been rejected");
+            };
+            assert!(err.is_instance_of::<OptimizerError>(py));
+            assert!(
+                PrognosesProvider::two_register(
+                    py,
+                    EuroPerWh { value: 0.00032 },
+                    EuroPerWh { value: 0.00026 },
+                    night(6),
+                    night(6),
+                    "UTC",
+                )
+                .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn test_stacked_providers_are_called_once_per_interval() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("WattHour", py.get_type::<WattHour>())
+                .unwrap();
+            py.run(
+                c"
+calls = [0] * 10
+def pv(index):
+    def get(curr, next):
+        calls[index] += 1
+        return WattHour(0.1 * (index + 1) if 8 <= curr.hour < 16 else 0.0)
+    return get
+providers = [pv(index) for index in range(10)]
+def sum_of_all(curr, next):
+    return WattHour(5.5 if 8 <= curr.hour < 16 else 0.0)
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let get = |name: &str| globals.get_item(name).unwrap().unwrap();
+            let calls = || get("calls").extract::<Vec<u32>>().unwrap();
+
+            let context = || OptimizerContext {
+                constant_actions: vec![],
+                ..example_context()
+            };
+            let (mut stacked, mut summed) = (context(), context());
+            for provider in get("providers").try_iter().unwrap() {
+                let provider = PrognosesProvider::new(provider.unwrap().unbind());
+                stacked
+                    .add_generated_electricity_prognoses(py, &provider)
+                    .unwrap();
+            }
+            summed
+                .add_generated_electricity_prognoses(
+                    py,
+                    &PrognosesProvider::new(get("sum_of_all").unbind()),
+                )
+                .unwrap();
+            assert_eq!(calls(), vec![STEPS_PER_DAY; 10]);
+
+            // The sum is materialized, solving doesn't call the providers again
+            let (stacked_cost, stacked_schedule) = run_exact(py, &stacked).unwrap();
+            let (summed_cost, summed_schedule) = run_exact(py, &summed).unwrap();
+            assert_eq!(calls(), vec![STEPS_PER_DAY; 10]);
+            for t in 0..STEPS_PER_DAY {
+                let time = Time::from_timestep(t);
+                let stacked = *stacked.generated_electricity.get(time).unwrap();
+                let summed = *summed.generated_electricity.get(time).unwrap();
+                // Each provider is rounded to whole milli-Wh on its own
+                assert!(
+                    (stacked - summed).abs() <= 10,
+                    "{stacked} != {summed} at {t}"
+                );
+            }
+            assert!((stacked_cost.value - summed_cost.value).abs() < 1e-6);
+            assert_eq!(
+                stacked_schedule.inner.get_energy_cost(),
+                summed_schedule.inner.get_energy_cost()
+            );
+        });
+    }
+
+    #[test]
+    fn test_base_load_above_generation_is_drawn_from_grid() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            globals
+                .set_item("WattHour", py.get_type::<WattHour>())
+                .unwrap();
+            py.run(
+                c"def price(curr, next):\n    return EuroPerWh(0.0001)\ndef pv(curr, next):\n    return WattHour(1.0)\ndef base_load(curr, next):\n    return WattHour(1.0 if curr.hour == 7 else 0.5)\ndef broken_meter(curr, next):\n    return WattHour(-1.0 if curr.hour == 9 else 0.5)\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let provider = |name: &str| {
+                PrognosesProvider::new(globals.get_item(name).unwrap().unwrap().unbind())
+            };
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+            let mut context = OptimizerContext::new(
+                py,
+                start_time,
+                &provider("price"),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            context
+                .add_generated_electricity_prognoses(py, &provider("pv"))
+                .unwrap();
+            context
+                .add_beyond_control_consumption_prognoses(py, &provider("base_load"))
+                .unwrap();
+
+            let err = context
+                .add_beyond_control_consumption_prognoses(py, &provider("broken_meter"))
+                .unwrap_err();
+            assert!(err.is_instance_of::<PrognosesError>(py));
+            // The meter is negative for a whole hour of generation
+            assert_eq!(
+                err.value(py).to_string(),
+                "Beyond control consumption is negative while electricity is generated in \
+                 [2025-03-01 09:00:00 UTC, 2025-03-01 10:00:00 UTC). The generation is most \
+                 likely counted twice, once in add_generated_electricity_prognoses and once as \
+                 negative consumption in add_beyond_control_consumption_prognoses; leave it out \
+                 of the consumption."
+            );
+            // A short glitch is only an invalid value
+            let glitch = PrognosesProvider::new(
+                py.eval(
+                    c"lambda curr, next: WattHour(-1.0 if curr.hour == 9 and curr.minute < 5 else 0.5)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind(),
+            );
+            let err = context
+                .add_beyond_control_consumption_prognoses(py, &glitch)
+                .unwrap_err();
+            assert!(err.is_instance_of::<PrognosesError>(py));
+            assert_eq!(
+                err.value(py).to_string(),
+                "Beyond control consumption must not be negative, got -1 Wh for interval [2025-03-01 09:00:00 UTC, 2025-03-01 09:01:00 UTC)"
+            );
+
+            // 1 Wh per timestep for 10 minutes between 7:30 and 8:00, on top of the 1 Wh base
+            // load from 7:00
+            let action_start = start_time + TimeDelta::minutes(90);
+            let action = Py::new(
+                py,
+                ConstantAction::new(
+                    action_start,
+                    action_start + TimeDelta::minutes(30),
+                    TimeDelta::minutes(10),
+                    Watt { value: 60.0 },
+                    1,
+                    None,
+                    false,
+                ),
+            )
+            .unwrap();
+            context
+                .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                .unwrap();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 5).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+
+            // Generation covers everything else, only the action is drawn from the grid
+            let placed = schedule.inner.get_constant_action(1).unwrap();
+            let action_steps = placed.get_start_time().to_timestep() as usize
+                ..placed.get_end_time().to_timestep() as usize;
+            assert!(90 <= action_steps.start && action_steps.end <= 120);
+            let network = schedule.inner.network_consumption.get_data();
+            for (t, consumed) in network.iter().enumerate() {
+                let expected = if action_steps.contains(&t) { 1000 } else { 0 };
+                assert_eq!(*consumed, expected, "timestep {}", t);
+            }
+        });
+    }
+
+    #[test]
+    fn test_theoretical_best_bounds_annealing_cost() {
+        let context = example_context();
+        let best = theoretical_best(&context, vec![]).unwrap();
+        let relaxed = theoretical_best(&context, vec!["action_windows".to_string()]).unwrap();
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.9 },
+            ..Default::default()
+        };
+        let (cost, _, _) = RustSolver::new(context.to_rust().unwrap(), config, Some(1))
+            .solve(|_| Ok::<(), ()>(()))
+            .unwrap();
+        assert!(relaxed.to_nano_euro() <= best.to_nano_euro());
+        assert!(best.to_nano_euro() <= cost as f64);
+        assert!(theoretical_best(&context, vec!["battery".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_exact_cost_not_above_annealed_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.batteries = vec![Rc::new(RustBattery::new(5000, 0, 500, 500, 1.0, 4))];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(8, 0),
+                3000,
+                100,
+                3,
+            ))];
+            let Err(err) = run_exact(py, &context) else {
+                panic!("Constant actions should be rejected");
+            };
+            assert_eq!(
+                err.value(py).to_string(),
+                "run_exact can't place constant actions or contiguous variable actions (ids 1, 2), use run_simulated_annealing instead"
+            );
+
+            context.constant_actions = vec![];
+            let (exact, schedule) = run_exact(py, &context).unwrap();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (annealed, _) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            assert!(exact.to_nano_euro() <= annealed.to_nano_euro());
+            let consumed: i64 = schedule.inner.network_consumption.get_data().iter().sum();
+            assert!(consumed >= 3000);
+        });
+    }
+
+    #[test]
+    fn test_objective_breakdown_is_opt_in() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.peak_price = Some(1000);
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let solve = |explain_cost| {
+                run_simulated_annealing(
+                    py,
+                    &context,
+                    Some(1),
+                    None,
+                    None,
+                    Some("linear"),
+                    Some(explain_cost),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(&options),
+                )
+                .unwrap()
+                .unpack(py)
+            };
+            let (_, schedule) = solve(false);
+            assert!(schedule.get_objective_breakdown().is_none());
+
+            let (cost, schedule) = solve(true);
+            let total = schedule.inner.get_objective_breakdown().unwrap().total();
+            assert_eq!(total as f64, cost.to_nano_euro().round());
+            let parts = schedule.get_objective_breakdown().unwrap();
+            assert_eq!(parts.len(), 11);
+            assert!(parts["grid_import"].value > 0.0);
+            assert!(parts["peak_charge"].value > 0.0);
+
+            // The exact solver always explains its cost
+            context.constant_actions = vec![];
+            let (cost, schedule) = run_exact(py, &context).unwrap();
+            let total = schedule.inner.get_objective_breakdown().unwrap().total();
+            assert_eq!(total as f64, cost.to_nano_euro().round());
+        });
+    }
+
+    #[test]
+    fn test_cost_breakdown_sums_to_energy_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.generated_electricity =
+                Prognoses::from_closure(|t| (t.to_timestep() % 7) as i64 * 3);
+            context.beyond_control_consumption = Prognoses::from_closure(|_| 20);
+            context.batteries = vec![Rc::new(RustBattery::new(1000, 500, 50, 50, 0.9, 4))];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(8, 0),
+                2000,
+                50,
+                3,
+            ))];
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+
+            let breakdown = schedule.get_cost_breakdown(py, &context).unwrap();
+            let euro = |value: Bound<'_, PyAny>| value.extract::<Euro>().unwrap().value;
+            let item = |key| euro(breakdown.get_item(key).unwrap().unwrap());
+            let by_id = |key| {
+                let costs = breakdown.get_item(key).unwrap().unwrap();
+                let costs = costs.cast_into::<PyDict>().unwrap();
+                costs
+                    .iter()
+                    .map(|(id, cost)| (id.extract::<u32>().unwrap(), euro(cost)))
+                    .collect::<BTreeMap<_, _>>()
+            };
+            let per_timestep: Vec<(DateTime<Utc>, Bound<'_, PyAny>)> = breakdown
+                .get_item("per_timestep")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(per_timestep.len(), STEPS_PER_DAY as usize);
+            assert_eq!(per_timestep[0].0, context.start_time);
+
+            let constant = by_id("constant_actions");
+            let variable = by_id("variable_actions");
+            assert_eq!(constant.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+            assert_eq!(variable.keys().copied().collect::<Vec<_>>(), vec![3]);
+            assert!(item("generation_savings") > 0.0);
+            let consumers = constant.values().sum::<f64>()
+                + variable.values().sum::<f64>()
+                + item("beyond_control");
+            let total = consumers - item("generation_savings") - item("battery_savings");
+            let energy_cost = schedule.get_energy_cost().value;
+            assert!((item("total") - energy_cost).abs() < 1e-9);
+            assert!((total - energy_cost).abs() < 1e-9);
+            let timesteps: f64 = per_timestep.into_iter().map(|(_, cost)| euro(cost)).sum();
+            assert!((timesteps - energy_cost).abs() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_network_fee_shifts_loads_to_low_fee_band() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            // Expensive until 9:00, cheap afterwards
+            let get_data = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh(0.001 if curr.hour < 9 else 0.00001)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let provider = PrognosesProvider::new(get_data);
+            let mut context = example_context();
+            context.electricity_price = Prognoses::from_closure(|_| 0);
+            context.constant_actions = vec![];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(6, 0),
+                6000,
+                200,
+                1,
+            ))];
+            context.set_network_fee(py, &provider).unwrap();
+
+            let (cost, schedule) = run_exact(py, &context).unwrap();
+            for (time, power) in schedule.get_network_consumption_profile().unwrap() {
+                if time.hour() < 9 {
+                    assert_eq!(power.value, 0.0, "consumed at {time}");
+                }
+            }
+            assert_eq!(schedule.get_energy_cost().value, 0.0);
+            let fees = schedule.get_network_fee_cost();
+            assert!(fees.value > 0.0);
+            assert!((fees.value - cost.value).abs() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_co2_weight_shifts_loads_to_low_carbon_hours() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Dirty until 9:00, clean afterwards
+            let get_data = py
+                .eval(
+                    c"lambda curr, next: 0.5 if curr.hour < 9 else 0.1",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let provider = PrognosesProvider::new(get_data);
+            let mut context = example_context();
+            context.electricity_price = Prognoses::from_closure(|_| 300);
+            context.constant_actions = vec![];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(6, 0),
+                6000,
+                200,
+                1,
+            ))];
+            let (_, plain) = run_exact(py, &context).unwrap();
+            assert!(plain.get_co2_emissions().is_none());
+
+            // Without a weight the emissions are only reported
+            context.set_co2_intensity(py, &provider, 0.0).unwrap();
+            let (cost, reported) = run_exact(py, &context).unwrap();
+            assert_eq!(
+                reported.inner.network_consumption.get_data(),
+                plain.inner.network_consumption.get_data()
+            );
+            assert!((cost.value - reported.get_energy_cost().value).abs() < 1e-9);
+            assert!(reported.get_co2_emissions().unwrap() > 0.0);
+
+            context.set_co2_intensity(py, &provider, 0.001).unwrap();
+            let (_, weighted) = run_exact(py, &context).unwrap();
+            let mut imported = 0.0;
+            for (time, power) in weighted.get_network_consumption_profile().unwrap() {
+                if time.hour() < 9 {
+                    assert_eq!(power.value, 0.0, "consumed at {time}");
+                }
+                imported += power.value / 60.0;
+            }
+            let emissions = weighted.get_co2_emissions().unwrap();
+            assert!((emissions - 0.1 * imported).abs() < 1e-6, "{emissions} g");
+            assert!(emissions < reported.get_co2_emissions().unwrap());
+            assert_eq!(
+                weighted.get_energy_cost().value,
+                reported.get_energy_cost().value
+            );
+
+            assert!(context.set_co2_intensity(py, &provider, -1.0).is_err());
+        });
+    }
+
+    #[test]
+    fn test_rebase_moves_windows_and_takes_new_prognoses() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            globals
+                .set_item("WattHour", py.get_type::<WattHour>())
+                .unwrap();
+            let provider = |code: &std::ffi::CStr| {
+                PrognosesProvider::new(py.eval(code, Some(&globals), None).unwrap().unbind())
+            };
+            // The price grows with the day of the month
+            let price = provider(c"lambda curr, next: EuroPerWh(0.0001 * curr.day)");
+            let start = Utc.with_ymd_and_hms(2025, 6, 1, 6, 0, 0).unwrap();
+            let mut context = OptimizerContext::new(
+                py,
+                start,
+                &price,
+                None,
+                None,
+                None,
+                None,
+                Some(TimeDelta::hours(1)),
+            )
+            .unwrap();
+            let at = |hours| start + TimeDelta::hours(hours);
+            let action = ConstantAction::new(
+                at(2),
+                at(8),
+                TimeDelta::hours(1),
+                Watt { value: 1_000.0 },
+                1,
+                None,
+                false,
+            );
+            context
+                .add_constant_action(
+                    py,
+                    AnyConstantAction::Flat(Py::new(py, action).unwrap().borrow(py)),
+                )
+                .unwrap();
+            let charger = VariableAction::new(
+                at(1),
+                at(5),
+                WattHour { value: 2_000.0 },
+                MaxConsumption::Constant(Watt { value: 1_000.0 }),
+                2,
+                None,
+                None,
+                0.1,
+                false,
+                false,
+            );
+            context.add_variable_action(py, &charger).unwrap();
+            context
+                .add_battery(
+                    &Battery::new(
+                        WattHour { value: 5_000.0 },
+                        Watt { value: 1_000.0 },
+                        Watt { value: 1_000.0 },
+                        WattHour { value: 0.0 },
+                        3,
+                        1.0,
+                        true,
+                        WattHour::default(),
+                        None,
+                        0.0,
+                        EuroPerWh::default(),
+                    )
+                    .unwrap(),
+                )
+                .unwrap();
+
+            let generation = provider(c"lambda curr, next: WattHour(100.0)");
+            let next_day = context
+                .rebase(py, at(24), &price, Some(&generation), None)
+                .unwrap();
+            assert_eq!(next_day.start_time, at(24));
+            let step = Time::from_timestep(0);
+            assert_eq!(*context.electricity_price.get(step).unwrap(), 100);
+            assert_eq!(*next_day.electricity_price.get(step).unwrap(), 200);
+            assert_eq!(*next_day.generated_electricity.get(step).unwrap(), 100_000);
+            assert_eq!(*context.generated_electricity.get(step).unwrap(), 0);
+
+            let grid = next_day.grid();
+            let constant = &next_day.constant_actions[0];
+            assert_eq!(grid.to_datetime(constant.get_start_from()).unwrap(), at(26));
+            assert_eq!(grid.to_datetime(constant.get_end_before()).unwrap(), at(32));
+            let variable = &next_day.variable_actions[0];
+            assert_eq!(grid.to_datetime(variable.get_start()).unwrap(), at(25));
+            assert_eq!(grid.to_datetime(variable.get_end()).unwrap(), at(29));
+            assert_eq!(next_day.batteries.len(), 1);
+
+            let options = PyDict::new(py);
+            options.set_item("iterations", 300).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &next_day,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            let run = schedule.get_constant_action(1).unwrap();
+            assert!(run.get_start_time().unwrap() >= at(26));
+            assert!(run.get_end_time().unwrap() <= at(32));
+        });
+    }
+
+    #[test]
+    fn test_rebase_keeps_absolute_windows() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let price = PrognosesProvider::new(
+                py.eval(
+                    c"lambda curr, next: EuroPerWh(0.0003)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind(),
+            );
+            let start = Utc.with_ymd_and_hms(2025, 6, 1, 6, 0, 0).unwrap();
+            let mut context = OptimizerContext::new(
+                py,
+                start,
+                &price,
+                None,
+                None,
+                None,
+                None,
+                Some(TimeDelta::hours(1)),
+            )
+            .unwrap();
+            let at = |hours| start + TimeDelta::hours(hours);
+            for (id, absolute) in [(1, false), (2, true)] {
+                let action = ConstantAction::new(
+                    at(8),
+                    at(12),
+                    TimeDelta::hours(1),
+                    Watt { value: 1_000.0 },
+                    id,
+                    None,
+                    absolute,
+                );
+                context
+                    .add_constant_action(
+                        py,
+                        AnyConstantAction::Flat(Py::new(py, action).unwrap().borrow(py)),
+                    )
+                    .unwrap();
+            }
+            let charger = VariableAction::new(
+                at(8),
+                at(12),
+                WattHour { value: 2_000.0 },
+                MaxConsumption::Constant(Watt { value: 1_000.0 }),
+                3,
+                None,
+                None,
+                0.1,
+                false,
+                true,
+            );
+            context.add_variable_action(py, &charger).unwrap();
+
+            let later = context.rebase(py, at(6), &price, None, None).unwrap();
+            let grid = later.grid();
+            let start_of = |id| {
+                let action = later.constant_actions.iter().find(|a| a.get_id() == id);
+                grid.to_datetime(action.unwrap().get_start_from()).unwrap()
+            };
+            assert_eq!(start_of(1), at(14));
+            assert_eq!(start_of(2), at(8));
+            let variable = &later.variable_actions[0];
+            assert_eq!(grid.to_datetime(variable.get_start()).unwrap(), at(8));
+            assert_eq!(grid.to_datetime(variable.get_end()).unwrap(), at(12));
+            // The absolute windows are kept for the next rebase, too
+            let again = later.rebase(py, at(7), &price, None, None).unwrap();
+            assert_eq!(again.absolute_actions.len(), 2);
+
+            let Err(err) = context.rebase(py, at(6) + TimeDelta::minutes(30), &price, None, None)
+            else {
+                panic!("Rebased by half a timestep");
+            };
+            assert!(err.is_instance_of::<TimestepAlignmentError>(py));
+            // The absolute windows are over by then
+            assert!(context.rebase(py, at(12), &price, None, None).is_err());
+        });
+    }
+
+    #[test]
+    fn test_effective_import_price_includes_the_network_fee() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let fee = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh(0.0001 if curr.hour < 12 else 0.0002)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let mut context = example_context();
+            context
+                .set_network_fee(py, &PrognosesProvider::new(fee))
+                .unwrap();
+            // Solar covers the base load from 10 to 14 hours in, nothing is drawn then
+            let sunny = |time: Time| (600..840).contains(&time.to_timestep());
+            context.generated_electricity =
+                Prognoses::from_closure(|time| if sunny(time) { 500 } else { 0 });
+            context.beyond_control_consumption = Prognoses::from_closure(|_| 200);
+            context.constant_actions = vec![];
+            context.batteries = vec![];
+
+            let (_, schedule) = run_exact(py, &context).unwrap();
+            let profile = schedule.get_effective_import_price_profile().unwrap();
+            assert_eq!(profile.len(), STEPS_PER_DAY as usize);
+            let mut paid = 0.0;
+            for (t, (time, price)) in profile.iter().enumerate() {
+                let step = Time::from_timestep(t as u32);
+                let import = schedule.get_network_consumption(py, *time, false).unwrap();
+                let Some(price) = price else {
+                    assert_eq!(import.value, 0.0, "drew from the grid at {time}");
+                    continue;
+                };
+                let expected = *context.electricity_price.get(step).unwrap()
+                    + *context.network_fee.as_ref().unwrap().get(step).unwrap();
+                assert!((price.value - expected as f64 / 1e6).abs() < 1e-12);
+                paid += price.value * import.value / 60.0;
+            }
+            assert!(profile.iter().any(|(_, price)| price.is_none()));
+            let bill = schedule.get_energy_cost().value + schedule.get_network_fee_cost().value;
+            assert!((paid - bill).abs() < 1e-9, "{paid} != {bill}");
+
+            let (time, price) = &profile[0];
+            let single = schedule
+                .get_effective_import_price(py, *time, false)
+                .unwrap();
+            assert_eq!(single.map(|p| p.value), price.as_ref().map(|p| p.value));
+            assert!(
+                schedule
+                    .get_effective_import_price(py, *time - TimeDelta::minutes(1), false)
+                    .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn test_net_metering_bills_import_minus_export() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.constant_actions = vec![];
+            // Solar surplus from 10 to 14 hours in, base load the rest of the day
+            context.generated_electricity = Prognoses::from_closure(|time| {
+                if (600..840).contains(&time.to_timestep()) {
+                    500
+                } else {
+                    0
+                }
+            });
+            context.beyond_control_consumption = Prognoses::from_closure(|time| {
+                if (600..840).contains(&time.to_timestep()) {
+                    0
+                } else {
+                    200
+                }
+            });
+            let result = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            assert!(result.bill.is_none());
+
+            context
+                .set_net_metering(Some(EuroPerWh { value: 0.0002 }), 0.5)
+                .unwrap();
+            let result = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            let schedule = result.schedule.borrow(py);
+            let import: i64 = schedule.inner.network_consumption.get_data().iter().sum();
+            let export: i64 = schedule.inner.grid_export.get_data().iter().sum();
+            assert!(import > 0 && export > 0, "import {import}, export {export}");
+            // 200 micro-euro per Wh on the netted milli-Wh gives nano-euro
+            let bill = result.bill.unwrap();
+            assert!((bill.value - (import - export) as f64 * 200.0 * 1e-9).abs() < 1e-9);
+            let direct = schedule
+                .get_net_metering_bill(EuroPerWh { value: 0.0002 })
+                .unwrap();
+            assert!((direct.value - bill.value).abs() < 1e-12);
+            // The objective is the weighted price, not the bill
+            assert!((result.cost.value - bill.value).abs() > 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_fixed_battery_plan_is_kept_and_planned_around() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            let start = context.start_time;
+            context.constant_actions = vec![];
+            context.grid_limit = Some(1000);
+            context.batteries = vec![Rc::new(RustBattery::new(
+                200_000, 10_000, 1000, 1000, 1.0, 1,
+            ))];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(6, 0),
+                6000,
+                200,
+                2,
+            ))];
+            // 60 W fill the grid limit of 1 Wh per minute for three hours
+            let plan = |power: f64| {
+                vec![
+                    (start, Watt { value: power }),
+                    (start + TimeDelta::hours(3), Watt { value: 0.0 }),
+                ]
+            };
+            context.set_fixed_battery_plan(1, plan(60.0)).unwrap();
+
+            let (_, schedule) = run_exact(py, &context).unwrap();
+            let action = schedule.inner.get_variable_action(2).unwrap();
+            for (time, consumption) in action.iter_consumption() {
+                if time < Time::at_default_resolution(3, 0) {
+                    assert_eq!(consumption, 0);
+                }
+            }
+            let battery = schedule.inner.get_battery(1).unwrap();
+            assert_eq!(
+                battery.get_charge_level(Time::at_default_resolution(1, 0)),
+                Some(&70_000)
+            );
+            assert_eq!(
+                battery.get_charge_level(Time::get_day_end()),
+                Some(&190_000)
+            );
+
+            let message = |result: PyResult<()>| {
+                let err = result.unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                err.value(py).to_string()
+            };
+            assert!(
+                message(context.set_fixed_battery_plan(1, plan(61.0)))
+                    .starts_with("Fixed plan for battery 1 is impossible")
+            );
+            assert!(message(context.set_fixed_battery_plan(7, plan(1.0))).contains("no battery"));
+            let mut unordered = plan(1.0);
+            unordered.reverse();
+            assert!(message(context.set_fixed_battery_plan(1, unordered)).contains("ascending"));
+            // Rejected plans keep the previous one
+            assert_eq!(context.fixed_battery_plans.len(), 1);
+            assert_eq!(
+                *context.fixed_battery_plans[0]
+                    .1
+                    .get(Time::at_default_resolution(0, 0))
+                    .unwrap(),
+                1000
+            );
+
+            let json = context.dump_problem(None).unwrap();
+            let reloaded = serde_json::from_str::<ProblemDump>(&json)
+                .unwrap()
+                .into_context()
+                .unwrap();
+            assert_eq!(
+                reloaded.fixed_battery_plans[0].1.get_data(),
+                context.fixed_battery_plans[0].1.get_data()
+            );
+        });
+    }
+
+    #[test]
+    fn test_self_discharge_rate_is_validated_and_passed_on() {
+        Python::initialize();
+        Python::attach(|py| {
+            let battery = |rate: f32| {
+                Battery::new(
+                    WattHour { value: 1000.0 },
+                    Watt { value: 100.0 },
+                    Watt { value: 100.0 },
+                    WattHour { value: 0.0 },
+                    1,
+                    1.0,
+                    true,
+                    WattHour::default(),
+                    None,
+                    rate,
+                    EuroPerWh::default(),
+                )
+            };
+            assert_eq!(
+                battery(0.25)
+                    .unwrap()
+                    .to_rust(TimestepConfig::default())
+                    .unwrap()
+                    .get_self_discharge_per_hour(),
+                0.25
+            );
+            for rate in [-0.1, 1.0] {
+                let err = battery(rate).err().unwrap();
+                assert!(err.is_instance_of::<PyValueError>(py));
+                assert!(
+                    err.value(py)
+                        .to_string()
+                        .starts_with("Battery self_discharge_per_hour must be in [0, 1)")
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_battery_cycle_cost_is_validated_and_itemized() {
+        Python::initialize();
+        Python::attach(|py| {
+            let battery = |cycle_cost: f64| {
+                Battery::new(
+                    WattHour { value: 1000.0 },
+                    Watt { value: 100.0 },
+                    Watt { value: 100.0 },
+                    WattHour { value: 0.0 },
+                    1,
+                    1.0,
+                    true,
+                    WattHour::default(),
+                    None,
+                    0.0,
+                    EuroPerWh { value: cycle_cost },
+                )
+            };
+            let err = battery(-0.00001).err().unwrap();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(
+                err.value(py)
+                    .to_string()
+                    .starts_with("Battery cycle_cost can't be negative")
+            );
+
+            let evening = Time::at_default_resolution(12, 0);
+            let solve = |evening_price: i64| {
+                let mut context = example_context();
+                context.constant_actions = vec![];
+                context.electricity_price =
+                    Prognoses::from_closure(|t| if t < evening { 10 } else { evening_price });
+                context.beyond_control_consumption =
+                    Prognoses::from_closure(|t| if t < evening { 0 } else { 1_000 });
+                context.add_battery(&battery(0.000005).unwrap()).unwrap();
+                let (cost, schedule) = run_exact(py, &context).unwrap();
+                let total = schedule.inner.get_objective_breakdown().unwrap().total();
+                assert_eq!(total as f64, cost.to_nano_euro().round());
+                let level = *schedule
+                    .inner
+                    .get_battery(1)
+                    .unwrap()
+                    .get_charge_level(evening)
+                    .unwrap();
+                (level, schedule.get_battery_wear().unwrap()[&1].value)
+            };
+
+            // Cycling at flat prices saves nothing and wears the battery
+            assert_eq!(solve(10), (0, 0.0));
+            // A large evening spike still pays for the wear of every stored Wh, so the whole
+            // evening consumption of 720 Wh is stored
+            let (level, wear) = solve(100);
+            assert_eq!(level, 720_000);
+            assert!((wear - 720.0 * 0.000005).abs() < 1e-12, "{wear}");
+        });
+    }
+
+    #[test]
+    fn test_negative_prices_give_negative_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.electricity_price =
+                Prognoses::from_closure(|t| -((t.to_timestep() % 120) as i64) - 1);
+            context.batteries = vec![Rc::new(RustBattery::new(5000, 1000, 500, 500, 1.0, 4))];
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (cost, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+            // Paid for every consumed Wh, including what is stored in the battery
+            assert!(cost.to_nano_euro() < 0.0);
+            let battery = schedule.inner.get_battery(4).unwrap();
+            assert_eq!(battery.get_charge_level(Time::get_day_end()), Some(&5000));
+        });
+    }
+
+    #[test]
+    fn test_flow_table_balances_and_explains_the_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            import_package(py);
+            let mut context = example_context();
+            context.batteries = vec![Rc::new(RustBattery::new(5000, 1000, 500, 500, 0.9, 4))];
+            let globals = PyDict::new(py);
+            globals
+                .set_item("context", Py::new(py, context).unwrap())
+                .unwrap();
+            py.run(
+                c"
+from collections import defaultdict
+from electricity_price_optimizer_py import run_simulated_annealing
+
+def solve(capture_flow):
+    _, schedule = run_simulated_annealing(
+        context, seed=1, cooling='linear', explain_cost=True, capture_flow=capture_flow, iterations=20
+    )
+    return schedule
+
+schedule = solve(True)
+table = schedule.get_flow_table()
+assert any(edge['from_label'] == 'Battery 4' for edge in table)
+# Constant actions are part of the consumption flowing from the wires into the sink
+assert any(edge['to_label'] == 'Sink' and edge['flow'] > 0 for edge in table)
+assert all(0 <= edge['flow'] <= edge['capacity'] for edge in table)
+
+# Conserved at the wire of every timestep
+balance = defaultdict(int)
+for edge in table:
+    if edge['to_label'] == 'Wire':
+        balance[edge['timestep']] += edge['flow']
+    if edge['from_label'] == 'Wire':
+        balance[edge['timestep']] -= edge['flow']
+assert len(balance) == 1440 and None not in balance
+assert all(value == 0 for value in balance.values())
+
+# The edges cost what the objective says, apart from the bonus on the initial charge
+flow_cost = sum(
+    edge['flow'] * edge['unit_cost'] for edge in table if edge['category'] != 'initial_charge'
+)
+breakdown = schedule.get_objective_breakdown()
+objective = sum(
+    cost.get_value()
+    for name, cost in breakdown.items()
+    if name not in ('min_consumption_rounding', 'unserved_penalty')
+)
+assert abs(flow_cost * 1e-9 - objective) < 1e-9, (flow_cost, objective)
+
+assert solve(False).get_flow_table() is None
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_failures_raise_typed_exceptions() {
+        Python::initialize();
+        Python::attach(|py| {
+            import_package(py);
+            py.run(
+                c"
+from datetime import datetime, timedelta, timezone
+from electricity_price_optimizer_py import (
+    Battery, ConstantAction, InfeasibleError, OptimizerContext, OptimizerError, OutOfRange,
+    PrognosesError, PrognosesProvider, TimestepAlignmentError, UnitError,
+)
+from electricity_price_optimizer_py.units import EuroPerWh, Watt, WattHour
+
+start = datetime(2025, 3, 1, 6, tzinfo=timezone.utc)
+prices = PrognosesProvider.from_values([EuroPerWh(0.0003)] * 24, start, timedelta(hours=1))
+
+def raised(call):
+    try:
+        call()
+    except OptimizerError as e:
+        assert isinstance(e, ValueError)
+        return e
+    raise AssertionError('nothing raised')
+
+def context():
+    return OptimizerContext(start, prices)
+
+def action(start_from, end_before, duration):
+    return ConstantAction(start_from, end_before, duration, Watt(1000.0), 1)
+
+# A window between two timestep boundaries
+e = raised(lambda: context().add_constant_action(
+    action(start + timedelta(seconds=30), start + timedelta(hours=2), timedelta(hours=1))
+))
+assert type(e) is TimestepAlignmentError, repr(e)
+e = raised(lambda: OptimizerContext(start, prices, settlement_period=timedelta(seconds=90)))
+assert type(e) is TimestepAlignmentError, repr(e)
+
+# A window shorter than the action
+e = raised(lambda: context().add_constant_action(
+    action(start, start + timedelta(hours=1), timedelta(hours=2))
+))
+assert type(e) is InfeasibleError, repr(e)
+
+# A prognoses callable that fails, with its error as the cause
+def broken(curr, next):
+    raise ZeroDivisionError('no data')
+e = raised(lambda: OptimizerContext(start, PrognosesProvider(broken)))
+assert type(e) is PrognosesError, repr(e)
+assert type(e.__cause__) is ZeroDivisionError
+e = raised(lambda: PrognosesProvider.from_values([EuroPerWh(0.0003)] * 23, start, timedelta(hours=1)))
+assert type(e) is PrognosesError, repr(e)
+
+# Values too large for the optimizer, also when returned by prognoses
+e = raised(lambda: context().add_battery(
+    Battery(WattHour(1e30), Watt(1000.0), Watt(1000.0), WattHour(0.0), 2)
+))
+assert type(e) is UnitError, repr(e)
+e = raised(lambda: OptimizerContext(start, PrognosesProvider(lambda curr, next: EuroPerWh(1e9))))
+assert type(e) is UnitError, repr(e)
+
+# Plain invalid inputs raise the base class
+e = raised(lambda: OptimizerContext(start, prices, grid_limit=Watt(-1.0)))
+assert type(e) is OptimizerError, repr(e)
+
+assert issubclass(OutOfRange, OptimizerError)
+",
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_peak_price_spreads_identical_actions() {
+        let mut context = example_context();
+        // 12 W per action, so running both at once costs 12 Euro more
+        context.peak_price = Some(peak_price_to_internal(1.0, TimestepConfig::default()).unwrap());
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.99 },
+            ..Default::default()
+        };
+        let (_, rust_schedule, _) = RustSolver::new(context.to_rust().unwrap(), config, Some(1))
+            .solve(|_| Ok::<(), ()>(()))
+            .unwrap();
+        let first = rust_schedule.get_constant_action(1).unwrap();
+        let second = rust_schedule.get_constant_action(2).unwrap();
+        assert!(
+            first.get_end_time() <= second.get_start_time()
+                || second.get_end_time() <= first.get_start_time()
+        );
+        let schedule = Schedule {
             inner: rust_schedule,
             start_timestamp: context.start_time,
-        },
-    ))
-}
+        };
+        let (_, peak) = schedule.get_peak_consumption().unwrap();
+        assert!((peak.value - 12.0).abs() < 1e-9);
+        assert!(
+            (peak_price_from_internal(
+                peak_price_to_internal(0.01, TimestepConfig::default()).unwrap(),
+                TimestepConfig::default(),
+            ) - 0.01)
+                .abs()
+                < 1e-12
+        );
+    }
 
-#[pymodule]
-/// Python module initializer. Registers units, classes, and functions.
-fn electricity_price_optimizer_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    // Register units submodule
-    register_units_submodule(m)?;
-    // Register classes
-    m.add_class::<PrognosesProvider>()?;
-    m.add_class::<ConstantAction>()?;
-    m.add_class::<AssignedConstantAction>()?;
-    m.add_class::<VariableAction>()?;
-    m.add_class::<AssignedVariableAction>()?;
-    m.add_class::<Battery>()?;
-    m.add_class::<AssignedBattery>()?;
-    m.add_class::<OptimizerContext>()?;
-    m.add_class::<Schedule>()?;
+    #[test]
+    fn test_settlement_periods_are_clock_aligned_averages() {
+        let solve = |settlement_period: Option<u32>| {
+            let mut context = example_context();
+            // Seven minutes past, so the first quarter hour is cut short
+            context.start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 7, 0).unwrap();
+            context.peak_price =
+                Some(peak_price_to_internal(1.0, TimestepConfig::default()).unwrap());
+            context.settlement_period = settlement_period;
+            // A five minute spike from 6:15 to 6:20
+            context.constant_actions = vec![Rc::new(RustConstantAction::new(
+                Time::at_default_resolution(0, 8),
+                Time::at_default_resolution(0, 13),
+                Time::at_default_resolution(0, 5),
+                300,
+                1,
+            ))];
+            let config = AnnealingConfig {
+                explain_cost: true,
+                ..Default::default()
+            };
+            let (_, rust_schedule, _) =
+                RustSolver::new(context.to_rust().unwrap(), config, Some(1))
+                    .solve(|_| Ok::<(), ()>(()))
+                    .unwrap();
+            Schedule {
+                inner: rust_schedule,
+                start_timestamp: context.start_time,
+            }
+        };
+        let power = |energy: f64| {
+            Watt::from_energy_per_step(energy, timestep_duration(TimestepConfig::default())).value
+        };
+        let at = |minute: u32| Utc.with_ymd_and_hms(2025, 3, 1, 6, minute, 0).unwrap();
 
-    // Register functions
-    m.add_function(wrap_pyfunction!(run_simulated_annealing, m)?)?;
+        let schedule = solve(None);
+        let profile = schedule.get_settlement_profile().unwrap();
+        assert_eq!(profile.len(), STEPS_PER_DAY as usize);
+        let peak_charge = schedule
+            .inner
+            .get_objective_breakdown()
+            .unwrap()
+            .peak_charge;
+        assert_eq!(
+            peak_charge,
+            300 * peak_price_to_internal(1.0, TimestepConfig::default()).unwrap()
+        );
 
-    Ok(())
+        let schedule = solve(Some(15));
+        let profile = schedule.get_settlement_profile().unwrap();
+        assert_eq!(profile[0].0, at(7));
+        assert_eq!(profile[1].0, at(15));
+        assert_eq!(profile[2].0, at(30));
+        assert_eq!(profile[0].1.value, 0.0);
+        assert!((profile[1].1.value - power(100.0)).abs() < 1e-9);
+        // The peak charge is measured on the average of the quarter hour, not the spike
+        let peak_charge = schedule
+            .inner
+            .get_objective_breakdown()
+            .unwrap()
+            .peak_charge;
+        assert_eq!(
+            peak_charge,
+            100 * peak_price_to_internal(1.0, TimestepConfig::default()).unwrap()
+        );
+        // Reporting per timestep is unchanged
+        let (_, peak) = schedule.get_peak_consumption().unwrap();
+        assert!((peak.value - power(300.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_settlement_period_must_be_multiple_of_timestep() {
+        assert_eq!(
+            settlement_period_to_steps(TimeDelta::minutes(15), TimestepConfig::default()).unwrap(),
+            15 / MINUTES_PER_TIMESTEP
+        );
+        for period in [
+            TimeDelta::zero(),
+            TimeDelta::seconds(90),
+            TimeDelta::days(1) + TimeDelta::minutes(1),
+        ] {
+            assert!(
+                settlement_period_to_steps(period, TimestepConfig::default()).is_err(),
+                "{}",
+                period
+            );
+        }
+    }
+
+    #[test]
+    fn test_total_required_energy_counts_constant_actions_in_full() {
+        let mut context = example_context();
+        context.beyond_control_consumption = Prognoses::from_closure(|_| 2);
+        context.variable_actions = vec![Rc::new(RustVariableAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(3, 0),
+            1_000,
+            30,
+            3,
+        ))];
+        let total = context.total_required_energy().unwrap();
+        let expected = 2 * 200 * 30 + 2 * STEPS_PER_DAY as i64 + 1_000;
+        assert_eq!(total.to_milli_wh(), expected as f64);
+    }
+
+    #[test]
+    fn test_estimate_complexity_matches_the_built_flow() {
+        let mut context = example_context();
+        context.generated_electricity = Prognoses::from_closure(|t| (t.to_timestep() % 7) as i64);
+        context.batteries = vec![Rc::new(RustBattery::new(5_000, 1_000, 40, 60, 1.0, 9))];
+        context.feed_in_tariff = Some(Prognoses::from_closure(|_| 2));
+        context.max_export = Some(50);
+
+        Python::initialize();
+        Python::attach(|py| {
+            let flow = SmartHomeFlowBuilder::from_context(
+                &context.to_rust().unwrap().with_blocks_as_constant_actions(),
+            )
+            .build()
+            .get_flow_size();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 300).unwrap();
+            let estimate = context
+                .estimate_complexity(py, "linear", Some(&options))
+                .unwrap();
+            let get = |key: &str| -> usize {
+                estimate.get_item(key).unwrap().unwrap().extract().unwrap()
+            };
+            assert_eq!(get("nodes"), flow.nodes);
+            assert_eq!(get("edges"), flow.edges);
+            assert_eq!(get("iterations"), 300);
+            assert!(get("peak_memory_bytes") > 0);
+            let class: String = estimate
+                .get_item("runtime_class")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(class, "seconds");
+
+            options.set_item("factor", 0.5).unwrap();
+            assert!(
+                context
+                    .estimate_complexity(py, "linear", Some(&options))
+                    .is_err()
+            );
+        });
+    }
+
+    #[test]
+    fn test_default_profile_applies_only_when_action_is_skipped() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+            let hourly = |values: Vec<Bound<'_, PyAny>>| {
+                let values = PyList::new(py, values).unwrap();
+                PrognosesProvider::from_values(
+                    values.as_any(),
+                    start_time,
+                    Some(TimeDelta::hours(1)),
+                )
+                .unwrap()
+            };
+            let wh = |value: f64| WattHour { value }.into_bound_py_any(py).unwrap();
+            // Cheap at night, the thermostat heats in the expensive evening
+            let price = |hour: usize| if hour < 6 { 0.0001 } else { 0.0004 };
+            let thermostat = |hour: usize| {
+                if hour == 18 || hour == 19 {
+                    1_500.0
+                } else {
+                    0.0
+                }
+            };
+            let prices = hourly(
+                (0..24)
+                    .map(|hour| {
+                        EuroPerWh { value: price(hour) }
+                            .into_bound_py_any(py)
+                            .unwrap()
+                    })
+                    .collect(),
+            );
+            let load = hourly((0..24).map(|hour| wh(500.0 + thermostat(hour))).collect());
+            let default_profile = Py::new(
+                py,
+                hourly((0..24).map(|hour| wh(thermostat(hour))).collect()),
+            )
+            .unwrap();
+
+            let mut context = OptimizerContext::new(
+                py,
+                start_time,
+                &prices,
+                None,
+                None,
+                None,
+                None,
+                Some(TimeDelta::hours(1)),
+            )
+            .unwrap();
+            context
+                .add_beyond_control_consumption_prognoses(py, &load)
+                .unwrap();
+            let heater = ConstantAction::new(
+                start_time,
+                start_time + TimeDelta::hours(24),
+                TimeDelta::hours(2),
+                Watt { value: 2_000.0 },
+                1,
+                Some(default_profile),
+                false,
+            );
+            let heater = Py::new(py, heater).unwrap();
+            context
+                .add_constant_action(py, AnyConstantAction::Flat(heater.borrow(py)))
+                .unwrap();
+
+            let base_cost: f64 = (0..24).map(|hour| 500.0 * price(hour)).sum();
+            let total_energy = |schedule: &Schedule| {
+                schedule
+                    .inner
+                    .network_consumption
+                    .get_data()
+                    .iter()
+                    .sum::<i64>() as f64
+                    / 1000.0
+            };
+
+            // Scheduled: the heater runs at night instead of the thermostat in the evening
+            let options = PyDict::new(py);
+            options.set_item("iterations", 300).unwrap();
+            let result = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap();
+            let scheduled = result.schedule.borrow(py);
+            let start = scheduled
+                .get_constant_action(1)
+                .unwrap()
+                .get_start_time()
+                .unwrap();
+            assert!(start < start_time + TimeDelta::hours(6), "{}", start);
+            assert!((result.cost.value - (base_cost + 4_000.0 * 0.0001)).abs() < 1e-6);
+            assert_eq!(total_energy(&scheduled), 24.0 * 500.0 + 4_000.0);
+
+            // Skipped: the thermostat heats at its default profile
+            context.set_enabled(1, false).unwrap();
+            let (cost, skipped) = run_exact(py, &context).unwrap();
+            assert!(skipped.get_constant_action(1).is_none());
+            assert!((cost.value - (base_cost + 3_000.0 * 0.0004)).abs() < 1e-6);
+            assert_eq!(total_energy(&skipped), 24.0 * 500.0 + 3_000.0);
+        });
+    }
+
+    #[test]
+    fn test_coarser_resolutions_agree_on_the_cost() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+            let at = |hour: i64| start_time + TimeDelta::hours(hour);
+            let prices = (0..24).map(|hour| EuroPerWh {
+                value: ((hour * 7) % 24 + 1) as f64 / 100_000.0,
+            });
+            let prices = PyList::new(py, prices).unwrap();
+            let price = PrognosesProvider::from_values(
+                prices.as_any(),
+                start_time,
+                Some(TimeDelta::hours(1)),
+            )
+            .unwrap();
+            let globals = PyDict::new(py);
+            globals
+                .set_item("WattHour", py.get_type::<WattHour>())
+                .unwrap();
+            // 500 W whatever the length of the interval
+            let base_load = PrognosesProvider::new(
+                py.eval(
+                    c"lambda curr, next: WattHour(500 * (next - curr).total_seconds() / 3600)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind(),
+            );
+            let context = |minutes: i64, constant: bool| {
+                let mut context = OptimizerContext::new(
+                    py,
+                    start_time,
+                    &price,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(TimeDelta::minutes(minutes)),
+                )
+                .unwrap();
+                context
+                    .add_beyond_control_consumption_prognoses(py, &base_load)
+                    .unwrap();
+                let battery = Battery::new(
+                    WattHour { value: 5_000.0 },
+                    Watt { value: 2_000.0 },
+                    Watt { value: 2_000.0 },
+                    WattHour { value: 1_000.0 },
+                    1,
+                    0.9,
+                    true,
+                    WattHour::default(),
+                    None,
+                    0.02,
+                    EuroPerWh::default(),
+                )
+                .unwrap();
+                context.add_battery(&battery).unwrap();
+                let charger = VariableAction::new(
+                    at(6),
+                    at(20),
+                    WattHour { value: 6_000.0 },
+                    MaxConsumption::Constant(Watt { value: 3_000.0 }),
+                    2,
+                    None,
+                    None,
+                    0.1,
+                    false,
+                    false,
+                );
+                context.add_variable_action(py, &charger).unwrap();
+                if constant {
+                    let action = ConstantAction::new(
+                        at(10),
+                        at(13),
+                        TimeDelta::hours(2),
+                        Watt { value: 2_000.0 },
+                        3,
+                        None,
+                        false,
+                    );
+                    let action = Py::new(py, action).unwrap();
+                    context
+                        .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                        .unwrap();
+                }
+                context
+            };
+            let options = PyDict::new(py);
+            options.set_item("iterations", 300).unwrap();
+            let cost = |minutes: i64, constant: bool| {
+                let context = context(minutes, constant);
+                let (cost, schedule) = if constant {
+                    let result = run_simulated_annealing(
+                        py,
+                        &context,
+                        Some(1),
+                        None,
+                        None,
+                        Some("linear"),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        Some(&options),
+                    )
+                    .unwrap();
+                    (result.cost, result.schedule)
+                } else {
+                    let (cost, schedule) = run_exact(py, &context).unwrap();
+                    (cost, Py::new(py, schedule).unwrap())
+                };
+                let schedule = schedule.borrow(py);
+                assert_eq!(
+                    schedule.inner.network_consumption.len() as i64,
+                    24 * 60 / minutes
+                );
+                // The level at the end of the horizon is on the grid of every resolution
+                let battery = schedule.get_battery(1).unwrap();
+                battery.get_charge_level(py, at(24), false).unwrap();
+                assert!(
+                    battery
+                        .get_charge_level(py, at(24) + TimeDelta::minutes(minutes), false)
+                        .is_err()
+                );
+                cost.value
+            };
+
+            for constant in [false, true] {
+                let fine = cost(15, constant);
+                let coarse = cost(60, constant);
+                // Energies are rounded per timestep, and electricity stored during a timestep
+                // only self-discharges from the next one on
+                assert!(
+                    (fine - coarse).abs() <= 0.02 * fine.abs(),
+                    "{} at 15 minutes, {} at an hour",
+                    fine,
+                    coarse
+                );
+            }
+
+            // Times and durations must lie on the coarser grid
+            let mut hourly = context(60, false);
+            let action = ConstantAction::new(
+                at(10),
+                at(13),
+                TimeDelta::minutes(90),
+                Watt { value: 2_000.0 },
+                3,
+                None,
+                false,
+            );
+            let action = Py::new(py, action).unwrap();
+            let err = hourly
+                .add_constant_action(py, AnyConstantAction::Flat(action.borrow(py)))
+                .unwrap_err();
+            assert!(err.is_instance_of::<TimestepAlignmentError>(py));
+            for minutes in [0, 7, 25 * 60] {
+                let Err(err) = OptimizerContext::new(
+                    py,
+                    start_time,
+                    &price,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(TimeDelta::minutes(minutes)),
+                ) else {
+                    panic!(
+                        "A resolution of {} minutes should have been rejected",
+                        minutes
+                    );
+                };
+                assert!(err.is_instance_of::<TimestepAlignmentError>(py));
+            }
+        });
+    }
+
+    #[test]
+    fn test_schedule_reports_the_same_power_at_every_resolution() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+            let at = |hour: i64| start_time + TimeDelta::hours(hour);
+            let prices = (0..24).map(|hour| EuroPerWh {
+                value: ((hour * 7) % 24 + 1) as f64 / 100_000.0,
+            });
+            let prices = PyList::new(py, prices).unwrap();
+            let price = PrognosesProvider::from_values(
+                prices.as_any(),
+                start_time,
+                Some(TimeDelta::hours(1)),
+            )
+            .unwrap();
+            let globals = PyDict::new(py);
+            globals
+                .set_item("WattHour", py.get_type::<WattHour>())
+                .unwrap();
+            // 500 W whatever the length of the interval
+            let base_load = PrognosesProvider::new(
+                py.eval(
+                    c"lambda curr, next: WattHour(500 * (next - curr).total_seconds() / 3600)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind(),
+            );
+            // Solves the same day with timesteps of `minutes` and returns the power drawn from
+            // the grid and by the charger, averaged over every hour
+            let hourly_power = |minutes: i64| {
+                let mut context = OptimizerContext::new(
+                    py,
+                    start_time,
+                    &price,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(TimeDelta::minutes(minutes)),
+                )
+                .unwrap();
+                context
+                    .add_beyond_control_consumption_prognoses(py, &base_load)
+                    .unwrap();
+                // Runs at full power in the two cheapest of the four hours of its window
+                let charger = VariableAction::new(
+                    at(6),
+                    at(10),
+                    WattHour { value: 6_000.0 },
+                    MaxConsumption::Constant(Watt { value: 3_000.0 }),
+                    2,
+                    None,
+                    None,
+                    0.1,
+                    false,
+                    false,
+                );
+                context.add_variable_action(py, &charger).unwrap();
+                let (_, schedule) = run_exact(py, &context).unwrap();
+
+                let per_hour = (60 / minutes) as usize;
+                let average = |profile: Vec<(DateTime<Utc>, Watt)>| -> Vec<f64> {
+                    assert!(
+                        profile
+                            .windows(2)
+                            .all(|pair| pair[1].0 - pair[0].0 == TimeDelta::minutes(minutes))
+                    );
+                    profile
+                        .chunks(per_hour)
+                        .map(|hour| {
+                            hour.iter().map(|(_, watt)| watt.value).sum::<f64>() / per_hour as f64
+                        })
+                        .collect()
+                };
+                (
+                    average(schedule.get_network_consumption_profile().unwrap()),
+                    average(
+                        schedule
+                            .get_variable_action(2)
+                            .unwrap()
+                            .get_consumption_profile()
+                            .unwrap(),
+                    ),
+                )
+            };
+
+            let (hourly_network, hourly_charger) = hourly_power(60);
+            assert_eq!(hourly_network.len(), 24);
+            assert_eq!(hourly_charger, vec![0.0, 3_000.0, 3_000.0, 0.0]);
+            assert_eq!(hourly_network[8], 3_500.0);
+            assert_eq!(hourly_network[9], 500.0);
+            for minutes in [1, 15] {
+                let (network, charger) = hourly_power(minutes);
+                assert_eq!(charger, hourly_charger, "{} minutes", minutes);
+                // Energies are whole mWh per timestep
+                let rounding = Watt::from_energy_per_step(1.0, TimeDelta::minutes(minutes)).value;
+                for (hour, (fine, coarse)) in network.iter().zip(&hourly_network).enumerate() {
+                    assert!(
+                        (fine - coarse).abs() <= rounding,
+                        "{} W at {} minutes, {} W hourly at hour {}",
+                        fine,
+                        minutes,
+                        coarse,
+                        hour
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_network_consumption_balances_load() {
+        let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+        let context = OptimizerContext {
+            electricity_price: Prognoses::from_closure(|t| (t.to_timestep() % 180) as i64 + 1),
+            generated_electricity: Prognoses::from_closure(|t| (t.to_timestep() % 7) as i64),
+            beyond_control_consumption: Prognoses::from_closure(|t| {
+                (t.to_timestep() % 5) as i64 + 10
+            }),
+            batteries: vec![Rc::new(RustBattery::new(5_000, 1_000, 40, 60, 1.0, 1))],
+            constant_actions: vec![],
+            variable_actions: vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(8, 0),
+                3_000,
+                30,
+                2,
+            ))],
+            feed_in_tariff: None,
+            max_export: None,
+            network_fee: None,
+            co2_intensity: None,
+            net_metering: None,
+            grid_limit: None,
+            peak_price: None,
+            settlement_period: None,
+            fixed_battery_plans: vec![],
+            precedences: vec![],
+            min_gaps: vec![],
+            exclusion_groups: vec![],
+            disabled: BTreeSet::new(),
+            absolute_actions: vec![],
+            plausibility_limits: PlausibilityLimits::default(),
+            start_time,
+            timestep: TimestepConfig::default(),
+        };
+        let rust_context = context.to_rust().unwrap();
+        let schedule = Schedule {
+            inner: SmartHomeFlowBuilder::from_context(&rust_context)
+                .build()
+                .get_schedule()
+                .unwrap(),
+            start_timestamp: start_time,
+        };
+
+        Python::initialize();
+        Python::attach(|py| {
+            let network = schedule.get_network_consumption_profile().unwrap();
+            assert_eq!(network.len(), STEPS_PER_DAY as usize);
+            let battery = schedule.get_battery(1).unwrap();
+            let action = schedule.get_variable_action(2).unwrap();
+            let to_milli_wh = |power: &Watt| {
+                power.to_energy_per_step(timestep_duration(TimestepConfig::default()))
+            };
+            for (t, (time, import)) in network.iter().enumerate().take(STEPS_PER_DAY as usize - 1) {
+                let step = Time::from_timestep(t as u32);
+                assert_eq!(
+                    to_milli_wh(&schedule.get_network_consumption(py, *time, false).unwrap()),
+                    to_milli_wh(import)
+                );
+                let generated = *context.generated_electricity.get(step).unwrap() as f64;
+                let battery_delta = battery
+                    .get_charge_level(
+                        py,
+                        *time + timestep_duration(TimestepConfig::default()),
+                        false,
+                    )
+                    .unwrap()
+                    .to_milli_wh()
+                    - battery
+                        .get_charge_level(py, *time, false)
+                        .unwrap()
+                        .to_milli_wh();
+                let action_consumption = if Time::at_default_resolution(2, 0) <= step
+                    && step < Time::at_default_resolution(8, 0)
+                {
+                    to_milli_wh(&action.get_consumption(py, *time, false).unwrap())
+                } else {
+                    0.0
+                };
+                let load = *context.beyond_control_consumption.get(step).unwrap() as f64
+                    + action_consumption;
+                let balance = to_milli_wh(import) + generated - battery_delta;
+                assert!(
+                    (balance - load).abs() < 1e-6,
+                    "{}: {} != {}",
+                    time,
+                    balance,
+                    load
+                );
+            }
+        });
+    }
+
+    /// Battery charged during timestep 100 and discharged during timestep 200, starting and
+    /// ending with 20 mWh.
+    fn single_charge_schedule() -> Schedule {
+        let electricity_price =
+            Prognoses::from_closure(|t| if t.to_timestep() == 100 { 1 } else { 1000 });
+        let consumption = Prognoses::from_closure(|t| if t.to_timestep() == 200 { 60 } else { 0 });
+        let battery = RustBattery::new(1000, 20, 60, 60, 1.0, 1).with_final_charge_target(20);
+        Schedule {
+            inner: SmartHomeFlowBuilder::new(
+                &Prognoses::from_closure(|_| 0),
+                &electricity_price,
+                &consumption,
+                1.0,
+            )
+            .add_battery(&Rc::new(battery))
+            .build()
+            .get_schedule()
+            .unwrap(),
+            start_timestamp: Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_diff_of_a_schedule_and_a_shifted_copy() {
+        Python::initialize();
+        Python::attach(|py| {
+            let context = example_context();
+            let options = PyDict::new(py);
+            options.set_item("iterations", 20).unwrap();
+            let (_, schedule) = run_simulated_annealing(
+                py,
+                &context,
+                Some(1),
+                None,
+                None,
+                Some("linear"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(&options),
+            )
+            .unwrap()
+            .unpack(py);
+
+            let same = schedule.diff(&schedule).unwrap();
+            assert_eq!(same.stability, 1.0);
+            let zero = BTreeMap::from([(1, TimeDelta::zero()), (2, TimeDelta::zero())]);
+            assert_eq!(same.constant_actions, zero);
+            assert!(same.only_in_this.is_empty() && same.only_in_other.is_empty());
+
+            // Action 1 moves by half an hour within its window, action 2 is left out
+            let mut inner = schedule.inner.clone();
+            let start = inner.constant_actions[&1].get_start_time();
+            let (moved, shift) = if start < Time::at_default_resolution(3, 0) {
+                (
+                    start + Time::at_default_resolution(0, 30),
+                    TimeDelta::minutes(30),
+                )
+            } else {
+                (
+                    start - Time::at_default_resolution(0, 30),
+                    TimeDelta::minutes(-30),
+                )
+            };
+            *inner
+                .constant_actions
+                .get_mut(&1)
+                .unwrap()
+                .get_start_time_mut() = moved;
+            inner.constant_actions.remove(&2);
+            let shifted = Schedule {
+                inner,
+                start_timestamp: schedule.start_timestamp,
+            };
+            let diff = schedule.diff(&shifted).unwrap();
+            assert_eq!(diff.constant_actions, BTreeMap::from([(1, shift)]));
+            assert_eq!(diff.only_in_this, vec![("constant_action", 2)]);
+            assert!(diff.only_in_other.is_empty());
+            // 30 of 330 minutes of slack and a missing action
+            let expected = 1.0 - (30.0 / 330.0 + 1.0) / 2.0;
+            assert!((diff.stability - expected).abs() < 1e-12);
+            let reverse = shifted.diff(&schedule).unwrap();
+            assert_eq!(reverse.only_in_other, vec![("constant_action", 2)]);
+
+            let later = Schedule {
+                inner: schedule.inner.clone(),
+                start_timestamp: schedule.start_timestamp + TimeDelta::hours(1),
+            };
+            let Err(err) = schedule.diff(&later) else {
+                panic!("Schedules over different horizons can't be compared");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_concat_merges_overlapping_solves() {
+        Python::initialize();
+        Python::attach(|py| {
+            let solve = |start_time: DateTime<Utc>, initial_level: i64| {
+                let mut context = example_context();
+                context.start_time = start_time;
+                context.constant_actions = vec![];
+                context.beyond_control_consumption = Prognoses::from_closure(|_| 30);
+                context.batteries = vec![Rc::new(RustBattery::new(
+                    20_000,
+                    initial_level,
+                    500,
+                    500,
+                    1.0,
+                    1,
+                ))];
+                context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                    Time::at_default_resolution(0, 0),
+                    Time::at_default_resolution(20, 0),
+                    30_000,
+                    100,
+                    7,
+                ))];
+                run_exact(py, &context).unwrap().1
+            };
+            let first = solve(example_context().start_time, 5_000);
+            let seam = first.start_timestamp + TimeDelta::hours(12);
+            let battery = first.inner.get_battery(1).unwrap();
+            let seam_level = *battery
+                .get_charge_level(Time::at_default_resolution(12, 0))
+                .unwrap();
+            let next = solve(seam, seam_level);
+            let merged = first.concat(py, &next, WattHour::default()).unwrap();
+
+            let minute = TimeDelta::minutes(1);
+            // Grid import, battery level and consumption of the variable action
+            let probe = |schedule: &Schedule, time| {
+                let battery = schedule.get_battery(1).unwrap();
+                let action = schedule.get_variable_action(7).unwrap();
+                (
+                    schedule
+                        .get_network_consumption(py, time, false)
+                        .unwrap()
+                        .value,
+                    battery.get_charge_level(py, time, false).unwrap().value,
+                    action.get_consumption(py, time, false).unwrap().value,
+                )
+            };
+            assert_eq!(probe(&merged, seam - minute), probe(&first, seam - minute));
+            assert_eq!(probe(&merged, seam), probe(&next, seam));
+            assert_eq!(probe(&merged, seam + minute), probe(&next, seam + minute));
+            let end = first.start_timestamp + TimeDelta::days(1);
+            let final_level = |schedule: &Schedule| {
+                let battery = schedule.get_battery(1).unwrap();
+                battery.get_charge_level(py, end, false).unwrap().value
+            };
+            assert_eq!(final_level(&merged), final_level(&next));
+            assert_eq!(
+                merged.get_merge_notes(),
+                [
+                    MergeNote::VariableActionReplaced { id: 7 },
+                    MergeNote::VariableActionCutAtEnd { id: 7 },
+                ]
+                .map(|note| note.to_string())
+            );
+
+            let moved = |start_timestamp| Schedule {
+                inner: next.inner.clone(),
+                start_timestamp,
+            };
+            let err = first
+                .concat(
+                    py,
+                    &moved(seam + TimeDelta::seconds(30)),
+                    WattHour::default(),
+                )
+                .err()
+                .unwrap();
+            assert!(err.value(py).to_string().contains("not on the timesteps"));
+            let err = first
+                .concat(py, &moved(end), WattHour::default())
+                .err()
+                .unwrap();
+            assert!(err.is_instance_of::<OutOfRange>(py));
+            let mut drained = moved(seam);
+            let levels = Prognoses::from_closure(|_| seam_level - 2);
+            let battery = RustAssignedBattery::new(battery.get_battery().clone(), levels);
+            drained.inner.batteries = HashMap::from([(1, battery)]);
+            let err = first
+                .concat(py, &drained, WattHour { value: 0.001 })
+                .err()
+                .unwrap();
+            assert!(
+                err.value(py)
+                    .to_string()
+                    .starts_with("Battery 1 has charge level")
+            );
+        });
+    }
+
+    #[test]
+    fn test_charge_speed_is_charge_during_timestep() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schedule = single_charge_schedule();
+            let at = |step: i64| {
+                schedule.start_timestamp
+                    + timestep_duration(TimestepConfig::default()) * step as i32
+            };
+            let battery = schedule.get_battery(1).unwrap();
+            let level = |step| battery.get_charge_level(py, at(step), false).unwrap().value;
+            let speed = |step| battery.get_charge_speed(py, at(step), false).unwrap().value;
+
+            // 60 mWh per minute is 3.6 W, charged during timestep 100.
+            assert_eq!((level(99), level(100), level(101)), (0.02, 0.02, 0.08));
+            assert_eq!((speed(99), speed(100), speed(101)), (0.0, 3.6, 0.0));
+            assert_eq!((level(200), level(201)), (0.08, 0.02));
+            assert_eq!((speed(199), speed(200), speed(201)), (0.0, -3.6, 0.0));
+            // The last timestep ends with the final level instead of an empty battery.
+            let last = STEPS_PER_DAY as i64 - 1;
+            assert_eq!((speed(last), level(last + 1)), (0.0, 0.02));
+            assert!(battery.get_charge_speed(py, at(last + 1), false).is_err());
+            assert!(battery.get_charge_level(py, at(last + 2), false).is_err());
+        });
+    }
+
+    #[test]
+    fn test_headroom_is_rate_left_after_the_plan() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schedule = single_charge_schedule();
+            let at = |step: i64| {
+                schedule.start_timestamp
+                    + timestep_duration(TimestepConfig::default()) * step as i32
+            };
+            let battery = schedule.get_battery(1).unwrap();
+            let discharge = |step| {
+                let headroom = battery.get_discharge_headroom(py, at(step), false);
+                headroom.unwrap().value
+            };
+            let charge = |step| {
+                battery
+                    .get_charge_headroom(py, at(step), false)
+                    .unwrap()
+                    .value
+            };
+            let flex = |step| schedule.get_total_flex(py, at(step), false).unwrap().value;
+
+            // Before charging only the initial 20 mWh can be discharged, 60 mWh per minute is 3.6 W
+            assert_eq!((discharge(99), charge(99), flex(99)), (1.2, 3.6, 1.2));
+            // Charging at the full rate leaves no charge headroom but all of the output rate
+            assert_eq!((discharge(100), charge(100)), (3.6, 0.0));
+            assert_eq!((discharge(200), charge(200), flex(200)), (0.0, 3.6, 0.0));
+            assert!(battery.get_discharge_headroom(py, at(-1), false).is_err());
+            let last = STEPS_PER_DAY as i64 - 1;
+            assert_eq!(
+                battery
+                    .get_charge_headroom(py, at(last + 1), true)
+                    .unwrap()
+                    .value,
+                charge(last)
+            );
+        });
+    }
+
+    #[test]
+    fn test_time_accessors_clamp_or_raise_out_of_range() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schedule = single_charge_schedule();
+            let start = schedule.start_timestamp;
+            let end = start + TimeDelta::days(1);
+            let battery = schedule.get_battery(1).unwrap();
+            let level = |time, clamp| {
+                battery
+                    .get_charge_level(py, time, clamp)
+                    .map(|level| level.value)
+            };
+
+            // Clamped queries give the initial and the final value.
+            let before = start - TimeDelta::minutes(5);
+            assert_eq!(level(before, true).unwrap(), 0.02);
+            assert_eq!(level(end + TimeDelta::minutes(1), true).unwrap(), 0.02);
+            assert_eq!(
+                battery.get_charge_speed(py, end, true).unwrap().value,
+                battery
+                    .get_charge_speed(
+                        py,
+                        end - timestep_duration(TimestepConfig::default()),
+                        false
+                    )
+                    .unwrap()
+                    .value
+            );
+            assert_eq!(
+                schedule
+                    .get_network_consumption(py, before, true)
+                    .unwrap()
+                    .value,
+                0.0
+            );
+
+            // Strict queries raise OutOfRange, which is also a ValueError.
+            let err = level(before, false).unwrap_err();
+            assert!(err.is_instance_of::<OutOfRange>(py));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let attribute = |err: &PyErr, name: &str| -> DateTime<Utc> {
+                err.value(py).getattr(name).unwrap().extract().unwrap()
+            };
+            assert_eq!(attribute(&err, "time"), before);
+            assert_eq!(attribute(&err, "start"), start);
+            assert_eq!(attribute(&err, "end"), end);
+
+            let err = schedule
+                .get_network_consumption(py, end, false)
+                .unwrap_err();
+            assert!(err.is_instance_of::<OutOfRange>(py));
+            assert_eq!(
+                attribute(&err, "end"),
+                end - timestep_duration(TimestepConfig::default())
+            );
+            assert_eq!(
+                err.value(py).to_string(),
+                "Time 2025-03-02 06:00:00 UTC is out of range, valid times are 2025-03-01 06:00:00 UTC to 2025-03-02 05:59:00 UTC"
+            );
+            assert!(schedule.get_grid_export(py, before, false).is_err());
+            assert!(battery.get_green_charge(py, end, false).is_err());
+        });
+    }
+
+    #[test]
+    fn test_min_off_time_is_kept_across_rolling_horizons() {
+        Python::initialize();
+        Python::attach(|py| {
+            // The first horizon has to run action 1 from 6:00 to 7:00
+            let mut first = example_context();
+            first.constant_actions = vec![Rc::new(RustConstantAction::new(
+                Time::at_default_resolution(0, 0),
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(1, 0),
+                200,
+                1,
+            ))];
+            let (_, previous) = solve_seeded(py, &first);
+            let run_end = previous
+                .get_constant_action(1)
+                .unwrap()
+                .get_end_time()
+                .unwrap();
+            assert_eq!(run_end, first.start_time + TimeDelta::hours(1));
+
+            // The next horizon starts at 7:00 and is cheapest at its start
+            let next = |min_off_time: TimeDelta, end_before: Time| {
+                let mut context = example_context();
+                context.start_time = run_end;
+                context.constant_actions = vec![Rc::new(RustConstantAction::new(
+                    Time::at_default_resolution(0, 0),
+                    end_before,
+                    Time::at_default_resolution(1, 0),
+                    200,
+                    1,
+                ))];
+                context
+                    .respect_min_off_times(&previous, BTreeMap::from([(1, min_off_time)]))
+                    .map(|()| context)
+            };
+            let rested = next(TimeDelta::minutes(30), Time::at_default_resolution(6, 0)).unwrap();
+            assert_eq!(
+                rested.constant_actions[0].get_start_from(),
+                Time::at_default_resolution(0, 30)
+            );
+            let (_, schedule) = solve_seeded(py, &rested);
+            let start = schedule
+                .get_constant_action(1)
+                .unwrap()
+                .get_start_time()
+                .unwrap();
+            assert!(start - run_end >= TimeDelta::minutes(30));
+
+            // Without an off time the window stays
+            let unchanged = next(TimeDelta::zero(), Time::at_default_resolution(6, 0)).unwrap();
+            assert_eq!(
+                unchanged.constant_actions[0].get_start_from(),
+                Time::at_default_resolution(0, 0)
+            );
+
+            // A rest period that leaves too little of the window is reported
+            let Err(err) = next(TimeDelta::minutes(30), Time::at_default_resolution(1, 15)) else {
+                panic!("The rest period left too little of the window");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert_eq!(
+                err.value(py).to_string(),
+                "Constant action 1 has to rest until 2025-03-01 07:30:00 UTC after its previous \
+                 run ended at 2025-03-01 07:00:00 UTC, its duration of 60 minutes no longer fits \
+                 before end_before 2025-03-01 08:15:00 UTC"
+            );
+            assert!(next(TimeDelta::minutes(-5), Time::at_default_resolution(6, 0)).is_err());
+            let mut context = example_context();
+            let err = context
+                .respect_min_off_times(&previous, BTreeMap::from([(9, TimeDelta::minutes(30))]))
+                .unwrap_err();
+            assert!(err.to_string().contains("No constant action with id 9"));
+        });
+    }
+
+    /// Solve a context with constant actions with seeded, short simulated annealing.
+    fn solve_seeded<'py>(
+        py: Python<'py>,
+        context: &OptimizerContext,
+    ) -> (Euro, PyRef<'py, Schedule>) {
+        let options = PyDict::new(py);
+        options.set_item("iterations", 200).unwrap();
+        run_simulated_annealing(
+            py,
+            context,
+            Some(1),
+            None,
+            None,
+            Some("linear"),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(&options),
+        )
+        .unwrap()
+        .unpack(py)
+    }
+
+    #[test]
+    fn test_out_of_range_queries_name_the_asset() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.constant_actions = vec![];
+            context.batteries = vec![Rc::new(RustBattery::new(2_000, 500, 100, 100, 1.0, 3))];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(3, 0),
+                1_000,
+                20,
+                7,
+            ))];
+            let (_, schedule) = run_exact(py, &context).unwrap();
+            let battery = schedule.get_battery(3).unwrap();
+            let action = schedule.get_variable_action(7).unwrap();
+            let start = context.start_time;
+            let step = timestep_duration(TimestepConfig::default());
+            let end = start + TimeDelta::days(1);
+
+            // Every query gives a value from the first to the last valid time and raises
+            // OutOfRange naming the asset one timestep outside of them
+            let check = |query: &dyn Fn(DateTime<Utc>) -> PyResult<f64>,
+                         first: DateTime<Utc>,
+                         last: DateTime<Utc>,
+                         asset: &str,
+                         id: u32| {
+                assert!(query(first).is_ok());
+                assert!(query(last).is_ok());
+                for time in [first - step, last + step] {
+                    let err = query(time).unwrap_err();
+                    assert!(err.is_instance_of::<OutOfRange>(py));
+                    assert!(err.is_instance_of::<PyValueError>(py));
+                    let value = err.value(py);
+                    let attribute = |name: &str| -> DateTime<Utc> {
+                        value.getattr(name).unwrap().extract().unwrap()
+                    };
+                    assert_eq!(attribute("time"), time);
+                    assert_eq!(attribute("start"), first);
+                    assert_eq!(attribute("end"), last);
+                    let asset_id: Option<u32> =
+                        value.getattr("asset_id").unwrap().extract().unwrap();
+                    assert_eq!(asset_id, Some(id));
+                    assert_eq!(
+                        value.to_string(),
+                        format!(
+                            "Time {} is out of range for {} {}, valid times are {} to {}",
+                            time, asset, id, first, last
+                        )
+                    );
+                }
+            };
+            check(
+                &|time| {
+                    battery
+                        .get_charge_level(py, time, false)
+                        .map(|level| level.value)
+                },
+                start,
+                end,
+                "battery",
+                3,
+            );
+            check(
+                &|time| {
+                    battery
+                        .get_charge_speed(py, time, false)
+                        .map(|speed| speed.value)
+                },
+                start,
+                end - step,
+                "battery",
+                3,
+            );
+            check(
+                &|time| {
+                    action
+                        .get_consumption(py, time, false)
+                        .map(|power| power.value)
+                },
+                start + TimeDelta::hours(1),
+                start + TimeDelta::hours(3) - step,
+                "variable action",
+                7,
+            );
+
+            // Queries of the whole schedule don't name an asset
+            let err = schedule
+                .get_network_consumption(py, end, false)
+                .unwrap_err();
+            assert!(err.value(py).getattr("asset_id").unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_unaligned_start_time_conversions() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 30).unwrap();
+            let grid = TimeGrid::new(start_time, TimestepConfig::default());
+            let at = |minute: u32, second: u32| {
+                Utc.with_ymd_and_hms(2025, 3, 1, 6, minute, second).unwrap()
+            };
+            // The start time and boundaries are accepted, times in between are not
+            let action = |start_from: DateTime<Utc>| {
+                ConstantAction::new(
+                    start_from,
+                    at(30, 0),
+                    TimeDelta::minutes(5),
+                    Watt { value: 100.0 },
+                    1,
+                    None,
+                    false,
+                )
+                .to_rust(py, TimeGrid::new(start_time, TimestepConfig::default()))
+            };
+            assert_eq!(
+                action(start_time).unwrap().get_start_from().to_timestep(),
+                0
+            );
+            assert_eq!(action(at(2, 0)).unwrap().get_start_from().to_timestep(), 2);
+            let err = action(at(2, 30)).unwrap_err();
+            assert!(err.to_string().contains("not on a timestep boundary"));
+            let err = action(at(0, 0)).unwrap_err();
+            assert!(err.to_string().contains("is before start time"));
+
+            // Queries fall into the timestep they are in, the first one starts at start_time
+            let query = |time: DateTime<Utc>| {
+                time_in_range(py, time, grid, schedule_range(grid), false, None)
+            };
+            assert_eq!(query(at(0, 45)).unwrap().to_timestep(), 0);
+            assert_eq!(query(at(1, 0)).unwrap().to_timestep(), 1);
+            assert_eq!(query(at(1, 59)).unwrap().to_timestep(), 1);
+            assert!(
+                query(at(0, 0))
+                    .unwrap_err()
+                    .is_instance_of::<OutOfRange>(py)
+            );
+        });
+    }
+
+    #[test]
+    fn test_extreme_datetimes_raise_instead_of_panicking() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Nanosecond timestamps only cover 1677 to 2262
+            for start_time in [
+                Utc.with_ymd_and_hms(1000, 1, 1, 0, 0, 30).unwrap(),
+                Utc.with_ymd_and_hms(9000, 6, 1, 12, 0, 30).unwrap(),
+            ] {
+                let next = TimeGrid::new(start_time, TimestepConfig::default())
+                    .to_datetime(Time::from_timestep(1))
+                    .unwrap();
+                assert_eq!(next, start_time + TimeDelta::seconds(30));
+                assert_eq!(
+                    TimeGrid::new(start_time, TimestepConfig::default())
+                        .to_datetime(Time::from_timestep(0))
+                        .unwrap(),
+                    start_time
+                );
+                assert_eq!(
+                    TimeGrid::new(start_time, TimestepConfig::default())
+                        .to_time(next)
+                        .unwrap()
+                        .to_timestep(),
+                    1
+                );
+
+                let mut context = example_context();
+                context.start_time = start_time;
+                let context = context.to_rust().unwrap();
+                assert_eq!(context.get_first_timestep_fraction(), 0.5);
+            }
+            // Rounds down to the timestep before 1970 as well
+            let start_time = Utc.with_ymd_and_hms(1960, 1, 1, 0, 0, 30).unwrap();
+            assert_eq!(
+                TimeGrid::new(start_time, TimestepConfig::default())
+                    .to_datetime(Time::from_timestep(2))
+                    .unwrap(),
+                Utc.with_ymd_and_hms(1960, 1, 1, 0, 2, 0).unwrap()
+            );
+            // Unchanged in the usual range
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 20).unwrap();
+            assert_eq!(
+                TimeGrid::new(start_time, TimestepConfig::default())
+                    .to_datetime(Time::from_timestep(90))
+                    .unwrap(),
+                Utc.with_ymd_and_hms(2025, 3, 1, 7, 30, 0).unwrap()
+            );
+
+            let err = TimeGrid::new(DateTime::<Utc>::MAX_UTC, TimestepConfig::default())
+                .to_datetime(Time::from_timestep(10))
+                .unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(
+                err.to_string()
+                    .contains("outside the supported datetime range")
+            );
+            let err = TimeGrid::new(start_time, TimestepConfig::default())
+                .to_time(DateTime::<Utc>::MAX_UTC)
+                .unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_schedule_json_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.generated_electricity = Prognoses::from_closure(|t| {
+                if t < Time::at_default_resolution(8, 0) {
+                    100
+                } else {
+                    0
+                }
+            });
+            context.batteries = vec![Rc::new(
+                RustBattery::new(20_000, 1_000, 500, 500, 0.9, 4).with_grid_charging_allowed(false),
+            )];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(10, 0),
+                3_000,
+                50,
+                3,
+            ))];
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.7 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let (_, schedule) = solver.solve(py, None, Some(1)).unwrap();
+
+            let json = schedule.to_json().unwrap();
+            let reloaded = Schedule::from_json(&json, context.start_time).unwrap();
+            assert_eq!(reloaded.start_timestamp, schedule.start_timestamp);
+
+            let values = |profile: Vec<(DateTime<Utc>, Watt)>| -> Vec<_> {
+                profile.into_iter().map(|(t, w)| (t, w.value)).collect()
+            };
+            for id in 1..=2 {
+                let (a, b) = (
+                    schedule.get_constant_action(id).unwrap(),
+                    reloaded.get_constant_action(id).unwrap(),
+                );
+                assert_eq!(a.get_start_time().unwrap(), b.get_start_time().unwrap());
+                assert_eq!(a.get_end_time().unwrap(), b.get_end_time().unwrap());
+                assert_eq!(
+                    values(a.get_consumption_profile().unwrap()),
+                    values(b.get_consumption_profile().unwrap())
+                );
+            }
+            let (a, b) = (
+                schedule.get_variable_action(3).unwrap(),
+                reloaded.get_variable_action(3).unwrap(),
+            );
+            assert_eq!(
+                values(a.get_consumption_profile().unwrap()),
+                values(b.get_consumption_profile().unwrap())
+            );
+            assert_eq!(a.get_total_assigned().value, b.get_total_assigned().value);
+            assert_eq!(
+                values(schedule.get_network_consumption_profile().unwrap()),
+                values(reloaded.get_network_consumption_profile().unwrap())
+            );
+
+            let (a, b) = (
+                schedule.get_battery(4).unwrap(),
+                reloaded.get_battery(4).unwrap(),
+            );
+            for step in 0..=STEPS_PER_DAY {
+                let time =
+                    context.start_time + timestep_duration(TimestepConfig::default()) * step as i32;
+                assert_eq!(
+                    a.get_charge_level(py, time, false).unwrap().value,
+                    b.get_charge_level(py, time, false).unwrap().value
+                );
+                if step == STEPS_PER_DAY {
+                    break;
+                }
+                assert_eq!(
+                    a.get_charge_speed(py, time, false).unwrap().value,
+                    b.get_charge_speed(py, time, false).unwrap().value
+                );
+                let green = |battery: &AssignedBattery| {
+                    battery
+                        .get_green_charge(py, time, false)
+                        .unwrap()
+                        .map(|w| w.value)
+                };
+                assert_eq!(green(&a), green(&b));
+                assert!(green(&a).is_some());
+                assert_eq!(
+                    schedule.get_grid_export(py, time, false).unwrap().value,
+                    reloaded.get_grid_export(py, time, false).unwrap().value
+                );
+            }
+            assert_eq!(
+                schedule.get_expensive_consumption(0.5).unwrap().len(),
+                reloaded.get_expensive_consumption(0.5).unwrap().len()
+            );
+
+            let err = Schedule::from_json("{}", context.start_time).err().unwrap();
+            assert!(err.is_instance_of::<PyValueError>(py));
+
+            let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let consumption = value["variable_actions"]["3"]["consumption"]
+                .as_array_mut()
+                .unwrap();
+            consumption.pop();
+            let Err(err) = Schedule::from_json(&value.to_string(), context.start_time) else {
+                panic!("a consumption list one value short was accepted");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err.to_string().contains("consumption of variable action 3"));
+        });
+    }
+
+    #[test]
+    fn test_schedule_bytes_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.batteries = vec![Rc::new(RustBattery::new(20_000, 1_000, 500, 500, 0.9, 4))];
+            context.variable_actions = vec![Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(2, 0),
+                Time::at_default_resolution(10, 0),
+                3_000,
+                50,
+                3,
+            ))];
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.7 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let (_, schedule) = solver.solve(py, None, Some(1)).unwrap();
+
+            let bytes = schedule.to_bytes().unwrap();
+            assert_eq!(&bytes[..5], b"EPOS\x09");
+            let reloaded = Schedule::from_bytes(&bytes, context.start_time).unwrap();
+            assert_eq!(reloaded.to_bytes().unwrap(), bytes);
+            assert_eq!(reloaded.to_json().unwrap(), schedule.to_json().unwrap());
+            assert!(bytes.len() < schedule.to_json().unwrap().len());
+
+            let mut outdated = bytes.clone();
+            outdated[4] = 0;
+            let Err(err) = Schedule::from_bytes(&outdated, context.start_time) else {
+                panic!("Accepted a blob of another format version");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(
+                err.to_string()
+                    .contains("Unsupported schedule format version 0")
+            );
+        });
+    }
+
+    #[test]
+    fn test_suggested_slot_matches_pinned_reoptimization() {
+        Python::initialize();
+        Python::attach(|py| {
+            let context = example_context();
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.7 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config.clone(), Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let (cost, schedule) = solver.solve(py, None, Some(1)).unwrap();
+            let hash = schedule.content_hash().unwrap();
+
+            let window = (context.start_time, context.start_time + TimeDelta::hours(6));
+            let (start, marginal) = schedule
+                .suggest_slot(
+                    py,
+                    &context,
+                    TimeDelta::minutes(30),
+                    Watt { value: 2000.0 },
+                    window,
+                )
+                .unwrap();
+            assert_eq!(schedule.content_hash().unwrap(), hash);
+            assert!(window.0 <= start && start + TimeDelta::minutes(30) <= window.1);
+            assert!(marginal.to_nano_euro() > 0.0);
+
+            // Re-optimize with every constant action pinned, the extra one at `extra_start`
+            let pinned_cost = |extra_start: DateTime<Utc>| {
+                let mut pinned = example_context();
+                let pin = |start: Time, consumption: i64, id: u32| {
+                    Rc::new(RustConstantAction::new(
+                        start,
+                        start + Time::at_default_resolution(0, 30),
+                        Time::at_default_resolution(0, 30),
+                        consumption,
+                        id,
+                    ))
+                };
+                pinned.constant_actions = (1..=2)
+                    .map(|id| {
+                        let start = schedule.inner.get_constant_action(id).unwrap();
+                        pin(start.get_start_time(), 200, id)
+                    })
+                    .chain([pin(
+                        TimeGrid::new(context.start_time, TimestepConfig::default())
+                            .to_time(extra_start)
+                            .unwrap(),
+                        Watt { value: 2000.0 }
+                            .to_energy_per_step(timestep_duration(TimestepConfig::default()))
+                            as i64,
+                        3,
+                    )])
+                    .collect();
+                let mut solver = Solver {
+                    inner: RustSolver::new(pinned.to_rust().unwrap(), config.clone(), Some(1)),
+                    grid: context.grid(),
+                    fingerprint: None,
+                    progress_every: 1,
+                };
+                solver.solve(py, None, Some(1)).unwrap().0.to_nano_euro()
+            };
+            let expected = cost.to_nano_euro() + marginal.to_nano_euro();
+            assert!((pinned_cost(start) - expected).abs() < 1e-6);
+            assert!(pinned_cost(window.0 + TimeDelta::minutes(15)) >= expected);
+
+            let Err(err) = schedule.suggest_slot(
+                py,
+                &context,
+                TimeDelta::hours(7),
+                Watt { value: 2000.0 },
+                window,
+            ) else {
+                panic!("A load longer than its window got a slot");
+            };
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    /// Context without constant actions, with two batteries and two variable actions added in
+    /// the given order.
+    fn hash_context(reversed: bool) -> OptimizerContext {
+        let mut context = example_context();
+        context.constant_actions = vec![];
+        // Strictly rising prices and idle batteries, so the optimum is unique
+        context.electricity_price = Prognoses::from_closure(|t| t.to_timestep() as i64 + 1);
+        context.batteries = vec![
+            Rc::new(RustBattery::new(2_000, 500, 0, 0, 1.0, 1)),
+            Rc::new(RustBattery::new(3_000, 700, 0, 0, 1.0, 2)),
+        ];
+        context.variable_actions = vec![
+            Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(1, 0),
+                Time::at_default_resolution(3, 0),
+                1_000,
+                20,
+                3,
+            )),
+            Rc::new(RustVariableAction::new(
+                Time::at_default_resolution(5, 0),
+                Time::at_default_resolution(7, 0),
+                2_000,
+                30,
+                4,
+            )),
+        ];
+        if reversed {
+            context.batteries.reverse();
+            context.variable_actions.reverse();
+        }
+        context
+    }
+
+    #[test]
+    fn test_content_hash_identifies_plan() {
+        let context = hash_context(false);
+        let mut flow = SmartHomeFlowBuilder::from_context(&context.to_rust().unwrap()).build();
+        let mut extract = || Schedule {
+            inner: flow.get_schedule().unwrap(),
+            start_timestamp: context.start_time,
+        };
+        // Every extraction builds its maps anew, with a different iteration order
+        let schedule = extract();
+        let hash = schedule.content_hash().unwrap();
+        assert_eq!(hash.len(), 32);
+        for _ in 0..5 {
+            assert_eq!(extract().content_hash().unwrap(), hash);
+        }
+
+        Python::initialize();
+        let (_, reversed) = Python::attach(|py| run_exact(py, &hash_context(true))).unwrap();
+        assert_eq!(reversed.content_hash().unwrap(), hash);
+
+        // Changing any assignment changes the hash
+        let action = Rc::new(RustConstantAction::new(
+            Time::at_default_resolution(0, 0),
+            Time::at_default_resolution(6, 0),
+            Time::at_default_resolution(0, 30),
+            200,
+            5,
+        ));
+        let mut placed = |start: Time| {
+            let mut schedule = extract();
+            schedule
+                .inner
+                .set_constant_actions(HashMap::from([(5, action.clone().with_start_time(start))]));
+            schedule.content_hash().unwrap()
+        };
+        let early = placed(Time::at_default_resolution(1, 0));
+        assert_ne!(early, hash);
+        assert_ne!(placed(Time::at_default_resolution(1, 1)), early);
+        assert_eq!(placed(Time::at_default_resolution(1, 0)), early);
+
+        let mut changed = extract();
+        let assigned = &changed.inner.variable_actions[&3];
+        let mut consumption: Vec<i64> = assigned.iter_consumption().map(|(_, c)| c).collect();
+        consumption[0] += 1;
+        changed.inner.variable_actions.insert(
+            3,
+            RustAssignedVariableAction::new(context.variable_actions[0].clone(), consumption),
+        );
+        assert_ne!(changed.content_hash().unwrap(), hash);
+    }
+
+    #[test]
+    fn test_provider_is_shared_across_threads() {
+        Python::initialize();
+        let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+        let provider = Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let get_data = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh((curr.hour * 60 + curr.minute) / 1e6)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            Py::new(py, PrognosesProvider::new(get_data)).unwrap()
+        });
+        let materialize = |provider: &Py<PrognosesProvider>| {
+            Python::attach(|py| {
+                MaterializedPrognoses::from_provider(py, provider.get(), start_time, None)
+                    .unwrap()
+                    .values()
+            })
+        };
+        let expected = materialize(&provider);
+
+        // Four threads materialize the same provider, and a copy of it, several times each
+        let outputs = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let copy = Python::attach(|py| {
+                            provider
+                                .bind(py)
+                                .call_method0("__copy__")
+                                .unwrap()
+                                .cast_into::<PrognosesProvider>()
+                                .unwrap()
+                                .unbind()
+                        });
+                        (0..5)
+                            .flat_map(|_| [materialize(&provider), materialize(&copy)])
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(outputs.len(), 40);
+        assert!(outputs.iter().all(|output| *output == expected));
+        assert_eq!(expected[90], 450.0 / 1e6);
+    }
+
+    #[test]
+    fn test_prognoses_round_trips() {
+        Python::initialize();
+        Python::attach(|py| {
+            let start_time = Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap();
+            let globals = PyDict::new(py);
+            globals
+                .set_item("EuroPerWh", py.get_type::<EuroPerWh>())
+                .unwrap();
+            let get_data = py
+                .eval(
+                    c"lambda curr, next: EuroPerWh(curr.minute / 1000)",
+                    Some(&globals),
+                    None,
+                )
+                .unwrap()
+                .unbind();
+            let prognoses = MaterializedPrognoses::from_provider(
+                py,
+                &PrognosesProvider::new(get_data),
+                start_time,
+                None,
+            )
+            .unwrap();
+            assert_eq!(prognoses.values()[59], 0.059);
+
+            let bytes = prognoses.to_bytes().unwrap();
+            let reloaded = MaterializedPrognoses::from_bytes(&bytes).unwrap();
+            assert!(reloaded == prognoses);
+            assert_eq!(reloaded.to_bytes().unwrap(), bytes);
+            let json = prognoses.to_json().unwrap();
+            assert!(MaterializedPrognoses::from_json(&json).unwrap() == prognoses);
+
+            // A schedule blob is not mistaken for prognoses
+            let Err(err) = Schedule::from_bytes(&bytes, start_time) else {
+                panic!("Read prognoses as a schedule");
+            };
+            assert!(err.to_string().contains("expected magic"));
+            assert!(MaterializedPrognoses::new(start_time, vec![1.0; 7]).is_err());
+        });
+    }
+
+    #[test]
+    fn test_verify_reports_violations_in_user_units() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut context = example_context();
+            context.grid_limit = Some(1_000);
+            let config = AnnealingConfig {
+                cooling: Cooling::Geometric { factor: 0.7 },
+                ..Default::default()
+            };
+            let mut solver = Solver {
+                inner: RustSolver::new(context.to_rust().unwrap(), config, Some(1)),
+                grid: context.grid(),
+                fingerprint: None,
+                progress_every: 1,
+            };
+            let (_, mut schedule) = solver.solve(py, None, Some(1)).unwrap();
+            assert!(schedule.verify(&context).unwrap().is_empty());
+
+            let action = schedule.inner.constant_actions.get_mut(&1).unwrap();
+            *action.get_start_time_mut() = Time::at_default_resolution(7, 0);
+            schedule
+                .inner
+                .network_consumption
+                .set(Time::at_default_resolution(12, 0), 1_200);
+            assert_eq!(
+                schedule.verify(&context).unwrap(),
+                vec![
+                    "Constant action 1 runs from 2025-03-01 13:00:00 UTC to 2025-03-01 13:30:00 UTC, outside of its window",
+                    "Grid import of 72.00 W at 2025-03-01 18:00:00 UTC exceeds grid_limit 60.00 W",
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_gantt_fits_the_requested_columns() {
+        Python::initialize();
+        Python::attach(|py| {
+            import_package(py);
+            let mut context = example_context();
+            context.batteries = vec![Rc::new(RustBattery::new(5000, 1000, 500, 500, 0.9, 4))];
+            let globals = PyDict::new(py);
+            globals
+                .set_item("context", Py::new(py, context).unwrap())
+                .unwrap();
+            py.run(
+                c"
+from electricity_price_optimizer_py import OptimizerError, run_simulated_annealing
+
+_, schedule = run_simulated_annealing(context, seed=1, cooling='linear', iterations=20)
+lines = schedule.gantt().splitlines()
+assert [line.split()[0] for line in lines] == ['Price', 'Battery', 'Action', 'Action'], lines
+gutter = len('Battery 4 ')
+for columns in [1, 5, 24, 48, 97, 1440]:
+    lines = schedule.gantt(columns).splitlines()
+    assert all(len(line) <= gutter + columns for line in lines), columns
+    assert all(set(line[gutter:]) <= set('+-.') for line in lines if line.startswith('Battery'))
+for columns in [0, 1441]:
+    try:
+        schedule.gantt(columns)
+        assert False
+    except OptimizerError:
+        pass
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_stalled_flow_raises_solver_stalled_with_diagnostics() {
+        Python::initialize();
+        Python::attach(|py| {
+            let stalled = electricity_price_optimizer::optimizer::SolverStalled {
+                phase: SolvePhase::Dijkstra,
+                flow: 1_500,
+                cost: 2_000_000_000,
+                counters: electricity_price_optimizer::optimizer::SolveCounters {
+                    iterations: 1_000,
+                    augmentations: 4,
+                    cycle_cancellations: 0,
+                },
+            };
+            let err = flow_solve_error(FlowSolveError::Stalled(stalled));
+            assert!(err.is_instance_of::<SolverStalled>(py));
+            assert!(err.is_instance_of::<OptimizerError>(py));
+            let value = err.value(py);
+            let phase: String = value.getattr("phase").unwrap().extract().unwrap();
+            assert_eq!(phase, "dijkstra");
+            let iterations: u64 = value.getattr("iterations").unwrap().extract().unwrap();
+            assert_eq!(iterations, 1_000);
+            let augmentations: u64 = value.getattr("augmentations").unwrap().extract().unwrap();
+            assert_eq!(augmentations, 4);
+            let flow: WattHour = value.getattr("flow").unwrap().extract().unwrap();
+            assert_eq!(flow.value, 1.5);
+            let cost: Euro = value.getattr("cost").unwrap().extract().unwrap();
+            assert_eq!(cost.value, 2.0);
+
+            // A full flow stack is a bug of the library, not of the input
+            let err = flow_solve_error(FlowSolveError::Stack(
+                electricity_price_optimizer::StackError::PopLast,
+            ));
+            assert!(err.is_instance_of::<PyRuntimeError>(py));
+        });
+    }
+
+    /// Options with every setting away from its default.
+    fn custom_options(py: Python<'_>) -> OptimizerOptions {
+        let cooling_options = PyDict::new(py);
+        cooling_options.set_item("iterations", 30).unwrap();
+        cooling_options.set_item("min_temperature", 0.5).unwrap();
+        OptimizerOptions::new(
+            Some(7),
+            "linear",
+            10,
+            true,
+            true,
+            true,
+            3,
+            1_000_000,
+            Some(&cooling_options),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_optimizer_options_take_effect() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            py.run(
+                c"calls = []\ndef progress(iteration, temperature, cost, best_cost):\n    calls.append(iteration)\n",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            let progress = globals.get_item("progress").unwrap().unwrap().unbind();
+            let calls = || -> Vec<usize> {
+                let calls = globals.get_item("calls").unwrap().unwrap();
+                let seen = calls.extract().unwrap();
+                calls.call_method0("clear").unwrap();
+                seen
+            };
+            let mut context = example_context();
+            let options = custom_options(py);
+            let solve =
+                |context: &OptimizerContext, options: &OptimizerOptions, seed: Option<u64>| {
+                    run_simulated_annealing(
+                        py,
+                        context,
+                        seed,
+                        Some(progress.clone_ref(py)),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        Some(Py::new(py, options.clone()).unwrap().borrow(py)),
+                        None,
+                    )
+                };
+
+            // cooling, iterations and progress_every
+            let result = solve(&context, &options, None).unwrap();
+            assert_eq!(calls(), vec![10, 20, 30]);
+            // explain_cost and capture_flow
+            let schedule = result.schedule.borrow(py);
+            assert!(schedule.inner.get_objective_breakdown().is_some());
+            assert!(schedule.inner.get_flow_table().is_some());
+            // seed: the same options give the same schedule, a keyword seed overrides it
+            let hash = |result: OptimizeResult| result.schedule.borrow(py).content_hash().unwrap();
+            let seeded = |seed| OptimizerOptions {
+                inner: SolveOptions {
+                    seed: Some(seed),
+                    ..options.inner.clone()
+                },
+                ..options.clone()
+            };
+            assert_eq!(
+                schedule.content_hash().unwrap(),
+                hash(solve(&context, &options, None).unwrap())
+            );
+            assert_eq!(
+                hash(solve(&context, &options, Some(2)).unwrap()),
+                hash(solve(&context, &seeded(2), None).unwrap())
+            );
+            calls();
+            // Keywords override the options; another cooling drops their iterations
+            let cooling_options = PyDict::new(py);
+            cooling_options.set_item("factor", 0.7).unwrap();
+            let result = run_simulated_annealing(
+                py,
+                &context,
+                None,
+                Some(progress.clone_ref(py)),
+                Some(1),
+                Some("geometric"),
+                Some(false),
+                None,
+                None,
+                None,
+                None,
+                Some(Py::new(py, options.clone()).unwrap().borrow(py)),
+                Some(&cooling_options),
+            )
+            .unwrap();
+            assert!(
+                result
+                    .schedule
+                    .borrow(py)
+                    .inner
+                    .get_objective_breakdown()
+                    .is_none()
+            );
+            // 0.7^17 * 40 < 0.1, so the run has 17 iterations
+            assert_eq!(calls(), (1..=17).collect::<Vec<_>>());
+
+            // flow_max_iterations
+            let capped = OptimizerOptions {
+                inner: SolveOptions {
+                    flow_max_iterations: 1,
+                    ..options.inner.clone()
+                },
+                ..options.clone()
+            };
+            let Err(err) = solve(&context, &capped, None) else {
+                panic!("One iteration is too few for the flow");
+            };
+            assert!(err.is_instance_of::<SolverStalled>(py));
+
+            // isolate_errors
+            let mut broken = (*context.constant_actions[1]).clone();
+            broken.end_before = Time::at_default_resolution(0, 20);
+            context.constant_actions[1] = Rc::new(broken);
+            let result = solve(&context, &options, None).unwrap();
+            assert_eq!(result.excluded.bind(py).len(), 1);
+            let strict = OptimizerOptions {
+                isolate_errors: false,
+                ..options.clone()
+            };
+            let Err(err) = solve(&context, &strict, None) else {
+                panic!("The broken action should have failed the run");
+            };
+            assert!(err.is_instance_of::<InfeasibleError>(py));
+
+            calls();
+            // A Solver takes the same options, but can't isolate errors
+            let context = example_context();
+            let err = Solver::new(
+                py,
+                &context,
+                None,
+                None,
+                None,
+                None,
+                Some(Py::new(py, options.clone()).unwrap().borrow(py)),
+                None,
+            )
+            .err()
+            .unwrap();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let mut solver = Solver::new(
+                py,
+                &context,
+                None,
+                None,
+                None,
+                None,
+                Some(Py::new(py, strict).unwrap().borrow(py)),
+                None,
+            )
+            .unwrap();
+            solver
+                .solve(py, Some(progress.clone_ref(py)), None)
+                .unwrap();
+            assert_eq!(calls(), vec![10, 20, 30]);
+        });
+    }
 }
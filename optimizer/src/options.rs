@@ -0,0 +1,298 @@
+//! Settings of a solve in one object, see [`OptimizerOptions`].
+//!
+//! The solve functions take the same settings as keywords. These are merged into the options
+//! given with them by [`OptimizerOptions::overridden`], so both are validated the same way.
+use electricity_price_optimizer::{
+    optimizer::DEFAULT_MAX_ITERATIONS,
+    simulated_annealing::{Cooling, SolveOptions},
+};
+use pyo3::{
+    Bound, IntoPyObject, PyRef, PyResult, Python, pyclass, pymethods,
+    types::{PyAnyMethods, PyDict, PyDictMethods},
+};
+
+use crate::{OptimizerError, annealing_config};
+
+/// Name of a cooling schedule as annealing_config takes it.
+fn cooling_name(cooling: &Cooling) -> &'static str {
+    match cooling {
+        Cooling::Geometric { .. } => "geometric",
+        Cooling::Linear { .. } => "linear",
+        Cooling::Logarithmic { .. } => "logarithmic",
+        Cooling::Lam { .. } => "lam",
+    }
+}
+
+/// Keywords of OptimizerOptions other than the cooling options.
+const OPTION_NAMES: [&str; 8] = [
+    "seed",
+    "cooling",
+    "progress_every",
+    "explain_cost",
+    "capture_flow",
+    "isolate_errors",
+    "moves_per_iteration",
+    "flow_max_iterations",
+];
+
+#[pyclass(frozen)]
+#[derive(Clone, Debug, PartialEq)]
+/// All settings of a run of run_simulated_annealing or a Solver in one object, e.g. to store
+/// them with to_dict and use them again with from_dict. They are validated on creation.
+pub(crate) struct OptimizerOptions {
+    pub(crate) inner: SolveOptions,
+    /// Number of iterations between two progress calls.
+    #[pyo3(get)]
+    pub(crate) progress_every: usize,
+    /// Leave assets that fail validation out instead of failing the run.
+    #[pyo3(get)]
+    pub(crate) isolate_errors: bool,
+}
+#[pymethods]
+impl OptimizerOptions {
+    #[new]
+    #[pyo3(signature = (seed=None, cooling="geometric", progress_every=1, explain_cost=false, capture_flow=false, isolate_errors=false, moves_per_iteration=2, flow_max_iterations=DEFAULT_MAX_ITERATIONS, **cooling_options))]
+    /// Create options, see run_simulated_annealing for the meaning of most of them.
+    /// moves_per_iteration is the number of constant action moves of an iteration,
+    /// flow_max_iterations the number of iterations a min-cost flow solve may take before it
+    /// raises SolverStalled.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        seed: Option<u64>,
+        cooling: &str,
+        progress_every: usize,
+        explain_cost: bool,
+        capture_flow: bool,
+        isolate_errors: bool,
+        moves_per_iteration: usize,
+        flow_max_iterations: u64,
+        cooling_options: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let config = annealing_config(cooling, cooling_options)?;
+        if progress_every == 0 {
+            return Err(OptimizerError::new_err("progress_every must be at least 1"));
+        }
+        if moves_per_iteration == 0 {
+            return Err(OptimizerError::new_err(
+                "moves_per_iteration must be at least 1",
+            ));
+        }
+        if flow_max_iterations == 0 {
+            return Err(OptimizerError::new_err(
+                "flow_max_iterations must be at least 1",
+            ));
+        }
+        Ok(OptimizerOptions {
+            inner: SolveOptions {
+                seed,
+                initial_temperature: config.initial_temperature,
+                min_temperature: config.min_temperature,
+                cooling: config.cooling,
+                moves_per_iteration,
+                explain_cost,
+                capture_flow,
+                flow_max_iterations,
+            },
+            progress_every,
+            isolate_errors,
+        })
+    }
+    #[getter]
+    fn seed(&self) -> Option<u64> {
+        self.inner.seed
+    }
+    /// Name of the cooling schedule.
+    #[getter]
+    fn cooling(&self) -> &'static str {
+        cooling_name(&self.inner.cooling)
+    }
+    /// Parameters of the cooling schedule, with the defaults filled in.
+    #[getter]
+    fn cooling_options<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("initial_temperature", self.inner.initial_temperature)?;
+        dict.set_item("min_temperature", self.inner.min_temperature)?;
+        match self.inner.cooling {
+            Cooling::Geometric { factor } => dict.set_item("factor", factor)?,
+            Cooling::Linear { iterations }
+            | Cooling::Logarithmic { iterations }
+            | Cooling::Lam { iterations } => dict.set_item("iterations", iterations)?,
+        }
+        Ok(dict)
+    }
+    #[getter]
+    fn explain_cost(&self) -> bool {
+        self.inner.explain_cost
+    }
+    #[getter]
+    fn capture_flow(&self) -> bool {
+        self.inner.capture_flow
+    }
+    #[getter]
+    fn moves_per_iteration(&self) -> usize {
+        self.inner.moves_per_iteration
+    }
+    #[getter]
+    fn flow_max_iterations(&self) -> u64 {
+        self.inner.flow_max_iterations
+    }
+    /// All options as a flat dict of keywords of the constructor, cooling options included.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("seed", self.inner.seed)?;
+        dict.set_item("cooling", self.cooling())?;
+        dict.set_item("progress_every", self.progress_every)?;
+        dict.set_item("explain_cost", self.inner.explain_cost)?;
+        dict.set_item("capture_flow", self.inner.capture_flow)?;
+        dict.set_item("isolate_errors", self.isolate_errors)?;
+        dict.set_item("moves_per_iteration", self.inner.moves_per_iteration)?;
+        dict.set_item("flow_max_iterations", self.inner.flow_max_iterations)?;
+        dict.update(self.cooling_options(py)?.as_mapping())?;
+        Ok(dict)
+    }
+    #[staticmethod]
+    /// Create options from a dict like the one to_dict returns. Missing keys keep their
+    /// defaults; unknown keys are rejected like unknown cooling options.
+    fn from_dict(options: &Bound<'_, PyDict>) -> PyResult<Self> {
+        let options = options
+            .py()
+            .get_type::<OptimizerOptions>()
+            .call((), Some(options))?;
+        Ok(options.extract::<PyRef<'_, OptimizerOptions>>()?.clone())
+    }
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("OptimizerOptions({})", self.to_dict(py)?.repr()?))
+    }
+}
+impl OptimizerOptions {
+    /// `options`, or the defaults without them, with the keywords in `overrides` replacing
+    /// their settings. Another cooling schedule in `overrides` doesn't keep any cooling options
+    /// of `options`.
+    pub(crate) fn overridden(
+        options: Option<&Self>,
+        overrides: &Bound<'_, PyDict>,
+    ) -> PyResult<Self> {
+        let py = overrides.py();
+        let merged = match options {
+            Some(options) => options.to_dict(py)?,
+            None => PyDict::new(py),
+        };
+        if overrides.contains("cooling")? {
+            for key in merged.keys() {
+                if !OPTION_NAMES.contains(&key.extract::<String>()?.as_str()) {
+                    merged.del_item(key)?;
+                }
+            }
+        }
+        merged.update(overrides.as_mapping())?;
+        Self::from_dict(&merged)
+    }
+}
+
+/// The cooling options given as keywords to a solve function, to be completed with the other
+/// keywords given and passed to OptimizerOptions.overridden.
+pub(crate) fn keyword_overrides<'py>(
+    py: Python<'py>,
+    cooling_options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    match cooling_options {
+        Some(cooling_options) => cooling_options.copy(),
+        None => Ok(PyDict::new(py)),
+    }
+}
+
+/// Adds a keyword to `overrides` if it was given.
+pub(crate) fn set_given<'py, T: IntoPyObject<'py>>(
+    overrides: &Bound<'py, PyDict>,
+    key: &str,
+    value: Option<T>,
+) -> PyResult<()> {
+    match value {
+        Some(value) => overrides.set_item(key, value),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{IntoPyObjectExt, PyAny, exceptions::PyValueError};
+
+    use super::*;
+
+    /// Options with every setting away from its default.
+    fn custom_options(py: Python<'_>) -> OptimizerOptions {
+        let cooling_options = PyDict::new(py);
+        cooling_options.set_item("iterations", 30).unwrap();
+        cooling_options.set_item("min_temperature", 0.5).unwrap();
+        OptimizerOptions::new(
+            Some(7),
+            "linear",
+            10,
+            true,
+            true,
+            true,
+            3,
+            1_000_000,
+            Some(&cooling_options),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_optimizer_options_round_trip_through_dict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let options = custom_options(py);
+            let dict = options.to_dict(py).unwrap();
+            let iterations: usize = dict
+                .get_item("iterations")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(iterations, 30);
+            assert!(dict.get_item("factor").unwrap().is_none());
+            let restored = OptimizerOptions::from_dict(&dict).unwrap();
+            assert!(restored == options);
+            assert_eq!(restored.cooling(), "linear");
+            assert_eq!(restored.inner.cooling, Cooling::Linear { iterations: 30 });
+
+            // The Rust side stores the same settings
+            let json = serde_json::to_string(&options.inner).unwrap();
+            let inner: SolveOptions = serde_json::from_str(&json).unwrap();
+            assert_eq!(inner, options.inner);
+            let config = inner.annealing_config();
+            assert_eq!(config.moves_per_iteration, 3);
+            assert_eq!(config.flow_max_iterations, 1_000_000);
+            assert_eq!(config.min_temperature, 0.5);
+
+            // Missing keys keep their defaults, invalid ones are rejected on creation
+            let defaults = OptimizerOptions::from_dict(&PyDict::new(py)).unwrap();
+            assert_eq!(defaults.inner, SolveOptions::default());
+            assert_eq!(defaults.progress_every, 1);
+            let rejected = |key: &str, value: Bound<'_, PyAny>| {
+                let dict = PyDict::new(py);
+                dict.set_item(key, value).unwrap();
+                let err = OptimizerOptions::from_dict(&dict).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py), "{key}");
+                err.value(py).to_string()
+            };
+            assert!(
+                rejected("colling", "linear".into_bound_py_any(py).unwrap()).contains("colling")
+            );
+            assert!(
+                rejected("iterations", 5.into_bound_py_any(py).unwrap()).contains("iterations")
+            );
+            for key in [
+                "progress_every",
+                "moves_per_iteration",
+                "flow_max_iterations",
+            ] {
+                assert!(rejected(key, 0.into_bound_py_any(py).unwrap()).contains(key));
+            }
+        });
+    }
+}
@@ -0,0 +1,292 @@
+//! Heuristic checks for prognoses that are most likely given in the wrong unit.
+//!
+//! The optimizer accepts any value that fits, so prognoses that are 1000 times too large, e.g.
+//! from a conversion between W and kW in the wrong direction, silently produce absurd plans.
+//! [`PlausibilityLimits`] flags values no household would see. By default this is an
+//! `OptimizerWarning`, in strict mode a ValueError. The same goes for generation that is
+//! counted twice, once as generated electricity and once as negative beyond control
+//! consumption.
+use std::ffi::CString;
+
+use chrono::TimeDelta;
+use electricity_price_optimizer::{optimizer_context::prognoses::Prognoses, time::Time};
+use pyo3::{
+    PyResult, PyTypeInfo, Python, create_exception, exceptions::PyUserWarning, pyclass, pymethods,
+};
+
+use crate::{
+    PrognosesError,
+    timegrid::TimeGrid,
+    units::{EuroPerWh, Watt},
+};
+
+create_exception!(
+    electricity_price_optimizer_py,
+    OptimizerWarning,
+    PyUserWarning,
+    "Warning about an input that is valid but most likely a mistake, e.g. prognoses in the wrong unit."
+);
+
+#[pyclass]
+#[derive(Clone, Debug)]
+/// Limits of plausible prognoses, checked when prognoses are added to a context.
+/// Values outside of them warn with an OptimizerWarning, or raise ValueError in strict mode.
+pub struct PlausibilityLimits {
+    /// Highest plausible generated electricity, as average power per timestep.
+    #[pyo3(get, set)]
+    max_generation: Watt,
+    /// Highest plausible beyond control consumption, as average power per timestep.
+    #[pyo3(get, set)]
+    max_base_load: Watt,
+    /// Highest plausible electricity price, feed-in tariff and network fee.
+    #[pyo3(get, set)]
+    max_price: EuroPerWh,
+    /// Lowest plausible electricity price, feed-in tariff and network fee.
+    #[pyo3(get, set)]
+    min_price: EuroPerWh,
+    /// Raise ValueError instead of warning.
+    #[pyo3(get, set)]
+    strict: bool,
+}
+
+impl Default for PlausibilityLimits {
+    fn default() -> Self {
+        PlausibilityLimits {
+            max_generation: Watt { value: 100_000.0 },
+            max_base_load: Watt { value: 50_000.0 },
+            max_price: EuroPerWh { value: 0.01 },
+            min_price: EuroPerWh { value: -0.005 },
+            strict: false,
+        }
+    }
+}
+
+#[pymethods]
+impl PlausibilityLimits {
+    #[new]
+    #[pyo3(signature = (max_generation=None, max_base_load=None, max_price=None, min_price=None, strict=false))]
+    /// Create limits. Limits that aren't given fit a household: 100 kW of generation, 50 kW of
+    /// base load and prices between -5 and 10 €/kWh.
+    pub(crate) fn new(
+        max_generation: Option<Watt>,
+        max_base_load: Option<Watt>,
+        max_price: Option<EuroPerWh>,
+        min_price: Option<EuroPerWh>,
+        strict: bool,
+    ) -> Self {
+        let default = PlausibilityLimits::default();
+        PlausibilityLimits {
+            max_generation: max_generation.unwrap_or(default.max_generation),
+            max_base_load: max_base_load.unwrap_or(default.max_base_load),
+            max_price: max_price.unwrap_or(default.max_price),
+            min_price: min_price.unwrap_or(default.min_price),
+            strict,
+        }
+    }
+    fn __repr__(&self) -> String {
+        format!(
+            "PlausibilityLimits(max_generation={} W, max_base_load={} W, max_price={} €/Wh, min_price={} €/Wh, strict={})",
+            self.max_generation.value,
+            self.max_base_load.value,
+            self.max_price.value,
+            self.min_price.value,
+            if self.strict { "True" } else { "False" }
+        )
+    }
+}
+
+/// Shortest stretch of negative consumption during generation that counts as double counting
+/// rather than a glitch of the meter.
+const SUSTAINED_NEGATIVE: TimeDelta = TimeDelta::minutes(15);
+
+const POWER_HINT: &str = "e.g. from converting between W and kW or Wh and kWh the wrong way";
+const PRICE_HINT: &str = "e.g. a price per kWh given as a price per Wh";
+
+/// A power prognoses in milli-Wh per timestep of `grid`, shown in W.
+fn power(milli_wh: i64, grid: TimeGrid) -> f64 {
+    Watt::from_energy_per_step(milli_wh as f64, grid.step()).value
+}
+
+/// A price prognoses in micro-euro per Wh, shown in €/Wh.
+fn price(micro_euro_per_wh: i64) -> f64 {
+    EuroPerWh::from_micro_euro_per_wh(micro_euro_per_wh as f64).value
+}
+
+impl PlausibilityLimits {
+    /// Check generated electricity in milli-Wh per timestep against `max_generation`.
+    pub(crate) fn check_generation(
+        &self,
+        py: Python<'_>,
+        prognoses: &Prognoses<i64>,
+        grid: TimeGrid,
+    ) -> PyResult<()> {
+        let field = "Generated electricity";
+        self.check_power(py, prognoses, grid, field, &self.max_generation)
+    }
+
+    /// Check beyond control consumption in milli-Wh per timestep against `max_base_load`.
+    pub(crate) fn check_base_load(
+        &self,
+        py: Python<'_>,
+        prognoses: &Prognoses<i64>,
+        grid: TimeGrid,
+    ) -> PyResult<()> {
+        let field = "Beyond control consumption";
+        self.check_power(py, prognoses, grid, field, &self.max_base_load)
+    }
+
+    fn check_power(
+        &self,
+        py: Python<'_>,
+        prognoses: &Prognoses<i64>,
+        grid: TimeGrid,
+        field: &str,
+        max: &Watt,
+    ) -> PyResult<()> {
+        self.check(py, prognoses, grid, field, POWER_HINT, |value| {
+            let value = power(value, grid);
+            (value > max.value).then(|| {
+                format!(
+                    "of {} W is above the plausible maximum of {} W",
+                    value, max.value
+                )
+            })
+        })
+    }
+
+    /// Check a price in micro-euro per Wh against `min_price` and `max_price`. `field` names
+    /// the price, e.g. "Electricity price".
+    pub(crate) fn check_price(
+        &self,
+        py: Python<'_>,
+        prognoses: &Prognoses<i64>,
+        grid: TimeGrid,
+        field: &str,
+    ) -> PyResult<()> {
+        let (min, max) = (self.min_price.value, self.max_price.value);
+        self.check(py, prognoses, grid, field, PRICE_HINT, |value| {
+            let value = price(value);
+            if value > max {
+                Some(format!(
+                    "of {} €/Wh is above the plausible maximum of {} €/Wh",
+                    value, max
+                ))
+            } else if value < min {
+                Some(format!(
+                    "of {} €/Wh is below the plausible minimum of {} €/Wh",
+                    value, min
+                ))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Check beyond control consumption in milli-Wh per timestep for sustained negative
+    /// stretches during generation, which most likely hold the generation a second time.
+    /// `sources` names how the generation and the consumption were added.
+    pub(crate) fn check_double_counting(
+        &self,
+        py: Python<'_>,
+        consumption: &Prognoses<i64>,
+        generated: &Prognoses<i64>,
+        grid: TimeGrid,
+        sources: (&str, &str),
+    ) -> PyResult<()> {
+        match double_counting(consumption, generated, grid, sources)? {
+            Some(message) => self.report(py, message),
+            None => Ok(()),
+        }
+    }
+
+    /// Report the first timestep `implausible` describes a problem for, together with the
+    /// number of further timesteps with a problem. `hint` names the likely unit mistake.
+    fn check(
+        &self,
+        py: Python<'_>,
+        prognoses: &Prognoses<i64>,
+        grid: TimeGrid,
+        field: &str,
+        hint: &str,
+        implausible: impl Fn(i64) -> Option<String>,
+    ) -> PyResult<()> {
+        let mut found = prognoses
+            .get_data()
+            .iter()
+            .enumerate()
+            .filter_map(|(t, &value)| implausible(value).map(|problem| (t, problem)));
+        let Some((t, problem)) = found.next() else {
+            return Ok(());
+        };
+        let more = match found.count() {
+            0 => String::new(),
+            1 => " and 1 more interval".to_string(),
+            count => format!(" and {} more intervals", count),
+        };
+        let time = Time::from_timestep(t as u32);
+        let message = format!(
+            "{} {} for interval [{}, {}){}. The prognoses might be in the wrong unit and \
+             1000 times too large, {}.",
+            field,
+            problem,
+            grid.to_datetime(time)?,
+            grid.to_datetime(time.get_next_timestep())?,
+            more,
+            hint
+        );
+        self.report(py, message)
+    }
+
+    /// Warn with `message`, or raise it in strict mode.
+    fn report(&self, py: Python<'_>, message: String) -> PyResult<()> {
+        if self.strict {
+            return Err(PrognosesError::new_err(message));
+        }
+        let message = CString::new(message).expect("Messages contain no null bytes");
+        pyo3::PyErr::warn(py, &OptimizerWarning::type_object(py), &message, 1)
+    }
+}
+
+/// Describe the stretches of at least `SUSTAINED_NEGATIVE` in which the consumption is
+/// negative while electricity is generated, or None if there are none.
+pub(crate) fn double_counting(
+    consumption: &Prognoses<i64>,
+    generated: &Prognoses<i64>,
+    grid: TimeGrid,
+    (generation_source, consumption_source): (&str, &str),
+) -> PyResult<Option<String>> {
+    let overlapping: Vec<bool> = consumption
+        .get_data()
+        .iter()
+        .zip(generated.get_data())
+        .map(|(&consumed, &generated)| consumed < 0 && generated > 0)
+        .collect();
+    let mut stretches = Vec::new();
+    let mut t = 0;
+    while t < overlapping.len() {
+        if !overlapping[t] {
+            t += 1;
+            continue;
+        }
+        let start = t;
+        while t < overlapping.len() && overlapping[t] {
+            t += 1;
+        }
+        let start = grid.to_datetime(Time::from_timestep(start as u32))?;
+        let end = grid.to_datetime(Time::from_timestep(t as u32))?;
+        if end - start >= SUSTAINED_NEGATIVE {
+            stretches.push(format!("[{}, {})", start, end));
+        }
+    }
+    if stretches.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "Beyond control consumption is negative while electricity is generated in {}. The \
+         generation is most likely counted twice, once in {} and once as negative consumption \
+         in {}; leave it out of the consumption.",
+        stretches.join(", "),
+        generation_source,
+        consumption_source
+    )))
+}
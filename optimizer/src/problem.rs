@@ -0,0 +1,657 @@
+//! Machine-readable dumps of a fully materialized optimization problem.
+//!
+//! A dump contains everything needed to replay a solve: the raw i64 prognoses arrays,
+//! all asset definitions in internal units, the start time and the timestep configuration.
+//! It is meant to be attached to bug reports and loaded again with `load_problem`.
+//!
+//! The format is versioned by [`FORMAT_VERSION`], which is shared with the schedule
+//! serialization so both are bumped together.
+use std::rc::Rc;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use electricity_price_optimizer::{
+    optimizer_context::{
+        action::{constant::ConstantAction, variable::VariableAction},
+        battery::Battery,
+        prognoses::Prognoses,
+    },
+    time::{Time, TimestepConfig},
+};
+use pyo3::PyResult;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    OptimizerContext, OptimizerError, PrognosesError, plausibility::PlausibilityLimits,
+    settlement_period_to_steps,
+};
+
+/// Version of the serialization formats produced by this crate.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A battery definition in internal units (milli-Wh and milli-Wh per timestep).
+pub struct BatteryDump {
+    pub id: u32,
+    pub capacity: i64,
+    pub initial_level: i64,
+    pub max_charge: i64,
+    pub max_output: i64,
+    pub efficiency: f32,
+    #[serde(default = "grid_charging_allowed_default")]
+    pub grid_charging_allowed: bool,
+    #[serde(default)]
+    pub min_charge: i64,
+    #[serde(default)]
+    pub final_charge_target: Option<i64>,
+    #[serde(default)]
+    pub self_discharge_per_hour: f32,
+    /// Wear cost per stored milli-Wh, in micro-euro per Wh.
+    #[serde(default)]
+    pub cycle_cost: i64,
+}
+
+/// Batteries in older dumps could always be charged from the grid.
+fn grid_charging_allowed_default() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A constant action definition. Times are minutes since the problem start.
+pub struct ConstantActionDump {
+    pub id: u32,
+    pub start_from: u32,
+    pub end_before: u32,
+    pub duration: u32,
+    pub consumption: i64,
+    /// Consumption for every timestep of the duration, only set if it isn't flat.
+    #[serde(default)]
+    pub profile: Option<Vec<i64>>,
+    /// Consumption for every timestep if the action isn't scheduled, only set if the action
+    /// has one.
+    #[serde(default)]
+    pub default_profile: Option<Vec<i64>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A variable action definition. Times are minutes since the problem start.
+pub struct VariableActionDump {
+    pub id: u32,
+    pub start: u32,
+    pub end: u32,
+    pub total_consumption: i64,
+    pub max_consumption: i64,
+    /// Maximum consumption for every timestep of the window, only set if it varies.
+    #[serde(default)]
+    pub max_consumption_profile: Option<Vec<i64>>,
+    /// Least consumption of every timestep the action consumes anything in.
+    #[serde(default)]
+    pub min_consumption: i64,
+    /// Preference for every timestep of the window, only set if the action has one.
+    #[serde(default)]
+    pub preference: Option<Vec<i64>>,
+    /// Largest preference as a share of the average absolute electricity price.
+    #[serde(default)]
+    pub preference_limit: f32,
+    /// Whether the total is consumed in one block of consecutive timesteps.
+    #[serde(default)]
+    pub contiguous: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The fixed dispatch of a battery, milli-Wh stored (positive) or discharged (negative) for
+/// every timestep.
+pub struct FixedBatteryPlanDump {
+    pub id: u32,
+    pub charge: Vec<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The complete optimization problem as stored in a dump.
+pub struct ProblemDump {
+    pub format_version: u32,
+    pub crate_version: String,
+    pub start_time: DateTime<Utc>,
+    /// Length of the timesteps, the prognoses have one value for each of the day.
+    pub minutes_per_timestep: u32,
+    /// Micro-euro per Wh for every timestep.
+    pub electricity_price: Vec<i64>,
+    /// Milli-Wh per timestep.
+    pub generated_electricity: Vec<i64>,
+    /// Milli-Wh per timestep.
+    pub beyond_control_consumption: Vec<i64>,
+    pub batteries: Vec<BatteryDump>,
+    pub constant_actions: Vec<ConstantActionDump>,
+    pub variable_actions: Vec<VariableActionDump>,
+    /// Micro-euro per Wh for every timestep, absent if grid export is disabled.
+    #[serde(default)]
+    pub feed_in_tariff: Option<Vec<i64>>,
+    /// Milli-Wh per timestep, absent if unlimited.
+    #[serde(default)]
+    pub max_export: Option<i64>,
+    /// Micro-euro per Wh for every timestep, absent without a network fee.
+    #[serde(default)]
+    pub network_fee: Option<Vec<i64>>,
+    /// Milligram CO2 per Wh for every timestep and the price of a milligram in micro-euro,
+    /// absent if emissions aren't tracked.
+    #[serde(default)]
+    pub co2_intensity: Option<(Vec<i64>, f64)>,
+    /// Flat rate in micro-euro per Wh and weight of the electricity price in the objective,
+    /// absent without net metering.
+    #[serde(default)]
+    pub net_metering: Option<(i64, f64)>,
+    /// Maximum grid import in milli-Wh per timestep, absent if unlimited.
+    #[serde(default)]
+    pub grid_limit: Option<i64>,
+    /// Nano-euro per milli-Wh per timestep of the highest grid draw, absent without peak charge.
+    #[serde(default)]
+    pub peak_price: Option<i64>,
+    /// Minutes per settlement period the peak is averaged over, absent if single timesteps
+    /// are measured.
+    #[serde(default)]
+    pub settlement_period: Option<u32>,
+    /// Batteries whose dispatch isn't optimized. Their ids are also in `batteries`.
+    #[serde(default)]
+    pub fixed_battery_plans: Vec<FixedBatteryPlanDump>,
+    /// Pairs of constant action ids, the first must end before the second starts.
+    #[serde(default)]
+    pub precedences: Vec<(u32, u32)>,
+    /// Constant action ids and the timesteps the second starts after the first ends at least.
+    #[serde(default)]
+    pub min_gaps: Vec<(u32, u32, u32)>,
+    /// Groups of constant action ids of which no two may run at the same time.
+    #[serde(default)]
+    pub exclusion_groups: Vec<Vec<u32>>,
+    /// IDs of assets that are left out when solving.
+    #[serde(default)]
+    pub disabled: Vec<u32>,
+}
+
+impl ProblemDump {
+    /// Capture the current state of a context.
+    pub fn from_context(context: &OptimizerContext) -> Self {
+        let minutes_per_timestep = context.timestep.get_minutes_per_timestep();
+        let minutes = |time: Time| time.to_timestep() * minutes_per_timestep;
+        ProblemDump {
+            format_version: FORMAT_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            start_time: context.start_time,
+            minutes_per_timestep,
+            electricity_price: context.electricity_price.get_data().to_vec(),
+            generated_electricity: context.generated_electricity.get_data().to_vec(),
+            beyond_control_consumption: context.beyond_control_consumption.get_data().to_vec(),
+            batteries: context
+                .batteries
+                .iter()
+                .map(|b| BatteryDump {
+                    id: b.get_id(),
+                    capacity: b.get_capacity(),
+                    initial_level: b.get_initial_level(),
+                    max_charge: b.get_max_charge(),
+                    max_output: b.get_max_output(),
+                    efficiency: b.get_efficiency(),
+                    grid_charging_allowed: b.is_grid_charging_allowed(),
+                    min_charge: b.get_min_charge(),
+                    final_charge_target: b.get_final_charge_target(),
+                    self_discharge_per_hour: b.get_self_discharge_per_hour(),
+                    cycle_cost: b.get_cycle_cost(),
+                })
+                .collect(),
+            constant_actions: context
+                .constant_actions
+                .iter()
+                .map(|a| ConstantActionDump {
+                    id: a.get_id(),
+                    start_from: minutes(a.get_start_from()),
+                    end_before: minutes(a.get_end_before()),
+                    duration: minutes(a.duration),
+                    consumption: a.get_flat_consumption().unwrap_or(0),
+                    profile: match a.get_flat_consumption() {
+                        Some(_) => None,
+                        None => Some(a.get_profile().to_vec()),
+                    },
+                    default_profile: a.get_default_profile().map(<[i64]>::to_vec),
+                })
+                .collect(),
+            variable_actions: context
+                .variable_actions
+                .iter()
+                .map(|a| VariableActionDump {
+                    id: a.get_id(),
+                    start: minutes(a.get_start()),
+                    end: minutes(a.get_end()),
+                    total_consumption: a.get_total_consumption(),
+                    max_consumption: a.get_max_consumption(),
+                    max_consumption_profile: a.get_max_consumption_profile().map(<[i64]>::to_vec),
+                    min_consumption: a.get_min_consumption(),
+                    preference: a.get_preference().map(<[i64]>::to_vec),
+                    preference_limit: a.get_preference_limit(),
+                    contiguous: a.is_contiguous(),
+                })
+                .collect(),
+            feed_in_tariff: context
+                .feed_in_tariff
+                .as_ref()
+                .map(|tariff| tariff.get_data().to_vec()),
+            max_export: context.max_export,
+            network_fee: context
+                .network_fee
+                .as_ref()
+                .map(|fee| fee.get_data().to_vec()),
+            co2_intensity: context
+                .co2_intensity
+                .as_ref()
+                .map(|(intensity, co2_price)| (intensity.get_data().to_vec(), *co2_price)),
+            net_metering: context.net_metering,
+            grid_limit: context.grid_limit,
+            peak_price: context.peak_price,
+            settlement_period: context
+                .settlement_period
+                .map(|steps| steps * minutes_per_timestep),
+            fixed_battery_plans: context
+                .fixed_battery_plans
+                .iter()
+                .map(|(id, charge)| FixedBatteryPlanDump {
+                    id: *id,
+                    charge: charge.get_data().to_vec(),
+                })
+                .collect(),
+            precedences: context.precedences.clone(),
+            min_gaps: context.min_gaps.clone(),
+            exclusion_groups: context.exclusion_groups.clone(),
+            disabled: context.disabled.iter().copied().collect(),
+        }
+    }
+
+    /// Rebuild a context from the dump. Errors if the dump was written with an incompatible
+    /// format or timestep configuration, or if it contains invalid assets.
+    pub fn into_context(self) -> PyResult<OptimizerContext> {
+        if self.format_version != FORMAT_VERSION {
+            return Err(OptimizerError::new_err(format!(
+                "Unsupported problem format version {}, expected {}",
+                self.format_version, FORMAT_VERSION
+            )));
+        }
+        let Some(timestep) = TimestepConfig::new(self.minutes_per_timestep) else {
+            return Err(OptimizerError::new_err(format!(
+                "Problem was dumped with {} minutes per timestep, which don't divide the day",
+                self.minutes_per_timestep
+            )));
+        };
+        let minutes_per_timestep = self.minutes_per_timestep;
+        // Times are whole timesteps, so minutes between two timesteps are rejected
+        let on_grid = |minutes: u32| minutes.is_multiple_of(minutes_per_timestep);
+        let time = |minutes: u32| Time::from_timestep(minutes / minutes_per_timestep);
+
+        let batteries = self
+            .batteries
+            .into_iter()
+            .map(|b| {
+                let valid_rates = b.efficiency > 0.0
+                    && b.efficiency <= 1.0
+                    && (0.0..1.0).contains(&b.self_discharge_per_hour)
+                    && b.cycle_cost >= 0;
+                if b.initial_level > b.capacity
+                    || !valid_rates
+                    || !(0..=b.initial_level).contains(&b.min_charge)
+                    || b.final_charge_target
+                        .is_some_and(|target| target > b.capacity)
+                {
+                    return Err(OptimizerError::new_err(format!("Invalid battery {}", b.id)));
+                }
+                let battery = Battery::new(
+                    b.capacity,
+                    b.initial_level,
+                    b.max_charge,
+                    b.max_output,
+                    b.efficiency,
+                    b.id,
+                )
+                .with_grid_charging_allowed(b.grid_charging_allowed)
+                .with_min_charge(b.min_charge)
+                .with_self_discharge_per_hour(b.self_discharge_per_hour)
+                .with_cycle_cost(b.cycle_cost);
+                Ok(Rc::new(match b.final_charge_target {
+                    Some(target) => battery.with_final_charge_target(target),
+                    None => battery,
+                }))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        if self.max_export.is_some_and(|max_export| max_export < 0) {
+            return Err(OptimizerError::new_err("Negative max_export"));
+        }
+        if self
+            .net_metering
+            .is_some_and(|(_, price_weight)| !price_weight.is_finite() || price_weight < 0.0)
+        {
+            return Err(OptimizerError::new_err("Invalid net_metering price_weight"));
+        }
+        if self.grid_limit.is_some_and(|grid_limit| grid_limit < 0) {
+            return Err(OptimizerError::new_err("Negative grid_limit"));
+        }
+        if self.peak_price.is_some_and(|peak_price| peak_price < 0) {
+            return Err(OptimizerError::new_err("Negative peak_price"));
+        }
+        let settlement_period = self
+            .settlement_period
+            .map(|minutes| settlement_period_to_steps(TimeDelta::minutes(minutes as i64), timestep))
+            .transpose()?;
+        let constant_actions =
+            self.constant_actions
+                .into_iter()
+                .map(|a| {
+                    let profile_matches_duration = a.profile.as_ref().is_none_or(|profile| {
+                        profile.len() as u32 * minutes_per_timestep == a.duration
+                    });
+                    if a.start_from + a.duration > a.end_before
+                        || !profile_matches_duration
+                        || ![a.start_from, a.end_before, a.duration]
+                            .into_iter()
+                            .all(on_grid)
+                    {
+                        return Err(OptimizerError::new_err(format!(
+                            "Invalid time bounds for constant action {}",
+                            a.id
+                        )));
+                    }
+                    if a.default_profile.as_ref().is_some_and(|profile| {
+                        profile.len() != timestep.get_steps_per_day() as usize
+                    }) {
+                        return Err(OptimizerError::new_err(format!(
+                            "Default profile of constant action {} needs one value per timestep",
+                            a.id
+                        )));
+                    }
+                    let action = match a.profile {
+                        Some(profile) => ConstantAction::try_new_profiled(
+                            time(a.start_from),
+                            time(a.end_before),
+                            profile,
+                            a.id,
+                        ),
+                        None => ConstantAction::try_new(
+                            time(a.start_from),
+                            time(a.end_before),
+                            time(a.duration),
+                            a.consumption,
+                            a.id,
+                        ),
+                    };
+                    let action = match a.default_profile {
+                        Some(default_profile) => {
+                            action.and_then(|action| action.with_default_profile(default_profile))
+                        }
+                        None => action,
+                    };
+                    action
+                        .and_then(|action| action.validate_for(timestep).map(|_| action))
+                        .map(Rc::new)
+                        .map_err(|e| OptimizerError::new_err(e.to_string()))
+                })
+                .collect::<PyResult<Vec<_>>>()?;
+        let variable_actions = self
+            .variable_actions
+            .into_iter()
+            .map(|a| {
+                let profile_matches_window = |profile: &Vec<i64>| {
+                    profile.len() as u32 * minutes_per_timestep == a.end - a.start
+                };
+                if a.start >= a.end
+                    || !on_grid(a.start)
+                    || !on_grid(a.end)
+                    || !a.max_consumption_profile.iter().all(profile_matches_window)
+                    || a.min_consumption < 0
+                    || !a.preference.iter().all(profile_matches_window)
+                    || !(a.preference_limit.is_finite() && a.preference_limit >= 0.0)
+                {
+                    return Err(OptimizerError::new_err(format!(
+                        "Invalid time bounds for variable action {}",
+                        a.id
+                    )));
+                }
+                let action = VariableAction::try_new(
+                    time(a.start),
+                    time(a.end),
+                    a.total_consumption,
+                    a.max_consumption,
+                    a.id,
+                )
+                .and_then(|action| action.validate_for(timestep).map(|_| action))
+                .map_err(|e| OptimizerError::new_err(e.to_string()))?
+                .with_min_consumption(a.min_consumption)
+                .with_contiguous(a.contiguous);
+                let action = match a.max_consumption_profile {
+                    Some(profile) => action.with_max_consumption_profile(profile),
+                    None => action,
+                };
+                if a.contiguous {
+                    action
+                        .get_block()
+                        .map_err(|e| OptimizerError::new_err(e.to_string()))?;
+                }
+                Ok(Rc::new(match a.preference {
+                    Some(preference) => action.with_preference(preference, a.preference_limit),
+                    None => action,
+                }))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let fixed_battery_plans = self
+            .fixed_battery_plans
+            .into_iter()
+            .map(|plan| {
+                let name = format!("fixed_battery_plans[{}]", plan.id);
+                Ok((plan.id, prognoses_from_vec(&name, plan.charge, timestep)?))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let context = OptimizerContext {
+            electricity_price: prognoses_from_vec(
+                "electricity_price",
+                self.electricity_price,
+                timestep,
+            )?,
+            generated_electricity: prognoses_from_vec(
+                "generated_electricity",
+                self.generated_electricity,
+                timestep,
+            )?,
+            beyond_control_consumption: prognoses_from_vec(
+                "beyond_control_consumption",
+                self.beyond_control_consumption,
+                timestep,
+            )?,
+            batteries,
+            constant_actions,
+            variable_actions,
+            feed_in_tariff: self
+                .feed_in_tariff
+                .map(|tariff| prognoses_from_vec("feed_in_tariff", tariff, timestep))
+                .transpose()?,
+            max_export: self.max_export,
+            network_fee: self
+                .network_fee
+                .map(|fee| prognoses_from_vec("network_fee", fee, timestep))
+                .transpose()?,
+            co2_intensity: self
+                .co2_intensity
+                .map(|(intensity, co2_price)| {
+                    prognoses_from_vec("co2_intensity", intensity, timestep)
+                        .map(|intensity| (intensity, co2_price))
+                })
+                .transpose()?,
+            net_metering: self.net_metering,
+            grid_limit: self.grid_limit,
+            peak_price: self.peak_price,
+            settlement_period,
+            fixed_battery_plans,
+            precedences: self.precedences,
+            min_gaps: self.min_gaps,
+            exclusion_groups: self.exclusion_groups,
+            disabled: self.disabled.into_iter().collect(),
+            absolute_actions: vec![],
+            plausibility_limits: PlausibilityLimits::default(),
+            start_time: self.start_time,
+            timestep,
+        };
+        // Fixed plans, precedences and exclusion groups are only checked against their assets
+        // when converting
+        context.to_rust()?;
+        Ok(context)
+    }
+}
+
+/// Convert a dumped series back into prognoses, checking it covers exactly one horizon of
+/// `timestep`.
+fn prognoses_from_vec(
+    name: &str,
+    data: Vec<i64>,
+    timestep: TimestepConfig,
+) -> PyResult<Prognoses<i64>> {
+    let (len, expected) = (data.len(), timestep.get_steps_per_day());
+    if len != expected as usize {
+        return Err(PrognosesError::new_err(format!(
+            "{} has {} entries, expected {}",
+            name, len, expected
+        )));
+    }
+    Prognoses::try_from(data).map_err(PrognosesError::new_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::TimeZone;
+    use electricity_price_optimizer::optimizer::SmartHomeFlowBuilder;
+
+    use super::*;
+
+    fn example_context() -> OptimizerContext {
+        OptimizerContext {
+            electricity_price: Prognoses::from_closure(|t| (t.to_timestep() % 90) as i64 + 5),
+            generated_electricity: Prognoses::from_closure(|t| (t.to_timestep() % 60) as i64),
+            beyond_control_consumption: Prognoses::from_closure(|_| 20),
+            batteries: vec![Rc::new(
+                Battery::new(1000, 100, 50, 50, 0.9, 1)
+                    .with_min_charge(20)
+                    .with_final_charge_target(500)
+                    .with_self_discharge_per_hour(0.01)
+                    .with_cycle_cost(3),
+            )],
+            constant_actions: vec![
+                Rc::new(
+                    ConstantAction::new(
+                        Time::at_default_resolution(1, 0),
+                        Time::at_default_resolution(5, 0),
+                        Time::at_default_resolution(1, 0),
+                        300,
+                        2,
+                    )
+                    .with_default_profile(Prognoses::from_closure(|_| 5).into())
+                    .unwrap(),
+                ),
+                Rc::new(ConstantAction::new_profiled(
+                    Time::at_default_resolution(2, 0),
+                    Time::at_default_resolution(4, 0),
+                    vec![500, 500, 40, 40, 40],
+                    4,
+                )),
+            ],
+            variable_actions: vec![Rc::new(VariableAction::new(
+                Time::at_default_resolution(6, 0),
+                Time::at_default_resolution(12, 0),
+                2000,
+                50,
+                3,
+            ))],
+            feed_in_tariff: Some(Prognoses::from_closure(|_| 4)),
+            max_export: Some(40),
+            network_fee: Some(Prognoses::from_closure(|t| {
+                if t < Time::at_default_resolution(6, 0) {
+                    9
+                } else {
+                    3
+                }
+            })),
+            co2_intensity: Some((
+                Prognoses::from_closure(|t| {
+                    if t < Time::at_default_resolution(12, 0) {
+                        400
+                    } else {
+                        150
+                    }
+                }),
+                0.02,
+            )),
+            net_metering: Some((250, 0.5)),
+            grid_limit: Some(400),
+            peak_price: Some(600_000),
+            settlement_period: Some(15),
+            fixed_battery_plans: vec![],
+            precedences: vec![],
+            min_gaps: vec![],
+            exclusion_groups: vec![vec![2, 4]],
+            disabled: BTreeSet::new(),
+            absolute_actions: vec![],
+            plausibility_limits: PlausibilityLimits::default(),
+            start_time: Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap(),
+            timestep: TimestepConfig::default(),
+        }
+    }
+
+    fn exact_cost(context: &OptimizerContext) -> i64 {
+        let context = context.to_rust().unwrap();
+        SmartHomeFlowBuilder::new(
+            context.get_generated_electricity(),
+            context.get_electricity_price(),
+            context.get_beyond_control_consumption(),
+            context.get_first_timestep_fraction(),
+        )
+        .with_network_fee(context.get_network_fee().unwrap())
+        .with_co2(
+            context.get_co2_intensity().unwrap(),
+            &context.get_co2_cost().unwrap(),
+        )
+        .with_feed_in_tariff(
+            context.get_feed_in_tariff().unwrap(),
+            context.get_max_export(),
+        )
+        .add_batteries(context.get_batteries())
+        .add_actions(context.get_variable_actions())
+        .with_grid_limit(context.get_grid_limit().unwrap())
+        .build()
+        .get_cost()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_problem_dump_round_trip() {
+        let context = example_context();
+        let dump = ProblemDump::from_context(&context);
+        let json = serde_json::to_string(&dump).unwrap();
+
+        let reloaded: ProblemDump = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded, dump);
+        let reloaded = reloaded.into_context().unwrap();
+        assert_eq!(ProblemDump::from_context(&reloaded), dump);
+        assert_eq!(exact_cost(&reloaded), exact_cost(&context));
+    }
+
+    #[test]
+    fn test_problem_dump_rejects_other_versions() {
+        let mut dump = ProblemDump::from_context(&example_context());
+        dump.format_version = FORMAT_VERSION + 1;
+        assert!(dump.into_context().is_err());
+    }
+
+    #[test]
+    fn test_problem_dump_rejects_negative_max_export() {
+        let mut dump = ProblemDump::from_context(&example_context());
+        dump.max_export = Some(-1);
+        assert!(dump.into_context().is_err());
+
+        let mut dump = ProblemDump::from_context(&example_context());
+        dump.grid_limit = Some(-1);
+        assert!(dump.into_context().is_err());
+    }
+}
@@ -0,0 +1,404 @@
+//! Per-timestep records of a schedule.
+//!
+//! A record is a dict with the keys `time`, `network_consumption`, `grid_export`, `constant_actions`,
+//! `variable_actions` and `batteries`. The asset entries map IDs to the consumption (Watt)
+//! or charge level (WattHour) at that timestep. Records are computed on demand from the
+//! Rust schedule, so iterating over a long horizon does not materialize all rows at once.
+//!
+//! `to_csv` writes the same timesteps as one table, see `Schedule.to_csv`.
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use electricity_price_optimizer::{
+    optimizer_context::OptimizerContext as RustOptimizerContext, time::Time,
+};
+use pyo3::{
+    Bound, Py, PyRef, PyRefMut, PyResult, Python, pyclass, pymethods,
+    types::{PyDict, PyDictMethods},
+};
+
+use crate::{
+    OptimizerError, Schedule,
+    units::{EuroPerWh, Watt, WattHour},
+};
+
+/// IDs of all scheduled assets in ascending order, so records have a stable key order.
+struct AssetIds {
+    constant_actions: Vec<u32>,
+    variable_actions: Vec<u32>,
+    batteries: Vec<u32>,
+}
+
+impl AssetIds {
+    fn new(schedule: &Schedule) -> Self {
+        let sorted = |mut ids: Vec<u32>| {
+            ids.sort_unstable();
+            ids
+        };
+        AssetIds {
+            constant_actions: sorted(schedule.inner.constant_actions.keys().copied().collect()),
+            variable_actions: sorted(schedule.inner.variable_actions.keys().copied().collect()),
+            batteries: sorted(schedule.inner.batteries.keys().copied().collect()),
+        }
+    }
+}
+
+/// Convert optional range bounds into a timestep range, validating alignment and order.
+/// Missing bounds default to the start and end of the schedule horizon.
+pub(crate) fn timestep_range(
+    schedule: &Schedule,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> PyResult<(u32, u32)> {
+    let grid = schedule.grid();
+    let steps = grid.timestep().get_steps_per_day();
+    let to_timestep = |dt: DateTime<Utc>| -> PyResult<u32> {
+        let timestep = grid.to_time(dt)?.to_timestep();
+        if timestep > steps {
+            return Err(OptimizerError::new_err(format!(
+                "DateTime {} is after the end of the schedule",
+                dt
+            )));
+        }
+        Ok(timestep)
+    };
+    let start_step = start.map(to_timestep).transpose()?.unwrap_or(0);
+    let end_step = end.map(to_timestep).transpose()?.unwrap_or(steps);
+    if start_step > end_step {
+        return Err(OptimizerError::new_err(
+            "Start of the record range is after its end",
+        ));
+    }
+    Ok((start_step, end_step))
+}
+
+/// Build the record of a single timestep.
+fn build_record<'py>(
+    py: Python<'py>,
+    schedule: &Schedule,
+    ids: &AssetIds,
+    timestep: u32,
+) -> PyResult<Bound<'py, PyDict>> {
+    let time = Time::from_timestep(timestep);
+    let inner = &schedule.inner;
+    let grid = schedule.grid();
+    let record = PyDict::new(py);
+    record.set_item("time", grid.to_datetime(time)?)?;
+    let network_consumption = inner.network_consumption.get(time).copied().unwrap_or(0);
+    record.set_item(
+        "network_consumption",
+        Watt::from_energy_per_step(network_consumption as f64, grid.step()),
+    )?;
+    record.set_item(
+        "grid_export",
+        Watt::from_energy_per_step(inner.get_grid_export(time) as f64, grid.step()),
+    )?;
+
+    let constant_actions = PyDict::new(py);
+    for id in &ids.constant_actions {
+        let action = &inner.constant_actions[id];
+        let consumption = action.get_consumption(time);
+        constant_actions.set_item(
+            id,
+            Watt::from_energy_per_step(consumption as f64, grid.step()),
+        )?;
+    }
+    record.set_item("constant_actions", constant_actions)?;
+
+    let variable_actions = PyDict::new(py);
+    for id in &ids.variable_actions {
+        let action = &inner.variable_actions[id];
+        let consumption = if action.get_start() <= time && time < action.get_end() {
+            action.get_consumption(time)
+        } else {
+            0
+        };
+        variable_actions.set_item(
+            id,
+            Watt::from_energy_per_step(consumption as f64, grid.step()),
+        )?;
+    }
+    record.set_item("variable_actions", variable_actions)?;
+
+    let batteries = PyDict::new(py);
+    for id in &ids.batteries {
+        let level = inner.batteries[id]
+            .get_charge_level(time)
+            .copied()
+            .unwrap_or(0);
+        batteries.set_item(id, WattHour::from_milli_wh(level as f64))?;
+    }
+    record.set_item("batteries", batteries)?;
+    Ok(record)
+}
+
+/// Build all records of a timestep range at once.
+pub(crate) fn collect_records<'py>(
+    py: Python<'py>,
+    schedule: &Schedule,
+    start_step: u32,
+    end_step: u32,
+) -> PyResult<Vec<Bound<'py, PyDict>>> {
+    let ids = AssetIds::new(schedule);
+    (start_step..end_step)
+        .map(|timestep| build_record(py, schedule, &ids, timestep))
+        .collect()
+}
+
+/// Write every timestep of the horizon as a CSV row with the price in Euro per Wh, the grid
+/// import and export, the generation if `context` is given, the level (Wh) and charge speed
+/// (W) of every battery and the consumption (W) of every action, see `Schedule.to_csv`.
+pub(crate) fn to_csv(
+    schedule: &Schedule,
+    context: Option<&RustOptimizerContext>,
+) -> PyResult<String> {
+    let rows = schedule.inner.to_rows(context);
+    let grid = schedule.grid();
+    let power = |energy: i64| Watt::from_energy_per_step(energy as f64, grid.step()).value;
+
+    let mut csv = String::from("time,electricity_price,network_consumption,grid_export");
+    if context.is_some() {
+        csv.push_str(",generation");
+    }
+    if let Some(first) = rows.first() {
+        for (id, _, _) in &first.batteries {
+            write!(csv, ",battery_{id}_level,battery_{id}_speed").expect("Writing to a String");
+        }
+        for (id, _) in &first.actions {
+            write!(csv, ",action_{id}_consumption").expect("Writing to a String");
+        }
+    }
+    csv.push('\n');
+    for row in rows {
+        let time = grid
+            .to_datetime(row.time)?
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        let mut values = vec![
+            EuroPerWh::from_micro_euro_per_wh(row.electricity_price as f64).value,
+            power(row.network_consumption),
+            power(row.grid_export),
+        ];
+        values.extend(row.generation.map(power));
+        for (_, level, charge) in row.batteries {
+            values.push(WattHour::from_milli_wh(level as f64).value);
+            values.push(power(charge));
+        }
+        values.extend(
+            row.actions
+                .into_iter()
+                .map(|(_, consumption)| power(consumption)),
+        );
+        csv.push_str(&time);
+        for value in values {
+            write!(csv, ",{value}").expect("Writing to a String");
+        }
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+#[pyclass(unsendable)]
+/// Lazy iterator over the per-timestep records of a Schedule.
+/// Each record is computed when it is requested.
+pub struct ScheduleRecordIterator {
+    schedule: Py<Schedule>,
+    ids: AssetIds,
+    next_step: u32,
+    end_step: u32,
+}
+
+impl ScheduleRecordIterator {
+    pub(crate) fn new(schedule: PyRef<'_, Schedule>, start_step: u32, end_step: u32) -> Self {
+        let ids = AssetIds::new(&schedule);
+        ScheduleRecordIterator {
+            schedule: schedule.into(),
+            ids,
+            next_step: start_step,
+            end_step,
+        }
+    }
+}
+
+#[pymethods]
+impl ScheduleRecordIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    fn __next__<'py>(mut slf: PyRefMut<'py, Self>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        if slf.next_step >= slf.end_step {
+            return Ok(None);
+        }
+        let py = slf.py();
+        let timestep = slf.next_step;
+        slf.next_step += 1;
+        let schedule = slf.schedule.borrow(py);
+        build_record(py, &schedule, &slf.ids, timestep).map(Some)
+    }
+    /// Number of records that have not been yielded yet.
+    fn __len__(&self) -> usize {
+        (self.end_step - self.next_step) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, rc::Rc};
+
+    use chrono::{TimeDelta, TimeZone};
+    use electricity_price_optimizer::{
+        optimizer_context::{
+            action::{
+                constant::{AssignedConstantAction, ConstantAction},
+                variable::{AssignedVariableAction, VariableAction},
+            },
+            battery::{AssignedBattery, Battery},
+            prognoses::Prognoses,
+        },
+        schedule::Schedule as RustSchedule,
+        time::STEPS_PER_DAY,
+    };
+    use pyo3::{PyAny, types::PyAnyMethods};
+
+    use super::*;
+
+    fn example_schedule() -> Schedule {
+        let constant_action = Rc::new(ConstantAction::new(
+            Time::at_default_resolution(1, 0),
+            Time::at_default_resolution(5, 0),
+            Time::at_default_resolution(1, 0),
+            300,
+            2,
+        ));
+        let variable_action = Rc::new(VariableAction::new(
+            Time::at_default_resolution(1, 30),
+            Time::at_default_resolution(2, 30),
+            600,
+            20,
+            3,
+        ));
+        let battery = Rc::new(Battery::new(1000, 100, 50, 50, 1.0, 1));
+        let inner = RustSchedule::new(
+            HashMap::from([(
+                2,
+                AssignedConstantAction::new(constant_action, Time::at_default_resolution(2, 0)),
+            )]),
+            HashMap::from([(
+                3,
+                AssignedVariableAction::new(variable_action, vec![10; 60]),
+            )]),
+            HashMap::from([(
+                1,
+                AssignedBattery::new(
+                    battery,
+                    Prognoses::from_closure(|t| t.to_timestep() as i64 % 100),
+                ),
+            )]),
+            Prognoses::from_closure(|t| t.to_timestep() as i64 * 2),
+            Prognoses::from_closure(|t| t.to_timestep() as i64 % 3),
+            Prognoses::from_closure(|_| 10),
+        );
+        Schedule {
+            inner,
+            start_timestamp: Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_iter_records_matches_eager_slice() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schedule = Py::new(py, example_schedule()).unwrap();
+            let start_timestamp = schedule.borrow(py).start_timestamp;
+            let eager = schedule.borrow(py).to_records(py, None, None).unwrap();
+            assert_eq!(eager.len(), STEPS_PER_DAY as usize);
+
+            let start = start_timestamp + TimeDelta::minutes(90);
+            let end = start_timestamp + TimeDelta::minutes(190);
+            let iterator =
+                Schedule::iter_records(schedule.borrow(py), Some(start), Some(end)).unwrap();
+            let iterator = Bound::new(py, iterator).unwrap();
+            assert_eq!(iterator.len().unwrap(), 100);
+            let lazy = iterator
+                .try_iter()
+                .unwrap()
+                .collect::<PyResult<Vec<Bound<'_, PyAny>>>>()
+                .unwrap();
+
+            assert_eq!(lazy.len(), 100);
+            for (lazy, eager) in lazy.iter().zip(&eager[90..190]) {
+                assert!(lazy.eq(eager).unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn test_iter_records_validates_range() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schedule = Py::new(py, example_schedule()).unwrap();
+            let start_timestamp = schedule.borrow(py).start_timestamp;
+            let misaligned = start_timestamp + TimeDelta::seconds(30);
+            let later = start_timestamp + TimeDelta::hours(2);
+            let after_horizon = start_timestamp + TimeDelta::days(2);
+            for (start, end) in [
+                (Some(misaligned), None),
+                (Some(later), Some(start_timestamp)),
+                (None, Some(after_horizon)),
+            ] {
+                assert!(Schedule::iter_records(schedule.borrow(py), start, end).is_err());
+            }
+        });
+    }
+
+    #[test]
+    fn test_csv_matches_accessors() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut schedule = example_schedule();
+            let battery = schedule.inner.batteries.remove(&1).unwrap();
+            schedule
+                .inner
+                .batteries
+                .insert(1, battery.with_final_charge_level(70));
+            let globals = PyDict::new(py);
+            globals
+                .set_item("schedule", Py::new(py, schedule).unwrap())
+                .unwrap();
+            py.run(
+                c"
+import csv, io
+from datetime import datetime
+
+rows = list(csv.DictReader(io.StringIO(schedule.to_csv())))
+assert len(rows) == 1440
+assert list(rows[0]) == [
+    'time', 'electricity_price', 'network_consumption', 'grid_export',
+    'battery_1_level', 'battery_1_speed', 'action_2_consumption', 'action_3_consumption',
+]
+assert rows[0]['time'] == '2025-03-01T06:00:00Z'
+battery = schedule.get_battery(1)
+constant = schedule.get_constant_action(2)
+variable = schedule.get_variable_action(3)
+for row in rows:
+    time = datetime.fromisoformat(row['time'])
+    values = {key: float(value) for key, value in row.items() if key != 'time'}
+    assert values['electricity_price'] == 0.00001
+    assert values['network_consumption'] == schedule.get_network_consumption(time).get_value()
+    assert values['grid_export'] == schedule.get_grid_export(time).get_value()
+    assert values['battery_1_level'] == battery.get_charge_level(time).get_value()
+    assert values['battery_1_speed'] == battery.get_charge_speed(time).get_value()
+    running = constant.get_start_time() <= time < constant.get_end_time()
+    expected = constant.get_consumption().get_value() if running else 0.0
+    assert values['action_2_consumption'] == expected, row
+    start, end = variable.get_consumption_profile()[0][0], variable.get_consumption_profile()[-1][0]
+    expected = variable.get_consumption(time).get_value() if start <= time <= end else 0.0
+    assert values['action_3_consumption'] == expected, row
+assert float(rows[-1]['battery_1_speed']) != 0.0
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
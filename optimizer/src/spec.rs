@@ -0,0 +1,1233 @@
+//! Declarative description of a whole problem, e.g. loaded from a YAML config.
+//!
+//! A spec is a dict with the start time, the names of the prognoses providers and all assets.
+//! Quantities are strings with a unit ("2.5 kW", "11 kWh"), durations are ISO 8601 durations
+//! ("PT2H") and timestamps are RFC 3339 strings. Parse errors name the path into the spec,
+//! e.g. `actions[2].duration`.
+//!
+//! ```text
+//! {
+//!     "start_time": "2025-03-01T06:00:00Z",
+//!     "resolution": "PT15M",                        # optional, one minute by default
+//!     "electricity_price": "price",                 # provider names
+//!     "generated_electricity": ["pv_east", "pv_west"],
+//!     "beyond_control_consumption": "base_load",
+//!     "feed_in_tariff": "tariff",
+//!     "max_export": "5 kW",
+//!     "network_fee": "grid_fee",
+//!     "grid_limit": "17 kW",
+//!     "peak_price": 0.01,                           # Euro per W of the highest grid draw
+//!     "settlement_period": "PT15M",                 # peak measured on 15 minute averages
+//!     "batteries": [{"id": 1, "capacity": "11 kWh", "max_charge_rate": "5 kW",
+//!                    "max_discharge_rate": "5 kW", "initial_charge": "2 kWh"}],
+//!     "actions": [
+//!         {"type": "constant", "id": 2, "end_before": "2025-03-01T10:00:00Z",
+//!          "duration": "PT2H", "consumption": "2.5 kW"},
+//!         {"type": "profiled", "id": 3, "end_before": "...", "profile": ["2 kW", "0.2 kW"]},
+//!         {"type": "variable", "id": 4, "end": "...", "total_consumption": "11 kWh",
+//!          "max_consumption": "11 kW"},
+//!     ],
+//! }
+//! ```
+//!
+//! The `max_consumption` of a variable action can also be a list with one power per timestep
+//! of its window, its optional `min_consumption` is the least power it runs at and its optional
+//! `contiguous` makes it consume in one block. Optional battery keys are `efficiency`, `grid_charging_allowed`, `min_charge`,
+//! `final_charge_target`, `self_discharge_per_hour` and `cycle_cost` (Euro per Wh stored). Actions without `start_from` (or `start` for variable actions) can
+//! start at the start time. `to_spec` refers to the prognoses by the names of the keys.
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use electricity_price_optimizer::{
+    optimizer_context::prognoses::Prognoses,
+    time::{Time, TimestepConfig},
+};
+use pyo3::{
+    Bound, PyAny, PyErr, PyRef, PyResult, Python,
+    prelude::FromPyObjectOwned,
+    types::{PyAnyMethods, PyDict, PyDictMethods, PyString, PyStringMethods},
+};
+
+use crate::{
+    Battery, ConstantAction, MaxConsumption, OptimizerContext, OptimizerError,
+    ProfiledConstantAction, PrognosesProvider, VariableAction, peak_price_from_internal,
+    settlement_period_to_steps,
+    timegrid::TimeGrid,
+    timestep_config,
+    units::{EuroPerWh, Watt, WattHour},
+};
+
+/// Units with the power of ten they are away from the base unit.
+const POWER_UNITS: [(&str, i32); 3] = [("W", 0), ("kW", 3), ("MW", 6)];
+const ENERGY_UNITS: [(&str, i32); 3] = [("Wh", 0), ("kWh", 3), ("MWh", 6)];
+
+/// A value inside the spec together with its path, used for error messages.
+struct Node<'py> {
+    value: Bound<'py, PyAny>,
+    path: String,
+}
+
+impl<'py> Node<'py> {
+    fn error(&self, message: impl std::fmt::Display) -> PyErr {
+        OptimizerError::new_err(format!("{}: {}", self.path, message))
+    }
+
+    /// Prefix an error raised while building an asset with the path of its description.
+    /// Errors of the optimizer keep their class, e.g. values too large for it stay a UnitError.
+    fn wrap(&self, err: PyErr) -> PyErr {
+        let py = self.value.py();
+        if err.is_instance_of::<OptimizerError>(py) {
+            return PyErr::from_type(
+                err.get_type(py),
+                format!("{}: {}", self.path, err.value(py)),
+            );
+        }
+        self.error(err.value(py))
+    }
+
+    fn dict(&self) -> PyResult<&Bound<'py, PyDict>> {
+        self.value
+            .cast::<PyDict>()
+            .map_err(|_| self.error("expected a dict"))
+    }
+
+    /// Errors on keys that aren't in `allowed`, so typos don't go unnoticed.
+    fn check_keys(&self, allowed: &[&str]) -> PyResult<()> {
+        for key in self.dict()?.keys() {
+            let key: String = key
+                .extract()
+                .map_err(|_| self.error("keys must be strings"))?;
+            if !allowed.contains(&key.as_str()) {
+                return Err(self.error(format!("unknown key '{}'", key)));
+            }
+        }
+        Ok(())
+    }
+
+    fn child(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    /// A key that may be missing or None.
+    fn optional(&self, key: &str) -> PyResult<Option<Node<'py>>> {
+        Ok(self
+            .dict()?
+            .get_item(key)?
+            .filter(|value| !value.is_none())
+            .map(|value| Node {
+                value,
+                path: self.child(key),
+            }))
+    }
+
+    fn required(&self, key: &str) -> PyResult<Node<'py>> {
+        self.optional(key)?
+            .ok_or_else(|| OptimizerError::new_err(format!("{}: missing", self.child(key))))
+    }
+
+    fn items(&self) -> PyResult<Vec<Node<'py>>> {
+        let items: Vec<Bound<'py, PyAny>> = self
+            .value
+            .extract()
+            .map_err(|_| self.error("expected a list"))?;
+        Ok(items
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| Node {
+                value,
+                path: format!("{}[{}]", self.path, i),
+            })
+            .collect())
+    }
+
+    fn extract<T: FromPyObjectOwned<'py>>(&self, expected: &str) -> PyResult<T> {
+        self.value
+            .extract()
+            .map_err(|_| self.error(format!("expected {}", expected)))
+    }
+
+    fn string(&self) -> PyResult<String> {
+        Ok(self
+            .value
+            .cast::<PyString>()
+            .map_err(|_| self.error("expected a string"))?
+            .to_str()?
+            .to_string())
+    }
+
+    /// One or more names, given as a string or a list of strings.
+    fn names(&self) -> PyResult<Vec<String>> {
+        if self.value.cast::<PyString>().is_ok() {
+            return Ok(vec![self.string()?]);
+        }
+        self.items()?.iter().map(Node::string).collect()
+    }
+
+    fn quantity(&self, units: &[(&str, i32)]) -> PyResult<f64> {
+        let text = self.string()?;
+        parse_quantity(&text, units).ok_or_else(|| {
+            let names: Vec<&str> = units.iter().map(|(name, _)| *name).collect();
+            self.error(format!(
+                "expected a number with one of the units {}, got '{}'",
+                names.join(", "),
+                text
+            ))
+        })
+    }
+
+    fn power(&self) -> PyResult<Watt> {
+        Ok(Watt {
+            value: self.quantity(&POWER_UNITS)?,
+        })
+    }
+
+    /// A power, or a list with one power per timestep.
+    fn max_consumption(&self) -> PyResult<MaxConsumption> {
+        if self.value.is_instance_of::<PyString>() {
+            return Ok(MaxConsumption::Constant(self.power()?));
+        }
+        let caps = self
+            .items()?
+            .iter()
+            .map(Node::power)
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(MaxConsumption::PerTimestep(caps))
+    }
+
+    fn energy(&self) -> PyResult<WattHour> {
+        Ok(WattHour {
+            value: self.quantity(&ENERGY_UNITS)?,
+        })
+    }
+
+    fn duration(&self) -> PyResult<TimeDelta> {
+        let text = self.string()?;
+        parse_iso_duration(&text).ok_or_else(|| {
+            self.error(format!(
+                "expected an ISO 8601 duration like 'PT2H', got '{}'",
+                text
+            ))
+        })
+    }
+
+    fn datetime(&self) -> PyResult<DateTime<Utc>> {
+        let text = self.string()?;
+        DateTime::parse_from_rfc3339(&text)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|e| {
+                self.error(format!(
+                    "expected an RFC 3339 timestamp ({}), got '{}'",
+                    e, text
+                ))
+            })
+    }
+}
+
+/// Parse "2.5 kW" style quantities into the base unit. The space is optional.
+fn parse_quantity(text: &str, units: &[(&str, i32)]) -> Option<f64> {
+    let text = text.trim();
+    let unit_start = text.find(|c: char| c.is_alphabetic())?;
+    let (number, unit) = text.split_at(unit_start);
+    let number = number.trim();
+    if !number.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+        return None;
+    }
+    let (_, exponent) = units.iter().find(|(name, _)| *name == unit)?;
+    // Shift the decimal point while parsing, so "1.001 kWh" is exactly 1001 Wh.
+    let value: f64 = format!("{}e{}", number, exponent).parse().ok()?;
+    value.is_finite().then_some(value)
+}
+
+/// Parse the day and time parts of an ISO 8601 duration, e.g. "P1DT2H30M" or "PT45S".
+fn parse_iso_duration(text: &str) -> Option<TimeDelta> {
+    let rest = text.strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, time),
+        None => (rest, ""),
+    };
+    if date.is_empty() && time.is_empty() {
+        return None;
+    }
+    let mut total = TimeDelta::zero();
+    for (part, designators) in [(date, "D"), (time, "HMS")] {
+        let mut number = String::new();
+        let mut last_designator = 0;
+        for c in part.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            // Designators must be in order and each needs a number in front of it.
+            let index = designators.find(c)? + 1;
+            if index <= last_designator || number.is_empty() {
+                return None;
+            }
+            last_designator = index;
+            let value: i64 = number.parse().ok()?;
+            number.clear();
+            total += match c {
+                'D' => TimeDelta::try_days(value)?,
+                'H' => TimeDelta::try_hours(value)?,
+                'M' => TimeDelta::try_minutes(value)?,
+                _ => TimeDelta::try_seconds(value)?,
+            };
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Some(total)
+}
+
+fn format_iso_duration(duration: TimeDelta) -> String {
+    let minutes = duration.num_minutes();
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "PT0S".to_string(),
+        (hours, 0) => format!("PT{}H", hours),
+        (0, minutes) => format!("PT{}M", minutes),
+        (hours, minutes) => format!("PT{}H{}M", hours, minutes),
+    }
+}
+
+/// Shortest decimal of a base unit value that converts back to `internal`. Conversions to the
+/// internal integers truncate, so the exact value would often come back one lower.
+fn format_round_trip(
+    internal: i64,
+    to_base: impl Fn(f64) -> f64,
+    to_internal: impl Fn(f64) -> i64,
+    unit: &str,
+) -> String {
+    // The middle of the interval that truncates to `internal` has the most room for rounding.
+    let middle = to_base(internal as f64 + 0.5_f64.copysign(internal as f64));
+    (0..=12)
+        .map(|decimals| format!("{:.*}", decimals, middle))
+        .find(|text| {
+            text.parse()
+                .is_ok_and(|value| to_internal(value) == internal)
+        })
+        .map_or_else(
+            || format!("{} {}", middle, unit),
+            |text| format!("{} {}", text, unit),
+        )
+}
+
+fn format_power(milli_wh_per_step: i64, grid: TimeGrid) -> String {
+    format_round_trip(
+        milli_wh_per_step,
+        |milli_wh| Watt::from_energy_per_step(milli_wh, grid.step()).value,
+        |value| Watt { value }.to_energy_per_step(grid.step()) as i64,
+        "W",
+    )
+}
+
+fn format_energy(milli_wh: i64) -> String {
+    format_round_trip(
+        milli_wh,
+        |milli_wh| WattHour::from_milli_wh(milli_wh).value,
+        |value| WattHour { value }.to_milli_wh() as i64,
+        "Wh",
+    )
+}
+
+fn format_datetime(time: Time, grid: TimeGrid) -> PyResult<String> {
+    Ok(grid
+        .to_datetime(time)?
+        .to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+/// Look up a provider by the name used in the spec.
+fn provider<'py>(
+    providers: &Bound<'py, PyDict>,
+    node: &Node<'py>,
+    name: &str,
+) -> PyResult<PyRef<'py, PrognosesProvider>> {
+    providers
+        .get_item(name)?
+        .ok_or_else(|| node.error(format!("no provider named '{}'", name)))?
+        .extract()
+        .map_err(|_| node.error(format!("provider '{}' is not a PrognosesProvider", name)))
+}
+
+/// Sum the energy prognoses of all providers named at `node`.
+fn energy_prognoses<'py>(
+    py: Python<'py>,
+    providers: &Bound<'py, PyDict>,
+    node: &Node<'py>,
+    grid: TimeGrid,
+) -> PyResult<Prognoses<i64>> {
+    let mut total = Prognoses::from_closure_for(grid.timestep(), |_| 0);
+    for name in node.names()? {
+        total += provider(providers, node, &name)?
+            .try_get_prognoses(py, grid, |energy: WattHour| energy.to_internal("energy"))
+            .map_err(|e| node.wrap(e))?;
+    }
+    Ok(total)
+}
+
+fn battery_from_spec(node: &Node<'_>) -> PyResult<Battery> {
+    node.check_keys(&[
+        "id",
+        "capacity",
+        "max_charge_rate",
+        "max_discharge_rate",
+        "initial_charge",
+        "efficiency",
+        "grid_charging_allowed",
+        "min_charge",
+        "final_charge_target",
+        "self_discharge_per_hour",
+        "cycle_cost",
+    ])?;
+    let efficiency = match node.optional("efficiency")? {
+        Some(efficiency) => efficiency.extract("a number")?,
+        None => 1.0,
+    };
+    let grid_charging_allowed = match node.optional("grid_charging_allowed")? {
+        Some(allowed) => allowed.extract("a bool")?,
+        None => true,
+    };
+    let min_charge = match node.optional("min_charge")? {
+        Some(min_charge) => min_charge.energy()?,
+        None => WattHour::default(),
+    };
+    let final_charge_target = node
+        .optional("final_charge_target")?
+        .map(|target| target.energy())
+        .transpose()?;
+    let self_discharge_per_hour = match node.optional("self_discharge_per_hour")? {
+        Some(rate) => rate.extract("a number")?,
+        None => 0.0,
+    };
+    let cycle_cost = match node.optional("cycle_cost")? {
+        Some(cost) => EuroPerWh {
+            value: cost.extract("a number")?,
+        },
+        None => EuroPerWh::default(),
+    };
+    Battery::new(
+        node.required("capacity")?.energy()?,
+        node.required("max_charge_rate")?.power()?,
+        node.required("max_discharge_rate")?.power()?,
+        node.required("initial_charge")?.energy()?,
+        node.required("id")?.extract("an integer id")?,
+        efficiency,
+        grid_charging_allowed,
+        min_charge,
+        final_charge_target,
+        self_discharge_per_hour,
+        cycle_cost,
+    )
+    .map_err(|e| node.wrap(e))
+}
+
+fn add_action_from_spec<'py>(
+    py: Python<'py>,
+    context: &mut OptimizerContext,
+    node: &Node<'py>,
+) -> PyResult<()> {
+    let kind = node.required("type")?.string()?;
+    let id: u32 = node.required("id")?.extract("an integer id")?;
+    let result = match kind.as_str() {
+        "constant" => {
+            node.check_keys(&[
+                "type",
+                "id",
+                "start_from",
+                "end_before",
+                "duration",
+                "consumption",
+            ])?;
+            let action = ConstantAction {
+                start_from: node
+                    .optional("start_from")?
+                    .map(|n| n.datetime())
+                    .transpose()?,
+                end_before: node.required("end_before")?.datetime()?,
+                duration: node.required("duration")?.duration()?,
+                consumption: node.required("consumption")?.power()?,
+                default_profile: None,
+                absolute: false,
+                id,
+            };
+            action
+                .to_rust(py, context.grid())
+                .and_then(|action| context.push_constant_action(action))
+        }
+        "profiled" => {
+            node.check_keys(&["type", "id", "start_from", "end_before", "profile"])?;
+            let profile = node
+                .required("profile")?
+                .items()?
+                .iter()
+                .map(Node::power)
+                .collect::<PyResult<Vec<_>>>()?;
+            let start_from = node.optional("start_from")?;
+            let end_before = node.required("end_before")?.datetime()?;
+            let action = ProfiledConstantAction {
+                start_from: match start_from {
+                    Some(start_from) => start_from.datetime()?,
+                    None => context.start_time,
+                },
+                end_before,
+                profile,
+                absolute: false,
+                id,
+            };
+            action
+                .to_rust(context.grid())
+                .and_then(|action| context.push_constant_action(action))
+        }
+        "variable" => {
+            node.check_keys(&[
+                "type",
+                "id",
+                "start",
+                "end",
+                "total_consumption",
+                "max_consumption",
+                "min_consumption",
+                "contiguous",
+            ])?;
+            let action = VariableAction {
+                start: node.optional("start")?.map(|n| n.datetime()).transpose()?,
+                end: node.required("end")?.datetime()?,
+                total_consumption: node.required("total_consumption")?.energy()?,
+                max_consumption: node.required("max_consumption")?.max_consumption()?,
+                min_consumption: node
+                    .optional("min_consumption")?
+                    .map(|n| n.power())
+                    .transpose()?
+                    .unwrap_or_default(),
+                preference: None,
+                preference_limit: 0.1,
+                contiguous: match node.optional("contiguous")? {
+                    Some(contiguous) => contiguous.extract("a bool")?,
+                    None => false,
+                },
+                absolute: false,
+                id,
+            };
+            context.add_variable_action(py, &action)
+        }
+        other => {
+            return Err(node.error(format!(
+                "unknown action type '{}', expected constant, profiled or variable",
+                other
+            )));
+        }
+    };
+    result.map_err(|e| node.wrap(e))
+}
+
+/// Build a context from a spec, resolving prognoses names with `providers`.
+pub(crate) fn context_from_spec<'py>(
+    py: Python<'py>,
+    spec: &Bound<'py, PyDict>,
+    providers: &Bound<'py, PyDict>,
+) -> PyResult<OptimizerContext> {
+    let root = Node {
+        value: spec.clone().into_any(),
+        path: String::new(),
+    };
+    root.check_keys(&[
+        "start_time",
+        "resolution",
+        "electricity_price",
+        "generated_electricity",
+        "beyond_control_consumption",
+        "feed_in_tariff",
+        "max_export",
+        "network_fee",
+        "grid_limit",
+        "peak_price",
+        "settlement_period",
+        "batteries",
+        "actions",
+    ])?;
+    let start_time = root.required("start_time")?.datetime()?;
+    let resolution = root
+        .optional("resolution")?
+        .map(|node| -> PyResult<TimeDelta> {
+            let resolution = node.duration()?;
+            timestep_config(resolution).map_err(|e| node.wrap(e))?;
+            Ok(resolution)
+        })
+        .transpose()?;
+    let price = root.required("electricity_price")?;
+    let grid_limit = root
+        .optional("grid_limit")?
+        .map(|node| {
+            let grid_limit = node.power()?;
+            if grid_limit.value < 0.0 {
+                return Err(node.error("must not be negative"));
+            }
+            Ok(grid_limit)
+        })
+        .transpose()?;
+    let peak_price = root
+        .optional("peak_price")?
+        .map(|node| {
+            let peak_price: f64 = node.extract("a number")?;
+            if peak_price.is_nan() || peak_price < 0.0 {
+                return Err(node.error("must not be negative"));
+            }
+            Ok(peak_price)
+        })
+        .transpose()?;
+    let mut context = OptimizerContext::new(
+        py,
+        start_time,
+        &*provider(providers, &price, &price.string()?)?,
+        grid_limit,
+        peak_price,
+        None,
+        None,
+        resolution,
+    )
+    .map_err(|e| price.wrap(e))?;
+    let grid = context.grid();
+    if let Some(node) = root.optional("settlement_period")? {
+        let steps = settlement_period_to_steps(node.duration()?, context.timestep)
+            .map_err(|e| node.wrap(e))?;
+        context.settlement_period = Some(steps);
+    }
+
+    if let Some(node) = root.optional("generated_electricity")? {
+        let generated = energy_prognoses(py, providers, &node, grid)?;
+        context
+            .plausibility_limits
+            .check_generation(py, &generated, grid)
+            .map_err(|e| node.wrap(e))?;
+        context.generated_electricity += generated;
+    }
+    if let Some(node) = root.optional("beyond_control_consumption")? {
+        let consumption = energy_prognoses(py, providers, &node, grid)?;
+        context
+            .plausibility_limits
+            .check_base_load(py, &consumption, grid)
+            .and_then(|()| {
+                context.plausibility_limits.check_double_counting(
+                    py,
+                    &consumption,
+                    &context.generated_electricity,
+                    grid,
+                    ("generated_electricity", "beyond_control_consumption"),
+                )
+            })
+            .map_err(|e| node.wrap(e))?;
+        context.beyond_control_consumption += consumption;
+    }
+    if let Some(node) = root.optional("feed_in_tariff")? {
+        let max_export = root
+            .optional("max_export")?
+            .map(|max_export| max_export.power())
+            .transpose()?;
+        context
+            .set_feed_in_tariff(
+                py,
+                &*provider(providers, &node, &node.string()?)?,
+                max_export,
+            )
+            .map_err(|e| node.wrap(e))?;
+    } else if let Some(node) = root.optional("max_export")? {
+        return Err(node.error("only allowed together with feed_in_tariff"));
+    }
+    if let Some(node) = root.optional("network_fee")? {
+        context
+            .set_network_fee(py, &*provider(providers, &node, &node.string()?)?)
+            .map_err(|e| node.wrap(e))?;
+    }
+
+    if let Some(batteries) = root.optional("batteries")? {
+        for node in batteries.items()? {
+            let battery = battery_from_spec(&node)?;
+            context.add_battery(&battery).map_err(|e| node.wrap(e))?;
+        }
+    }
+    if let Some(actions) = root.optional("actions")? {
+        for node in actions.items()? {
+            add_action_from_spec(py, &mut context, &node)?;
+        }
+    }
+    Ok(context)
+}
+
+/// Describe a context as a spec. Prognoses are referenced by the names of their keys and are
+/// left out if they are zero everywhere.
+pub(crate) fn context_to_spec<'py>(
+    py: Python<'py>,
+    context: &OptimizerContext,
+) -> PyResult<Bound<'py, PyDict>> {
+    let grid = context.grid();
+    let spec = PyDict::new(py);
+    spec.set_item(
+        "start_time",
+        context
+            .start_time
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+    )?;
+    if context.timestep != TimestepConfig::default() {
+        spec.set_item("resolution", format_iso_duration(grid.step()))?;
+    }
+    spec.set_item("electricity_price", "electricity_price")?;
+    for (key, prognoses) in [
+        ("generated_electricity", &context.generated_electricity),
+        (
+            "beyond_control_consumption",
+            &context.beyond_control_consumption,
+        ),
+    ] {
+        if prognoses.get_data().iter().any(|value| *value != 0) {
+            spec.set_item(key, key)?;
+        }
+    }
+    if context.feed_in_tariff.is_some() {
+        spec.set_item("feed_in_tariff", "feed_in_tariff")?;
+        if let Some(max_export) = context.max_export {
+            spec.set_item("max_export", format_power(max_export, grid))?;
+        }
+    }
+    if context.network_fee.is_some() {
+        spec.set_item("network_fee", "network_fee")?;
+    }
+    if let Some(grid_limit) = context.grid_limit {
+        spec.set_item("grid_limit", format_power(grid_limit, grid))?;
+    }
+    if let Some(peak_price) = context.peak_price {
+        spec.set_item(
+            "peak_price",
+            peak_price_from_internal(peak_price, context.timestep),
+        )?;
+    }
+    if let Some(steps) = context.settlement_period {
+        spec.set_item(
+            "settlement_period",
+            format_iso_duration(grid.step() * steps as i32),
+        )?;
+    }
+
+    let batteries = context
+        .batteries
+        .iter()
+        .map(|battery| -> PyResult<Bound<'py, PyDict>> {
+            let dict = PyDict::new(py);
+            dict.set_item("id", battery.get_id())?;
+            dict.set_item("capacity", format_energy(battery.get_capacity()))?;
+            dict.set_item(
+                "max_charge_rate",
+                format_power(battery.get_max_charge(), grid),
+            )?;
+            dict.set_item(
+                "max_discharge_rate",
+                format_power(battery.get_max_output(), grid),
+            )?;
+            dict.set_item("initial_charge", format_energy(battery.get_initial_level()))?;
+            dict.set_item("efficiency", battery.get_efficiency())?;
+            dict.set_item("grid_charging_allowed", battery.is_grid_charging_allowed())?;
+            dict.set_item("min_charge", format_energy(battery.get_min_charge()))?;
+            if let Some(target) = battery.get_final_charge_target() {
+                dict.set_item("final_charge_target", format_energy(target))?;
+            }
+            dict.set_item(
+                "self_discharge_per_hour",
+                battery.get_self_discharge_per_hour(),
+            )?;
+            if battery.get_cycle_cost() > 0 {
+                let cost = EuroPerWh::from_micro_euro_per_wh(battery.get_cycle_cost() as f64);
+                dict.set_item("cycle_cost", cost.value)?;
+            }
+            Ok(dict)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    spec.set_item("batteries", batteries)?;
+
+    let mut actions = Vec::new();
+    for action in &context.constant_actions {
+        let dict = PyDict::new(py);
+        match action.get_flat_consumption() {
+            Some(consumption) => {
+                dict.set_item("type", "constant")?;
+                dict.set_item("id", action.get_id())?;
+                dict.set_item(
+                    "duration",
+                    format_iso_duration(grid.step() * action.duration.to_timestep() as i32),
+                )?;
+                dict.set_item("consumption", format_power(consumption, grid))?;
+            }
+            None => {
+                dict.set_item("type", "profiled")?;
+                dict.set_item("id", action.get_id())?;
+                let profile: Vec<String> = action
+                    .get_profile()
+                    .iter()
+                    .map(|c| format_power(*c, grid))
+                    .collect();
+                dict.set_item("profile", profile)?;
+            }
+        }
+        dict.set_item(
+            "start_from",
+            format_datetime(action.get_start_from(), grid)?,
+        )?;
+        dict.set_item(
+            "end_before",
+            format_datetime(action.get_end_before(), grid)?,
+        )?;
+        actions.push(dict);
+    }
+    for action in &context.variable_actions {
+        let dict = PyDict::new(py);
+        dict.set_item("type", "variable")?;
+        dict.set_item("id", action.get_id())?;
+        dict.set_item("start", format_datetime(action.get_start(), grid)?)?;
+        dict.set_item("end", format_datetime(action.get_end(), grid)?)?;
+        dict.set_item(
+            "total_consumption",
+            format_energy(action.get_total_consumption()),
+        )?;
+        match action.get_max_consumption_profile() {
+            Some(profile) => dict.set_item(
+                "max_consumption",
+                profile
+                    .iter()
+                    .map(|cap| format_power(*cap, grid))
+                    .collect::<Vec<_>>(),
+            )?,
+            None => dict.set_item(
+                "max_consumption",
+                format_power(action.get_max_consumption(), grid),
+            )?,
+        }
+        if action.get_min_consumption() > 0 {
+            dict.set_item(
+                "min_consumption",
+                format_power(action.get_min_consumption(), grid),
+            )?;
+        }
+        if action.is_contiguous() {
+            dict.set_item("contiguous", true)?;
+        }
+        actions.push(dict);
+    }
+    spec.set_item("actions", actions)?;
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike};
+    use electricity_price_optimizer::simulated_annealing::{
+        AnnealingConfig, Cooling, Solver as RustSolver,
+    };
+    use pyo3::{
+        Py,
+        types::{PyCFunction, PyTuple},
+    };
+
+    use super::*;
+    use crate::{AnyConstantAction, Solver, plausibility::OptimizerWarning, problem::ProblemDump};
+
+    fn start_time() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 1, 6, 0, 0).unwrap()
+    }
+
+    /// Provider returning `value(minute of day)` for every interval.
+    fn provider<'py, T: for<'a> pyo3::IntoPyObject<'a> + 'static>(
+        py: Python<'py>,
+        value: impl Fn(u32) -> T + Send + 'static,
+    ) -> Py<PrognosesProvider> {
+        let get_data = PyCFunction::new_closure(
+            py,
+            None,
+            None,
+            move |args: &Bound<'_, PyTuple>, _| -> PyResult<T> {
+                let curr: DateTime<Utc> = args.get_item(0)?.extract()?;
+                Ok(value(curr.hour() * 60 + curr.minute()))
+            },
+        )
+        .unwrap();
+        Py::new(py, PrognosesProvider::new(get_data.into_any().unbind())).unwrap()
+    }
+
+    fn providers(py: Python<'_>) -> Bound<'_, PyDict> {
+        let providers = PyDict::new(py);
+        let price = provider(py, |minute| {
+            EuroPerWh::from_micro_euro_per_wh((minute % 90) as f64 + 5.0)
+        });
+        let pv = provider(py, |minute| WattHour {
+            value: if (600..900).contains(&minute) {
+                30.0
+            } else {
+                0.0
+            },
+        });
+        let tariff = provider(py, |_| EuroPerWh::from_micro_euro_per_wh(4.0));
+        providers.set_item("price", price).unwrap();
+        providers.set_item("pv", pv).unwrap();
+        providers.set_item("tariff", tariff).unwrap();
+        providers
+    }
+
+    fn example_spec(py: Python<'_>) -> Bound<'_, PyDict> {
+        let globals = PyDict::new(py);
+        py.run(
+            cr#"spec = {
+    "start_time": "2025-03-01T06:00:00Z",
+    "electricity_price": "price",
+    "generated_electricity": ["pv", "pv"],
+    "feed_in_tariff": "tariff",
+    "max_export": "2.5 kW",
+    "network_fee": "tariff",
+    "grid_limit": "17 kW",
+    "peak_price": 0.01,
+    "settlement_period": "PT15M",
+    "batteries": [{"id": 1, "capacity": "11 kWh", "max_charge_rate": "3.7 kW",
+                   "max_discharge_rate": "5 kW", "initial_charge": "1.001 kWh",
+                   "efficiency": 0.9, "min_charge": "0.5 kWh", "final_charge_target": "2 kWh",
+                   "self_discharge_per_hour": 0.02, "cycle_cost": 0.00002}],
+    "actions": [
+        {"type": "constant", "id": 2, "start_from": "2025-03-01T07:00:00Z",
+         "end_before": "2025-03-01T11:00:00Z", "duration": "PT1H30M", "consumption": "2.5 kW"},
+        {"type": "profiled", "id": 3, "end_before": "2025-03-01T09:00:00+01:00",
+         "profile": ["2 kW", "2 kW", "200 W"]},
+        {"type": "variable", "id": 4, "end": "2025-03-01T12:00:00Z",
+         "total_consumption": "7.4 kWh", "max_consumption": "11 kW"},
+    ],
+}
+"#,
+            Some(&globals),
+            None,
+        )
+        .unwrap();
+        globals
+            .get_item("spec")
+            .unwrap()
+            .unwrap()
+            .cast_into()
+            .unwrap()
+    }
+
+    fn cost(py: Python<'_>, context: &OptimizerContext) -> f64 {
+        let config = AnnealingConfig {
+            cooling: Cooling::Geometric { factor: 0.7 },
+            ..Default::default()
+        };
+        let mut solver = Solver {
+            inner: RustSolver::new(context.to_rust().unwrap(), config, Some(3)),
+            grid: context.grid(),
+            fingerprint: None,
+            progress_every: 1,
+        };
+        solver.solve(py, None, Some(1)).unwrap().0.to_nano_euro()
+    }
+
+    #[test]
+    fn test_spec_round_trip_matches_imperative_context() {
+        Python::initialize();
+        Python::attach(|py| {
+            let providers = providers(py);
+            let get = |name: &str| -> PyRef<'_, PrognosesProvider> {
+                providers
+                    .get_item(name)
+                    .unwrap()
+                    .unwrap()
+                    .extract()
+                    .unwrap()
+            };
+            let at = |hour: u32| Utc.with_ymd_and_hms(2025, 3, 1, hour, 0, 0).unwrap();
+
+            let mut expected = OptimizerContext::new(
+                py,
+                start_time(),
+                &get("price"),
+                Some(Watt { value: 17_000.0 }),
+                Some(0.01),
+                Some(TimeDelta::minutes(15)),
+                None,
+                None,
+            )
+            .unwrap();
+            expected
+                .add_generated_electricity_prognoses(py, &get("pv"))
+                .unwrap();
+            expected
+                .add_generated_electricity_prognoses(py, &get("pv"))
+                .unwrap();
+            expected
+                .set_feed_in_tariff(py, &get("tariff"), Some(Watt { value: 2500.0 }))
+                .unwrap();
+            expected.set_network_fee(py, &get("tariff")).unwrap();
+            let battery = Battery::new(
+                WattHour { value: 11_000.0 },
+                Watt { value: 3_700.0 },
+                Watt { value: 5_000.0 },
+                WattHour { value: 1_001.0 },
+                1,
+                0.9,
+                true,
+                WattHour { value: 500.0 },
+                Some(WattHour { value: 2_000.0 }),
+                0.02,
+                EuroPerWh { value: 0.00002 },
+            )
+            .unwrap();
+            expected.add_battery(&battery).unwrap();
+            let constant = Py::new(
+                py,
+                ConstantAction::new(
+                    at(7),
+                    at(11),
+                    TimeDelta::minutes(90),
+                    Watt { value: 2500.0 },
+                    2,
+                    None,
+                    false,
+                ),
+            )
+            .unwrap();
+            expected
+                .add_constant_action(py, AnyConstantAction::Flat(constant.borrow(py)))
+                .unwrap();
+            let profiled = Py::new(
+                py,
+                ProfiledConstantAction::new(
+                    start_time(),
+                    at(8),
+                    vec![
+                        Watt { value: 2000.0 },
+                        Watt { value: 2000.0 },
+                        Watt { value: 200.0 },
+                    ],
+                    3,
+                    false,
+                ),
+            )
+            .unwrap();
+            expected
+                .add_constant_action(py, AnyConstantAction::Profiled(profiled.borrow(py)))
+                .unwrap();
+            let variable = VariableAction::with_deadline(
+                at(12),
+                WattHour { value: 7_400.0 },
+                MaxConsumption::Constant(Watt { value: 11_000.0 }),
+                4,
+                None,
+                None,
+                0.1,
+                false,
+                false,
+            );
+            expected.add_variable_action(py, &variable).unwrap();
+
+            let context = OptimizerContext::from_spec(py, &example_spec(py), &providers).unwrap();
+            let dump = ProblemDump::from_context(&context);
+            assert_eq!(dump, ProblemDump::from_context(&expected));
+            assert_eq!(cost(py, &context), cost(py, &expected));
+
+            // to_spec names the prognoses after the keys.
+            let spec = context.to_spec(py).unwrap();
+            let canonical = PyDict::new(py);
+            canonical
+                .set_item("electricity_price", get("price"))
+                .unwrap();
+            canonical
+                .set_item("generated_electricity", context_provider(py, &context))
+                .unwrap();
+            canonical.set_item("feed_in_tariff", get("tariff")).unwrap();
+            canonical.set_item("network_fee", get("tariff")).unwrap();
+            let reloaded = OptimizerContext::from_spec(py, &spec, &canonical).unwrap();
+            assert_eq!(ProblemDump::from_context(&reloaded), dump);
+            assert!(reloaded.to_spec(py).unwrap().eq(&spec).unwrap());
+            assert_eq!(
+                spec.get_item("max_export")
+                    .unwrap()
+                    .unwrap()
+                    .extract::<String>()
+                    .unwrap(),
+                "2500 W"
+            );
+        });
+    }
+
+    /// Provider replaying the generated electricity of a context.
+    fn context_provider(py: Python<'_>, context: &OptimizerContext) -> Py<PrognosesProvider> {
+        let generated = context.generated_electricity.get_data().to_vec();
+        let start = start_time().hour() * 60 + start_time().minute();
+        provider(py, move |minute| {
+            let step = (minute + 24 * 60 - start) % (24 * 60);
+            WattHour::from_milli_wh(generated[step as usize] as f64)
+        })
+    }
+
+    fn action<'py>(spec: &Bound<'py, PyDict>, index: usize) -> Bound<'py, PyDict> {
+        let actions = spec.get_item("actions").unwrap().unwrap();
+        actions.get_item(index).unwrap().cast_into().unwrap()
+    }
+
+    #[test]
+    fn test_spec_errors_name_the_path() {
+        Python::initialize();
+        Python::attach(|py| {
+            let providers = providers(py);
+            let error = |edit: &dyn Fn(&Bound<'_, PyDict>)| -> String {
+                let spec = example_spec(py);
+                edit(&spec);
+                let Err(err) = OptimizerContext::from_spec(py, &spec, &providers) else {
+                    panic!("spec should have been rejected");
+                };
+                err.value(py).to_string()
+            };
+
+            let message = error(&|spec| action(spec, 2).set_item("type", "constant").unwrap());
+            assert!(
+                message.starts_with("actions[2]: unknown key"),
+                "{}",
+                message
+            );
+            let message = error(&|spec| {
+                let constant = action(spec, 0).copy().unwrap();
+                constant.del_item("duration").unwrap();
+                spec.get_item("actions")
+                    .unwrap()
+                    .unwrap()
+                    .set_item(2, constant)
+                    .unwrap();
+            });
+            assert_eq!(message, "actions[2].duration: missing");
+            let message = error(&|spec| spec.set_item("settlement_period", "PT0S").unwrap());
+            assert!(
+                message.starts_with("settlement_period: settlement_period must be a multiple"),
+                "{}",
+                message
+            );
+            let message = error(&|spec| action(spec, 0).set_item("duration", "2h").unwrap());
+            assert!(message.starts_with("actions[0].duration: expected an ISO 8601 duration"));
+            let message = error(&|spec| {
+                action(spec, 1)
+                    .get_item("profile")
+                    .unwrap()
+                    .unwrap()
+                    .set_item(1, "2 kWh")
+                    .unwrap()
+            });
+            assert!(message.starts_with(
+                "actions[1].profile[1]: expected a number with one of the units W, kW, MW"
+            ));
+            let message = error(&|spec| {
+                spec.set_item("generated_electricity", ["pv", "wind"])
+                    .unwrap()
+            });
+            assert_eq!(message, "generated_electricity: no provider named 'wind'");
+            let message = error(&|spec| spec.set_item("grid_limit", "-1 kW").unwrap());
+            assert_eq!(message, "grid_limit: must not be negative");
+            let message = error(&|spec| {
+                let battery = spec
+                    .get_item("batteries")
+                    .unwrap()
+                    .unwrap()
+                    .get_item(0)
+                    .unwrap();
+                battery.set_item("efficiency", 1.5).unwrap();
+            });
+            assert_eq!(
+                message,
+                "batteries[0]: Battery efficiency must be in (0, 1], got 1.5"
+            );
+        });
+    }
+
+    #[test]
+    fn test_generation_in_negative_consumption_warns() {
+        Python::initialize();
+        Python::attach(|py| {
+            let providers = providers(py);
+            // A base load metered behind the PV, which is also given on its own
+            let net_load = provider(py, |minute| WattHour {
+                value: if (660..720).contains(&minute) {
+                    -10.0
+                } else {
+                    5.0
+                },
+            });
+            providers.set_item("net_load", net_load).unwrap();
+            let spec = example_spec(py);
+            spec.set_item("beyond_control_consumption", "net_load")
+                .unwrap();
+
+            let warnings = py.import("warnings").unwrap();
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("record", true).unwrap();
+            let catcher = warnings
+                .call_method("catch_warnings", (), Some(&kwargs))
+                .unwrap();
+            let log = catcher.call_method0("__enter__").unwrap();
+            warnings.call_method1("simplefilter", ("always",)).unwrap();
+            OptimizerContext::from_spec(py, &spec, &providers).unwrap();
+            catcher
+                .call_method1("__exit__", (py.None(), py.None(), py.None()))
+                .unwrap();
+
+            let warned: Vec<String> = log
+                .try_iter()
+                .unwrap()
+                .map(|warning| {
+                    let warning = warning.unwrap();
+                    let category = warning.getattr("category").unwrap();
+                    assert!(category.is(py.get_type::<OptimizerWarning>()));
+                    warning
+                        .getattr("message")
+                        .unwrap()
+                        .str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect();
+            assert_eq!(
+                warned,
+                vec![
+                    "Beyond control consumption is negative while electricity is generated in \
+                     [2025-03-01 11:00:00 UTC, 2025-03-01 12:00:00 UTC). The generation is most \
+                     likely counted twice, once in generated_electricity and once as negative \
+                     consumption in beyond_control_consumption; leave it out of the consumption."
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_formatted_quantities_convert_back_exactly() {
+        let grid = TimeGrid::new(start_time(), TimestepConfig::default());
+        for milli_wh in 0..20_000 {
+            let text = format_energy(milli_wh);
+            let value = parse_quantity(&text, &ENERGY_UNITS).unwrap();
+            assert_eq!(
+                WattHour { value }.to_milli_wh() as i64,
+                milli_wh,
+                "{}",
+                text
+            );
+            let text = format_power(milli_wh, grid);
+            let value = parse_quantity(&text, &POWER_UNITS).unwrap();
+            let converted = Watt { value }.to_energy_per_step(grid.step()) as i64;
+            assert_eq!(converted, milli_wh, "{}", text);
+        }
+        assert_eq!(format_power(41_666, grid), "2500 W");
+        assert_eq!(format_energy(1_001_000), "1001 Wh");
+    }
+
+    #[test]
+    fn test_parse_iso_duration() {
+        assert_eq!(parse_iso_duration("PT2H"), Some(TimeDelta::hours(2)));
+        assert_eq!(
+            parse_iso_duration("P1DT1H30M15S"),
+            Some(TimeDelta::seconds(24 * 3600 + 5415))
+        );
+        assert_eq!(parse_iso_duration("PT0S"), Some(TimeDelta::zero()));
+        for invalid in ["", "P", "PT", "2H", "PT2", "PTH", "PT30M2H", "P1H"] {
+            assert_eq!(parse_iso_duration(invalid), None, "{}", invalid);
+        }
+    }
+}
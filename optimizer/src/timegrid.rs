@@ -0,0 +1,289 @@
+//! Conversion between datetimes and optimizer timesteps.
+//!
+//! Timestep boundaries are multiples of the timestep length since the Unix epoch, so with
+//! timesteps of a whole number of minutes dividing the day, see TimestepConfig, they fall on
+//! whole minutes that are a multiple of it. A context starts at an anchor that may lie between two boundaries; its
+//! first timestep then only covers the rest of the timestep the anchor falls into.
+//!
+//! All arithmetic is done in milliseconds, which cover every datetime chrono can represent.
+//! The nanoseconds below a millisecond are only checked for alignment.
+use chrono::{DateTime, TimeDelta, TimeZone, Timelike, Utc};
+use electricity_price_optimizer::time::{MINUTES_PER_TIMESTEP, Time, TimestepConfig};
+use pyo3::{PyErr, PyResult};
+
+use crate::{OptimizerError, TimestepAlignmentError};
+
+/// Length of one optimizer timestep of `timestep`. All conversions between per-timestep
+/// internals and user units go through this, so the grid is defined in one place.
+pub(crate) fn timestep_duration(timestep: TimestepConfig) -> TimeDelta {
+    TimeDelta::minutes(timestep.get_minutes_per_timestep() as i64)
+}
+
+/// The timesteps of a context starting at `anchor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TimeGrid {
+    anchor: DateTime<Utc>,
+    step: TimeDelta,
+}
+
+impl TimeGrid {
+    /// Grid of optimizer timesteps of `timestep` starting at `anchor`.
+    pub(crate) fn new(anchor: DateTime<Utc>, timestep: TimestepConfig) -> Self {
+        Self::with_step(anchor, timestep_duration(timestep))
+    }
+
+    /// Grid of timesteps of length `step` starting at `anchor`.
+    ///
+    /// # Panics
+    /// Panics if `step` isn't a positive whole number of milliseconds.
+    pub(crate) fn with_step(anchor: DateTime<Utc>, step: TimeDelta) -> Self {
+        assert!(
+            step > TimeDelta::zero() && step.subsec_nanos() % 1_000_000 == 0,
+            "Timestep must be a positive whole number of milliseconds, got {step}"
+        );
+        Self { anchor, step }
+    }
+
+    /// Returns the start of the first timestep.
+    pub(crate) fn anchor(self) -> DateTime<Utc> {
+        self.anchor
+    }
+
+    /// Returns the length of a full timestep.
+    pub(crate) fn step(self) -> TimeDelta {
+        self.step
+    }
+
+    /// Returns the optimizer timesteps of the grid.
+    ///
+    /// # Panics
+    /// Panics if the grid wasn't created from optimizer timesteps, see `new`.
+    pub(crate) fn timestep(self) -> TimestepConfig {
+        u32::try_from(self.step.num_minutes())
+            .ok()
+            .filter(|_| self.step.num_seconds() % 60 == 0 && self.step.subsec_nanos() == 0)
+            .and_then(TimestepConfig::new)
+            .expect("The grid has optimizer timesteps")
+    }
+
+    /// Returns the end of the last timestep of the day, relative to the anchor.
+    pub(crate) fn day_end(self) -> Time {
+        self.timestep().get_day_end()
+    }
+
+    /// Whether `dt` starts a timestep, i.e. is the anchor or a timestep boundary.
+    pub(crate) fn is_aligned(self, dt: DateTime<Utc>) -> bool {
+        dt == self.anchor || self.is_boundary(dt)
+    }
+
+    /// Returns the last timestep boundary at or before `dt`.
+    /// Errors if it is outside the range of representable datetimes.
+    pub(crate) fn floor(self, dt: DateTime<Utc>) -> PyResult<DateTime<Utc>> {
+        let ms = dt.timestamp_millis();
+        // rem_euclid also rounds down before 1970
+        let floored = ms - ms.rem_euclid(self.step_ms());
+        from_millis(floored).ok_or_else(|| {
+            OptimizerError::new_err(format!(
+                "Timestep boundary before {} is outside the supported datetime range",
+                dt
+            ))
+        })
+    }
+
+    /// Returns the first timestep boundary at or after `dt`.
+    /// Errors if it is outside the range of representable datetimes.
+    pub(crate) fn ceil(self, dt: DateTime<Utc>) -> PyResult<DateTime<Utc>> {
+        if self.is_boundary(dt) {
+            return Ok(dt);
+        }
+        let out_of_range = || {
+            OptimizerError::new_err(format!(
+                "Timestep boundary after {} is outside the supported datetime range",
+                dt
+            ))
+        };
+        let floored = self.floor(dt)?.timestamp_millis();
+        floored
+            .checked_add(self.step_ms())
+            .and_then(from_millis)
+            .ok_or_else(out_of_range)
+    }
+
+    /// Convert the start of a timestep to the optimizer Time counting timesteps from the
+    /// anchor. Errors if `dt` is before the anchor, doesn't start a timestep or is too far
+    /// after the anchor.
+    pub(crate) fn to_time(self, dt: DateTime<Utc>) -> PyResult<Time> {
+        if dt < self.anchor {
+            return Err(OptimizerError::new_err(format!(
+                "DateTime {} is before start time {}",
+                dt, self.anchor
+            )));
+        }
+        if !self.is_aligned(dt) {
+            return Err(TimestepAlignmentError::new_err(format!(
+                "DateTime is not on a timestep boundary: minute={}, second={}, nanos={}",
+                dt.minute(),
+                dt.second(),
+                dt.timestamp_subsec_nanos()
+            )));
+        }
+        // Timesteps are counted from the boundary the first timestep belongs to
+        let first = self.floor(self.anchor)?.timestamp_millis();
+        let steps = (dt.timestamp_millis() - first) / self.step_ms();
+        let steps = u32::try_from(steps).map_err(|_| too_far(dt, self.anchor))?;
+        if steps.checked_mul(MINUTES_PER_TIMESTEP).is_none() {
+            return Err(too_far(dt, self.anchor));
+        }
+        Ok(Time::from_timestep(steps))
+    }
+
+    /// Convert an optimizer Time to the datetime its timestep starts at. This is never before
+    /// the anchor. Errors if the result is outside the range of representable datetimes.
+    pub(crate) fn to_datetime(self, time: Time) -> PyResult<DateTime<Utc>> {
+        let out_of_range = || {
+            OptimizerError::new_err(format!(
+                "Timestep {} after {} is outside the supported datetime range",
+                time.to_timestep(),
+                self.anchor
+            ))
+        };
+        let first = self.floor(self.anchor)?.timestamp_millis();
+        let start = (time.to_timestep() as i64)
+            .checked_mul(self.step_ms())
+            .and_then(|offset| first.checked_add(offset))
+            .and_then(from_millis)
+            .ok_or_else(out_of_range)?;
+        Ok(start.max(self.anchor))
+    }
+
+    fn step_ms(self) -> i64 {
+        self.step.num_milliseconds()
+    }
+
+    fn is_boundary(self, dt: DateTime<Utc>) -> bool {
+        dt.timestamp_subsec_nanos().is_multiple_of(1_000_000)
+            && dt.timestamp_millis().rem_euclid(self.step_ms()) == 0
+    }
+}
+
+fn from_millis(ms: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(ms).single()
+}
+
+fn too_far(dt: DateTime<Utc>, anchor: DateTime<Utc>) -> PyErr {
+    OptimizerError::new_err(format!(
+        "DateTime {} is too far after start time {}",
+        dt, anchor
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use electricity_price_optimizer::time::STEPS_PER_DAY;
+    use proptest::prelude::*;
+
+    /// Milliseconds since the epoch from about 1000 to 9000, beyond nanosecond timestamps.
+    const RANGE_MS: std::ops::Range<i64> = -30_000_000_000_000..220_000_000_000_000;
+
+    fn grid(anchor_ms: i64, step_ms: i64) -> TimeGrid {
+        TimeGrid::with_step(
+            from_millis(anchor_ms).unwrap(),
+            TimeDelta::milliseconds(step_ms),
+        )
+    }
+
+    prop_compose! {
+        /// Steps from a millisecond to a day, weighted towards whole minutes.
+        fn step_ms()(step in prop_oneof![1..86_400_000i64, (1..1440i64).prop_map(|m| m * 60_000)]) -> i64 {
+            step
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn aligned_datetimes_round_trip(anchor in RANGE_MS, step in step_ms(), steps in 0..100_000u32) {
+            let grid = grid(anchor, step);
+            let dt = grid.to_datetime(Time::from_timestep(steps)).unwrap();
+            prop_assert!(grid.is_aligned(dt));
+            let time = grid.to_time(dt).unwrap();
+            prop_assert_eq!(time.to_timestep(), steps);
+            prop_assert_eq!(grid.to_datetime(time).unwrap(), dt);
+        }
+
+        #[test]
+        fn every_timestep_of_the_horizon_round_trips(anchor in RANGE_MS, misalignment_ms in prop_oneof![Just(0i64), 1..60_000 * MINUTES_PER_TIMESTEP as i64]) {
+            // On a whole minute, or up to a timestep after one
+            let anchor = anchor - anchor.rem_euclid(60_000) + misalignment_ms;
+            assert_horizon_round_trips(TimeGrid::new(from_millis(anchor).unwrap(), TimestepConfig::default()));
+        }
+
+        #[test]
+        fn conversions_are_monotonic(anchor in RANGE_MS, step in step_ms(), a in 0..100_000u32, b in 0..100_000u32) {
+            let grid = grid(anchor, step);
+            let (a, b) = (a.min(b), a.max(b));
+            let first = grid.to_datetime(Time::from_timestep(a)).unwrap();
+            let second = grid.to_datetime(Time::from_timestep(b)).unwrap();
+            prop_assert!(first <= second);
+            prop_assert!(grid.to_time(first).unwrap() <= grid.to_time(second).unwrap());
+        }
+
+        #[test]
+        fn floor_and_ceil_enclose(anchor in RANGE_MS, step in step_ms(), offset in 0..1_000_000_000_000i64, nanos in 0..1_000_000u32) {
+            let grid = grid(anchor, step);
+            let dt = from_millis(anchor + offset).unwrap() + TimeDelta::nanoseconds(nanos as i64);
+            let (floor, ceil) = (grid.floor(dt).unwrap(), grid.ceil(dt).unwrap());
+            prop_assert!(floor <= dt && dt <= ceil);
+            prop_assert!(ceil - floor <= grid.step());
+            prop_assert!(grid.is_aligned(floor) && grid.is_aligned(ceil));
+            prop_assert_eq!(floor == ceil, floor == dt);
+        }
+
+        #[test]
+        fn is_aligned_agrees_with_to_time(anchor in RANGE_MS, step in step_ms(), offset in 0..1_000_000_000i64, nanos in prop_oneof![Just(0u32), 0..1_000_000u32]) {
+            let grid = grid(anchor, step);
+            let dt = from_millis(anchor + offset).unwrap() + TimeDelta::nanoseconds(nanos as i64);
+            prop_assert_eq!(grid.is_aligned(dt), grid.to_time(dt).is_ok());
+        }
+    }
+
+    /// Every timestep of a day converts to a datetime and back to itself.
+    fn assert_horizon_round_trips(grid: TimeGrid) {
+        for step in 0..STEPS_PER_DAY {
+            let time = Time::from_timestep(step);
+            let dt = grid.to_datetime(time).unwrap();
+            assert_eq!(
+                grid.to_time(dt).unwrap(),
+                time,
+                "{dt} from {}",
+                grid.anchor()
+            );
+        }
+    }
+
+    #[test]
+    fn test_horizon_round_trips_across_dst_change_and_leap_day() {
+        let anchors = [
+            // Europe switches to summer time at 01:00 UTC
+            "2025-03-30T00:07:00Z",
+            "2025-03-29T22:00:00Z",
+            // and back to winter time at 01:00 UTC
+            "2025-10-26T00:59:30.5Z",
+            "2024-02-28T10:07:00Z",
+            "2024-02-29T00:00:00Z",
+            "2024-02-29T23:59:59.999Z",
+        ];
+        for anchor in anchors {
+            let anchor = DateTime::parse_from_rfc3339(anchor).unwrap().to_utc();
+            assert_horizon_round_trips(TimeGrid::new(anchor, TimestepConfig::default()));
+        }
+    }
+
+    #[test]
+    fn test_before_anchor_is_rejected() {
+        let grid = grid(0, 60_000);
+        let before = from_millis(-60_000).unwrap();
+        assert!(grid.is_aligned(before));
+        assert!(grid.to_time(before).is_err());
+    }
+}
@@ -10,27 +10,120 @@
 //! - Watt * TimeDelta -> WattHour
 //! - WattHour * EuroPerWh -> Euro
 //! - Add/Sub/Div for same-unit arithmetic; Div between compatible units where meaningful
+//! - Add/Sub and comparisons also take plain numbers, taken to be in the same unit
+//! - kW, kWh, ct and ct/kWh constructors and accessors, e.g. EuroPerWh.from_cent_per_kwh(30)
 //!
 //! Internal conversions used by the optimizer:
-//! - Watt to milli-Wh per timestep for discrete scheduling
+//! - Watt to/from milli-Wh per timestep for discrete scheduling, for an explicit timestep length
 //! - WattHour to/from milli-Wh
 //! - Euro to/from nano-euro
 //! - EuroPerWh to micro-euro per Wh
 //!
-//! Note: TimeDelta-based operations use nanoseconds for precision.
+//! Values passed to the optimizer are limited by MAX_ENERGY and MAX_PRICE, so the cost of a
+//! whole horizon can't overflow the optimizer's i64 arithmetic. Larger values raise
+//! UnitError naming the field.
+//!
+//! Note: TimeDelta-based operations are precise to the nanosecond and work for every TimeDelta.
+//! A TimeDelta that can't be represented raises UnitError, a ValueError.
 
 use std::ops::{Add, Div, Mul, Sub};
 
 use chrono::TimeDelta;
-use electricity_price_optimizer::time::MINUTES_PER_TIMESTEP;
+use electricity_price_optimizer::time::STEPS_PER_DAY;
 use pyo3::{
     Bound, FromPyObject, IntoPyObjectExt, PyAny, PyResult, Python,
     basic::CompareOp,
     exceptions::PyTypeError,
     pyclass, pymethods,
-    types::{PyModule, PyModuleMethods},
+    types::{PyAnyMethods, PyFloat, PyModule, PyModuleMethods, PyType},
 };
-const NANOSECONDS_PER_HOUR: f64 = 3_600_000_000_000.0;
+
+use crate::UnitError;
+
+const SECONDS_PER_HOUR: f64 = 3_600.0;
+
+/// Largest price passed to the optimizer, in micro-euro per Wh (100 €/kWh).
+pub const MAX_PRICE: i64 = 100_000;
+/// How often the cost of a whole horizon fits into an i64, so several costs can be summed.
+const HEADROOM: i64 = 16;
+/// Largest energy passed to the optimizer, in milli-Wh (about 4 MWh). Buying it in every
+/// timestep of the horizon at MAX_PRICE costs i64::MAX / HEADROOM nano-euro. Powers are
+/// limited to this energy per timestep.
+pub const MAX_ENERGY: i64 = i64::MAX / HEADROOM / MAX_PRICE / STEPS_PER_DAY as i64;
+/// Largest peak price passed to the optimizer, in nano-euro per milli-Wh per timestep. A peak
+/// of MAX_ENERGY per timestep then costs i64::MAX / HEADROOM nano-euro.
+pub const MAX_PEAK_PRICE: i64 = i64::MAX / HEADROOM / MAX_ENERGY;
+
+/// Convert a value in internal units to i64, truncating like `as i64`. Raises UnitError
+/// naming `field` if its magnitude is above `max` or it is NaN; `show` formats an internal
+/// value in user units for the message.
+pub(crate) fn checked_internal(
+    value: f64,
+    max: i64,
+    field: &str,
+    show: impl Fn(f64) -> String,
+) -> PyResult<i64> {
+    if value.abs() <= max as f64 {
+        return Ok(value as i64);
+    }
+    Err(UnitError::new_err(format!(
+        "{} of {} is out of range, the largest supported magnitude is {}",
+        field,
+        show(value),
+        show(max as f64)
+    )))
+}
+
+/// `value` times `factor`, rounded to 9 decimal places. Dividing by a power of ten isn't exact
+/// in floating point, the rounding makes values created from a decimal convert back to it,
+/// e.g. 7.4 ct/kWh.
+fn to_decimal_unit(value: f64, factor: f64) -> f64 {
+    (value * factor * 1e9).round() / 1e9
+}
+
+/// Length of a TimeDelta in hours.
+fn hours(delta: TimeDelta) -> f64 {
+    delta.as_seconds_f64() / SECONDS_PER_HOUR
+}
+
+/// Same-unit operand of +, - and comparisons: the unit itself or a plain number, taken to be in
+/// the same unit. Other units fail to extract, so Python raises TypeError.
+#[derive(FromPyObject)]
+enum SameUnitOrFloat<T> {
+    Unit(T),
+    Float(f64),
+}
+impl<T: UnitValue> SameUnitOrFloat<T> {
+    fn value(&self) -> f64 {
+        match self {
+            SameUnitOrFloat::Unit(unit) => unit.value(),
+            SameUnitOrFloat::Float(f) => *f,
+        }
+    }
+}
+
+/// Raw value of a unit, in the unit it is named after.
+trait UnitValue {
+    fn value(&self) -> f64;
+}
+
+/// Compare two raw values of the same unit.
+fn compare(value: f64, other: f64, op: CompareOp) -> bool {
+    match op {
+        CompareOp::Eq => value == other,
+        CompareOp::Ne => value != other,
+        CompareOp::Lt => value < other,
+        CompareOp::Le => value <= other,
+        CompareOp::Gt => value > other,
+        CompareOp::Ge => value >= other,
+    }
+}
+
+/// Hash a raw value like the equal Python float, so `hash(Watt(5)) == hash(5)`.
+fn hash(py: Python<'_>, value: f64) -> PyResult<isize> {
+    PyFloat::new(py, value).hash()
+}
+
 #[derive(FromPyObject)]
 enum UnitOrTimeOrFloat {
     Watt(Watt),
@@ -40,7 +133,7 @@ enum UnitOrTimeOrFloat {
     Float(f64),
 }
 
-#[pyclass]
+#[pyclass(module = "electricity_price_optimizer_py.units")]
 #[derive(Clone, Debug, Default)]
 /// Power in watts (W).
 /// Python: supports +, -, *, / with float; * TimeDelta -> WattHour; / Watt -> float.
@@ -51,23 +144,31 @@ impl Add for &Watt {
     type Output = Watt;
 
     fn add(self, other: &Watt) -> Watt {
-        self.__add__(other)
+        Watt {
+            value: self.value + other.value,
+        }
     }
 }
 impl Sub for &Watt {
     type Output = Watt;
 
     fn sub(self, other: &Watt) -> Watt {
-        self.__sub__(other)
+        Watt {
+            value: self.value - other.value,
+        }
+    }
+}
+impl UnitValue for Watt {
+    fn value(&self) -> f64 {
+        self.value
     }
 }
 impl Mul<TimeDelta> for &Watt {
     type Output = WattHour;
 
     fn mul(self, other: TimeDelta) -> WattHour {
-        let hours = other.num_nanoseconds().unwrap() as f64 / NANOSECONDS_PER_HOUR;
         WattHour {
-            value: self.value * hours,
+            value: self.value * hours(other),
         }
     }
 }
@@ -103,6 +204,21 @@ impl Watt {
     fn new(value: f64) -> Self {
         Watt { value }
     }
+    /// Python __reduce__: pickle as the value.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (f64,)) {
+        (slf.get_type(), (slf.borrow().value,))
+    }
+    #[staticmethod]
+    /// Construct a Watt value from kW.
+    fn from_kilowatt(value: f64) -> Self {
+        Watt {
+            value: value * 1_000.0,
+        }
+    }
+    /// Get the value in kW.
+    fn to_kilowatt(&self) -> f64 {
+        self.value / 1_000.0
+    }
     /// Python __mul__: supports TimeDelta (returns WattHour) and float (returns Watt).
     fn __mul__<'py>(
         &self,
@@ -133,18 +249,44 @@ impl Watt {
         self.__mul__(py, other)
     }
 
-    /// Python __add__: Watt + Watt.
-    fn __add__(&self, other: &Watt) -> Watt {
+    /// Python __add__: Watt + Watt or a number of W.
+    fn __add__(&self, other: SameUnitOrFloat<Watt>) -> Watt {
         Watt {
-            value: self.value + other.value,
+            value: self.value + other.value(),
         }
     }
-    /// Python __sub__: Watt - Watt.
-    fn __sub__(&self, other: &Watt) -> Watt {
+    /// Python __radd__: number of W + Watt, e.g. the 0 `sum` starts with.
+    fn __radd__(&self, other: f64) -> Watt {
         Watt {
-            value: self.value - other.value,
+            value: other + self.value,
+        }
+    }
+    /// Python __sub__: Watt - Watt or a number of W.
+    fn __sub__(&self, other: SameUnitOrFloat<Watt>) -> Watt {
+        Watt {
+            value: self.value - other.value(),
         }
     }
+    /// Python __rsub__: number of W - Watt.
+    fn __rsub__(&self, other: f64) -> Watt {
+        Watt {
+            value: other - self.value,
+        }
+    }
+    /// Python __neg__: -Watt.
+    fn __neg__(&self) -> Watt {
+        Watt { value: -self.value }
+    }
+    /// Python __abs__: abs(Watt).
+    fn __abs__(&self) -> Watt {
+        Watt {
+            value: self.value.abs(),
+        }
+    }
+    /// Python __hash__: equal to the hash of the value, as Watt compares equal to it.
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        hash(py, self.value)
+    }
     /// Python __truediv__: supports float (returns Watt) and Watt (returns float).
     fn __truediv__<'py>(
         &self,
@@ -165,44 +307,50 @@ impl Watt {
             )),
         }
     }
-    /// Python __repr__: formatted string.
+    /// Python __repr__: formatted string, in kW from 1000 W on.
     fn __repr__(&self) -> String {
         // format with 2 decimal places
-        format!("{:.2} W", self.value)
+        if self.value.abs() >= 1_000.0 {
+            format!("{:.2} kW", self.to_kilowatt())
+        } else {
+            format!("{:.2} W", self.value)
+        }
     }
 
     /// Get raw value in W.
     fn get_value(&self) -> f64 {
         self.value
     }
-    /// Python __richcmp__: supports all rich comparison operations.
-    fn __richcmp__(&self, other: &Watt, op: CompareOp) -> bool {
-        match op {
-            CompareOp::Eq => self.value == other.value,
-            CompareOp::Ne => self.value != other.value,
-            CompareOp::Lt => self.value < other.value,
-            CompareOp::Le => self.value <= other.value,
-            CompareOp::Gt => self.value > other.value,
-            CompareOp::Ge => self.value >= other.value,
-        }
+    /// Python __richcmp__: supports all rich comparison operations with Watt or a number of
+    /// W.
+    fn __richcmp__(&self, other: SameUnitOrFloat<Watt>, op: CompareOp) -> bool {
+        compare(self.value, other.value(), op)
     }
 }
 impl Watt {
-    /// Convert to milli-Wh per timestep using MINUTES_PER_TIMESTEP.
-    pub fn to_milli_watt_hour_per_timestep(&self) -> f64 {
-        let timestep_duration = TimeDelta::minutes(MINUTES_PER_TIMESTEP as i64);
-        let wh = self * timestep_duration;
+    /// Convert to the milli-Wh consumed during one timestep of length `step`.
+    pub fn to_energy_per_step(&self, step: TimeDelta) -> f64 {
+        let wh = self * step;
         wh.to_milli_wh()
     }
-    /// Construct a Watt from milli-Wh per timestep.
-    pub fn from_milli_watt_hour_per_timestep(value: f64) -> Self {
-        let timestep_duration = TimeDelta::minutes(MINUTES_PER_TIMESTEP as i64);
-        let wh = WattHour::from_milli_wh(value);
-        &wh / timestep_duration
+    /// Construct a Watt from the milli-Wh consumed during one timestep of length `step`.
+    pub fn from_energy_per_step(milli_wh: f64, step: TimeDelta) -> Self {
+        let wh = WattHour::from_milli_wh(milli_wh);
+        &wh / step
+    }
+    /// Convert to the milli-Wh of one timestep of length `step` for the optimizer.
+    /// Raises UnitError naming `field` if that is more than MAX_ENERGY.
+    pub fn to_internal_per_step(&self, step: TimeDelta, field: &str) -> PyResult<i64> {
+        checked_internal(
+            self.to_energy_per_step(step),
+            MAX_ENERGY,
+            field,
+            |milli_wh| format!("{} W", Watt::from_energy_per_step(milli_wh, step).value),
+        )
     }
 }
 
-#[pyclass]
+#[pyclass(module = "electricity_price_optimizer_py.units")]
 #[derive(Clone, Debug, Default)]
 /// Energy in watt-hours (Wh).
 /// Python: supports +, -, *, / with float; / TimeDelta -> Watt; / Watt -> TimeDelta; * EuroPerWh -> Euro.
@@ -222,20 +370,24 @@ impl Div<TimeDelta> for &WattHour {
     type Output = Watt;
 
     fn div(self, other: TimeDelta) -> Watt {
-        // calc in nanos for precision
-        let hours = other.num_nanoseconds().unwrap() as f64 / NANOSECONDS_PER_HOUR;
         Watt {
-            value: self.value / hours,
+            value: self.value / hours(other),
         }
     }
 }
 impl Div<Watt> for &WattHour {
-    type Output = TimeDelta;
+    /// None if the duration isn't finite or too long for a TimeDelta.
+    type Output = Option<TimeDelta>;
 
-    fn div(self, other: Watt) -> TimeDelta {
-        let hours = self.value / other.value;
-        let nanos = (hours * NANOSECONDS_PER_HOUR) as i64;
-        TimeDelta::nanoseconds(nanos)
+    fn div(self, other: Watt) -> Option<TimeDelta> {
+        let seconds = self.value / other.value * SECONDS_PER_HOUR;
+        if !seconds.is_finite() {
+            return None;
+        }
+        let whole = seconds.floor();
+        let nanos = ((seconds - whole) * 1e9).round().min(999_999_999.0) as u32;
+        // Saturates for huge values, which TimeDelta::new rejects
+        TimeDelta::new(whole as i64, nanos)
     }
 }
 impl Div<&WattHour> for &WattHour {
@@ -258,14 +410,23 @@ impl Add for &WattHour {
     type Output = WattHour;
 
     fn add(self, other: &WattHour) -> WattHour {
-        self.__add__(other)
+        WattHour {
+            value: self.value + other.value,
+        }
     }
 }
 impl Sub for &WattHour {
     type Output = WattHour;
 
     fn sub(self, other: &WattHour) -> WattHour {
-        self.__sub__(other)
+        WattHour {
+            value: self.value - other.value,
+        }
+    }
+}
+impl UnitValue for WattHour {
+    fn value(&self) -> f64 {
+        self.value
     }
 }
 impl Mul<&EuroPerWh> for &WattHour {
@@ -284,6 +445,21 @@ impl WattHour {
     fn new(value: f64) -> Self {
         WattHour { value }
     }
+    /// Python __reduce__: pickle as the value.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (f64,)) {
+        (slf.get_type(), (slf.borrow().value,))
+    }
+    #[staticmethod]
+    /// Construct a WattHour value from kWh.
+    fn from_kilowatt_hour(value: f64) -> Self {
+        WattHour {
+            value: value * 1_000.0,
+        }
+    }
+    /// Get the value in kWh.
+    fn to_kilowatt_hour(&self) -> f64 {
+        self.value / 1_000.0
+    }
 
     /// Python __mul__: supports EuroPerWh (returns Euro) and float (returns WattHour).
     fn __mul__<'py>(
@@ -331,7 +507,13 @@ impl WattHour {
                 Ok(result.into_bound_py_any(py)?)
             }
             UnitOrTimeOrFloat::Watt(w) => {
-                let result = self / w;
+                let result = (self / w.clone()).ok_or_else(|| {
+                    UnitError::new_err(format!(
+                        "{} at {} doesn't last a representable duration",
+                        self.__repr__(),
+                        w.__repr__()
+                    ))
+                })?;
                 Ok(result.into_bound_py_any(py)?)
             }
             UnitOrTimeOrFloat::WattHour(wh) => {
@@ -344,38 +526,62 @@ impl WattHour {
         }
     }
 
-    /// Python __add__: WattHour + WattHour.
-    fn __add__(&self, other: &WattHour) -> WattHour {
+    /// Python __add__: WattHour + WattHour or a number of Wh.
+    fn __add__(&self, other: SameUnitOrFloat<WattHour>) -> WattHour {
         WattHour {
-            value: self.value + other.value,
+            value: self.value + other.value(),
         }
     }
-    /// Python __sub__: WattHour - WattHour.
-    fn __sub__(&self, other: &WattHour) -> WattHour {
+    /// Python __radd__: number of Wh + WattHour, e.g. the 0 `sum` starts with.
+    fn __radd__(&self, other: f64) -> WattHour {
         WattHour {
-            value: self.value - other.value,
+            value: other + self.value,
         }
     }
-    /// Python __repr__: formatted string.
+    /// Python __sub__: WattHour - WattHour or a number of Wh.
+    fn __sub__(&self, other: SameUnitOrFloat<WattHour>) -> WattHour {
+        WattHour {
+            value: self.value - other.value(),
+        }
+    }
+    /// Python __rsub__: number of Wh - WattHour.
+    fn __rsub__(&self, other: f64) -> WattHour {
+        WattHour {
+            value: other - self.value,
+        }
+    }
+    /// Python __neg__: -WattHour.
+    fn __neg__(&self) -> WattHour {
+        WattHour { value: -self.value }
+    }
+    /// Python __abs__: abs(WattHour).
+    fn __abs__(&self) -> WattHour {
+        WattHour {
+            value: self.value.abs(),
+        }
+    }
+    /// Python __hash__: equal to the hash of the value, as WattHour compares equal to it.
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        hash(py, self.value)
+    }
+    /// Python __repr__: formatted string, in kWh from 1000 Wh on.
     fn __repr__(&self) -> String {
         // format with 2 decimal places
-        format!("{:.2} Wh", self.value)
+        if self.value.abs() >= 1_000.0 {
+            format!("{:.2} kWh", self.to_kilowatt_hour())
+        } else {
+            format!("{:.2} Wh", self.value)
+        }
     }
 
     /// Get raw value in Wh.
     fn get_value(&self) -> f64 {
         self.value
     }
-    /// Python __richcmp__: supports all rich comparison operations.
-    fn __richcmp__(&self, other: &WattHour, op: CompareOp) -> bool {
-        match op {
-            CompareOp::Eq => self.value == other.value,
-            CompareOp::Ne => self.value != other.value,
-            CompareOp::Lt => self.value < other.value,
-            CompareOp::Le => self.value <= other.value,
-            CompareOp::Gt => self.value > other.value,
-            CompareOp::Ge => self.value >= other.value,
-        }
+    /// Python __richcmp__: supports all rich comparison operations with WattHour or a number of
+    /// Wh.
+    fn __richcmp__(&self, other: SameUnitOrFloat<WattHour>, op: CompareOp) -> bool {
+        compare(self.value, other.value(), op)
     }
 }
 impl WattHour {
@@ -387,9 +593,16 @@ impl WattHour {
     pub fn from_milli_wh(value: f64) -> Self {
         WattHour::new(value / 1_000.0)
     }
+    /// Convert to milli-Wh for the optimizer.
+    /// Raises UnitError naming `field` if that is more than MAX_ENERGY.
+    pub fn to_internal(&self, field: &str) -> PyResult<i64> {
+        checked_internal(self.to_milli_wh(), MAX_ENERGY, field, |milli_wh| {
+            format!("{} Wh", WattHour::from_milli_wh(milli_wh).value)
+        })
+    }
 }
 
-#[pyclass]
+#[pyclass(module = "electricity_price_optimizer_py.units")]
 #[derive(Clone, Debug, Default)]
 /// Currency in euros (€).
 /// Python: supports +, -, *, / with float; / WattHour -> EuroPerWh.
@@ -434,14 +647,23 @@ impl Add for &Euro {
     type Output = Euro;
 
     fn add(self, other: &Euro) -> Euro {
-        self.__add__(other)
+        Euro {
+            value: self.value + other.value,
+        }
     }
 }
 impl Sub for &Euro {
     type Output = Euro;
 
     fn sub(self, other: &Euro) -> Euro {
-        self.__sub__(other)
+        Euro {
+            value: self.value - other.value,
+        }
+    }
+}
+impl UnitValue for Euro {
+    fn value(&self) -> f64 {
+        self.value
     }
 }
 #[pymethods]
@@ -451,6 +673,21 @@ impl Euro {
     fn new(value: f64) -> Self {
         Euro { value }
     }
+    /// Python __reduce__: pickle as the value.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (f64,)) {
+        (slf.get_type(), (slf.borrow().value,))
+    }
+    #[staticmethod]
+    /// Construct a Euro value from cents.
+    fn from_cent(value: f64) -> Self {
+        Euro {
+            value: value / 100.0,
+        }
+    }
+    /// Get the value in cents.
+    fn to_cent(&self) -> f64 {
+        to_decimal_unit(self.value, 100.0)
+    }
 
     /// Python __mul__: supports float (returns Euro).
     fn __mul__<'py>(
@@ -499,18 +736,44 @@ impl Euro {
         }
     }
 
-    /// Python __add__: Euro + Euro.
-    fn __add__(&self, other: &Euro) -> Euro {
+    /// Python __add__: Euro + Euro or a number of €.
+    fn __add__(&self, other: SameUnitOrFloat<Euro>) -> Euro {
         Euro {
-            value: self.value + other.value,
+            value: self.value + other.value(),
         }
     }
-    /// Python __sub__: Euro - Euro.
-    fn __sub__(&self, other: &Euro) -> Euro {
+    /// Python __radd__: number of € + Euro, e.g. the 0 `sum` starts with.
+    fn __radd__(&self, other: f64) -> Euro {
         Euro {
-            value: self.value - other.value,
+            value: other + self.value,
         }
     }
+    /// Python __sub__: Euro - Euro or a number of €.
+    fn __sub__(&self, other: SameUnitOrFloat<Euro>) -> Euro {
+        Euro {
+            value: self.value - other.value(),
+        }
+    }
+    /// Python __rsub__: number of € - Euro.
+    fn __rsub__(&self, other: f64) -> Euro {
+        Euro {
+            value: other - self.value,
+        }
+    }
+    /// Python __neg__: -Euro.
+    fn __neg__(&self) -> Euro {
+        Euro { value: -self.value }
+    }
+    /// Python __abs__: abs(Euro).
+    fn __abs__(&self) -> Euro {
+        Euro {
+            value: self.value.abs(),
+        }
+    }
+    /// Python __hash__: equal to the hash of the value, as Euro compares equal to it.
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        hash(py, self.value)
+    }
     /// Python __repr__: formatted string.
     fn __repr__(&self) -> String {
         // format with 2 decimal places
@@ -521,16 +784,10 @@ impl Euro {
     fn get_value(&self) -> f64 {
         self.value
     }
-    /// Python __richcmp__: supports all rich comparison operations.
-    fn __richcmp__(&self, other: &Euro, op: CompareOp) -> bool {
-        match op {
-            CompareOp::Eq => self.value == other.value,
-            CompareOp::Ne => self.value != other.value,
-            CompareOp::Lt => self.value < other.value,
-            CompareOp::Le => self.value <= other.value,
-            CompareOp::Gt => self.value > other.value,
-            CompareOp::Ge => self.value >= other.value,
-        }
+    /// Python __richcmp__: supports all rich comparison operations with Euro or a number of
+    /// €.
+    fn __richcmp__(&self, other: SameUnitOrFloat<Euro>, op: CompareOp) -> bool {
+        compare(self.value, other.value(), op)
     }
 }
 impl Euro {
@@ -544,7 +801,7 @@ impl Euro {
     }
 }
 
-#[pyclass]
+#[pyclass(module = "electricity_price_optimizer_py.units")]
 #[derive(Clone, Debug, Default)]
 /// Price per watt-hour (€/Wh).
 /// Python: supports +, -, *, / with float; * WattHour -> Euro; / EuroPerWh -> float.
@@ -590,14 +847,23 @@ impl Add for &EuroPerWh {
     type Output = EuroPerWh;
 
     fn add(self, other: &EuroPerWh) -> EuroPerWh {
-        self.__add__(other)
+        EuroPerWh {
+            value: self.value + other.value,
+        }
     }
 }
 impl Sub for &EuroPerWh {
     type Output = EuroPerWh;
 
     fn sub(self, other: &EuroPerWh) -> EuroPerWh {
-        self.__sub__(other)
+        EuroPerWh {
+            value: self.value - other.value,
+        }
+    }
+}
+impl UnitValue for EuroPerWh {
+    fn value(&self) -> f64 {
+        self.value
     }
 }
 #[pymethods]
@@ -607,6 +873,21 @@ impl EuroPerWh {
     fn new(value: f64) -> Self {
         EuroPerWh { value }
     }
+    /// Python __reduce__: pickle as the value.
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (f64,)) {
+        (slf.get_type(), (slf.borrow().value,))
+    }
+    #[staticmethod]
+    /// Construct a EuroPerWh value from ct/kWh, the unit prices are usually quoted in.
+    fn from_cent_per_kwh(value: f64) -> Self {
+        EuroPerWh {
+            value: value / 100_000.0,
+        }
+    }
+    /// Get the value in ct/kWh.
+    fn to_cent_per_kwh(&self) -> f64 {
+        to_decimal_unit(self.value, 100_000.0)
+    }
 
     /// Python __mul__: supports WattHour (returns Euro) and float (returns EuroPerWh).
     fn __mul__<'py>(
@@ -657,18 +938,44 @@ impl EuroPerWh {
         }
     }
 
-    /// Python __add__: EuroPerWh + EuroPerWh.
-    fn __add__(&self, other: &EuroPerWh) -> EuroPerWh {
+    /// Python __add__: EuroPerWh + EuroPerWh or a number of €/Wh.
+    fn __add__(&self, other: SameUnitOrFloat<EuroPerWh>) -> EuroPerWh {
         EuroPerWh {
-            value: self.value + other.value,
+            value: self.value + other.value(),
         }
     }
-    /// Python __sub__: EuroPerWh - EuroPerWh.
-    fn __sub__(&self, other: &EuroPerWh) -> EuroPerWh {
+    /// Python __radd__: number of €/Wh + EuroPerWh, e.g. the 0 `sum` starts with.
+    fn __radd__(&self, other: f64) -> EuroPerWh {
         EuroPerWh {
-            value: self.value - other.value,
+            value: other + self.value,
+        }
+    }
+    /// Python __sub__: EuroPerWh - EuroPerWh or a number of €/Wh.
+    fn __sub__(&self, other: SameUnitOrFloat<EuroPerWh>) -> EuroPerWh {
+        EuroPerWh {
+            value: self.value - other.value(),
+        }
+    }
+    /// Python __rsub__: number of €/Wh - EuroPerWh.
+    fn __rsub__(&self, other: f64) -> EuroPerWh {
+        EuroPerWh {
+            value: other - self.value,
+        }
+    }
+    /// Python __neg__: -EuroPerWh.
+    fn __neg__(&self) -> EuroPerWh {
+        EuroPerWh { value: -self.value }
+    }
+    /// Python __abs__: abs(EuroPerWh).
+    fn __abs__(&self) -> EuroPerWh {
+        EuroPerWh {
+            value: self.value.abs(),
         }
     }
+    /// Python __hash__: equal to the hash of the value, as EuroPerWh compares equal to it.
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        hash(py, self.value)
+    }
     /// Python __repr__: formatted string.
     fn __repr__(&self) -> String {
         // format with 6 decimal places
@@ -678,16 +985,10 @@ impl EuroPerWh {
     fn get_value(&self) -> f64 {
         self.value
     }
-    /// Python __richcmp__: supports all rich comparison operations.
-    fn __richcmp__(&self, other: &EuroPerWh, op: CompareOp) -> bool {
-        match op {
-            CompareOp::Eq => self.value == other.value,
-            CompareOp::Ne => self.value != other.value,
-            CompareOp::Lt => self.value < other.value,
-            CompareOp::Le => self.value <= other.value,
-            CompareOp::Gt => self.value > other.value,
-            CompareOp::Ge => self.value >= other.value,
-        }
+    /// Python __richcmp__: supports all rich comparison operations with EuroPerWh or a number of
+    /// €/Wh.
+    fn __richcmp__(&self, other: SameUnitOrFloat<EuroPerWh>, op: CompareOp) -> bool {
+        compare(self.value, other.value(), op)
     }
 }
 impl EuroPerWh {
@@ -695,6 +996,17 @@ impl EuroPerWh {
     pub fn to_micro_euro_per_wh(&self) -> f64 {
         self.value * 1_000_000.0
     }
+    /// Construct from micro-euro per Wh.
+    pub fn from_micro_euro_per_wh(value: f64) -> Self {
+        EuroPerWh::new(value / 1_000_000.0)
+    }
+    /// Convert to micro-euro per Wh for the optimizer.
+    /// Raises UnitError naming `field` if that is more than MAX_PRICE.
+    pub fn to_internal(&self, field: &str) -> PyResult<i64> {
+        checked_internal(self.to_micro_euro_per_wh(), MAX_PRICE, field, |price| {
+            format!("{} €/Wh", EuroPerWh::from_micro_euro_per_wh(price).value)
+        })
+    }
 }
 
 /// Register the `units` submodule under the Python module.
@@ -712,3 +1024,165 @@ pub fn register_units_submodule(parent_module: &Bound<'_, PyModule>) -> PyResult
     parent_module.add_submodule(&units_mod)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP_MINUTES: [i64; 4] = [1, 5, 15, 60];
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-9 * a.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_energy_per_step_round_trip() {
+        for minutes in STEP_MINUTES {
+            let step = TimeDelta::minutes(minutes);
+            for watt in [0.0, 1.0, 230.5, 3_600.0, 11_000.0, -500.0] {
+                let milli_wh = Watt { value: watt }.to_energy_per_step(step);
+                assert_close(milli_wh, watt * 1000.0 * minutes as f64 / 60.0);
+                assert_close(Watt::from_energy_per_step(milli_wh, step).value, watt);
+            }
+            for milli_wh in [0.0, 1.0, 17.0, 250_000.0] {
+                let watt = Watt::from_energy_per_step(milli_wh, step);
+                assert_close(watt.to_energy_per_step(step), milli_wh);
+            }
+        }
+    }
+
+    #[test]
+    fn test_power_is_independent_of_resolution() {
+        // One hour of a physical load profile, sampled per minute in milli-Wh.
+        let per_minute: Vec<f64> = (0..60)
+            .map(|m| {
+                Watt {
+                    value: 1_000.0 + 37.0 * (m / 10) as f64,
+                }
+                .to_energy_per_step(TimeDelta::minutes(1))
+            })
+            .collect();
+        for minutes in STEP_MINUTES {
+            let step = TimeDelta::minutes(minutes);
+            // Aggregate into coarser steps and compare the average power of every coarse step.
+            for chunk in per_minute.chunks(minutes as usize) {
+                let coarse = Watt::from_energy_per_step(chunk.iter().sum(), step);
+                let fine_average = chunk
+                    .iter()
+                    .map(|e| Watt::from_energy_per_step(*e, TimeDelta::minutes(1)).value)
+                    .sum::<f64>()
+                    / chunk.len() as f64;
+                assert_close(coarse.value, fine_average);
+            }
+        }
+    }
+
+    #[test]
+    fn test_extreme_durations_dont_panic() {
+        // Far more nanoseconds than an i64 holds
+        let energy = &Watt { value: 2.0 } * TimeDelta::MAX;
+        assert!(energy.value.is_finite() && energy.value > 0.0);
+        assert!((&WattHour { value: 1.0 } / TimeDelta::MAX).value > 0.0);
+
+        assert_eq!(
+            &WattHour { value: 1_500.0 } / Watt { value: 1_000.0 },
+            Some(TimeDelta::minutes(90))
+        );
+        assert_eq!(&WattHour { value: 1e30 } / Watt { value: 1.0 }, None);
+        assert_eq!(&WattHour { value: 1.0 } / Watt { value: 0.0 }, None);
+
+        Python::initialize();
+        Python::attach(|py| {
+            let err = WattHour { value: 1.0 }
+                .__truediv__(py, UnitOrTimeOrFloat::Watt(Watt { value: 0.0 }))
+                .unwrap_err();
+            assert!(err.is_instance_of::<UnitError>(py));
+        });
+    }
+
+    #[test]
+    fn test_kilo_and_cent_round_trip() {
+        for value in [0.0, 0.1, 3.7, 7.4, 11.0, 12.5, 29.87, -4.5, 250.0] {
+            assert_eq!(Watt::from_kilowatt(value).to_kilowatt(), value);
+            assert_eq!(
+                WattHour::from_kilowatt_hour(value).to_kilowatt_hour(),
+                value
+            );
+            assert_eq!(Euro::from_cent(value).to_cent(), value);
+            assert_eq!(EuroPerWh::from_cent_per_kwh(value).to_cent_per_kwh(), value);
+        }
+        assert_eq!(Watt::from_kilowatt(11.0).value, 11_000.0);
+        assert_eq!(WattHour::from_kilowatt_hour(7.4).value, 7_400.0);
+        assert_eq!(Euro::from_cent(250.0).value, 2.5);
+        assert_eq!(
+            EuroPerWh::from_cent_per_kwh(30.0).to_micro_euro_per_wh(),
+            300.0
+        );
+
+        assert_eq!(Watt::new(999.0).__repr__(), "999.00 W");
+        assert_eq!(Watt::new(1_500.0).__repr__(), "1.50 kW");
+        assert_eq!(Watt::new(-11_000.0).__repr__(), "-11.00 kW");
+        assert_eq!(WattHour::new(500.0).__repr__(), "500.00 Wh");
+        assert_eq!(WattHour::new(7_400.0).__repr__(), "7.40 kWh");
+    }
+
+    #[test]
+    fn test_same_unit_operators_take_numbers() {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = pyo3::types::PyDict::new(py);
+            for (name, class) in [
+                ("Watt", py.get_type::<Watt>()),
+                ("WattHour", py.get_type::<WattHour>()),
+                ("Euro", py.get_type::<Euro>()),
+                ("EuroPerWh", py.get_type::<EuroPerWh>()),
+            ] {
+                globals.set_item(name, class).unwrap();
+            }
+            py.run(
+                c"
+for unit in (Watt, WattHour, Euro, EuroPerWh):
+    for other in (2, 2.0, unit(2.0)):
+        assert (unit(5.0) + other).get_value() == 7.0, (unit, other)
+        assert (unit(5.0) - other).get_value() == 3.0, (unit, other)
+        assert unit(5.0) > other and unit(5.0) >= other, (unit, other)
+        assert unit(1.0) < other and unit(1.0) <= other, (unit, other)
+        assert unit(2.0) == other and not unit(2.0) != other, (unit, other)
+        assert unit(5.0) != other and not unit(5.0) == other, (unit, other)
+        assert unit(2.0) <= other and unit(2.0) >= other, (unit, other)
+    assert isinstance(unit(5.0) + 0, unit)
+    assert (0 + unit(5.0)).get_value() == 5.0 and (7 - unit(5.0)).get_value() == 2.0
+    assert (-unit(5.0)).get_value() == -5.0 and abs(unit(-5.0)).get_value() == 5.0
+    assert hash(unit(5.0)) == hash(5) == hash(unit(5))
+    assert len({unit(5.0), unit(5), unit(1.0)}) == 2
+    assert unit(5.0) != 'five'
+
+for left, right in ((Watt(1.0), WattHour(1.0)), (Euro(1.0), EuroPerWh(1.0))):
+    for op in (
+        lambda a, b: a + b,
+        lambda a, b: a - b,
+        lambda a, b: a < b,
+        lambda a, b: a <= b,
+        lambda a, b: a > b,
+        lambda a, b: a >= b,
+    ):
+        try:
+            op(left, right)
+        except TypeError:
+            pass
+        else:
+            raise AssertionError((left, right))
+    assert left != right
+
+prices = sorted([Euro(3.0), Euro(-1.0), Euro(2.5)])
+assert [p.get_value() for p in prices] == [-1.0, 2.5, 3.0]
+assert sum(prices).get_value() == 4.5
+assert max(prices) == 3
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+    }
+}